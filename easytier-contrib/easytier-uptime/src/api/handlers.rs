@@ -14,7 +14,7 @@ use crate::api::{
     models::*,
 };
 use crate::db::entity::{self, health_records, shared_nodes};
-use crate::db::{Db, operations::*};
+use crate::db::{Db, encode_probe_protocols, operations::*};
 use crate::health_checker_manager::HealthCheckerManager;
 use axum_extra::extract::Query;
 use std::sync::Arc;
@@ -165,12 +165,16 @@ pub async fn get_nodes(
 pub async fn create_node(
     State(app_state): State<AppState>,
     Json(request): Json<CreateNodeRequest>,
-) -> ApiResult<Json<ApiResponse<NodeResponse>>> {
+) -> ApiResult<Json<ApiResponse<CreateNodeResponse>>> {
     request.validate()?;
 
     let node = NodeOperations::create_node(&app_state.db, request).await?;
+    let owner_token = issue_owner_token(&node)?;
 
-    Ok(Json(ApiResponse::success(NodeResponse::from(node))))
+    Ok(Json(ApiResponse::success(CreateNodeResponse {
+        node: NodeResponse::from(node),
+        owner_token,
+    })))
 }
 
 pub async fn test_connection(
@@ -505,6 +509,9 @@ pub async fn admin_update_node(
     if let Some(qq_number) = request.qq_number {
         node.qq_number = Set(qq_number);
     }
+    if let Some(probe_protocols) = request.probe_protocols {
+        node.probe_protocols = Set(encode_probe_protocols(&probe_protocols));
+    }
 
     node.updated_at = Set(chrono::Utc::now().fixed_offset());
 
@@ -605,3 +612,413 @@ pub async fn get_all_tags(
     let tags = NodeOperations::get_all_tags(&app_state.db).await?;
     Ok(Json(ApiResponse::success(tags)))
 }
+
+// 状态页相关处理器（只读、无需认证，仅展示已审核节点）
+use crate::db::status::{
+    SLA_WINDOWS, SlaWindow, compute_sla_windows, derive_incidents, protocol_status,
+};
+use crate::prober::probe_targets;
+use std::collections::HashMap;
+
+fn current_health_status(app_state: &AppState, node_id: i32) -> Option<String> {
+    app_state
+        .health_checker_manager
+        .get_node_memory_record(node_id)
+        .map(|record| record.get_current_health_status().to_string())
+}
+
+pub async fn get_status_page(
+    State(app_state): State<AppState>,
+) -> ApiResult<Json<ApiResponse<Vec<StatusPageNodeResponse>>>> {
+    let nodes = entity::shared_nodes::Entity::find()
+        .filter(entity::shared_nodes::Column::IsApproved.eq(true))
+        .order_by_asc(entity::shared_nodes::Column::Id)
+        .all(app_state.db.orm_db())
+        .await?;
+
+    // 各窗口的探测次数，按（节点，协议）聚合
+    let now = Utc::now();
+    let mut sla_map: HashMap<(i32, String), Vec<SlaWindow>> = HashMap::new();
+    for (idx, (window, hours)) in SLA_WINDOWS.iter().enumerate() {
+        let counts =
+            ProbeOperations::get_probe_counts(&app_state.db, now - Duration::hours(*hours)).await?;
+        for row in counts {
+            let windows = sla_map
+                .entry((row.node_id, row.protocol.clone()))
+                .or_insert_with(|| {
+                    SLA_WINDOWS
+                        .iter()
+                        .map(|(name, _)| SlaWindow::from_counts(name, 0, 0))
+                        .collect()
+                });
+            windows[idx] = SlaWindow::from_counts(window, row.total as u64, row.successful as u64);
+        }
+    }
+
+    let mut latest_map: HashMap<(i32, String), entity::probe_results::Model> = HashMap::new();
+    for record in ProbeOperations::get_latest_probe_results(&app_state.db).await? {
+        latest_map.insert((record.node_id, record.protocol.clone()), record);
+    }
+
+    let items = nodes
+        .into_iter()
+        .map(|node| {
+            let protocols = probe_targets(&node)
+                .into_iter()
+                .map(|target| {
+                    let key = (node.id, target.protocol.clone());
+                    let latest = latest_map.remove(&key);
+                    ProtocolStatusResponse {
+                        status: protocol_status(latest.as_ref()).to_string(),
+                        latest_probe: latest.map(ProbeResultResponse::from),
+                        sla: sla_map.remove(&key).unwrap_or_else(|| {
+                            SLA_WINDOWS
+                                .iter()
+                                .map(|(name, _)| SlaWindow::from_counts(name, 0, 0))
+                                .collect()
+                        }),
+                        protocol: target.protocol,
+                        port: target.port,
+                    }
+                })
+                .collect();
+            StatusPageNodeResponse {
+                id: node.id,
+                name: node.name,
+                version: node.version,
+                current_health_status: current_health_status(&app_state, node.id),
+                protocols,
+            }
+        })
+        .collect();
+
+    Ok(Json(ApiResponse::success(items)))
+}
+
+pub async fn get_status_page_node(
+    State(app_state): State<AppState>,
+    Path(id): Path<i32>,
+    Query(filters): Query<IncidentFilterParams>,
+) -> ApiResult<Json<ApiResponse<StatusPageNodeDetailResponse>>> {
+    let node = NodeOperations::get_node_by_id(&app_state.db, id)
+        .await?
+        .filter(|node| node.is_approved)
+        .ok_or_else(|| ApiError::NotFound(format!("Node with id {} not found", id)))?;
+
+    let now = Utc::now();
+    let (_, max_hours) = SLA_WINDOWS[SLA_WINDOWS.len() - 1];
+    let records = ProbeOperations::get_node_probe_results(
+        &app_state.db,
+        id,
+        now - Duration::hours(max_hours),
+    )
+    .await?;
+
+    let mut protocols = Vec::new();
+    let mut incidents = Vec::new();
+    for target in probe_targets(&node) {
+        let protocol_records: Vec<_> = records
+            .iter()
+            .filter(|r| r.protocol == target.protocol)
+            .cloned()
+            .collect();
+
+        if filters
+            .protocol
+            .as_ref()
+            .is_none_or(|p| p.eq_ignore_ascii_case(&target.protocol))
+        {
+            incidents.extend(derive_incidents(&target.protocol, &protocol_records));
+        }
+
+        let latest = protocol_records.last();
+        protocols.push(ProtocolStatusResponse {
+            status: protocol_status(latest).to_string(),
+            latest_probe: latest.cloned().map(ProbeResultResponse::from),
+            sla: compute_sla_windows(&protocol_records, now),
+            protocol: target.protocol,
+            port: target.port,
+        });
+    }
+    incidents.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+
+    Ok(Json(ApiResponse::success(StatusPageNodeDetailResponse {
+        node: StatusPageNodeResponse {
+            id: node.id,
+            current_health_status: current_health_status(&app_state, node.id),
+            name: node.name,
+            version: node.version,
+            protocols,
+        },
+        incidents,
+    })))
+}
+
+// 节点所有者相关处理器：创建节点时签发的令牌只能管理对应的节点
+use crate::notifier::SUBSCRIPTION_KINDS;
+
+/// 所有者令牌不设置过期时间，通过轮换节点上的随机数使其失效。
+/// 由于缺少 exp 字段，它也无法通过管理员令牌的校验。
+#[derive(Debug, Serialize, Deserialize)]
+struct OwnerClaims {
+    sub: String,
+    node_id: i32,
+    nonce: String,
+    iat: usize,
+}
+
+fn issue_owner_token(node: &shared_nodes::Model) -> ApiResult<String> {
+    let config = AppConfig::default();
+
+    let claims = OwnerClaims {
+        sub: format!("node:{}", node.id),
+        node_id: node.id,
+        nonce: node.owner_token_nonce.clone(),
+        iat: Utc::now().timestamp() as usize,
+    };
+
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(config.security.jwt_secret.as_ref()),
+    )
+    .map_err(|e| ApiError::Internal(format!("Token generation failed: {}", e)))
+}
+
+/// 校验所有者令牌并返回对应的节点
+async fn verify_owner_token(
+    app_state: &AppState,
+    headers: &HeaderMap,
+    id: i32,
+) -> ApiResult<shared_nodes::Model> {
+    let config = AppConfig::default();
+
+    let token = headers
+        .get("authorization")
+        .ok_or_else(|| ApiError::Unauthorized("Missing authorization header".to_string()))?
+        .to_str()
+        .map_err(|_| ApiError::Unauthorized("Invalid authorization header".to_string()))?
+        .strip_prefix("Bearer ")
+        .ok_or_else(|| ApiError::Unauthorized("Invalid authorization format".to_string()))?;
+
+    let mut validation = Validation::default();
+    validation.validate_exp = false;
+    validation.required_spec_claims.clear();
+
+    let claims = decode::<OwnerClaims>(
+        token,
+        &DecodingKey::from_secret(config.security.jwt_secret.as_ref()),
+        &validation,
+    )
+    .map_err(|_| ApiError::Unauthorized("Invalid token".to_string()))?
+    .claims;
+
+    if claims.node_id != id {
+        return Err(ApiError::Forbidden(
+            "Token does not belong to this node".to_string(),
+        ));
+    }
+
+    let node = NodeOperations::get_node_by_id(&app_state.db, id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Node with id {} not found", id)))?;
+
+    if node.owner_token_nonce.is_empty() || node.owner_token_nonce != claims.nonce {
+        return Err(ApiError::Unauthorized("Token has been revoked".to_string()));
+    }
+
+    Ok(node)
+}
+
+async fn node_response(app_state: &AppState, node: shared_nodes::Model) -> ApiResult<NodeResponse> {
+    let mut resp = NodeResponse::from(node);
+    resp.tags = NodeOperations::get_node_tags(&app_state.db, resp.id).await?;
+    Ok(resp)
+}
+
+pub async fn owner_get_node(
+    State(app_state): State<AppState>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+) -> ApiResult<Json<ApiResponse<NodeResponse>>> {
+    let node = verify_owner_token(&app_state, &headers, id).await?;
+    Ok(Json(ApiResponse::success(
+        node_response(&app_state, node).await?,
+    )))
+}
+
+pub async fn owner_update_node(
+    State(app_state): State<AppState>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    Json(request): Json<OwnerUpdateNodeRequest>,
+) -> ApiResult<Json<ApiResponse<NodeResponse>>> {
+    let node = verify_owner_token(&app_state, &headers, id).await?;
+    request.validate()?;
+
+    let changes_connection = request.changes_connection();
+    let mut node = node.into_active_model();
+
+    if let Some(name) = request.name {
+        node.name = Set(name);
+    }
+    if let Some(host) = request.host {
+        node.host = Set(host);
+    }
+    if let Some(port) = request.port {
+        node.port = Set(port);
+    }
+    if let Some(protocol) = request.protocol {
+        node.protocol = Set(protocol);
+    }
+    if let Some(description) = request.description {
+        node.description = Set(description);
+    }
+    if let Some(max_connections) = request.max_connections {
+        node.max_connections = Set(max_connections);
+    }
+    if let Some(allow_relay) = request.allow_relay {
+        node.allow_relay = Set(allow_relay);
+    }
+    if let Some(network_name) = request.network_name {
+        node.network_name = Set(network_name);
+    }
+    if let Some(network_secret) = request.network_secret {
+        node.network_secret = Set(network_secret);
+    }
+    if let Some(wechat) = request.wechat {
+        node.wechat = Set(wechat);
+    }
+    if let Some(mail) = request.mail {
+        node.mail = Set(mail);
+    }
+    if let Some(qq_number) = request.qq_number {
+        node.qq_number = Set(qq_number);
+    }
+    if let Some(probe_protocols) = request.probe_protocols {
+        node.probe_protocols = Set(encode_probe_protocols(&probe_protocols));
+    }
+
+    // 修改连接信息后需要管理员重新审核
+    if changes_connection {
+        node.is_approved = Set(false);
+    }
+    node.updated_at = Set(chrono::Utc::now().fixed_offset());
+
+    let updated_node = entity::shared_nodes::Entity::update(node)
+        .exec(app_state.db.orm_db())
+        .await?;
+
+    Ok(Json(ApiResponse::success(
+        node_response(&app_state, updated_node).await?,
+    )))
+}
+
+pub async fn owner_pause_node(
+    State(app_state): State<AppState>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    Json(request): Json<PauseNodeRequest>,
+) -> ApiResult<Json<ApiResponse<NodeResponse>>> {
+    verify_owner_token(&app_state, &headers, id).await?;
+
+    let node = NodeOperations::set_node_paused(&app_state.db, id, request.paused).await?;
+
+    Ok(Json(ApiResponse::success(
+        node_response(&app_state, node).await?,
+    )))
+}
+
+pub async fn owner_rotate_token(
+    State(app_state): State<AppState>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+) -> ApiResult<Json<ApiResponse<OwnerTokenResponse>>> {
+    verify_owner_token(&app_state, &headers, id).await?;
+
+    let node = NodeOperations::rotate_owner_token_nonce(&app_state.db, id).await?;
+
+    Ok(Json(ApiResponse::success(OwnerTokenResponse {
+        token: issue_owner_token(&node)?,
+    })))
+}
+
+pub async fn owner_get_subscriptions(
+    State(app_state): State<AppState>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+) -> ApiResult<Json<ApiResponse<Vec<SubscriptionResponse>>>> {
+    verify_owner_token(&app_state, &headers, id).await?;
+
+    let subs = SubscriptionOperations::get_node_subscriptions(&app_state.db, id).await?;
+
+    Ok(Json(ApiResponse::success(
+        subs.into_iter().map(SubscriptionResponse::from).collect(),
+    )))
+}
+
+pub async fn owner_create_subscription(
+    State(app_state): State<AppState>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+    Json(request): Json<CreateSubscriptionRequest>,
+) -> ApiResult<Json<ApiResponse<SubscriptionResponse>>> {
+    verify_owner_token(&app_state, &headers, id).await?;
+    request.validate()?;
+
+    if !SUBSCRIPTION_KINDS.contains(&request.kind.as_str()) {
+        return Err(ApiError::BadRequest(format!(
+            "Unsupported subscription kind: {}",
+            request.kind
+        )));
+    }
+
+    let sub = SubscriptionOperations::create_subscription(
+        &app_state.db,
+        id,
+        request.kind,
+        request.target,
+        request.notify_recovery.unwrap_or(true),
+    )
+    .await?;
+
+    Ok(Json(ApiResponse::success(SubscriptionResponse::from(sub))))
+}
+
+pub async fn owner_delete_subscription(
+    State(app_state): State<AppState>,
+    Path((id, sub_id)): Path<(i32, i32)>,
+    headers: HeaderMap,
+) -> ApiResult<Json<ApiResponse<String>>> {
+    verify_owner_token(&app_state, &headers, id).await?;
+
+    let deleted = SubscriptionOperations::delete_subscription(&app_state.db, id, sub_id).await?;
+    if deleted == 0 {
+        return Err(ApiError::NotFound(format!(
+            "Subscription with id {} not found",
+            sub_id
+        )));
+    }
+
+    Ok(Json(ApiResponse::message(
+        "Subscription deleted successfully".to_string(),
+    )))
+}
+
+/// 管理员为节点重新签发所有者令牌（之前的令牌失效），用于在本功能上线前创建的节点
+pub async fn admin_issue_owner_token(
+    State(app_state): State<AppState>,
+    Path(id): Path<i32>,
+    headers: HeaderMap,
+) -> ApiResult<Json<ApiResponse<OwnerTokenResponse>>> {
+    verify_admin_token(&headers)?;
+
+    NodeOperations::get_node_by_id(&app_state.db, id)
+        .await?
+        .ok_or_else(|| ApiError::NotFound(format!("Node with id {} not found", id)))?;
+    let node = NodeOperations::rotate_owner_token_nonce(&app_state.db, id).await?;
+
+    Ok(Json(ApiResponse::success(OwnerTokenResponse {
+        token: issue_owner_token(&node)?,
+    })))
+}
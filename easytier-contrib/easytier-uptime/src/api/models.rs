@@ -102,6 +102,23 @@ pub struct CreateNodeRequest {
 
     #[validate(email)]
     pub mail: Option<String>,
+
+    // 除注册协议外额外探测的协议
+    #[validate(custom(function = "validate_probe_protocols"))]
+    pub probe_protocols: Option<Vec<String>>,
+}
+
+// 自定义验证函数：探测协议必须是受支持的协议
+fn validate_probe_protocols(protocols: &[String]) -> Result<(), validator::ValidationError> {
+    let all_supported = protocols
+        .iter()
+        .all(|p| crate::db::PROBE_PROTOCOLS.contains(&p.trim().to_lowercase().as_str()));
+    if !all_supported {
+        return Err(validator::ValidationError::new(
+            "unsupported_probe_protocol",
+        ));
+    }
+    Ok(())
 }
 
 // 自定义验证函数：确保至少填写一种联系方式
@@ -163,10 +180,140 @@ pub struct UpdateNodeRequest {
     #[validate(email)]
     pub mail: Option<String>,
 
+    #[validate(custom(function = "validate_probe_protocols"))]
+    pub probe_protocols: Option<Vec<String>>,
+
     // 标签字段（仅管理员可用）
     pub tags: Option<Vec<String>>,
 }
 
+/// 节点所有者可修改的字段，修改连接信息后需要重新审核
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct OwnerUpdateNodeRequest {
+    #[validate(length(min = 1, max = 100))]
+    pub name: Option<String>,
+
+    #[validate(length(min = 1, max = 255))]
+    pub host: Option<String>,
+
+    #[validate(range(min = 1, max = 65535))]
+    pub port: Option<i32>,
+
+    #[validate(length(min = 1, max = 20))]
+    pub protocol: Option<String>,
+
+    #[validate(length(max = 500))]
+    pub description: Option<String>,
+
+    #[validate(range(min = 1, max = 10000))]
+    pub max_connections: Option<i32>,
+
+    pub allow_relay: Option<bool>,
+
+    #[validate(length(min = 1, max = 100))]
+    pub network_name: Option<String>,
+
+    #[validate(length(max = 100))]
+    pub network_secret: Option<String>,
+
+    // 联系方式字段
+    #[validate(length(max = 20))]
+    pub qq_number: Option<String>,
+
+    #[validate(length(max = 50))]
+    pub wechat: Option<String>,
+
+    #[validate(email)]
+    pub mail: Option<String>,
+
+    #[validate(custom(function = "validate_probe_protocols"))]
+    pub probe_protocols: Option<Vec<String>>,
+}
+
+impl OwnerUpdateNodeRequest {
+    /// 是否修改了需要重新审核的连接信息
+    pub fn changes_connection(&self) -> bool {
+        self.host.is_some()
+            || self.port.is_some()
+            || self.protocol.is_some()
+            || self.network_name.is_some()
+            || self.network_secret.is_some()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PauseNodeRequest {
+    pub paused: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_subscription_target"))]
+pub struct CreateSubscriptionRequest {
+    /// webhook / email
+    pub kind: String,
+
+    #[validate(length(min = 1, max = 500))]
+    pub target: String,
+
+    /// 是否在节点恢复时通知，默认通知
+    pub notify_recovery: Option<bool>,
+}
+
+// 自定义验证函数：webhook 必须是 http(s) 地址，email 必须是邮箱
+fn validate_subscription_target(
+    request: &CreateSubscriptionRequest,
+) -> Result<(), validator::ValidationError> {
+    use validator::{ValidateEmail, ValidateUrl};
+
+    let valid = match request.kind.as_str() {
+        "webhook" => {
+            request.target.validate_url()
+                && (request.target.starts_with("http://") || request.target.starts_with("https://"))
+        }
+        "email" => request.target.validate_email(),
+        _ => {
+            return Err(validator::ValidationError::new(
+                "unsupported_subscription_kind",
+            ));
+        }
+    };
+    if !valid {
+        return Err(validator::ValidationError::new(
+            "invalid_subscription_target",
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubscriptionResponse {
+    pub id: i32,
+    pub node_id: i32,
+    pub kind: String,
+    pub target: String,
+    pub notify_recovery: bool,
+    pub last_notified_state: Option<String>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<entity::node_subscriptions::Model> for SubscriptionResponse {
+    fn from(sub: entity::node_subscriptions::Model) -> Self {
+        Self {
+            id: sub.id,
+            node_id: sub.node_id,
+            kind: sub.kind,
+            target: sub.target,
+            notify_recovery: sub.notify_recovery,
+            last_notified_state: if sub.last_notified_state.is_empty() {
+                None
+            } else {
+                Some(sub.last_notified_state)
+            },
+            created_at: sub.created_at.into(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NodeResponse {
     pub id: i32,
@@ -180,6 +327,7 @@ pub struct NodeResponse {
     pub current_connections: i32,
     pub is_active: bool,
     pub is_approved: bool,
+    pub is_paused: bool,
     pub allow_relay: bool,
     pub network_name: Option<String>,
     pub network_secret: Option<String>,
@@ -202,10 +350,12 @@ pub struct NodeResponse {
     pub wechat: Option<String>,
     pub mail: Option<String>,
     pub tags: Vec<String>,
+    pub probe_protocols: Vec<String>,
 }
 
 impl From<entity::shared_nodes::Model> for NodeResponse {
     fn from(node: entity::shared_nodes::Model) -> Self {
+        let probe_protocols = node.get_probe_protocols();
         Self {
             id: node.id,
             name: node.name.clone(),
@@ -218,6 +368,7 @@ impl From<entity::shared_nodes::Model> for NodeResponse {
             current_connections: node.current_connections,
             is_active: node.is_active,
             is_approved: node.is_approved,
+            is_paused: node.is_paused,
             allow_relay: node.allow_relay,
             network_name: Some(node.network_name.clone()),
             network_secret: Some(node.network_secret.clone()),
@@ -252,10 +403,25 @@ impl From<entity::shared_nodes::Model> for NodeResponse {
                 Some(node.mail)
             },
             tags: Vec::new(),
+            probe_protocols,
         }
     }
 }
 
+/// 创建节点的返回值，附带节点所有者令牌
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateNodeResponse {
+    #[serde(flatten)]
+    pub node: NodeResponse,
+    /// 用于自助管理节点的令牌，只在创建时返回一次
+    pub owner_token: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OwnerTokenResponse {
+    pub token: String,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HealthRecordResponse {
     pub id: i32,
@@ -281,6 +447,74 @@ impl From<entity::health_records::Model> for HealthRecordResponse {
 
 pub type HealthStatsResponse = crate::db::HealthStats;
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProbeResultResponse {
+    pub protocol: String,
+    pub success: bool,
+    pub handshake_ms: Option<i32>,
+    pub rtt_avg_ms: Option<f64>,
+    pub rtt_min_ms: Option<f64>,
+    pub rtt_max_ms: Option<f64>,
+    pub loss_rate: Option<f64>,
+    pub samples: i32,
+    pub error_message: Option<String>,
+    pub probed_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<entity::probe_results::Model> for ProbeResultResponse {
+    fn from(record: entity::probe_results::Model) -> Self {
+        Self {
+            protocol: record.protocol,
+            success: record.success,
+            handshake_ms: record.handshake_ms,
+            rtt_avg_ms: record.rtt_avg_ms,
+            rtt_min_ms: record.rtt_min_ms,
+            rtt_max_ms: record.rtt_max_ms,
+            loss_rate: record.loss_rate,
+            samples: record.samples,
+            error_message: if record.error_message.is_empty() {
+                None
+            } else {
+                Some(record.error_message)
+            },
+            probed_at: record.probed_at.into(),
+        }
+    }
+}
+
+// 状态页相关模型
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProtocolStatusResponse {
+    pub protocol: String,
+    pub port: u16,
+    /// up / down / unknown
+    pub status: String,
+    pub latest_probe: Option<ProbeResultResponse>,
+    pub sla: Vec<crate::db::status::SlaWindow>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusPageNodeResponse {
+    pub id: i32,
+    pub name: String,
+    pub version: String,
+    pub current_health_status: Option<String>,
+    pub protocols: Vec<ProtocolStatusResponse>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StatusPageNodeDetailResponse {
+    #[serde(flatten)]
+    pub node: StatusPageNodeResponse,
+    /// 最近 30 天的故障，按开始时间倒序
+    pub incidents: Vec<crate::db::status::Incident>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IncidentFilterParams {
+    pub protocol: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct NodeFilterParams {
     pub is_active: Option<bool>,
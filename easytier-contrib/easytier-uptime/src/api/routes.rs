@@ -5,9 +5,12 @@ use tower_http::cors::CorsLayer;
 
 use super::handlers::AppState;
 use super::handlers::{
-    admin_approve_node, admin_delete_node, admin_get_nodes, admin_login, admin_revoke_approval,
-    admin_update_node, admin_verify_token, create_node, get_all_tags, get_node, get_node_health,
-    get_node_health_stats, get_nodes, health_check,
+    admin_approve_node, admin_delete_node, admin_get_nodes, admin_issue_owner_token, admin_login,
+    admin_revoke_approval, admin_update_node, admin_verify_token, create_node, get_all_tags,
+    get_node, get_node_health, get_node_health_stats, get_nodes, get_status_page,
+    get_status_page_node, health_check, owner_create_subscription, owner_delete_subscription,
+    owner_get_node, owner_get_subscriptions, owner_pause_node, owner_rotate_token,
+    owner_update_node,
 };
 use crate::api::{get_node_connect_url, test_connection};
 use crate::config::AppConfig;
@@ -42,6 +45,27 @@ pub fn create_routes() -> Router<AppState> {
         .route("/api/test_connection", post(test_connection))
         .route("/api/nodes/{id}/health", get(get_node_health))
         .route("/api/nodes/{id}/health/stats", get(get_node_health_stats))
+        // 状态页（只读）
+        .route("/api/status", get(get_status_page))
+        .route("/api/status/nodes/{id}", get(get_status_page_node))
+        // 节点所有者路由（使用创建节点时返回的令牌）
+        .route(
+            "/api/owner/nodes/{id}",
+            get(owner_get_node).put(owner_update_node),
+        )
+        .route("/api/owner/nodes/{id}/pause", put(owner_pause_node))
+        .route(
+            "/api/owner/nodes/{id}/token/rotate",
+            post(owner_rotate_token),
+        )
+        .route(
+            "/api/owner/nodes/{id}/subscriptions",
+            get(owner_get_subscriptions).post(owner_create_subscription),
+        )
+        .route(
+            "/api/owner/nodes/{id}/subscriptions/{sub_id}",
+            delete(owner_delete_subscription),
+        )
         // 管理员路由
         .route("/api/admin/login", post(admin_login))
         .route("/api/admin/verify", get(admin_verify_token))
@@ -51,6 +75,10 @@ pub fn create_routes() -> Router<AppState> {
         .route(
             "/api/admin/nodes/{id}",
             put(admin_update_node).delete(admin_delete_node),
+        )
+        .route(
+            "/api/admin/nodes/{id}/owner-token",
+            put(admin_issue_owner_token),
         );
 
     if let Some(layer) = compression_layer {
@@ -12,6 +12,7 @@ pub struct AppConfig {
     pub logging: LoggingConfig,
     pub cors: CorsConfig,
     pub security: SecurityConfig,
+    pub notification: NotificationConfig,
 }
 
 #[derive(Debug, Clone)]
@@ -50,6 +51,17 @@ pub struct SecurityConfig {
     pub admin_password: String,
 }
 
+#[derive(Debug, Clone)]
+pub struct NotificationConfig {
+    /// 节点故障通知的检查间隔
+    pub check_interval_seconds: u64,
+    /// SMTP 服务器（host:port），未配置时不发送邮件通知
+    pub smtp_server: Option<String>,
+    pub smtp_from: String,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+}
+
 impl Default for AppConfig {
     fn default() -> Self {
         Self::from_env().unwrap_or_else(|_| Self::default_config())
@@ -136,6 +148,17 @@ impl AppConfig {
             admin_password: env::var("ADMIN_PASSWORD").unwrap_or_else(|_| "admin123".to_string()),
         };
 
+        let notification_config = NotificationConfig {
+            check_interval_seconds: env::var("NOTIFY_CHECK_INTERVAL")
+                .map(|s| s.parse().unwrap_or(30))
+                .unwrap_or(30),
+            smtp_server: env::var("SMTP_SERVER").ok(),
+            smtp_from: env::var("SMTP_FROM")
+                .unwrap_or_else(|_| "easytier-uptime@localhost".to_string()),
+            smtp_username: env::var("SMTP_USERNAME").ok(),
+            smtp_password: env::var("SMTP_PASSWORD").ok(),
+        };
+
         Ok(AppConfig {
             server: server_config,
             database: database_config,
@@ -143,6 +166,7 @@ impl AppConfig {
             logging: logging_config,
             cors: cors_config,
             security: security_config,
+            notification: notification_config,
         })
     }
 
@@ -193,6 +217,13 @@ impl AppConfig {
                 jwt_secret: "default-jwt-secret".to_string(),
                 admin_password: "admin123".to_string(),
             },
+            notification: NotificationConfig {
+                check_interval_seconds: 30,
+                smtp_server: None,
+                smtp_from: "easytier-uptime@localhost".to_string(),
+                smtp_username: None,
+                smtp_password: None,
+            },
         }
     }
 
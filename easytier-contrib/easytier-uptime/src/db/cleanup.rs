@@ -97,6 +97,10 @@ impl CleanupManager {
             Self::cleanup_excess_health_records(db, config.max_health_records_per_node).await?;
         result.excess_health_records_cleaned = excess_cleanup_result.records_removed;
 
+        // 清理旧的探测结果（与健康记录使用相同的保留天数，覆盖状态页的 30 天 SLA 窗口）
+        result.old_probe_results_cleaned =
+            Self::cleanup_old_probe_results(db, config.health_record_retention_days).await?;
+
         // 数据库维护
         let maintenance_result = Self::perform_database_maintenance(db).await?;
         result.vacuum_performed = maintenance_result.vacuum_performed;
@@ -131,6 +135,25 @@ impl CleanupManager {
         Ok(CleanupHealthRecordsResult { records_removed })
     }
 
+    /// 清理旧的探测结果
+    async fn cleanup_old_probe_results(db: &Db, days: i64) -> anyhow::Result<u64> {
+        let cutoff = chrono::Utc::now().fixed_offset() - chrono::Duration::days(days);
+
+        let result = probe_results::Entity::delete_many()
+            .filter(probe_results::Column::ProbedAt.lt(cutoff))
+            .exec(db.orm_db())
+            .await?;
+
+        if result.rows_affected > 0 {
+            info!(
+                "Cleaned {} old probe results (older than {} days)",
+                result.rows_affected, days
+            );
+        }
+
+        Ok(result.rows_affected)
+    }
+
     /// 清理过量的健康记录
     async fn cleanup_excess_health_records(
         db: &Db,
@@ -275,6 +298,7 @@ pub struct CleanupResult {
     pub old_health_records_cleaned: u64,
     pub old_instances_cleaned: u64,
     pub excess_health_records_cleaned: u64,
+    pub old_probe_results_cleaned: u64,
     pub vacuum_performed: bool,
     pub analyze_performed: bool,
 }
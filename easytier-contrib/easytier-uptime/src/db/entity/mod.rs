@@ -3,5 +3,7 @@
 pub mod prelude;
 
 pub mod health_records;
+pub mod node_subscriptions;
 pub mod node_tags;
+pub mod probe_results;
 pub mod shared_nodes;
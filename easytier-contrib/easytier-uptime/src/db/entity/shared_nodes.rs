@@ -25,6 +25,10 @@ pub struct Model {
     pub qq_number: String,
     pub wechat: String,
     pub mail: String,
+    pub probe_protocols: String,
+    pub is_paused: bool,
+    #[serde(skip_serializing, default)]
+    pub owner_token_nonce: String,
     pub created_at: DateTimeWithTimeZone,
     pub updated_at: DateTimeWithTimeZone,
 }
@@ -36,6 +40,10 @@ pub enum Relation {
     // add relation to node_tags
     #[sea_orm(has_many = "super::node_tags::Entity")]
     NodeTags,
+    #[sea_orm(has_many = "super::probe_results::Entity")]
+    ProbeResults,
+    #[sea_orm(has_many = "super::node_subscriptions::Entity")]
+    NodeSubscriptions,
 }
 
 impl Related<super::health_records::Entity> for Entity {
@@ -50,4 +58,16 @@ impl Related<super::node_tags::Entity> for Entity {
     }
 }
 
+impl Related<super::probe_results::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::ProbeResults.def()
+    }
+}
+
+impl Related<super::node_subscriptions::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::NodeSubscriptions.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
@@ -1,6 +1,7 @@
 pub mod cleanup;
 pub mod entity;
 pub mod operations;
+pub mod status;
 
 use std::fmt;
 
@@ -295,8 +296,78 @@ impl entity::health_records::Model {
     }
 }
 
+/// 单次协议探测的测量结果
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProbeMeasurement {
+    /// 探测的协议（tcp/udp/ws/wss/quic）
+    pub protocol: String,
+    /// 从启动实例到与目标节点建立连接的耗时（毫秒）
+    pub handshake_ms: Option<i32>,
+    /// 隧道内 RTT 的平均/最小/最大值（毫秒）
+    pub rtt_avg_ms: Option<f64>,
+    pub rtt_min_ms: Option<f64>,
+    pub rtt_max_ms: Option<f64>,
+    /// 丢包率（0.0 ~ 1.0）
+    pub loss_rate: Option<f64>,
+    /// 采样次数
+    pub samples: i32,
+    /// 失败原因，为 None 表示探测成功
+    pub error: Option<String>,
+}
+
+/// Model 的扩展方法
+impl entity::probe_results::Model {
+    /// 创建新的活动模型
+    pub fn new_active_model(
+        node_id: i32,
+        measurement: ProbeMeasurement,
+    ) -> entity::probe_results::ActiveModel {
+        entity::probe_results::ActiveModel {
+            node_id: Set(node_id),
+            protocol: Set(measurement.protocol),
+            success: Set(measurement.error.is_none()),
+            handshake_ms: Set(measurement.handshake_ms),
+            rtt_avg_ms: Set(measurement.rtt_avg_ms),
+            rtt_min_ms: Set(measurement.rtt_min_ms),
+            rtt_max_ms: Set(measurement.rtt_max_ms),
+            loss_rate: Set(measurement.loss_rate),
+            samples: Set(measurement.samples),
+            error_message: Set(measurement.error.unwrap_or_default()),
+            probed_at: Set(chrono::Utc::now().fixed_offset()),
+            ..Default::default()
+        }
+    }
+}
+
+/// 支持探测的协议
+pub const PROBE_PROTOCOLS: [&str; 5] = ["tcp", "udp", "ws", "wss", "quic"];
+
+/// 将协议列表编码为 probe_protocols 列的存储格式（逗号分隔，去重并统一小写）
+pub fn encode_probe_protocols(protocols: &[String]) -> String {
+    let mut list: Vec<String> = Vec::new();
+    for p in protocols {
+        let p = p.trim().to_lowercase();
+        if !p.is_empty() && !list.contains(&p) {
+            list.push(p);
+        }
+    }
+    list.join(",")
+}
+
 /// Model 的扩展方法
 impl entity::shared_nodes::Model {
+    /// 获取需要探测的协议列表，注册协议总是排在第一位
+    pub fn get_probe_protocols(&self) -> Vec<String> {
+        let mut list = vec![self.protocol.to_lowercase()];
+        for p in self.probe_protocols.split(',') {
+            let p = p.trim().to_lowercase();
+            if !p.is_empty() && !list.contains(&p) {
+                list.push(p);
+            }
+        }
+        list
+    }
+
     /// 创建新的活动模型
     #[allow(clippy::too_many_arguments)]
     pub fn new_active_model(
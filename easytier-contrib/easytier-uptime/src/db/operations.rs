@@ -3,6 +3,8 @@ use crate::db::Db;
 use crate::db::HealthStats;
 use crate::db::HealthStatus;
 use crate::db::entity::*;
+use crate::db::status::ProbeCountRow;
+use crate::db::{ProbeMeasurement, encode_probe_protocols};
 use sea_orm::*;
 use std::collections::{HashMap, HashSet};
 
@@ -29,6 +31,11 @@ impl NodeOperations {
             qq_number: Set(req.qq_number.unwrap_or_default()),
             wechat: Set(req.wechat.unwrap_or_default()),
             mail: Set(req.mail.unwrap_or_default()),
+            probe_protocols: Set(encode_probe_protocols(
+                &req.probe_protocols.unwrap_or_default(),
+            )),
+            is_paused: Set(false),
+            owner_token_nonce: Set(uuid::Uuid::new_v4().to_string()),
             created_at: Set(chrono::Utc::now().fixed_offset()),
             updated_at: Set(chrono::Utc::now().fixed_offset()),
         }
@@ -143,6 +150,39 @@ impl NodeOperations {
     }
 }
 
+impl NodeOperations {
+    /// 暂停或恢复节点的监控
+    pub async fn set_node_paused(
+        db: &Db,
+        id: i32,
+        paused: bool,
+    ) -> Result<shared_nodes::Model, DbErr> {
+        let node = shared_nodes::Entity::find_by_id(id)
+            .one(db.orm_db())
+            .await?
+            .ok_or(DbErr::RecordNotFound("Node not found".to_string()))?;
+
+        let mut node = node.into_active_model();
+        node.is_paused = Set(paused);
+        node.updated_at = Set(chrono::Utc::now().fixed_offset());
+
+        shared_nodes::Entity::update(node).exec(db.orm_db()).await
+    }
+
+    /// 重新生成所有者令牌随机数，使之前签发的令牌全部失效
+    pub async fn rotate_owner_token_nonce(db: &Db, id: i32) -> Result<shared_nodes::Model, DbErr> {
+        let node = shared_nodes::Entity::find_by_id(id)
+            .one(db.orm_db())
+            .await?
+            .ok_or(DbErr::RecordNotFound("Node not found".to_string()))?;
+
+        let mut node = node.into_active_model();
+        node.owner_token_nonce = Set(uuid::Uuid::new_v4().to_string());
+
+        shared_nodes::Entity::update(node).exec(db.orm_db()).await
+    }
+}
+
 /// 健康记录操作
 pub struct HealthOperations;
 
@@ -230,6 +270,154 @@ impl HealthOperations {
         Ok(result.rows_affected)
     }
 }
+/// 协议探测结果操作
+pub struct ProbeOperations;
+
+impl ProbeOperations {
+    /// 保存一次探测结果
+    pub async fn create_probe_result(
+        db: &Db,
+        node_id: i32,
+        measurement: ProbeMeasurement,
+    ) -> Result<probe_results::Model, DbErr> {
+        let record = probe_results::Model::new_active_model(node_id, measurement);
+        let insert_result = probe_results::Entity::insert(record)
+            .exec(db.orm_db())
+            .await?;
+
+        probe_results::Entity::find_by_id(insert_result.last_insert_id)
+            .one(db.orm_db())
+            .await?
+            .ok_or(DbErr::RecordNotFound(
+                "Failed to retrieve created probe result".to_string(),
+            ))
+    }
+
+    /// 获取节点自某时间以来的探测结果（按时间正序）
+    pub async fn get_node_probe_results(
+        db: &Db,
+        node_id: i32,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<probe_results::Model>, DbErr> {
+        probe_results::Entity::find()
+            .filter(probe_results::Column::NodeId.eq(node_id))
+            .filter(probe_results::Column::ProbedAt.gte(since.fixed_offset()))
+            .order_by_asc(probe_results::Column::ProbedAt)
+            .all(db.orm_db())
+            .await
+    }
+
+    /// 按节点和协议统计自某时间以来的探测次数与成功次数
+    pub async fn get_probe_counts(
+        db: &Db,
+        since: chrono::DateTime<chrono::Utc>,
+    ) -> Result<Vec<ProbeCountRow>, DbErr> {
+        probe_results::Entity::find()
+            .select_only()
+            .column(probe_results::Column::NodeId)
+            .column(probe_results::Column::Protocol)
+            .column_as(probe_results::Column::Id.count(), "total")
+            .column_as(
+                sea_query::Expr::cust("SUM(CASE WHEN success THEN 1 ELSE 0 END)"),
+                "successful",
+            )
+            .filter(probe_results::Column::ProbedAt.gte(since.fixed_offset()))
+            .group_by(probe_results::Column::NodeId)
+            .group_by(probe_results::Column::Protocol)
+            .into_model::<ProbeCountRow>()
+            .all(db.orm_db())
+            .await
+    }
+
+    /// 获取每个节点每个协议最近一次的探测结果
+    pub async fn get_latest_probe_results(db: &Db) -> Result<Vec<probe_results::Model>, DbErr> {
+        let latest_ids: Vec<i32> = probe_results::Entity::find()
+            .select_only()
+            .column_as(probe_results::Column::Id.max(), "id")
+            .group_by(probe_results::Column::NodeId)
+            .group_by(probe_results::Column::Protocol)
+            .into_tuple()
+            .all(db.orm_db())
+            .await?;
+
+        if latest_ids.is_empty() {
+            return Ok(vec![]);
+        }
+        probe_results::Entity::find()
+            .filter(probe_results::Column::Id.is_in(latest_ids))
+            .all(db.orm_db())
+            .await
+    }
+}
+
+/// 节点通知订阅操作
+pub struct SubscriptionOperations;
+
+impl SubscriptionOperations {
+    /// 创建订阅
+    pub async fn create_subscription(
+        db: &Db,
+        node_id: i32,
+        kind: String,
+        target: String,
+        notify_recovery: bool,
+    ) -> Result<node_subscriptions::Model, DbErr> {
+        let am = node_subscriptions::ActiveModel {
+            id: NotSet,
+            node_id: Set(node_id),
+            kind: Set(kind),
+            target: Set(target),
+            notify_recovery: Set(notify_recovery),
+            last_notified_state: Set(String::new()),
+            created_at: Set(chrono::Utc::now().fixed_offset()),
+        };
+        am.insert(db.orm_db()).await
+    }
+
+    /// 获取节点的全部订阅
+    pub async fn get_node_subscriptions(
+        db: &Db,
+        node_id: i32,
+    ) -> Result<Vec<node_subscriptions::Model>, DbErr> {
+        node_subscriptions::Entity::find()
+            .filter(node_subscriptions::Column::NodeId.eq(node_id))
+            .order_by_asc(node_subscriptions::Column::Id)
+            .all(db.orm_db())
+            .await
+    }
+
+    /// 获取所有订阅
+    pub async fn get_all_subscriptions(db: &Db) -> Result<Vec<node_subscriptions::Model>, DbErr> {
+        node_subscriptions::Entity::find()
+            .order_by_asc(node_subscriptions::Column::NodeId)
+            .all(db.orm_db())
+            .await
+    }
+
+    /// 删除节点的订阅
+    pub async fn delete_subscription(db: &Db, node_id: i32, id: i32) -> Result<u64, DbErr> {
+        let result = node_subscriptions::Entity::delete_many()
+            .filter(node_subscriptions::Column::Id.eq(id))
+            .filter(node_subscriptions::Column::NodeId.eq(node_id))
+            .exec(db.orm_db())
+            .await?;
+        Ok(result.rows_affected)
+    }
+
+    /// 记录最后一次成功通知的状态
+    pub async fn set_last_notified_state(db: &Db, id: i32, state: &str) -> Result<(), DbErr> {
+        node_subscriptions::Entity::update_many()
+            .col_expr(
+                node_subscriptions::Column::LastNotifiedState,
+                sea_query::Expr::value(state),
+            )
+            .filter(node_subscriptions::Column::Id.eq(id))
+            .exec(db.orm_db())
+            .await?;
+        Ok(())
+    }
+}
+
 impl NodeOperations {
     /// 获取节点的全部标签
     pub async fn get_node_tags(db: &Db, node_id: i32) -> Result<Vec<String>, DbErr> {
@@ -375,6 +563,7 @@ mod tests {
             qq_number: Some("123456789".to_string()),
             wechat: Some("test_wechat".to_string()),
             mail: Some("test@example.com".to_string()),
+            probe_protocols: None,
         };
 
         // 测试创建节点
@@ -423,6 +612,7 @@ mod tests {
             qq_number: Some("123456789".to_string()),
             wechat: Some("test_wechat".to_string()),
             mail: Some("test@example.com".to_string()),
+            probe_protocols: None,
         };
 
         // 创建测试节点
@@ -463,4 +653,135 @@ mod tests {
         assert_eq!(stats.healthy_count, 1);
         assert_eq!(stats.health_percentage, 100.0);
     }
+
+    #[tokio::test]
+    async fn test_probe_operations() {
+        let db = Db::memory_db().await;
+
+        let req = CreateNodeRequest {
+            name: "Test Node".to_string(),
+            host: "test.example.com".to_string(),
+            port: 11010,
+            protocol: "tcp".to_string(),
+            description: None,
+            max_connections: 100,
+            allow_relay: false,
+            network_name: "test-network".to_string(),
+            network_secret: None,
+            qq_number: None,
+            wechat: None,
+            mail: Some("test@example.com".to_string()),
+            probe_protocols: Some(vec!["UDP".to_string(), "tcp".to_string()]),
+        };
+        let node = NodeOperations::create_node(&db, req).await.unwrap();
+        assert_eq!(node.get_probe_protocols(), vec!["tcp", "udp"]);
+
+        let since = chrono::Utc::now() - chrono::Duration::hours(1);
+        for (protocol, error) in [
+            ("tcp", None),
+            ("tcp", Some("handshake timed out".to_string())),
+            ("udp", None),
+        ] {
+            let measurement = ProbeMeasurement {
+                protocol: protocol.to_string(),
+                samples: 10,
+                error,
+                ..Default::default()
+            };
+            ProbeOperations::create_probe_result(&db, node.id, measurement)
+                .await
+                .unwrap();
+        }
+
+        let records = ProbeOperations::get_node_probe_results(&db, node.id, since)
+            .await
+            .unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records.iter().filter(|r| !r.success).count(), 1);
+
+        let mut counts = ProbeOperations::get_probe_counts(&db, since).await.unwrap();
+        counts.sort_by(|a, b| a.protocol.cmp(&b.protocol));
+        assert_eq!(counts.len(), 2);
+        assert_eq!((counts[0].total, counts[0].successful), (2, 1));
+        assert_eq!((counts[1].total, counts[1].successful), (1, 1));
+
+        let latest = ProbeOperations::get_latest_probe_results(&db)
+            .await
+            .unwrap();
+        assert_eq!(latest.len(), 2);
+        assert!(latest.iter().any(|r| r.protocol == "tcp" && !r.success));
+    }
+
+    #[tokio::test]
+    async fn test_owner_operations() {
+        let db = Db::memory_db().await;
+
+        let req = CreateNodeRequest {
+            name: "Test Node".to_string(),
+            host: "test.example.com".to_string(),
+            port: 11010,
+            protocol: "tcp".to_string(),
+            description: None,
+            max_connections: 100,
+            allow_relay: false,
+            network_name: "test-network".to_string(),
+            network_secret: None,
+            qq_number: None,
+            wechat: None,
+            mail: Some("test@example.com".to_string()),
+            probe_protocols: None,
+        };
+        let node = NodeOperations::create_node(&db, req).await.unwrap();
+        assert!(!node.is_paused);
+        assert!(!node.owner_token_nonce.is_empty());
+
+        let paused = NodeOperations::set_node_paused(&db, node.id, true)
+            .await
+            .unwrap();
+        assert!(paused.is_paused);
+
+        let rotated = NodeOperations::rotate_owner_token_nonce(&db, node.id)
+            .await
+            .unwrap();
+        assert_ne!(rotated.owner_token_nonce, node.owner_token_nonce);
+
+        let sub = SubscriptionOperations::create_subscription(
+            &db,
+            node.id,
+            "webhook".to_string(),
+            "https://example.com/hook".to_string(),
+            true,
+        )
+        .await
+        .unwrap();
+        SubscriptionOperations::set_last_notified_state(&db, sub.id, "down")
+            .await
+            .unwrap();
+
+        let subs = SubscriptionOperations::get_node_subscriptions(&db, node.id)
+            .await
+            .unwrap();
+        assert_eq!(subs.len(), 1);
+        assert_eq!(subs[0].last_notified_state, "down");
+
+        // 不能删除其他节点的订阅
+        assert_eq!(
+            SubscriptionOperations::delete_subscription(&db, node.id + 1, sub.id)
+                .await
+                .unwrap(),
+            0
+        );
+        assert_eq!(
+            SubscriptionOperations::delete_subscription(&db, node.id, sub.id)
+                .await
+                .unwrap(),
+            1
+        );
+        assert!(
+            SubscriptionOperations::get_all_subscriptions(&db)
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
 }
@@ -0,0 +1,212 @@
+//! 状态页统计：按协议计算 SLA，并根据探测结果的状态变化推导故障事件
+
+use chrono::{DateTime, Utc};
+use sea_orm::FromQueryResult;
+use serde::{Deserialize, Serialize};
+
+use super::entity::probe_results;
+
+/// 状态页展示的 SLA 统计窗口（名称，小时数）
+pub const SLA_WINDOWS: [(&str, i64); 3] = [("24h", 24), ("7d", 24 * 7), ("30d", 24 * 30)];
+
+/// 某个时间窗口内的 SLA
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlaWindow {
+    /// 窗口名称（24h/7d/30d）
+    pub window: String,
+    /// 窗口内的探测次数
+    pub total_probes: u64,
+    /// 窗口内成功的探测次数
+    pub successful_probes: u64,
+    /// 可用率百分比，窗口内没有探测时为 None
+    pub sla_percentage: Option<f64>,
+}
+
+/// 由连续失败的探测组成的一次故障
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Incident {
+    pub protocol: String,
+    /// 第一次失败的探测时间
+    pub started_at: DateTime<Utc>,
+    /// 恢复后第一次成功的探测时间，仍在故障中时为 None
+    pub resolved_at: Option<DateTime<Utc>>,
+    /// 故障持续时间（秒），仍在故障中时为 None
+    pub duration_secs: Option<i64>,
+    /// 故障期间失败的探测次数
+    pub failed_probes: u64,
+    /// 故障期间最后一次的错误信息
+    pub last_error: String,
+}
+
+impl SlaWindow {
+    pub fn from_counts(window: &str, total_probes: u64, successful_probes: u64) -> Self {
+        Self {
+            window: window.to_string(),
+            total_probes,
+            successful_probes,
+            sla_percentage: (total_probes > 0)
+                .then(|| successful_probes as f64 / total_probes as f64 * 100.0),
+        }
+    }
+}
+
+/// 按节点和协议聚合的探测次数
+#[derive(Debug, Clone, FromQueryResult)]
+pub struct ProbeCountRow {
+    pub node_id: i32,
+    pub protocol: String,
+    pub total: i64,
+    pub successful: i64,
+}
+
+/// 根据最近一次探测给出协议当前状态
+pub fn protocol_status(latest: Option<&probe_results::Model>) -> &'static str {
+    match latest {
+        Some(r) if r.success => "up",
+        Some(_) => "down",
+        None => "unknown",
+    }
+}
+
+/// 计算 `since` 之后探测结果的 SLA
+pub fn compute_sla(
+    window: &str,
+    records: &[probe_results::Model],
+    since: DateTime<Utc>,
+) -> SlaWindow {
+    let in_window = records.iter().filter(|r| r.probed_at.to_utc() >= since);
+    let (total_probes, successful_probes) = in_window.fold((0u64, 0u64), |(total, ok), r| {
+        (total + 1, ok + u64::from(r.success))
+    });
+
+    SlaWindow::from_counts(window, total_probes, successful_probes)
+}
+
+/// 计算状态页上所有窗口的 SLA
+pub fn compute_sla_windows(records: &[probe_results::Model], now: DateTime<Utc>) -> Vec<SlaWindow> {
+    SLA_WINDOWS
+        .iter()
+        .map(|(name, hours)| compute_sla(name, records, now - chrono::Duration::hours(*hours)))
+        .collect()
+}
+
+/// 从同一协议的探测结果推导故障事件：由成功变为失败时开始一次故障，再次成功时结束。
+/// 返回的故障按开始时间正序排列。
+pub fn derive_incidents(protocol: &str, records: &[probe_results::Model]) -> Vec<Incident> {
+    let mut sorted: Vec<&probe_results::Model> =
+        records.iter().filter(|r| r.protocol == protocol).collect();
+    sorted.sort_by_key(|r| r.probed_at);
+
+    let mut incidents = Vec::new();
+    let mut current: Option<Incident> = None;
+
+    for record in sorted {
+        let probed_at = record.probed_at.to_utc();
+        match (record.success, current.take()) {
+            (false, Some(mut incident)) => {
+                incident.failed_probes += 1;
+                incident.last_error = record.error_message.clone();
+                current = Some(incident);
+            }
+            (false, None) => {
+                current = Some(Incident {
+                    protocol: protocol.to_string(),
+                    started_at: probed_at,
+                    resolved_at: None,
+                    duration_secs: None,
+                    failed_probes: 1,
+                    last_error: record.error_message.clone(),
+                });
+            }
+            (true, Some(mut incident)) => {
+                incident.resolved_at = Some(probed_at);
+                incident.duration_secs = Some((probed_at - incident.started_at).num_seconds());
+                incidents.push(incident);
+            }
+            (true, None) => {}
+        }
+    }
+
+    incidents.extend(current);
+    incidents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn probe(id: i32, protocol: &str, success: bool, minutes_ago: i64) -> probe_results::Model {
+        probe_results::Model {
+            id,
+            node_id: 1,
+            protocol: protocol.to_string(),
+            success,
+            handshake_ms: success.then_some(50),
+            rtt_avg_ms: success.then_some(20.0),
+            rtt_min_ms: success.then_some(10.0),
+            rtt_max_ms: success.then_some(30.0),
+            loss_rate: success.then_some(0.0),
+            samples: 10,
+            error_message: if success {
+                String::new()
+            } else {
+                format!("error {}", id)
+            },
+            probed_at: (Utc::now() - chrono::Duration::minutes(minutes_ago)).fixed_offset(),
+        }
+    }
+
+    #[test]
+    fn test_sla_windows() {
+        let now = Utc::now();
+        let records = vec![
+            probe(1, "tcp", false, 60 * 24 * 10),
+            probe(2, "tcp", true, 60 * 24 * 3),
+            probe(3, "tcp", false, 60),
+            probe(4, "tcp", true, 30),
+        ];
+
+        let windows = compute_sla_windows(&records, now);
+        assert_eq!(windows.len(), 3);
+
+        assert_eq!(windows[0].window, "24h");
+        assert_eq!(windows[0].total_probes, 2);
+        assert_eq!(windows[0].sla_percentage, Some(50.0));
+
+        assert_eq!(windows[1].total_probes, 3);
+        assert_eq!(windows[1].successful_probes, 2);
+
+        assert_eq!(windows[2].total_probes, 4);
+        assert_eq!(windows[2].sla_percentage, Some(50.0));
+
+        let empty = compute_sla("24h", &[], now - chrono::Duration::hours(24));
+        assert_eq!(empty.sla_percentage, None);
+    }
+
+    #[test]
+    fn test_derive_incidents() {
+        let records = vec![
+            probe(1, "tcp", true, 50),
+            probe(2, "tcp", false, 40),
+            probe(3, "udp", false, 35),
+            probe(4, "tcp", false, 30),
+            probe(5, "tcp", true, 20),
+            probe(6, "tcp", false, 10),
+        ];
+
+        let incidents = derive_incidents("tcp", &records);
+        assert_eq!(incidents.len(), 2);
+
+        assert_eq!(incidents[0].failed_probes, 2);
+        assert_eq!(incidents[0].last_error, "error 4");
+        assert_eq!(incidents[0].duration_secs, Some(20 * 60));
+
+        // 最后一次故障尚未恢复
+        assert_eq!(incidents[1].failed_probes, 1);
+        assert!(incidents[1].resolved_at.is_none());
+
+        let udp = derive_incidents("udp", &records);
+        assert_eq!(udp.len(), 1);
+        assert!(udp[0].resolved_at.is_none());
+    }
+}
@@ -11,6 +11,7 @@ use easytier::{
         ConfigFileControl, ConfigLoader, NetworkIdentity, PeerConfig, TomlConfigLoader,
     },
     instance_manager::NetworkInstanceManager,
+    proto::api::instance::PeerRoutePair,
 };
 use guarden::defer;
 use serde::{Deserialize, Serialize};
@@ -21,8 +22,9 @@ use tracing::{debug, error, info, instrument, warn};
 use crate::db::{
     Db, HealthStatus,
     entity::shared_nodes,
-    operations::{HealthOperations, NodeOperations},
+    operations::{HealthOperations, NodeOperations, ProbeOperations},
 };
+use crate::prober::{self, PROBE_INTERVAL, ProbeTarget};
 
 pub struct HealthCheckOneNode {
     node_id: String,
@@ -37,6 +39,16 @@ const HEALTH_CHECK_RING_MAX_DURATION_SEC: usize = 60 * 60 * 24; // 最多一天
 const HEALTH_CHECK_RING_SIZE: usize =
     HEALTH_CHECK_RING_MAX_DURATION_SEC / HEALTH_CHECK_RING_GRANULARITY_SEC;
 
+/// 查找健康检查实例直连的目标节点
+pub(crate) fn find_dst_peer(pairs: &[PeerRoutePair]) -> Option<&PeerRoutePair> {
+    pairs.iter().find(|x| {
+        // we disable p2p, so we only check direct connected peer
+        x.route.as_ref().is_some_and(|route| {
+            !route.feature_flag.unwrap().is_public_server && route.hostname != "HealthCheckNode"
+        }) && x.peer.as_ref().is_some_and(|p| !p.conns.is_empty())
+    })
+}
+
 #[derive(Debug, Default, Clone)]
 struct RingItem {
     counter: u64,
@@ -241,6 +253,7 @@ pub struct HealthChecker {
     instance_mgr: Arc<NetworkInstanceManager>,
     inst_id_map: DashMap<i32, uuid::Uuid>,
     node_tasks: DashMap<i32, AbortOnDropHandle<()>>,
+    probe_tasks: DashMap<i32, (Vec<ProbeTarget>, AbortOnDropHandle<()>)>,
     node_records: Arc<DashMap<i32, HealthyMemRecord>>,
     node_cfg: Arc<DashMap<i32, TomlConfigLoader>>,
 }
@@ -253,6 +266,7 @@ impl HealthChecker {
             instance_mgr,
             inst_id_map: DashMap::new(),
             node_tasks: DashMap::new(),
+            probe_tasks: DashMap::new(),
             node_records: Arc::new(DashMap::new()),
             node_cfg: Arc::new(DashMap::new()),
         }
@@ -335,30 +349,67 @@ impl HealthChecker {
             .get(&node_id)
             .ok_or_else(|| anyhow::anyhow!("old node cfg not found, node_id: {}", node_id))?
             .clone();
-        let new_cfg = self.get_node_cfg(node_id, Some(old_cfg.get_id())).await?;
+        let node_info = NodeOperations::get_node_by_id(&self.db, node_id)
+            .await
+            .with_context(|| format!("failed to get node by id: {}", node_id))?
+            .ok_or_else(|| anyhow::anyhow!("node not found"))?;
+        let new_cfg = self
+            .get_node_cfg_with_model(&node_info, Some(old_cfg.get_id()))
+            .await?;
 
         if new_cfg.dump() != old_cfg.dump() {
             self.remove_node(node_id).await?;
             self.add_node(node_id).await?;
             info!("node {} cfg updated", node_id);
+            return Ok(());
+        }
+
+        // 探测协议变化时重新调度探测任务
+        let targets = prober::probe_targets(&node_info);
+        let changed = self
+            .probe_tasks
+            .get(&node_id)
+            .is_none_or(|entry| entry.0 != targets);
+        if changed {
+            self.start_probe_task(node_info, targets);
+            info!("node {} probe targets updated", node_id);
         }
 
         Ok(())
     }
 
+    /// 启动（或替换）节点的协议探测任务
+    fn start_probe_task(&self, node_info: shared_nodes::Model, targets: Vec<ProbeTarget>) {
+        let node_id = node_info.id;
+        let task = AbortOnDropHandle::new(tokio::spawn(Self::node_probe_task(
+            node_info,
+            targets.clone(),
+            Arc::clone(&self.instance_mgr),
+            self.db.clone(),
+        )));
+        self.probe_tasks.insert(node_id, (targets, task));
+    }
+
     async fn get_node_cfg_with_model(
         &self,
         node_info: &shared_nodes::Model,
         inst_id: Option<uuid::Uuid>,
+    ) -> anyhow::Result<TomlConfigLoader> {
+        let uri = format!(
+            "{}://{}:{}",
+            node_info.protocol, node_info.host, node_info.port
+        );
+        Self::build_node_cfg(node_info, &uri, inst_id)
+    }
+
+    fn build_node_cfg(
+        node_info: &shared_nodes::Model,
+        uri: &str,
+        inst_id: Option<uuid::Uuid>,
     ) -> anyhow::Result<TomlConfigLoader> {
         let cfg = TomlConfigLoader::default();
         cfg.set_peers(vec![PeerConfig {
-            uri: format!(
-                "{}://{}:{}",
-                node_info.protocol, node_info.host, node_info.port
-            )
-            .parse()
-            .with_context(|| "failed to parse peer uri")?,
+            uri: uri.parse().with_context(|| "failed to parse peer uri")?,
             peer_public_key: None,
         }]);
 
@@ -416,20 +467,12 @@ impl HealthChecker {
         Err(anyhow::anyhow!("test node healthy failed, err: {:?}", err))
     }
 
-    async fn get_node_cfg(
-        &self,
-        node_id: i32,
-        inst_id: Option<uuid::Uuid>,
-    ) -> anyhow::Result<TomlConfigLoader> {
+    pub async fn add_node(&self, node_id: i32) -> anyhow::Result<()> {
         let node_info = NodeOperations::get_node_by_id(&self.db, node_id)
             .await
             .with_context(|| format!("failed to get node by id: {}", node_id))?
             .ok_or_else(|| anyhow::anyhow!("node not found"))?;
-        self.get_node_cfg_with_model(&node_info, inst_id).await
-    }
-
-    pub async fn add_node(&self, node_id: i32) -> anyhow::Result<()> {
-        let cfg = self.get_node_cfg(node_id, None).await?;
+        let cfg = self.get_node_cfg_with_model(&node_info, None).await?;
         info!(
             "Add node {} to health checker, cfg: {}",
             node_id,
@@ -475,11 +518,16 @@ impl HealthChecker {
         self.node_tasks.insert(node_id, task);
         self.node_cfg.insert(node_id, cfg.clone());
 
+        // 启动协议探测任务
+        let targets = prober::probe_targets(&node_info);
+        self.start_probe_task(node_info, targets);
+
         Ok(())
     }
 
     pub async fn remove_node(&self, node_id: i32) -> anyhow::Result<()> {
         self.node_tasks.remove(&node_id);
+        self.probe_tasks.remove(&node_id);
         if let Some(inst_id) = self.inst_id_map.remove(&node_id) {
             let _ = self.instance_mgr.delete_network_instance(vec![inst_id.1]);
         }
@@ -512,14 +560,8 @@ impl HealthChecker {
             anyhow::bail!("healthy check node has error: {}", err);
         }
 
-        let p = instance.peer_route_pairs;
         // dst node is not online
-        let Some(dst_node) = p.iter().find(|x| {
-            // we disable p2p, so we only check direct connected peer
-            x.route.as_ref().is_some_and(|route| {
-                !route.feature_flag.unwrap().is_public_server && route.hostname != "HealthCheckNode"
-            }) && x.peer.as_ref().is_some_and(|p| !p.conns.is_empty())
-        }) else {
+        let Some(dst_node) = find_dst_peer(&instance.peer_route_pairs) else {
             anyhow::bail!("dst node is not online");
         };
 
@@ -563,6 +605,41 @@ impl HealthChecker {
         Ok((version, response_time, conn_count))
     }
 
+    async fn node_probe_task(
+        node_info: shared_nodes::Model,
+        targets: Vec<ProbeTarget>,
+        instance_mgr: Arc<NetworkInstanceManager>,
+        db: Db,
+    ) {
+        let mut tick = tokio::time::interval(PROBE_INTERVAL);
+        loop {
+            tick.tick().await;
+
+            for target in &targets {
+                let uri = target.uri(&node_info.host);
+                let cfg = match Self::build_node_cfg(&node_info, &uri, None) {
+                    Ok(cfg) => cfg,
+                    Err(e) => {
+                        error!("Failed to build probe cfg for {}: {:#}", uri, e);
+                        continue;
+                    }
+                };
+
+                let measurement = prober::run_probe(instance_mgr.clone(), cfg, target).await;
+                debug!("probe result of node {}: {:?}", node_info.id, measurement);
+
+                if let Err(e) =
+                    ProbeOperations::create_probe_result(&db, node_info.id, measurement).await
+                {
+                    error!(
+                        "Failed to create probe result for node {}: {}",
+                        node_info.id, e
+                    );
+                }
+            }
+        }
+    }
+
     async fn node_health_check_task(
         node_id: i32,
         inst_id: uuid::Uuid,
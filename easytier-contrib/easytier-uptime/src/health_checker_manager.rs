@@ -67,7 +67,12 @@ impl HealthCheckerManager {
             .await
             .with_context(|| "Failed to get all nodes from database")?;
 
-        let db_node_ids: HashSet<i32> = db_nodes.iter().map(|node| node.id).collect();
+        // 所有者暂停的节点不再监控
+        let db_node_ids: HashSet<i32> = db_nodes
+            .iter()
+            .filter(|node| !node.is_paused)
+            .map(|node| node.id)
+            .collect();
 
         let mut current_nodes_guard = current_nodes.write().await;
 
@@ -6,6 +6,8 @@ mod db;
 mod health_checker;
 mod health_checker_manager;
 mod migrator;
+mod notifier;
+mod prober;
 
 use api::routes::create_routes;
 use clap::Parser;
@@ -14,6 +16,7 @@ use db::{Db, operations::NodeOperations};
 use easytier::common::log;
 use health_checker::HealthChecker;
 use health_checker_manager::HealthCheckerManager;
+use notifier::OwnerNotifier;
 use std::env;
 use std::net::SocketAddr;
 use std::path::PathBuf;
@@ -79,6 +82,9 @@ async fn main() -> anyhow::Result<()> {
     let cleanup_manager = CleanupManager::new(db.clone(), CleanupConfig::default());
     cleanup_manager.start_auto_cleanup().await?;
 
+    // 启动节点所有者的故障通知
+    OwnerNotifier::new(db.clone(), config.notification.clone()).start();
+
     // 启动节点监控
     health_checker_manager.start_monitoring().await?;
     tracing::info!("Health checker manager started successfully!");
@@ -108,11 +114,22 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("  DELETE /api/nodes/:id - Delete node");
     tracing::info!("  GET  /api/nodes/:id/health - Get node health history");
     tracing::info!("  GET  /api/nodes/:id/health/stats - Get node health stats");
+    tracing::info!("  GET  /api/status - Status page overview with per-protocol SLA");
+    tracing::info!("  GET  /api/status/nodes/:id - Status page node detail with incidents");
+    tracing::info!("Owner endpoints (owner token returned on node creation):");
+    tracing::info!("  GET  /api/owner/nodes/:id - Get own node");
+    tracing::info!("  PUT  /api/owner/nodes/:id - Update own node");
+    tracing::info!("  PUT  /api/owner/nodes/:id/pause - Pause/resume monitoring");
+    tracing::info!("  POST /api/owner/nodes/:id/token/rotate - Rotate owner token");
+    tracing::info!("  GET  /api/owner/nodes/:id/subscriptions - List outage subscriptions");
+    tracing::info!("  POST /api/owner/nodes/:id/subscriptions - Subscribe to outage notifications");
+    tracing::info!("  DELETE /api/owner/nodes/:id/subscriptions/:sub_id - Unsubscribe");
     tracing::info!("Admin endpoints:");
     tracing::info!("  POST /api/admin/login - Admin login");
     tracing::info!("  GET  /api/admin/nodes - Get all nodes (including pending)");
     tracing::info!("  PUT  /api/admin/nodes/:id/approve - Approve/reject node");
     tracing::info!("  DELETE /api/admin/nodes/:id - Delete node (admin only)");
+    tracing::info!("  PUT  /api/admin/nodes/:id/owner-token - Reissue owner token");
 
     // 启动服务器
     let listener = tokio::net::TcpListener::bind(addr).await?;
@@ -0,0 +1,116 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum ProbeResults {
+    Table,
+    Id,
+    NodeId,
+    Protocol,
+    Success,
+    HandshakeMs,
+    RttAvgMs,
+    RttMinMs,
+    RttMaxMs,
+    LossRate,
+    Samples,
+    ErrorMessage,
+    ProbedAt,
+}
+
+#[derive(DeriveIden)]
+enum SharedNodes {
+    Table,
+    Id,
+    ProbeProtocols,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // 节点额外探测的协议列表（逗号分隔），为空时只探测注册协议
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SharedNodes::Table)
+                    .add_column(string(SharedNodes::ProbeProtocols).default(""))
+                    .to_owned(),
+            )
+            .await?;
+
+        // 创建 probe_results 表
+        manager
+            .create_table(
+                Table::create()
+                    .table(ProbeResults::Table)
+                    .if_not_exists()
+                    .col(pk_auto(ProbeResults::Id).not_null())
+                    .col(integer(ProbeResults::NodeId).not_null())
+                    .col(string(ProbeResults::Protocol).not_null())
+                    .col(boolean(ProbeResults::Success).not_null())
+                    .col(integer_null(ProbeResults::HandshakeMs))
+                    .col(double_null(ProbeResults::RttAvgMs))
+                    .col(double_null(ProbeResults::RttMinMs))
+                    .col(double_null(ProbeResults::RttMaxMs))
+                    .col(double_null(ProbeResults::LossRate))
+                    .col(integer(ProbeResults::Samples).default(0))
+                    .col(text(ProbeResults::ErrorMessage).default(""))
+                    .col(
+                        timestamp_with_time_zone(ProbeResults::ProbedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_probe_results_node")
+                            .from(ProbeResults::Table, ProbeResults::NodeId)
+                            .to(SharedNodes::Table, SharedNodes::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // 索引：按节点、协议、时间查询
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_probe_results_node_protocol_time")
+                    .table(ProbeResults::Table)
+                    .col(ProbeResults::NodeId)
+                    .col(ProbeResults::Protocol)
+                    .col(ProbeResults::ProbedAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_probe_results_node_protocol_time")
+                    .table(ProbeResults::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(ProbeResults::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SharedNodes::Table)
+                    .drop_column(SharedNodes::ProbeProtocols)
+                    .to_owned(),
+            )
+            .await
+    }
+}
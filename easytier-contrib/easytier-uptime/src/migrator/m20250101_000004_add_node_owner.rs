@@ -0,0 +1,121 @@
+use sea_orm_migration::{prelude::*, schema::*};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(DeriveIden)]
+enum NodeSubscriptions {
+    Table,
+    Id,
+    NodeId,
+    Kind,
+    Target,
+    NotifyRecovery,
+    LastNotifiedState,
+    CreatedAt,
+}
+
+#[derive(DeriveIden)]
+enum SharedNodes {
+    Table,
+    Id,
+    IsPaused,
+    OwnerTokenNonce,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // SQLite 每条 ALTER TABLE 只能添加一列
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SharedNodes::Table)
+                    .add_column(boolean(SharedNodes::IsPaused).default(false))
+                    .to_owned(),
+            )
+            .await?;
+        // 节点所有者令牌的随机数，为空表示尚未签发令牌
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SharedNodes::Table)
+                    .add_column(string(SharedNodes::OwnerTokenNonce).default(""))
+                    .to_owned(),
+            )
+            .await?;
+
+        // 创建 node_subscriptions 表
+        manager
+            .create_table(
+                Table::create()
+                    .table(NodeSubscriptions::Table)
+                    .if_not_exists()
+                    .col(pk_auto(NodeSubscriptions::Id).not_null())
+                    .col(integer(NodeSubscriptions::NodeId).not_null())
+                    .col(string(NodeSubscriptions::Kind).not_null())
+                    .col(string(NodeSubscriptions::Target).not_null())
+                    .col(boolean(NodeSubscriptions::NotifyRecovery).default(true))
+                    .col(string(NodeSubscriptions::LastNotifiedState).default(""))
+                    .col(
+                        timestamp_with_time_zone(NodeSubscriptions::CreatedAt)
+                            .not_null()
+                            .default(Expr::current_timestamp()),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("fk_node_subscriptions_node")
+                            .from(NodeSubscriptions::Table, NodeSubscriptions::NodeId)
+                            .to(SharedNodes::Table, SharedNodes::Id)
+                            .on_delete(ForeignKeyAction::Cascade)
+                            .on_update(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .create_index(
+                Index::create()
+                    .name("idx_node_subscriptions_node")
+                    .table(NodeSubscriptions::Table)
+                    .col(NodeSubscriptions::NodeId)
+                    .to_owned(),
+            )
+            .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_index(
+                Index::drop()
+                    .name("idx_node_subscriptions_node")
+                    .table(NodeSubscriptions::Table)
+                    .to_owned(),
+            )
+            .await?;
+
+        manager
+            .drop_table(Table::drop().table(NodeSubscriptions::Table).to_owned())
+            .await?;
+
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SharedNodes::Table)
+                    .drop_column(SharedNodes::OwnerTokenNonce)
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(SharedNodes::Table)
+                    .drop_column(SharedNodes::IsPaused)
+                    .to_owned(),
+            )
+            .await
+    }
+}
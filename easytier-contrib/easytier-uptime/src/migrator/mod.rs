@@ -2,6 +2,8 @@ use sea_orm_migration::prelude::*;
 
 mod m20250101_000001_create_tables;
 mod m20250101_000002_create_node_tags;
+mod m20250101_000003_create_probe_results;
+mod m20250101_000004_add_node_owner;
 
 pub struct Migrator;
 
@@ -11,6 +13,8 @@ impl MigratorTrait for Migrator {
         vec![
             Box::new(m20250101_000001_create_tables::Migration),
             Box::new(m20250101_000002_create_node_tags::Migration),
+            Box::new(m20250101_000003_create_probe_results::Migration),
+            Box::new(m20250101_000004_add_node_owner::Migration),
         ]
     }
 }
@@ -0,0 +1,614 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+    net::TcpStream,
+};
+use tracing::{error, info, warn};
+
+use crate::config::NotificationConfig;
+use crate::db::{
+    Db,
+    entity::{health_records, node_subscriptions, shared_nodes},
+    operations::{HealthOperations, NodeOperations, SubscriptionOperations},
+};
+
+/// 支持的订阅方式
+pub const SUBSCRIPTION_KINDS: [&str; 2] = ["webhook", "email"];
+
+/// 状态需要持续多久才会被确认（秒）
+const STATE_CONFIRM_SECS: i64 = 120;
+/// 判断状态时读取的健康记录范围（秒）
+const STATE_LOOKBACK_SECS: i64 = 600;
+
+/// 每次状态变化增加的惩罚值
+const FLAP_PENALTY: f64 = 1000.0;
+/// 惩罚值的半衰期（秒）
+const FLAP_HALF_LIFE_SECS: f64 = 900.0;
+/// 惩罚值超过该阈值后暂停通知
+const FLAP_SUPPRESS_THRESHOLD: f64 = 2500.0;
+/// 惩罚值衰减到该阈值以下后恢复通知
+const FLAP_REUSE_THRESHOLD: f64 = 750.0;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+const SMTP_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NodeState {
+    Up,
+    Down,
+}
+
+impl NodeState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeState::Up => "up",
+            NodeState::Down => "down",
+        }
+    }
+}
+
+/// 根据按时间倒序排列的健康记录判断节点状态：最近连续相同结果持续超过
+/// `STATE_CONFIRM_SECS` 才确认，否则返回 None
+pub fn observed_state(records: &[health_records::Model]) -> Option<NodeState> {
+    let latest = records.first()?;
+    let healthy = latest.is_healthy();
+    let streak_start = records
+        .iter()
+        .take_while(|r| r.is_healthy() == healthy)
+        .last()?;
+
+    let streak = latest.checked_at - streak_start.checked_at;
+    if streak.num_seconds() < STATE_CONFIRM_SECS {
+        return None;
+    }
+    Some(if healthy {
+        NodeState::Up
+    } else {
+        NodeState::Down
+    })
+}
+
+/// 抖动抑制：每次状态变化累积惩罚值并按半衰期衰减，超过阈值后暂停通知，
+/// 衰减到复用阈值以下后恢复
+#[derive(Debug, Clone)]
+pub struct FlapDamper {
+    penalty: f64,
+    updated_at: DateTime<Utc>,
+    suppressed: bool,
+}
+
+impl FlapDamper {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            penalty: 0.0,
+            updated_at: now,
+            suppressed: false,
+        }
+    }
+
+    fn decay(&mut self, now: DateTime<Utc>) {
+        let elapsed = (now - self.updated_at).num_milliseconds() as f64 / 1000.0;
+        if elapsed > 0.0 {
+            self.penalty *= 0.5f64.powf(elapsed / FLAP_HALF_LIFE_SECS);
+            self.updated_at = now;
+        }
+        if self.suppressed && self.penalty <= FLAP_REUSE_THRESHOLD {
+            self.suppressed = false;
+        }
+    }
+
+    pub fn record_transition(&mut self, now: DateTime<Utc>) {
+        self.decay(now);
+        self.penalty += FLAP_PENALTY;
+        if self.penalty >= FLAP_SUPPRESS_THRESHOLD {
+            self.suppressed = true;
+        }
+    }
+
+    pub fn is_suppressed(&mut self, now: DateTime<Utc>) -> bool {
+        self.decay(now);
+        self.suppressed
+    }
+}
+
+/// 单个节点的通知状态
+struct NodeTracker {
+    observed: Option<NodeState>,
+    damper: FlapDamper,
+}
+
+impl NodeTracker {
+    fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            observed: None,
+            damper: FlapDamper::new(now),
+        }
+    }
+
+    /// 更新观测到的状态，返回可以用于通知的稳定状态；抖动被抑制时返回 None
+    fn update(&mut self, state: Option<NodeState>, now: DateTime<Utc>) -> Option<NodeState> {
+        if let Some(state) = state {
+            if self.observed.is_some_and(|observed| observed != state) {
+                self.damper.record_transition(now);
+            }
+            self.observed = Some(state);
+        }
+        if self.damper.is_suppressed(now) {
+            return None;
+        }
+        self.observed
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscriptionAction {
+    /// 无需处理
+    Nothing,
+    /// 只记录状态，不发送通知
+    MarkOnly,
+    /// 发送通知并记录状态
+    Notify,
+}
+
+/// 决定订阅在节点处于 `state` 时需要做什么
+pub fn subscription_action(
+    subscription: &node_subscriptions::Model,
+    state: NodeState,
+) -> SubscriptionAction {
+    if subscription.last_notified_state == state.as_str() {
+        return SubscriptionAction::Nothing;
+    }
+    match state {
+        NodeState::Down => SubscriptionAction::Notify,
+        // 第一次看到节点在线时不发送恢复通知
+        NodeState::Up
+            if subscription.last_notified_state.is_empty() || !subscription.notify_recovery =>
+        {
+            SubscriptionAction::MarkOnly
+        }
+        NodeState::Up => SubscriptionAction::Notify,
+    }
+}
+
+/// 发送给订阅者的通知内容
+#[derive(Debug, Clone, Serialize)]
+pub struct NodeNotification {
+    /// outage / recovery
+    pub event: String,
+    pub state: NodeState,
+    pub node_id: i32,
+    pub node_name: String,
+    pub address: String,
+    pub message: String,
+    pub last_error: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+impl NodeNotification {
+    fn new(
+        node: &shared_nodes::Model,
+        state: NodeState,
+        latest: Option<&health_records::Model>,
+    ) -> Self {
+        let address = format!("{}://{}:{}", node.protocol, node.host, node.port);
+        let (event, message) = match state {
+            NodeState::Down => (
+                "outage",
+                format!("Node {} ({}) is down", node.name, address),
+            ),
+            NodeState::Up => (
+                "recovery",
+                format!("Node {} ({}) has recovered", node.name, address),
+            ),
+        };
+        Self {
+            event: event.to_string(),
+            state,
+            node_id: node.id,
+            node_name: node.name.clone(),
+            address,
+            message,
+            last_error: latest
+                .filter(|r| !r.error_message.is_empty())
+                .map(|r| r.error_message.clone()),
+            occurred_at: Utc::now(),
+        }
+    }
+
+    fn title(&self) -> String {
+        format!("[EasyTier Uptime] {}", self.message)
+    }
+}
+
+/// 节点所有者通知管理器
+pub struct OwnerNotifier {
+    db: Db,
+    config: NotificationConfig,
+    client: reqwest::Client,
+}
+
+impl OwnerNotifier {
+    pub fn new(db: Db, config: NotificationConfig) -> Self {
+        Self {
+            db,
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// 启动通知任务
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Owner notifier started");
+            let mut trackers = HashMap::new();
+            let mut tick =
+                tokio::time::interval(Duration::from_secs(self.config.check_interval_seconds));
+            loop {
+                tick.tick().await;
+                if let Err(e) = self.check_once(&mut trackers).await {
+                    error!("Owner notifier check failed: {:#}", e);
+                }
+            }
+        });
+    }
+
+    async fn check_once(&self, trackers: &mut HashMap<i32, NodeTracker>) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let mut by_node: HashMap<i32, Vec<node_subscriptions::Model>> = HashMap::new();
+        for sub in SubscriptionOperations::get_all_subscriptions(&self.db).await? {
+            by_node.entry(sub.node_id).or_default().push(sub);
+        }
+
+        let mut active = HashSet::new();
+        for (node_id, subs) in by_node {
+            let Some(node) = NodeOperations::get_node_by_id(&self.db, node_id).await? else {
+                continue;
+            };
+            // 暂停期间不发送通知
+            if node.is_paused {
+                continue;
+            }
+            active.insert(node_id);
+
+            let from_date = now.naive_utc() - chrono::Duration::seconds(STATE_LOOKBACK_SECS);
+            let records =
+                HealthOperations::get_node_health_records(&self.db, node_id, Some(from_date), None)
+                    .await?;
+            let tracker = trackers
+                .entry(node_id)
+                .or_insert_with(|| NodeTracker::new(now));
+            let Some(state) = tracker.update(observed_state(&records), now) else {
+                continue;
+            };
+
+            let notification = NodeNotification::new(&node, state, records.first());
+            for sub in subs {
+                match subscription_action(&sub, state) {
+                    SubscriptionAction::Nothing => continue,
+                    SubscriptionAction::MarkOnly => {}
+                    SubscriptionAction::Notify => {
+                        if let Err(e) = self.send(&sub, &notification).await {
+                            // 保留旧状态，下一轮重试
+                            warn!(
+                                "Failed to notify subscription {} of node {}: {:#}",
+                                sub.id, node_id, e
+                            );
+                            continue;
+                        }
+                        info!(
+                            "Notified subscription {} of node {} {}",
+                            sub.id, node_id, notification.event
+                        );
+                    }
+                }
+                SubscriptionOperations::set_last_notified_state(&self.db, sub.id, state.as_str())
+                    .await?;
+            }
+        }
+
+        trackers.retain(|node_id, _| active.contains(node_id));
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        sub: &node_subscriptions::Model,
+        notification: &NodeNotification,
+    ) -> anyhow::Result<()> {
+        match sub.kind.as_str() {
+            "webhook" => self.send_webhook(&sub.target, notification).await,
+            "email" => self.send_email(&sub.target, notification).await,
+            kind => anyhow::bail!("unknown subscription kind: {}", kind),
+        }
+    }
+
+    async fn send_webhook(&self, url: &str, notification: &NodeNotification) -> anyhow::Result<()> {
+        let resp = self
+            .client
+            .post(url)
+            .timeout(WEBHOOK_TIMEOUT)
+            .json(notification)
+            .send()
+            .await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("webhook returned status {}", resp.status());
+        }
+        Ok(())
+    }
+
+    async fn send_email(&self, to: &str, notification: &NodeNotification) -> anyhow::Result<()> {
+        let Some(server) = &self.config.smtp_server else {
+            anyhow::bail!("smtp server is not configured");
+        };
+        tokio::time::timeout(
+            SMTP_TIMEOUT,
+            send_smtp(server, &self.config, to, notification),
+        )
+        .await
+        .map_err(|_| anyhow::anyhow!("smtp delivery timed out"))?
+    }
+}
+
+async fn read_smtp_reply<R>(reader: &mut BufReader<R>) -> anyhow::Result<(u16, String)>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    let mut text = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            anyhow::bail!("smtp server closed the connection");
+        }
+        let line = line.trim_end();
+        if line.len() < 3 {
+            anyhow::bail!("malformed smtp reply: {:?}", line);
+        }
+        let code = line[..3].parse::<u16>()?;
+        text.push_str(line.get(4..).unwrap_or_default());
+        // "250-..." 表示多行回复尚未结束
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok((code, text));
+        }
+        text.push('\n');
+    }
+}
+
+async fn smtp_command<R, W>(
+    reader: &mut BufReader<R>,
+    writer: &mut W,
+    cmd: &str,
+    expected: &[u16],
+) -> anyhow::Result<()>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    writer.write_all(cmd.as_bytes()).await?;
+    writer.write_all(b"\r\n").await?;
+    let (code, text) = read_smtp_reply(reader).await?;
+    if !expected.contains(&code) {
+        let verb = cmd.split_whitespace().next().unwrap_or_default();
+        anyhow::bail!("smtp {} failed: {} {}", verb, code, text);
+    }
+    Ok(())
+}
+
+fn smtp_message(from: &str, to: &str, notification: &NodeNotification) -> String {
+    let body = serde_json::to_string_pretty(notification).unwrap_or_default();
+    let msg = format!(
+        "From: {}\nTo: {}\nSubject: {}\nDate: {}\nContent-Type: text/plain; charset=utf-8\n\n{}\n\n{}\n",
+        from,
+        to,
+        notification.title(),
+        Utc::now().to_rfc2822(),
+        notification.message,
+        body,
+    );
+    // 统一换行符并做点转义（RFC 5321 4.5.2）
+    msg.replace("\r\n", "\n")
+        .replace('\n', "\r\n")
+        .replace("\r\n.", "\r\n..")
+}
+
+/// 通过明文 SMTP 发送邮件，TLS 需要由本地中继处理
+async fn send_smtp(
+    server: &str,
+    config: &NotificationConfig,
+    to: &str,
+    notification: &NodeNotification,
+) -> anyhow::Result<()> {
+    let stream = TcpStream::connect(server).await?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    let (code, text) = read_smtp_reply(&mut reader).await?;
+    if code != 220 {
+        anyhow::bail!("unexpected smtp greeting: {} {}", code, text);
+    }
+    smtp_command(&mut reader, &mut writer, "EHLO easytier-uptime", &[250]).await?;
+
+    if let (Some(username), Some(password)) = (&config.smtp_username, &config.smtp_password) {
+        let token = base64::engine::general_purpose::STANDARD
+            .encode(format!("\0{}\0{}", username, password));
+        smtp_command(
+            &mut reader,
+            &mut writer,
+            &format!("AUTH PLAIN {}", token),
+            &[235],
+        )
+        .await?;
+    }
+
+    smtp_command(
+        &mut reader,
+        &mut writer,
+        &format!("MAIL FROM:<{}>", config.smtp_from),
+        &[250],
+    )
+    .await?;
+    smtp_command(
+        &mut reader,
+        &mut writer,
+        &format!("RCPT TO:<{}>", to),
+        &[250, 251],
+    )
+    .await?;
+    smtp_command(&mut reader, &mut writer, "DATA", &[354]).await?;
+    smtp_command(
+        &mut reader,
+        &mut writer,
+        &format!("{}.", smtp_message(&config.smtp_from, to, notification)),
+        &[250],
+    )
+    .await?;
+    let _ = smtp_command(&mut reader, &mut writer, "QUIT", &[221]).await;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Json, Router, extract::State, routing::post};
+    use tokio::{net::TcpListener, sync::mpsc};
+
+    use super::*;
+    use crate::db::HealthStatus;
+
+    fn record(healthy: bool, secs_ago: i64) -> health_records::Model {
+        health_records::Model {
+            id: 0,
+            node_id: 1,
+            status: if healthy {
+                HealthStatus::Healthy
+            } else {
+                HealthStatus::Unhealthy
+            }
+            .to_string(),
+            response_time: 0,
+            error_message: String::new(),
+            checked_at: (Utc::now() - chrono::Duration::seconds(secs_ago)).fixed_offset(),
+        }
+    }
+
+    fn subscription(last_notified_state: &str, notify_recovery: bool) -> node_subscriptions::Model {
+        node_subscriptions::Model {
+            id: 1,
+            node_id: 1,
+            kind: "webhook".to_string(),
+            target: "http://127.0.0.1/hook".to_string(),
+            notify_recovery,
+            last_notified_state: last_notified_state.to_string(),
+            created_at: Utc::now().fixed_offset(),
+        }
+    }
+
+    #[test]
+    fn test_observed_state_requires_confirmation() {
+        // 刚刚开始失败，尚未确认
+        let records = vec![record(false, 0), record(false, 60), record(true, 120)];
+        assert_eq!(observed_state(&records), None);
+
+        let records = vec![record(false, 0), record(false, 150), record(true, 200)];
+        assert_eq!(observed_state(&records), Some(NodeState::Down));
+
+        let records = vec![record(true, 0), record(true, 300)];
+        assert_eq!(observed_state(&records), Some(NodeState::Up));
+
+        assert_eq!(observed_state(&[]), None);
+    }
+
+    #[test]
+    fn test_flap_damper_suppresses_and_recovers() {
+        let start = Utc::now();
+        let mut tracker = NodeTracker::new(start);
+        assert_eq!(
+            tracker.update(Some(NodeState::Up), start),
+            Some(NodeState::Up)
+        );
+
+        // 短时间内反复变化后被抑制
+        let mut now = start;
+        for state in [NodeState::Down, NodeState::Up, NodeState::Down] {
+            now += chrono::Duration::seconds(10);
+            tracker.update(Some(state), now);
+        }
+        assert_eq!(tracker.update(None, now), None);
+
+        // 惩罚值衰减后恢复，并给出最新的状态
+        now += chrono::Duration::seconds(FLAP_HALF_LIFE_SECS as i64 * 3);
+        assert_eq!(tracker.update(None, now), Some(NodeState::Down));
+    }
+
+    #[test]
+    fn test_subscription_action() {
+        assert_eq!(
+            subscription_action(&subscription("", true), NodeState::Up),
+            SubscriptionAction::MarkOnly
+        );
+        assert_eq!(
+            subscription_action(&subscription("", true), NodeState::Down),
+            SubscriptionAction::Notify
+        );
+        assert_eq!(
+            subscription_action(&subscription("down", true), NodeState::Down),
+            SubscriptionAction::Nothing
+        );
+        assert_eq!(
+            subscription_action(&subscription("down", true), NodeState::Up),
+            SubscriptionAction::Notify
+        );
+        assert_eq!(
+            subscription_action(&subscription("down", false), NodeState::Up),
+            SubscriptionAction::MarkOnly
+        );
+    }
+
+    #[tokio::test]
+    async fn test_webhook_delivery() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(
+                    |State(tx): State<mpsc::UnboundedSender<serde_json::Value>>,
+                     Json(body): Json<serde_json::Value>| async move {
+                        tx.send(body).unwrap();
+                    },
+                ),
+            )
+            .with_state(tx);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let db = Db::memory_db().await;
+        let notifier =
+            OwnerNotifier::new(db, crate::config::AppConfig::default_config().notification);
+        let notification = NodeNotification {
+            event: "outage".to_string(),
+            state: NodeState::Down,
+            node_id: 1,
+            node_name: "relay".to_string(),
+            address: "tcp://example.com:11010".to_string(),
+            message: "Node relay (tcp://example.com:11010) is down".to_string(),
+            last_error: None,
+            occurred_at: Utc::now(),
+        };
+        notifier
+            .send_webhook(&format!("http://{}/hook", addr), &notification)
+            .await
+            .unwrap();
+
+        let body = rx.recv().await.unwrap();
+        assert_eq!(body["event"], "outage");
+        assert_eq!(body["state"], "down");
+        assert_eq!(body["node_name"], "relay");
+    }
+}
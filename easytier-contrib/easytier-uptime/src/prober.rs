@@ -0,0 +1,231 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::Context as _;
+use easytier::{
+    common::config::{ConfigFileControl, ConfigLoader, TomlConfigLoader},
+    instance_manager::NetworkInstanceManager,
+    proto::api::instance::PeerConnInfo,
+    tunnel::IpScheme,
+};
+
+use crate::db::{ProbeMeasurement, entity::shared_nodes};
+use crate::health_checker::find_dst_peer;
+
+/// 每轮协议探测的间隔
+pub const PROBE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+/// 等待与目标节点建立连接的最长时间
+const PROBE_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(15);
+/// 连接建立后在隧道内采样的次数
+const PROBE_PING_COUNT: usize = 10;
+/// 两次采样的间隔，与隧道内心跳的频率一致
+const PROBE_PING_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 节点的一个探测目标
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProbeTarget {
+    pub protocol: String,
+    pub port: u16,
+}
+
+impl ProbeTarget {
+    pub fn uri(&self, host: &str) -> String {
+        format!("{}://{}:{}", self.protocol, host, self.port)
+    }
+}
+
+/// 计算节点需要探测的目标。注册协议使用节点登记的端口，其余协议按 EasyTier
+/// 默认的端口偏移（ws +1，wss/quic +2）从注册协议的基准端口推算。
+pub fn probe_targets(node: &shared_nodes::Model) -> Vec<ProbeTarget> {
+    let Ok(port) = u16::try_from(node.port) else {
+        return vec![];
+    };
+    let registered = node.protocol.to_lowercase();
+    let base_port = registered
+        .parse::<IpScheme>()
+        .ok()
+        .map(|scheme| port.saturating_sub(scheme.port_offset()));
+
+    node.get_probe_protocols()
+        .into_iter()
+        .filter_map(|protocol| {
+            if protocol == registered {
+                return Some(ProbeTarget { protocol, port });
+            }
+            let scheme = protocol.parse::<IpScheme>().ok()?;
+            let port = base_port?.checked_add(scheme.port_offset())?;
+            Some(ProbeTarget { protocol, port })
+        })
+        .collect()
+}
+
+/// 汇总 RTT 采样，返回（平均值，最小值，最大值）
+fn rtt_summary(samples: &[f64]) -> Option<(f64, f64, f64)> {
+    if samples.is_empty() {
+        return None;
+    }
+    let avg = samples.iter().sum::<f64>() / samples.len() as f64;
+    let min = samples.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = samples.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    Some((avg, min, max))
+}
+
+/// 获取探测实例与目标节点之间延迟最低的直连连接
+async fn direct_conn(
+    instance_mgr: &NetworkInstanceManager,
+    inst_id: &uuid::Uuid,
+) -> anyhow::Result<Option<PeerConnInfo>> {
+    let Some(instance) = instance_mgr.get_network_info(inst_id).await else {
+        anyhow::bail!("probe node is not started");
+    };
+    if let Some(err) = instance.error_msg {
+        anyhow::bail!("probe node has error: {}", err);
+    }
+
+    Ok(find_dst_peer(&instance.peer_route_pairs)
+        .and_then(|pair| pair.peer.as_ref())
+        .and_then(|peer| {
+            peer.conns
+                .iter()
+                .min_by_key(|conn| conn.stats.map(|s| s.latency_us).unwrap_or(u64::MAX))
+                .cloned()
+        }))
+}
+
+async fn measure(
+    instance_mgr: &NetworkInstanceManager,
+    cfg: TomlConfigLoader,
+    measurement: &mut ProbeMeasurement,
+) -> anyhow::Result<()> {
+    let inst_id = cfg.get_id();
+    let start = Instant::now();
+    instance_mgr
+        .run_network_instance(cfg, false, ConfigFileControl::STATIC_CONFIG)
+        .with_context(|| "failed to run probe instance")?;
+
+    // 握手耗时：从启动实例到出现与目标节点的直连连接
+    while direct_conn(instance_mgr, &inst_id).await?.is_none() {
+        if start.elapsed() > PROBE_HANDSHAKE_TIMEOUT {
+            anyhow::bail!("handshake timed out after {:?}", PROBE_HANDSHAKE_TIMEOUT);
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    measurement.handshake_ms = Some(start.elapsed().as_millis() as i32);
+
+    // 通过已建立的隧道采样 RTT 与丢包，连接消失的采样计为完全丢包
+    let mut rtts = Vec::with_capacity(PROBE_PING_COUNT);
+    let mut loss_sum = 0.0;
+    for _ in 0..PROBE_PING_COUNT {
+        tokio::time::sleep(PROBE_PING_INTERVAL).await;
+        match direct_conn(instance_mgr, &inst_id).await? {
+            Some(conn) => {
+                if let Some(latency_us) = conn.stats.map(|s| s.latency_us).filter(|l| *l > 0) {
+                    rtts.push(latency_us as f64 / 1000.0);
+                }
+                loss_sum += f64::from(conn.loss_rate);
+            }
+            None => loss_sum += 1.0,
+        }
+        measurement.samples += 1;
+    }
+    measurement.loss_rate = Some(loss_sum / PROBE_PING_COUNT as f64);
+
+    let (avg, min, max) =
+        rtt_summary(&rtts).ok_or_else(|| anyhow::anyhow!("no rtt sample through the tunnel"))?;
+    measurement.rtt_avg_ms = Some(avg);
+    measurement.rtt_min_ms = Some(min);
+    measurement.rtt_max_ms = Some(max);
+
+    Ok(())
+}
+
+/// 使用独立的网络实例对一个探测目标执行一次完整探测，结束后销毁实例
+pub async fn run_probe(
+    instance_mgr: Arc<NetworkInstanceManager>,
+    cfg: TomlConfigLoader,
+    target: &ProbeTarget,
+) -> ProbeMeasurement {
+    let inst_id = cfg.get_id();
+    let mut measurement = ProbeMeasurement {
+        protocol: target.protocol.clone(),
+        ..Default::default()
+    };
+
+    let ret = measure(&instance_mgr, cfg, &mut measurement).await;
+    let _ = instance_mgr.delete_network_instance(vec![inst_id]);
+
+    if let Err(e) = ret {
+        measurement.error = Some(format!("{:#}", e));
+    }
+    measurement
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(protocol: &str, port: i32, probe_protocols: &str) -> shared_nodes::Model {
+        let now = chrono::Utc::now().fixed_offset();
+        shared_nodes::Model {
+            id: 1,
+            name: "node".to_string(),
+            host: "example.com".to_string(),
+            port,
+            protocol: protocol.to_string(),
+            version: String::new(),
+            allow_relay: false,
+            network_name: "test".to_string(),
+            network_secret: String::new(),
+            description: String::new(),
+            max_connections: 100,
+            current_connections: 0,
+            is_active: true,
+            is_approved: true,
+            qq_number: String::new(),
+            wechat: String::new(),
+            mail: String::new(),
+            probe_protocols: probe_protocols.to_string(),
+            is_paused: false,
+            owner_token_nonce: String::new(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[test]
+    fn test_probe_targets_use_port_offsets() {
+        let targets = probe_targets(&node("tcp", 11010, "udp, ws,quic,tcp,bogus"));
+        let got: Vec<(&str, u16)> = targets
+            .iter()
+            .map(|t| (t.protocol.as_str(), t.port))
+            .collect();
+        assert_eq!(
+            got,
+            vec![
+                ("tcp", 11010),
+                ("udp", 11010),
+                ("ws", 11011),
+                ("quic", 11012)
+            ]
+        );
+        assert_eq!(targets[2].uri("example.com"), "ws://example.com:11011");
+
+        // 以 wss 注册时从其端口反推基准端口
+        let targets = probe_targets(&node("wss", 11012, "tcp"));
+        assert_eq!(
+            targets[1],
+            ProbeTarget {
+                protocol: "tcp".to_string(),
+                port: 11010,
+            }
+        );
+    }
+
+    #[test]
+    fn test_rtt_summary() {
+        assert_eq!(rtt_summary(&[]), None);
+        assert_eq!(rtt_summary(&[10.0, 30.0, 20.0]), Some((20.0, 10.0, 30.0)));
+    }
+}
@@ -1091,6 +1091,7 @@ mod manager {
                     BaseController::default(),
                     SetLoggerConfigRequest {
                         level: LoggerRpcService::string_to_log_level(&level).into(),
+                        filter: None,
                     },
                 )
                 .await?;
@@ -1353,6 +1354,7 @@ pub fn run_gui() -> std::process::ExitCode {
                     file: None,
                     size_mb: None,
                     count: None,
+                    format: None,
                 })
                 .build()
                 .map_err(|e| e.to_string())?;
@@ -12,6 +12,7 @@ export interface SecureModeConfig {
   // does not render secure-mode or credential inputs.
   local_private_key?: string
   local_public_key?: string
+  pq_hybrid?: boolean
 }
 
 export enum AclProtocol {
@@ -131,10 +132,14 @@ export interface NetworkConfig {
   multi_thread?: boolean
   proxy_forward_by_system?: boolean
   disable_encryption?: boolean
+  enable_conn_rekey?: boolean
   disable_tcp_hole_punching?: boolean
   disable_udp_hole_punching?: boolean
   disable_upnp?: boolean
   enable_udp_broadcast_relay?: boolean
+  enable_lan_discovery?: boolean
+  enable_tap?: boolean
+  enable_tun_offload?: boolean
   disable_sym_hole_punching?: boolean
 
   enable_relay_network_whitelist?: boolean
@@ -209,10 +214,14 @@ export function DEFAULT_NETWORK_CONFIG(): NetworkConfig {
     multi_thread: true,
     proxy_forward_by_system: false,
     disable_encryption: false,
+    enable_conn_rekey: false,
     disable_tcp_hole_punching: false,
     disable_udp_hole_punching: false,
     disable_upnp: false,
     enable_udp_broadcast_relay: false,
+    enable_lan_discovery: false,
+    enable_tap: false,
+    enable_tun_offload: false,
     disable_sym_hole_punching: false,
     enable_relay_network_whitelist: false,
     relay_network_whitelist: [],
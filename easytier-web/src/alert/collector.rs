@@ -0,0 +1,422 @@
+use std::{collections::HashMap, time::Duration};
+
+use chrono::{DateTime, FixedOffset};
+use easytier::proto::{
+    api::instance::{
+        CredentialInfo, CredentialManageRpc as _, CredentialManageRpcClientFactory,
+        InstanceIdentifier, ListCredentialsRequest, ListPeerRequest, ListRouteRequest,
+        PeerManageRpc as _, PeerManageRpcClientFactory, PeerRoutePair,
+        instance_identifier::Selector, list_peer_route_pair,
+    },
+    rpc_types::controller::BaseController,
+};
+
+use super::{AlertRule, AlertRuleKind, evaluator::RuleObservation, evaluator::Violation};
+use crate::client_manager::session::Session;
+
+const RPC_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Credentials expiring within this window fire when a rule has no threshold.
+const DEFAULT_CREDENTIAL_EXPIRY_SECS: f64 = 7.0 * 24.0 * 3600.0;
+
+#[derive(Debug, Clone, Default)]
+pub struct InstanceSnapshot {
+    pub inst_id: uuid::Uuid,
+    pub pairs: Vec<PeerRoutePair>,
+    pub credentials: Vec<CredentialInfo>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MachineSnapshot {
+    pub hostname: String,
+    /// `None` if the instances of this machine could not be inspected.
+    pub instances: Option<Vec<InstanceSnapshot>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OfflineMachine {
+    pub hostname: Option<String>,
+    pub last_seen: Option<DateTime<FixedOffset>>,
+}
+
+/// State of all machines of one organization at the time of an evaluation round.
+#[derive(Debug, Clone, Default)]
+pub struct FleetSnapshot {
+    pub online: HashMap<uuid::Uuid, MachineSnapshot>,
+    pub offline: HashMap<uuid::Uuid, OfflineMachine>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CollectOptions {
+    pub peers: bool,
+    pub credentials: bool,
+}
+
+impl CollectOptions {
+    pub fn for_rules<'a>(rules: impl Iterator<Item = &'a AlertRule>) -> Self {
+        let mut opts = CollectOptions::default();
+        for rule in rules {
+            match rule.kind {
+                AlertRuleKind::DeviceOffline => {}
+                AlertRuleKind::CredentialExpiry => opts.credentials = true,
+                _ => opts.peers = true,
+            }
+        }
+        opts
+    }
+}
+
+async fn with_timeout<T, E: std::fmt::Debug>(
+    fut: impl std::future::Future<Output = Result<T, E>>,
+) -> Option<T> {
+    match tokio::time::timeout(RPC_TIMEOUT, fut).await {
+        Ok(Ok(v)) => Some(v),
+        Ok(Err(e)) => {
+            tracing::debug!(?e, "alert collector rpc failed");
+            None
+        }
+        Err(_) => {
+            tracing::debug!("alert collector rpc timed out");
+            None
+        }
+    }
+}
+
+pub async fn collect_instance(
+    session: &Session,
+    inst_id: uuid::Uuid,
+    opts: CollectOptions,
+) -> Option<InstanceSnapshot> {
+    let instance = Some(InstanceIdentifier {
+        selector: Some(Selector::Id(inst_id.into())),
+    });
+
+    let mut snapshot = InstanceSnapshot {
+        inst_id,
+        ..Default::default()
+    };
+
+    if opts.peers {
+        let client = session.scoped_client::<PeerManageRpcClientFactory<BaseController>>();
+        let peers = with_timeout(client.list_peer(
+            BaseController::default(),
+            ListPeerRequest {
+                instance: instance.clone(),
+            },
+        ))
+        .await?;
+        let routes = with_timeout(client.list_route(
+            BaseController::default(),
+            ListRouteRequest {
+                instance: instance.clone(),
+            },
+        ))
+        .await?;
+        snapshot.pairs = list_peer_route_pair(peers.peer_infos, routes.routes);
+    }
+
+    if opts.credentials {
+        let client = session.scoped_client::<CredentialManageRpcClientFactory<BaseController>>();
+        let resp = with_timeout(client.list_credentials(
+            BaseController::default(),
+            ListCredentialsRequest { instance },
+        ))
+        .await?;
+        snapshot.credentials = resp.credentials;
+    }
+
+    Some(snapshot)
+}
+
+fn machine_label(hostname: Option<&str>, machine_id: &uuid::Uuid) -> String {
+    match hostname {
+        Some(h) if !h.is_empty() => format!("{} ({})", h, machine_id),
+        _ => machine_id.to_string(),
+    }
+}
+
+fn peer_matches_target(pair: &PeerRoutePair, target: Option<&str>) -> bool {
+    let Some(target) = target else {
+        return true;
+    };
+    let Some(route) = pair.route.as_ref() else {
+        return false;
+    };
+    route.hostname == target
+        || route
+            .ipv4_addr
+            .as_ref()
+            .and_then(|addr| addr.address.as_ref())
+            .is_some_and(|addr| addr.to_string() == target)
+}
+
+fn peer_label(pair: &PeerRoutePair) -> String {
+    let route = pair.route.clone().unwrap_or_default();
+    if route.hostname.is_empty() {
+        route.peer_id.to_string()
+    } else {
+        route.hostname
+    }
+}
+
+fn peer_latency_ms(pair: &PeerRoutePair) -> Option<f64> {
+    let route = pair.route.as_ref()?;
+    if route.cost == 1 {
+        pair.get_latency_ms()
+    } else {
+        Some(route.path_latency_latency_first() as f64)
+    }
+}
+
+fn is_relayed(pair: &PeerRoutePair) -> bool {
+    let Some(route) = pair.route.as_ref() else {
+        return false;
+    };
+    let has_direct_conn = pair
+        .peer
+        .as_ref()
+        .is_some_and(|peer| peer.conns.iter().any(|conn| !conn.is_closed));
+    route.cost > 1 || !has_direct_conn
+}
+
+/// Turn a fleet snapshot into the violations of one rule.
+pub fn observe(
+    rule: &AlertRule,
+    fleet: &FleetSnapshot,
+    now: DateTime<FixedOffset>,
+) -> RuleObservation {
+    let mut obs = RuleObservation::default();
+    let in_scope = |machine_id: &uuid::Uuid| rule.machine_id.is_none_or(|id| id == *machine_id);
+
+    if rule.kind == AlertRuleKind::DeviceOffline {
+        obs.covered_machines
+            .extend(fleet.online.keys().chain(fleet.offline.keys()).copied());
+        let mut offline = fleet.offline.clone();
+        if let Some(machine_id) = rule.machine_id
+            && !fleet.online.contains_key(&machine_id)
+        {
+            // a pinned machine counts as offline even if we never saw it
+            obs.covered_machines.insert(machine_id);
+            offline.entry(machine_id).or_default();
+        }
+        for (machine_id, info) in offline.iter().filter(|(id, _)| in_scope(id)) {
+            let label = machine_label(info.hostname.as_deref(), machine_id);
+            obs.violations.push(Violation {
+                fingerprint: format!("device:{}", machine_id),
+                machine_id: Some(*machine_id),
+                subject: label.clone(),
+                message: match info.last_seen {
+                    Some(t) => format!("device {} is offline, last seen at {}", label, t),
+                    None => format!("device {} is offline", label),
+                },
+            });
+        }
+        return obs;
+    }
+
+    for (machine_id, machine) in fleet.online.iter().filter(|(id, _)| in_scope(id)) {
+        let Some(instances) = machine.instances.as_ref() else {
+            continue;
+        };
+        obs.covered_machines.insert(*machine_id);
+        let machine = machine_label(Some(&machine.hostname), machine_id);
+
+        for inst in instances.iter() {
+            let prefix = format!("{}/{}", machine_id, inst.inst_id);
+            let mut push = |key: String, subject: String, message: String| {
+                obs.violations.push(Violation {
+                    fingerprint: format!("{}/{}", prefix, key),
+                    machine_id: Some(*machine_id),
+                    subject,
+                    message,
+                });
+            };
+            let target = rule.target.as_deref();
+            let mut peers = inst
+                .pairs
+                .iter()
+                .filter(|pair| peer_matches_target(pair, target));
+
+            match rule.kind {
+                AlertRuleKind::DeviceOffline => unreachable!(),
+                AlertRuleKind::PeerUnreachable => {
+                    let Some(target) = target else {
+                        continue;
+                    };
+                    if peers.next().is_none() {
+                        push(
+                            format!("unreachable:{}", target),
+                            format!("{} -> {}", machine, target),
+                            format!("peer {} is unreachable from {}", target, machine),
+                        );
+                    }
+                }
+                AlertRuleKind::HighLatency => {
+                    let threshold = rule.threshold.unwrap_or(f64::MAX);
+                    for pair in peers {
+                        let Some(latency) = peer_latency_ms(pair) else {
+                            continue;
+                        };
+                        if latency > threshold {
+                            let peer = peer_label(pair);
+                            push(
+                                format!("latency:{}", peer),
+                                format!("{} -> {}", machine, peer),
+                                format!(
+                                    "latency from {} to {} is {:.1}ms (threshold {:.1}ms)",
+                                    machine, peer, latency, threshold
+                                ),
+                            );
+                        }
+                    }
+                }
+                AlertRuleKind::RelayOnly => {
+                    for pair in peers.filter(|pair| is_relayed(pair)) {
+                        let peer = peer_label(pair);
+                        push(
+                            format!("relay:{}", peer),
+                            format!("{} -> {}", machine, peer),
+                            format!("{} reaches {} only through a relay", machine, peer),
+                        );
+                    }
+                }
+                AlertRuleKind::CredentialExpiry => {
+                    let threshold = rule.threshold.unwrap_or(DEFAULT_CREDENTIAL_EXPIRY_SECS);
+                    for cred in inst.credentials.iter() {
+                        if target.is_some_and(|t| t != cred.credential_id) {
+                            continue;
+                        }
+                        let remaining = (cred.expiry_unix - now.timestamp()) as f64;
+                        if remaining <= threshold {
+                            push(
+                                format!("credential:{}", cred.credential_id),
+                                format!("credential {}", cred.credential_id),
+                                if remaining > 0.0 {
+                                    format!(
+                                        "credential {} on {} expires in {}s",
+                                        cred.credential_id, machine, remaining as i64
+                                    )
+                                } else {
+                                    format!(
+                                        "credential {} on {} has expired",
+                                        cred.credential_id, machine
+                                    )
+                                },
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    obs
+}
+
+#[cfg(test)]
+mod tests {
+    use easytier::proto::api::instance::{PeerConnInfo, PeerInfo, Route};
+
+    use super::*;
+
+    fn rule(kind: AlertRuleKind, target: Option<&str>, threshold: Option<f64>) -> AlertRule {
+        AlertRule {
+            id: 1,
+            organization_id: 1,
+            name: "rule".to_string(),
+            kind,
+            machine_id: None,
+            target: target.map(str::to_string),
+            threshold,
+            duration: Duration::ZERO,
+            channel_ids: vec![],
+        }
+    }
+
+    fn pair(hostname: &str, cost: i32, path_latency: i32, direct: bool) -> PeerRoutePair {
+        PeerRoutePair {
+            route: Some(Route {
+                peer_id: 10,
+                hostname: hostname.to_string(),
+                cost,
+                path_latency,
+                path_latency_latency_first: Some(path_latency),
+                ..Default::default()
+            }),
+            peer: direct.then(|| PeerInfo {
+                peer_id: 10,
+                conns: vec![PeerConnInfo::default()],
+                ..Default::default()
+            }),
+        }
+    }
+
+    fn fleet(machine_id: uuid::Uuid, pairs: Vec<PeerRoutePair>) -> FleetSnapshot {
+        FleetSnapshot {
+            online: HashMap::from([(
+                machine_id,
+                MachineSnapshot {
+                    hostname: "gw".to_string(),
+                    instances: Some(vec![InstanceSnapshot {
+                        inst_id: uuid::Uuid::nil(),
+                        pairs,
+                        credentials: vec![],
+                    }]),
+                },
+            )]),
+            offline: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn relay_and_unreachable_peers_are_observed() {
+        let machine_id = uuid::Uuid::new_v4();
+        let fleet = fleet(
+            machine_id,
+            vec![pair("direct", 1, 5, true), pair("relayed", 2, 80, false)],
+        );
+        let now = chrono::Local::now().fixed_offset();
+
+        let obs = observe(&rule(AlertRuleKind::RelayOnly, None, None), &fleet, now);
+        assert_eq!(obs.violations.len(), 1);
+        assert!(obs.violations[0].fingerprint.ends_with("relay:relayed"));
+        assert!(obs.covered_machines.contains(&machine_id));
+
+        let obs = observe(
+            &rule(AlertRuleKind::PeerUnreachable, Some("missing"), None),
+            &fleet,
+            now,
+        );
+        assert_eq!(obs.violations.len(), 1);
+
+        let obs = observe(
+            &rule(AlertRuleKind::PeerUnreachable, Some("direct"), None),
+            &fleet,
+            now,
+        );
+        assert!(obs.violations.is_empty());
+
+        let obs = observe(
+            &rule(AlertRuleKind::HighLatency, None, Some(50.0)),
+            &fleet,
+            now,
+        );
+        assert_eq!(obs.violations.len(), 1);
+        assert!(obs.violations[0].fingerprint.ends_with("latency:relayed"));
+    }
+
+    #[test]
+    fn pinned_offline_machine_is_observed() {
+        let machine_id = uuid::Uuid::new_v4();
+        let mut rule = rule(AlertRuleKind::DeviceOffline, None, None);
+        rule.machine_id = Some(machine_id);
+
+        let obs = observe(
+            &rule,
+            &FleetSnapshot::default(),
+            chrono::Local::now().fixed_offset(),
+        );
+        assert_eq!(obs.violations.len(), 1);
+        assert_eq!(obs.violations[0].machine_id, Some(machine_id));
+    }
+}
@@ -0,0 +1,319 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, FixedOffset};
+
+use super::AlertRule;
+
+/// A single condition that is currently violated for a rule. The fingerprint
+/// identifies the alert across evaluation rounds and is used for deduplication.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub fingerprint: String,
+    pub machine_id: Option<uuid::Uuid>,
+    pub subject: String,
+    pub message: String,
+}
+
+/// Everything a rule observed in one evaluation round.
+///
+/// `covered_machines` lists the machines that could actually be inspected.
+/// Alerts bound to a machine outside this set keep their current state, so a
+/// failed RPC round never resolves (or fires) anything by accident.
+#[derive(Debug, Clone, Default)]
+pub struct RuleObservation {
+    pub violations: Vec<Violation>,
+    pub covered_machines: HashSet<uuid::Uuid>,
+}
+
+impl RuleObservation {
+    fn covers(&self, machine_id: Option<&uuid::Uuid>) -> bool {
+        machine_id.is_none_or(|id| self.covered_machines.contains(id))
+    }
+}
+
+#[derive(Debug, Clone)]
+enum AlertState {
+    Pending {
+        since: DateTime<FixedOffset>,
+        machine_id: Option<uuid::Uuid>,
+    },
+    Firing {
+        event_id: Option<i32>,
+        machine_id: Option<uuid::Uuid>,
+    },
+}
+
+impl AlertState {
+    fn machine_id(&self) -> Option<&uuid::Uuid> {
+        match self {
+            AlertState::Pending { machine_id, .. } | AlertState::Firing { machine_id, .. } => {
+                machine_id.as_ref()
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AlertTransition {
+    Firing {
+        rule_id: i32,
+        since: DateTime<FixedOffset>,
+        violation: Violation,
+    },
+    Resolved {
+        rule_id: i32,
+        fingerprint: String,
+        event_id: Option<i32>,
+    },
+}
+
+type AlertKey = (i32, String);
+
+/// Tracks the pending/firing state of every alert and turns raw observations
+/// into firing/resolved transitions. Each alert fires at most once until it is
+/// resolved again.
+#[derive(Debug, Default)]
+pub struct AlertEvaluator {
+    states: HashMap<AlertKey, AlertState>,
+}
+
+impl AlertEvaluator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Restore a firing alert loaded from the database after a restart.
+    pub fn restore_firing(
+        &mut self,
+        rule_id: i32,
+        fingerprint: String,
+        event_id: i32,
+        machine_id: Option<uuid::Uuid>,
+    ) {
+        self.states.insert(
+            (rule_id, fingerprint),
+            AlertState::Firing {
+                event_id: Some(event_id),
+                machine_id,
+            },
+        );
+    }
+
+    /// Remember the persisted event id of an alert that just started firing.
+    pub fn set_event_id(&mut self, rule_id: i32, fingerprint: &str, id: i32) {
+        if let Some(AlertState::Firing { event_id, .. }) =
+            self.states.get_mut(&(rule_id, fingerprint.to_string()))
+        {
+            *event_id = Some(id);
+        }
+    }
+
+    pub fn evaluate(
+        &mut self,
+        rule: &AlertRule,
+        observation: &RuleObservation,
+        now: DateTime<FixedOffset>,
+    ) -> Vec<AlertTransition> {
+        let mut transitions = Vec::new();
+        let mut seen = HashSet::new();
+
+        for violation in observation.violations.iter() {
+            let key = (rule.id, violation.fingerprint.clone());
+            seen.insert(key.clone());
+
+            let state = self.states.entry(key).or_insert(AlertState::Pending {
+                since: now,
+                machine_id: violation.machine_id,
+            });
+            let AlertState::Pending { since, machine_id } = *state else {
+                continue;
+            };
+            if now
+                .signed_duration_since(since)
+                .to_std()
+                .unwrap_or_default()
+                < rule.duration
+            {
+                continue;
+            }
+
+            transitions.push(AlertTransition::Firing {
+                rule_id: rule.id,
+                since,
+                violation: violation.clone(),
+            });
+            *state = AlertState::Firing {
+                event_id: None,
+                machine_id,
+            };
+        }
+
+        self.states.retain(|key, state| {
+            if key.0 != rule.id || seen.contains(key) || !observation.covers(state.machine_id()) {
+                return true;
+            }
+            if let AlertState::Firing { event_id, .. } = state {
+                transitions.push(AlertTransition::Resolved {
+                    rule_id: key.0,
+                    fingerprint: key.1.clone(),
+                    event_id: *event_id,
+                });
+            }
+            false
+        });
+
+        transitions
+    }
+
+    /// Drop the state of rules that no longer exist or were disabled, returning
+    /// the event ids of alerts that were still firing.
+    pub fn retain_rules(&mut self, rule_ids: &HashSet<i32>) -> Vec<i32> {
+        let mut dropped = Vec::new();
+        self.states.retain(|(rule_id, _), state| {
+            if rule_ids.contains(rule_id) {
+                return true;
+            }
+            if let AlertState::Firing {
+                event_id: Some(event_id),
+                ..
+            } = state
+            {
+                dropped.push(*event_id);
+            }
+            false
+        });
+        dropped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::alert::AlertRuleKind;
+
+    fn rule(duration_secs: u64) -> AlertRule {
+        AlertRule {
+            id: 1,
+            organization_id: 1,
+            name: "offline".to_string(),
+            kind: AlertRuleKind::DeviceOffline,
+            machine_id: None,
+            target: None,
+            threshold: None,
+            duration: Duration::from_secs(duration_secs),
+            channel_ids: vec![],
+        }
+    }
+
+    fn violation(fingerprint: &str, machine_id: Option<uuid::Uuid>) -> Violation {
+        Violation {
+            fingerprint: fingerprint.to_string(),
+            machine_id,
+            subject: fingerprint.to_string(),
+            message: String::new(),
+        }
+    }
+
+    fn at(secs: i64) -> DateTime<FixedOffset> {
+        DateTime::<chrono::Utc>::from_timestamp(1_700_000_000 + secs, 0)
+            .unwrap()
+            .fixed_offset()
+    }
+
+    #[test]
+    fn fires_after_duration_and_only_once() {
+        let rule = rule(60);
+        let mut evaluator = AlertEvaluator::new();
+        let obs = RuleObservation {
+            violations: vec![violation("device:a", None)],
+            ..Default::default()
+        };
+
+        assert!(evaluator.evaluate(&rule, &obs, at(0)).is_empty());
+        assert!(evaluator.evaluate(&rule, &obs, at(30)).is_empty());
+
+        let transitions = evaluator.evaluate(&rule, &obs, at(60));
+        assert_eq!(transitions.len(), 1);
+        assert!(matches!(
+            &transitions[0],
+            AlertTransition::Firing { since, .. } if *since == at(0)
+        ));
+
+        assert!(evaluator.evaluate(&rule, &obs, at(90)).is_empty());
+
+        let transitions = evaluator.evaluate(&rule, &RuleObservation::default(), at(120));
+        assert_eq!(
+            transitions,
+            vec![AlertTransition::Resolved {
+                rule_id: 1,
+                fingerprint: "device:a".to_string(),
+                event_id: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn pending_alert_is_dropped_silently() {
+        let rule = rule(60);
+        let mut evaluator = AlertEvaluator::new();
+        let obs = RuleObservation {
+            violations: vec![violation("device:a", None)],
+            ..Default::default()
+        };
+
+        assert!(evaluator.evaluate(&rule, &obs, at(0)).is_empty());
+        assert!(
+            evaluator
+                .evaluate(&rule, &RuleObservation::default(), at(30))
+                .is_empty()
+        );
+        // the pending timer restarts from scratch
+        assert!(evaluator.evaluate(&rule, &obs, at(70)).is_empty());
+        assert_eq!(evaluator.evaluate(&rule, &obs, at(130)).len(), 1);
+    }
+
+    #[test]
+    fn uncovered_machine_keeps_firing_state() {
+        let rule = rule(0);
+        let machine = uuid::Uuid::new_v4();
+        let mut evaluator = AlertEvaluator::new();
+        let obs = RuleObservation {
+            violations: vec![violation("peer:a", Some(machine))],
+            covered_machines: HashSet::from([machine]),
+        };
+        assert_eq!(evaluator.evaluate(&rule, &obs, at(0)).len(), 1);
+        evaluator.set_event_id(1, "peer:a", 42);
+
+        // machine could not be inspected this round
+        assert!(
+            evaluator
+                .evaluate(&rule, &RuleObservation::default(), at(10))
+                .is_empty()
+        );
+
+        let covered = RuleObservation {
+            violations: vec![],
+            covered_machines: HashSet::from([machine]),
+        };
+        assert_eq!(
+            evaluator.evaluate(&rule, &covered, at(20)),
+            vec![AlertTransition::Resolved {
+                rule_id: 1,
+                fingerprint: "peer:a".to_string(),
+                event_id: Some(42),
+            }]
+        );
+    }
+
+    #[test]
+    fn retain_rules_returns_dropped_firing_events() {
+        let mut evaluator = AlertEvaluator::new();
+        evaluator.restore_firing(1, "a".to_string(), 10, None);
+        evaluator.restore_firing(2, "b".to_string(), 20, None);
+
+        assert_eq!(evaluator.retain_rules(&HashSet::from([2])), vec![10]);
+        assert!(evaluator.retain_rules(&HashSet::from([2])).is_empty());
+    }
+}
@@ -0,0 +1,889 @@
+//! Alert rules evaluated against the live state of connected devices.
+//!
+//! Every evaluation round collects a [`collector::FleetSnapshot`] per organization
+//! (online sessions, peer/route lists and credentials fetched through session
+//! RPC), turns it into violations per rule and feeds them to the
+//! [`evaluator::AlertEvaluator`]. Firing/resolved transitions are persisted in
+//! `alert_events` and delivered through the rule's notification channels
+//! unless a matching silence is active.
+
+pub mod collector;
+pub mod evaluator;
+pub mod notifier;
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{DateTime, FixedOffset};
+use dashmap::DashMap;
+use easytier::proto::web::HeartbeatRequest;
+use sea_orm::{
+    ActiveModelTrait as _, ColumnTrait as _, DbErr, EntityTrait as _, IntoActiveModel as _,
+    QueryFilter as _, QueryOrder as _, QuerySelect as _, Set,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use tokio_util::task::AbortOnDropHandle;
+
+use crate::{
+    client_manager::{ClientManager, storage::StorageToken},
+    db::{
+        Db, OrgIdInDb,
+        entity::{
+            alert_channels, alert_events, alert_rules, alert_silences, user_running_network_configs,
+        },
+    },
+};
+use collector::{CollectOptions, FleetSnapshot, MachineSnapshot, OfflineMachine};
+use evaluator::{AlertEvaluator, AlertTransition};
+use notifier::{AlertNotification, AlertStatus, NotificationChannel, Notifier};
+
+#[derive(Debug, Clone, clap::Args)]
+pub struct AlertOptions {
+    /// Interval in seconds between two evaluations of the alert rules, 0 disables alerting.
+    #[arg(long, env = "ET_ALERT_EVAL_INTERVAL", default_value = "30")]
+    pub alert_eval_interval: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertRuleKind {
+    /// The device has no active session.
+    DeviceOffline,
+    /// `target` (hostname or virtual ipv4) is missing from the route table.
+    PeerUnreachable,
+    /// Latency to a peer is above `threshold` milliseconds.
+    HighLatency,
+    /// A peer is only reachable through a relay.
+    RelayOnly,
+    /// A credential expires within `threshold` seconds.
+    CredentialExpiry,
+}
+
+impl AlertRuleKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertRuleKind::DeviceOffline => "device_offline",
+            AlertRuleKind::PeerUnreachable => "peer_unreachable",
+            AlertRuleKind::HighLatency => "high_latency",
+            AlertRuleKind::RelayOnly => "relay_only",
+            AlertRuleKind::CredentialExpiry => "credential_expiry",
+        }
+    }
+}
+
+impl FromStr for AlertRuleKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "device_offline" => AlertRuleKind::DeviceOffline,
+            "peer_unreachable" => AlertRuleKind::PeerUnreachable,
+            "high_latency" => AlertRuleKind::HighLatency,
+            "relay_only" => AlertRuleKind::RelayOnly,
+            "credential_expiry" => AlertRuleKind::CredentialExpiry,
+            _ => anyhow::bail!("unknown alert rule kind: {}", s),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AlertRule {
+    pub id: i32,
+    pub organization_id: OrgIdInDb,
+    pub name: String,
+    pub kind: AlertRuleKind,
+    pub machine_id: Option<uuid::Uuid>,
+    pub target: Option<String>,
+    pub threshold: Option<f64>,
+    /// How long a condition must hold before the alert fires.
+    pub duration: Duration,
+    pub channel_ids: Vec<i32>,
+}
+
+impl TryFrom<&alert_rules::Model> for AlertRule {
+    type Error = anyhow::Error;
+
+    fn try_from(m: &alert_rules::Model) -> Result<Self, Self::Error> {
+        Ok(AlertRule {
+            id: m.id,
+            organization_id: m.organization_id,
+            name: m.name.clone(),
+            kind: m.kind.parse()?,
+            machine_id: m.machine_id.as_deref().map(str::parse).transpose()?,
+            target: m.target.clone(),
+            threshold: m.threshold,
+            duration: Duration::from_secs(m.duration_secs.max(0) as u64),
+            channel_ids: serde_json::from_str(&m.channel_ids)?,
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AlertError {
+    #[error("{0} not found")]
+    NotFound(&'static str),
+    #[error("invalid request: {0}")]
+    Invalid(String),
+    #[error(transparent)]
+    Db(#[from] DbErr),
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRuleReq {
+    pub name: String,
+    pub kind: AlertRuleKind,
+    pub machine_id: Option<uuid::Uuid>,
+    pub target: Option<String>,
+    pub threshold: Option<f64>,
+    #[serde(default)]
+    pub duration_secs: u64,
+    #[serde(default)]
+    pub channel_ids: Vec<i32>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertChannelReq {
+    pub name: String,
+    pub kind: String,
+    pub config: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertSilenceReq {
+    pub rule_id: Option<i32>,
+    pub machine_id: Option<uuid::Uuid>,
+    #[serde(default)]
+    pub comment: String,
+    pub starts_at: Option<DateTime<FixedOffset>>,
+    pub ends_at: DateTime<FixedOffset>,
+}
+
+fn now() -> DateTime<FixedOffset> {
+    chrono::Local::now().fixed_offset()
+}
+
+pub struct AlertManager {
+    db: Db,
+    client_mgr: Arc<ClientManager>,
+    notifier: Notifier,
+    evaluator: Mutex<AlertEvaluator>,
+    last_seen: DashMap<(OrgIdInDb, uuid::Uuid), (String, DateTime<FixedOffset>)>,
+}
+
+impl std::fmt::Debug for AlertManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AlertManager").finish()
+    }
+}
+
+impl AlertManager {
+    pub fn new(db: Db, client_mgr: Arc<ClientManager>) -> Self {
+        AlertManager {
+            db,
+            client_mgr,
+            notifier: Notifier::new(),
+            evaluator: Mutex::new(AlertEvaluator::new()),
+            last_seen: DashMap::new(),
+        }
+    }
+
+    /// Restore firing alerts from the database and start the evaluation loop.
+    pub async fn start(
+        self: &Arc<Self>,
+        interval: Duration,
+    ) -> anyhow::Result<AbortOnDropHandle<()>> {
+        let firing = alert_events::Entity::find()
+            .filter(alert_events::Column::Status.eq(AlertStatus::Firing.as_str()))
+            .all(self.db.orm_db())
+            .await?;
+        {
+            let mut evaluator = self.evaluator.lock().await;
+            for event in firing {
+                evaluator.restore_firing(
+                    event.rule_id,
+                    event.fingerprint,
+                    event.id,
+                    event.machine_id.and_then(|id| id.parse().ok()),
+                );
+            }
+        }
+
+        let this = self.clone();
+        Ok(AbortOnDropHandle::new(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = this.evaluate_once().await {
+                    tracing::warn!(?e, "alert evaluation round failed");
+                }
+            }
+        })))
+    }
+
+    pub async fn evaluate_once(&self) -> anyhow::Result<()> {
+        let now = now();
+        let rules = alert_rules::Entity::find()
+            .filter(alert_rules::Column::Enabled.eq(true))
+            .all(self.db.orm_db())
+            .await?
+            .iter()
+            .filter_map(|m| match AlertRule::try_from(m) {
+                Ok(rule) => Some(rule),
+                Err(e) => {
+                    tracing::warn!(rule_id = m.id, ?e, "skip malformed alert rule");
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        let mut evaluator = self.evaluator.lock().await;
+        let rule_ids = rules.iter().map(|r| r.id).collect::<HashSet<_>>();
+        for event_id in evaluator.retain_rules(&rule_ids) {
+            self.resolve_event(event_id, now).await?;
+        }
+
+        let sessions = self.client_mgr.list_sessions().await;
+        let mut rules_by_org = BTreeMap::<OrgIdInDb, Vec<AlertRule>>::new();
+        for rule in rules {
+            rules_by_org
+                .entry(rule.organization_id)
+                .or_default()
+                .push(rule);
+        }
+
+        for (org_id, rules) in rules_by_org {
+            let opts = CollectOptions::for_rules(rules.iter());
+            let fleet = self.collect_fleet(org_id, &sessions, opts, now).await?;
+            for rule in rules.iter() {
+                let observation = collector::observe(rule, &fleet, now);
+                for transition in evaluator.evaluate(rule, &observation, now) {
+                    self.apply_transition(&mut evaluator, rule, transition, now)
+                        .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn collect_fleet(
+        &self,
+        org_id: OrgIdInDb,
+        sessions: &[StorageToken],
+        opts: CollectOptions,
+        now: DateTime<FixedOffset>,
+    ) -> Result<FleetSnapshot, DbErr> {
+        let mut fleet = FleetSnapshot::default();
+
+        for token in sessions.iter().filter(|t| t.organization_id == org_id) {
+            let Some(session) = self
+                .client_mgr
+                .get_session_by_machine_id(org_id, &token.machine_id)
+            else {
+                continue;
+            };
+            let Some(req) = session.get_heartbeat_req().await else {
+                continue;
+            };
+            self.last_seen
+                .insert((org_id, token.machine_id), (req.hostname.clone(), now));
+
+            let instances = if opts.peers || opts.credentials {
+                Self::collect_instances(&session, &req, opts).await
+            } else {
+                Some(vec![])
+            };
+            fleet.online.insert(
+                token.machine_id,
+                MachineSnapshot {
+                    hostname: req.hostname,
+                    instances,
+                },
+            );
+        }
+
+        for item in self.last_seen.iter() {
+            let ((uid, machine_id), (hostname, last_seen)) = item.pair();
+            if *uid != org_id || fleet.online.contains_key(machine_id) {
+                continue;
+            }
+            fleet.offline.insert(
+                *machine_id,
+                OfflineMachine {
+                    hostname: Some(hostname.clone()),
+                    last_seen: Some(*last_seen),
+                },
+            );
+        }
+
+        let device_ids: Vec<String> = user_running_network_configs::Entity::find()
+            .select_only()
+            .column(user_running_network_configs::Column::DeviceId)
+            .distinct()
+            .filter(user_running_network_configs::Column::OrganizationId.eq(org_id))
+            .into_tuple()
+            .all(self.db.orm_db())
+            .await?;
+        for machine_id in device_ids.iter().filter_map(|id| id.parse().ok()) {
+            if !fleet.online.contains_key(&machine_id) {
+                fleet.offline.entry(machine_id).or_default();
+            }
+        }
+
+        Ok(fleet)
+    }
+
+    async fn collect_instances(
+        session: &crate::client_manager::session::Session,
+        req: &HeartbeatRequest,
+        opts: CollectOptions,
+    ) -> Option<Vec<collector::InstanceSnapshot>> {
+        let mut instances = Vec::with_capacity(req.running_network_instances.len());
+        for inst_id in req.running_network_instances.iter() {
+            instances.push(collector::collect_instance(session, (*inst_id).into(), opts).await?);
+        }
+        Some(instances)
+    }
+
+    async fn apply_transition(
+        &self,
+        evaluator: &mut AlertEvaluator,
+        rule: &AlertRule,
+        transition: AlertTransition,
+        now: DateTime<FixedOffset>,
+    ) -> Result<(), DbErr> {
+        let event = match transition {
+            AlertTransition::Firing {
+                rule_id,
+                since,
+                violation,
+            } => {
+                let existing = alert_events::Entity::find()
+                    .filter(alert_events::Column::RuleId.eq(rule_id))
+                    .filter(alert_events::Column::Fingerprint.eq(violation.fingerprint.clone()))
+                    .filter(alert_events::Column::Status.eq(AlertStatus::Firing.as_str()))
+                    .one(self.db.orm_db())
+                    .await?;
+                if let Some(existing) = existing {
+                    // already notified before a restart, don't send it twice
+                    evaluator.set_event_id(rule_id, &violation.fingerprint, existing.id);
+                    return Ok(());
+                }
+
+                let event = alert_events::ActiveModel {
+                    rule_id: Set(rule_id),
+                    fingerprint: Set(violation.fingerprint.clone()),
+                    machine_id: Set(violation.machine_id.map(|id| id.to_string())),
+                    subject: Set(violation.subject),
+                    message: Set(violation.message),
+                    status: Set(AlertStatus::Firing.as_str().to_string()),
+                    started_at: Set(since),
+                    resolved_at: Set(None),
+                    ..Default::default()
+                }
+                .insert(self.db.orm_db())
+                .await?;
+                evaluator.set_event_id(rule_id, &violation.fingerprint, event.id);
+                event
+            }
+            AlertTransition::Resolved {
+                rule_id,
+                fingerprint,
+                event_id,
+            } => {
+                let event = match event_id {
+                    Some(id) => {
+                        alert_events::Entity::find_by_id(id)
+                            .one(self.db.orm_db())
+                            .await?
+                    }
+                    None => {
+                        alert_events::Entity::find()
+                            .filter(alert_events::Column::RuleId.eq(rule_id))
+                            .filter(alert_events::Column::Fingerprint.eq(fingerprint))
+                            .filter(alert_events::Column::Status.eq(AlertStatus::Firing.as_str()))
+                            .one(self.db.orm_db())
+                            .await?
+                    }
+                };
+                let Some(event) = event else {
+                    return Ok(());
+                };
+                let mut active = event.into_active_model();
+                active.status = Set(AlertStatus::Resolved.as_str().to_string());
+                active.resolved_at = Set(Some(now));
+                active.update(self.db.orm_db()).await?
+            }
+        };
+
+        let machine_id = event.machine_id.as_deref().and_then(|id| id.parse().ok());
+        if self.is_silenced(rule, machine_id, now).await? {
+            tracing::debug!(rule_id = rule.id, event_id = event.id, "alert is silenced");
+            return Ok(());
+        }
+
+        let notification = AlertNotification {
+            status: if event.status == AlertStatus::Firing.as_str() {
+                AlertStatus::Firing
+            } else {
+                AlertStatus::Resolved
+            },
+            rule_id: rule.id,
+            rule_name: rule.name.clone(),
+            kind: rule.kind.as_str().to_string(),
+            fingerprint: event.fingerprint,
+            machine_id,
+            subject: event.subject,
+            message: event.message,
+            started_at: event.started_at.to_rfc3339(),
+            resolved_at: event.resolved_at.map(|t| t.to_rfc3339()),
+        };
+        self.notify(rule, notification).await
+    }
+
+    async fn resolve_event(&self, event_id: i32, now: DateTime<FixedOffset>) -> Result<(), DbErr> {
+        let Some(event) = alert_events::Entity::find_by_id(event_id)
+            .one(self.db.orm_db())
+            .await?
+        else {
+            return Ok(());
+        };
+        let mut active = event.into_active_model();
+        active.status = Set(AlertStatus::Resolved.as_str().to_string());
+        active.resolved_at = Set(Some(now));
+        active.update(self.db.orm_db()).await?;
+        Ok(())
+    }
+
+    async fn is_silenced(
+        &self,
+        rule: &AlertRule,
+        machine_id: Option<uuid::Uuid>,
+        now: DateTime<FixedOffset>,
+    ) -> Result<bool, DbErr> {
+        let silences = alert_silences::Entity::find()
+            .filter(alert_silences::Column::OrganizationId.eq(rule.organization_id))
+            .filter(alert_silences::Column::StartsAt.lte(now))
+            .filter(alert_silences::Column::EndsAt.gt(now))
+            .all(self.db.orm_db())
+            .await?;
+        Ok(silences.iter().any(|s| {
+            s.rule_id.is_none_or(|id| id == rule.id)
+                && s.machine_id
+                    .as_deref()
+                    .is_none_or(|id| machine_id.is_some_and(|m| m.to_string() == id))
+        }))
+    }
+
+    async fn notify(&self, rule: &AlertRule, notification: AlertNotification) -> Result<(), DbErr> {
+        if rule.channel_ids.is_empty() {
+            return Ok(());
+        }
+        let channels = alert_channels::Entity::find()
+            .filter(alert_channels::Column::OrganizationId.eq(rule.organization_id))
+            .filter(alert_channels::Column::Id.is_in(rule.channel_ids.clone()))
+            .all(self.db.orm_db())
+            .await?;
+
+        for channel in channels {
+            let parsed = match NotificationChannel::from_parts(&channel.kind, &channel.config) {
+                Ok(c) => c,
+                Err(e) => {
+                    tracing::warn!(channel_id = channel.id, ?e, "invalid alert channel");
+                    continue;
+                }
+            };
+            let notifier = self.notifier.clone();
+            let notification = notification.clone();
+            tokio::spawn(async move {
+                if let Err(e) = notifier.send(&parsed, &notification).await {
+                    tracing::warn!(channel_id = channel.id, ?e, "failed to deliver alert");
+                }
+            });
+        }
+
+        Ok(())
+    }
+
+    // --- management API ---
+
+    async fn validate_rule(&self, org_id: OrgIdInDb, req: &AlertRuleReq) -> Result<(), AlertError> {
+        if req.name.trim().is_empty() {
+            return Err(AlertError::Invalid("rule name is empty".to_string()));
+        }
+        match req.kind {
+            AlertRuleKind::PeerUnreachable if req.target.is_none() => {
+                return Err(AlertError::Invalid(
+                    "peer_unreachable rule requires a target".to_string(),
+                ));
+            }
+            AlertRuleKind::HighLatency if req.threshold.is_none() => {
+                return Err(AlertError::Invalid(
+                    "high_latency rule requires a threshold".to_string(),
+                ));
+            }
+            _ => {}
+        }
+        let channels = alert_channels::Entity::find()
+            .filter(alert_channels::Column::OrganizationId.eq(org_id))
+            .filter(alert_channels::Column::Id.is_in(req.channel_ids.clone()))
+            .all(self.db.orm_db())
+            .await?;
+        if channels.len() != req.channel_ids.iter().collect::<HashSet<_>>().len() {
+            return Err(AlertError::NotFound("alert channel"));
+        }
+        Ok(())
+    }
+
+    fn rule_active_model(req: AlertRuleReq) -> alert_rules::ActiveModel {
+        alert_rules::ActiveModel {
+            name: Set(req.name),
+            kind: Set(req.kind.as_str().to_string()),
+            machine_id: Set(req.machine_id.map(|id| id.to_string())),
+            target: Set(req.target),
+            threshold: Set(req.threshold),
+            duration_secs: Set(req.duration_secs.min(i64::MAX as u64) as i64),
+            channel_ids: Set(serde_json::to_string(&req.channel_ids).unwrap_or_default()),
+            enabled: Set(req.enabled),
+            update_time: Set(now()),
+            ..Default::default()
+        }
+    }
+
+    pub async fn list_rules(
+        &self,
+        org_id: OrgIdInDb,
+    ) -> Result<Vec<alert_rules::Model>, AlertError> {
+        Ok(alert_rules::Entity::find()
+            .filter(alert_rules::Column::OrganizationId.eq(org_id))
+            .order_by_asc(alert_rules::Column::Id)
+            .all(self.db.orm_db())
+            .await?)
+    }
+
+    pub async fn create_rule(
+        &self,
+        org_id: OrgIdInDb,
+        req: AlertRuleReq,
+    ) -> Result<alert_rules::Model, AlertError> {
+        self.validate_rule(org_id, &req).await?;
+        let mut model = Self::rule_active_model(req);
+        model.organization_id = Set(org_id);
+        model.create_time = Set(now());
+        Ok(model.insert(self.db.orm_db()).await?)
+    }
+
+    pub async fn update_rule(
+        &self,
+        org_id: OrgIdInDb,
+        rule_id: i32,
+        req: AlertRuleReq,
+    ) -> Result<alert_rules::Model, AlertError> {
+        self.validate_rule(org_id, &req).await?;
+        let existing = alert_rules::Entity::find_by_id(rule_id)
+            .filter(alert_rules::Column::OrganizationId.eq(org_id))
+            .one(self.db.orm_db())
+            .await?
+            .ok_or(AlertError::NotFound("alert rule"))?;
+        let mut model = Self::rule_active_model(req);
+        model.id = Set(existing.id);
+        Ok(model.update(self.db.orm_db()).await?)
+    }
+
+    pub async fn delete_rule(&self, org_id: OrgIdInDb, rule_id: i32) -> Result<(), AlertError> {
+        let ret = alert_rules::Entity::delete_many()
+            .filter(alert_rules::Column::Id.eq(rule_id))
+            .filter(alert_rules::Column::OrganizationId.eq(org_id))
+            .exec(self.db.orm_db())
+            .await?;
+        if ret.rows_affected == 0 {
+            return Err(AlertError::NotFound("alert rule"));
+        }
+        Ok(())
+    }
+
+    pub async fn list_channels(
+        &self,
+        org_id: OrgIdInDb,
+    ) -> Result<Vec<alert_channels::Model>, AlertError> {
+        Ok(alert_channels::Entity::find()
+            .filter(alert_channels::Column::OrganizationId.eq(org_id))
+            .order_by_asc(alert_channels::Column::Id)
+            .all(self.db.orm_db())
+            .await?)
+    }
+
+    pub async fn create_channel(
+        &self,
+        org_id: OrgIdInDb,
+        req: AlertChannelReq,
+    ) -> Result<alert_channels::Model, AlertError> {
+        let config = req.config.to_string();
+        NotificationChannel::from_parts(&req.kind, &config)
+            .map_err(|e| AlertError::Invalid(e.to_string()))?;
+        Ok(alert_channels::ActiveModel {
+            organization_id: Set(org_id),
+            name: Set(req.name),
+            kind: Set(req.kind),
+            config: Set(config),
+            create_time: Set(now()),
+            ..Default::default()
+        }
+        .insert(self.db.orm_db())
+        .await?)
+    }
+
+    pub async fn delete_channel(
+        &self,
+        org_id: OrgIdInDb,
+        channel_id: i32,
+    ) -> Result<(), AlertError> {
+        let ret = alert_channels::Entity::delete_many()
+            .filter(alert_channels::Column::Id.eq(channel_id))
+            .filter(alert_channels::Column::OrganizationId.eq(org_id))
+            .exec(self.db.orm_db())
+            .await?;
+        if ret.rows_affected == 0 {
+            return Err(AlertError::NotFound("alert channel"));
+        }
+        Ok(())
+    }
+
+    /// Deliver a synthetic notification so that a channel can be verified.
+    pub async fn test_channel(&self, org_id: OrgIdInDb, channel_id: i32) -> Result<(), AlertError> {
+        let channel = alert_channels::Entity::find_by_id(channel_id)
+            .filter(alert_channels::Column::OrganizationId.eq(org_id))
+            .one(self.db.orm_db())
+            .await?
+            .ok_or(AlertError::NotFound("alert channel"))?;
+        let parsed = NotificationChannel::from_parts(&channel.kind, &channel.config)
+            .map_err(|e| AlertError::Invalid(e.to_string()))?;
+        let notification = AlertNotification {
+            status: AlertStatus::Firing,
+            rule_id: 0,
+            rule_name: "test".to_string(),
+            kind: "test".to_string(),
+            fingerprint: format!("test:{}", channel.id),
+            machine_id: None,
+            subject: channel.name.clone(),
+            message: format!("test notification for channel {}", channel.name),
+            started_at: now().to_rfc3339(),
+            resolved_at: None,
+        };
+        self.notifier
+            .send(&parsed, &notification)
+            .await
+            .map_err(|e| AlertError::Invalid(format!("delivery failed: {:#}", e)))
+    }
+
+    pub async fn list_silences(
+        &self,
+        org_id: OrgIdInDb,
+    ) -> Result<Vec<alert_silences::Model>, AlertError> {
+        Ok(alert_silences::Entity::find()
+            .filter(alert_silences::Column::OrganizationId.eq(org_id))
+            .order_by_desc(alert_silences::Column::EndsAt)
+            .all(self.db.orm_db())
+            .await?)
+    }
+
+    pub async fn create_silence(
+        &self,
+        org_id: OrgIdInDb,
+        req: AlertSilenceReq,
+    ) -> Result<alert_silences::Model, AlertError> {
+        let starts_at = req.starts_at.unwrap_or_else(now);
+        if req.ends_at <= starts_at {
+            return Err(AlertError::Invalid(
+                "silence must end after it starts".to_string(),
+            ));
+        }
+        if let Some(rule_id) = req.rule_id {
+            alert_rules::Entity::find_by_id(rule_id)
+                .filter(alert_rules::Column::OrganizationId.eq(org_id))
+                .one(self.db.orm_db())
+                .await?
+                .ok_or(AlertError::NotFound("alert rule"))?;
+        }
+        Ok(alert_silences::ActiveModel {
+            organization_id: Set(org_id),
+            rule_id: Set(req.rule_id),
+            machine_id: Set(req.machine_id.map(|id| id.to_string())),
+            comment: Set(req.comment),
+            starts_at: Set(starts_at),
+            ends_at: Set(req.ends_at),
+            create_time: Set(now()),
+            ..Default::default()
+        }
+        .insert(self.db.orm_db())
+        .await?)
+    }
+
+    pub async fn delete_silence(
+        &self,
+        org_id: OrgIdInDb,
+        silence_id: i32,
+    ) -> Result<(), AlertError> {
+        let ret = alert_silences::Entity::delete_many()
+            .filter(alert_silences::Column::Id.eq(silence_id))
+            .filter(alert_silences::Column::OrganizationId.eq(org_id))
+            .exec(self.db.orm_db())
+            .await?;
+        if ret.rows_affected == 0 {
+            return Err(AlertError::NotFound("alert silence"));
+        }
+        Ok(())
+    }
+
+    pub async fn list_events(
+        &self,
+        org_id: OrgIdInDb,
+        status: Option<AlertStatus>,
+    ) -> Result<Vec<alert_events::Model>, AlertError> {
+        let rule_ids = self
+            .list_rules(org_id)
+            .await?
+            .into_iter()
+            .map(|r| r.id)
+            .collect::<Vec<_>>();
+        let query =
+            alert_events::Entity::find().filter(alert_events::Column::RuleId.is_in(rule_ids));
+        let query = match status {
+            Some(status) => query.filter(alert_events::Column::Status.eq(status.as_str())),
+            None => query,
+        };
+        Ok(query
+            .order_by_desc(alert_events::Column::StartedAt)
+            .limit(500)
+            .all(self.db.orm_db())
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{FeatureFlags, webhook::WebhookConfig};
+
+    async fn alert_manager() -> (AlertManager, OrgIdInDb) {
+        let db = Db::memory_db().await;
+        let org_id = db
+            .auto_create_user("alert-user")
+            .await
+            .unwrap()
+            .default_organization_id
+            .unwrap();
+        let client_mgr = Arc::new(ClientManager::new(
+            db.clone(),
+            None,
+            Arc::new(FeatureFlags::default()),
+            Arc::new(WebhookConfig::new(None, None, None, None, None)),
+        ));
+        (AlertManager::new(db, client_mgr), org_id)
+    }
+
+    #[tokio::test]
+    async fn offline_device_fires_once_and_respects_silence() {
+        let (mgr, org_id) = alert_manager().await;
+        let machine_id = uuid::Uuid::new_v4();
+        let rule = mgr
+            .create_rule(
+                org_id,
+                AlertRuleReq {
+                    name: "gw offline".to_string(),
+                    kind: AlertRuleKind::DeviceOffline,
+                    machine_id: Some(machine_id),
+                    target: None,
+                    threshold: None,
+                    duration_secs: 0,
+                    channel_ids: vec![],
+                    enabled: true,
+                },
+            )
+            .await
+            .unwrap();
+
+        mgr.evaluate_once().await.unwrap();
+        mgr.evaluate_once().await.unwrap();
+
+        let firing = mgr
+            .list_events(org_id, Some(AlertStatus::Firing))
+            .await
+            .unwrap();
+        assert_eq!(firing.len(), 1);
+        assert_eq!(firing[0].rule_id, rule.id);
+        assert_eq!(firing[0].machine_id, Some(machine_id.to_string()));
+
+        mgr.create_silence(
+            org_id,
+            AlertSilenceReq {
+                rule_id: Some(rule.id),
+                machine_id: None,
+                comment: "maintenance".to_string(),
+                starts_at: None,
+                ends_at: now() + chrono::Duration::hours(1),
+            },
+        )
+        .await
+        .unwrap();
+        let parsed = AlertRule::try_from(&rule).unwrap();
+        assert!(
+            mgr.is_silenced(&parsed, Some(machine_id), now())
+                .await
+                .unwrap()
+        );
+
+        // disabling the rule resolves the firing alert
+        let mut req: AlertRuleReq = serde_json::from_value(serde_json::json!({
+            "name": "gw offline",
+            "kind": "device_offline",
+            "machine_id": machine_id,
+        }))
+        .unwrap();
+        req.enabled = false;
+        mgr.update_rule(org_id, rule.id, req).await.unwrap();
+        mgr.evaluate_once().await.unwrap();
+        assert!(
+            mgr.list_events(org_id, Some(AlertStatus::Firing))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn rule_validation_rejects_foreign_channels() {
+        let (mgr, org_id) = alert_manager().await;
+        let err = mgr
+            .create_rule(
+                org_id,
+                AlertRuleReq {
+                    name: "latency".to_string(),
+                    kind: AlertRuleKind::HighLatency,
+                    machine_id: None,
+                    target: None,
+                    threshold: Some(100.0),
+                    duration_secs: 60,
+                    channel_ids: vec![42],
+                    enabled: true,
+                },
+            )
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AlertError::NotFound(_)));
+    }
+}
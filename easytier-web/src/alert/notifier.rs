@@ -0,0 +1,459 @@
+use std::{collections::HashMap, time::Duration};
+
+use base64::Engine as _;
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncBufReadExt as _, AsyncWriteExt as _, BufReader},
+    net::TcpStream,
+};
+
+const SMTP_TIMEOUT: Duration = Duration::from_secs(15);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertStatus {
+    Firing,
+    Resolved,
+}
+
+impl AlertStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertStatus::Firing => "firing",
+            AlertStatus::Resolved => "resolved",
+        }
+    }
+}
+
+/// Payload delivered to every notification channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertNotification {
+    pub status: AlertStatus,
+    pub rule_id: i32,
+    pub rule_name: String,
+    pub kind: String,
+    pub fingerprint: String,
+    pub machine_id: Option<uuid::Uuid>,
+    pub subject: String,
+    pub message: String,
+    pub started_at: String,
+    pub resolved_at: Option<String>,
+}
+
+impl AlertNotification {
+    fn title(&self) -> String {
+        format!(
+            "[{}] {}: {}",
+            self.status.as_str().to_uppercase(),
+            self.rule_name,
+            self.subject
+        )
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookChannelConfig {
+    pub url: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+/// Plain SMTP delivery. TLS is expected to be handled by a local relay.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmtpChannelConfig {
+    /// `host:port` of the SMTP server.
+    pub server: String,
+    pub from: String,
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum NotificationChannel {
+    Webhook(WebhookChannelConfig),
+    Smtp(SmtpChannelConfig),
+}
+
+impl NotificationChannel {
+    pub fn from_parts(kind: &str, config: &str) -> anyhow::Result<Self> {
+        Ok(match kind {
+            "webhook" => NotificationChannel::Webhook(serde_json::from_str(config)?),
+            "smtp" => NotificationChannel::Smtp(serde_json::from_str(config)?),
+            _ => anyhow::bail!("unknown alert channel kind: {}", kind),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    client: reqwest::Client,
+}
+
+impl Notifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub async fn send(
+        &self,
+        channel: &NotificationChannel,
+        notification: &AlertNotification,
+    ) -> anyhow::Result<()> {
+        match channel {
+            NotificationChannel::Webhook(cfg) => self.send_webhook(cfg, notification).await,
+            NotificationChannel::Smtp(cfg) => send_smtp(cfg, notification).await,
+        }
+    }
+
+    async fn send_webhook(
+        &self,
+        cfg: &WebhookChannelConfig,
+        notification: &AlertNotification,
+    ) -> anyhow::Result<()> {
+        let mut req = self
+            .client
+            .post(&cfg.url)
+            .timeout(Duration::from_secs(10))
+            .json(notification);
+        for (k, v) in cfg.headers.iter() {
+            req = req.header(k, v);
+        }
+        let resp = req.send().await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("alert webhook returned status {}", resp.status());
+        }
+        Ok(())
+    }
+}
+
+async fn read_smtp_reply<R>(reader: &mut BufReader<R>) -> anyhow::Result<(u16, String)>
+where
+    R: tokio::io::AsyncRead + Unpin,
+{
+    let mut text = String::new();
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            anyhow::bail!("smtp server closed the connection");
+        }
+        let line = line.trim_end();
+        if line.len() < 3 {
+            anyhow::bail!("malformed smtp reply: {:?}", line);
+        }
+        let code = line[..3].parse::<u16>()?;
+        text.push_str(line.get(4..).unwrap_or_default());
+        // "250-..." continues a multi-line reply, "250 ..." ends it
+        if line.as_bytes().get(3) != Some(&b'-') {
+            return Ok((code, text));
+        }
+        text.push('\n');
+    }
+}
+
+async fn smtp_command<R, W>(
+    reader: &mut BufReader<R>,
+    writer: &mut W,
+    cmd: &str,
+    expected: &[u16],
+) -> anyhow::Result<()>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    writer.write_all(cmd.as_bytes()).await?;
+    writer.write_all(b"\r\n").await?;
+    let (code, text) = read_smtp_reply(reader).await?;
+    if !expected.contains(&code) {
+        let verb = cmd.split_whitespace().next().unwrap_or_default();
+        anyhow::bail!("smtp {} failed: {} {}", verb, code, text);
+    }
+    Ok(())
+}
+
+// hostnames in the subject are reported by devices, a line break in a header
+// value would start another header or the body
+fn header_value(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect()
+}
+
+// RFC 2047 encoded words for non-ascii header values, each word is at most 75
+// chars so the base64 text of one word is limited to 45 bytes
+fn encode_header_value(value: &str) -> String {
+    let value = header_value(value);
+    if value.is_ascii() {
+        return value;
+    }
+
+    let mut words = vec![];
+    let mut start = 0;
+    while start < value.len() {
+        let mut end = value.len().min(start + 45);
+        while !value.is_char_boundary(end) {
+            end -= 1;
+        }
+        words.push(format!(
+            "=?UTF-8?B?{}?=",
+            base64::engine::general_purpose::STANDARD.encode(&value[start..end])
+        ));
+        start = end;
+    }
+    // folded onto continuation lines
+    words.join("\n ")
+}
+
+fn smtp_message(cfg: &SmtpChannelConfig, notification: &AlertNotification) -> String {
+    let body = serde_json::to_string_pretty(notification).unwrap_or_default();
+    let msg = format!(
+        "From: {}\nTo: {}\nSubject: {}\nDate: {}\nContent-Type: text/plain; charset=utf-8\n\n{}\n\n{}\n",
+        header_value(&cfg.from),
+        header_value(&cfg.to.join(", ")),
+        encode_header_value(&notification.title()),
+        chrono::Local::now().to_rfc2822(),
+        notification.message,
+        body,
+    );
+    // normalize line endings, then apply dot-stuffing (RFC 5321 section 4.5.2)
+    msg.replace("\r\n", "\n")
+        .replace('\n', "\r\n")
+        .replace("\r\n.", "\r\n..")
+}
+
+async fn send_smtp(
+    cfg: &SmtpChannelConfig,
+    notification: &AlertNotification,
+) -> anyhow::Result<()> {
+    if cfg.to.is_empty() {
+        anyhow::bail!("smtp channel has no recipients");
+    }
+
+    tokio::time::timeout(SMTP_TIMEOUT, async {
+        let stream = TcpStream::connect(&cfg.server).await?;
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        let (code, text) = read_smtp_reply(&mut reader).await?;
+        if code != 220 {
+            anyhow::bail!("unexpected smtp greeting: {} {}", code, text);
+        }
+        smtp_command(&mut reader, &mut writer, "EHLO easytier-web", &[250]).await?;
+
+        if let (Some(username), Some(password)) = (&cfg.username, &cfg.password) {
+            let token = base64::engine::general_purpose::STANDARD
+                .encode(format!("\0{}\0{}", username, password));
+            smtp_command(
+                &mut reader,
+                &mut writer,
+                &format!("AUTH PLAIN {}", token),
+                &[235],
+            )
+            .await?;
+        }
+
+        smtp_command(
+            &mut reader,
+            &mut writer,
+            &format!("MAIL FROM:<{}>", cfg.from),
+            &[250],
+        )
+        .await?;
+        for to in cfg.to.iter() {
+            smtp_command(
+                &mut reader,
+                &mut writer,
+                &format!("RCPT TO:<{}>", to),
+                &[250, 251],
+            )
+            .await?;
+        }
+        smtp_command(&mut reader, &mut writer, "DATA", &[354]).await?;
+        smtp_command(
+            &mut reader,
+            &mut writer,
+            &format!("{}.", smtp_message(cfg, notification)),
+            &[250],
+        )
+        .await?;
+        let _ = smtp_command(&mut reader, &mut writer, "QUIT", &[221]).await;
+        Ok(())
+    })
+    .await
+    .map_err(|_| anyhow::anyhow!("smtp delivery timed out"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Json, Router, extract::State, routing::post};
+    use tokio::{net::TcpListener, sync::mpsc};
+
+    use super::*;
+
+    fn notification() -> AlertNotification {
+        AlertNotification {
+            status: AlertStatus::Firing,
+            rule_id: 1,
+            rule_name: "office offline".to_string(),
+            kind: "device_offline".to_string(),
+            fingerprint: "device:1".to_string(),
+            machine_id: None,
+            subject: "office-gw".to_string(),
+            message: "device has been offline for 5 minutes\n.".to_string(),
+            started_at: "2026-01-01T00:00:00+00:00".to_string(),
+            resolved_at: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn webhook_channel_posts_json() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let app = Router::new()
+            .route(
+                "/hook",
+                post(
+                    |State(tx): State<mpsc::UnboundedSender<serde_json::Value>>,
+                     Json(body): Json<serde_json::Value>| async move {
+                        tx.send(body).unwrap();
+                    },
+                ),
+            )
+            .with_state(tx);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let channel = NotificationChannel::from_parts(
+            "webhook",
+            &format!(r#"{{"url":"http://{}/hook"}}"#, addr),
+        )
+        .unwrap();
+        Notifier::new()
+            .send(&channel, &notification())
+            .await
+            .unwrap();
+
+        let body = rx.recv().await.unwrap();
+        assert_eq!(body["status"], "firing");
+        assert_eq!(body["rule_name"], "office offline");
+    }
+
+    #[tokio::test]
+    async fn smtp_channel_delivers_message() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (reader, mut writer) = stream.into_split();
+            let mut reader = BufReader::new(reader);
+            writer.write_all(b"220 stand-in ESMTP\r\n").await.unwrap();
+
+            let mut commands = vec![];
+            let mut data = String::new();
+            let mut in_data = false;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).await.unwrap() == 0 {
+                    break;
+                }
+                if in_data {
+                    if line == ".\r\n" {
+                        in_data = false;
+                        writer.write_all(b"250 queued\r\n").await.unwrap();
+                    } else {
+                        data.push_str(&line);
+                    }
+                    continue;
+                }
+                let line = line.trim_end().to_string();
+                let reply: &[u8] = if line.starts_with("EHLO") {
+                    b"250-stand-in\r\n250 AUTH PLAIN\r\n"
+                } else if line.starts_with("AUTH") {
+                    b"235 ok\r\n"
+                } else if line == "DATA" {
+                    in_data = true;
+                    b"354 go ahead\r\n"
+                } else if line == "QUIT" {
+                    writer.write_all(b"221 bye\r\n").await.unwrap();
+                    commands.push(line);
+                    break;
+                } else {
+                    b"250 ok\r\n"
+                };
+                commands.push(line);
+                writer.write_all(reply).await.unwrap();
+            }
+            (commands, data)
+        });
+
+        let channel = NotificationChannel::Smtp(SmtpChannelConfig {
+            server: addr.to_string(),
+            from: "alerts@example.com".to_string(),
+            to: vec!["ops@example.com".to_string()],
+            username: Some("user".to_string()),
+            password: Some("pass".to_string()),
+        });
+        Notifier::new()
+            .send(&channel, &notification())
+            .await
+            .unwrap();
+
+        let (commands, data) = server.await.unwrap();
+        assert!(commands.contains(&"MAIL FROM:<alerts@example.com>".to_string()));
+        assert!(commands.contains(&"RCPT TO:<ops@example.com>".to_string()));
+        assert!(data.contains("Subject: [FIRING] office offline: office-gw"));
+        // a line consisting of a single dot must be escaped
+        assert!(data.contains("\r\n..\r\n"));
+    }
+
+    #[test]
+    fn smtp_headers_ignore_line_breaks_in_hostnames() {
+        let cfg = SmtpChannelConfig {
+            server: "127.0.0.1:25".to_string(),
+            from: "alerts@example.com".to_string(),
+            to: vec!["ops@example.com".to_string()],
+            username: None,
+            password: None,
+        };
+        let mut n = notification();
+        n.subject = "gw\nBcc: victim@example.com\r\n\r\nforged body (1)".to_string();
+
+        let msg = smtp_message(&cfg, &n);
+        let (headers, _) = msg.split_once("\r\n\r\n").unwrap();
+        let headers = headers.split("\r\n").collect::<Vec<_>>();
+        assert_eq!(headers.len(), 5);
+        assert!(headers.iter().all(|h| !h.starts_with("Bcc")));
+        assert!(headers[2].starts_with("Subject: [FIRING] office offline: gw Bcc: victim"));
+
+        n.subject = "办公室网关".repeat(4);
+        let msg = smtp_message(&cfg, &n);
+        let subject = msg
+            .split("\r\n")
+            .skip_while(|l| !l.starts_with("Subject: "))
+            .take_while(|l| l.starts_with("Subject: ") || l.starts_with(' '))
+            .map(|l| l.trim_start_matches("Subject: ").trim())
+            .collect::<Vec<_>>();
+        assert!(subject.len() > 1);
+        let decoded = subject
+            .iter()
+            .map(|word| {
+                assert!(word.len() <= 75);
+                let b64 = word
+                    .strip_prefix("=?UTF-8?B?")
+                    .and_then(|w| w.strip_suffix("?="))
+                    .unwrap();
+                String::from_utf8(
+                    base64::engine::general_purpose::STANDARD
+                        .decode(b64)
+                        .unwrap(),
+                )
+                .unwrap()
+            })
+            .collect::<String>();
+        assert_eq!(decoded, format!("[FIRING] office offline: {}", n.subject));
+    }
+}
@@ -23,7 +23,18 @@ use crate::FeatureFlags;
 use crate::webhook::{ManagedNetworkConfig, SharedWebhookConfig};
 use tokio::task::JoinSet;
 
-use crate::db::{Db, UserIdInDb, entity::user_running_network_configs};
+use crate::db::{
+    Db, DeviceStatus, OrgIdInDb, UserIdInDb,
+    entity::{devices, user_running_network_configs},
+};
+
+/// A device from the inventory together with whether it is connected now.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DeviceInventoryItem {
+    #[serde(flatten)]
+    pub device: devices::Model,
+    pub online: bool,
+}
 
 #[derive(rust_embed::Embed)]
 #[folder = "resources/"]
@@ -151,12 +162,12 @@ impl ClientManager {
 
     pub fn get_session_by_machine_id(
         &self,
-        user_id: UserIdInDb,
+        org_id: OrgIdInDb,
         machine_id: &uuid::Uuid,
     ) -> Option<Arc<Session>> {
         let c_url = self
             .storage
-            .get_client_url_by_machine_id(user_id, machine_id)?;
+            .get_client_url_by_machine_id(org_id, machine_id)?;
         self.client_sessions
             .get(&c_url)
             .map(|item| item.value().clone())
@@ -164,12 +175,12 @@ impl ClientManager {
 
     pub async fn disconnect_session_by_machine_id(
         &self,
-        user_id: UserIdInDb,
+        org_id: OrgIdInDb,
         machine_id: &uuid::Uuid,
     ) -> bool {
         let Some(client_url) = self
             .storage
-            .get_client_url_by_machine_id(user_id, machine_id)
+            .get_client_url_by_machine_id(org_id, machine_id)
         else {
             return false;
         };
@@ -180,19 +191,26 @@ impl ClientManager {
         true
     }
 
-    pub async fn list_machine_by_user_id(&self, user_id: UserIdInDb) -> Vec<url::Url> {
-        self.storage.list_user_clients(user_id)
+    pub async fn list_machine_by_org_id(&self, org_id: OrgIdInDb) -> Vec<url::Url> {
+        self.storage.list_org_clients(org_id)
     }
 
-    pub async fn reconcile_managed_network_configs(
+    pub async fn get_default_organization_id(
         &self,
         user_id: UserIdInDb,
+    ) -> Result<OrgIdInDb, sea_orm::DbErr> {
+        self.db().get_default_organization_id(user_id).await
+    }
+
+    pub async fn reconcile_managed_network_configs(
+        &self,
+        org_id: OrgIdInDb,
         machine_id: uuid::Uuid,
         desired_configs: Vec<ManagedNetworkConfig>,
     ) -> anyhow::Result<()> {
         session::SessionRpcService::reconcile_web_source_configs(
             &self.storage,
-            user_id,
+            org_id,
             machine_id,
             desired_configs,
         )
@@ -210,6 +228,69 @@ impl ClientManager {
         s.data().read().await.location().cloned()
     }
 
+    pub async fn list_devices(
+        &self,
+        org_id: OrgIdInDb,
+        status: Option<DeviceStatus>,
+    ) -> Result<Vec<DeviceInventoryItem>, sea_orm::DbErr> {
+        let devices = self.db().list_devices(org_id, status).await?;
+        let mut items = Vec::with_capacity(devices.len());
+        for device in devices {
+            let session = device
+                .machine_id
+                .parse()
+                .ok()
+                .and_then(|machine_id| self.get_session_by_machine_id(org_id, &machine_id));
+            let online = match session {
+                Some(session) => session.get_heartbeat_req().await.is_some(),
+                None => false,
+            };
+            items.push(DeviceInventoryItem { device, online });
+        }
+        Ok(items)
+    }
+
+    /// Move a device to a new enrollment state. Rejected and retired devices
+    /// are disconnected; with `wipe`, a retired device also loses its managed
+    /// configs, right away if it is online or on its next connection attempt.
+    pub async fn set_device_status(
+        &self,
+        org_id: OrgIdInDb,
+        machine_id: uuid::Uuid,
+        status: DeviceStatus,
+        wipe: bool,
+    ) -> anyhow::Result<Option<devices::Model>> {
+        let wipe = wipe && status == DeviceStatus::Retired;
+        let Some(device) = self
+            .db()
+            .set_device_status(org_id, machine_id, status, wipe)
+            .await?
+        else {
+            return Ok(None);
+        };
+        if matches!(status, DeviceStatus::Approved | DeviceStatus::Pending) {
+            return Ok(Some(device));
+        }
+
+        if let Some(session) = self.get_session_by_machine_id(org_id, &machine_id) {
+            if wipe
+                && let Err(e) = Session::wipe_managed_configs(
+                    &session.scoped_rpc_client(),
+                    self.db(),
+                    org_id,
+                    machine_id,
+                )
+                .await
+            {
+                tracing::warn!(?org_id, ?machine_id, %e, "Failed to wipe managed configs, will retry on reconnect");
+            }
+            self.disconnect_session_by_machine_id(org_id, &machine_id)
+                .await;
+        }
+
+        Ok(self.db().get_device(org_id, machine_id).await?)
+    }
+
     fn db(&self) -> &Db {
         self.storage.db()
     }
@@ -305,23 +386,23 @@ impl ClientManager {
 
 impl
     RemoteClientManager<
-        (UserIdInDb, uuid::Uuid),
+        (OrgIdInDb, uuid::Uuid),
         user_running_network_configs::Model,
         sea_orm::DbErr,
     > for ClientManager
 {
     fn get_rpc_client(
         &self,
-        (user_id, machine_id): (UserIdInDb, uuid::Uuid),
+        (org_id, machine_id): (OrgIdInDb, uuid::Uuid),
     ) -> Option<Box<dyn WebClientService<Controller = BaseController> + Send>> {
-        let s = self.get_session_by_machine_id(user_id, &machine_id)?;
+        let s = self.get_session_by_machine_id(org_id, &machine_id)?;
         Some(s.scoped_rpc_client())
     }
 
     fn get_storage(
         &self,
     ) -> &impl remote_client::Storage<
-        (UserIdInDb, uuid::Uuid),
+        (OrgIdInDb, uuid::Uuid),
         user_running_network_configs::Model,
         sea_orm::DbErr,
     > {
@@ -22,11 +22,12 @@ use easytier::{
     rpc_service::remote_client::{ListNetworkProps, PersistentConfig as _, Storage as _},
     tunnel::Tunnel,
 };
-use tokio::sync::{RwLock, broadcast};
+use tokio::sync::{RwLock, broadcast, mpsc};
 use tokio_util::task::AbortOnDropHandle;
 
 use super::storage::{Storage, StorageToken, WeakRefStorage};
 use crate::FeatureFlags;
+use crate::db::{Db, DeviceCheckIn, DeviceOwner, DeviceStatus, OrgIdInDb};
 use crate::webhook::SharedWebhookConfig;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -75,6 +76,9 @@ pub struct Location {
     pub region: Option<String>,
 }
 
+/// A retired machine whose managed configs should be removed over this session.
+type DeviceWipeRequest = (OrgIdInDb, uuid::Uuid);
+
 #[derive(Debug)]
 pub struct SessionData {
     storage: WeakRefStorage,
@@ -89,6 +93,7 @@ pub struct SessionData {
     req: Option<HeartbeatRequest>,
     location: Option<Location>,
     heartbeat_count: std::sync::atomic::AtomicU32,
+    wipe_requester: mpsc::Sender<DeviceWipeRequest>,
 }
 
 impl SessionData {
@@ -98,6 +103,7 @@ impl SessionData {
         location: Option<Location>,
         feature_flags: Arc<FeatureFlags>,
         webhook_config: SharedWebhookConfig,
+        wipe_requester: mpsc::Sender<DeviceWipeRequest>,
     ) -> Self {
         let (tx, _rx1) = broadcast::channel(2);
 
@@ -113,6 +119,7 @@ impl SessionData {
             req: None,
             location,
             heartbeat_count: std::sync::atomic::AtomicU32::new(0),
+            wipe_requester,
         }
     }
 
@@ -136,11 +143,20 @@ impl Drop for SessionData {
         {
             storage.remove_client(token);
 
+            let db = storage.db().clone();
+            let (org_id, machine_id) = (token.organization_id, token.machine_id);
+            tokio::spawn(async move {
+                if let Err(e) = db.touch_device(org_id, machine_id).await {
+                    tracing::warn!(?org_id, ?machine_id, %e, "Failed to update device last seen");
+                }
+            });
+
             // Notify the webhook receiver when a node disconnects.
             if self.webhook_config.is_enabled() {
                 let webhook = self.webhook_config.clone();
                 let machine_id = token.machine_id.to_string();
                 let user_id = Some(token.user_id);
+                let organization_id = Some(token.organization_id);
                 let token_value = token.token.clone();
                 let web_instance_id = webhook.web_instance_id.clone();
                 let binding_version = self.binding_version;
@@ -150,6 +166,7 @@ impl Drop for SessionData {
                             machine_id,
                             token: token_value,
                             user_id,
+                            organization_id,
                             web_instance_id,
                             binding_version,
                         })
@@ -194,13 +211,13 @@ impl SessionRpcService {
 
     pub(super) async fn reconcile_web_source_configs(
         storage: &Storage,
-        user_id: i32,
+        org_id: OrgIdInDb,
         machine_id: uuid::Uuid,
         desired_configs: Vec<crate::webhook::ManagedNetworkConfig>,
     ) -> anyhow::Result<()> {
         let existing_configs = storage
             .db()
-            .list_network_configs((user_id, machine_id), ListNetworkProps::All)
+            .list_network_configs((org_id, machine_id), ListNetworkProps::All)
             .await
             .map_err(|e| anyhow::anyhow!("failed to list existing network configs: {:?}", e))?;
         let existing_sources = existing_configs
@@ -229,7 +246,7 @@ impl SessionRpcService {
             })?;
             if let Some(PersistedConfigSource::User) = existing_sources.get(&inst_id) {
                 tracing::warn!(
-                    ?user_id,
+                    ?org_id,
                     ?machine_id,
                     instance_id = %inst_id,
                     "skip web config because a user-owned config already exists"
@@ -245,7 +262,7 @@ impl SessionRpcService {
             storage
                 .db()
                 .insert_or_update_user_network_config(
-                    (user_id, machine_id),
+                    (org_id, machine_id),
                     inst_id,
                     config,
                     ConfigSource::Web,
@@ -263,7 +280,7 @@ impl SessionRpcService {
         if !stale_ids.is_empty() {
             storage
                 .db()
-                .delete_network_configs((user_id, machine_id), &stale_ids)
+                .delete_network_configs((org_id, machine_id), &stale_ids)
                 .await
                 .map_err(|e| anyhow::anyhow!("failed to delete stale network configs: {:?}", e))?;
         }
@@ -294,7 +311,14 @@ impl SessionRpcService {
         &self,
         req: HeartbeatRequest,
     ) -> rpc_types::error::Result<HeartbeatResponse> {
-        let (storage, feature_flags, webhook_config, client_url, applied_config_revision) = {
+        let (
+            storage,
+            feature_flags,
+            webhook_config,
+            client_url,
+            applied_config_revision,
+            wipe_requester,
+        ) = {
             let data = self.data.read().await;
             let Ok(storage) = Storage::try_from(data.storage.clone()) else {
                 tracing::error!("Failed to get storage");
@@ -306,6 +330,7 @@ impl SessionRpcService {
                 data.webhook_config.clone(),
                 data.client_url.clone(),
                 data.applied_config_revision.clone(),
+                data.wipe_requester.clone(),
             )
         };
 
@@ -328,11 +353,12 @@ impl SessionRpcService {
         };
 
         let (
-            user_id,
+            owner,
             webhook_source_configs,
             webhook_config_revision,
             webhook_validated,
             binding_version,
+            pre_approved,
         ) = if webhook_config.is_enabled() {
             if should_call_webhook {
                 let webhook_req = crate::webhook::ValidateTokenRequest {
@@ -354,13 +380,13 @@ impl SessionRpcService {
                     .map_err(|e| anyhow::anyhow!("Webhook token validation failed: {:?}", e))?;
 
                 if resp.valid {
-                    let user_id = match storage
+                    let owner = match storage
                         .db()
-                        .get_user_id_by_token(req.user_token.clone())
+                        .get_device_owner_by_token(req.user_token.clone())
                         .await
                         .map_err(|e| anyhow::anyhow!("DB error: {:?}", e))?
                     {
-                        Some(id) => id,
+                        Some(owner) => owner,
                         None => storage
                             .auto_create_user(&req.user_token)
                             .await
@@ -369,6 +395,7 @@ impl SessionRpcService {
                             })?,
                     };
                     let binding_version = resp.binding_version;
+                    let pre_approved = resp.pre_approved;
                     let (webhook_source_configs, webhook_config_revision) =
                         Self::managed_configs_for_revision(
                             applied_config_revision.as_deref(),
@@ -376,11 +403,12 @@ impl SessionRpcService {
                         )
                         .map_err(rpc_types::error::Error::from)?;
                     (
-                        user_id,
+                        owner,
                         webhook_source_configs,
                         webhook_config_revision,
                         true,
                         Some(binding_version),
+                        pre_approved,
                     )
                 } else {
                     return Err(anyhow::anyhow!(
@@ -391,30 +419,41 @@ impl SessionRpcService {
                     .into());
                 }
             } else {
-                let user_id = cached_storage_token
+                let owner = cached_storage_token
                     .as_ref()
+                    .map(|token| DeviceOwner {
+                        user_id: token.user_id,
+                        organization_id: token.organization_id,
+                        enrollment_token_id: None,
+                    })
                     .ok_or_else(|| {
                         anyhow::anyhow!("Storage token not found for non-first heartbeat")
-                    })?
-                    .user_id;
+                    })?;
                 let binding_version = {
                     let data = self.data.read().await;
                     data.binding_version
                 };
-                (user_id, Vec::new(), String::new(), false, binding_version)
+                (
+                    owner,
+                    Vec::new(),
+                    String::new(),
+                    false,
+                    binding_version,
+                    false,
+                )
             }
         } else {
-            let user_id = match storage
+            let owner = match storage
                 .db()
-                .get_user_id_by_token(req.user_token.clone())
+                .get_device_owner_by_token(req.user_token.clone())
                 .await
                 .with_context(|| {
                     format!(
-                        "Failed to get user id by token from db: {:?}",
+                        "Failed to get device owner by token from db: {:?}",
                         req.user_token
                     )
                 })? {
-                Some(id) => id,
+                Some(owner) => owner,
                 None if feature_flags.allow_auto_create_user => storage
                     .auto_create_user(&req.user_token)
                     .await
@@ -425,15 +464,47 @@ impl SessionRpcService {
                     );
                 }
             };
-            (user_id, Vec::new(), String::new(), false, None)
+            (owner, Vec::new(), String::new(), false, None, false)
         };
 
+        // Only machines approved in their organization get bound to the session.
+        if cached_storage_token.is_none() {
+            let check_in = DeviceCheckIn {
+                hostname: Some(req.hostname.clone()),
+                version: Some(req.easytier_version.clone()),
+                os_type: req.device_os.as_ref().map(|info| info.os_type.clone()),
+                os_version: req.device_os.as_ref().map(|info| info.version.clone()),
+                os_distribution: req.device_os.as_ref().map(|info| info.distribution.clone()),
+                public_ip: client_url.host_str().map(str::to_string),
+            };
+            let device = storage
+                .db()
+                .check_in_device(&owner, machine_id, &check_in, pre_approved)
+                .await
+                .with_context(|| format!("Failed to check in machine {:?}", machine_id))?;
+            match device.device_status() {
+                DeviceStatus::Approved => {}
+                status => {
+                    if status == DeviceStatus::Retired && device.wipe_pending {
+                        let _ = wipe_requester.try_send((owner.organization_id, machine_id));
+                    }
+                    return Err(anyhow::anyhow!(
+                        "Machine {:?} is {} in organization {}",
+                        machine_id,
+                        status.as_str(),
+                        owner.organization_id
+                    )
+                    .into());
+                }
+            }
+        }
+
         let should_reconcile = webhook_validated
             && applied_config_revision.as_deref() != Some(webhook_config_revision.as_str());
         if should_reconcile {
             Self::reconcile_web_source_configs(
                 &storage,
-                user_id,
+                owner.organization_id,
                 machine_id,
                 webhook_source_configs,
             )
@@ -455,7 +526,8 @@ impl SessionRpcService {
                     token: req.user_token.clone(),
                     client_url: data.client_url.clone(),
                     machine_id,
-                    user_id,
+                    user_id: owner.user_id,
+                    organization_id: owner.organization_id,
                 });
                 data.binding_version = binding_version;
 
@@ -465,7 +537,8 @@ impl SessionRpcService {
                         crate::webhook::NodeConnectedRequest {
                             machine_id: machine_id.to_string(),
                             token: req.user_token.clone(),
-                            user_id: Some(user_id),
+                            user_id: Some(owner.user_id),
+                            organization_id: Some(owner.organization_id),
                             hostname: req.hostname.clone(),
                             version: req.easytier_version.clone(),
                             os_type: req.device_os.as_ref().map(|info| info.os_type.clone()),
@@ -540,6 +613,8 @@ pub struct Session {
     data: SharedSessionData,
 
     config_reconcile_task: Option<AbortOnDropHandle<()>>,
+    wipe_receiver: Option<mpsc::Receiver<DeviceWipeRequest>>,
+    device_wipe_task: Option<AbortOnDropHandle<()>>,
 }
 
 impl Debug for Session {
@@ -558,8 +633,15 @@ impl Session {
         feature_flags: Arc<FeatureFlags>,
         webhook_config: SharedWebhookConfig,
     ) -> Self {
-        let session_data =
-            SessionData::new(storage, client_url, location, feature_flags, webhook_config);
+        let (wipe_requester, wipe_receiver) = mpsc::channel(1);
+        let session_data = SessionData::new(
+            storage,
+            client_url,
+            location,
+            feature_flags,
+            webhook_config,
+            wipe_requester,
+        );
         let data = Arc::new(RwLock::new(session_data));
 
         let rpc_mgr =
@@ -574,6 +656,8 @@ impl Session {
             rpc_mgr,
             data,
             config_reconcile_task: None,
+            wipe_receiver: Some(wipe_receiver),
+            device_wipe_task: None,
         }
     }
 
@@ -589,6 +673,75 @@ impl Session {
                     self.scoped_rpc_client(),
                 ),
             )));
+        if let Some(wipe_receiver) = self.wipe_receiver.take() {
+            self.device_wipe_task
+                .replace(AbortOnDropHandle::new(tokio::spawn(
+                    Self::wipe_devices_on_request(
+                        wipe_receiver,
+                        data.storage.clone(),
+                        self.scoped_rpc_client(),
+                    ),
+                )));
+        }
+    }
+
+    /// Remove every network config the web manages for a machine, first from
+    /// the device and then from the database, and clear its pending wipe.
+    pub async fn wipe_managed_configs(
+        rpc_client: &SessionRpcClient,
+        db: &Db,
+        org_id: OrgIdInDb,
+        machine_id: uuid::Uuid,
+    ) -> anyhow::Result<()> {
+        let configs = db
+            .list_network_configs((org_id, machine_id), ListNetworkProps::All)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to list network configs: {:?}", e))?;
+        let inst_ids = configs
+            .iter()
+            .filter_map(|cfg| cfg.network_instance_id.parse().ok())
+            .collect::<Vec<uuid::Uuid>>();
+
+        if !inst_ids.is_empty() {
+            rpc_client
+                .delete_network_instance(
+                    BaseController::default(),
+                    DeleteNetworkInstanceRequest {
+                        inst_ids: inst_ids.iter().map(|id| (*id).into()).collect(),
+                    },
+                )
+                .await?;
+            db.delete_network_configs((org_id, machine_id), &inst_ids)
+                .await
+                .map_err(|e| anyhow::anyhow!("failed to delete network configs: {:?}", e))?;
+        }
+        db.finish_device_wipe(org_id, machine_id).await?;
+        tracing::info!(
+            ?org_id,
+            ?machine_id,
+            count = inst_ids.len(),
+            "Wiped managed configs"
+        );
+        Ok(())
+    }
+
+    async fn wipe_devices_on_request(
+        mut receiver: mpsc::Receiver<DeviceWipeRequest>,
+        storage: WeakRefStorage,
+        rpc_client: SessionRpcClient,
+    ) {
+        // A retired machine is refused on every heartbeat, which keeps asking
+        // for the wipe until one round succeeds and clears the pending flag.
+        while let Some((org_id, machine_id)) = receiver.recv().await {
+            let Ok(storage) = Storage::try_from(storage.clone()) else {
+                return;
+            };
+            if let Err(e) =
+                Self::wipe_managed_configs(&rpc_client, storage.db(), org_id, machine_id).await
+            {
+                tracing::warn!(?org_id, ?machine_id, %e, "Failed to wipe managed configs");
+            }
+        }
     }
 
     fn collect_web_source_instance_ids(metas: &[NetworkMeta]) -> HashSet<String> {
@@ -639,7 +792,7 @@ impl Session {
 
     async fn sync_running_config_sources(
         db: &crate::db::Db,
-        user_id: i32,
+        org_id: OrgIdInDb,
         machine_id: uuid::Uuid,
         local_configs: &[crate::db::entity::user_running_network_configs::Model],
         metas: &[NetworkMeta],
@@ -670,7 +823,7 @@ impl Session {
             }
 
             db.insert_or_update_user_network_config(
-                (user_id, machine_id),
+                (org_id, machine_id),
                 inst_id,
                 local_cfg.get_network_config().map_err(|e| {
                     anyhow::anyhow!("failed to decode local network config {}: {:?}", inst_id, e)
@@ -735,25 +888,25 @@ impl Session {
                 return;
             };
 
-            let user_id = match storage
+            let org_id = match storage
                 .db
-                .get_user_id_by_token(req.user_token.clone())
+                .get_device_owner_by_token(req.user_token.clone())
                 .await
             {
-                Ok(Some(user_id)) => user_id,
+                Ok(Some(owner)) => owner.organization_id,
                 Ok(None) => {
                     tracing::info!("User not found by token: {:?}", req.user_token);
                     return;
                 }
                 Err(e) => {
-                    tracing::error!("Failed to get user id by token, error: {:?}", e);
+                    tracing::error!("Failed to get device owner by token, error: {:?}", e);
                     return;
                 }
             };
 
             let local_configs = match storage
                 .db
-                .list_network_configs((user_id, machine_id.into()), ListNetworkProps::EnabledOnly)
+                .list_network_configs((org_id, machine_id.into()), ListNetworkProps::EnabledOnly)
                 .await
             {
                 Ok(configs) => configs,
@@ -785,7 +938,7 @@ impl Session {
                     Ok(metas) => {
                         if let Err(e) = Self::sync_running_config_sources(
                             &storage.db,
-                            user_id,
+                            org_id,
                             machine_id.into(),
                             &local_configs,
                             &metas,
@@ -793,7 +946,7 @@ impl Session {
                         .await
                         {
                             tracing::warn!(
-                                ?user_id,
+                                ?org_id,
                                 ?machine_id,
                                 %e,
                                 "Failed to sync running network config sources"
@@ -802,7 +955,7 @@ impl Session {
                             local_configs = match storage
                                 .db
                                 .list_network_configs(
-                                    (user_id, machine_id.into()),
+                                    (org_id, machine_id.into()),
                                     ListNetworkProps::EnabledOnly,
                                 )
                                 .await
@@ -821,7 +974,7 @@ impl Session {
                     }
                     Err(e) => {
                         tracing::warn!(
-                            ?user_id,
+                            ?org_id,
                             %e,
                             "Failed to list running network instance metadata"
                         );
@@ -842,7 +995,7 @@ impl Session {
             if !cleaned_web_source_instances || desired_changed {
                 let db_web_inst_ids = match storage
                     .db
-                    .list_network_configs((user_id, machine_id.into()), ListNetworkProps::All)
+                    .list_network_configs((org_id, machine_id.into()), ListNetworkProps::All)
                     .await
                 {
                     Ok(configs) => Self::desired_web_source_instance_ids(&configs),
@@ -874,7 +1027,7 @@ impl Session {
                         )
                         .await;
                     tracing::info!(
-                        ?user_id,
+                        ?org_id,
                         "Clean stale web-source network instances on heartbeat: {:?}, user_token: {:?}",
                         ret,
                         req.user_token
@@ -900,7 +1053,7 @@ impl Session {
                     Ok(cfg) => cfg,
                     Err(e) => {
                         tracing::error!(
-                            ?user_id,
+                            ?org_id,
                             ?machine_id,
                             instance_id = %c.network_instance_id,
                             "Failed to deserialize network config, skipping: {:?}",
@@ -922,7 +1075,7 @@ impl Session {
                     )
                     .await;
                 tracing::info!(
-                    ?user_id,
+                    ?org_id,
                     "Run network instance: {:?}, user_token: {:?}",
                     ret,
                     req.user_token
@@ -990,7 +1143,11 @@ mod tests {
     #[tokio::test]
     async fn reconcile_web_source_configs_upserts_and_deletes_exact_set() {
         let storage = Storage::new(crate::db::Db::memory_db().await);
-        let user_id = storage.db().auto_create_user("web-user").await.unwrap().id;
+        let org_id = storage
+            .auto_create_user("web-user")
+            .await
+            .unwrap()
+            .organization_id;
         let machine_id = uuid::Uuid::new_v4();
         let keep_id = uuid::Uuid::new_v4();
         let stale_id = uuid::Uuid::new_v4();
@@ -999,7 +1156,7 @@ mod tests {
         storage
             .db()
             .insert_or_update_user_network_config(
-                (user_id, machine_id),
+                (org_id, machine_id),
                 keep_id,
                 NetworkConfig {
                     network_name: Some("old-name".to_string()),
@@ -1012,7 +1169,7 @@ mod tests {
         storage
             .db()
             .insert_or_update_user_network_config(
-                (user_id, machine_id),
+                (org_id, machine_id),
                 stale_id,
                 NetworkConfig {
                     network_name: Some("stale".to_string()),
@@ -1025,7 +1182,7 @@ mod tests {
 
         SessionRpcService::reconcile_web_source_configs(
             &storage,
-            user_id,
+            org_id,
             machine_id,
             vec![
                 crate::webhook::ManagedNetworkConfig {
@@ -1049,7 +1206,7 @@ mod tests {
 
         let configs = storage
             .db()
-            .list_network_configs((user_id, machine_id), ListNetworkProps::All)
+            .list_network_configs((org_id, machine_id), ListNetworkProps::All)
             .await
             .unwrap();
         let config_ids = configs
@@ -1064,7 +1221,7 @@ mod tests {
 
         let updated_keep = storage
             .db()
-            .get_network_config((user_id, machine_id), &keep_id.to_string())
+            .get_network_config((org_id, machine_id), &keep_id.to_string())
             .await
             .unwrap()
             .unwrap();
@@ -1080,12 +1237,11 @@ mod tests {
     #[tokio::test]
     async fn reconcile_web_source_configs_keep_user_owned_configs() {
         let storage = Storage::new(crate::db::Db::memory_db().await);
-        let user_id = storage
-            .db()
+        let org_id = storage
             .auto_create_user("web-user-keep-user")
             .await
             .unwrap()
-            .id;
+            .organization_id;
         let machine_id = uuid::Uuid::new_v4();
         let user_owned_id = uuid::Uuid::new_v4();
         let web_owned_id = uuid::Uuid::new_v4();
@@ -1093,7 +1249,7 @@ mod tests {
         storage
             .db()
             .insert_or_update_user_network_config(
-                (user_id, machine_id),
+                (org_id, machine_id),
                 user_owned_id,
                 NetworkConfig {
                     network_name: Some("user-owned".to_string()),
@@ -1106,7 +1262,7 @@ mod tests {
         storage
             .db()
             .insert_or_update_user_network_config(
-                (user_id, machine_id),
+                (org_id, machine_id),
                 web_owned_id,
                 NetworkConfig {
                     network_name: Some("web-owned".to_string()),
@@ -1119,7 +1275,7 @@ mod tests {
 
         SessionRpcService::reconcile_web_source_configs(
             &storage,
-            user_id,
+            org_id,
             machine_id,
             vec![crate::webhook::ManagedNetworkConfig {
                 instance_id: user_owned_id.to_string(),
@@ -1134,7 +1290,7 @@ mod tests {
 
         let user_owned = storage
             .db()
-            .get_network_config((user_id, machine_id), &user_owned_id.to_string())
+            .get_network_config((org_id, machine_id), &user_owned_id.to_string())
             .await
             .unwrap()
             .unwrap();
@@ -1145,7 +1301,7 @@ mod tests {
 
         let web_owned = storage
             .db()
-            .get_network_config((user_id, machine_id), &web_owned_id.to_string())
+            .get_network_config((org_id, machine_id), &web_owned_id.to_string())
             .await
             .unwrap();
         assert!(web_owned.is_none());
@@ -1210,19 +1366,18 @@ mod tests {
     #[tokio::test]
     async fn sync_running_config_sources_updates_enabled_config_source_from_runtime() {
         let storage = Storage::new(crate::db::Db::memory_db().await);
-        let user_id = storage
-            .db()
+        let org_id = storage
             .auto_create_user("web-user-sync-source")
             .await
             .unwrap()
-            .id;
+            .organization_id;
         let machine_id = uuid::Uuid::new_v4();
         let inst_id = uuid::Uuid::new_v4();
 
         storage
             .db()
             .insert_or_update_user_network_config(
-                (user_id, machine_id),
+                (org_id, machine_id),
                 inst_id,
                 NetworkConfig {
                     network_name: Some("web-owned".to_string()),
@@ -1235,12 +1390,12 @@ mod tests {
 
         let local_configs = storage
             .db()
-            .list_network_configs((user_id, machine_id), ListNetworkProps::EnabledOnly)
+            .list_network_configs((org_id, machine_id), ListNetworkProps::EnabledOnly)
             .await
             .unwrap();
         Session::sync_running_config_sources(
             storage.db(),
-            user_id,
+            org_id,
             machine_id,
             &local_configs,
             &[easytier::proto::api::manage::NetworkMeta {
@@ -1254,7 +1409,7 @@ mod tests {
 
         let updated = storage
             .db()
-            .get_network_config((user_id, machine_id), &inst_id.to_string())
+            .get_network_config((org_id, machine_id), &inst_id.to_string())
             .await
             .unwrap()
             .unwrap();
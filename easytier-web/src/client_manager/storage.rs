@@ -2,7 +2,7 @@ use std::sync::{Arc, Weak};
 
 use dashmap::DashMap;
 
-use crate::db::{Db, UserIdInDb};
+use crate::db::{Db, DeviceOwner, OrgIdInDb, UserIdInDb};
 
 // use this to maintain Storage
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -11,6 +11,7 @@ pub struct StorageToken {
     pub client_url: url::Url,
     pub machine_id: uuid::Uuid,
     pub user_id: UserIdInDb,
+    pub organization_id: OrgIdInDb,
 }
 
 #[derive(Debug, Clone)]
@@ -21,7 +22,7 @@ struct ClientInfo {
 
 #[derive(Debug)]
 pub struct StorageInner {
-    user_clients_map: DashMap<UserIdInDb, DashMap<uuid::Uuid, ClientInfo>>,
+    org_clients_map: DashMap<OrgIdInDb, DashMap<uuid::Uuid, ClientInfo>>,
     pub db: Db,
 }
 
@@ -40,7 +41,7 @@ impl TryFrom<WeakRefStorage> for Storage {
 impl Storage {
     pub fn new(db: Db) -> Self {
         Storage(Arc::new(StorageInner {
-            user_clients_map: DashMap::new(),
+            org_clients_map: DashMap::new(),
             db,
         }))
     }
@@ -48,7 +49,7 @@ impl Storage {
     fn remove_client_info_map(map: &DashMap<uuid::Uuid, ClientInfo>, stoken: &StorageToken) {
         map.remove_if(&stoken.machine_id, |_, v| {
             v.storage_token.client_url == stoken.client_url
-                && v.storage_token.user_id == stoken.user_id
+                && v.storage_token.organization_id == stoken.organization_id
         });
     }
 
@@ -67,7 +68,11 @@ impl Storage {
     }
 
     pub fn update_client(&self, stoken: StorageToken, report_time: i64) {
-        let inner = self.0.user_clients_map.entry(stoken.user_id).or_default();
+        let inner = self
+            .0
+            .org_clients_map
+            .entry(stoken.organization_id)
+            .or_default();
 
         let client_info = ClientInfo {
             storage_token: stoken.clone(),
@@ -78,8 +83,8 @@ impl Storage {
 
     pub fn remove_client(&self, stoken: &StorageToken) {
         self.0
-            .user_clients_map
-            .remove_if(&stoken.user_id, |_, set| {
+            .org_clients_map
+            .remove_if(&stoken.organization_id, |_, set| {
                 Self::remove_client_info_map(set, stoken);
                 set.is_empty()
             });
@@ -91,20 +96,20 @@ impl Storage {
 
     pub fn get_client_url_by_machine_id(
         &self,
-        user_id: UserIdInDb,
+        org_id: OrgIdInDb,
         machine_id: &uuid::Uuid,
     ) -> Option<url::Url> {
-        self.0.user_clients_map.get(&user_id).and_then(|info_map| {
+        self.0.org_clients_map.get(&org_id).and_then(|info_map| {
             info_map
                 .get(machine_id)
                 .map(|info| info.storage_token.client_url.clone())
         })
     }
 
-    pub fn list_user_clients(&self, user_id: UserIdInDb) -> Vec<url::Url> {
+    pub fn list_org_clients(&self, org_id: OrgIdInDb) -> Vec<url::Url> {
         self.0
-            .user_clients_map
-            .get(&user_id)
+            .org_clients_map
+            .get(&org_id)
             .map(|info_map| {
                 info_map
                     .iter()
@@ -116,10 +121,10 @@ impl Storage {
 
     pub fn list_clients(&self) -> Vec<StorageToken> {
         self.0
-            .user_clients_map
+            .org_clients_map
             .iter()
-            .flat_map(|user_clients| {
-                user_clients
+            .flat_map(|org_clients| {
+                org_clients
                     .value()
                     .iter()
                     .map(|info| info.value().storage_token.clone())
@@ -132,10 +137,14 @@ impl Storage {
         &self.0.db
     }
 
-    pub async fn auto_create_user(&self, username: &str) -> anyhow::Result<UserIdInDb> {
+    pub async fn auto_create_user(&self, username: &str) -> anyhow::Result<DeviceOwner> {
         let new_user = self.db().auto_create_user(username).await?;
         tracing::info!("Auto-created user '{}' with id {}", username, new_user.id);
-        Ok(new_user.id)
+        Ok(DeviceOwner {
+            user_id: new_user.id,
+            organization_id: self.db().get_default_organization_id(new_user.id).await?,
+            enrollment_token_id: None,
+        })
     }
 }
 
@@ -144,7 +153,7 @@ mod tests {
     use super::*;
 
     fn make_storage_token(
-        user_id: UserIdInDb,
+        org_id: OrgIdInDb,
         machine_id: uuid::Uuid,
         client_url: &str,
     ) -> StorageToken {
@@ -152,39 +161,40 @@ mod tests {
             token: format!("token-{machine_id}"),
             client_url: client_url.parse().unwrap(),
             machine_id,
-            user_id,
+            user_id: org_id,
+            organization_id: org_id,
         }
     }
 
     #[tokio::test]
-    async fn machine_id_is_scoped_within_each_user() {
+    async fn machine_id_is_scoped_within_each_organization() {
         let storage = Storage::new(Db::memory_db().await);
         let machine_id = uuid::Uuid::new_v4();
 
-        let user1_token = make_storage_token(1, machine_id, "tcp://127.0.0.1:1001");
-        let user2_token = make_storage_token(2, machine_id, "tcp://127.0.0.1:1002");
+        let org1_token = make_storage_token(1, machine_id, "tcp://127.0.0.1:1001");
+        let org2_token = make_storage_token(2, machine_id, "tcp://127.0.0.1:1002");
 
-        storage.update_client(user1_token.clone(), 10);
-        storage.update_client(user2_token.clone(), 20);
+        storage.update_client(org1_token.clone(), 10);
+        storage.update_client(org2_token.clone(), 20);
 
         assert_eq!(
             storage.get_client_url_by_machine_id(1, &machine_id),
-            Some(user1_token.client_url.clone())
+            Some(org1_token.client_url.clone())
         );
         assert_eq!(
             storage.get_client_url_by_machine_id(2, &machine_id),
-            Some(user2_token.client_url.clone())
+            Some(org2_token.client_url.clone())
         );
 
-        storage.remove_client(&user1_token);
+        storage.remove_client(&org1_token);
 
         assert_eq!(storage.get_client_url_by_machine_id(1, &machine_id), None);
         assert_eq!(
             storage.get_client_url_by_machine_id(2, &machine_id),
-            Some(user2_token.client_url.clone())
+            Some(org2_token.client_url.clone())
         );
 
-        storage.remove_client(&user2_token);
+        storage.remove_client(&org2_token);
 
         assert_eq!(storage.get_client_url_by_machine_id(2, &machine_id), None);
     }
@@ -192,21 +202,21 @@ mod tests {
     #[tokio::test]
     async fn list_clients_returns_current_storage_tokens() {
         let storage = Storage::new(Db::memory_db().await);
-        let user1_token = make_storage_token(1, uuid::Uuid::new_v4(), "tcp://127.0.0.1:1001");
-        let user2_token = make_storage_token(2, uuid::Uuid::new_v4(), "tcp://127.0.0.1:1002");
+        let org1_token = make_storage_token(1, uuid::Uuid::new_v4(), "tcp://127.0.0.1:1001");
+        let org2_token = make_storage_token(2, uuid::Uuid::new_v4(), "tcp://127.0.0.1:1002");
 
-        storage.update_client(user1_token.clone(), 10);
-        storage.update_client(user2_token.clone(), 20);
+        storage.update_client(org1_token.clone(), 10);
+        storage.update_client(org2_token.clone(), 20);
 
         let tokens = storage.list_clients();
         assert_eq!(tokens.len(), 2);
-        assert!(tokens.iter().any(|token| token.token == user1_token.token));
-        assert!(tokens.iter().any(|token| token.token == user2_token.token));
+        assert!(tokens.iter().any(|token| token.token == org1_token.token));
+        assert!(tokens.iter().any(|token| token.token == org2_token.token));
 
-        storage.remove_client(&user1_token);
+        storage.remove_client(&org1_token);
 
         let tokens = storage.list_clients();
         assert_eq!(tokens.len(), 1);
-        assert_eq!(tokens[0].token, user2_token.token);
+        assert_eq!(tokens[0].token, org2_token.token);
     }
 }
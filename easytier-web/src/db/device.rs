@@ -0,0 +1,459 @@
+use std::str::FromStr;
+
+use rand::{Rng as _, distributions::Alphanumeric};
+use sea_orm::{
+    ActiveModelTrait as _, ColumnTrait as _, DbErr, EntityTrait as _, IntoActiveModel as _,
+    QueryFilter as _, QueryOrder as _, Set, TransactionTrait as _, prelude::Expr,
+};
+use sqlx::types::chrono;
+
+use super::{
+    Db, DeviceOwner, OrgIdInDb, UserIdInDb,
+    entity::{devices, enrollment_tokens, organizations},
+};
+
+/// Prefix that tells enrollment tokens apart from user tokens at a glance.
+pub const ENROLLMENT_TOKEN_PREFIX: &str = "et-";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceStatus {
+    Pending,
+    Approved,
+    Rejected,
+    Retired,
+}
+
+impl DeviceStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeviceStatus::Pending => "pending",
+            DeviceStatus::Approved => "approved",
+            DeviceStatus::Rejected => "rejected",
+            DeviceStatus::Retired => "retired",
+        }
+    }
+}
+
+impl FromStr for DeviceStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pending" => Ok(DeviceStatus::Pending),
+            "approved" => Ok(DeviceStatus::Approved),
+            "rejected" => Ok(DeviceStatus::Rejected),
+            "retired" => Ok(DeviceStatus::Retired),
+            _ => anyhow::bail!("unknown device status: {}", s),
+        }
+    }
+}
+
+impl devices::Model {
+    pub fn device_status(&self) -> DeviceStatus {
+        // Unknown values only appear if the row was edited by hand; keep such
+        // devices out until someone looks at them.
+        self.status.parse().unwrap_or(DeviceStatus::Pending)
+    }
+}
+
+impl enrollment_tokens::Model {
+    /// Whether the token can still enroll a new machine.
+    pub fn is_usable(&self, now: chrono::DateTime<chrono::FixedOffset>) -> bool {
+        !self.revoked
+            && self.expires_at.is_none_or(|t| t > now)
+            && self.max_uses.is_none_or(|max| self.use_count < max)
+    }
+}
+
+/// What a device reports about itself when it checks in.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceCheckIn {
+    pub hostname: Option<String>,
+    pub version: Option<String>,
+    pub os_type: Option<String>,
+    pub os_version: Option<String>,
+    pub os_distribution: Option<String>,
+    pub public_ip: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NewEnrollmentToken {
+    pub description: String,
+    pub expires_at: Option<chrono::DateTime<chrono::FixedOffset>>,
+    pub max_uses: Option<i32>,
+}
+
+fn generate_enrollment_token() -> String {
+    let suffix: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(32)
+        .map(char::from)
+        .collect();
+    format!("{}{}", ENROLLMENT_TOKEN_PREFIX, suffix)
+}
+
+impl Db {
+    pub async fn create_enrollment_token(
+        &self,
+        org_id: OrgIdInDb,
+        created_by: UserIdInDb,
+        req: NewEnrollmentToken,
+    ) -> Result<enrollment_tokens::Model, DbErr> {
+        enrollment_tokens::ActiveModel {
+            organization_id: Set(org_id),
+            created_by: Set(created_by),
+            token: Set(generate_enrollment_token()),
+            description: Set(req.description),
+            expires_at: Set(req.expires_at),
+            max_uses: Set(req.max_uses),
+            use_count: Set(0),
+            revoked: Set(false),
+            create_time: Set(chrono::Local::now().fixed_offset()),
+            ..Default::default()
+        }
+        .insert(self.orm_db())
+        .await
+    }
+
+    pub async fn list_enrollment_tokens(
+        &self,
+        org_id: OrgIdInDb,
+    ) -> Result<Vec<enrollment_tokens::Model>, DbErr> {
+        enrollment_tokens::Entity::find()
+            .filter(enrollment_tokens::Column::OrganizationId.eq(org_id))
+            .order_by_asc(enrollment_tokens::Column::Id)
+            .all(self.orm_db())
+            .await
+    }
+
+    /// Stop a token from enrolling further machines. Machines it already
+    /// enrolled keep working, so the row is kept rather than deleted.
+    pub async fn revoke_enrollment_token(
+        &self,
+        org_id: OrgIdInDb,
+        token_id: i32,
+    ) -> Result<bool, DbErr> {
+        let ret = enrollment_tokens::Entity::update_many()
+            .col_expr(enrollment_tokens::Column::Revoked, Expr::value(true))
+            .filter(enrollment_tokens::Column::OrganizationId.eq(org_id))
+            .filter(enrollment_tokens::Column::Id.eq(token_id))
+            .exec(self.orm_db())
+            .await?;
+        Ok(ret.rows_affected > 0)
+    }
+
+    pub async fn get_enrollment_token(
+        &self,
+        token: &str,
+    ) -> Result<Option<enrollment_tokens::Model>, DbErr> {
+        enrollment_tokens::Entity::find()
+            .filter(enrollment_tokens::Column::Token.eq(token))
+            .one(self.orm_db())
+            .await
+    }
+
+    pub async fn set_require_device_approval(
+        &self,
+        org_id: OrgIdInDb,
+        require: bool,
+    ) -> Result<(), DbErr> {
+        organizations::Entity::update_many()
+            .col_expr(
+                organizations::Column::RequireDeviceApproval,
+                Expr::value(require),
+            )
+            .filter(organizations::Column::Id.eq(org_id))
+            .exec(self.orm_db())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_device(
+        &self,
+        org_id: OrgIdInDb,
+        machine_id: uuid::Uuid,
+    ) -> Result<Option<devices::Model>, DbErr> {
+        devices::Entity::find()
+            .filter(devices::Column::OrganizationId.eq(org_id))
+            .filter(devices::Column::MachineId.eq(machine_id.to_string()))
+            .one(self.orm_db())
+            .await
+    }
+
+    pub async fn list_devices(
+        &self,
+        org_id: OrgIdInDb,
+        status: Option<DeviceStatus>,
+    ) -> Result<Vec<devices::Model>, DbErr> {
+        let query = devices::Entity::find().filter(devices::Column::OrganizationId.eq(org_id));
+        let query = match status {
+            Some(status) => query.filter(devices::Column::Status.eq(status.as_str())),
+            None => query,
+        };
+        query
+            .order_by_asc(devices::Column::Id)
+            .all(self.orm_db())
+            .await
+    }
+
+    /// Record a device check-in and return its enrollment record.
+    ///
+    /// Known devices only get their inventory refreshed. A new device is
+    /// approved right away when it presents a usable enrollment token, when an
+    /// external validator pre-approved it, or when its organization does not
+    /// require approval; otherwise it waits in `pending`.
+    pub async fn check_in_device(
+        &self,
+        owner: &DeviceOwner,
+        machine_id: uuid::Uuid,
+        info: &DeviceCheckIn,
+        pre_approved: bool,
+    ) -> Result<devices::Model, DbErr> {
+        let now = chrono::Local::now().fixed_offset();
+        let txn = self.orm_db().begin().await?;
+
+        let existing = devices::Entity::find()
+            .filter(devices::Column::OrganizationId.eq(owner.organization_id))
+            .filter(devices::Column::MachineId.eq(machine_id.to_string()))
+            .one(&txn)
+            .await?;
+
+        let device = match existing {
+            Some(device) => {
+                let approve = pre_approved && device.device_status() == DeviceStatus::Pending;
+                let mut device = device.into_active_model();
+                device.hostname = Set(info.hostname.clone());
+                device.version = Set(info.version.clone());
+                device.os_type = Set(info.os_type.clone());
+                device.os_version = Set(info.os_version.clone());
+                device.os_distribution = Set(info.os_distribution.clone());
+                device.public_ip = Set(info.public_ip.clone());
+                device.last_seen = Set(now);
+                if approve {
+                    device.status = Set(DeviceStatus::Approved.as_str().to_string());
+                    device.status_changed_at = Set(now);
+                }
+                device.update(&txn).await?
+            }
+            None => {
+                let status = match owner.enrollment_token_id {
+                    Some(token_id) => {
+                        let token = enrollment_tokens::Entity::find_by_id(token_id)
+                            .one(&txn)
+                            .await?;
+                        match token {
+                            Some(token) if token.is_usable(now) => {
+                                let use_count = token.use_count + 1;
+                                let mut token = token.into_active_model();
+                                token.use_count = Set(use_count);
+                                token.update(&txn).await?;
+                                DeviceStatus::Approved
+                            }
+                            // An exhausted token must not let machines in, even
+                            // when the organization does not require approval.
+                            _ => DeviceStatus::Pending,
+                        }
+                    }
+                    None => {
+                        let require_approval =
+                            organizations::Entity::find_by_id(owner.organization_id)
+                                .one(&txn)
+                                .await?
+                                .is_some_and(|org| org.require_device_approval);
+                        if pre_approved || !require_approval {
+                            DeviceStatus::Approved
+                        } else {
+                            DeviceStatus::Pending
+                        }
+                    }
+                };
+                devices::ActiveModel {
+                    organization_id: Set(owner.organization_id),
+                    machine_id: Set(machine_id.to_string()),
+                    status: Set(status.as_str().to_string()),
+                    enrollment_token_id: Set(owner.enrollment_token_id),
+                    hostname: Set(info.hostname.clone()),
+                    version: Set(info.version.clone()),
+                    os_type: Set(info.os_type.clone()),
+                    os_version: Set(info.os_version.clone()),
+                    os_distribution: Set(info.os_distribution.clone()),
+                    public_ip: Set(info.public_ip.clone()),
+                    wipe_pending: Set(false),
+                    first_seen: Set(now),
+                    last_seen: Set(now),
+                    status_changed_at: Set(now),
+                    ..Default::default()
+                }
+                .insert(&txn)
+                .await?
+            }
+        };
+
+        txn.commit().await?;
+        Ok(device)
+    }
+
+    /// Change the status of a device. `wipe` marks managed configs for removal
+    /// the next time the device can be reached.
+    pub async fn set_device_status(
+        &self,
+        org_id: OrgIdInDb,
+        machine_id: uuid::Uuid,
+        status: DeviceStatus,
+        wipe: bool,
+    ) -> Result<Option<devices::Model>, DbErr> {
+        let Some(device) = self.get_device(org_id, machine_id).await? else {
+            return Ok(None);
+        };
+        let mut device = device.into_active_model();
+        device.status = Set(status.as_str().to_string());
+        device.status_changed_at = Set(chrono::Local::now().fixed_offset());
+        device.wipe_pending = Set(wipe);
+        Ok(Some(device.update(self.orm_db()).await?))
+    }
+
+    pub async fn finish_device_wipe(
+        &self,
+        org_id: OrgIdInDb,
+        machine_id: uuid::Uuid,
+    ) -> Result<(), DbErr> {
+        devices::Entity::update_many()
+            .col_expr(devices::Column::WipePending, Expr::value(false))
+            .filter(devices::Column::OrganizationId.eq(org_id))
+            .filter(devices::Column::MachineId.eq(machine_id.to_string()))
+            .exec(self.orm_db())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn touch_device(
+        &self,
+        org_id: OrgIdInDb,
+        machine_id: uuid::Uuid,
+    ) -> Result<(), DbErr> {
+        devices::Entity::update_many()
+            .col_expr(
+                devices::Column::LastSeen,
+                Expr::value(chrono::Local::now().fixed_offset()),
+            )
+            .filter(devices::Column::OrganizationId.eq(org_id))
+            .filter(devices::Column::MachineId.eq(machine_id.to_string()))
+            .exec(self.orm_db())
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn owner_of(db: &Db, name: &str) -> DeviceOwner {
+        db.auto_create_user(name).await.unwrap();
+        db.get_device_owner_by_token(name).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn devices_wait_for_approval_only_when_required() {
+        let db = Db::memory_db().await;
+        let owner = owner_of(&db, "device-user").await;
+        let info = DeviceCheckIn {
+            hostname: Some("host".to_string()),
+            ..Default::default()
+        };
+
+        let open = uuid::Uuid::new_v4();
+        let device = db
+            .check_in_device(&owner, open, &info, false)
+            .await
+            .unwrap();
+        assert_eq!(device.device_status(), DeviceStatus::Approved);
+
+        db.set_require_device_approval(owner.organization_id, true)
+            .await
+            .unwrap();
+        let gated = uuid::Uuid::new_v4();
+        let device = db
+            .check_in_device(&owner, gated, &info, false)
+            .await
+            .unwrap();
+        assert_eq!(device.device_status(), DeviceStatus::Pending);
+
+        // known devices keep their status, the validator can still approve
+        let device = db
+            .check_in_device(&owner, open, &info, false)
+            .await
+            .unwrap();
+        assert_eq!(device.device_status(), DeviceStatus::Approved);
+        let device = db
+            .check_in_device(&owner, gated, &info, true)
+            .await
+            .unwrap();
+        assert_eq!(device.device_status(), DeviceStatus::Approved);
+
+        let device = db
+            .set_device_status(owner.organization_id, gated, DeviceStatus::Retired, true)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(device.wipe_pending);
+        let device = db
+            .check_in_device(&owner, gated, &info, true)
+            .await
+            .unwrap();
+        assert_eq!(device.device_status(), DeviceStatus::Retired);
+    }
+
+    #[tokio::test]
+    async fn enrollment_token_respects_usage_limit() {
+        let db = Db::memory_db().await;
+        let user = owner_of(&db, "token-user").await;
+        let token = db
+            .create_enrollment_token(
+                user.organization_id,
+                user.user_id,
+                NewEnrollmentToken {
+                    max_uses: Some(1),
+                    ..Default::default()
+                },
+            )
+            .await
+            .unwrap();
+        assert!(token.token.starts_with(ENROLLMENT_TOKEN_PREFIX));
+
+        let owner = db
+            .get_device_owner_by_token(&token.token)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(owner.organization_id, user.organization_id);
+        assert_eq!(owner.enrollment_token_id, Some(token.id));
+
+        let first = uuid::Uuid::new_v4();
+        let device = db
+            .check_in_device(&owner, first, &DeviceCheckIn::default(), false)
+            .await
+            .unwrap();
+        assert_eq!(device.device_status(), DeviceStatus::Approved);
+
+        let second = uuid::Uuid::new_v4();
+        let device = db
+            .check_in_device(&owner, second, &DeviceCheckIn::default(), false)
+            .await
+            .unwrap();
+        assert_eq!(device.device_status(), DeviceStatus::Pending);
+
+        // reconnecting does not consume the token again
+        let device = db
+            .check_in_device(&owner, first, &DeviceCheckIn::default(), false)
+            .await
+            .unwrap();
+        assert_eq!(device.device_status(), DeviceStatus::Approved);
+        let tokens = db
+            .list_enrollment_tokens(user.organization_id)
+            .await
+            .unwrap();
+        assert_eq!(tokens[0].use_count, 1);
+    }
+}
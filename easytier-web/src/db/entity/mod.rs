@@ -2,8 +2,16 @@
 
 pub mod prelude;
 
+pub mod alert_channels;
+pub mod alert_events;
+pub mod alert_rules;
+pub mod alert_silences;
+pub mod devices;
+pub mod enrollment_tokens;
 pub mod groups;
 pub mod groups_permissions;
+pub mod organization_members;
+pub mod organizations;
 pub mod permissions;
 pub mod tower_sessions;
 pub mod user_running_network_configs;
@@ -1,7 +1,15 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.1.0
 
+pub use super::alert_channels::Entity as AlertChannels;
+pub use super::alert_events::Entity as AlertEvents;
+pub use super::alert_rules::Entity as AlertRules;
+pub use super::alert_silences::Entity as AlertSilences;
+pub use super::devices::Entity as Devices;
+pub use super::enrollment_tokens::Entity as EnrollmentTokens;
 pub use super::groups::Entity as Groups;
 pub use super::groups_permissions::Entity as GroupsPermissions;
+pub use super::organization_members::Entity as OrganizationMembers;
+pub use super::organizations::Entity as Organizations;
 pub use super::permissions::Entity as Permissions;
 pub use super::tower_sessions::Entity as TowerSessions;
 pub use super::user_running_network_configs::Entity as UserRunningNetworkConfigs;
@@ -12,7 +12,7 @@ use serde::{Deserialize, Serialize};
 pub struct Model {
     #[sea_orm(primary_key)]
     pub id: i32,
-    pub user_id: i32,
+    pub organization_id: i32,
     #[sea_orm(column_type = "Text")]
     pub device_id: String,
     #[sea_orm(column_type = "Text")]
@@ -29,18 +29,18 @@ pub struct Model {
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
     #[sea_orm(
-        belongs_to = "super::users::Entity",
-        from = "Column::UserId",
-        to = "super::users::Column::Id",
+        belongs_to = "super::organizations::Entity",
+        from = "Column::OrganizationId",
+        to = "super::organizations::Column::Id",
         on_update = "Cascade",
         on_delete = "Cascade"
     )]
-    Users,
+    Organizations,
 }
 
-impl Related<super::users::Entity> for Entity {
+impl Related<super::organizations::Entity> for Entity {
     fn to() -> RelationDef {
-        Relation::Users.def()
+        Relation::Organizations.def()
     }
 }
 
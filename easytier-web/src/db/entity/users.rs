@@ -11,19 +11,28 @@ pub struct Model {
     #[sea_orm(unique)]
     pub username: String,
     pub password: String,
+    pub default_organization_id: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
-    #[sea_orm(has_many = "super::user_running_network_configs::Entity")]
-    UserRunningNetworkConfigs,
+    #[sea_orm(has_many = "super::enrollment_tokens::Entity")]
+    EnrollmentTokens,
+    #[sea_orm(has_many = "super::organization_members::Entity")]
+    OrganizationMembers,
     #[sea_orm(has_many = "super::users_groups::Entity")]
     UsersGroups,
 }
 
-impl Related<super::user_running_network_configs::Entity> for Entity {
+impl Related<super::enrollment_tokens::Entity> for Entity {
     fn to() -> RelationDef {
-        Relation::UserRunningNetworkConfigs.def()
+        Relation::EnrollmentTokens.def()
+    }
+}
+
+impl Related<super::organization_members::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::OrganizationMembers.def()
     }
 }
 
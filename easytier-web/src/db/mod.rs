@@ -1,6 +1,8 @@
 // sea-orm-cli generate entity -u sqlite:./et.db -o easytier-web/src/db/entity/ --with-serde both --with-copy-enums
+pub mod device;
 #[allow(unused_imports)]
 pub mod entity;
+pub mod organization;
 
 use easytier::{
     common::config::ConfigSource,
@@ -9,8 +11,9 @@ use easytier::{
 };
 use entity::user_running_network_configs;
 use sea_orm::{
-    ColumnTrait as _, DatabaseConnection, DbErr, EntityTrait, QueryFilter as _, Set,
-    SqlxSqliteConnector, TransactionTrait as _, prelude::Expr, sea_query::OnConflict,
+    ActiveModelTrait as _, ColumnTrait as _, DatabaseConnection, DbErr, EntityTrait,
+    IntoActiveModel as _, QueryFilter as _, Set, SqlxSqliteConnector, TransactionTrait as _,
+    prelude::Expr, sea_query::OnConflict,
 };
 use sea_orm_migration::MigratorTrait as _;
 use sqlx::{Sqlite, SqlitePool, migrate::MigrateDatabase as _, types::chrono};
@@ -19,6 +22,9 @@ use uuid::Uuid;
 use crate::migrator;
 use async_trait::async_trait;
 
+pub use device::{DeviceCheckIn, DeviceStatus};
+pub use organization::{DeviceOwner, OrgIdInDb, OrgRole};
+
 pub type UserIdInDb = i32;
 
 #[derive(Debug, Clone)]
@@ -84,7 +90,8 @@ impl Db {
     }
 
     /// `password_hash` must be pre-hashed by the caller.
-    /// Creates user + joins "users" group in one transaction. Returns the created user model.
+    /// Creates user + joins "users" group + a personal organization in one transaction.
+    /// Returns the created user model.
     pub async fn create_user_and_join_users_group(
         &self,
         username: &str,
@@ -119,6 +126,11 @@ impl Db {
         };
         users_groups::Entity::insert(ug_active).exec(&txn).await?;
 
+        let org = organization::create_organization_with(&txn, username, new_user.id).await?;
+        let mut user_active = new_user.into_active_model();
+        user_active.default_organization_id = Set(Some(org.id));
+        let new_user = user_active.update(&txn).await?;
+
         txn.commit().await?;
 
         Ok(new_user)
@@ -144,10 +156,10 @@ impl Db {
 }
 
 #[async_trait]
-impl Storage<(UserIdInDb, Uuid), user_running_network_configs::Model, DbErr> for Db {
+impl Storage<(OrgIdInDb, Uuid), user_running_network_configs::Model, DbErr> for Db {
     async fn insert_or_update_user_network_config(
         &self,
-        (user_id, device_id): (UserIdInDb, Uuid),
+        (org_id, device_id): (OrgIdInDb, Uuid),
         network_inst_id: Uuid,
         network_config: NetworkConfig,
         source: ConfigSource,
@@ -157,7 +169,7 @@ impl Storage<(UserIdInDb, Uuid), user_running_network_configs::Model, DbErr> for
         use entity::user_running_network_configs as urnc;
 
         let on_conflict = OnConflict::columns([
-            urnc::Column::UserId,
+            urnc::Column::OrganizationId,
             urnc::Column::DeviceId,
             urnc::Column::NetworkInstanceId,
         ])
@@ -169,7 +181,7 @@ impl Storage<(UserIdInDb, Uuid), user_running_network_configs::Model, DbErr> for
         ])
         .to_owned();
         let insert_m = urnc::ActiveModel {
-            user_id: sea_orm::Set(user_id),
+            organization_id: sea_orm::Set(org_id),
             device_id: sea_orm::Set(device_id.to_string()),
             network_instance_id: sea_orm::Set(network_inst_id.to_string()),
             network_config: sea_orm::Set(
@@ -192,13 +204,13 @@ impl Storage<(UserIdInDb, Uuid), user_running_network_configs::Model, DbErr> for
 
     async fn delete_network_configs(
         &self,
-        (user_id, device_id): (UserIdInDb, Uuid),
+        (org_id, device_id): (OrgIdInDb, Uuid),
         network_inst_ids: &[Uuid],
     ) -> Result<(), DbErr> {
         use entity::user_running_network_configs as urnc;
 
         urnc::Entity::delete_many()
-            .filter(urnc::Column::UserId.eq(user_id))
+            .filter(urnc::Column::OrganizationId.eq(org_id))
             .filter(urnc::Column::DeviceId.eq(device_id.to_string()))
             .filter(
                 urnc::Column::NetworkInstanceId
@@ -212,14 +224,14 @@ impl Storage<(UserIdInDb, Uuid), user_running_network_configs::Model, DbErr> for
 
     async fn update_network_config_state(
         &self,
-        (user_id, device_id): (UserIdInDb, Uuid),
+        (org_id, device_id): (OrgIdInDb, Uuid),
         network_inst_id: Uuid,
         disabled: bool,
     ) -> Result<(), DbErr> {
         use entity::user_running_network_configs as urnc;
 
         urnc::Entity::update_many()
-            .filter(urnc::Column::UserId.eq(user_id))
+            .filter(urnc::Column::OrganizationId.eq(org_id))
             .filter(urnc::Column::DeviceId.eq(device_id.to_string()))
             .filter(urnc::Column::NetworkInstanceId.eq(network_inst_id.to_string()))
             .col_expr(urnc::Column::Disabled, Expr::value(disabled))
@@ -235,12 +247,12 @@ impl Storage<(UserIdInDb, Uuid), user_running_network_configs::Model, DbErr> for
 
     async fn list_network_configs(
         &self,
-        (user_id, device_id): (UserIdInDb, Uuid),
+        (org_id, device_id): (OrgIdInDb, Uuid),
         props: ListNetworkProps,
     ) -> Result<Vec<user_running_network_configs::Model>, DbErr> {
         use entity::user_running_network_configs as urnc;
 
-        let configs = urnc::Entity::find().filter(urnc::Column::UserId.eq(user_id));
+        let configs = urnc::Entity::find().filter(urnc::Column::OrganizationId.eq(org_id));
         let configs = if matches!(
             props,
             ListNetworkProps::EnabledOnly | ListNetworkProps::DisabledOnly
@@ -263,13 +275,13 @@ impl Storage<(UserIdInDb, Uuid), user_running_network_configs::Model, DbErr> for
 
     async fn get_network_config(
         &self,
-        (user_id, device_id): (UserIdInDb, Uuid),
+        (org_id, device_id): (OrgIdInDb, Uuid),
         network_inst_id: &str,
     ) -> Result<Option<user_running_network_configs::Model>, DbErr> {
         use entity::user_running_network_configs as urnc;
 
         let config = urnc::Entity::find()
-            .filter(urnc::Column::UserId.eq(user_id))
+            .filter(urnc::Column::OrganizationId.eq(org_id))
             .filter(urnc::Column::DeviceId.eq(device_id.to_string()))
             .filter(urnc::Column::NetworkInstanceId.eq(network_inst_id))
             .one(self.orm_db())
@@ -293,7 +305,7 @@ mod tests {
     #[tokio::test]
     async fn test_user_network_config_management() {
         let db = Db::memory_db().await;
-        let user_id = 1;
+        let org_id = 1;
         let network_config = NetworkConfig {
             network_name: Some("test_config".to_string()),
             ..Default::default()
@@ -303,7 +315,7 @@ mod tests {
         let device_id = uuid::Uuid::new_v4();
 
         db.insert_or_update_user_network_config(
-            (user_id, device_id),
+            (org_id, device_id),
             inst_id,
             network_config,
             ConfigSource::User,
@@ -312,7 +324,7 @@ mod tests {
         .unwrap();
 
         let result = user_running_network_configs::Entity::find()
-            .filter(user_running_network_configs::Column::UserId.eq(user_id))
+            .filter(user_running_network_configs::Column::OrganizationId.eq(org_id))
             .one(db.orm_db())
             .await
             .unwrap()
@@ -328,7 +340,7 @@ mod tests {
         };
         let network_config_json = serde_json::to_string(&network_config).unwrap();
         db.insert_or_update_user_network_config(
-            (user_id, device_id),
+            (org_id, device_id),
             inst_id,
             network_config,
             ConfigSource::Web,
@@ -337,7 +349,7 @@ mod tests {
         .unwrap();
 
         let result2 = user_running_network_configs::Entity::find()
-            .filter(user_running_network_configs::Column::UserId.eq(user_id))
+            .filter(user_running_network_configs::Column::OrganizationId.eq(org_id))
             .one(db.orm_db())
             .await
             .unwrap()
@@ -354,18 +366,18 @@ mod tests {
         assert_ne!(result.update_time, result2.update_time);
 
         assert_eq!(
-            db.list_network_configs((user_id, device_id), ListNetworkProps::All)
+            db.list_network_configs((org_id, device_id), ListNetworkProps::All)
                 .await
                 .unwrap()
                 .len(),
             1
         );
 
-        db.delete_network_configs((user_id, device_id), &[inst_id])
+        db.delete_network_configs((org_id, device_id), &[inst_id])
             .await
             .unwrap();
         let result3 = user_running_network_configs::Entity::find()
-            .filter(user_running_network_configs::Column::UserId.eq(user_id))
+            .filter(user_running_network_configs::Column::OrganizationId.eq(org_id))
             .one(db.orm_db())
             .await
             .unwrap();
@@ -375,12 +387,12 @@ mod tests {
     #[tokio::test]
     async fn test_unknown_network_config_source_defaults_to_user_runtime_source() {
         let db = Db::memory_db().await;
-        let user_id = 1;
+        let org_id = 1;
         let inst_id = uuid::Uuid::new_v4();
         let device_id = uuid::Uuid::new_v4();
 
         user_running_network_configs::ActiveModel {
-            user_id: Set(user_id),
+            organization_id: Set(org_id),
             device_id: Set(device_id.to_string()),
             network_instance_id: Set(inst_id.to_string()),
             network_config: Set(serde_json::to_string(&NetworkConfig {
@@ -399,7 +411,7 @@ mod tests {
         .unwrap();
 
         let result = user_running_network_configs::Entity::find()
-            .filter(user_running_network_configs::Column::UserId.eq(user_id))
+            .filter(user_running_network_configs::Column::OrganizationId.eq(org_id))
             .one(db.orm_db())
             .await
             .unwrap()
@@ -414,13 +426,18 @@ mod tests {
     #[tokio::test]
     async fn test_user_network_config_same_instance_id_is_scoped_by_device() {
         let db = Db::memory_db().await;
-        let user_id = db.auto_create_user("user-1").await.unwrap().id;
+        let org_id = db
+            .auto_create_user("user-1")
+            .await
+            .unwrap()
+            .default_organization_id
+            .unwrap();
         let device1 = uuid::Uuid::new_v4();
         let device2 = uuid::Uuid::new_v4();
         let inst_id = uuid::Uuid::new_v4();
 
         db.insert_or_update_user_network_config(
-            (user_id, device1),
+            (org_id, device1),
             inst_id,
             NetworkConfig {
                 network_name: Some("cfg-1".to_string()),
@@ -431,7 +448,7 @@ mod tests {
         .await
         .unwrap();
         db.insert_or_update_user_network_config(
-            (user_id, device2),
+            (org_id, device2),
             inst_id,
             NetworkConfig {
                 network_name: Some("cfg-2".to_string()),
@@ -443,26 +460,26 @@ mod tests {
         .unwrap();
 
         let first = db
-            .get_network_config((user_id, device1), &inst_id.to_string())
+            .get_network_config((org_id, device1), &inst_id.to_string())
             .await
             .unwrap()
             .unwrap();
         let second = db
-            .get_network_config((user_id, device2), &inst_id.to_string())
+            .get_network_config((org_id, device2), &inst_id.to_string())
             .await
             .unwrap()
             .unwrap();
-        assert_eq!(first.user_id, user_id);
+        assert_eq!(first.organization_id, org_id);
         assert_eq!(first.device_id, device1.to_string());
-        assert_eq!(second.user_id, user_id);
+        assert_eq!(second.organization_id, org_id);
         assert_eq!(second.device_id, device2.to_string());
 
         let device1_configs = db
-            .list_network_configs((user_id, device1), ListNetworkProps::All)
+            .list_network_configs((org_id, device1), ListNetworkProps::All)
             .await
             .unwrap();
         let device2_configs = db
-            .list_network_configs((user_id, device2), ListNetworkProps::All)
+            .list_network_configs((org_id, device2), ListNetworkProps::All)
             .await
             .unwrap();
         assert_eq!(device1_configs.len(), 1);
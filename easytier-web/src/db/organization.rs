@@ -0,0 +1,447 @@
+use std::str::FromStr;
+
+use sea_orm::{
+    ActiveModelTrait as _, ColumnTrait as _, ConnectionTrait, DbErr, EntityTrait as _,
+    IntoActiveModel as _, JoinType, QueryFilter as _, QueryOrder as _, QuerySelect as _,
+    RelationTrait as _, Set, TransactionTrait as _, prelude::Expr,
+};
+use sqlx::types::chrono;
+
+use super::{
+    Db, UserIdInDb,
+    entity::{organization_members, organizations, users},
+};
+
+pub type OrgIdInDb = i32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OrgRole {
+    Owner,
+    Admin,
+    Viewer,
+}
+
+impl OrgRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrgRole::Owner => "owner",
+            OrgRole::Admin => "admin",
+            OrgRole::Viewer => "viewer",
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            OrgRole::Owner => 2,
+            OrgRole::Admin => 1,
+            OrgRole::Viewer => 0,
+        }
+    }
+
+    /// Whether this role grants at least the permissions of `required`.
+    pub fn satisfies(&self, required: OrgRole) -> bool {
+        self.rank() >= required.rank()
+    }
+}
+
+impl FromStr for OrgRole {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "owner" => Ok(OrgRole::Owner),
+            "admin" => Ok(OrgRole::Admin),
+            "viewer" => Ok(OrgRole::Viewer),
+            _ => anyhow::bail!("unknown organization role: {}", s),
+        }
+    }
+}
+
+/// Who a device token belongs to: the user and the organization that owns the
+/// devices enrolled with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceOwner {
+    pub user_id: UserIdInDb,
+    pub organization_id: OrgIdInDb,
+    /// Set when the device presented an enrollment token instead of a user token.
+    pub enrollment_token_id: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct OrganizationItem {
+    pub id: OrgIdInDb,
+    pub name: String,
+    pub role: OrgRole,
+    pub is_default: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct OrganizationMember {
+    pub user_id: UserIdInDb,
+    pub username: String,
+    pub role: OrgRole,
+}
+
+fn parse_role(role: &str) -> Result<OrgRole, DbErr> {
+    role.parse()
+        .map_err(|e: anyhow::Error| DbErr::Custom(e.to_string()))
+}
+
+/// Create an organization owned by `owner` on any connection, so it can take
+/// part in a caller's transaction.
+pub(super) async fn create_organization_with<C: ConnectionTrait>(
+    conn: &C,
+    name: &str,
+    owner: UserIdInDb,
+) -> Result<organizations::Model, DbErr> {
+    let now = chrono::Local::now().fixed_offset();
+    let org = organizations::ActiveModel {
+        name: Set(name.to_string()),
+        create_time: Set(now),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await?;
+    organization_members::ActiveModel {
+        organization_id: Set(org.id),
+        user_id: Set(owner),
+        role: Set(OrgRole::Owner.as_str().to_string()),
+        create_time: Set(now),
+        ..Default::default()
+    }
+    .insert(conn)
+    .await?;
+    Ok(org)
+}
+
+impl Db {
+    pub async fn create_organization(
+        &self,
+        name: &str,
+        owner: UserIdInDb,
+    ) -> Result<organizations::Model, DbErr> {
+        let txn = self.orm_db().begin().await?;
+        let org = create_organization_with(&txn, name, owner).await?;
+        txn.commit().await?;
+        Ok(org)
+    }
+
+    /// Delete an organization together with its devices' configs and alerts.
+    /// Users that enrolled devices into it fall back to another organization.
+    pub async fn delete_organization(&self, org_id: OrgIdInDb) -> Result<(), DbErr> {
+        let txn = self.orm_db().begin().await?;
+        users::Entity::update_many()
+            .col_expr(
+                users::Column::DefaultOrganizationId,
+                Expr::value(Option::<i32>::None),
+            )
+            .filter(users::Column::DefaultOrganizationId.eq(org_id))
+            .exec(&txn)
+            .await?;
+        organizations::Entity::delete_by_id(org_id)
+            .exec(&txn)
+            .await?;
+        txn.commit().await
+    }
+
+    pub async fn get_organization(
+        &self,
+        org_id: OrgIdInDb,
+    ) -> Result<Option<organizations::Model>, DbErr> {
+        organizations::Entity::find_by_id(org_id)
+            .one(self.orm_db())
+            .await
+    }
+
+    pub async fn list_user_organizations(
+        &self,
+        user_id: UserIdInDb,
+    ) -> Result<Vec<OrganizationItem>, DbErr> {
+        let default_org = self.get_default_organization_id(user_id).await?;
+        let rows: Vec<(OrgIdInDb, String, String)> = organization_members::Entity::find()
+            .select_only()
+            .column(organizations::Column::Id)
+            .column(organizations::Column::Name)
+            .column(organization_members::Column::Role)
+            .join(
+                JoinType::InnerJoin,
+                organization_members::Relation::Organizations.def(),
+            )
+            .filter(organization_members::Column::UserId.eq(user_id))
+            .order_by_asc(organizations::Column::Id)
+            .into_tuple()
+            .all(self.orm_db())
+            .await?;
+
+        rows.into_iter()
+            .map(|(id, name, role)| {
+                Ok(OrganizationItem {
+                    id,
+                    name,
+                    role: parse_role(&role)?,
+                    is_default: id == default_org,
+                })
+            })
+            .collect()
+    }
+
+    pub async fn get_member_role(
+        &self,
+        org_id: OrgIdInDb,
+        user_id: UserIdInDb,
+    ) -> Result<Option<OrgRole>, DbErr> {
+        let member = organization_members::Entity::find()
+            .filter(organization_members::Column::OrganizationId.eq(org_id))
+            .filter(organization_members::Column::UserId.eq(user_id))
+            .one(self.orm_db())
+            .await?;
+        member.map(|m| parse_role(&m.role)).transpose()
+    }
+
+    pub async fn list_organization_members(
+        &self,
+        org_id: OrgIdInDb,
+    ) -> Result<Vec<OrganizationMember>, DbErr> {
+        let rows: Vec<(UserIdInDb, String, String)> = organization_members::Entity::find()
+            .select_only()
+            .column(users::Column::Id)
+            .column(users::Column::Username)
+            .column(organization_members::Column::Role)
+            .join(
+                JoinType::InnerJoin,
+                organization_members::Relation::Users.def(),
+            )
+            .filter(organization_members::Column::OrganizationId.eq(org_id))
+            .order_by_asc(users::Column::Id)
+            .into_tuple()
+            .all(self.orm_db())
+            .await?;
+
+        rows.into_iter()
+            .map(|(user_id, username, role)| {
+                Ok(OrganizationMember {
+                    user_id,
+                    username,
+                    role: parse_role(&role)?,
+                })
+            })
+            .collect()
+    }
+
+    /// Add a user to an organization or change the role of an existing member.
+    pub async fn set_organization_member(
+        &self,
+        org_id: OrgIdInDb,
+        user_id: UserIdInDb,
+        role: OrgRole,
+    ) -> Result<(), DbErr> {
+        let existing = organization_members::Entity::find()
+            .filter(organization_members::Column::OrganizationId.eq(org_id))
+            .filter(organization_members::Column::UserId.eq(user_id))
+            .one(self.orm_db())
+            .await?;
+        match existing {
+            Some(member) => {
+                let mut member = member.into_active_model();
+                member.role = Set(role.as_str().to_string());
+                member.update(self.orm_db()).await?;
+            }
+            None => {
+                organization_members::ActiveModel {
+                    organization_id: Set(org_id),
+                    user_id: Set(user_id),
+                    role: Set(role.as_str().to_string()),
+                    create_time: Set(chrono::Local::now().fixed_offset()),
+                    ..Default::default()
+                }
+                .insert(self.orm_db())
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn remove_organization_member(
+        &self,
+        org_id: OrgIdInDb,
+        user_id: UserIdInDb,
+    ) -> Result<bool, DbErr> {
+        let txn = self.orm_db().begin().await?;
+        let ret = organization_members::Entity::delete_many()
+            .filter(organization_members::Column::OrganizationId.eq(org_id))
+            .filter(organization_members::Column::UserId.eq(user_id))
+            .exec(&txn)
+            .await?;
+        users::Entity::update_many()
+            .col_expr(
+                users::Column::DefaultOrganizationId,
+                Expr::value(Option::<i32>::None),
+            )
+            .filter(users::Column::Id.eq(user_id))
+            .filter(users::Column::DefaultOrganizationId.eq(org_id))
+            .exec(&txn)
+            .await?;
+        txn.commit().await?;
+        Ok(ret.rows_affected > 0)
+    }
+
+    pub async fn count_organization_owners(&self, org_id: OrgIdInDb) -> Result<u64, DbErr> {
+        use sea_orm::PaginatorTrait as _;
+
+        organization_members::Entity::find()
+            .filter(organization_members::Column::OrganizationId.eq(org_id))
+            .filter(organization_members::Column::Role.eq(OrgRole::Owner.as_str()))
+            .count(self.orm_db())
+            .await
+    }
+
+    pub async fn set_default_organization(
+        &self,
+        user_id: UserIdInDb,
+        org_id: OrgIdInDb,
+    ) -> Result<(), DbErr> {
+        users::Entity::update_many()
+            .col_expr(users::Column::DefaultOrganizationId, Expr::value(org_id))
+            .filter(users::Column::Id.eq(user_id))
+            .exec(self.orm_db())
+            .await?;
+        Ok(())
+    }
+
+    /// The organization that devices enrolled with the user's token belong to.
+    ///
+    /// Falls back to the first organization the user can manage, and creates a
+    /// personal organization if there is none.
+    pub async fn get_default_organization_id(
+        &self,
+        user_id: UserIdInDb,
+    ) -> Result<OrgIdInDb, DbErr> {
+        let user = users::Entity::find_by_id(user_id)
+            .one(self.orm_db())
+            .await?
+            .ok_or_else(|| DbErr::RecordNotFound(format!("user {}", user_id)))?;
+
+        if let Some(org_id) = user.default_organization_id
+            && self
+                .get_member_role(org_id, user_id)
+                .await?
+                .is_some_and(|role| role.satisfies(OrgRole::Admin))
+        {
+            return Ok(org_id);
+        }
+
+        let fallback = organization_members::Entity::find()
+            .filter(organization_members::Column::UserId.eq(user_id))
+            .filter(
+                organization_members::Column::Role
+                    .is_in([OrgRole::Owner.as_str(), OrgRole::Admin.as_str()]),
+            )
+            .order_by_asc(organization_members::Column::OrganizationId)
+            .one(self.orm_db())
+            .await?;
+        let org_id = match fallback {
+            Some(member) => member.organization_id,
+            None => self.create_organization(&user.username, user_id).await?.id,
+        };
+        self.set_default_organization(user_id, org_id).await?;
+        Ok(org_id)
+    }
+
+    /// Resolve a device token to the user and the organization it enrolls into.
+    ///
+    /// Enrollment tokens resolve even when expired or revoked so that machines
+    /// they already enrolled keep working; whether a new machine may use one is
+    /// decided at check-in.
+    pub async fn get_device_owner_by_token<T: ToString>(
+        &self,
+        token: T,
+    ) -> Result<Option<DeviceOwner>, DbErr> {
+        let token = token.to_string();
+        if let Some(user_id) = self.get_user_id_by_token(&token).await? {
+            return Ok(Some(DeviceOwner {
+                user_id,
+                organization_id: self.get_default_organization_id(user_id).await?,
+                enrollment_token_id: None,
+            }));
+        }
+        Ok(self
+            .get_enrollment_token(&token)
+            .await?
+            .map(|t| DeviceOwner {
+                user_id: t.created_by,
+                organization_id: t.organization_id,
+                enrollment_token_id: Some(t.id),
+            }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn new_user_owns_personal_organization() {
+        let db = Db::memory_db().await;
+        let user = db.auto_create_user("org-user").await.unwrap();
+
+        let orgs = db.list_user_organizations(user.id).await.unwrap();
+        assert_eq!(orgs.len(), 1);
+        assert_eq!(orgs[0].name, "org-user");
+        assert_eq!(orgs[0].role, OrgRole::Owner);
+        assert!(orgs[0].is_default);
+
+        let owner = db.get_device_owner_by_token("org-user").await.unwrap();
+        assert_eq!(
+            owner,
+            Some(DeviceOwner {
+                user_id: user.id,
+                organization_id: orgs[0].id,
+                enrollment_token_id: None,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn default_organization_falls_back_when_membership_is_lost() {
+        let db = Db::memory_db().await;
+        let owner = db.auto_create_user("team-owner").await.unwrap();
+        let member = db.auto_create_user("team-member").await.unwrap();
+        let personal = db.get_default_organization_id(member.id).await.unwrap();
+
+        let team = db.create_organization("team", owner.id).await.unwrap();
+        db.set_organization_member(team.id, member.id, OrgRole::Admin)
+            .await
+            .unwrap();
+        db.set_default_organization(member.id, team.id)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_default_organization_id(member.id).await.unwrap(),
+            team.id
+        );
+
+        // viewers cannot enroll devices into the organization
+        db.set_organization_member(team.id, member.id, OrgRole::Viewer)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_default_organization_id(member.id).await.unwrap(),
+            personal
+        );
+
+        db.set_default_organization(member.id, team.id)
+            .await
+            .unwrap();
+        assert!(
+            db.remove_organization_member(team.id, member.id)
+                .await
+                .unwrap()
+        );
+        assert_eq!(
+            db.get_default_organization_id(member.id).await.unwrap(),
+            personal
+        );
+        assert_eq!(db.count_organization_owners(team.id).await.unwrap(), 1);
+    }
+}
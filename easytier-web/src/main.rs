@@ -24,6 +24,7 @@ use easytier::tunnel::IpScheme;
 use easytier::utils::BoxExt;
 use mimalloc::MiMalloc;
 
+mod alert;
 mod client_manager;
 mod db;
 mod migrator;
@@ -156,6 +157,9 @@ struct Cli {
 
     #[command(flatten)]
     webhook: WebhookOptions,
+
+    #[command(flatten)]
+    alert: alert::AlertOptions,
 }
 
 #[derive(Debug, Clone, Default, clap::Args)]
@@ -218,6 +222,7 @@ impl LoggingConfigLoader for &Cli {
             file: None,
             size_mb: None,
             count: None,
+            format: None,
         }
     }
 }
@@ -331,6 +336,20 @@ async fn main() {
 
     let mgr = Arc::new(mgr);
 
+    let alert_mgr = Arc::new(alert::AlertManager::new(db.clone(), mgr.clone()));
+    let _alert_task = if cli.alert.alert_eval_interval > 0 {
+        Some(
+            alert_mgr
+                .start(std::time::Duration::from_secs(
+                    cli.alert.alert_eval_interval,
+                ))
+                .await
+                .unwrap(),
+        )
+    } else {
+        None
+    };
+
     #[cfg(feature = "embed")]
     let (web_router_restful, web_router_static) = if cli.no_web {
         (None, None)
@@ -369,6 +388,7 @@ async fn main() {
         feature_flags,
         oidc_config,
         webhook_config,
+        alert_mgr,
     )
     .await
     .unwrap()
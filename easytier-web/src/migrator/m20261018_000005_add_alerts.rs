@@ -0,0 +1,113 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20261018_000005_add_alerts"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared(
+            r#"
+            CREATE TABLE alert_channels (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                user_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                config TEXT NOT NULL,
+                create_time TEXT NOT NULL,
+                CONSTRAINT fk_alert_channels_user_id_to_users_id
+                    FOREIGN KEY (user_id) REFERENCES users(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            CREATE INDEX idx_alert_channels_user_id ON alert_channels(user_id);
+
+            CREATE TABLE alert_rules (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                user_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                machine_id TEXT,
+                target TEXT,
+                threshold REAL,
+                duration_secs INTEGER NOT NULL DEFAULT 0,
+                channel_ids TEXT NOT NULL DEFAULT '[]',
+                enabled BOOLEAN NOT NULL DEFAULT TRUE,
+                create_time TEXT NOT NULL,
+                update_time TEXT NOT NULL,
+                CONSTRAINT fk_alert_rules_user_id_to_users_id
+                    FOREIGN KEY (user_id) REFERENCES users(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            CREATE INDEX idx_alert_rules_user_id ON alert_rules(user_id);
+
+            CREATE TABLE alert_silences (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                user_id INTEGER NOT NULL,
+                rule_id INTEGER,
+                machine_id TEXT,
+                comment TEXT NOT NULL DEFAULT '',
+                starts_at TEXT NOT NULL,
+                ends_at TEXT NOT NULL,
+                create_time TEXT NOT NULL,
+                CONSTRAINT fk_alert_silences_user_id_to_users_id
+                    FOREIGN KEY (user_id) REFERENCES users(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE,
+                CONSTRAINT fk_alert_silences_rule_id_to_alert_rules_id
+                    FOREIGN KEY (rule_id) REFERENCES alert_rules(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            CREATE INDEX idx_alert_silences_user_id ON alert_silences(user_id);
+
+            CREATE TABLE alert_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                rule_id INTEGER NOT NULL,
+                fingerprint TEXT NOT NULL,
+                machine_id TEXT,
+                subject TEXT NOT NULL,
+                message TEXT NOT NULL,
+                status TEXT NOT NULL,
+                started_at TEXT NOT NULL,
+                resolved_at TEXT,
+                CONSTRAINT fk_alert_events_rule_id_to_alert_rules_id
+                    FOREIGN KEY (rule_id) REFERENCES alert_rules(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            CREATE INDEX idx_alert_events_rule_id ON alert_events(rule_id);
+            CREATE UNIQUE INDEX idx_alert_events_firing_fingerprint
+                ON alert_events(rule_id, fingerprint)
+                WHERE status = 'firing';
+            "#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared(
+            r#"
+            DROP TABLE IF EXISTS alert_events;
+            DROP TABLE IF EXISTS alert_silences;
+            DROP TABLE IF EXISTS alert_rules;
+            DROP TABLE IF EXISTS alert_channels;
+            "#,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,416 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20261018_000006_add_organizations"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // Every existing user gets a personal organization that reuses the user id,
+        // so rows that were owned by `user_id` can be moved by copying the id over.
+        db.execute_unprepared(
+            r#"
+            CREATE TABLE organizations (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                name TEXT NOT NULL,
+                create_time TEXT NOT NULL
+            );
+
+            CREATE TABLE organization_members (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                organization_id INTEGER NOT NULL,
+                user_id INTEGER NOT NULL,
+                role TEXT NOT NULL,
+                create_time TEXT NOT NULL,
+                CONSTRAINT fk_organization_members_organization_id_to_organizations_id
+                    FOREIGN KEY (organization_id) REFERENCES organizations(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE,
+                CONSTRAINT fk_organization_members_user_id_to_users_id
+                    FOREIGN KEY (user_id) REFERENCES users(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            CREATE UNIQUE INDEX idx_organization_members_scope_user
+                ON organization_members(organization_id, user_id);
+            CREATE INDEX idx_organization_members_user_id ON organization_members(user_id);
+
+            ALTER TABLE users ADD COLUMN default_organization_id INTEGER;
+
+            INSERT INTO organizations (id, name, create_time)
+            SELECT id, username, strftime('%Y-%m-%d %H:%M:%S', 'now') || '+00:00'
+            FROM users;
+
+            INSERT INTO organization_members (organization_id, user_id, role, create_time)
+            SELECT id, id, 'owner', strftime('%Y-%m-%d %H:%M:%S', 'now') || '+00:00'
+            FROM users;
+
+            UPDATE users SET default_organization_id = id;
+
+            CREATE TABLE user_running_network_configs_new (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                organization_id INTEGER NOT NULL,
+                device_id TEXT NOT NULL,
+                network_instance_id TEXT NOT NULL,
+                network_config TEXT NOT NULL,
+                source TEXT NOT NULL DEFAULT 'user',
+                disabled BOOLEAN NOT NULL DEFAULT FALSE,
+                create_time TEXT NOT NULL,
+                update_time TEXT NOT NULL,
+                CONSTRAINT fk_user_running_network_configs_organization_id_to_organizations_id
+                    FOREIGN KEY (organization_id) REFERENCES organizations(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+
+            INSERT INTO user_running_network_configs_new (
+                id,
+                organization_id,
+                device_id,
+                network_instance_id,
+                network_config,
+                source,
+                disabled,
+                create_time,
+                update_time
+            )
+            SELECT
+                id,
+                user_id,
+                device_id,
+                network_instance_id,
+                network_config,
+                source,
+                disabled,
+                create_time,
+                update_time
+            FROM user_running_network_configs;
+
+            DROP TABLE user_running_network_configs;
+            ALTER TABLE user_running_network_configs_new RENAME TO user_running_network_configs;
+
+            CREATE INDEX idx_user_running_network_configs_organization_id
+                ON user_running_network_configs(organization_id);
+            CREATE UNIQUE INDEX idx_user_running_network_configs_scope_inst
+                ON user_running_network_configs(organization_id, device_id, network_instance_id);
+            "#,
+        )
+        .await?;
+
+        // Alert tables are rebuilt together. Events and silences reference the new
+        // rules table directly, so dropping the old rules table cascades to nothing.
+        db.execute_unprepared(
+            r#"
+            CREATE TABLE alert_channels_new (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                organization_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                config TEXT NOT NULL,
+                create_time TEXT NOT NULL,
+                CONSTRAINT fk_alert_channels_organization_id_to_organizations_id
+                    FOREIGN KEY (organization_id) REFERENCES organizations(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            INSERT INTO alert_channels_new (id, organization_id, name, kind, config, create_time)
+            SELECT id, user_id, name, kind, config, create_time FROM alert_channels;
+
+            CREATE TABLE alert_rules_new (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                organization_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                machine_id TEXT,
+                target TEXT,
+                threshold REAL,
+                duration_secs INTEGER NOT NULL DEFAULT 0,
+                channel_ids TEXT NOT NULL DEFAULT '[]',
+                enabled BOOLEAN NOT NULL DEFAULT TRUE,
+                create_time TEXT NOT NULL,
+                update_time TEXT NOT NULL,
+                CONSTRAINT fk_alert_rules_organization_id_to_organizations_id
+                    FOREIGN KEY (organization_id) REFERENCES organizations(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            INSERT INTO alert_rules_new (
+                id, organization_id, name, kind, machine_id, target, threshold,
+                duration_secs, channel_ids, enabled, create_time, update_time
+            )
+            SELECT
+                id, user_id, name, kind, machine_id, target, threshold,
+                duration_secs, channel_ids, enabled, create_time, update_time
+            FROM alert_rules;
+
+            CREATE TABLE alert_silences_new (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                organization_id INTEGER NOT NULL,
+                rule_id INTEGER,
+                machine_id TEXT,
+                comment TEXT NOT NULL DEFAULT '',
+                starts_at TEXT NOT NULL,
+                ends_at TEXT NOT NULL,
+                create_time TEXT NOT NULL,
+                CONSTRAINT fk_alert_silences_organization_id_to_organizations_id
+                    FOREIGN KEY (organization_id) REFERENCES organizations(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE,
+                CONSTRAINT fk_alert_silences_rule_id_to_alert_rules_id
+                    FOREIGN KEY (rule_id) REFERENCES alert_rules_new(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            INSERT INTO alert_silences_new (
+                id, organization_id, rule_id, machine_id, comment, starts_at, ends_at, create_time
+            )
+            SELECT id, user_id, rule_id, machine_id, comment, starts_at, ends_at, create_time
+            FROM alert_silences;
+
+            CREATE TABLE alert_events_new (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                rule_id INTEGER NOT NULL,
+                fingerprint TEXT NOT NULL,
+                machine_id TEXT,
+                subject TEXT NOT NULL,
+                message TEXT NOT NULL,
+                status TEXT NOT NULL,
+                started_at TEXT NOT NULL,
+                resolved_at TEXT,
+                CONSTRAINT fk_alert_events_rule_id_to_alert_rules_id
+                    FOREIGN KEY (rule_id) REFERENCES alert_rules_new(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            INSERT INTO alert_events_new (
+                id, rule_id, fingerprint, machine_id, subject, message, status,
+                started_at, resolved_at
+            )
+            SELECT
+                id, rule_id, fingerprint, machine_id, subject, message, status,
+                started_at, resolved_at
+            FROM alert_events;
+
+            DROP TABLE alert_events;
+            DROP TABLE alert_silences;
+            DROP TABLE alert_rules;
+            DROP TABLE alert_channels;
+
+            ALTER TABLE alert_channels_new RENAME TO alert_channels;
+            ALTER TABLE alert_rules_new RENAME TO alert_rules;
+            ALTER TABLE alert_silences_new RENAME TO alert_silences;
+            ALTER TABLE alert_events_new RENAME TO alert_events;
+
+            CREATE INDEX idx_alert_channels_organization_id ON alert_channels(organization_id);
+            CREATE INDEX idx_alert_rules_organization_id ON alert_rules(organization_id);
+            CREATE INDEX idx_alert_silences_organization_id ON alert_silences(organization_id);
+            CREATE INDEX idx_alert_events_rule_id ON alert_events(rule_id);
+            CREATE UNIQUE INDEX idx_alert_events_firing_fingerprint
+                ON alert_events(rule_id, fingerprint)
+                WHERE status = 'firing';
+            "#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // Rows go back to the first owner of their organization. Organizations
+        // without an owner lose their rows.
+        db.execute_unprepared(
+            r#"
+            CREATE TABLE org_owners (
+                organization_id INTEGER PRIMARY KEY NOT NULL,
+                user_id INTEGER NOT NULL
+            );
+            INSERT INTO org_owners (organization_id, user_id)
+            SELECT organization_id, MIN(user_id)
+            FROM organization_members
+            WHERE role = 'owner'
+            GROUP BY organization_id;
+
+            CREATE TABLE user_running_network_configs_old (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                user_id INTEGER NOT NULL,
+                device_id TEXT NOT NULL,
+                network_instance_id TEXT NOT NULL,
+                network_config TEXT NOT NULL,
+                source TEXT NOT NULL DEFAULT 'user',
+                disabled BOOLEAN NOT NULL DEFAULT FALSE,
+                create_time TEXT NOT NULL,
+                update_time TEXT NOT NULL,
+                CONSTRAINT fk_user_running_network_configs_user_id_to_users_id
+                    FOREIGN KEY (user_id) REFERENCES users(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            INSERT OR IGNORE INTO user_running_network_configs_old (
+                id,
+                user_id,
+                device_id,
+                network_instance_id,
+                network_config,
+                source,
+                disabled,
+                create_time,
+                update_time
+            )
+            SELECT
+                c.id,
+                o.user_id,
+                c.device_id,
+                c.network_instance_id,
+                c.network_config,
+                c.source,
+                c.disabled,
+                c.create_time,
+                c.update_time
+            FROM user_running_network_configs c
+            JOIN org_owners o ON o.organization_id = c.organization_id;
+
+            DROP TABLE user_running_network_configs;
+            ALTER TABLE user_running_network_configs_old RENAME TO user_running_network_configs;
+
+            CREATE INDEX idx_user_running_network_configs_user_id
+                ON user_running_network_configs(user_id);
+            CREATE UNIQUE INDEX idx_user_running_network_configs_scope_inst
+                ON user_running_network_configs(user_id, device_id, network_instance_id);
+
+            CREATE TABLE alert_channels_old (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                user_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                config TEXT NOT NULL,
+                create_time TEXT NOT NULL,
+                CONSTRAINT fk_alert_channels_user_id_to_users_id
+                    FOREIGN KEY (user_id) REFERENCES users(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            INSERT INTO alert_channels_old (id, user_id, name, kind, config, create_time)
+            SELECT c.id, o.user_id, c.name, c.kind, c.config, c.create_time
+            FROM alert_channels c
+            JOIN org_owners o ON o.organization_id = c.organization_id;
+
+            CREATE TABLE alert_rules_old (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                user_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                machine_id TEXT,
+                target TEXT,
+                threshold REAL,
+                duration_secs INTEGER NOT NULL DEFAULT 0,
+                channel_ids TEXT NOT NULL DEFAULT '[]',
+                enabled BOOLEAN NOT NULL DEFAULT TRUE,
+                create_time TEXT NOT NULL,
+                update_time TEXT NOT NULL,
+                CONSTRAINT fk_alert_rules_user_id_to_users_id
+                    FOREIGN KEY (user_id) REFERENCES users(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            INSERT INTO alert_rules_old (
+                id, user_id, name, kind, machine_id, target, threshold,
+                duration_secs, channel_ids, enabled, create_time, update_time
+            )
+            SELECT
+                r.id, o.user_id, r.name, r.kind, r.machine_id, r.target, r.threshold,
+                r.duration_secs, r.channel_ids, r.enabled, r.create_time, r.update_time
+            FROM alert_rules r
+            JOIN org_owners o ON o.organization_id = r.organization_id;
+
+            CREATE TABLE alert_silences_old (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                user_id INTEGER NOT NULL,
+                rule_id INTEGER,
+                machine_id TEXT,
+                comment TEXT NOT NULL DEFAULT '',
+                starts_at TEXT NOT NULL,
+                ends_at TEXT NOT NULL,
+                create_time TEXT NOT NULL,
+                CONSTRAINT fk_alert_silences_user_id_to_users_id
+                    FOREIGN KEY (user_id) REFERENCES users(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE,
+                CONSTRAINT fk_alert_silences_rule_id_to_alert_rules_id
+                    FOREIGN KEY (rule_id) REFERENCES alert_rules_old(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            INSERT INTO alert_silences_old (
+                id, user_id, rule_id, machine_id, comment, starts_at, ends_at, create_time
+            )
+            SELECT
+                s.id, o.user_id, s.rule_id, s.machine_id, s.comment, s.starts_at, s.ends_at,
+                s.create_time
+            FROM alert_silences s
+            JOIN org_owners o ON o.organization_id = s.organization_id
+            WHERE s.rule_id IS NULL OR s.rule_id IN (SELECT id FROM alert_rules_old);
+
+            CREATE TABLE alert_events_old (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                rule_id INTEGER NOT NULL,
+                fingerprint TEXT NOT NULL,
+                machine_id TEXT,
+                subject TEXT NOT NULL,
+                message TEXT NOT NULL,
+                status TEXT NOT NULL,
+                started_at TEXT NOT NULL,
+                resolved_at TEXT,
+                CONSTRAINT fk_alert_events_rule_id_to_alert_rules_id
+                    FOREIGN KEY (rule_id) REFERENCES alert_rules_old(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            INSERT INTO alert_events_old (
+                id, rule_id, fingerprint, machine_id, subject, message, status,
+                started_at, resolved_at
+            )
+            SELECT
+                id, rule_id, fingerprint, machine_id, subject, message, status,
+                started_at, resolved_at
+            FROM alert_events
+            WHERE rule_id IN (SELECT id FROM alert_rules_old);
+
+            DROP TABLE alert_events;
+            DROP TABLE alert_silences;
+            DROP TABLE alert_rules;
+            DROP TABLE alert_channels;
+
+            ALTER TABLE alert_channels_old RENAME TO alert_channels;
+            ALTER TABLE alert_rules_old RENAME TO alert_rules;
+            ALTER TABLE alert_silences_old RENAME TO alert_silences;
+            ALTER TABLE alert_events_old RENAME TO alert_events;
+
+            CREATE INDEX idx_alert_channels_user_id ON alert_channels(user_id);
+            CREATE INDEX idx_alert_rules_user_id ON alert_rules(user_id);
+            CREATE INDEX idx_alert_silences_user_id ON alert_silences(user_id);
+            CREATE INDEX idx_alert_events_rule_id ON alert_events(rule_id);
+            CREATE UNIQUE INDEX idx_alert_events_firing_fingerprint
+                ON alert_events(rule_id, fingerprint)
+                WHERE status = 'firing';
+
+            DROP TABLE org_owners;
+
+            ALTER TABLE users DROP COLUMN default_organization_id;
+            DROP TABLE organization_members;
+            DROP TABLE organizations;
+            "#,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,105 @@
+use sea_orm_migration::prelude::*;
+
+pub struct Migration;
+
+impl MigrationName for Migration {
+    fn name(&self) -> &str {
+        "m20261018_000007_add_device_enrollment"
+    }
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        // Machines that already have configs stored are treated as approved so
+        // upgrading does not lock existing devices out.
+        db.execute_unprepared(
+            r#"
+            ALTER TABLE organizations
+                ADD COLUMN require_device_approval BOOLEAN NOT NULL DEFAULT 0;
+
+            CREATE TABLE enrollment_tokens (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                organization_id INTEGER NOT NULL,
+                created_by INTEGER NOT NULL,
+                token TEXT NOT NULL,
+                description TEXT NOT NULL DEFAULT '',
+                expires_at TEXT,
+                max_uses INTEGER,
+                use_count INTEGER NOT NULL DEFAULT 0,
+                revoked BOOLEAN NOT NULL DEFAULT 0,
+                create_time TEXT NOT NULL,
+                CONSTRAINT fk_enrollment_tokens_organization_id_to_organizations_id
+                    FOREIGN KEY (organization_id) REFERENCES organizations(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE,
+                CONSTRAINT fk_enrollment_tokens_created_by_to_users_id
+                    FOREIGN KEY (created_by) REFERENCES users(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE
+            );
+            CREATE UNIQUE INDEX idx_enrollment_tokens_token ON enrollment_tokens(token);
+            CREATE INDEX idx_enrollment_tokens_organization_id
+                ON enrollment_tokens(organization_id);
+
+            CREATE TABLE devices (
+                id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+                organization_id INTEGER NOT NULL,
+                machine_id TEXT NOT NULL,
+                status TEXT NOT NULL,
+                enrollment_token_id INTEGER,
+                hostname TEXT,
+                version TEXT,
+                os_type TEXT,
+                os_version TEXT,
+                os_distribution TEXT,
+                public_ip TEXT,
+                wipe_pending BOOLEAN NOT NULL DEFAULT 0,
+                first_seen TEXT NOT NULL,
+                last_seen TEXT NOT NULL,
+                status_changed_at TEXT NOT NULL,
+                CONSTRAINT fk_devices_organization_id_to_organizations_id
+                    FOREIGN KEY (organization_id) REFERENCES organizations(id)
+                    ON DELETE CASCADE
+                    ON UPDATE CASCADE,
+                CONSTRAINT fk_devices_enrollment_token_id_to_enrollment_tokens_id
+                    FOREIGN KEY (enrollment_token_id) REFERENCES enrollment_tokens(id)
+                    ON DELETE SET NULL
+                    ON UPDATE CASCADE
+            );
+            CREATE UNIQUE INDEX idx_devices_scope_machine ON devices(organization_id, machine_id);
+            CREATE INDEX idx_devices_status ON devices(organization_id, status);
+
+            INSERT INTO devices (
+                organization_id, machine_id, status,
+                first_seen, last_seen, status_changed_at
+            )
+            SELECT organization_id, device_id, 'approved',
+                MIN(create_time), MAX(update_time),
+                strftime('%Y-%m-%d %H:%M:%S', 'now') || '+00:00'
+            FROM user_running_network_configs
+            GROUP BY organization_id, device_id;
+            "#,
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+
+        db.execute_unprepared(
+            r#"
+            DROP TABLE IF EXISTS devices;
+            DROP TABLE IF EXISTS enrollment_tokens;
+            ALTER TABLE organizations DROP COLUMN require_device_approval;
+            "#,
+        )
+        .await?;
+
+        Ok(())
+    }
+}
@@ -4,6 +4,9 @@ mod m20241029_000001_init;
 mod m20260403_000002_scope_network_config_unique;
 mod m20260421_000003_add_network_config_source;
 mod m20260514_000004_rename_web_config_source;
+mod m20261018_000005_add_alerts;
+mod m20261018_000006_add_organizations;
+mod m20261018_000007_add_device_enrollment;
 
 pub struct Migrator;
 
@@ -15,6 +18,9 @@ impl MigratorTrait for Migrator {
             Box::new(m20260403_000002_scope_network_config_unique::Migration),
             Box::new(m20260421_000003_add_network_config_source::Migration),
             Box::new(m20260514_000004_rename_web_config_source::Migration),
+            Box::new(m20261018_000005_add_alerts::Migration),
+            Box::new(m20261018_000006_add_organizations::Migration),
+            Box::new(m20261018_000007_add_device_enrollment::Migration),
         ]
     }
 }
@@ -0,0 +1,242 @@
+use std::sync::Arc;
+
+use axum::{
+    Extension, Json, Router,
+    extract::{Path, Query},
+    http::{HeaderMap, StatusCode},
+    routing::{delete, get, post, put},
+};
+
+use crate::alert::{
+    AlertChannelReq, AlertError, AlertManager, AlertRuleReq, AlertSilenceReq, notifier::AlertStatus,
+};
+use crate::db::{
+    OrgIdInDb, OrgRole,
+    entity::{alert_channels, alert_events, alert_rules, alert_silences},
+};
+
+use super::organizations::org_context;
+use super::users::AuthSession;
+use super::{HttpHandleError, convert_db_error, other_error};
+
+fn convert_alert_error(e: AlertError) -> HttpHandleError {
+    match e {
+        AlertError::NotFound(_) => (StatusCode::NOT_FOUND, other_error(e).into()),
+        AlertError::Invalid(_) => (StatusCode::BAD_REQUEST, other_error(e).into()),
+        AlertError::Db(e) => convert_db_error(e),
+    }
+}
+
+async fn get_org_id(
+    auth_session: &AuthSession,
+    headers: &HeaderMap,
+    required: OrgRole,
+) -> Result<OrgIdInDb, HttpHandleError> {
+    Ok(org_context(auth_session, headers, required)
+        .await?
+        .organization_id)
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ListAlertEventsQuery {
+    status: Option<AlertStatus>,
+}
+
+async fn handle_list_events(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Extension(alert_mgr): Extension<Arc<AlertManager>>,
+    Query(query): Query<ListAlertEventsQuery>,
+) -> Result<Json<Vec<alert_events::Model>>, HttpHandleError> {
+    let org_id = get_org_id(&auth_session, &headers, OrgRole::Viewer).await?;
+    alert_mgr
+        .list_events(org_id, query.status)
+        .await
+        .map(Json)
+        .map_err(convert_alert_error)
+}
+
+async fn handle_list_rules(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Extension(alert_mgr): Extension<Arc<AlertManager>>,
+) -> Result<Json<Vec<alert_rules::Model>>, HttpHandleError> {
+    let org_id = get_org_id(&auth_session, &headers, OrgRole::Viewer).await?;
+    alert_mgr
+        .list_rules(org_id)
+        .await
+        .map(Json)
+        .map_err(convert_alert_error)
+}
+
+async fn handle_create_rule(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Extension(alert_mgr): Extension<Arc<AlertManager>>,
+    Json(req): Json<AlertRuleReq>,
+) -> Result<Json<alert_rules::Model>, HttpHandleError> {
+    let org_id = get_org_id(&auth_session, &headers, OrgRole::Admin).await?;
+    alert_mgr
+        .create_rule(org_id, req)
+        .await
+        .map(Json)
+        .map_err(convert_alert_error)
+}
+
+async fn handle_update_rule(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Extension(alert_mgr): Extension<Arc<AlertManager>>,
+    Path(rule_id): Path<i32>,
+    Json(req): Json<AlertRuleReq>,
+) -> Result<Json<alert_rules::Model>, HttpHandleError> {
+    let org_id = get_org_id(&auth_session, &headers, OrgRole::Admin).await?;
+    alert_mgr
+        .update_rule(org_id, rule_id, req)
+        .await
+        .map(Json)
+        .map_err(convert_alert_error)
+}
+
+async fn handle_delete_rule(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Extension(alert_mgr): Extension<Arc<AlertManager>>,
+    Path(rule_id): Path<i32>,
+) -> Result<StatusCode, HttpHandleError> {
+    let org_id = get_org_id(&auth_session, &headers, OrgRole::Admin).await?;
+    alert_mgr
+        .delete_rule(org_id, rule_id)
+        .await
+        .map_err(convert_alert_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn handle_list_channels(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Extension(alert_mgr): Extension<Arc<AlertManager>>,
+) -> Result<Json<Vec<alert_channels::Model>>, HttpHandleError> {
+    let org_id = get_org_id(&auth_session, &headers, OrgRole::Viewer).await?;
+    alert_mgr
+        .list_channels(org_id)
+        .await
+        .map(Json)
+        .map_err(convert_alert_error)
+}
+
+async fn handle_create_channel(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Extension(alert_mgr): Extension<Arc<AlertManager>>,
+    Json(req): Json<AlertChannelReq>,
+) -> Result<Json<alert_channels::Model>, HttpHandleError> {
+    let org_id = get_org_id(&auth_session, &headers, OrgRole::Admin).await?;
+    alert_mgr
+        .create_channel(org_id, req)
+        .await
+        .map(Json)
+        .map_err(convert_alert_error)
+}
+
+async fn handle_delete_channel(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Extension(alert_mgr): Extension<Arc<AlertManager>>,
+    Path(channel_id): Path<i32>,
+) -> Result<StatusCode, HttpHandleError> {
+    let org_id = get_org_id(&auth_session, &headers, OrgRole::Admin).await?;
+    alert_mgr
+        .delete_channel(org_id, channel_id)
+        .await
+        .map_err(convert_alert_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn handle_test_channel(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Extension(alert_mgr): Extension<Arc<AlertManager>>,
+    Path(channel_id): Path<i32>,
+) -> Result<StatusCode, HttpHandleError> {
+    let org_id = get_org_id(&auth_session, &headers, OrgRole::Admin).await?;
+    alert_mgr
+        .test_channel(org_id, channel_id)
+        .await
+        .map_err(convert_alert_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn handle_list_silences(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Extension(alert_mgr): Extension<Arc<AlertManager>>,
+) -> Result<Json<Vec<alert_silences::Model>>, HttpHandleError> {
+    let org_id = get_org_id(&auth_session, &headers, OrgRole::Viewer).await?;
+    alert_mgr
+        .list_silences(org_id)
+        .await
+        .map(Json)
+        .map_err(convert_alert_error)
+}
+
+async fn handle_create_silence(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Extension(alert_mgr): Extension<Arc<AlertManager>>,
+    Json(req): Json<AlertSilenceReq>,
+) -> Result<Json<alert_silences::Model>, HttpHandleError> {
+    let org_id = get_org_id(&auth_session, &headers, OrgRole::Admin).await?;
+    alert_mgr
+        .create_silence(org_id, req)
+        .await
+        .map(Json)
+        .map_err(convert_alert_error)
+}
+
+async fn handle_delete_silence(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Extension(alert_mgr): Extension<Arc<AlertManager>>,
+    Path(silence_id): Path<i32>,
+) -> Result<StatusCode, HttpHandleError> {
+    let org_id = get_org_id(&auth_session, &headers, OrgRole::Admin).await?;
+    alert_mgr
+        .delete_silence(org_id, silence_id)
+        .await
+        .map_err(convert_alert_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub fn router() -> Router<super::AppStateInner> {
+    Router::new()
+        .route("/api/v1/alerts", get(handle_list_events))
+        .route(
+            "/api/v1/alerts/rules",
+            get(handle_list_rules).post(handle_create_rule),
+        )
+        .route(
+            "/api/v1/alerts/rules/:rule-id",
+            put(handle_update_rule).delete(handle_delete_rule),
+        )
+        .route(
+            "/api/v1/alerts/channels",
+            get(handle_list_channels).post(handle_create_channel),
+        )
+        .route(
+            "/api/v1/alerts/channels/:channel-id",
+            delete(handle_delete_channel),
+        )
+        .route(
+            "/api/v1/alerts/channels/:channel-id/test",
+            post(handle_test_channel),
+        )
+        .route(
+            "/api/v1/alerts/silences",
+            get(handle_list_silences).post(handle_create_silence),
+        )
+        .route(
+            "/api/v1/alerts/silences/:silence-id",
+            delete(handle_delete_silence),
+        )
+}
@@ -0,0 +1,215 @@
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    routing::{delete, get, put},
+};
+use sqlx::types::chrono;
+
+use crate::client_manager::DeviceInventoryItem;
+use crate::db::{
+    DeviceStatus, OrgRole,
+    device::NewEnrollmentToken,
+    entity::{devices, enrollment_tokens},
+};
+
+use super::organizations::org_context;
+use super::users::AuthSession;
+use super::{AppState, HttpHandleError, convert_db_error, other_error};
+
+#[derive(Debug, serde::Deserialize)]
+struct ListDevicesQuery {
+    status: Option<DeviceStatus>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct UpdateDeviceStatusReq {
+    status: DeviceStatus,
+    /// Remove the device's managed configs when retiring it.
+    #[serde(default)]
+    wipe: bool,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+struct DevicePolicy {
+    require_approval: bool,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CreateEnrollmentTokenReq {
+    #[serde(default)]
+    description: String,
+    expires_in_secs: Option<u64>,
+    max_uses: Option<i32>,
+}
+
+async fn handle_list_devices(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    State(client_mgr): AppState,
+    Query(query): Query<ListDevicesQuery>,
+) -> Result<Json<Vec<DeviceInventoryItem>>, HttpHandleError> {
+    let ctx = org_context(&auth_session, &headers, OrgRole::Viewer).await?;
+    client_mgr
+        .list_devices(ctx.organization_id, query.status)
+        .await
+        .map(Json)
+        .map_err(convert_db_error)
+}
+
+async fn handle_update_device_status(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    State(client_mgr): AppState,
+    Path(machine_id): Path<uuid::Uuid>,
+    Json(req): Json<UpdateDeviceStatusReq>,
+) -> Result<Json<devices::Model>, HttpHandleError> {
+    let ctx = org_context(&auth_session, &headers, OrgRole::Admin).await?;
+    client_mgr
+        .set_device_status(ctx.organization_id, machine_id, req.status, req.wipe)
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                other_error(format!("{:#}", e)).into(),
+            )
+        })?
+        .map(Json)
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            other_error("Device not found").into(),
+        ))
+}
+
+async fn handle_get_device_policy(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+) -> Result<Json<DevicePolicy>, HttpHandleError> {
+    let ctx = org_context(&auth_session, &headers, OrgRole::Viewer).await?;
+    let org = auth_session
+        .backend
+        .db()
+        .get_organization(ctx.organization_id)
+        .await
+        .map_err(convert_db_error)?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            other_error("Organization not found").into(),
+        ))?;
+    Ok(Json(DevicePolicy {
+        require_approval: org.require_device_approval,
+    }))
+}
+
+async fn handle_set_device_policy(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Json(req): Json<DevicePolicy>,
+) -> Result<Json<DevicePolicy>, HttpHandleError> {
+    let ctx = org_context(&auth_session, &headers, OrgRole::Admin).await?;
+    auth_session
+        .backend
+        .db()
+        .set_require_device_approval(ctx.organization_id, req.require_approval)
+        .await
+        .map_err(convert_db_error)?;
+    Ok(Json(req))
+}
+
+async fn handle_list_enrollment_tokens(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+) -> Result<Json<Vec<enrollment_tokens::Model>>, HttpHandleError> {
+    // Tokens let machines in, so only admins get to see them.
+    let ctx = org_context(&auth_session, &headers, OrgRole::Admin).await?;
+    auth_session
+        .backend
+        .db()
+        .list_enrollment_tokens(ctx.organization_id)
+        .await
+        .map(Json)
+        .map_err(convert_db_error)
+}
+
+async fn handle_create_enrollment_token(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Json(req): Json<CreateEnrollmentTokenReq>,
+) -> Result<Json<enrollment_tokens::Model>, HttpHandleError> {
+    let ctx = org_context(&auth_session, &headers, OrgRole::Admin).await?;
+    if req.max_uses.is_some_and(|max| max <= 0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            other_error("max_uses must be positive").into(),
+        ));
+    }
+    let expires_at = req
+        .expires_in_secs
+        .map(|secs| {
+            chrono::Duration::try_seconds(secs as i64)
+                .map(|d| chrono::Local::now().fixed_offset() + d)
+                .ok_or((
+                    StatusCode::BAD_REQUEST,
+                    other_error("expires_in_secs is out of range").into(),
+                ))
+        })
+        .transpose()?;
+    auth_session
+        .backend
+        .db()
+        .create_enrollment_token(
+            ctx.organization_id,
+            ctx.user_id,
+            NewEnrollmentToken {
+                description: req.description,
+                expires_at,
+                max_uses: req.max_uses,
+            },
+        )
+        .await
+        .map(Json)
+        .map_err(convert_db_error)
+}
+
+async fn handle_revoke_enrollment_token(
+    auth_session: AuthSession,
+    headers: HeaderMap,
+    Path(token_id): Path<i32>,
+) -> Result<StatusCode, HttpHandleError> {
+    let ctx = org_context(&auth_session, &headers, OrgRole::Admin).await?;
+    if auth_session
+        .backend
+        .db()
+        .revoke_enrollment_token(ctx.organization_id, token_id)
+        .await
+        .map_err(convert_db_error)?
+    {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err((
+            StatusCode::NOT_FOUND,
+            other_error("Enrollment token not found").into(),
+        ))
+    }
+}
+
+pub fn router() -> Router<super::AppStateInner> {
+    Router::new()
+        .route("/api/v1/devices", get(handle_list_devices))
+        .route(
+            "/api/v1/devices/policy",
+            get(handle_get_device_policy).put(handle_set_device_policy),
+        )
+        .route(
+            "/api/v1/devices/:machine-id/status",
+            put(handle_update_device_status),
+        )
+        .route(
+            "/api/v1/enrollment-tokens",
+            get(handle_list_enrollment_tokens).post(handle_create_enrollment_token),
+        )
+        .route(
+            "/api/v1/enrollment-tokens/:token-id",
+            delete(handle_revoke_enrollment_token),
+        )
+}
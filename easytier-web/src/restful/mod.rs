@@ -1,20 +1,23 @@
+mod alerts;
 mod auth;
 pub(crate) mod captcha;
+mod devices;
 mod network;
 pub(crate) mod oidc;
+mod organizations;
 mod rpc;
 mod users;
 
 use std::{net::SocketAddr, sync::Arc};
 
 use axum::extract::Path;
-use axum::http::{Request, StatusCode, header};
+use axum::http::{HeaderMap, Request, StatusCode, header};
 use axum::middleware::{self as axum_mw, Next};
 use axum::response::Response;
 use axum::routing::{delete, post};
 use axum::{Extension, Json, Router, extract::State, routing::get};
 use axum_login::tower_sessions::{ExpiredDeletion, SessionManagerLayer};
-use axum_login::{AuthManagerLayerBuilder, AuthUser, AuthzBackend, login_required};
+use axum_login::{AuthManagerLayerBuilder, AuthzBackend, login_required};
 use axum_messages::MessagesManagerLayer;
 use easytier::common::config::{ConfigLoader, TomlConfigLoader};
 use easytier::launcher::NetworkConfig;
@@ -30,9 +33,10 @@ use tower_sessions_sqlx_store::SqliteStore;
 use users::{AuthSession, Backend};
 
 use crate::FeatureFlags;
+use crate::alert::AlertManager;
 use crate::client_manager::ClientManager;
 use crate::client_manager::storage::StorageToken;
-use crate::db::{Db, UserIdInDb};
+use crate::db::{Db, OrgIdInDb, OrgRole, UserIdInDb};
 use crate::webhook::SharedWebhookConfig;
 
 /// Embed assets for web dashboard, build frontend first
@@ -46,6 +50,7 @@ pub struct RestfulServer {
     client_mgr: Arc<ClientManager>,
     feature_flags: Arc<FeatureFlags>,
     webhook_config: SharedWebhookConfig,
+    alert_mgr: Arc<AlertManager>,
     db: Db,
     oidc_config: oidc::OidcConfig,
     web_router: Option<Router>,
@@ -97,6 +102,22 @@ pub fn other_error<T: ToString>(error_message: T) -> Error {
     }
 }
 
+/// The internal api used to be scoped by user (`/api/internal/users/:user-id/...`).
+/// Those routes are kept for existing integrations and act on the user's
+/// default organization.
+async fn legacy_user_organization(
+    client_mgr: &ClientManager,
+    user_id: UserIdInDb,
+) -> Result<OrgIdInDb, HttpHandleError> {
+    match client_mgr.get_default_organization_id(user_id).await {
+        Ok(org_id) => Ok(org_id),
+        Err(DbErr::RecordNotFound(_)) => {
+            Err((StatusCode::NOT_FOUND, other_error("user not found").into()))
+        }
+        Err(e) => Err(convert_db_error(e)),
+    }
+}
+
 pub fn convert_db_error(e: DbErr) -> HttpHandleError {
     (
         StatusCode::INTERNAL_SERVER_ERROR,
@@ -105,6 +126,7 @@ pub fn convert_db_error(e: DbErr) -> HttpHandleError {
 }
 
 impl RestfulServer {
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
         bind_addr: SocketAddr,
         client_mgr: Arc<ClientManager>,
@@ -113,6 +135,7 @@ impl RestfulServer {
         feature_flags: Arc<FeatureFlags>,
         oidc_config: oidc::OidcConfig,
         webhook_config: SharedWebhookConfig,
+        alert_mgr: Arc<AlertManager>,
     ) -> anyhow::Result<Self> {
         assert!(client_mgr.is_running());
 
@@ -121,6 +144,7 @@ impl RestfulServer {
             client_mgr,
             feature_flags,
             webhook_config,
+            alert_mgr,
             db,
             oidc_config,
             web_router,
@@ -143,13 +167,12 @@ impl RestfulServer {
 
     async fn handle_get_summary(
         auth_session: AuthSession,
+        headers: HeaderMap,
         State(client_mgr): AppState,
     ) -> Result<Json<GetSummaryJsonResp>, HttpHandleError> {
-        let Some(user) = auth_session.user else {
-            return Err((StatusCode::UNAUTHORIZED, other_error("No such user").into()));
-        };
+        let ctx = organizations::org_context(&auth_session, &headers, OrgRole::Viewer).await?;
 
-        let machines = client_mgr.list_machine_by_user_id(user.id()).await;
+        let machines = client_mgr.list_machine_by_org_id(ctx.organization_id).await;
 
         Ok(GetSummaryJsonResp {
             device_count: machines.len() as u32,
@@ -250,9 +273,13 @@ impl RestfulServer {
                     get(Self::handle_list_all_sessions_internal),
                 )
                 .route(
-                    "/api/internal/users/:user-id/sessions/:machine-id",
+                    "/api/internal/organizations/:organization-id/sessions/:machine-id",
                     delete(Self::handle_disconnect_session_internal),
                 )
+                .route(
+                    "/api/internal/users/:user-id/sessions/:machine-id",
+                    delete(Self::handle_disconnect_session_internal_legacy),
+                )
                 .merge(NetworkApi::build_route_internal())
                 .merge(rpc::router_internal())
                 .with_state(self.client_mgr.clone())
@@ -270,6 +297,9 @@ impl RestfulServer {
             .route("/api/v1/sessions", get(Self::handle_list_all_sessions))
             .merge(NetworkApi::build_route())
             .merge(rpc::router())
+            .merge(alerts::router())
+            .merge(organizations::router())
+            .merge(devices::router())
             .route_layer(login_required!(Backend))
             .merge(auth::router().layer(Extension(self.feature_flags.clone())))
             .merge(oidc::router())
@@ -280,6 +310,7 @@ impl RestfulServer {
             )
             .route("/api/v1/parse-config", post(Self::handle_parse_config))
             .layer(Extension(self.oidc_config.clone()))
+            .layer(Extension(self.alert_mgr.clone()))
             .layer(MessagesManagerLayer)
             .layer(auth_layer)
             .layer(tower_http::cors::CorsLayer::very_permissive())
@@ -312,11 +343,11 @@ impl RestfulServer {
     }
 
     async fn handle_disconnect_session_internal(
-        Path((user_id, machine_id)): Path<(UserIdInDb, uuid::Uuid)>,
+        Path((org_id, machine_id)): Path<(OrgIdInDb, uuid::Uuid)>,
         State(client_mgr): AppState,
     ) -> Result<StatusCode, HttpHandleError> {
         if client_mgr
-            .disconnect_session_by_machine_id(user_id, &machine_id)
+            .disconnect_session_by_machine_id(org_id, &machine_id)
             .await
         {
             Ok(StatusCode::NO_CONTENT)
@@ -327,6 +358,15 @@ impl RestfulServer {
             ))
         }
     }
+
+    async fn handle_disconnect_session_internal_legacy(
+        Path((user_id, machine_id)): Path<(UserIdInDb, uuid::Uuid)>,
+        State(client_mgr): AppState,
+    ) -> Result<StatusCode, HttpHandleError> {
+        let org_id = legacy_user_organization(&client_mgr, user_id).await?;
+        Self::handle_disconnect_session_internal(Path((org_id, machine_id)), State(client_mgr))
+            .await
+    }
 }
 
 /// Middleware that validates X-Internal-Auth for token-authenticated routes.
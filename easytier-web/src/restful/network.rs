@@ -1,8 +1,7 @@
 use axum::extract::Path;
-use axum::http::StatusCode;
+use axum::http::{HeaderMap, StatusCode};
 use axum::routing::{delete, post};
 use axum::{Json, Router, extract::State, routing::get};
-use axum_login::AuthUser;
 use easytier::common::config::ConfigSource as RuntimeConfigSource;
 use easytier::launcher::NetworkConfig;
 use easytier::proto::common::Void;
@@ -13,11 +12,13 @@ use easytier::rpc_service::remote_client::{
 use sea_orm::DbErr;
 
 use crate::client_manager::session::Location;
-use crate::db::UserIdInDb;
+use crate::db::{OrgIdInDb, OrgRole, UserIdInDb};
 
+use super::organizations::org_context;
 use super::users::AuthSession;
 use super::{
-    AppState, AppStateInner, Error, HttpHandleError, RpcError, convert_db_error, other_error,
+    AppState, AppStateInner, Error, HttpHandleError, RpcError, convert_db_error,
+    legacy_user_organization, other_error,
 };
 
 fn convert_rpc_error(e: RpcError) -> (StatusCode, Json<Error>) {
@@ -110,25 +111,29 @@ struct ListMachineJsonResp {
 pub struct NetworkApi;
 
 impl NetworkApi {
-    fn get_user_id(auth_session: &AuthSession) -> Result<UserIdInDb, (StatusCode, Json<Error>)> {
-        let Some(user_id) = auth_session.user.as_ref().map(|x| x.id()) else {
-            return Err((
-                StatusCode::UNAUTHORIZED,
-                other_error("No user id found".to_string()).into(),
-            ));
-        };
-        Ok(user_id)
+    async fn get_org_id(
+        auth_session: &AuthSession,
+        headers: &HeaderMap,
+        required: OrgRole,
+    ) -> Result<OrgIdInDb, HttpHandleError> {
+        Ok(org_context(auth_session, headers, required)
+            .await?
+            .organization_id)
     }
 
     async fn handle_validate_config(
         auth_session: AuthSession,
+        headers: HeaderMap,
         State(client_mgr): AppState,
         Path(machine_id): Path<uuid::Uuid>,
         Json(payload): Json<ValidateConfigJsonReq>,
     ) -> Result<Json<ValidateConfigResponse>, HttpHandleError> {
         Ok(client_mgr
             .handle_validate_config(
-                (Self::get_user_id(&auth_session)?, machine_id),
+                (
+                    Self::get_org_id(&auth_session, &headers, OrgRole::Viewer).await?,
+                    machine_id,
+                ),
                 payload.config,
             )
             .await
@@ -138,13 +143,17 @@ impl NetworkApi {
 
     async fn handle_run_network_instance(
         auth_session: AuthSession,
+        headers: HeaderMap,
         State(client_mgr): AppState,
         Path(machine_id): Path<uuid::Uuid>,
         Json(payload): Json<RunNetworkJsonReq>,
     ) -> Result<Json<Void>, HttpHandleError> {
         client_mgr
             .handle_run_network_instance_with_source(
-                (Self::get_user_id(&auth_session)?, machine_id),
+                (
+                    Self::get_org_id(&auth_session, &headers, OrgRole::Admin).await?,
+                    machine_id,
+                ),
                 payload.config,
                 payload.save,
                 RuntimeConfigSource::Web,
@@ -156,12 +165,16 @@ impl NetworkApi {
 
     async fn handle_collect_one_network_info(
         auth_session: AuthSession,
+        headers: HeaderMap,
         State(client_mgr): AppState,
         Path((machine_id, inst_id)): Path<(uuid::Uuid, uuid::Uuid)>,
     ) -> Result<Json<CollectNetworkInfoResponse>, HttpHandleError> {
         Ok(client_mgr
             .handle_collect_network_info(
-                (Self::get_user_id(&auth_session)?, machine_id),
+                (
+                    Self::get_org_id(&auth_session, &headers, OrgRole::Viewer).await?,
+                    machine_id,
+                ),
                 Some(vec![inst_id]),
             )
             .await
@@ -171,13 +184,17 @@ impl NetworkApi {
 
     async fn handle_collect_network_info(
         auth_session: AuthSession,
+        headers: HeaderMap,
         State(client_mgr): AppState,
         Path(machine_id): Path<uuid::Uuid>,
         Json(payload): Json<CollectNetworkInfoJsonReq>,
     ) -> Result<Json<CollectNetworkInfoResponse>, HttpHandleError> {
         Ok(client_mgr
             .handle_collect_network_info(
-                (Self::get_user_id(&auth_session)?, machine_id),
+                (
+                    Self::get_org_id(&auth_session, &headers, OrgRole::Viewer).await?,
+                    machine_id,
+                ),
                 payload.inst_ids,
             )
             .await
@@ -187,11 +204,15 @@ impl NetworkApi {
 
     async fn handle_list_network_instance_ids(
         auth_session: AuthSession,
+        headers: HeaderMap,
         State(client_mgr): AppState,
         Path(machine_id): Path<uuid::Uuid>,
     ) -> Result<Json<ListNetworkInstanceIdsJsonResp>, HttpHandleError> {
         Ok(client_mgr
-            .handle_list_network_instance_ids((Self::get_user_id(&auth_session)?, machine_id))
+            .handle_list_network_instance_ids((
+                Self::get_org_id(&auth_session, &headers, OrgRole::Viewer).await?,
+                machine_id,
+            ))
             .await
             .map_err(convert_error)?
             .into())
@@ -199,12 +220,16 @@ impl NetworkApi {
 
     async fn handle_remove_network_instance(
         auth_session: AuthSession,
+        headers: HeaderMap,
         State(client_mgr): AppState,
         Path((machine_id, inst_id)): Path<(uuid::Uuid, uuid::Uuid)>,
     ) -> Result<(), HttpHandleError> {
         client_mgr
             .handle_remove_network_instances(
-                (Self::get_user_id(&auth_session)?, machine_id),
+                (
+                    Self::get_org_id(&auth_session, &headers, OrgRole::Admin).await?,
+                    machine_id,
+                ),
                 vec![inst_id],
             )
             .await
@@ -213,11 +238,12 @@ impl NetworkApi {
 
     async fn handle_list_machines(
         auth_session: AuthSession,
+        headers: HeaderMap,
         State(client_mgr): AppState,
     ) -> Result<Json<ListMachineJsonResp>, HttpHandleError> {
-        let user_id = Self::get_user_id(&auth_session)?;
+        let org_id = Self::get_org_id(&auth_session, &headers, OrgRole::Viewer).await?;
 
-        let client_urls = client_mgr.list_machine_by_user_id(user_id).await;
+        let client_urls = client_mgr.list_machine_by_org_id(org_id).await;
 
         let mut machines = vec![];
         for item in client_urls.iter() {
@@ -236,6 +262,7 @@ impl NetworkApi {
 
     async fn handle_update_network_state(
         auth_session: AuthSession,
+        headers: HeaderMap,
         State(client_mgr): AppState,
         Path((machine_id, inst_id)): Path<(uuid::Uuid, Option<uuid::Uuid>)>,
         Json(payload): Json<UpdateNetworkStateJsonReq>,
@@ -250,7 +277,10 @@ impl NetworkApi {
 
         client_mgr
             .handle_update_network_state(
-                (auth_session.user.unwrap().id(), machine_id),
+                (
+                    Self::get_org_id(&auth_session, &headers, OrgRole::Admin).await?,
+                    machine_id,
+                ),
                 inst_id,
                 payload.disabled,
             )
@@ -260,6 +290,7 @@ impl NetworkApi {
 
     async fn handle_get_network_metas(
         auth_session: AuthSession,
+        headers: HeaderMap,
         State(client_mgr): AppState,
         Path(machine_id): Path<uuid::Uuid>,
         Json(payload): Json<GetNetworkMetasJsonReq>,
@@ -267,7 +298,10 @@ impl NetworkApi {
         Ok(Json(
             client_mgr
                 .handle_get_network_metas(
-                    (Self::get_user_id(&auth_session)?, machine_id),
+                    (
+                        Self::get_org_id(&auth_session, &headers, OrgRole::Viewer).await?,
+                        machine_id,
+                    ),
                     payload.instance_ids,
                 )
                 .await
@@ -277,6 +311,7 @@ impl NetworkApi {
 
     async fn handle_save_network_config(
         auth_session: AuthSession,
+        headers: HeaderMap,
         State(client_mgr): AppState,
         Path((machine_id, inst_id)): Path<(uuid::Uuid, uuid::Uuid)>,
         Json(payload): Json<SaveNetworkJsonReq>,
@@ -289,7 +324,10 @@ impl NetworkApi {
         }
         client_mgr
             .handle_save_network_config_with_source(
-                (Self::get_user_id(&auth_session)?, machine_id),
+                (
+                    Self::get_org_id(&auth_session, &headers, OrgRole::Admin).await?,
+                    machine_id,
+                ),
                 inst_id,
                 payload.config,
                 RuntimeConfigSource::Web,
@@ -300,11 +338,18 @@ impl NetworkApi {
 
     async fn handle_get_network_config(
         auth_session: AuthSession,
+        headers: HeaderMap,
         State(client_mgr): AppState,
         Path((machine_id, inst_id)): Path<(uuid::Uuid, uuid::Uuid)>,
     ) -> Result<Json<NetworkConfig>, HttpHandleError> {
         Ok(client_mgr
-            .handle_get_network_config((auth_session.user.unwrap().id(), machine_id), inst_id)
+            .handle_get_network_config(
+                (
+                    Self::get_org_id(&auth_session, &headers, OrgRole::Viewer).await?,
+                    machine_id,
+                ),
+                inst_id,
+            )
             .await
             .map_err(convert_error)?
             .into())
@@ -314,7 +359,7 @@ impl NetworkApi {
 
     async fn handle_run_network_instance_internal(
         State(client_mgr): AppState,
-        Path((user_id, machine_id)): Path<(UserIdInDb, uuid::Uuid)>,
+        Path((org_id, machine_id)): Path<(OrgIdInDb, uuid::Uuid)>,
         Json(payload): Json<RunNetworkJsonReq>,
     ) -> Result<Json<Void>, HttpHandleError> {
         let source = payload
@@ -323,7 +368,7 @@ impl NetworkApi {
             .unwrap_or(RuntimeConfigSource::Web);
         client_mgr
             .handle_run_network_instance_with_source(
-                (user_id, machine_id),
+                (org_id, machine_id),
                 payload.config,
                 payload.save,
                 source,
@@ -335,17 +380,17 @@ impl NetworkApi {
 
     async fn handle_remove_network_instance_internal(
         State(client_mgr): AppState,
-        Path((user_id, machine_id, inst_id)): Path<(UserIdInDb, uuid::Uuid, uuid::Uuid)>,
+        Path((org_id, machine_id, inst_id)): Path<(OrgIdInDb, uuid::Uuid, uuid::Uuid)>,
     ) -> Result<(), HttpHandleError> {
         client_mgr
-            .handle_remove_network_instances((user_id, machine_id), vec![inst_id])
+            .handle_remove_network_instances((org_id, machine_id), vec![inst_id])
             .await
             .map_err(convert_error)
     }
 
     async fn handle_reconcile_managed_network_configs_internal(
         State(client_mgr): AppState,
-        Path((user_id, machine_id)): Path<(UserIdInDb, uuid::Uuid)>,
+        Path((org_id, machine_id)): Path<(OrgIdInDb, uuid::Uuid)>,
         Json(payload): Json<ReconcileManagedNetworkConfigsJsonReq>,
     ) -> Result<Json<Void>, HttpHandleError> {
         let desired = payload
@@ -357,7 +402,7 @@ impl NetworkApi {
             })
             .collect();
         client_mgr
-            .reconcile_managed_network_configs(user_id, machine_id, desired)
+            .reconcile_managed_network_configs(org_id, machine_id, desired)
             .await
             .map_err(|err| {
                 (
@@ -370,10 +415,10 @@ impl NetworkApi {
 
     async fn handle_list_network_instance_ids_internal(
         State(client_mgr): AppState,
-        Path((user_id, machine_id)): Path<(UserIdInDb, uuid::Uuid)>,
+        Path((org_id, machine_id)): Path<(OrgIdInDb, uuid::Uuid)>,
     ) -> Result<Json<ListNetworkInstanceIdsJsonResp>, HttpHandleError> {
         Ok(client_mgr
-            .handle_list_network_instance_ids((user_id, machine_id))
+            .handle_list_network_instance_ids((org_id, machine_id))
             .await
             .map_err(convert_error)?
             .into())
@@ -381,32 +426,112 @@ impl NetworkApi {
 
     async fn handle_collect_network_info_internal(
         State(client_mgr): AppState,
-        Path((user_id, machine_id)): Path<(UserIdInDb, uuid::Uuid)>,
+        Path((org_id, machine_id)): Path<(OrgIdInDb, uuid::Uuid)>,
         Json(payload): Json<CollectNetworkInfoJsonReq>,
     ) -> Result<Json<CollectNetworkInfoResponse>, HttpHandleError> {
         Ok(client_mgr
-            .handle_collect_network_info((user_id, machine_id), payload.inst_ids)
+            .handle_collect_network_info((org_id, machine_id), payload.inst_ids)
             .await
             .map_err(convert_error)?
             .into())
     }
 
+    async fn handle_run_network_instance_internal_legacy(
+        State(client_mgr): AppState,
+        Path((user_id, machine_id)): Path<(UserIdInDb, uuid::Uuid)>,
+        payload: Json<RunNetworkJsonReq>,
+    ) -> Result<Json<Void>, HttpHandleError> {
+        let org_id = legacy_user_organization(&client_mgr, user_id).await?;
+        Self::handle_run_network_instance_internal(
+            State(client_mgr),
+            Path((org_id, machine_id)),
+            payload,
+        )
+        .await
+    }
+
+    async fn handle_remove_network_instance_internal_legacy(
+        State(client_mgr): AppState,
+        Path((user_id, machine_id, inst_id)): Path<(UserIdInDb, uuid::Uuid, uuid::Uuid)>,
+    ) -> Result<(), HttpHandleError> {
+        let org_id = legacy_user_organization(&client_mgr, user_id).await?;
+        Self::handle_remove_network_instance_internal(
+            State(client_mgr),
+            Path((org_id, machine_id, inst_id)),
+        )
+        .await
+    }
+
+    async fn handle_reconcile_managed_network_configs_internal_legacy(
+        State(client_mgr): AppState,
+        Path((user_id, machine_id)): Path<(UserIdInDb, uuid::Uuid)>,
+        payload: Json<ReconcileManagedNetworkConfigsJsonReq>,
+    ) -> Result<Json<Void>, HttpHandleError> {
+        let org_id = legacy_user_organization(&client_mgr, user_id).await?;
+        Self::handle_reconcile_managed_network_configs_internal(
+            State(client_mgr),
+            Path((org_id, machine_id)),
+            payload,
+        )
+        .await
+    }
+
+    async fn handle_list_network_instance_ids_internal_legacy(
+        State(client_mgr): AppState,
+        Path((user_id, machine_id)): Path<(UserIdInDb, uuid::Uuid)>,
+    ) -> Result<Json<ListNetworkInstanceIdsJsonResp>, HttpHandleError> {
+        let org_id = legacy_user_organization(&client_mgr, user_id).await?;
+        Self::handle_list_network_instance_ids_internal(
+            State(client_mgr),
+            Path((org_id, machine_id)),
+        )
+        .await
+    }
+
+    async fn handle_collect_network_info_internal_legacy(
+        State(client_mgr): AppState,
+        Path((user_id, machine_id)): Path<(UserIdInDb, uuid::Uuid)>,
+        payload: Json<CollectNetworkInfoJsonReq>,
+    ) -> Result<Json<CollectNetworkInfoResponse>, HttpHandleError> {
+        let org_id = legacy_user_organization(&client_mgr, user_id).await?;
+        Self::handle_collect_network_info_internal(
+            State(client_mgr),
+            Path((org_id, machine_id)),
+            payload,
+        )
+        .await
+    }
+
     pub fn build_route_internal() -> Router<AppStateInner> {
         Router::new()
             .route(
-                "/api/internal/users/:user-id/machines/:machine-id/networks",
+                "/api/internal/organizations/:organization-id/machines/:machine-id/networks",
                 post(Self::handle_run_network_instance_internal)
                     .put(Self::handle_reconcile_managed_network_configs_internal)
                     .get(Self::handle_list_network_instance_ids_internal),
             )
             .route(
-                "/api/internal/users/:user-id/machines/:machine-id/networks/:inst-id",
+                "/api/internal/organizations/:organization-id/machines/:machine-id/networks/:inst-id",
                 delete(Self::handle_remove_network_instance_internal),
             )
             .route(
-                "/api/internal/users/:user-id/machines/:machine-id/networks/info",
+                "/api/internal/organizations/:organization-id/machines/:machine-id/networks/info",
                 get(Self::handle_collect_network_info_internal),
             )
+            .route(
+                "/api/internal/users/:user-id/machines/:machine-id/networks",
+                post(Self::handle_run_network_instance_internal_legacy)
+                    .put(Self::handle_reconcile_managed_network_configs_internal_legacy)
+                    .get(Self::handle_list_network_instance_ids_internal_legacy),
+            )
+            .route(
+                "/api/internal/users/:user-id/machines/:machine-id/networks/:inst-id",
+                delete(Self::handle_remove_network_instance_internal_legacy),
+            )
+            .route(
+                "/api/internal/users/:user-id/machines/:machine-id/networks/info",
+                get(Self::handle_collect_network_info_internal_legacy),
+            )
     }
 
     pub fn build_route() -> Router<AppStateInner> {
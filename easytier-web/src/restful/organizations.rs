@@ -0,0 +1,310 @@
+use axum::{
+    Json, Router,
+    extract::Path,
+    http::{HeaderMap, StatusCode},
+    routing::{delete, get, put},
+};
+use axum_login::AuthUser as _;
+
+use crate::db::{
+    OrgIdInDb, OrgRole, UserIdInDb,
+    entity::organizations,
+    organization::{OrganizationItem, OrganizationMember},
+};
+
+use super::users::AuthSession;
+use super::{HttpHandleError, convert_db_error, other_error};
+
+/// Header selecting the organization a request acts on. Requests without it
+/// use the caller's default organization.
+pub const ORGANIZATION_HEADER: &str = "X-Organization-Id";
+
+/// The organization a request is scoped to and the caller's role in it.
+#[derive(Debug, Clone, Copy)]
+pub struct OrgContext {
+    pub user_id: UserIdInDb,
+    pub organization_id: OrgIdInDb,
+    pub role: OrgRole,
+}
+
+fn forbidden(msg: &str) -> HttpHandleError {
+    (StatusCode::FORBIDDEN, other_error(msg).into())
+}
+
+fn get_user_id(auth_session: &AuthSession) -> Result<UserIdInDb, HttpHandleError> {
+    auth_session.user.as_ref().map(|x| x.id()).ok_or((
+        StatusCode::UNAUTHORIZED,
+        other_error("No user id found").into(),
+    ))
+}
+
+async fn require_role(
+    auth_session: &AuthSession,
+    org_id: OrgIdInDb,
+    required: OrgRole,
+) -> Result<OrgContext, HttpHandleError> {
+    let user_id = get_user_id(auth_session)?;
+    let role = auth_session
+        .backend
+        .db()
+        .get_member_role(org_id, user_id)
+        .await
+        .map_err(convert_db_error)?
+        .ok_or_else(|| forbidden("Not a member of this organization"))?;
+    if !role.satisfies(required) {
+        return Err(forbidden("Insufficient organization role"));
+    }
+    Ok(OrgContext {
+        user_id,
+        organization_id: org_id,
+        role,
+    })
+}
+
+/// Resolve the organization a request acts on and check that the caller holds
+/// at least `required` in it.
+pub async fn org_context(
+    auth_session: &AuthSession,
+    headers: &HeaderMap,
+    required: OrgRole,
+) -> Result<OrgContext, HttpHandleError> {
+    let org_id = match headers.get(ORGANIZATION_HEADER) {
+        Some(v) => v
+            .to_str()
+            .ok()
+            .and_then(|v| v.trim().parse::<OrgIdInDb>().ok())
+            .ok_or((
+                StatusCode::BAD_REQUEST,
+                other_error(format!("Invalid {} header", ORGANIZATION_HEADER)).into(),
+            ))?,
+        None => auth_session
+            .backend
+            .db()
+            .get_default_organization_id(get_user_id(auth_session)?)
+            .await
+            .map_err(convert_db_error)?,
+    };
+    require_role(auth_session, org_id, required).await
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CreateOrganizationReq {
+    name: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct AddMemberReq {
+    username: String,
+    role: OrgRole,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct UpdateMemberReq {
+    role: OrgRole,
+}
+
+async fn handle_list_organizations(
+    auth_session: AuthSession,
+) -> Result<Json<Vec<OrganizationItem>>, HttpHandleError> {
+    let user_id = get_user_id(&auth_session)?;
+    auth_session
+        .backend
+        .db()
+        .list_user_organizations(user_id)
+        .await
+        .map(Json)
+        .map_err(convert_db_error)
+}
+
+async fn handle_create_organization(
+    auth_session: AuthSession,
+    Json(req): Json<CreateOrganizationReq>,
+) -> Result<Json<organizations::Model>, HttpHandleError> {
+    let user_id = get_user_id(&auth_session)?;
+    let name = req.name.trim();
+    if name.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            other_error("Organization name must not be empty").into(),
+        ));
+    }
+    auth_session
+        .backend
+        .db()
+        .create_organization(name, user_id)
+        .await
+        .map(Json)
+        .map_err(convert_db_error)
+}
+
+async fn handle_delete_organization(
+    auth_session: AuthSession,
+    Path(org_id): Path<OrgIdInDb>,
+) -> Result<StatusCode, HttpHandleError> {
+    require_role(&auth_session, org_id, OrgRole::Owner).await?;
+    auth_session
+        .backend
+        .db()
+        .delete_organization(org_id)
+        .await
+        .map_err(convert_db_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn handle_set_default_organization(
+    auth_session: AuthSession,
+    Path(org_id): Path<OrgIdInDb>,
+) -> Result<StatusCode, HttpHandleError> {
+    // Devices enroll into the default organization, so viewers cannot pick it.
+    let ctx = require_role(&auth_session, org_id, OrgRole::Admin).await?;
+    auth_session
+        .backend
+        .db()
+        .set_default_organization(ctx.user_id, org_id)
+        .await
+        .map_err(convert_db_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn handle_list_members(
+    auth_session: AuthSession,
+    Path(org_id): Path<OrgIdInDb>,
+) -> Result<Json<Vec<OrganizationMember>>, HttpHandleError> {
+    require_role(&auth_session, org_id, OrgRole::Viewer).await?;
+    auth_session
+        .backend
+        .db()
+        .list_organization_members(org_id)
+        .await
+        .map(Json)
+        .map_err(convert_db_error)
+}
+
+/// Only owners may hand out or take away ownership, and an organization must
+/// always keep at least one owner.
+async fn check_member_change(
+    auth_session: &AuthSession,
+    ctx: &OrgContext,
+    target: UserIdInDb,
+    new_role: Option<OrgRole>,
+) -> Result<(), HttpHandleError> {
+    let db = auth_session.backend.db();
+    let current = db
+        .get_member_role(ctx.organization_id, target)
+        .await
+        .map_err(convert_db_error)?;
+    let touches_owner = current == Some(OrgRole::Owner) || new_role == Some(OrgRole::Owner);
+    if touches_owner && ctx.role != OrgRole::Owner {
+        return Err(forbidden("Only owners can change ownership"));
+    }
+    if current == Some(OrgRole::Owner)
+        && new_role != Some(OrgRole::Owner)
+        && db
+            .count_organization_owners(ctx.organization_id)
+            .await
+            .map_err(convert_db_error)?
+            <= 1
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            other_error("Organization must keep at least one owner").into(),
+        ));
+    }
+    Ok(())
+}
+
+async fn handle_add_member(
+    auth_session: AuthSession,
+    Path(org_id): Path<OrgIdInDb>,
+    Json(req): Json<AddMemberReq>,
+) -> Result<StatusCode, HttpHandleError> {
+    let ctx = require_role(&auth_session, org_id, OrgRole::Admin).await?;
+    let db = auth_session.backend.db();
+    let target = db
+        .get_user_id(&req.username)
+        .await
+        .map_err(convert_db_error)?
+        .ok_or((StatusCode::NOT_FOUND, other_error("User not found").into()))?;
+    check_member_change(&auth_session, &ctx, target, Some(req.role)).await?;
+    db.set_organization_member(org_id, target, req.role)
+        .await
+        .map_err(convert_db_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn handle_update_member(
+    auth_session: AuthSession,
+    Path((org_id, target)): Path<(OrgIdInDb, UserIdInDb)>,
+    Json(req): Json<UpdateMemberReq>,
+) -> Result<StatusCode, HttpHandleError> {
+    let ctx = require_role(&auth_session, org_id, OrgRole::Admin).await?;
+    let db = auth_session.backend.db();
+    if db
+        .get_member_role(org_id, target)
+        .await
+        .map_err(convert_db_error)?
+        .is_none()
+    {
+        return Err((
+            StatusCode::NOT_FOUND,
+            other_error("Member not found").into(),
+        ));
+    }
+    check_member_change(&auth_session, &ctx, target, Some(req.role)).await?;
+    db.set_organization_member(org_id, target, req.role)
+        .await
+        .map_err(convert_db_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn handle_remove_member(
+    auth_session: AuthSession,
+    Path((org_id, target)): Path<(OrgIdInDb, UserIdInDb)>,
+) -> Result<StatusCode, HttpHandleError> {
+    // Members may always leave on their own; removing others needs admin.
+    let required = if auth_session.user.as_ref().map(|x| x.id()) == Some(target) {
+        OrgRole::Viewer
+    } else {
+        OrgRole::Admin
+    };
+    let ctx = require_role(&auth_session, org_id, required).await?;
+    check_member_change(&auth_session, &ctx, target, None).await?;
+    if auth_session
+        .backend
+        .db()
+        .remove_organization_member(org_id, target)
+        .await
+        .map_err(convert_db_error)?
+    {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err((
+            StatusCode::NOT_FOUND,
+            other_error("Member not found").into(),
+        ))
+    }
+}
+
+pub fn router() -> Router<super::AppStateInner> {
+    Router::new()
+        .route(
+            "/api/v1/organizations",
+            get(handle_list_organizations).post(handle_create_organization),
+        )
+        .route(
+            "/api/v1/organizations/:organization-id",
+            delete(handle_delete_organization),
+        )
+        .route(
+            "/api/v1/organizations/:organization-id/default",
+            put(handle_set_default_organization),
+        )
+        .route(
+            "/api/v1/organizations/:organization-id/members",
+            get(handle_list_members).post(handle_add_member),
+        )
+        .route(
+            "/api/v1/organizations/:organization-id/members/:user-id",
+            put(handle_update_member).delete(handle_remove_member),
+        )
+}
@@ -1,15 +1,15 @@
 use axum::{
     Json, Router,
     extract::{Path, State},
-    http::StatusCode,
+    http::{HeaderMap, StatusCode},
     routing::post,
 };
-use axum_login::AuthUser as _;
 use easytier::proto::rpc_types::controller::BaseController;
 
-use crate::db::UserIdInDb;
+use crate::db::{OrgIdInDb, OrgRole, UserIdInDb};
 
-use super::{AppState, HttpHandleError, other_error};
+use super::organizations::org_context;
+use super::{AppState, HttpHandleError, legacy_user_organization, other_error};
 
 #[derive(Debug, serde::Deserialize)]
 pub struct ProxyRpcRequest {
@@ -143,20 +143,32 @@ async fn handle_proxy_rpc_by_session(
     }
 }
 
+/// Whether a proxied method only reads state, judged by its name. Viewers are
+/// limited to these.
+fn is_read_only_method(method_name: &str) -> bool {
+    let name = method_name.to_ascii_lowercase();
+    ["list", "get", "show", "dump"]
+        .iter()
+        .any(|prefix| name.starts_with(prefix))
+}
+
 pub async fn handle_proxy_rpc(
     auth_session: super::users::AuthSession,
+    headers: HeaderMap,
     State(client_mgr): AppState,
     Path(machine_id): Path<uuid::Uuid>,
     Json(req): Json<ProxyRpcRequest>,
 ) -> Result<Json<serde_json::Value>, HttpHandleError> {
-    let user_id = auth_session
-        .user
-        .as_ref()
-        .ok_or((StatusCode::UNAUTHORIZED, other_error("Unauthorized").into()))?
-        .id();
+    let ctx = org_context(&auth_session, &headers, OrgRole::Viewer).await?;
+    if !ctx.role.satisfies(OrgRole::Admin) && !is_read_only_method(&req.method_name) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            other_error("Viewers can only call read-only methods").into(),
+        ));
+    }
 
     let session = client_mgr
-        .get_session_by_machine_id(user_id, &machine_id)
+        .get_session_by_machine_id(ctx.organization_id, &machine_id)
         .ok_or((
             StatusCode::NOT_FOUND,
             other_error("Session not found").into(),
@@ -174,11 +186,11 @@ pub fn router() -> Router<super::AppStateInner> {
 /// Internal proxy-rpc handler: no AuthSession, resolves the active session by machine_id.
 pub async fn handle_proxy_rpc_internal(
     State(client_mgr): AppState,
-    Path((user_id, machine_id)): Path<(UserIdInDb, uuid::Uuid)>,
+    Path((org_id, machine_id)): Path<(OrgIdInDb, uuid::Uuid)>,
     Json(req): Json<ProxyRpcRequest>,
 ) -> Result<Json<serde_json::Value>, HttpHandleError> {
     let session = client_mgr
-        .get_session_by_machine_id(user_id, &machine_id)
+        .get_session_by_machine_id(org_id, &machine_id)
         .ok_or((
             StatusCode::NOT_FOUND,
             other_error("Session not found").into(),
@@ -186,9 +198,84 @@ pub async fn handle_proxy_rpc_internal(
     handle_proxy_rpc_by_session(session.as_ref(), req).await
 }
 
+async fn handle_proxy_rpc_internal_legacy(
+    State(client_mgr): AppState,
+    Path((user_id, machine_id)): Path<(UserIdInDb, uuid::Uuid)>,
+    req: Json<ProxyRpcRequest>,
+) -> Result<Json<serde_json::Value>, HttpHandleError> {
+    let org_id = legacy_user_organization(&client_mgr, user_id).await?;
+    handle_proxy_rpc_internal(State(client_mgr), Path((org_id, machine_id)), req).await
+}
+
 pub fn router_internal() -> Router<super::AppStateInner> {
-    Router::new().route(
-        "/api/internal/users/:user-id/machines/:machine-id/proxy-rpc",
-        post(handle_proxy_rpc_internal),
-    )
+    Router::new()
+        .route(
+            "/api/internal/organizations/:organization-id/machines/:machine-id/proxy-rpc",
+            post(handle_proxy_rpc_internal),
+        )
+        .route(
+            "/api/internal/users/:user-id/machines/:machine-id/proxy-rpc",
+            post(handle_proxy_rpc_internal_legacy),
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_only_methods_are_detected_by_prefix() {
+        assert!(is_read_only_method("list_peer"));
+        assert!(is_read_only_method("ListRoute"));
+        assert!(is_read_only_method("GetConfig"));
+        assert!(is_read_only_method("show_node_info"));
+        assert!(!is_read_only_method("run_network_instance"));
+        assert!(!is_read_only_method("DeleteNetworkInstance"));
+        assert!(!is_read_only_method("PatchConfig"));
+    }
+
+    #[tokio::test]
+    async fn legacy_user_route_uses_default_organization() {
+        use std::sync::Arc;
+
+        use crate::{FeatureFlags, client_manager::ClientManager, db::Db, webhook::WebhookConfig};
+
+        let db = Db::memory_db().await;
+        let user = db.auto_create_user("legacy-user").await.unwrap();
+        let client_mgr = Arc::new(ClientManager::new(
+            db,
+            None,
+            Arc::new(FeatureFlags::default()),
+            Arc::new(WebhookConfig::new(None, None, None, None, None)),
+        ));
+        let req = || {
+            Json(ProxyRpcRequest {
+                service_name: "api.manage.WebClientService".to_string(),
+                method_name: "ListNetworkInstance".to_string(),
+                payload: serde_json::Value::Null,
+                scope: None,
+            })
+        };
+
+        // the user resolves to its organization, which has no such machine
+        let (status, err) = handle_proxy_rpc_internal_legacy(
+            State(client_mgr.clone()),
+            Path((user.id, uuid::Uuid::new_v4())),
+            req(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(err.message, "Session not found");
+
+        let (status, err) = handle_proxy_rpc_internal_legacy(
+            State(client_mgr),
+            Path((user.id + 1000, uuid::Uuid::new_v4())),
+            req(),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(err.message, "user not found");
+    }
 }
@@ -84,6 +84,7 @@ pub struct NodeConnectedRequest {
     pub machine_id: String,
     pub token: String,
     pub user_id: Option<i32>,
+    pub organization_id: Option<i32>,
     pub hostname: String,
     pub version: String,
     pub os_type: Option<String>,
@@ -98,6 +99,7 @@ pub struct NodeDisconnectedRequest {
     pub machine_id: String,
     pub token: String,
     pub user_id: Option<i32>,
+    pub organization_id: Option<i32>,
     pub web_instance_id: Option<String>,
     pub binding_version: Option<u64>,
 }
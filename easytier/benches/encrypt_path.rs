@@ -0,0 +1,80 @@
+// Send path of a nic packet: pooled packet buffer -> compress -> encrypt ->
+// tunnel send, for every encryption algorithm.
+
+use std::sync::Arc;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use easytier::{
+    common::config::EncryptionAlgorithm,
+    peers::{
+        encrypt::{Encryptor, create_encryptor},
+        peer_manager::PeerManager,
+    },
+    tunnel::{
+        packet_def::{CompressorAlgo, PacketType, ZCPacket},
+        ring::create_ring_tunnel_pair,
+    },
+};
+use futures::{SinkExt as _, StreamExt as _};
+use strum::VariantArray as _;
+
+const PAYLOAD_LEN: usize = 1400;
+
+fn compress_algos() -> Vec<CompressorAlgo> {
+    vec![
+        CompressorAlgo::None,
+        #[cfg(feature = "zstd")]
+        CompressorAlgo::ZstdDefault,
+    ]
+}
+
+fn encrypt_path(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let mut sink = rt.block_on(async {
+        let (local, remote) = create_ring_tunnel_pair();
+        let (mut stream, remote_sink) = remote.split();
+        tokio::spawn(async move {
+            let _remote_sink = remote_sink;
+            while stream.next().await.is_some() {}
+        });
+        local.split().1
+    });
+
+    let payload = vec![0x5a; PAYLOAD_LEN];
+    let mut group = c.benchmark_group("encrypt_path");
+    group.throughput(Throughput::Bytes(PAYLOAD_LEN as u64));
+
+    for algo in EncryptionAlgorithm::VARIANTS {
+        let encryptor: Arc<dyn Encryptor> = create_encryptor(&algo.to_string(), [1; 16], [2; 32]);
+        for compress_algo in compress_algos() {
+            let id = BenchmarkId::new(algo.to_string(), format!("{:?}", compress_algo));
+            group.bench_function(id, |b| {
+                b.iter(|| {
+                    rt.block_on(async {
+                        let mut packet = ZCPacket::new_with_payload(&payload);
+                        packet.fill_peer_manager_hdr(1, 2, PacketType::Data as u8);
+                        PeerManager::try_compress_and_encrypt(
+                            compress_algo,
+                            &encryptor,
+                            &mut packet,
+                            false,
+                        )
+                        .await
+                        .unwrap();
+                        sink.send(packet).await.unwrap();
+                    })
+                })
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, encrypt_path);
+criterion_main!(benches);
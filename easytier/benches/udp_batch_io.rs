@@ -0,0 +1,56 @@
+// Compares one send syscall per datagram with the batched (sendmmsg / gso)
+// udp sender used by the udp tunnel.
+
+use bytes::Bytes;
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use easytier::tunnel::udp_batch::{MAX_BATCH_SIZE, UdpBatchReceiver, UdpBatchSender};
+use tokio::net::UdpSocket;
+
+const PAYLOAD_LEN: usize = 1024;
+
+fn udp_batch_io(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(2)
+        .enable_all()
+        .build()
+        .unwrap();
+
+    let (send_socket, addr) = rt.block_on(async {
+        let recv_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let send_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = recv_socket.local_addr().unwrap();
+        // drain the receiver so the socket buffer doesn't fill up
+        tokio::spawn(async move {
+            let mut receiver = UdpBatchReceiver::new(&recv_socket);
+            while receiver.recv(&recv_socket).await.is_ok() {}
+        });
+        (send_socket, addr)
+    });
+
+    let bufs = vec![Bytes::from(vec![0u8; PAYLOAD_LEN]); MAX_BATCH_SIZE];
+    let mut group = c.benchmark_group("udp_send");
+    group.throughput(Throughput::Elements(bufs.len() as u64));
+
+    group.bench_function("per_datagram", |b| {
+        b.iter(|| {
+            rt.block_on(async {
+                for buf in bufs.iter() {
+                    send_socket.send_to(buf, addr).await.unwrap();
+                }
+            })
+        })
+    });
+
+    let mut sender = UdpBatchSender::new();
+    group.bench_function("batched", |b| {
+        b.iter(|| {
+            rt.block_on(sender.send(&send_socket, &addr, &bufs))
+                .unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, udp_batch_io);
+criterion_main!(benches);
@@ -184,7 +184,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         .file_descriptor_set_path(&descriptor)
         .service_generator(Box::new(ServiceGenerator::default()))
         .btree_map(["."])
-        .skip_debug([".common.Ipv4Addr", ".common.Ipv6Addr", ".common.UUID"]);
+        .skip_debug([
+            ".common.Ipv4Addr",
+            ".common.Ipv6Addr",
+            ".common.UUID",
+            ".common.SecureModeConfig",
+        ]);
 
     config.compile_protos(&proto_files, &["src/proto/"])?;
 
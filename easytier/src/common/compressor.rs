@@ -2,7 +2,6 @@
 use anyhow::Context;
 #[cfg(feature = "zstd")]
 use dashmap::DashMap;
-#[cfg(feature = "zstd")]
 use std::cell::RefCell;
 #[cfg(feature = "zstd")]
 use zstd::bulk;
@@ -41,19 +40,39 @@ impl DefaultCompressor {
         data: &[u8],
         compress_algo: CompressorAlgo,
     ) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        self.compress_into(data, compress_algo, &mut buf)?;
+        Ok(buf)
+    }
+
+    // compress into `out`, reusing its allocation
+    fn compress_into(
+        &self,
+        data: &[u8],
+        compress_algo: CompressorAlgo,
+        out: &mut Vec<u8>,
+    ) -> Result<(), Error> {
+        out.clear();
         match compress_algo {
             #[cfg(feature = "zstd")]
             CompressorAlgo::ZstdDefault => CTX_MAP.with(|map_cell| {
+                out.reserve(zstd::zstd_safe::compress_bound(data.len()));
                 let map = map_cell.borrow();
                 let mut ctx_entry = map.entry(compress_algo).or_default();
-                ctx_entry.compress(data).with_context(|| {
-                    format!(
-                        "Failed to compress data with algorithm: {:?}",
-                        compress_algo
-                    )
-                })
+                ctx_entry
+                    .compress_to_buffer(data, out)
+                    .map(|_| ())
+                    .with_context(|| {
+                        format!(
+                            "Failed to compress data with algorithm: {:?}",
+                            compress_algo
+                        )
+                    })
             }),
-            CompressorAlgo::None => Ok(data.to_vec()),
+            CompressorAlgo::None => {
+                out.extend_from_slice(data);
+                Ok(())
+            }
         }
     }
 
@@ -107,26 +126,28 @@ impl Compressor for DefaultCompressor {
         }
 
         let tail = CompressorTail::new(compress_algo);
-        let buf = self
-            .compress_raw(zc_packet.payload(), compress_algo)
-            .await?;
-
-        if buf.len() + COMPRESSOR_TAIL_SIZE > pm_header.len.get() as usize {
-            // Compressed data is larger than original data, don't compress
-            return Ok(());
-        }
-
-        zc_packet
-            .mut_peer_manager_header()
-            .unwrap()
-            .set_compressed(true);
-
-        let payload_offset = zc_packet.payload_offset();
-        zc_packet.mut_inner().truncate(payload_offset);
-        zc_packet.mut_inner().extend_from_slice(&buf);
-        zc_packet.mut_inner().extend_from_slice(tail.as_bytes());
-
-        Ok(())
+        let payload_len = pm_header.len.get() as usize;
+        // compress into a per-thread buffer and copy back, the packet only shrinks
+        // so its buffer is reused.
+        COMPRESS_BUF.with_borrow_mut(|buf| -> Result<(), Error> {
+            self.compress_into(zc_packet.payload(), compress_algo, buf)?;
+
+            if buf.len() + COMPRESSOR_TAIL_SIZE > payload_len {
+                // Compressed data is larger than original data, don't compress
+                return Ok(());
+            }
+
+            zc_packet
+                .mut_peer_manager_header()
+                .unwrap()
+                .set_compressed(true);
+
+            let payload_offset = zc_packet.payload_offset();
+            zc_packet.mut_inner().truncate(payload_offset);
+            zc_packet.mut_inner().extend_from_slice(buf);
+            zc_packet.mut_inner().extend_from_slice(tail.as_bytes());
+            Ok(())
+        })
     }
 
     async fn decompress(&self, zc_packet: &mut ZCPacket) -> Result<(), Error> {
@@ -175,6 +196,10 @@ impl Compressor for DefaultCompressor {
     }
 }
 
+thread_local! {
+    static COMPRESS_BUF: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
 #[cfg(feature = "zstd")]
 thread_local! {
     static CTX_MAP: RefCell<DashMap<CompressorAlgo, bulk::Compressor<'static>>> = RefCell::new(DashMap::new());
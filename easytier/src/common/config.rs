@@ -1,6 +1,6 @@
 use std::{
     hash::Hasher,
-    net::{IpAddr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     path::PathBuf,
     sync::{Arc, Mutex},
 };
@@ -24,7 +24,10 @@ use crate::{
     tunnel::{IpScheme, TunnelScheme, generate_digest_from_str},
 };
 
-use super::env_parser;
+use super::{
+    env_parser,
+    secret_ref::{ResolvedSecrets, is_secret_ref},
+};
 
 pub type Flags = crate::proto::common::FlagsInConfig;
 
@@ -74,6 +77,11 @@ pub fn gen_default_flags() -> Flags {
         disable_relay_data: false,
         enable_udp_broadcast_relay: false,
         socket_mark: None,
+        enable_lan_discovery: false,
+        enable_tap: false,
+        tap_bridge: "".to_string(),
+        enable_tun_offload: false,
+        enable_conn_rekey: false,
     }
 }
 
@@ -249,11 +257,61 @@ pub trait ConfigLoader: Send + Sync {
     }
     fn set_credential_file(&self, _path: Option<std::path::PathBuf>) {}
 
+    fn get_vpn_portal_client_file(&self) -> Option<std::path::PathBuf> {
+        None
+    }
+    fn set_vpn_portal_client_file(&self, _path: Option<std::path::PathBuf>) {}
+
+    /// The file this config was loaded from, None if it must not be written back.
+    fn get_config_file(&self) -> Option<std::path::PathBuf> {
+        None
+    }
+    fn set_config_file(&self, _path: Option<std::path::PathBuf>) {}
+
+    /// Write the running config back to its config file, a no-op without one.
+    fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = self.get_config_file() else {
+            return Ok(());
+        };
+        std::fs::write(&path, self.dump())
+            .with_context(|| format!("failed to write config file: {:?}", path))
+    }
+
+    fn get_flow_log_config(&self) -> Option<FlowLogConfig> {
+        None
+    }
+    fn set_flow_log_config(&self, _config: Option<FlowLogConfig>) {}
+
+    fn get_link_metrics(&self) -> Vec<LinkMetricConfig> {
+        vec![]
+    }
+    fn set_link_metrics(&self, _metrics: Option<Vec<LinkMetricConfig>>) {}
+
+    fn get_bgp_config(&self) -> Option<BgpConfig> {
+        None
+    }
+    fn set_bgp_config(&self, _config: Option<BgpConfig>) {}
+
+    fn get_relay_quota_config(&self) -> Option<RelayQuotaConfig> {
+        None
+    }
+    fn set_relay_quota_config(&self, _config: Option<RelayQuotaConfig>) {}
+
+    fn get_secret_rotation_config(&self) -> Option<SecretRotationConfig> {
+        None
+    }
+    fn set_secret_rotation_config(&self, _config: Option<SecretRotationConfig>) {}
+
     fn get_network_config_source(&self) -> ConfigSource {
         ConfigSource::User
     }
     fn set_network_config_source(&self, _source: Option<ConfigSource>) {}
 
+    /// The secret reference `secret` was resolved from, or `secret` itself.
+    fn redact_secret(&self, secret: &str) -> String {
+        secret.to_owned()
+    }
+
     fn dump(&self) -> String;
 }
 
@@ -265,7 +323,7 @@ pub trait LoggingConfigLoader {
 
 pub type NetworkSecretDigest = [u8; 32];
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct NetworkIdentity {
     pub network_name: String,
     pub network_secret: Option<String>,
@@ -365,6 +423,20 @@ impl PartialEq for NetworkIdentity {
 
 impl Eq for NetworkIdentity {}
 
+// never print the secret itself, identities end up in logs
+impl std::fmt::Debug for NetworkIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NetworkIdentity")
+            .field("network_name", &self.network_name)
+            .field(
+                "network_secret",
+                &self.network_secret.as_ref().map(|_| "<redacted>"),
+            )
+            .field("network_secret_digest", &self.network_secret_digest)
+            .finish()
+    }
+}
+
 impl std::hash::Hash for NetworkIdentity {
     fn hash<H: Hasher>(&self, state: &mut H) {
         let self_with_digest = NetworkIdentityWithOnlyDigest::from(self.clone());
@@ -421,6 +493,8 @@ pub struct FileLoggerConfig {
     pub dir: Option<String>,
     pub size_mb: Option<u64>,
     pub count: Option<usize>,
+    // "text" (default) or "json"
+    pub format: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
@@ -446,6 +520,99 @@ impl LoggingConfigLoader for &LoggingConfig {
     }
 }
 
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowExportFormat {
+    #[default]
+    Ipfix,
+    Netflow9,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+pub struct FlowLogConfig {
+    // rotating json lines file of completed flows
+    pub file: Option<String>,
+    pub size_mb: Option<u64>,
+    pub count: Option<usize>,
+    // udp collector address, e.g. 10.0.0.1:4739
+    pub collector: Option<String>,
+    pub format: Option<FlowExportFormat>,
+    // track 1 of every n packets
+    pub sample_rate: Option<u32>,
+    pub idle_timeout_sec: Option<u64>,
+    // long lived flows are reported every active_timeout_sec
+    pub active_timeout_sec: Option<u64>,
+    pub max_flows: Option<usize>,
+}
+
+// per link routing cost settings, applied to the links this node advertises
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+pub struct LinkMetricConfig {
+    // peer id or hostname of a direct peer, "*" matches every direct peer
+    pub peer: String,
+    // replaces the measured cost of the link
+    pub cost: Option<u32>,
+    pub bandwidth_mbps: Option<u32>,
+}
+
+// embedded bgp speaker announcing overlay routes to a lan router
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+pub struct BgpConfig {
+    pub local_as: u32,
+    pub remote_as: u32,
+    // address of the router, with optional port, e.g. 192.168.1.1 or 192.168.1.1:179
+    pub neighbor: String,
+    // defaults to the virtual ipv4 address
+    pub router_id: Option<Ipv4Addr>,
+    // address to accept the session on, default 0.0.0.0:179
+    pub listen: Option<SocketAddr>,
+    // connect to the neighbor instead of waiting for it
+    pub active: Option<bool>,
+    pub hold_time_sec: Option<u16>,
+    // next hop of exported prefixes, defaults to the local address of the session
+    pub next_hop: Option<Ipv4Addr>,
+    // use prefixes learned from the neighbor as local proxy cidrs
+    pub import: Option<bool>,
+    // only import prefixes inside these networks
+    pub import_filter: Option<Vec<cidr::Ipv4Cidr>>,
+}
+
+// limits for foreign networks relayed by this node
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+pub struct RelayQuotaConfig {
+    pub max_networks: Option<usize>,
+    pub max_peers_per_network: Option<usize>,
+    pub network_bps_limit: Option<u64>,
+    // relayed bytes allowed per network in a calendar month (utc)
+    pub network_monthly_bytes: Option<u64>,
+    // relay data of networks over budget is throttled to this rate, dropped if unset
+    pub over_budget_bps: Option<u64>,
+    // where monthly usage is persisted, kept in memory only if unset
+    pub usage_file: Option<PathBuf>,
+    // per network overrides, the first matching entry wins
+    pub network: Option<Vec<RelayNetworkQuotaConfig>>,
+}
+
+// the next network secret, accepted alongside the current one until the switch
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+pub struct SecretRotationConfig {
+    pub next_network_secret: String,
+    // unix seconds to switch to the next secret. set it on the admin node driving
+    // the rotation, other nodes learn it through route sync.
+    pub activate_at: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+pub struct RelayNetworkQuotaConfig {
+    // network name, wildcard allowed
+    pub name: String,
+    // priority networks are admitted even when max_networks is reached
+    pub priority: Option<bool>,
+    pub max_peers: Option<usize>,
+    pub bps_limit: Option<u64>,
+    pub monthly_bytes: Option<u64>,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 pub struct VpnPortalConfig {
     pub client_cidr: cidr::Ipv4Cidr,
@@ -566,7 +733,18 @@ struct Config {
     stun_servers_v6: Option<Vec<String>>,
 
     credential_file: Option<PathBuf>,
+    vpn_portal_client_file: Option<PathBuf>,
+    flow_log: Option<FlowLogConfig>,
+    link_metric: Option<Vec<LinkMetricConfig>>,
+    bgp: Option<BgpConfig>,
+    relay_quota: Option<RelayQuotaConfig>,
+    secret_rotation: Option<SecretRotationConfig>,
     source: Option<ConfigSourceConfig>,
+
+    #[serde(skip)]
+    resolved_secrets: ResolvedSecrets,
+    #[serde(skip)]
+    config_file: Option<PathBuf>,
 }
 
 #[derive(Debug, Clone)]
@@ -603,6 +781,10 @@ impl TomlConfigLoader {
             config: Arc::new(Mutex::new(config)),
         };
 
+        // configs pushed from the web must not read files or run commands on this host
+        let allow_refs = !matches!(config.get_network_config_source(), ConfigSource::Web);
+        config.resolve_secret_refs(allow_refs)?;
+
         let old_ns = config.get_network_identity();
 
         // Detect credential mode: secure_mode enabled + no network_secret in TOML
@@ -636,6 +818,48 @@ impl TomlConfigLoader {
         Ok(ret)
     }
 
+    /// Resolve secret references (`file://`, `exec://`, `credential://`) used for the
+    /// network secret, the next network secret and the secure mode private key.
+    /// Runs when the config is loaded. Secrets resolved earlier are read again from
+    /// their source. Any reference is an error unless `allow_refs` is set.
+    pub fn resolve_secret_refs(&self, allow_refs: bool) -> anyhow::Result<()> {
+        let mut locked_config = self.config.lock().unwrap();
+        let config = &mut *locked_config;
+        let resolved_secrets = &mut config.resolved_secrets;
+
+        if let Some(identity) = config.network_identity.as_mut()
+            && let Some(secret) = identity.network_secret.as_deref()
+            && let Some(resolved) = resolved_secrets
+                .resolve(secret, allow_refs)
+                .with_context(|| "failed to resolve network_secret")?
+        {
+            *identity = NetworkIdentity::new(identity.network_name.clone(), resolved);
+        }
+
+        if let Some(rotation) = config.secret_rotation.as_mut()
+            && let Some(resolved) = resolved_secrets
+                .resolve(&rotation.next_network_secret, allow_refs)
+                .with_context(|| "failed to resolve next_network_secret")?
+        {
+            rotation.next_network_secret = resolved;
+        }
+
+        if let Some(secure_mode) = config.secure_mode.as_mut()
+            && let Some(key) = secure_mode.local_private_key.as_deref()
+            && let Some(resolved) = resolved_secrets
+                .resolve(key, allow_refs)
+                .with_context(|| "failed to resolve local_private_key")?
+        {
+            // the public key was derived from the old private key
+            if key != resolved && !is_secret_ref(key) {
+                secure_mode.local_public_key = None;
+            }
+            secure_mode.local_private_key = Some(resolved);
+        }
+
+        Ok(())
+    }
+
     fn gen_flags(flags_hashmap: serde_json::Map<String, serde_json::Value>) -> Flags {
         let mut merged_hashmap = match serde_json::to_value(gen_default_flags()) {
             Ok(serde_json::Value::Object(map)) => map,
@@ -1024,6 +1248,67 @@ impl ConfigLoader for TomlConfigLoader {
         self.config.lock().unwrap().credential_file = path;
     }
 
+    fn get_vpn_portal_client_file(&self) -> Option<PathBuf> {
+        self.config.lock().unwrap().vpn_portal_client_file.clone()
+    }
+
+    fn set_vpn_portal_client_file(&self, path: Option<PathBuf>) {
+        self.config.lock().unwrap().vpn_portal_client_file = path;
+    }
+
+    fn get_config_file(&self) -> Option<PathBuf> {
+        self.config.lock().unwrap().config_file.clone()
+    }
+
+    fn set_config_file(&self, path: Option<PathBuf>) {
+        self.config.lock().unwrap().config_file = path;
+    }
+
+    fn get_flow_log_config(&self) -> Option<FlowLogConfig> {
+        self.config.lock().unwrap().flow_log.clone()
+    }
+
+    fn set_flow_log_config(&self, config: Option<FlowLogConfig>) {
+        self.config.lock().unwrap().flow_log = config;
+    }
+
+    fn get_link_metrics(&self) -> Vec<LinkMetricConfig> {
+        self.config
+            .lock()
+            .unwrap()
+            .link_metric
+            .clone()
+            .unwrap_or_default()
+    }
+
+    fn set_link_metrics(&self, metrics: Option<Vec<LinkMetricConfig>>) {
+        self.config.lock().unwrap().link_metric = metrics;
+    }
+
+    fn get_bgp_config(&self) -> Option<BgpConfig> {
+        self.config.lock().unwrap().bgp.clone()
+    }
+
+    fn set_bgp_config(&self, config: Option<BgpConfig>) {
+        self.config.lock().unwrap().bgp = config;
+    }
+
+    fn get_relay_quota_config(&self) -> Option<RelayQuotaConfig> {
+        self.config.lock().unwrap().relay_quota.clone()
+    }
+
+    fn set_relay_quota_config(&self, config: Option<RelayQuotaConfig>) {
+        self.config.lock().unwrap().relay_quota = config;
+    }
+
+    fn get_secret_rotation_config(&self) -> Option<SecretRotationConfig> {
+        self.config.lock().unwrap().secret_rotation.clone()
+    }
+
+    fn set_secret_rotation_config(&self, config: Option<SecretRotationConfig>) {
+        self.config.lock().unwrap().secret_rotation = config;
+    }
+
     fn get_network_config_source(&self) -> ConfigSource {
         self.config
             .lock()
@@ -1041,6 +1326,10 @@ impl ConfigLoader for TomlConfigLoader {
         });
     }
 
+    fn redact_secret(&self, secret: &str) -> String {
+        self.config.lock().unwrap().resolved_secrets.redact(secret)
+    }
+
     fn dump(&self) -> String {
         let default_flags_json = serde_json::to_string(&gen_default_flags()).unwrap();
         let default_flags_hashmap =
@@ -1070,6 +1359,25 @@ impl ConfigLoader for TomlConfigLoader {
         if config.stun_servers_v6 == Some(StunInfoCollector::get_default_servers_v6()) {
             config.stun_servers_v6 = None;
         }
+        // write resolved secrets back as their references
+        let resolved_secrets = &config.resolved_secrets;
+        if let Some(secret) = config
+            .network_identity
+            .as_mut()
+            .and_then(|identity| identity.network_secret.as_mut())
+        {
+            *secret = resolved_secrets.redact(secret);
+        }
+        if let Some(rotation) = config.secret_rotation.as_mut() {
+            rotation.next_network_secret = resolved_secrets.redact(&rotation.next_network_secret);
+        }
+        if let Some(key) = config
+            .secure_mode
+            .as_mut()
+            .and_then(|secure_mode| secure_mode.local_private_key.as_mut())
+        {
+            *key = resolved_secrets.redact(key);
+        }
         toml::to_string_pretty(&config).unwrap()
     }
 }
@@ -1390,6 +1698,95 @@ enabled = true
         }
     }
 
+    #[test]
+    fn test_toml_secret_rotation() {
+        let config = TomlConfigLoader::new_from_str(
+            r#"
+[network_identity]
+network_name = "rotating"
+network_secret = "old"
+
+[secret_rotation]
+next_network_secret = "new"
+activate_at = 1700000000
+"#,
+        )
+        .unwrap();
+
+        let rotation = config.get_secret_rotation_config().unwrap();
+        assert_eq!(rotation.next_network_secret, "new");
+        assert_eq!(rotation.activate_at, Some(1700000000));
+
+        let loaded = TomlConfigLoader::new_from_str(&config.dump()).unwrap();
+        assert_eq!(loaded.get_secret_rotation_config(), Some(rotation));
+    }
+
+    #[test]
+    fn test_toml_secret_refs_resolved_and_redacted() {
+        let dir = tempfile::tempdir().unwrap();
+        let secret_path = dir.path().join("network_secret");
+        std::fs::write(&secret_path, "from-file\n").unwrap();
+        let secret_ref = format!("file://{}", secret_path.display());
+
+        let config = TomlConfigLoader::new_from_str(&format!(
+            r#"
+[network_identity]
+network_name = "refs"
+network_secret = "{}"
+"#,
+            secret_ref
+        ))
+        .unwrap();
+
+        let identity = config.get_network_identity();
+        assert_eq!(
+            identity,
+            NetworkIdentity::new("refs".to_owned(), "from-file".to_owned())
+        );
+        assert_eq!(config.redact_secret("from-file"), secret_ref);
+
+        let dumped = config.dump();
+        assert!(dumped.contains(&secret_ref));
+        assert!(!dumped.contains("from-file"));
+        assert!(!format!("{:?}", identity).contains("from-file"));
+
+        // resolving again reads the changed secret
+        std::fs::write(&secret_path, "rotated").unwrap();
+        config.resolve_secret_refs(true).unwrap();
+        assert_eq!(
+            config.get_network_identity().network_secret.as_deref(),
+            Some("rotated")
+        );
+
+        assert!(
+            TomlConfigLoader::new_from_str(
+                r#"
+[network_identity]
+network_name = "refs"
+network_secret = "exec:///bin/echo secret"
+
+[source]
+source = "web"
+"#,
+            )
+            .is_err()
+        );
+        assert!(
+            TomlConfigLoader::new_from_str(&format!(
+                r#"
+[network_identity]
+network_name = "refs"
+network_secret = "{}"
+
+[source]
+source = "web"
+"#,
+                secret_ref
+            ))
+            .is_err()
+        );
+    }
+
     #[test]
     fn test_toml_secure_mode_without_network_identity_uses_default_secret() {
         let config = TomlConfigLoader::new_from_str(
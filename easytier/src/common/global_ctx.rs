@@ -2,7 +2,10 @@ use std::{
     collections::{BTreeSet, HashMap, hash_map::DefaultHasher},
     hash::Hasher,
     net::{IpAddr, SocketAddr},
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
     time::{SystemTime, UNIX_EPOCH},
 };
 
@@ -11,7 +14,7 @@ use dashmap::DashMap;
 
 use super::{
     PeerId,
-    config::{ConfigLoader, Flags},
+    config::{ConfigLoader, Flags, SecretRotationConfig},
     netns::NetNS,
     network::IPCollector,
     stun::{StunInfoCollector, StunInfoCollectorTrait},
@@ -21,6 +24,7 @@ use crate::{
         config::ProxyNetworkConfig, shrink_dashmap, stats_manager::StatsManager,
         token_bucket::TokenBucketManager,
     },
+    connector::diagnostics::ConnectAttemptLog,
     peers::{acl_filter::AclFilter, credential_manager::CredentialManager},
     proto::{
         acl::GroupIdentity,
@@ -30,6 +34,7 @@ use crate::{
     },
     rpc_service::protected_port,
     tunnel::matches_protocol,
+    vpn_portal::client_manager::VpnPortalClientManager,
 };
 use crossbeam::atomic::AtomicCell;
 use hmac::{Hmac, Mac};
@@ -38,6 +43,17 @@ use socket2::Protocol;
 
 pub type NetworkIdentity = crate::common::config::NetworkIdentity;
 
+/// How long the secret replaced by a rotation is still accepted, so peers that
+/// switch a little later than us do not get disconnected.
+pub const SECRET_ROTATION_GRACE_SECS: u64 = 600;
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
 #[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
 pub enum GlobalCtxEvent {
     TunDeviceReady(String),
@@ -83,6 +99,8 @@ pub enum GlobalCtxEvent {
     },
 
     CredentialChanged,
+
+    NetworkSecretRotated(u32), // (new secret generation)
 }
 
 pub type EventBus = tokio::sync::broadcast::Sender<GlobalCtxEvent>;
@@ -210,6 +228,8 @@ pub struct GlobalCtx {
     public_ipv6_lease: AtomicCell<Option<cidr::Ipv6Inet>>,
     public_ipv6_routes: Mutex<BTreeSet<std::net::Ipv6Addr>>,
     cached_proxy_cidrs: AtomicCell<Option<Vec<ProxyNetworkConfig>>>,
+    // proxy cidrs learned at runtime (e.g. from bgp), never written to the config
+    runtime_proxy_cidrs: Mutex<BTreeSet<cidr::Ipv4Cidr>>,
 
     ip_collector: Mutex<Option<Arc<IPCollector>>>,
 
@@ -238,9 +258,20 @@ pub struct GlobalCtx {
 
     credential_manager: Arc<CredentialManager>,
 
+    vpn_portal_client_manager: Arc<VpnPortalClientManager>,
+
+    connect_attempt_log: Arc<ConnectAttemptLog>,
+
     /// OSPF propagated trusted keys (peer pubkeys and admin credentials)
     /// Stored in ArcSwap for lock-free reads and atomic batch updates
     trusted_keys: Arc<TrustedKeyMapManager>,
+
+    // the identity replaced by the last secret rotation and when it happened
+    previous_network_identity: Mutex<Option<(NetworkIdentity, u64)>>,
+    // activation time of the next secret learned from an admin peer
+    announced_secret_activate_at: AtomicCell<Option<u64>>,
+    // bumped whenever the set of accepted secrets changes
+    network_secret_generation: AtomicU32,
 }
 
 impl std::fmt::Debug for GlobalCtx {
@@ -276,6 +307,7 @@ impl GlobalCtx {
         feature_flags.no_relay_quic = flags.disable_relay_quic;
         feature_flags.need_p2p = flags.need_p2p;
         feature_flags.disable_p2p = flags.disable_p2p;
+        feature_flags.tap = flags.enable_tap;
         Self::apply_disable_relay_data_flag(flags, feature_flags)
     }
 
@@ -311,6 +343,10 @@ impl GlobalCtx {
         let credential_storage_path = config_fs.get_credential_file();
         let credential_manager = Arc::new(CredentialManager::new(credential_storage_path));
 
+        let vpn_portal_client_manager = Arc::new(VpnPortalClientManager::new(
+            config_fs.get_vpn_portal_client_file(),
+        ));
+
         GlobalCtx {
             inst_name: config_fs.get_inst_name(),
             id,
@@ -324,6 +360,7 @@ impl GlobalCtx {
             public_ipv6_lease: AtomicCell::new(None),
             public_ipv6_routes: Mutex::new(BTreeSet::new()),
             cached_proxy_cidrs: AtomicCell::new(None),
+            runtime_proxy_cidrs: Mutex::new(BTreeSet::new()),
 
             ip_collector: Mutex::new(Some(Arc::new(IPCollector::new(
                 net_ns,
@@ -351,7 +388,15 @@ impl GlobalCtx {
 
             credential_manager,
 
+            vpn_portal_client_manager,
+
+            connect_attempt_log: Arc::new(ConnectAttemptLog::new()),
+
             trusted_keys: Arc::new(TrustedKeyMapManager::new()),
+
+            previous_network_identity: Mutex::new(None),
+            announced_secret_activate_at: AtomicCell::new(None),
+            network_secret_generation: AtomicU32::new(0),
         }
     }
 
@@ -473,13 +518,164 @@ impl GlobalCtx {
         self.config.get_network_identity()
     }
 
-    pub fn get_secret_proof(&self, challenge: &[u8]) -> Option<Hmac<Sha256>> {
-        let network_secret = self.get_network_identity().network_secret?;
-        let key = network_secret.as_bytes();
-        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+    fn secret_proof_with(network_secret: &str, challenge: &[u8]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(network_secret.as_bytes()).unwrap();
         mac.update(b"easytier secret proof");
         mac.update(challenge);
-        Some(mac)
+        mac
+    }
+
+    pub fn get_secret_proof(&self, challenge: &[u8]) -> Option<Hmac<Sha256>> {
+        let network_secret = self.get_network_identity().network_secret?;
+        Some(Self::secret_proof_with(&network_secret, challenge))
+    }
+
+    /// Verify a secret proof made with any of the accepted network secrets.
+    pub fn verify_secret_proof(&self, challenge: &[u8], proof: &[u8]) -> bool {
+        self.get_accepted_network_secrets().iter().any(|secret| {
+            Self::secret_proof_with(secret, challenge)
+                .verify_slice(proof)
+                .is_ok()
+        })
+    }
+
+    /// The identity built from the staged next secret, if a rotation is pending.
+    /// Credential nodes have no secret and never rotate.
+    pub fn get_next_network_identity(&self) -> Option<NetworkIdentity> {
+        let rotation = self.config.get_secret_rotation_config()?;
+        let current = self.get_network_identity();
+        if rotation.next_network_secret.is_empty()
+            || current.network_secret.is_none()
+            || current.network_secret.as_ref() == Some(&rotation.next_network_secret)
+        {
+            return None;
+        }
+        Some(NetworkIdentity::new(
+            current.network_name,
+            rotation.next_network_secret,
+        ))
+    }
+
+    /// The identity replaced by the last rotation, while it is in the grace period.
+    pub fn get_previous_network_identity(&self) -> Option<NetworkIdentity> {
+        let previous = self.previous_network_identity.lock().unwrap();
+        let (identity, rotated_at) = previous.as_ref()?;
+        (unix_now() < rotated_at + SECRET_ROTATION_GRACE_SECS).then(|| identity.clone())
+    }
+
+    /// Identities accepted from same-network peers: the current one first, then the
+    /// staged next one and the one replaced by the last rotation.
+    pub fn get_accepted_network_identities(&self) -> Vec<NetworkIdentity> {
+        let mut identities = vec![self.get_network_identity()];
+        for identity in [
+            self.get_next_network_identity(),
+            self.get_previous_network_identity(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if !identities.contains(&identity) {
+                identities.push(identity);
+            }
+        }
+        identities
+    }
+
+    pub fn is_accepted_network_identity(&self, identity: &NetworkIdentity) -> bool {
+        self.get_accepted_network_identities().contains(identity)
+    }
+
+    pub fn get_accepted_network_secrets(&self) -> Vec<String> {
+        self.get_accepted_network_identities()
+            .into_iter()
+            .filter_map(|identity| identity.network_secret)
+            .collect()
+    }
+
+    /// The accepted secret a peer with the given identity is using.
+    pub fn get_accepted_network_secret(&self, identity: &NetworkIdentity) -> Option<String> {
+        self.get_accepted_network_identities()
+            .into_iter()
+            .find(|accepted| accepted == identity)
+            .and_then(|accepted| accepted.network_secret)
+    }
+
+    /// Stage (or cancel with None) the secret the network is going to switch to.
+    pub fn set_secret_rotation_config(&self, rotation: Option<SecretRotationConfig>) {
+        self.config.set_secret_rotation_config(rotation);
+        self.announced_secret_activate_at.store(None);
+        self.network_secret_generation
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// When the staged secret becomes active: the locally configured time, or the
+    /// one an admin peer published through route sync.
+    pub fn get_secret_activate_at(&self) -> Option<u64> {
+        self.get_next_network_identity()?;
+        self.config
+            .get_secret_rotation_config()
+            .and_then(|rotation| rotation.activate_at)
+            .or(self.announced_secret_activate_at.load())
+    }
+
+    pub fn set_announced_secret_activate_at(&self, activate_at: Option<u64>) {
+        self.announced_secret_activate_at.store(activate_at);
+    }
+
+    /// Switch to the staged secret, returns false if none is staged. The old secret
+    /// stays accepted for SECRET_ROTATION_GRACE_SECS so slower peers can catch up.
+    ///
+    /// The new secret is written to the config file so a restart keeps using it. If
+    /// the file can't be written the switch is undone and the error returned. Configs
+    /// without a writable file (command line, read-only or env expanded files) only
+    /// change in memory, restart them with the new secret.
+    pub fn rotate_network_secret(&self) -> Result<bool, anyhow::Error> {
+        let Some(next) = self.get_next_network_identity() else {
+            return Ok(false);
+        };
+        let current = self.get_network_identity();
+        let rotation = self.config.get_secret_rotation_config();
+        self.config.set_network_identity(next);
+        self.config.set_secret_rotation_config(None);
+        if let Err(e) = self.config.persist() {
+            self.config.set_network_identity(current);
+            self.config.set_secret_rotation_config(rotation);
+            return Err(e.context("failed to persist the rotated network secret"));
+        }
+        self.announced_secret_activate_at.store(None);
+        *self.previous_network_identity.lock().unwrap() = Some((current, unix_now()));
+        let generation = self
+            .network_secret_generation
+            .fetch_add(1, Ordering::Relaxed)
+            + 1;
+        self.issue_event(GlobalCtxEvent::NetworkSecretRotated(generation));
+        Ok(true)
+    }
+
+    /// Drop the previous secret once its grace period is over, returns true if it was dropped.
+    pub fn expire_previous_network_identity(&self) -> bool {
+        let mut previous = self.previous_network_identity.lock().unwrap();
+        match previous.as_ref() {
+            Some((_, rotated_at)) if unix_now() >= rotated_at + SECRET_ROTATION_GRACE_SECS => {
+                *previous = None;
+                self.network_secret_generation
+                    .fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Unix seconds until the secret replaced by the last rotation is accepted.
+    pub fn previous_secret_accepted_until(&self) -> Option<u64> {
+        let previous = self.previous_network_identity.lock().unwrap();
+        let (_, rotated_at) = previous.as_ref()?;
+        let until = rotated_at + SECRET_ROTATION_GRACE_SECS;
+        (unix_now() < until).then_some(until)
+    }
+
+    pub fn network_secret_generation(&self) -> u32 {
+        self.network_secret_generation.load(Ordering::Relaxed)
     }
 
     pub fn get_network_name(&self) -> String {
@@ -524,6 +720,30 @@ impl GlobalCtx {
         }
     }
 
+    /// Proxy cidrs from the config, followed by the runtime-only ones not already configured.
+    pub fn get_proxy_cidrs(&self) -> Vec<ProxyNetworkConfig> {
+        let mut cidrs = self.config.get_proxy_cidrs();
+        let runtime = self.runtime_proxy_cidrs.lock().unwrap().clone();
+        for cidr in runtime {
+            if !cidrs.iter().any(|x| x.cidr == cidr) {
+                cidrs.push(ProxyNetworkConfig {
+                    cidr,
+                    mapped_cidr: None,
+                    allow: None,
+                });
+            }
+        }
+        cidrs
+    }
+
+    pub fn get_runtime_proxy_cidrs(&self) -> BTreeSet<cidr::Ipv4Cidr> {
+        self.runtime_proxy_cidrs.lock().unwrap().clone()
+    }
+
+    pub fn set_runtime_proxy_cidrs(&self, cidrs: BTreeSet<cidr::Ipv4Cidr>) {
+        *self.runtime_proxy_cidrs.lock().unwrap() = cidrs;
+    }
+
     pub fn get_vpn_portal_cidr(&self) -> Option<cidr::Ipv4Cidr> {
         self.config.get_vpn_portal_config().map(|x| x.client_cidr)
     }
@@ -546,12 +766,16 @@ impl GlobalCtx {
     }
 
     pub fn get_128_key(&self) -> [u8; 16] {
-        let mut key = [0u8; 16];
         let secret = self
             .config
             .get_network_identity()
             .network_secret
             .unwrap_or_default();
+        Self::derive_128_key(&secret)
+    }
+
+    pub fn derive_128_key(secret: &str) -> [u8; 16] {
+        let mut key = [0u8; 16];
         // fill key according to network secret
         let mut hasher = DefaultHasher::new();
         hasher.write(secret.as_bytes());
@@ -563,12 +787,16 @@ impl GlobalCtx {
     }
 
     pub fn get_256_key(&self) -> [u8; 32] {
-        let mut key = [0u8; 32];
         let secret = self
             .config
             .get_network_identity()
             .network_secret
             .unwrap_or_default();
+        Self::derive_256_key(&secret)
+    }
+
+    pub fn derive_256_key(secret: &str) -> [u8; 32] {
+        let mut key = [0u8; 32];
         // fill key according to network secret
         let mut hasher = DefaultHasher::new();
         hasher.write(secret.as_bytes());
@@ -666,6 +894,14 @@ impl GlobalCtx {
         &self.credential_manager
     }
 
+    pub fn get_vpn_portal_client_manager(&self) -> &Arc<VpnPortalClientManager> {
+        &self.vpn_portal_client_manager
+    }
+
+    pub fn get_connect_attempt_log(&self) -> &Arc<ConnectAttemptLog> {
+        &self.connect_attempt_log
+    }
+
     /// Check if a public key is trusted using two-level lookup:
     /// 1. OSPF propagated trusted_keys (lock-free)
     /// 2. Local credential_manager
@@ -1016,6 +1252,89 @@ pub mod tests {
         protected_port::clear_protected_tcp_ports_for_test();
     }
 
+    #[tokio::test]
+    async fn secret_rotation_accepts_both_secrets_until_grace_ends() {
+        let old = NetworkIdentity::new("net".to_string(), "old".to_string());
+        let new = NetworkIdentity::new("net".to_string(), "new".to_string());
+        let global_ctx = get_mock_global_ctx_with_network(Some(old.clone()));
+        assert!(!global_ctx.is_accepted_network_identity(&new));
+
+        global_ctx.set_secret_rotation_config(Some(SecretRotationConfig {
+            next_network_secret: "new".to_string(),
+            activate_at: None,
+        }));
+        assert!(global_ctx.is_accepted_network_identity(&old));
+        assert!(global_ctx.is_accepted_network_identity(&new));
+        let proof = GlobalCtx::secret_proof_with("new", b"challenge")
+            .finalize()
+            .into_bytes();
+        assert!(global_ctx.verify_secret_proof(b"challenge", &proof));
+
+        assert_eq!(global_ctx.get_secret_activate_at(), None);
+        global_ctx.set_announced_secret_activate_at(Some(100));
+        assert_eq!(global_ctx.get_secret_activate_at(), Some(100));
+
+        let mut subscriber = global_ctx.subscribe();
+        assert!(global_ctx.rotate_network_secret().unwrap());
+        assert!(matches!(
+            subscriber.recv().await.unwrap(),
+            GlobalCtxEvent::NetworkSecretRotated(_)
+        ));
+        assert_eq!(global_ctx.get_network_identity(), new);
+        assert_eq!(global_ctx.get_next_network_identity(), None);
+        assert_eq!(global_ctx.get_secret_activate_at(), None);
+        assert!(global_ctx.is_accepted_network_identity(&old));
+        assert!(!global_ctx.rotate_network_secret().unwrap());
+
+        // pretend the grace period is over
+        global_ctx
+            .previous_network_identity
+            .lock()
+            .unwrap()
+            .as_mut()
+            .unwrap()
+            .1 -= SECRET_ROTATION_GRACE_SECS;
+        assert!(global_ctx.expire_previous_network_identity());
+        assert!(!global_ctx.is_accepted_network_identity(&old));
+    }
+
+    #[tokio::test]
+    async fn secret_rotation_is_written_to_config_file() {
+        let old = NetworkIdentity::new("net".to_string(), "old".to_string());
+        let new = NetworkIdentity::new("net".to_string(), "new".to_string());
+        let rotation = SecretRotationConfig {
+            next_network_secret: "new".to_string(),
+            activate_at: None,
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+
+        let global_ctx = get_mock_global_ctx_with_network(Some(old.clone()));
+        global_ctx.config.set_config_file(Some(config_path.clone()));
+        global_ctx.set_secret_rotation_config(Some(rotation.clone()));
+        assert!(global_ctx.rotate_network_secret().unwrap());
+
+        let saved = TomlConfigLoader::new_from_str(&std::fs::read_to_string(&config_path).unwrap())
+            .unwrap();
+        assert_eq!(saved.get_network_identity(), new);
+        assert_eq!(saved.get_secret_rotation_config(), None);
+
+        // an unwritable config keeps the old secret
+        let global_ctx = get_mock_global_ctx_with_network(Some(old.clone()));
+        global_ctx
+            .config
+            .set_config_file(Some(dir.path().join("missing").join("config.toml")));
+        global_ctx.set_secret_rotation_config(Some(rotation.clone()));
+        let generation = global_ctx.network_secret_generation();
+        assert!(global_ctx.rotate_network_secret().is_err());
+        assert_eq!(global_ctx.get_network_identity(), old);
+        assert_eq!(
+            global_ctx.config.get_secret_rotation_config(),
+            Some(rotation)
+        );
+        assert_eq!(global_ctx.network_secret_generation(), generation);
+    }
+
     pub fn get_mock_global_ctx_with_network(
         network_identy: Option<NetworkIdentity>,
     ) -> ArcGlobalCtx {
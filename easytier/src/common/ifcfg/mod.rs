@@ -177,3 +177,23 @@ pub(crate) fn list_ipv6_route_messages()
 pub(crate) fn get_interface_index(name: &str) -> Result<u32, Error> {
     netlink::NetlinkIfConfiger::get_interface_index(name)
 }
+
+// bridge `ifname` with `target`. an existing bridge is joined directly, a
+// physical interface is put into a new bridge together with `ifname`.
+#[cfg(target_os = "linux")]
+pub(crate) async fn bridge_interface(ifname: &str, target: &str) -> Result<(), Error> {
+    let is_bridge =
+        |name: &str| std::path::Path::new(&format!("/sys/class/net/{}/bridge", name)).exists();
+    let bridge = if is_bridge(target) {
+        target.to_string()
+    } else {
+        let bridge: String = format!("etbr-{}", ifname).chars().take(15).collect();
+        if !is_bridge(&bridge) {
+            run_shell_cmd(&format!("ip link add name {} type bridge", bridge)).await?;
+        }
+        run_shell_cmd(&format!("ip link set dev {} master {}", target, bridge)).await?;
+        bridge
+    };
+    run_shell_cmd(&format!("ip link set dev {} master {}", ifname, bridge)).await?;
+    run_shell_cmd(&format!("ip link set dev {} up", bridge)).await
+}
@@ -1,10 +1,12 @@
 use crate::common::config::{FileLoggerConfig, LoggingConfigLoader};
 use crate::common::get_logger_timer_rfc3339;
+use crate::common::log_ring::{JsonFormat, LogRingLayer, normalize_directives};
 use crate::common::tracing_rolling_appender::{FileAppenderWrapper, RollingFileAppenderBase};
-use crate::rpc_service::logger::{CURRENT_LOG_LEVEL, LOGGER_LEVEL_SENDER};
+use crate::rpc_service::logger::{CURRENT_LOG_FILTER, CURRENT_LOG_LEVEL, LOGGER_LEVEL_SENDER};
 use anyhow::Context;
 use paste::paste;
 use std::io::IsTerminal;
+use std::sync::OnceLock;
 use tracing::level_filters::LevelFilter;
 use tracing::{Level, Metadata};
 use tracing_subscriber::Registry;
@@ -12,6 +14,7 @@ use tracing_subscriber::filter::{FilterExt, filter_fn};
 use tracing_subscriber::fmt::format::FmtSpan;
 use tracing_subscriber::fmt::layer;
 use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::reload;
 use tracing_subscriber::util::SubscriberInitExt;
 use tracing_subscriber::{EnvFilter, Layer};
 
@@ -73,6 +76,24 @@ fn parse_file_filter(level: LevelFilter) -> Result<EnvFilter, anyhow::Error> {
     }
 }
 
+/// Build a filter from EnvFilter directives, crate-relative targets such as
+/// `peers::peer_ospf_route=trace` are accepted.
+pub fn parse_filter_directives(directives: &str) -> Result<EnvFilter, anyhow::Error> {
+    EnvFilter::builder()
+        .parse(normalize_directives(directives))
+        .with_context(|| format!("invalid log filter: {directives:?}"))
+}
+
+// the reloader accepts either a bare level or a full directive string
+fn parse_reload_filter(filter: &str) -> Result<EnvFilter, anyhow::Error> {
+    match filter.parse::<LevelFilter>() {
+        Ok(level) => parse_file_filter(level),
+        Err(_) => parse_filter_directives(filter),
+    }
+}
+
+static RING_FILTER_RELOADER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+
 fn is_log(meta: &Metadata) -> bool {
     meta.target() == LOG_TARGET || meta.target().starts_with(&format!("{LOG_TARGET}::"))
 }
@@ -110,6 +131,9 @@ pub fn init(
     layers.extend(console_layers);
 
     let sender = if cfg!(not(test)) {
+        if reload {
+            layers.push(ring_layer()?);
+        }
         let (file_layers, sender) = file_layers(config.get_file_logger_config(), reload)?;
         layers.extend(file_layers);
         sender
@@ -174,6 +198,15 @@ fn console_layers(default_level: Option<LevelFilter>) -> anyhow::Result<Vec<BoxL
     Ok(layers)
 }
 
+// keeps recent events in memory for `easytier-cli logger tail`, follows the
+// runtime filter set through the logger rpc.
+fn ring_layer() -> anyhow::Result<BoxLayer> {
+    let (ring_filter, ring_filter_reloader) =
+        reload::Layer::<_, Registry>::new(parse_env_filter(Some(LevelFilter::INFO))?);
+    let _ = RING_FILTER_RELOADER.set(ring_filter_reloader);
+    Ok(LogRingLayer.with_filter(ring_filter).boxed())
+}
+
 fn file_layers(
     config: FileLoggerConfig,
     reload: bool,
@@ -189,6 +222,12 @@ fn file_layers(
         return Ok((layers, None));
     }
 
+    let json = match config.format.as_deref().unwrap_or("text") {
+        "text" => false,
+        "json" => true,
+        other => anyhow::bail!("invalid file log format: {other:?}, expected text or json"),
+    };
+
     let (file_filter, file_filter_reloader) =
         tracing_subscriber::reload::Layer::<_, Registry>::new(parse_file_filter(level)?);
 
@@ -219,14 +258,19 @@ fn file_layers(
         FileAppenderWrapper::new(file_appender)
     };
 
-    layers.push(
+    let file_layer = if json {
+        layer()
+            .event_format(JsonFormat)
+            .with_writer(wrapper)
+            .boxed()
+    } else {
         vec![
             tracing_layer!(layer(wrapper.clone())),
             log_layer!(layer(wrapper.clone())),
         ]
-        .with_filter(file_filter)
-        .boxed(),
-    );
+        .boxed()
+    };
+    layers.push(file_layer.with_filter(file_filter).boxed());
 
     if !reload {
         return Ok((layers, None));
@@ -237,18 +281,11 @@ fn file_layers(
     // 初始化全局状态
     let _ = LOGGER_LEVEL_SENDER.set(std::sync::Mutex::new(tx.clone()));
     let _ = CURRENT_LOG_LEVEL.set(std::sync::Mutex::new(level.to_string()));
+    let _ = CURRENT_LOG_FILTER.set(std::sync::Mutex::new(level.to_string()));
 
     std::thread::spawn(move || {
         while let Ok(lf) = rx.recv() {
-            let parsed_level = match lf.parse::<LevelFilter>() {
-                Ok(level) => level,
-                Err(e) => {
-                    error!("Failed to parse new log level {:?}: {}", lf, e);
-                    continue;
-                }
-            };
-
-            let mut new_filter = match parse_file_filter(parsed_level) {
+            let mut new_filter = match parse_reload_filter(&lf) {
                 Ok(filter) => Some(filter),
                 Err(e) => {
                     error!("Failed to build new log filter for {:?}: {:?}", lf, e);
@@ -256,13 +293,20 @@ fn file_layers(
                 }
             };
 
+            if let Some(ring_reloader) = RING_FILTER_RELOADER.get()
+                && let Ok(ring_filter) = parse_reload_filter(&lf)
+                && let Err(e) = ring_reloader.reload(ring_filter)
+            {
+                error!("Failed to reload log ring filter: {:?}", e);
+            }
+
             match file_filter_reloader.modify(|f| {
                 *f = new_filter
                     .take()
                     .expect("log filter reloader only applies one filter per reload");
             }) {
                 Ok(()) => {
-                    info!("Reload log filter succeed, new filter level: {:?}", lf);
+                    info!("Reload log filter succeed, new filter: {:?}", lf);
                 }
                 Err(e) => {
                     error!("Failed to reload log filter: {:?}", e);
@@ -469,6 +513,7 @@ mod tests {
             dir: Some(temp_dir.path().to_string_lossy().to_string()),
             size_mb: Some(10),
             count: Some(1),
+            format: None,
         };
 
         let (layers, sender) = file_layers(cfg, true).unwrap();
@@ -498,4 +543,85 @@ mod tests {
             "debug log should be visible after reload"
         );
     }
+    #[test]
+    #[serial_test::serial]
+    fn file_logger_reload_accepts_directives() {
+        let _guard = EnvVarGuard::unset(RUST_LOG);
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_file_name = "reload-directives-test.log".to_string();
+        let log_path = temp_dir.path().join(&log_file_name);
+
+        let cfg = FileLoggerConfig {
+            level: Some(LevelFilter::INFO.to_string()),
+            file: Some(log_file_name),
+            dir: Some(temp_dir.path().to_string_lossy().to_string()),
+            ..Default::default()
+        };
+
+        let (layers, sender) = file_layers(cfg, true).unwrap();
+        let sender = sender.expect("reload=true should return a sender");
+        let enabled_marker = "reload-directives-enabled-marker";
+        let filtered_marker = "reload-directives-filtered-marker";
+        let subscriber = Registry::default().with(layers);
+
+        tracing::subscriber::with_default(subscriber, || {
+            sender
+                .send("warn,CORE::directives=debug".to_string())
+                .unwrap();
+            std::thread::sleep(std::time::Duration::from_millis(300));
+
+            tracing::debug!(target: "CORE::directives", "{}", enabled_marker);
+            tracing::info!(target: LOG_TARGET, "{}", filtered_marker);
+            std::thread::sleep(std::time::Duration::from_millis(300));
+        });
+
+        let content = std::fs::read_to_string(&log_path).unwrap_or_default();
+        assert!(content.contains(enabled_marker));
+        assert!(!content.contains(filtered_marker));
+        assert!(parse_filter_directives("peers=loud").is_err());
+    }
+
+    #[test]
+    #[serial_test::serial]
+    fn file_logger_json_format() {
+        let _guard = EnvVarGuard::unset(RUST_LOG);
+        let temp_dir = tempfile::tempdir().unwrap();
+        let log_file_name = "json-format-test.log".to_string();
+        let log_path = temp_dir.path().join(&log_file_name);
+
+        let cfg = FileLoggerConfig {
+            level: Some(LevelFilter::INFO.to_string()),
+            file: Some(log_file_name),
+            dir: Some(temp_dir.path().to_string_lossy().to_string()),
+            format: Some("json".to_string()),
+            ..Default::default()
+        };
+
+        let (layers, _sender) = file_layers(cfg, false).unwrap();
+        let marker = "json-format-marker";
+        let subscriber = Registry::default().with(layers);
+
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: LOG_TARGET, peer_id = 7, "{}", marker);
+            std::thread::sleep(std::time::Duration::from_millis(300));
+        });
+
+        let content = std::fs::read_to_string(&log_path).unwrap_or_default();
+        let line = content
+            .lines()
+            .find(|l| l.contains(marker))
+            .expect("json log line should be written");
+        let value: serde_json::Value = serde_json::from_str(line).unwrap();
+        assert_eq!(value["level"], "INFO");
+        assert_eq!(value["target"], LOG_TARGET);
+        assert_eq!(value["message"], marker);
+        assert_eq!(value["fields"]["peer_id"], "7");
+
+        let cfg = FileLoggerConfig {
+            level: Some(LevelFilter::INFO.to_string()),
+            format: Some("xml".to_string()),
+            ..Default::default()
+        };
+        assert!(file_layers(cfg, false).is_err());
+    }
 }
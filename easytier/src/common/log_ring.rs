@@ -0,0 +1,463 @@
+// in-memory ring of recent log events for `easytier-cli logger tail`, plus the
+// json line formatter used by the file logger.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::Write as _,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use tracing::{Event, Level, Subscriber, field::Field, level_filters::LevelFilter};
+use tracing_subscriber::{
+    Layer,
+    fmt::{FmtContext, FormatEvent, FormatFields, format},
+    layer::Context,
+    registry::LookupSpan,
+};
+
+use crate::proto::api::logger::{LogEvent, LogLevel, TailLogResponse};
+
+pub const LOG_RING_CAPACITY: usize = 4096;
+pub const MAX_TAIL_EVENTS: usize = 1024;
+const MAX_MESSAGE_LEN: usize = 4096;
+
+// top level modules of this crate, so directives like `peers::peer_ospf_route=trace`
+// can be written without the `easytier::` prefix.
+const CRATE_MODULES: &[&str] = &[
+    "arch",
+    "common",
+    "connector",
+    "core",
+    "gateway",
+    "instance",
+    "instance_manager",
+    "launcher",
+    "peer_center",
+    "peers",
+    "proto",
+    "rpc_service",
+    "service_manager",
+    "tunnel",
+    "utils",
+    "vpn_portal",
+    "web_client",
+];
+
+fn normalize_target(target: &str) -> String {
+    let first = target.split("::").next().unwrap_or_default();
+    if CRATE_MODULES.contains(&first) {
+        format!("easytier::{target}")
+    } else {
+        target.to_string()
+    }
+}
+
+/// Prefix crate-relative targets in EnvFilter directives with `easytier::`.
+pub fn normalize_directives(directives: &str) -> String {
+    directives
+        .split(',')
+        .map(str::trim)
+        .filter(|d| !d.is_empty())
+        .map(|d| {
+            let target_end = d.find(['[', '=']).unwrap_or(d.len());
+            let (target, rest) = d.split_at(target_end);
+            if target.is_empty() || target.parse::<LevelFilter>().is_ok() {
+                d.to_string()
+            } else {
+                format!("{}{}", normalize_target(target), rest)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn level_to_proto(level: &Level) -> LogLevel {
+    match *level {
+        Level::ERROR => LogLevel::Error,
+        Level::WARN => LogLevel::Warning,
+        Level::INFO => LogLevel::Info,
+        Level::DEBUG => LogLevel::Debug,
+        Level::TRACE => LogLevel::Trace,
+    }
+}
+
+fn proto_to_level_filter(level: LogLevel) -> LevelFilter {
+    match level {
+        LogLevel::Disabled => LevelFilter::OFF,
+        LogLevel::Error => LevelFilter::ERROR,
+        LogLevel::Warning => LevelFilter::WARN,
+        LogLevel::Info => LevelFilter::INFO,
+        LogLevel::Debug => LevelFilter::DEBUG,
+        LogLevel::Trace => LevelFilter::TRACE,
+    }
+}
+
+fn truncate(mut s: String, max: usize) -> String {
+    if s.len() > max {
+        let mut end = max;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        s.truncate(end);
+        s.push('…');
+    }
+    s
+}
+
+#[derive(Default)]
+struct FieldCollector {
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+impl tracing::field::Visit for FieldCollector {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            self.fields
+                .push((field.name().to_string(), value.to_string()));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.fields
+                .push((field.name().to_string(), format!("{:?}", value)));
+        }
+    }
+}
+
+/// Target / level matcher applied to events already stored in the ring.
+///
+/// Accepts comma separated `level`, `target` or `target=level` directives, the
+/// longest matching target wins. Without a bare level, events whose target
+/// matches no directive are dropped.
+#[derive(Debug, Clone, Default)]
+pub struct TailFilter {
+    default: Option<LevelFilter>,
+    targets: Vec<(String, LevelFilter)>,
+}
+
+impl std::str::FromStr for TailFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = TailFilter::default();
+        for directive in s.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            match directive.split_once('=') {
+                Some((target, level)) => {
+                    let level = level
+                        .trim()
+                        .parse::<LevelFilter>()
+                        .map_err(|_| anyhow::anyhow!("invalid level in {:?}", directive))?;
+                    filter
+                        .targets
+                        .push((normalize_target(target.trim()), level));
+                }
+                None => match directive.parse::<LevelFilter>() {
+                    Ok(level) => filter.default = Some(level),
+                    Err(_) => filter
+                        .targets
+                        .push((normalize_target(directive), LevelFilter::TRACE)),
+                },
+            }
+        }
+        Ok(filter)
+    }
+}
+
+impl TailFilter {
+    fn target_matches(target: &str, prefix: &str) -> bool {
+        target == prefix || (target.starts_with(prefix) && target[prefix.len()..].starts_with("::"))
+    }
+
+    pub fn matches(&self, event: &LogEvent) -> bool {
+        let level = match self
+            .targets
+            .iter()
+            .filter(|(t, _)| Self::target_matches(&event.target, t))
+            .max_by_key(|(t, _)| t.len())
+        {
+            Some((_, level)) => *level,
+            None if self.targets.is_empty() => self.default.unwrap_or(LevelFilter::TRACE),
+            None => self.default.unwrap_or(LevelFilter::OFF),
+        };
+        level != LevelFilter::OFF && proto_to_level_filter(event.level()) <= level
+    }
+}
+
+struct RingInner {
+    events: VecDeque<LogEvent>,
+    next_seq: u64,
+}
+
+pub struct LogRing {
+    inner: Mutex<RingInner>,
+    notify: tokio::sync::Notify,
+    capacity: usize,
+}
+
+impl LogRing {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            inner: Mutex::new(RingInner {
+                events: VecDeque::with_capacity(capacity.min(1024)),
+                next_seq: 1,
+            }),
+            notify: tokio::sync::Notify::new(),
+            capacity,
+        }
+    }
+
+    fn push(&self, mut event: LogEvent) {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            event.seq = inner.next_seq;
+            inner.next_seq += 1;
+            if inner.events.len() >= self.capacity {
+                inner.events.pop_front();
+            }
+            inner.events.push_back(event);
+        }
+        self.notify.notify_waiters();
+    }
+
+    /// Events after `after_seq` matching `filter`, or the latest `max` matching
+    /// events when `after_seq` is 0.
+    pub fn read(&self, after_seq: u64, filter: &TailFilter, max: usize) -> TailLogResponse {
+        let inner = self.inner.lock().unwrap();
+        let newest_seq = inner.next_seq - 1;
+        let oldest_seq = inner
+            .events
+            .front()
+            .map(|e| e.seq)
+            .unwrap_or(inner.next_seq);
+
+        if after_seq == 0 {
+            let mut events = inner
+                .events
+                .iter()
+                .rev()
+                .filter(|e| filter.matches(e))
+                .take(max)
+                .cloned()
+                .collect::<Vec<_>>();
+            events.reverse();
+            return TailLogResponse {
+                events,
+                last_seq: newest_seq,
+                missed: 0,
+            };
+        }
+
+        let missed = oldest_seq.saturating_sub(after_seq + 1);
+        let mut last_seq = newest_seq;
+        let mut events: Vec<LogEvent> = Vec::new();
+        for e in inner.events.iter().filter(|e| e.seq > after_seq) {
+            if !filter.matches(e) {
+                continue;
+            }
+            if events.len() >= max {
+                last_seq = events.last().map(|e| e.seq).unwrap_or(after_seq);
+                break;
+            }
+            events.push(e.clone());
+        }
+
+        TailLogResponse {
+            events,
+            last_seq,
+            missed,
+        }
+    }
+
+    /// Like [`LogRing::read`], but waits up to `wait` for a matching event when
+    /// nothing new is available.
+    pub async fn tail(
+        &self,
+        after_seq: u64,
+        filter: &TailFilter,
+        max: usize,
+        wait: Duration,
+    ) -> TailLogResponse {
+        let deadline = tokio::time::Instant::now() + wait;
+        loop {
+            let notified = self.notify.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            let resp = self.read(after_seq, filter, max);
+            if after_seq == 0 || !resp.events.is_empty() || resp.missed > 0 {
+                return resp;
+            }
+
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return resp;
+            }
+        }
+    }
+}
+
+pub fn log_ring() -> &'static LogRing {
+    static RING: OnceLock<LogRing> = OnceLock::new();
+    RING.get_or_init(|| LogRing::new(LOG_RING_CAPACITY))
+}
+
+/// Layer pushing every event that passes its filter into [`log_ring`].
+pub struct LogRingLayer;
+
+impl<S: Subscriber> Layer<S> for LogRingLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        let mut collector = FieldCollector::default();
+        event.record(&mut collector);
+
+        log_ring().push(LogEvent {
+            seq: 0,
+            timestamp_ms: chrono::Utc::now().timestamp_millis(),
+            level: level_to_proto(meta.level()).into(),
+            target: meta.target().to_string(),
+            message: truncate(collector.message, MAX_MESSAGE_LEN),
+            fields: collector
+                .fields
+                .into_iter()
+                .map(|(k, v)| (k, truncate(v, MAX_MESSAGE_LEN)))
+                .collect::<BTreeMap<_, _>>(),
+        });
+    }
+}
+
+/// Writes one json object per event: timestamp, level, target, message, fields
+/// and the names of the enclosing spans.
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: format::Writer<'_>,
+        event: &Event<'_>,
+    ) -> std::fmt::Result {
+        let meta = event.metadata();
+        let mut collector = FieldCollector::default();
+        event.record(&mut collector);
+
+        let spans = ctx
+            .event_scope()
+            .map(|scope| {
+                scope
+                    .from_root()
+                    .map(|span| span.name().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let line = serde_json::json!({
+            "timestamp": chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, false),
+            "level": meta.level().as_str(),
+            "target": meta.target(),
+            "message": collector.message,
+            "fields": collector.fields.into_iter().collect::<serde_json::Map<_, _>>(),
+            "spans": spans,
+        });
+
+        writer.write_str(&line.to_string())?;
+        writer.write_char('\n')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(level: LogLevel, target: &str, message: &str) -> LogEvent {
+        LogEvent {
+            level: level.into(),
+            target: target.to_string(),
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn normalize_crate_directives() {
+        assert_eq!(
+            normalize_directives("info, peers::peer_ospf_route=trace,tokio=warn"),
+            "info,easytier::peers::peer_ospf_route=trace,tokio=warn"
+        );
+        assert_eq!(
+            normalize_directives("tunnel[conn]=debug,CORE=info"),
+            "easytier::tunnel[conn]=debug,CORE=info"
+        );
+    }
+
+    #[test]
+    fn tail_filter_longest_prefix_wins() {
+        let filter: TailFilter = "warn,peers=debug,peers::peer_ospf_route=error"
+            .parse()
+            .unwrap();
+        let e = |l, t| event(l, t, "");
+
+        assert!(filter.matches(&e(LogLevel::Debug, "easytier::peers::peer_map")));
+        assert!(!filter.matches(&e(LogLevel::Trace, "easytier::peers::peer_map")));
+        assert!(!filter.matches(&e(LogLevel::Warning, "easytier::peers::peer_ospf_route")));
+        assert!(filter.matches(&e(LogLevel::Warning, "easytier::tunnel::udp")));
+        assert!(!filter.matches(&e(LogLevel::Info, "easytier::tunnel::udp")));
+        assert!(!filter.matches(&e(LogLevel::Debug, "easytier::peersx")));
+
+        let only_target: TailFilter = "tunnel".parse().unwrap();
+        assert!(only_target.matches(&e(LogLevel::Trace, "easytier::tunnel::tcp")));
+        assert!(!only_target.matches(&e(LogLevel::Error, "easytier::peers")));
+
+        assert!(TailFilter::default().matches(&e(LogLevel::Trace, "anything")));
+        assert!("peers=loud".parse::<TailFilter>().is_err());
+    }
+
+    #[tokio::test]
+    async fn ring_read_and_tail() {
+        let ring = LogRing::new(4);
+        let all = TailFilter::default();
+        for i in 0..6 {
+            ring.push(event(LogLevel::Info, "easytier::peers", &i.to_string()));
+        }
+
+        let latest = ring.read(0, &all, 2);
+        assert_eq!(
+            latest.events.iter().map(|e| e.seq).collect::<Vec<_>>(),
+            vec![5, 6]
+        );
+        assert_eq!(latest.last_seq, 6);
+
+        let resumed = ring.read(1, &all, 10);
+        assert_eq!(resumed.missed, 1);
+        assert_eq!(resumed.events.len(), 4);
+
+        let limited = ring.read(2, &all, 2);
+        assert_eq!(limited.events.len(), 2);
+        assert_eq!(limited.last_seq, 4);
+
+        let empty = ring.tail(6, &all, 10, Duration::from_millis(50)).await;
+        assert!(empty.events.is_empty());
+        assert_eq!(empty.last_seq, 6);
+
+        let ring = std::sync::Arc::new(ring);
+        let r = ring.clone();
+        let task = tokio::spawn(async move {
+            r.tail(6, &TailFilter::default(), 10, Duration::from_secs(5))
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        ring.push(event(LogLevel::Error, "easytier::tunnel", "late"));
+        let resp = task.await.unwrap();
+        assert_eq!(resp.events.len(), 1);
+        assert_eq!(resp.events[0].message, "late");
+    }
+}
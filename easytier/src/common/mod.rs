@@ -18,10 +18,12 @@ pub mod global_ctx;
 pub mod idn;
 pub mod ifcfg;
 pub mod log;
+pub mod log_ring;
 pub mod machine_id;
 pub mod netns;
 pub mod network;
 pub mod os_info;
+pub mod secret_ref;
 pub mod stats_manager;
 pub mod stun;
 pub mod stun_codec_ext;
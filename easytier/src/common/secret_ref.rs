@@ -0,0 +1,219 @@
+//! Secret references for config values such as `network_secret` and private keys.
+//!
+//! Instead of writing a secret in plain text, a config value may point at where the
+//! secret lives:
+//!
+//! - `file:///run/secrets/et` reads the file content.
+//! - `exec:///usr/bin/helper arg1 arg2` runs the command and takes its stdout.
+//! - `credential://name` reads `$CREDENTIALS_DIRECTORY/name` (systemd `LoadCredential=`).
+//!
+//! Trailing newlines are stripped from the resolved value.
+//!
+//! References are resolved when a config is loaded, so a changed secret is picked
+//! up when the instance is started again with its config. Configs that may come
+//! from a remote source (the web console, `NetworkConfig`) cannot use references
+//! at all, otherwise they could read local files or run commands on this host.
+
+use std::{fmt, path::PathBuf, process::Stdio};
+
+use anyhow::Context as _;
+
+const FILE_SCHEME: &str = "file://";
+const EXEC_SCHEME: &str = "exec://";
+const CREDENTIAL_SCHEME: &str = "credential://";
+const CREDENTIALS_DIRECTORY_ENV: &str = "CREDENTIALS_DIRECTORY";
+
+pub fn is_secret_ref(value: &str) -> bool {
+    [FILE_SCHEME, EXEC_SCHEME, CREDENTIAL_SCHEME]
+        .iter()
+        .any(|scheme| value.starts_with(scheme))
+}
+
+fn trim_secret(mut secret: String) -> anyhow::Result<String> {
+    let len = secret.trim_end_matches(['\r', '\n']).len();
+    secret.truncate(len);
+    if secret.is_empty() {
+        anyhow::bail!("resolved secret is empty");
+    }
+    Ok(secret)
+}
+
+fn read_secret_file(path: PathBuf) -> anyhow::Result<String> {
+    let secret = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read secret file {}", path.display()))?;
+    trim_secret(secret)
+}
+
+fn credential_path(name: &str) -> anyhow::Result<PathBuf> {
+    if name.is_empty() || name.contains(['/', '\\']) || name == "." || name == ".." {
+        anyhow::bail!("invalid credential name: {:?}", name);
+    }
+    let dir = std::env::var_os(CREDENTIALS_DIRECTORY_ENV).with_context(|| {
+        format!(
+            "{} is not set, cannot resolve credential://{}",
+            CREDENTIALS_DIRECTORY_ENV, name
+        )
+    })?;
+    Ok(PathBuf::from(dir).join(name))
+}
+
+fn exec_secret(command: &str) -> anyhow::Result<String> {
+    let mut parts = command.split_whitespace();
+    let program = parts
+        .next()
+        .with_context(|| "exec secret reference has no command")?;
+    let output = std::process::Command::new(program)
+        .args(parts)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("failed to run secret command {}", program))?;
+    if !output.status.success() {
+        anyhow::bail!("secret command {} exited with {}", program, output.status);
+    }
+    let secret = String::from_utf8(output.stdout)
+        .with_context(|| format!("secret command {} printed non utf-8 output", program))?;
+    trim_secret(secret)
+}
+
+/// Resolve a secret reference. Returns `None` if `value` is not a reference.
+/// References are rejected unless `allow_refs` is set, which is only the case for
+/// configs from this host (config file, command line).
+pub fn resolve_secret_ref(value: &str, allow_refs: bool) -> anyhow::Result<Option<String>> {
+    if !allow_refs && is_secret_ref(value) {
+        anyhow::bail!("secret references are not allowed in configs from a remote source");
+    }
+    let secret = if let Some(path) = value.strip_prefix(FILE_SCHEME) {
+        read_secret_file(PathBuf::from(path))?
+    } else if let Some(command) = value.strip_prefix(EXEC_SCHEME) {
+        exec_secret(command)?
+    } else if let Some(name) = value.strip_prefix(CREDENTIAL_SCHEME) {
+        read_secret_file(credential_path(name)?)?
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(secret))
+}
+
+/// Remembers which reference each resolved secret came from, so the secret can be
+/// resolved again from its source and written back as the reference instead of
+/// its value.
+#[derive(Clone, Default, PartialEq)]
+pub struct ResolvedSecrets {
+    // (reference, resolved secret)
+    entries: Vec<(String, String)>,
+}
+
+impl ResolvedSecrets {
+    /// Resolve `value` if it is a reference or a secret previously resolved from one.
+    /// Returns `None` for plain values.
+    pub fn resolve(&mut self, value: &str, allow_refs: bool) -> anyhow::Result<Option<String>> {
+        let reference = if is_secret_ref(value) {
+            value.to_owned()
+        } else if let Some(reference) = self.reference_of(value) {
+            reference.to_owned()
+        } else {
+            return Ok(None);
+        };
+        let secret = resolve_secret_ref(&reference, allow_refs)?
+            .with_context(|| format!("not a secret reference: {}", reference))?;
+        self.entries.retain(|(r, _)| *r != reference);
+        self.entries.push((reference, secret.clone()));
+        Ok(Some(secret))
+    }
+
+    pub fn reference_of(&self, secret: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(_, s)| s == secret)
+            .map(|(r, _)| r.as_str())
+    }
+
+    /// The reference a secret was resolved from, or the value itself.
+    pub fn redact(&self, value: &str) -> String {
+        self.reference_of(value).unwrap_or(value).to_owned()
+    }
+}
+
+impl fmt::Debug for ResolvedSecrets {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.entries.iter().map(|(r, _)| r))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_file_and_exec_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret");
+        std::fs::write(&path, "s3cret\n").unwrap();
+
+        let file_ref = format!("file://{}", path.display());
+        assert_eq!(
+            resolve_secret_ref(&file_ref, true).unwrap().as_deref(),
+            Some("s3cret")
+        );
+        assert_eq!(resolve_secret_ref("plain", true).unwrap(), None);
+
+        assert_eq!(
+            resolve_secret_ref("exec:///bin/echo from-exec", true)
+                .unwrap()
+                .as_deref(),
+            Some("from-exec")
+        );
+        assert!(resolve_secret_ref("exec:///bin/false", true).is_err());
+        assert!(resolve_secret_ref("credential://../secret", true).is_err());
+    }
+
+    #[test]
+    fn remote_configs_cannot_use_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret");
+        std::fs::write(&path, "s3cret").unwrap();
+
+        for reference in [
+            format!("file://{}", path.display()),
+            "exec:///bin/echo from-exec".to_owned(),
+            "credential://secret".to_owned(),
+        ] {
+            assert!(
+                resolve_secret_ref(&reference, false).is_err(),
+                "{}",
+                reference
+            );
+            assert!(
+                ResolvedSecrets::default()
+                    .resolve(&reference, false)
+                    .is_err()
+            );
+        }
+        assert_eq!(resolve_secret_ref("plain", false).unwrap(), None);
+    }
+
+    #[test]
+    fn resolved_secrets_reresolve_and_redact() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret");
+        std::fs::write(&path, "first").unwrap();
+        let file_ref = format!("file://{}", path.display());
+
+        let mut refs = ResolvedSecrets::default();
+        let first = refs.resolve(&file_ref, true).unwrap().unwrap();
+        assert_eq!(first, "first");
+        assert_eq!(refs.redact(&first), file_ref);
+        assert_eq!(refs.redact("other"), "other");
+        assert!(!format!("{:?}", refs).contains("first"));
+
+        // resolving again re-reads the source behind an already resolved secret
+        std::fs::write(&path, "second").unwrap();
+        let second = refs.resolve(&first, true).unwrap().unwrap();
+        assert_eq!(second, "second");
+        assert_eq!(refs.redact(&second), file_ref);
+        assert_eq!(refs.reference_of(&first), None);
+    }
+}
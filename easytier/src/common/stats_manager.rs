@@ -104,6 +104,13 @@ pub enum MetricName {
     CompressionBytesTxAfter,
 
     TcpProxyConnect,
+
+    /// UDP hole punching rounds between two hard symmetric NATs
+    UdpHolePunchBothHardSymAttempts,
+    /// UDP hole punching rounds between two hard symmetric NATs that built a tunnel
+    UdpHolePunchBothHardSymSuccess,
+    /// UDP hole punching probes sent to random ports of a hard symmetric NAT
+    UdpHolePunchBothHardSymProbesSent,
 }
 
 impl fmt::Display for MetricName {
@@ -195,6 +202,16 @@ impl fmt::Display for MetricName {
             MetricName::CompressionBytesTxAfter => write!(f, "compression_bytes_tx_after"),
 
             MetricName::TcpProxyConnect => write!(f, "tcp_proxy_connect"),
+
+            MetricName::UdpHolePunchBothHardSymAttempts => {
+                write!(f, "udp_hole_punch_both_hard_sym_attempts")
+            }
+            MetricName::UdpHolePunchBothHardSymSuccess => {
+                write!(f, "udp_hole_punch_both_hard_sym_success")
+            }
+            MetricName::UdpHolePunchBothHardSymProbesSent => {
+                write!(f, "udp_hole_punch_both_hard_sym_probes_sent")
+            }
         }
     }
 }
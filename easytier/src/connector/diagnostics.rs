@@ -0,0 +1,403 @@
+// records the connection attempts of the p2p connectors, so we can explain why a peer
+// is still relayed.
+
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use dashmap::DashMap;
+
+use crate::{
+    common::{PeerId, global_ctx::ArcGlobalCtx},
+    connector::udp_hole_punch::common::{UdpNatType, UdpPunchClientMethod},
+    proto::{
+        api::instance::{ConnectAttempt, ConnectAttemptMethod, ConnectBlockState},
+        common::{NatType, PeerFeatureFlag},
+    },
+};
+
+const MAX_ATTEMPTS_PER_PEER: usize = 32;
+const MAX_TRACKED_PEERS: usize = 1024;
+// forget peers without any attempt for this long when there are too many peers
+const PEER_STATE_EXPIRE_SEC: i64 = 3600;
+
+fn now_unix() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+#[derive(Default)]
+struct PeerConnectState {
+    attempts: VecDeque<ConnectAttempt>,
+    blocks: Vec<ConnectBlockState>,
+    last_update_unix: i64,
+}
+
+impl PeerConnectState {
+    fn set_block(&mut self, block: ConnectBlockState) {
+        self.blocks
+            .retain(|b| b.method != block.method || b.target != block.target);
+        self.blocks.push(block);
+    }
+}
+
+#[derive(Default)]
+pub struct ConnectAttemptLog {
+    peers: DashMap<PeerId, PeerConnectState>,
+}
+
+impl ConnectAttemptLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update_peer(&self, peer_id: PeerId, f: impl FnOnce(&mut PeerConnectState)) {
+        if self.peers.len() >= MAX_TRACKED_PEERS && !self.peers.contains_key(&peer_id) {
+            let expire_before = now_unix() - PEER_STATE_EXPIRE_SEC;
+            self.peers
+                .retain(|_, state| state.last_update_unix > expire_before);
+            if self.peers.len() >= MAX_TRACKED_PEERS {
+                return;
+            }
+        }
+
+        let mut state = self.peers.entry(peer_id).or_default();
+        state.last_update_unix = now_unix();
+        f(&mut state);
+    }
+
+    pub fn record_attempt<T, E: Display>(
+        &self,
+        peer_id: PeerId,
+        method: ConnectAttemptMethod,
+        target: impl Into<String>,
+        result: &Result<T, E>,
+    ) {
+        let target = target.into();
+        self.update_peer(peer_id, |state| {
+            if result.is_ok() {
+                // a success clears the backoff or blacklist of the same target
+                state
+                    .blocks
+                    .retain(|b| b.method != method as i32 || b.target != target);
+            }
+            if state.attempts.len() >= MAX_ATTEMPTS_PER_PEER {
+                state.attempts.pop_front();
+            }
+            state.attempts.push_back(ConnectAttempt {
+                method: method.into(),
+                target,
+                success: result.is_ok(),
+                error: result
+                    .as_ref()
+                    .err()
+                    .map(|e| e.to_string())
+                    .unwrap_or_default(),
+                time_unix: now_unix(),
+            });
+        });
+    }
+
+    pub fn record_blacklisted(
+        &self,
+        peer_id: PeerId,
+        method: ConnectAttemptMethod,
+        target: impl Into<String>,
+        reason: impl Into<String>,
+        duration: Duration,
+    ) {
+        self.update_peer(peer_id, |state| {
+            state.set_block(ConnectBlockState {
+                method: method.into(),
+                target: target.into(),
+                reason: reason.into(),
+                blacklisted: true,
+                until_unix: now_unix() + duration.as_secs() as i64,
+            });
+        });
+    }
+
+    pub fn record_backoff(
+        &self,
+        peer_id: PeerId,
+        method: ConnectAttemptMethod,
+        target: impl Into<String>,
+        duration: Duration,
+    ) {
+        if duration.is_zero() {
+            return;
+        }
+        self.update_peer(peer_id, |state| {
+            state.set_block(ConnectBlockState {
+                method: method.into(),
+                target: target.into(),
+                reason: "waiting for next retry".to_string(),
+                blacklisted: false,
+                until_unix: now_unix() + duration.as_secs().max(1) as i64,
+            });
+        });
+    }
+
+    /// Attempts of the peer, oldest first.
+    pub fn get_attempts(&self, peer_id: PeerId) -> Vec<ConnectAttempt> {
+        self.peers
+            .get(&peer_id)
+            .map(|state| state.attempts.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    /// Blacklist and backoff state of the peer that is still in effect.
+    pub fn get_blocks(&self, peer_id: PeerId) -> Vec<ConnectBlockState> {
+        let now = now_unix();
+        self.peers
+            .get(&peer_id)
+            .map(|state| {
+                state
+                    .blocks
+                    .iter()
+                    .filter(|b| b.until_unix > now)
+                    .cloned()
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+pub(crate) fn connect_attempt_method_name(method: i32) -> &'static str {
+    match ConnectAttemptMethod::try_from(method) {
+        Ok(ConnectAttemptMethod::DirectConnect) => "direct connect",
+        Ok(ConnectAttemptMethod::Ipv6Direct) => "ipv6 direct connect",
+        Ok(ConnectAttemptMethod::UdpHolePunch) => "udp hole punch",
+        Ok(ConnectAttemptMethod::TcpHolePunch) => "tcp hole punch",
+        Err(_) => "unknown",
+    }
+}
+
+pub(crate) struct DiagnoseInput<'a> {
+    pub directly_connected: bool,
+    pub my_udp_nat_type: NatType,
+    pub peer_udp_nat_type: NatType,
+    pub peer_feature_flag: Option<&'a PeerFeatureFlag>,
+    pub has_candidates: bool,
+    pub attempts: &'a [ConnectAttempt],
+    pub blocks: &'a [ConnectBlockState],
+}
+
+/// Explain why the peer is not directly connected, returns the verdict and suggestions.
+pub(crate) fn diagnose_verdict(
+    global_ctx: &ArcGlobalCtx,
+    input: &DiagnoseInput,
+) -> (String, Vec<String>) {
+    if input.directly_connected {
+        return ("peer is directly connected".to_string(), vec![]);
+    }
+
+    let flags = global_ctx.get_flags();
+    let mut reasons = vec![];
+    let mut suggestions = vec![];
+    let mut suggest = |s: &str| {
+        if !suggestions.iter().any(|x| x == s) {
+            suggestions.push(s.to_string());
+        }
+    };
+
+    if flags.disable_p2p && !input.peer_feature_flag.is_some_and(|f| f.need_p2p) {
+        reasons.push("p2p is disabled on this node".to_string());
+        suggest("remove --disable-p2p on this node, or set --need-p2p on the peer");
+    }
+    if let Some(feature_flag) = input.peer_feature_flag {
+        if feature_flag.disable_p2p && !flags.need_p2p {
+            reasons.push("the peer disabled p2p".to_string());
+            suggest("remove --disable-p2p on the peer, or set --need-p2p on this node");
+        }
+        if feature_flag.is_public_server {
+            reasons.push("the peer is a public server, hole punching is not tried".to_string());
+        }
+    }
+    if flags.lazy_p2p && input.attempts.is_empty() {
+        reasons.push("lazy p2p only connects after traffic is sent to the peer".to_string());
+        suggest("send some traffic to the peer, or remove --lazy-p2p");
+    }
+
+    if !input.has_candidates {
+        reasons.push("the peer reports no address usable for direct connect".to_string());
+        suggest(
+            "add a listener on the peer, or use --mapped-listeners if it is behind a port forward",
+        );
+    }
+
+    let my_nat: UdpNatType = input.my_udp_nat_type.into();
+    let peer_nat: UdpNatType = input.peer_udp_nat_type.into();
+    if my_nat.is_unknown() || peer_nat.is_unknown() {
+        reasons.push("nat type is unknown on at least one side".to_string());
+        suggest("make sure the stun servers are reachable over udp from both nodes");
+    } else if matches!(
+        my_nat.get_punch_hole_method(peer_nat, global_ctx.clone()),
+        UdpPunchClientMethod::None
+    ) && matches!(
+        peer_nat.get_punch_hole_method(my_nat, global_ctx.clone()),
+        UdpPunchClientMethod::None
+    ) && !my_nat.is_open()
+        && !peer_nat.is_open()
+    {
+        if flags.disable_sym_hole_punching && my_nat.is_sym() {
+            reasons.push("symmetric nat hole punching is disabled on this node".to_string());
+            suggest("remove --disable-sym-hole-punching");
+        } else {
+            reasons.push(format!(
+                "udp hole punching does not support {:?} <-> {:?}",
+                input.my_udp_nat_type, input.peer_udp_nat_type
+            ));
+        }
+        if flags.disable_upnp {
+            suggest("remove --disable-upnp so the router can map a port for this node");
+        }
+        suggest("forward a port to one of the nodes and add it with --mapped-listeners");
+        if !flags.enable_ipv6 {
+            suggest("enable ipv6, direct connect over public ipv6 does not need hole punching");
+        }
+    }
+
+    if flags.disable_udp_hole_punching {
+        reasons.push("udp hole punching is disabled on this node".to_string());
+        suggest("remove --disable-udp-hole-punching");
+    }
+
+    for block in input.blocks.iter().filter(|b| b.blacklisted) {
+        let method = connect_attempt_method_name(block.method);
+        if block.target.is_empty() {
+            reasons.push(format!("{} is blacklisted: {}", method, block.reason));
+        } else {
+            reasons.push(format!(
+                "{} to {} is blacklisted: {}",
+                method, block.target, block.reason
+            ));
+        }
+    }
+
+    // last failure of every method
+    let mut seen_methods = vec![];
+    for attempt in input.attempts.iter().rev() {
+        if seen_methods.contains(&attempt.method) {
+            continue;
+        }
+        seen_methods.push(attempt.method);
+        if !attempt.success {
+            reasons.push(format!(
+                "last {} attempt to {} failed: {}",
+                connect_attempt_method_name(attempt.method),
+                attempt.target,
+                attempt.error
+            ));
+        }
+    }
+    if !input.attempts.is_empty() && input.attempts.iter().all(|a| !a.success) {
+        suggest("check that the firewall of the peer allows its listener ports");
+    }
+
+    let verdict = if reasons.is_empty() {
+        if input.attempts.is_empty() {
+            "relayed, no connection attempt has been recorded yet".to_string()
+        } else {
+            "relayed, the last connection attempts succeeded but the connection is gone".to_string()
+        }
+    } else {
+        format!("relayed, {}", reasons.join("; "))
+    };
+
+    (verdict, suggestions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::global_ctx::tests::get_mock_global_ctx;
+
+    #[test]
+    fn test_attempt_log() {
+        let log = ConnectAttemptLog::new();
+        let target = "udp://1.2.3.4:11010";
+        log.record_attempt::<(), _>(
+            1,
+            ConnectAttemptMethod::DirectConnect,
+            target,
+            &Err("timeout"),
+        );
+        log.record_blacklisted(
+            1,
+            ConnectAttemptMethod::DirectConnect,
+            target,
+            "failed after retries",
+            Duration::from_secs(300),
+        );
+        log.record_backoff(
+            1,
+            ConnectAttemptMethod::UdpHolePunch,
+            "cone_to_cone",
+            Duration::from_secs(4),
+        );
+
+        let attempts = log.get_attempts(1);
+        assert_eq!(attempts.len(), 1);
+        assert!(!attempts[0].success);
+        assert_eq!(attempts[0].error, "timeout");
+        assert_eq!(log.get_blocks(1).len(), 2);
+        assert!(log.get_attempts(2).is_empty());
+
+        // success clears the blacklist of the same target
+        log.record_attempt::<(), &str>(1, ConnectAttemptMethod::DirectConnect, target, &Ok(()));
+        let blocks = log.get_blocks(1);
+        assert_eq!(blocks.len(), 1);
+        assert!(!blocks[0].blacklisted);
+
+        for _ in 0..MAX_ATTEMPTS_PER_PEER + 5 {
+            log.record_attempt::<(), _>(1, ConnectAttemptMethod::TcpHolePunch, "", &Err("x"));
+        }
+        assert_eq!(log.get_attempts(1).len(), MAX_ATTEMPTS_PER_PEER);
+    }
+
+    #[tokio::test]
+    async fn test_diagnose_verdict() {
+        let global_ctx = get_mock_global_ctx();
+        let mut input = DiagnoseInput {
+            directly_connected: true,
+            my_udp_nat_type: NatType::Symmetric,
+            peer_udp_nat_type: NatType::SymmetricEasyInc,
+            peer_feature_flag: None,
+            has_candidates: true,
+            attempts: &[],
+            blocks: &[],
+        };
+        let (verdict, suggestions) = diagnose_verdict(&global_ctx, &input);
+        assert_eq!(verdict, "peer is directly connected");
+        assert!(suggestions.is_empty());
+
+        input.directly_connected = false;
+        let (verdict, suggestions) = diagnose_verdict(&global_ctx, &input);
+        assert!(verdict.contains("udp hole punching does not support"));
+        assert!(suggestions.iter().any(|s| s.contains("--mapped-listeners")));
+
+        let attempts = [ConnectAttempt {
+            method: ConnectAttemptMethod::UdpHolePunch.into(),
+            target: "cone_to_cone".to_string(),
+            success: false,
+            error: "no punched socket".to_string(),
+            time_unix: now_unix(),
+        }];
+        input.my_udp_nat_type = NatType::PortRestricted;
+        input.peer_udp_nat_type = NatType::PortRestricted;
+        input.attempts = &attempts;
+        let (verdict, _) = diagnose_verdict(&global_ctx, &input);
+        assert!(!verdict.contains("does not support"));
+        assert!(verdict.contains("last udp hole punch attempt to cone_to_cone failed"));
+
+        input.my_udp_nat_type = NatType::Unknown;
+        let (verdict, suggestions) = diagnose_verdict(&global_ctx, &input);
+        assert!(verdict.contains("nat type is unknown"));
+        assert!(suggestions.iter().any(|s| s.contains("stun")));
+    }
+}
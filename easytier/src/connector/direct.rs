@@ -25,6 +25,7 @@ use crate::{
         peer_task::{PeerTaskLauncher, PeerTaskManager},
     },
     proto::{
+        api::instance::ConnectAttemptMethod,
         peer_rpc::{
             DirectConnectorRpc, DirectConnectorRpcClientFactory, DirectConnectorRpcServer,
             GetIpListRequest, GetIpListResponse, SendUdpHolePunchPacketRequest,
@@ -361,6 +362,13 @@ impl DirectConnectorManagerData {
             return Err(Error::UrlInBlacklist);
         }
 
+        let attempt_method =
+            if url::Url::parse(&addr).is_ok_and(|u| matches!(u.host(), Some(Host::Ipv6(_)))) {
+                ConnectAttemptMethod::Ipv6Direct
+            } else {
+                ConnectAttemptMethod::DirectConnect
+            };
+
         loop {
             if self.peer_manager.has_directly_connected_conn(dst_peer_id) {
                 return Ok(());
@@ -369,6 +377,12 @@ impl DirectConnectorManagerData {
             tracing::debug!(?dst_peer_id, ?addr, "try_connect_to_ip start one round");
             let ret = self.do_try_connect_to_ip(dst_peer_id, addr.clone()).await;
             tracing::debug!(?ret, ?dst_peer_id, ?addr, "try_connect_to_ip return");
+            self.global_ctx.get_connect_attempt_log().record_attempt(
+                dst_peer_id,
+                attempt_method,
+                addr.clone(),
+                &ret,
+            );
             if ret.is_ok() {
                 return Ok(());
             }
@@ -382,14 +396,27 @@ impl DirectConnectorManagerData {
                 assert!(delta > 0);
                 assert!(delta < backoff_ms[backoff_idx]);
 
-                tokio::time::sleep(Duration::from_millis(
-                    (backoff_ms[backoff_idx] + rand_gen.gen_range(-delta..delta)) as u64,
-                ))
-                .await;
+                let sleep_ms = (backoff_ms[backoff_idx] + rand_gen.gen_range(-delta..delta)) as u64;
+                self.global_ctx.get_connect_attempt_log().record_backoff(
+                    dst_peer_id,
+                    attempt_method,
+                    addr.clone(),
+                    Duration::from_millis(sleep_ms),
+                );
+                tokio::time::sleep(Duration::from_millis(sleep_ms)).await;
 
                 backoff_idx += 1;
                 continue;
             } else {
+                self.global_ctx
+                    .get_connect_attempt_log()
+                    .record_blacklisted(
+                        dst_peer_id,
+                        attempt_method,
+                        addr.clone(),
+                        "failed after all retries",
+                        std::time::Duration::from_secs(DIRECT_CONNECTOR_BLACKLIST_TIMEOUT_SEC),
+                    );
                 self.dst_listener_blacklist.insert(
                     DstListenerUrlBlackListItem(dst_peer_id, addr),
                     (),
@@ -637,7 +664,14 @@ impl DirectConnectorManagerData {
             }
 
             if attempt > 0 {
-                tokio::time::sleep(Duration::from_millis(backoff.next_backoff())).await;
+                let sleep_ms = backoff.next_backoff();
+                self.global_ctx.get_connect_attempt_log().record_backoff(
+                    dst_peer_id,
+                    ConnectAttemptMethod::DirectConnect,
+                    "",
+                    Duration::from_millis(sleep_ms),
+                );
+                tokio::time::sleep(Duration::from_millis(sleep_ms)).await;
             }
             attempt += 1;
 
@@ -656,8 +690,27 @@ impl DirectConnectorManagerData {
             let ip_list = rpc_stub
                 .get_ip_list(BaseController::default(), GetIpListRequest {})
                 .await;
-            let ip_list = handle_rpc_result(ip_list, dst_peer_id, &self.peer_black_list)
-                .with_context(|| format!("get ip list from peer {}", dst_peer_id))?;
+            let ip_list = handle_rpc_result(ip_list, dst_peer_id, &self.peer_black_list);
+            if let Err(e) = &ip_list {
+                let log = self.global_ctx.get_connect_attempt_log();
+                log.record_attempt::<(), _>(
+                    dst_peer_id,
+                    ConnectAttemptMethod::DirectConnect,
+                    "",
+                    &Err(format!("get ip list failed: {}", e)),
+                );
+                if self.peer_black_list.contains(&dst_peer_id) {
+                    log.record_blacklisted(
+                        dst_peer_id,
+                        ConnectAttemptMethod::DirectConnect,
+                        "",
+                        "peer does not serve the direct connector rpc",
+                        Duration::from_secs(udp_hole_punch::BLACKLIST_TIMEOUT_SEC),
+                    );
+                }
+            }
+            let ip_list =
+                ip_list.with_context(|| format!("get ip list from peer {}", dst_peer_id))?;
 
             tracing::info!(ip_list = ?ip_list, dst_peer_id = ?dst_peer_id, "got ip list");
 
@@ -843,7 +896,7 @@ mod tests {
         let remote_url: url::Url = "udp://[2001:db8::1]:11010".parse().unwrap();
         let takes_udp_ipv6_hole_punch_branch =
             matches_scheme!(remote_url, TunnelScheme::Ip(IpScheme::Udp))
-                && matches!(remote_url.host(), Some(url::Host::Ipv6(_)));
+                && matches!(remote_url.host(), Some(Host::Ipv6(_)));
 
         assert!(takes_udp_ipv6_hole_punch_branch);
     }
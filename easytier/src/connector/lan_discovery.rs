@@ -0,0 +1,576 @@
+// discover peers of the same network in the local network with udp multicast beacons,
+// and connect to them with the manual connector manager.
+//
+// beacons carry the peer id and listeners, authenticated with a hmac keyed by the
+// network secret. the secret itself or its digest is never sent.
+
+use std::{
+    collections::HashSet,
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::{Arc, Weak},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use dashmap::DashMap;
+use hmac::Mac;
+use prost::Message;
+use sha2::{Digest, Sha256};
+use tokio::{net::UdpSocket, task::JoinSet};
+
+use crate::{
+    common::{PeerId, error::Error, global_ctx::ArcGlobalCtx},
+    peers::peer_manager::PeerManager,
+    proto::{
+        api::instance::LanDiscoveredPeer,
+        peer_rpc::{LanDiscoveryBeacon, LanDiscoveryPacket},
+    },
+};
+
+use super::manual::ManualConnectorManager;
+
+pub const LAN_DISCOVERY_PORT: u16 = 11077;
+const LAN_DISCOVERY_MULTICAST_ADDR: Ipv4Addr = Ipv4Addr::new(239, 255, 110, 77);
+
+const BEACON_INTERVAL: Duration = Duration::from_secs(5);
+const PEER_EXPIRE: Duration = Duration::from_secs(30);
+const MAX_BEACON_CLOCK_SKEW_SEC: i64 = 120;
+const MAX_BEACON_SIZE: usize = 1400;
+const MAC_DOMAIN: &[u8] = b"easytier lan discovery";
+
+// rate limits, so a noisy lan can not make us create connectors in a tight loop
+const MAX_DISCOVERED_PEERS: usize = 64;
+const MAX_BEACONS_PER_SEC: u32 = 128;
+const MAX_NEW_CONNECTORS_PER_ROUND: usize = 2;
+const CONNECT_COOLDOWN: Duration = Duration::from_secs(30);
+
+fn now_unix() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+fn network_name_digest(network_name: &str) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(MAC_DOMAIN);
+    hasher.update(network_name.as_bytes());
+    hasher.finalize()[..16].to_vec()
+}
+
+fn beacon_mac(global_ctx: &ArcGlobalCtx, beacon: &[u8]) -> Option<hmac::Hmac<Sha256>> {
+    let mut challenge = MAC_DOMAIN.to_vec();
+    challenge.extend_from_slice(beacon);
+    global_ctx.get_secret_proof(&challenge)
+}
+
+/// Encode a signed beacon, returns None if the network has no secret to sign with.
+fn build_packet(
+    global_ctx: &ArcGlobalCtx,
+    peer_id: PeerId,
+    listeners: &[url::Url],
+) -> Option<Vec<u8>> {
+    let beacon = LanDiscoveryBeacon {
+        network_name_digest: network_name_digest(&global_ctx.get_network_name()),
+        peer_id,
+        listeners: listeners.iter().cloned().map(Into::into).collect(),
+        timestamp_unix: now_unix(),
+    }
+    .encode_to_vec();
+    let mac = beacon_mac(global_ctx, &beacon)?
+        .finalize()
+        .into_bytes()
+        .to_vec();
+    Some(LanDiscoveryPacket { beacon, mac }.encode_to_vec())
+}
+
+fn parse_packet(global_ctx: &ArcGlobalCtx, data: &[u8]) -> Option<LanDiscoveryBeacon> {
+    let packet = LanDiscoveryPacket::decode(data).ok()?;
+    let beacon = LanDiscoveryBeacon::decode(packet.beacon.as_slice()).ok()?;
+    if beacon.network_name_digest != network_name_digest(&global_ctx.get_network_name()) {
+        return None;
+    }
+    let mut challenge = MAC_DOMAIN.to_vec();
+    challenge.extend_from_slice(&packet.beacon);
+    // beacons signed with the staged or previous secret are fine during a secret rotation
+    if !global_ctx.verify_secret_proof(&challenge, &packet.mac) {
+        return None;
+    }
+    if (now_unix() - beacon.timestamp_unix).abs() > MAX_BEACON_CLOCK_SKEW_SEC {
+        tracing::debug!(?beacon, "lan discovery beacon is too old, ignore");
+        return None;
+    }
+    Some(beacon)
+}
+
+/// Listeners of a beacon usable from this node, best first. Unspecified hosts are
+/// replaced with the address the beacon came from.
+fn resolve_listeners(
+    listeners: &[url::Url],
+    source_ip: IpAddr,
+    default_protocol: &str,
+) -> Vec<url::Url> {
+    let mut ret: Vec<url::Url> = listeners
+        .iter()
+        .filter(|l| {
+            matches!(
+                l.scheme(),
+                "tcp" | "udp" | "quic" | "ws" | "wss" | "faketcp" | "obfs" | "h2" | "h2s" | "wt"
+            )
+        })
+        .filter(|l| l.port().is_some())
+        .filter_map(|l| {
+            let host_ip = match l.host()? {
+                url::Host::Ipv4(ip) => IpAddr::V4(ip),
+                url::Host::Ipv6(ip) => IpAddr::V6(ip),
+                url::Host::Domain(_) => return Some(l.clone()),
+            };
+            if host_ip.is_loopback() {
+                return None;
+            }
+            if !host_ip.is_unspecified() {
+                return Some(l.clone());
+            }
+            if host_ip.is_ipv4() != source_ip.is_ipv4() {
+                return None;
+            }
+            let mut l = l.clone();
+            l.set_ip_host(source_ip).ok()?;
+            Some(l)
+        })
+        .collect();
+
+    ret.sort_by_key(|l| {
+        if l.scheme() == default_protocol {
+            0
+        } else if l.scheme() == "tcp" {
+            1
+        } else if l.scheme() == "udp" {
+            2
+        } else {
+            3
+        }
+    });
+    ret
+}
+
+#[derive(Debug, Clone)]
+struct DiscoveredPeer {
+    source_addr: SocketAddr,
+    listeners: Vec<url::Url>,
+    last_seen: Instant,
+    last_seen_unix: i64,
+    connector_url: Option<url::Url>,
+    last_connect_at: Option<Instant>,
+}
+
+struct LanDiscoveryData {
+    global_ctx: ArcGlobalCtx,
+    peer_mgr: Arc<PeerManager>,
+    conn_manager: Weak<ManualConnectorManager>,
+    port: u16,
+    peers: DashMap<PeerId, DiscoveredPeer>,
+}
+
+impl LanDiscoveryData {
+    fn create_socket(&self) -> Result<UdpSocket, Error> {
+        let _g = self.global_ctx.net_ns.guard();
+        let socket = socket2::Socket::new(
+            socket2::Domain::IPV4,
+            socket2::Type::DGRAM,
+            Some(socket2::Protocol::UDP),
+        )?;
+        socket.set_reuse_address(true)?;
+        // several instances on one host all listen on the discovery port
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+        socket.set_reuse_port(true)?;
+        socket.set_broadcast(true)?;
+        socket.set_multicast_loop_v4(true)?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, self.port)).into())?;
+        socket.join_multicast_v4(&LAN_DISCOVERY_MULTICAST_ADDR, &Ipv4Addr::UNSPECIFIED)?;
+        socket.set_nonblocking(true)?;
+        Ok(UdpSocket::from_std(socket.into())?)
+    }
+
+    async fn send_beacon(&self, socket: &UdpSocket) {
+        let listeners = self.global_ctx.get_running_listeners();
+        let Some(packet) = build_packet(&self.global_ctx, self.peer_mgr.my_peer_id(), &listeners)
+        else {
+            return;
+        };
+        for dst in [LAN_DISCOVERY_MULTICAST_ADDR, Ipv4Addr::BROADCAST] {
+            if let Err(e) = socket
+                .send_to(&packet, SocketAddrV4::new(dst, self.port))
+                .await
+            {
+                tracing::debug!(?e, ?dst, "send lan discovery beacon failed");
+            }
+        }
+    }
+
+    fn handle_beacon(&self, beacon: LanDiscoveryBeacon, source_addr: SocketAddr) {
+        if beacon.peer_id == self.peer_mgr.my_peer_id() {
+            return;
+        }
+        if !self.peers.contains_key(&beacon.peer_id) && self.peers.len() >= MAX_DISCOVERED_PEERS {
+            tracing::debug!(?source_addr, "too many lan discovered peers, ignore beacon");
+            return;
+        }
+
+        let listeners: Vec<url::Url> = beacon.listeners.into_iter().map(Into::into).collect();
+        let listeners = resolve_listeners(
+            &listeners,
+            source_addr.ip(),
+            &self.global_ctx.get_flags().default_protocol,
+        );
+
+        let mut entry = self.peers.entry(beacon.peer_id).or_insert_with(|| {
+            tracing::info!(
+                peer_id = beacon.peer_id,
+                ?source_addr,
+                "lan discovery found peer"
+            );
+            DiscoveredPeer {
+                source_addr,
+                listeners: vec![],
+                last_seen: Instant::now(),
+                last_seen_unix: 0,
+                connector_url: None,
+                last_connect_at: None,
+            }
+        });
+        entry.source_addr = source_addr;
+        entry.listeners = listeners;
+        entry.last_seen = Instant::now();
+        entry.last_seen_unix = now_unix();
+    }
+
+    async fn recv_routine(self: Arc<Self>, socket: Arc<UdpSocket>) {
+        let mut buf = vec![0u8; MAX_BEACON_SIZE];
+        let mut window_start = Instant::now();
+        let mut beacons_in_window = 0;
+        loop {
+            let (len, source_addr) = match socket.recv_from(&mut buf).await {
+                Ok(ret) => ret,
+                Err(e) => {
+                    tracing::debug!(?e, "recv lan discovery beacon failed");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+
+            if window_start.elapsed() >= Duration::from_secs(1) {
+                window_start = Instant::now();
+                beacons_in_window = 0;
+            }
+            beacons_in_window += 1;
+            if beacons_in_window > MAX_BEACONS_PER_SEC {
+                continue;
+            }
+
+            if let Some(beacon) = parse_packet(&self.global_ctx, &buf[..len]) {
+                self.handle_beacon(beacon, source_addr);
+            }
+        }
+    }
+
+    async fn update_connectors(&self) {
+        let Some(conn_manager) = self.conn_manager.upgrade() else {
+            return;
+        };
+
+        let mut stale_urls = vec![];
+        self.peers.retain(|peer_id, peer| {
+            if peer.last_seen.elapsed() > PEER_EXPIRE {
+                tracing::info!(?peer_id, "lan discovered peer expired");
+                stale_urls.extend(peer.connector_url.take());
+                return false;
+            }
+            // the peer changed its listeners
+            if peer
+                .connector_url
+                .as_ref()
+                .is_some_and(|url| !peer.listeners.contains(url))
+            {
+                stale_urls.extend(peer.connector_url.take());
+            }
+            true
+        });
+        for url in stale_urls {
+            let _ = conn_manager.remove_connector(url).await;
+        }
+
+        let existing_urls: HashSet<url::Url> = conn_manager
+            .list_connectors()
+            .await
+            .into_iter()
+            .filter_map(|c| c.url.map(Into::into))
+            .collect();
+
+        // pick the peers first, the dashmap refs must not be held across awaits
+        let mut to_connect = vec![];
+        for mut peer in self.peers.iter_mut() {
+            if to_connect.len() >= MAX_NEW_CONNECTORS_PER_ROUND {
+                break;
+            }
+            let peer_id = *peer.key();
+            if peer.connector_url.is_some()
+                || peer
+                    .last_connect_at
+                    .is_some_and(|t| t.elapsed() < CONNECT_COOLDOWN)
+                || self.peer_mgr.has_directly_connected_conn(peer_id)
+            {
+                continue;
+            }
+            let Some(url) = peer.listeners.first().cloned() else {
+                continue;
+            };
+            peer.last_connect_at = Some(Instant::now());
+            // the user already connects to it, leave it alone
+            if existing_urls.contains(&url) {
+                continue;
+            }
+            peer.connector_url = Some(url.clone());
+            to_connect.push((peer_id, url));
+        }
+
+        for (peer_id, url) in to_connect {
+            tracing::info!(?peer_id, %url, "lan discovery add connector");
+            let _ = conn_manager.add_connector_by_url(url).await;
+        }
+    }
+
+    async fn beacon_routine(self: Arc<Self>, socket: Arc<UdpSocket>) {
+        let mut interval = tokio::time::interval(BEACON_INTERVAL);
+        loop {
+            interval.tick().await;
+            self.send_beacon(&socket).await;
+            self.update_connectors().await;
+        }
+    }
+}
+
+pub struct LanDiscovery {
+    data: Arc<LanDiscoveryData>,
+    tasks: std::sync::Mutex<JoinSet<()>>,
+}
+
+impl LanDiscovery {
+    pub fn new(
+        global_ctx: ArcGlobalCtx,
+        peer_mgr: Arc<PeerManager>,
+        conn_manager: Weak<ManualConnectorManager>,
+    ) -> Self {
+        Self::new_with_port(global_ctx, peer_mgr, conn_manager, LAN_DISCOVERY_PORT)
+    }
+
+    fn new_with_port(
+        global_ctx: ArcGlobalCtx,
+        peer_mgr: Arc<PeerManager>,
+        conn_manager: Weak<ManualConnectorManager>,
+        port: u16,
+    ) -> Self {
+        Self {
+            data: Arc::new(LanDiscoveryData {
+                global_ctx,
+                peer_mgr,
+                conn_manager,
+                port,
+                peers: DashMap::new(),
+            }),
+            tasks: std::sync::Mutex::new(JoinSet::new()),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.tasks.lock().unwrap().is_empty()
+    }
+
+    pub fn start(&self) -> Result<(), Error> {
+        if self.is_running() {
+            return Ok(());
+        }
+        if self
+            .data
+            .global_ctx
+            .get_network_identity()
+            .network_secret
+            .is_none()
+        {
+            return Err(anyhow::anyhow!("lan discovery requires a network secret").into());
+        }
+
+        let socket = Arc::new(self.data.create_socket()?);
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.spawn(self.data.clone().recv_routine(socket.clone()));
+        tasks.spawn(self.data.clone().beacon_routine(socket));
+        tracing::info!(port = self.data.port, "lan discovery started");
+        Ok(())
+    }
+
+    pub fn list_discovered_peers(&self) -> Vec<LanDiscoveredPeer> {
+        let mut peers: Vec<_> = self
+            .data
+            .peers
+            .iter()
+            .map(|peer| LanDiscoveredPeer {
+                peer_id: *peer.key(),
+                source_addr: peer.source_addr.to_string(),
+                listeners: peer.listeners.iter().cloned().map(Into::into).collect(),
+                last_seen_unix: peer.last_seen_unix,
+                connector_url: peer.connector_url.clone().map(Into::into),
+                directly_connected: self.data.peer_mgr.has_directly_connected_conn(*peer.key()),
+            })
+            .collect();
+        peers.sort_by_key(|p| p.peer_id);
+        peers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::{config::NetworkIdentity, global_ctx::tests::get_mock_global_ctx_with_network},
+        peers::{
+            create_packet_recv_chan, peer_manager::RouteAlgoType,
+            tests::wait_route_appear_with_cost,
+        },
+        tunnel::common::tests::wait_for_condition,
+    };
+
+    fn ctx(name: &str, secret: &str) -> ArcGlobalCtx {
+        get_mock_global_ctx_with_network(Some(NetworkIdentity::new(
+            name.to_string(),
+            secret.to_string(),
+        )))
+    }
+
+    #[test]
+    fn test_beacon_authentication() {
+        let listeners = vec!["tcp://0.0.0.0:11010".parse().unwrap()];
+        let sender = ctx("net1", "secret");
+        let packet = build_packet(&sender, 100, &listeners).unwrap();
+
+        let beacon = parse_packet(&ctx("net1", "secret"), &packet).unwrap();
+        assert_eq!(beacon.peer_id, 100);
+        assert_eq!(beacon.listeners.len(), 1);
+
+        assert!(parse_packet(&ctx("net1", "other secret"), &packet).is_none());
+        assert!(parse_packet(&ctx("net2", "secret"), &packet).is_none());
+
+        // tampered beacon
+        let mut packet = LanDiscoveryPacket::decode(packet.as_slice()).unwrap();
+        let mut beacon = LanDiscoveryBeacon::decode(packet.beacon.as_slice()).unwrap();
+        beacon.peer_id = 200;
+        packet.beacon = beacon.encode_to_vec();
+        assert!(parse_packet(&ctx("net1", "secret"), &packet.encode_to_vec()).is_none());
+
+        // the secret never appears in the packet
+        let packet = build_packet(&sender, 100, &listeners).unwrap();
+        assert!(!packet.windows(b"secret".len()).any(|w| w == b"secret"));
+
+        let credential_node = get_mock_global_ctx_with_network(Some(
+            NetworkIdentity::new_credential("net1".to_string()),
+        ));
+        assert!(build_packet(&credential_node, 100, &listeners).is_none());
+    }
+
+    #[test]
+    fn test_resolve_listeners() {
+        let listeners: Vec<url::Url> = [
+            "udp://0.0.0.0:11010",
+            "tcp://0.0.0.0:11010",
+            "tcp://[::]:11010",
+            "tcp://127.0.0.1:11011",
+            "ring://abc",
+            "wg://192.168.1.3:11011",
+            "quic://192.168.1.3:11012",
+        ]
+        .iter()
+        .map(|s| s.parse().unwrap())
+        .collect();
+        let resolved = resolve_listeners(&listeners, "192.168.1.2".parse().unwrap(), "udp");
+        let resolved: Vec<String> = resolved.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            resolved,
+            vec![
+                "udp://192.168.1.2:11010",
+                "tcp://192.168.1.2:11010",
+                "quic://192.168.1.3:11012",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn lan_discovery_connects_peers() {
+        let create = || async {
+            let (s, _r) = create_packet_recv_chan();
+            let peer_mgr = Arc::new(PeerManager::new(
+                RouteAlgoType::Ospf,
+                ctx("lan_discovery_test", "secret"),
+                s,
+            ));
+            peer_mgr.run().await.unwrap();
+            peer_mgr
+        };
+        let peer_mgr_a = create().await;
+        let peer_mgr_b = create().await;
+
+        let mut listener =
+            crate::tunnel::tcp::TcpTunnelListener::new("tcp://0.0.0.0:31077".parse().unwrap());
+        crate::tunnel::TunnelListener::listen(&mut listener)
+            .await
+            .unwrap();
+        peer_mgr_b
+            .get_global_ctx()
+            .add_running_listener("tcp://0.0.0.0:31077".parse().unwrap());
+        let peer_mgr_b_clone = peer_mgr_b.clone();
+        tokio::spawn(async move {
+            let tunnel = crate::tunnel::TunnelListener::accept(&mut listener)
+                .await
+                .unwrap();
+            peer_mgr_b_clone
+                .add_tunnel_as_server(tunnel, true)
+                .await
+                .unwrap();
+        });
+
+        let conn_manager = Arc::new(ManualConnectorManager::new(
+            peer_mgr_a.get_global_ctx(),
+            peer_mgr_a.clone(),
+        ));
+        let discovery_a = LanDiscovery::new_with_port(
+            peer_mgr_a.get_global_ctx(),
+            peer_mgr_a.clone(),
+            Arc::downgrade(&conn_manager),
+            31078,
+        );
+        let conn_manager_b = Arc::new(ManualConnectorManager::new(
+            peer_mgr_b.get_global_ctx(),
+            peer_mgr_b.clone(),
+        ));
+        let discovery_b = LanDiscovery::new_with_port(
+            peer_mgr_b.get_global_ctx(),
+            peer_mgr_b.clone(),
+            Arc::downgrade(&conn_manager_b),
+            31078,
+        );
+        discovery_a.start().unwrap();
+        discovery_b.start().unwrap();
+
+        wait_for_condition(
+            || async {
+                discovery_a
+                    .list_discovered_peers()
+                    .iter()
+                    .any(|p| p.peer_id == peer_mgr_b.my_peer_id())
+            },
+            Duration::from_secs(15),
+        )
+        .await;
+
+        wait_route_appear_with_cost(peer_mgr_a.clone(), peer_mgr_b.my_peer_id(), Some(1))
+            .await
+            .unwrap();
+    }
+}
@@ -14,7 +14,7 @@ use crate::{
     proto::{
         api::instance::{
             Connector, ConnectorManageRpc, ConnectorStatus, ListConnectorRequest,
-            ListConnectorResponse,
+            ListConnectorResponse, ListLanDiscoveredPeersRequest, ListLanDiscoveredPeersResponse,
         },
         rpc_types::{self, controller::BaseController},
     },
@@ -32,7 +32,7 @@ use crate::{
     use_global_var,
 };
 
-use super::create_connector_by_url;
+use super::{create_connector_by_url, lan_discovery::LanDiscovery};
 
 type ConnectorMap = Arc<DashSet<url::Url>>;
 
@@ -422,7 +422,7 @@ impl ManualConnectorManager {
 }
 
 #[derive(Clone)]
-pub struct ConnectorManagerRpcService(pub Weak<ManualConnectorManager>);
+pub struct ConnectorManagerRpcService(pub Weak<ManualConnectorManager>, pub Weak<LanDiscovery>);
 
 #[async_trait::async_trait]
 impl ConnectorManageRpc for ConnectorManagerRpcService {
@@ -438,6 +438,18 @@ impl ConnectorManageRpc for ConnectorManagerRpcService {
         ret.connectors = connectors;
         Ok(ret)
     }
+
+    async fn list_lan_discovered_peers(
+        &self,
+        _: BaseController,
+        _request: ListLanDiscoveredPeersRequest,
+    ) -> Result<ListLanDiscoveredPeersResponse, rpc_types::error::Error> {
+        let lan_discovery = weak_upgrade(&self.1)?;
+        Ok(ListLanDiscoveredPeersResponse {
+            enabled: lan_discovery.is_running(),
+            peers: lan_discovery.list_discovered_peers(),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -13,7 +13,9 @@ use crate::{
 use http_connector::HttpTunnelConnector;
 use rand::seq::SliceRandom;
 
+pub mod diagnostics;
 pub mod direct;
+pub mod lan_discovery;
 pub mod manual;
 pub mod tcp_hole_punch;
 pub mod udp_hole_punch;
@@ -266,6 +268,20 @@ pub async fn create_connector_by_url(
                 }
                 #[cfg(feature = "faketcp")]
                 IpScheme::FakeTcp => tunnel::fake_tcp::FakeTcpTunnelConnector::new(url).boxed(),
+                #[cfg(feature = "obfs")]
+                IpScheme::Obfs => {
+                    use crate::tunnel::obfs::{ObfsConfig, ObfsTunnelConnector};
+                    let nid = global_ctx.get_network_identity();
+                    let obfs_config = ObfsConfig::new_from_network_identity(
+                        &nid.network_name,
+                        &nid.network_secret.unwrap_or_default(),
+                    );
+                    ObfsTunnelConnector::new(url, obfs_config).boxed()
+                }
+                #[cfg(feature = "http2")]
+                IpScheme::H2 | IpScheme::H2s => tunnel::http2::H2TunnelConnector::new(url).boxed(),
+                #[cfg(feature = "webtransport")]
+                IpScheme::Wt => tunnel::webtransport::WtTunnelConnector::new(url).boxed(),
             };
             connector.set_resolved_addr(resolved_addr.addr);
             connector.set_socket_mark(global_ctx.config.get_flags().socket_mark);
@@ -16,6 +16,7 @@ use crate::{
         peer_task::{PeerTaskLauncher, PeerTaskManager},
     },
     proto::{
+        api::instance::ConnectAttemptMethod,
         common::NatType,
         peer_rpc::{
             TcpHolePunchRequest, TcpHolePunchResponse, TcpHolePunchRpc,
@@ -241,9 +242,33 @@ impl TcpHolePunchConnectorData {
     async fn punch_as_initiator(self: Arc<Self>, dst_peer_id: PeerId) -> Result<(), Error> {
         let mut backoff = BackOff::new(vec![1000, 1000, 4000, 8000]);
 
+        let log = self
+            .peer_mgr
+            .get_global_ctx()
+            .get_connect_attempt_log()
+            .clone();
+
         loop {
-            backoff.sleep_for_next_backoff().await;
-            if self.do_punch_as_initiator(dst_peer_id).await.is_ok() {
+            let sleep_ms = backoff.next_backoff();
+            log.record_backoff(
+                dst_peer_id,
+                ConnectAttemptMethod::TcpHolePunch,
+                "",
+                Duration::from_millis(sleep_ms),
+            );
+            tokio::time::sleep(Duration::from_millis(sleep_ms)).await;
+
+            let ret = self.do_punch_as_initiator(dst_peer_id).await;
+            // ok without a direct conn means the punch is skipped, e.g. symmetric tcp nat
+            if ret.is_err() || self.peer_mgr.has_directly_connected_conn(dst_peer_id) {
+                log.record_attempt(
+                    dst_peer_id,
+                    ConnectAttemptMethod::TcpHolePunch,
+                    "",
+                    &ret.as_ref().map_err(|e| format!("{:#}", e)),
+                );
+            }
+            if ret.is_ok() {
                 break;
             }
 
@@ -252,6 +277,13 @@ impl TcpHolePunchConnectorData {
                     dst_peer_id,
                     "tcp hole punch initiator skipped (blacklisted)"
                 );
+                log.record_blacklisted(
+                    dst_peer_id,
+                    ConnectAttemptMethod::TcpHolePunch,
+                    "",
+                    "peer does not support tcp hole punching",
+                    Duration::from_secs(BLACKLIST_TIMEOUT_SEC),
+                );
                 break;
             }
         }
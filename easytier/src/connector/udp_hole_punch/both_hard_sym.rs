@@ -0,0 +1,464 @@
+use std::{
+    net::Ipv4Addr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Mutex;
+use tokio_util::task::AbortOnDropHandle;
+
+use crate::{
+    common::{
+        PeerId,
+        global_ctx::ArcGlobalCtx,
+        stats_manager::{CounterHandle, LabelSet, LabelType, MetricName},
+        stun::StunInfoCollectorTrait,
+    },
+    connector::udp_hole_punch::{
+        BLACKLIST_TIMEOUT_SEC,
+        common::{HOLE_PUNCH_PACKET_BODY_LEN, UdpHolePunchListener, try_connect_with_socket},
+        handle_rpc_result,
+    },
+    peers::peer_manager::PeerManager,
+    proto::{
+        peer_rpc::{
+            SendPunchPacketBothHardSymRequest, SendPunchPacketBothHardSymResponse,
+            UdpHolePunchRpcClientFactory,
+        },
+        rpc_types::{self, controller::BaseController},
+    },
+    tunnel::{Tunnel, udp::new_hole_punch_packet},
+};
+
+use super::common::{PunchHoleServerCommon, UdpSocketArray};
+
+// both sides bind this many sockets, each of them gets a random public port from the nat.
+const UDP_ARRAY_SIZE_FOR_BOTH_HARD_SYM: usize = 64;
+// upper bound of the socket count a remote can ask us to bind
+const MAX_UDP_ARRAY_SIZE_FOR_BOTH_HARD_SYM: usize = 128;
+// max probes sent by one side in one round
+const MAX_PROBES_FOR_BOTH_HARD_SYM: u32 = 4096;
+// every socket sends one probe per burst, so 64 sockets send ~1000 probes per second
+const PROBE_BURST_INTERVAL_MS: u64 = 64;
+const REMOTE_WAIT_TIME_MS: u64 = 5000;
+const MAX_REMOTE_WAIT_TIME_MS: u32 = 8000;
+
+fn public_ipv4s(global_ctx: &ArcGlobalCtx) -> Vec<Ipv4Addr> {
+    global_ctx
+        .get_stun_info_collector()
+        .get_stun_info()
+        .public_ip
+        .iter()
+        .filter_map(|x| x.parse().ok())
+        .collect()
+}
+
+#[derive(Clone)]
+pub(crate) struct BothHardSymStats {
+    attempts: CounterHandle,
+    success: CounterHandle,
+    probes_sent: CounterHandle,
+}
+
+impl BothHardSymStats {
+    fn new(global_ctx: &ArcGlobalCtx) -> Self {
+        let label_set =
+            LabelSet::new().with_label_type(LabelType::NetworkName(global_ctx.get_network_name()));
+        let stats_manager = global_ctx.stats_manager();
+
+        Self {
+            attempts: stats_manager.get_counter(
+                MetricName::UdpHolePunchBothHardSymAttempts,
+                label_set.clone(),
+            ),
+            success: stats_manager.get_counter(
+                MetricName::UdpHolePunchBothHardSymSuccess,
+                label_set.clone(),
+            ),
+            probes_sent: stats_manager
+                .get_counter(MetricName::UdpHolePunchBothHardSymProbesSent, label_set),
+        }
+    }
+}
+
+pub(crate) struct PunchBothHardSymHoleServer {
+    common: Arc<PunchHoleServerCommon>,
+    task: Mutex<Option<AbortOnDropHandle<()>>>,
+    stats: BothHardSymStats,
+}
+
+impl PunchBothHardSymHoleServer {
+    pub(crate) fn new(common: Arc<PunchHoleServerCommon>) -> Self {
+        let stats = BothHardSymStats::new(&common.get_global_ctx());
+        Self {
+            common,
+            task: Mutex::new(None),
+            stats,
+        }
+    }
+
+    // both sides spray probes from many sockets to random ports of each other, a tunnel
+    // can be built once a probe hits a mapping the other side has just opened.
+    #[tracing::instrument(skip(self), ret, err)]
+    pub(crate) async fn send_punch_packet_both_hard_sym(
+        &self,
+        request: SendPunchPacketBothHardSymRequest,
+    ) -> Result<SendPunchPacketBothHardSymResponse, rpc_types::error::Error> {
+        tracing::info!("send_punch_packet_both_hard_sym start");
+        let global_ctx = self.common.get_global_ctx();
+        if global_ctx.get_flags().disable_sym_hole_punching {
+            return Ok(SendPunchPacketBothHardSymResponse {
+                is_disabled: true,
+                ..Default::default()
+            });
+        }
+
+        let busy_resp = Ok(SendPunchPacketBothHardSymResponse {
+            is_busy: true,
+            ..Default::default()
+        });
+        let Ok(mut locked_task) = self.task.try_lock() else {
+            return busy_resp;
+        };
+        if locked_task.is_some() && !locked_task.as_ref().unwrap().is_finished() {
+            return busy_resp;
+        }
+
+        let remote_public_ips = request
+            .public_ips
+            .into_iter()
+            .map(Ipv4Addr::from)
+            .collect::<Vec<_>>();
+        if remote_public_ips.is_empty() {
+            return Err(
+                anyhow::anyhow!("send_punch_packet_both_hard_sym got zero len public ip").into(),
+            );
+        }
+        let my_public_ips = public_ipv4s(&global_ctx);
+        if my_public_ips.is_empty() {
+            return Err(anyhow::anyhow!("failed to get public ips").into());
+        }
+
+        let socket_count =
+            (request.udp_socket_count as usize).clamp(1, MAX_UDP_ARRAY_SIZE_FOR_BOTH_HARD_SYM);
+        let max_probe_count = request.max_probe_count.min(MAX_PROBES_FOR_BOTH_HARD_SYM) as usize;
+        let wait_time_ms = request.wait_time_ms.min(MAX_REMOTE_WAIT_TIME_MS);
+        let transaction_id = request.transaction_id;
+
+        let udp_array = UdpSocketArray::new(socket_count, global_ctx.net_ns.clone());
+        udp_array.start().await?;
+        udp_array.add_intreast_tid(transaction_id);
+
+        let peer_mgr = self.common.get_peer_mgr();
+        let common = self.common.clone();
+        let stats = self.stats.clone();
+        let punch_packet =
+            new_hole_punch_packet(transaction_id, HOLE_PUNCH_PACKET_BODY_LEN).into_bytes();
+
+        let task = tokio::spawn(async move {
+            let mut listeners = Vec::new();
+            let mut punched = vec![];
+            let mut probes_sent = 0;
+            let start_time = Instant::now();
+            'outer: while start_time.elapsed() < Duration::from_millis(wait_time_ms as u64) {
+                if probes_sent < max_probe_count {
+                    match udp_array
+                        .send_to_random_ports(&punch_packet, &remote_public_ips)
+                        .await
+                    {
+                        Ok(n) => probes_sent += n,
+                        Err(e) => {
+                            tracing::error!(?e, "failed to send hole punch packet");
+                            break;
+                        }
+                    }
+                }
+
+                tokio::time::sleep(Duration::from_millis(PROBE_BURST_INTERVAL_MS)).await;
+
+                while let Some(s) = udp_array.try_fetch_punched_socket(transaction_id) {
+                    tracing::info!(?s, ?transaction_id, "got punched socket in both hard sym");
+                    let Some(port) = s.socket.local_addr().ok().map(|addr| addr.port()) else {
+                        tracing::warn!("failed to get local addr from punched socket");
+                        continue;
+                    };
+                    let remote_addr = s.remote_addr;
+                    drop(s);
+
+                    // rebind the punched port as a listener so the client can connect to it
+                    let listener =
+                        match UdpHolePunchListener::new_ext(peer_mgr.clone(), false, Some(port))
+                            .await
+                        {
+                            Ok(l) => l,
+                            Err(e) => {
+                                tracing::warn!(?e, "failed to create listener");
+                                continue;
+                            }
+                        };
+                    punched.push((listener.get_socket().await, remote_addr));
+                    listeners.push(listener);
+                }
+
+                for (socket, remote_addr) in &punched {
+                    let send_remote_ret = socket.send_to(&punch_packet, remote_addr).await;
+                    tracing::debug!(
+                        ?send_remote_ret,
+                        ?socket,
+                        "send hole punch packet to punched remote"
+                    );
+                }
+
+                for l in &listeners {
+                    if l.get_conn_count().await > 0 {
+                        tracing::info!(?l, "got punched listener");
+                        break 'outer;
+                    }
+                }
+            }
+
+            stats.probes_sent.add(probes_sent as u64);
+            for l in listeners {
+                if l.get_conn_count().await > 0 {
+                    common.add_listener(l).await;
+                }
+            }
+        });
+
+        *locked_task = Some(AbortOnDropHandle::new(task));
+        Ok(SendPunchPacketBothHardSymResponse {
+            is_busy: false,
+            is_disabled: false,
+            public_ips: my_public_ips.into_iter().map(Into::into).collect(),
+        })
+    }
+}
+
+pub(crate) struct PunchBothHardSymHoleClient {
+    peer_mgr: Arc<PeerManager>,
+    blacklist: Arc<timedmap::TimedMap<PeerId, ()>>,
+    stats: BothHardSymStats,
+}
+
+impl std::fmt::Debug for PunchBothHardSymHoleClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PunchBothHardSymHoleClient")
+            .field("my_peer_id", &self.peer_mgr.my_peer_id())
+            .finish()
+    }
+}
+
+impl PunchBothHardSymHoleClient {
+    pub(crate) fn new(
+        peer_mgr: Arc<PeerManager>,
+        blacklist: Arc<timedmap::TimedMap<PeerId, ()>>,
+    ) -> Self {
+        let stats = BothHardSymStats::new(&peer_mgr.get_global_ctx());
+        Self {
+            peer_mgr,
+            blacklist,
+            stats,
+        }
+    }
+
+    #[tracing::instrument(ret)]
+    pub(crate) async fn do_hole_punching(
+        &self,
+        dst_peer_id: PeerId,
+        is_busy: &mut bool,
+    ) -> Result<Option<Box<dyn Tunnel>>, anyhow::Error> {
+        // Check if peer is blacklisted
+        if self.blacklist.contains(&dst_peer_id) {
+            tracing::debug!(?dst_peer_id, "peer is blacklisted, skipping hole punching");
+            return Ok(None);
+        }
+
+        *is_busy = false;
+
+        let global_ctx = self.peer_mgr.get_global_ctx();
+        let my_public_ips = public_ipv4s(&global_ctx);
+        if my_public_ips.is_empty() {
+            anyhow::bail!("failed to get public ips");
+        }
+
+        let udp_array =
+            UdpSocketArray::new(UDP_ARRAY_SIZE_FOR_BOTH_HARD_SYM, global_ctx.net_ns.clone());
+        udp_array.start().await?;
+
+        let rpc_stub = self
+            .peer_mgr
+            .get_peer_rpc_mgr()
+            .rpc_client()
+            .scoped_client::<UdpHolePunchRpcClientFactory<BaseController>>(
+                self.peer_mgr.my_peer_id(),
+                dst_peer_id,
+                global_ctx.get_network_name(),
+            );
+
+        let tid = rand::random();
+        udp_array.add_intreast_tid(tid);
+        let packet = new_hole_punch_packet(tid, HOLE_PUNCH_PACKET_BODY_LEN).into_bytes();
+
+        let remote_ret = rpc_stub
+            .send_punch_packet_both_hard_sym(
+                BaseController {
+                    timeout_ms: 2000,
+                    ..Default::default()
+                },
+                SendPunchPacketBothHardSymRequest {
+                    udp_socket_count: UDP_ARRAY_SIZE_FOR_BOTH_HARD_SYM as u32,
+                    public_ips: my_public_ips.into_iter().map(Into::into).collect(),
+                    transaction_id: tid,
+                    max_probe_count: MAX_PROBES_FOR_BOTH_HARD_SYM,
+                    wait_time_ms: REMOTE_WAIT_TIME_MS as u32,
+                },
+            )
+            .await;
+
+        let remote_ret = handle_rpc_result(remote_ret, dst_peer_id, &self.blacklist)?;
+
+        if remote_ret.is_disabled {
+            tracing::info!(?dst_peer_id, "remote disabled sym hole punching");
+            self.blacklist
+                .insert(dst_peer_id, (), Duration::from_secs(BLACKLIST_TIMEOUT_SEC));
+            return Ok(None);
+        }
+
+        if remote_ret.is_busy {
+            *is_busy = true;
+            anyhow::bail!("remote is busy");
+        }
+
+        let remote_public_ips = remote_ret
+            .public_ips
+            .into_iter()
+            .map(Ipv4Addr::from)
+            .collect::<Vec<_>>();
+        if remote_public_ips.is_empty() {
+            anyhow::bail!("remote public ips is required");
+        }
+
+        self.stats.attempts.inc();
+        tracing::debug!(
+            ?remote_public_ips,
+            "start send hole punch packet for both hard sym"
+        );
+
+        let mut probes_sent = 0;
+        let now = Instant::now();
+        let ret = 'outer: {
+            while now.elapsed() < Duration::from_millis(REMOTE_WAIT_TIME_MS + 1000) {
+                if probes_sent < MAX_PROBES_FOR_BOTH_HARD_SYM as usize {
+                    probes_sent += udp_array
+                        .send_to_random_ports(&packet, &remote_public_ips)
+                        .await?;
+                }
+
+                tokio::time::sleep(Duration::from_millis(PROBE_BURST_INTERVAL_MS)).await;
+
+                let Some(socket) = udp_array.try_fetch_punched_socket(tid) else {
+                    continue;
+                };
+                let remote_addr = socket.remote_addr;
+                tracing::info!(?socket, ?tid, "got punched socket in both hard sym");
+
+                // make sure the remote socket is punched too, so it starts listening
+                for _ in 0..3 {
+                    socket.socket.send_to(&packet, remote_addr).await?;
+                }
+                tokio::time::sleep(Duration::from_millis(PROBE_BURST_INTERVAL_MS * 2)).await;
+
+                for _ in 0..2 {
+                    match try_connect_with_socket(
+                        global_ctx.clone(),
+                        socket.socket.clone(),
+                        remote_addr,
+                    )
+                    .await
+                    {
+                        Ok(tunnel) => {
+                            self.stats.success.inc();
+                            break 'outer Some(tunnel);
+                        }
+                        Err(e) => {
+                            tracing::error!(?e, "failed to connect with socket");
+                            continue;
+                        }
+                    }
+                }
+                udp_array.add_new_socket(socket.socket).await?;
+            }
+            None
+        };
+
+        self.stats.probes_sent.add(probes_sent as u64);
+        Ok(ret)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::time::Duration;
+
+    use crate::{
+        common::stats_manager::MetricName,
+        connector::udp_hole_punch::{
+            RUN_TESTING, UdpHolePunchConnector, tests::create_mock_peer_manager_with_mock_stun,
+        },
+        peers::tests::{connect_peer_manager, wait_route_appear, wait_route_appear_with_cost},
+        proto::common::NatType,
+        tunnel::common::tests::wait_for_condition,
+    };
+
+    #[tokio::test]
+    #[serial_test::serial(hole_punch)]
+    async fn hole_punching_both_hard_sym() {
+        RUN_TESTING.store(true, std::sync::atomic::Ordering::Relaxed);
+
+        let p_a = create_mock_peer_manager_with_mock_stun(NatType::Symmetric).await;
+        let p_b = create_mock_peer_manager_with_mock_stun(NatType::PortRestricted).await;
+        let p_c = create_mock_peer_manager_with_mock_stun(NatType::Symmetric).await;
+        connect_peer_manager(p_a.clone(), p_b.clone()).await;
+        connect_peer_manager(p_b.clone(), p_c.clone()).await;
+        wait_route_appear(p_a.clone(), p_c.clone()).await.unwrap();
+
+        let mut hole_punching_a = UdpHolePunchConnector::new(p_a.clone());
+        let mut hole_punching_c = UdpHolePunchConnector::new(p_c.clone());
+
+        hole_punching_a.run().await.unwrap();
+        hole_punching_c.run().await.unwrap();
+
+        // the node with smaller peer id starts the punching
+        hole_punching_a.client.run_immediately().await;
+        hole_punching_c.client.run_immediately().await;
+
+        // without nat every probe hitting one of the 64 sockets of the other side punches
+        wait_for_condition(
+            || async {
+                wait_route_appear_with_cost(p_a.clone(), p_c.my_peer_id(), Some(1))
+                    .await
+                    .is_ok()
+            },
+            Duration::from_secs(60),
+        )
+        .await;
+
+        let initiator = if p_a.my_peer_id() < p_c.my_peer_id() {
+            p_a.clone()
+        } else {
+            p_c.clone()
+        };
+        let metric_value = |name: MetricName| {
+            initiator
+                .get_global_ctx()
+                .stats_manager()
+                .get_metrics_by_prefix(&name.to_string())
+                .iter()
+                .map(|m| m.value)
+                .sum::<u64>()
+        };
+        assert!(metric_value(MetricName::UdpHolePunchBothHardSymAttempts) > 0);
+        assert!(metric_value(MetricName::UdpHolePunchBothHardSymSuccess) > 0);
+        assert!(metric_value(MetricName::UdpHolePunchBothHardSymProbesSent) > 0);
+    }
+}
@@ -7,7 +7,7 @@ use std::{
 use crossbeam::atomic::AtomicCell;
 use dashmap::{DashMap, DashSet};
 use guarden::defer;
-use rand::seq::SliceRandom as _;
+use rand::{Rng as _, seq::SliceRandom as _};
 use tokio::{net::UdpSocket, sync::Mutex, task::JoinSet};
 use tracing::{Instrument, Level, instrument};
 use zerocopy::FromBytes as _;
@@ -40,6 +40,7 @@ pub(crate) enum UdpPunchClientMethod {
     ConeToCone,
     SymToCone,
     EasySymToEasySym,
+    HardSymToHardSym,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -165,7 +166,9 @@ impl UdpNatType {
                 return UdpPunchClientMethod::SymToCone;
             }
         } else if self.is_hard_sym() {
-            if other.is_sym() {
+            if other.is_hard_sym() {
+                return UdpPunchClientMethod::HardSymToHardSym;
+            } else if other.is_sym() {
                 return UdpPunchClientMethod::None;
             } else {
                 return UdpPunchClientMethod::SymToCone;
@@ -185,7 +188,9 @@ impl UdpNatType {
         match self.get_punch_hole_method(other, global_ctx) {
             UdpPunchClientMethod::None => false,
             UdpPunchClientMethod::ConeToCone | UdpPunchClientMethod::SymToCone => true,
-            UdpPunchClientMethod::EasySymToEasySym => my_peer_id < dst_peer_id,
+            UdpPunchClientMethod::EasySymToEasySym | UdpPunchClientMethod::HardSymToHardSym => {
+                my_peer_id < dst_peer_id
+            }
         }
     }
 }
@@ -318,6 +323,34 @@ impl UdpSocketArray {
         Ok(())
     }
 
+    /// Send one packet from every socket to a random port of each public ip, returns
+    /// the number of packets sent.
+    pub async fn send_to_random_ports(
+        &self,
+        data: &[u8],
+        public_ips: &[Ipv4Addr],
+    ) -> Result<usize, anyhow::Error> {
+        let sockets = self
+            .sockets
+            .iter()
+            .map(|s| s.value().clone())
+            .collect::<Vec<_>>();
+
+        let mut sent = 0;
+        for socket in sockets.iter() {
+            for ip in public_ips {
+                // nat rarely allocates well-known ports
+                let port = rand::thread_rng().gen_range(1024..=u16::MAX);
+                socket
+                    .send_to(data, SocketAddr::V4(SocketAddrV4::new(*ip, port)))
+                    .await?;
+                sent += 1;
+            }
+        }
+
+        Ok(sent)
+    }
+
     #[instrument(ret(level = Level::DEBUG))]
     pub fn try_fetch_punched_socket(&self, tid: u32) -> Option<PunchedUdpSocket> {
         tracing::debug!(?tid, "try fetch punched socket");
@@ -773,9 +806,12 @@ mod tests {
 
     use crate::common::global_ctx::tests::get_mock_global_ctx;
 
+    use crate::proto::common::NatType;
+
     use super::{
-        MAX_PUBLIC_UDP_HOLE_PUNCH_LISTENERS, easytier_managed_local_addr_error,
-        should_create_public_listener, should_retry_public_listener_selection,
+        MAX_PUBLIC_UDP_HOLE_PUNCH_LISTENERS, UdpNatType, UdpPunchClientMethod,
+        easytier_managed_local_addr_error, should_create_public_listener,
+        should_retry_public_listener_selection,
     };
 
     #[tokio::test]
@@ -846,4 +882,31 @@ mod tests {
             false, 1, true, true
         ));
     }
+
+    #[tokio::test]
+    async fn punch_method_for_both_hard_sym() {
+        let global_ctx = get_mock_global_ctx();
+        let hard_sym = UdpNatType::from(NatType::Symmetric);
+        let easy_sym = UdpNatType::from(NatType::SymmetricEasyInc);
+
+        assert!(matches!(
+            hard_sym.get_punch_hole_method(hard_sym, global_ctx.clone()),
+            UdpPunchClientMethod::HardSymToHardSym
+        ));
+        assert!(matches!(
+            hard_sym.get_punch_hole_method(easy_sym, global_ctx.clone()),
+            UdpPunchClientMethod::None
+        ));
+        // only one side initiates
+        assert!(hard_sym.can_punch_hole_as_client(hard_sym, 1, 2, global_ctx.clone()));
+        assert!(!hard_sym.can_punch_hole_as_client(hard_sym, 2, 1, global_ctx.clone()));
+
+        let mut flags = global_ctx.get_flags();
+        flags.disable_sym_hole_punching = true;
+        global_ctx.set_flags(flags);
+        assert!(matches!(
+            hard_sym.get_punch_hole_method(hard_sym, global_ctx),
+            UdpPunchClientMethod::None
+        ));
+    }
 }
@@ -5,6 +5,7 @@ use std::{
 
 use anyhow::{Context, Error};
 use both_easy_sym::{PunchBothEasySymHoleClient, PunchBothEasySymHoleServer};
+use both_hard_sym::{PunchBothHardSymHoleClient, PunchBothHardSymHoleServer};
 use common::{PunchHoleServerCommon, UdpNatType, UdpPunchClientMethod};
 use cone::{PunchConeHoleClient, PunchConeHoleServer};
 use dashmap::DashMap;
@@ -19,10 +20,12 @@ use crate::{
         peer_task::{PeerTaskLauncher, PeerTaskManager},
     },
     proto::{
+        api::instance::ConnectAttemptMethod,
         common::{NatType, Void},
         peer_rpc::{
             SelectPunchListenerRequest, SelectPunchListenerResponse,
             SendPunchPacketBothEasySymRequest, SendPunchPacketBothEasySymResponse,
+            SendPunchPacketBothHardSymRequest, SendPunchPacketBothHardSymResponse,
             SendPunchPacketConeRequest, SendPunchPacketEasySymRequest,
             SendPunchPacketHardSymRequest, SendPunchPacketHardSymResponse, UdpHolePunchRpc,
             UdpHolePunchRpcServer,
@@ -35,6 +38,7 @@ use crate::{
 use crate::connector::{should_background_p2p_with_peer, should_try_p2p_with_peer};
 
 pub(crate) mod both_easy_sym;
+pub(crate) mod both_hard_sym;
 pub(crate) mod common;
 pub(crate) mod cone;
 pub(crate) mod sym_to_cone;
@@ -46,6 +50,10 @@ pub static RUN_TESTING: Lazy<AtomicBool> = Lazy::new(|| AtomicBool::new(false));
 // Blacklist timeout in seconds
 pub const BLACKLIST_TIMEOUT_SEC: u64 = 3600;
 
+// both hard sym punching is expensive, give up for a while after these rounds failed
+const MAX_BOTH_HARD_SYM_ROUNDS: u32 = 6;
+const BOTH_HARD_SYM_GIVE_UP_SEC: u64 = 1800;
+
 fn get_sym_punch_lock(peer_id: PeerId) -> Arc<Mutex<()>> {
     SYM_PUNCH_LOCK
         .entry(peer_id)
@@ -59,6 +67,7 @@ struct UdpHolePunchServer {
     cone_server: PunchConeHoleServer,
     sym_to_cone_server: PunchSymToConeHoleServer,
     both_easy_sym_server: PunchBothEasySymHoleServer,
+    both_hard_sym_server: PunchBothHardSymHoleServer,
 }
 
 impl UdpHolePunchServer {
@@ -67,12 +76,14 @@ impl UdpHolePunchServer {
         let cone_server = PunchConeHoleServer::new(common.clone());
         let sym_to_cone_server = PunchSymToConeHoleServer::new(common.clone());
         let both_easy_sym_server = PunchBothEasySymHoleServer::new(common.clone());
+        let both_hard_sym_server = PunchBothHardSymHoleServer::new(common.clone());
 
         Arc::new(Self {
             common,
             cone_server,
             sym_to_cone_server,
             both_easy_sym_server,
+            both_hard_sym_server,
         })
     }
 }
@@ -147,6 +158,20 @@ impl UdpHolePunchRpc for UdpHolePunchServer {
             .send_punch_packet_both_easy_sym(input)
             .await
     }
+
+    /// nat4 to nat4 (both randomly)
+    async fn send_punch_packet_both_hard_sym(
+        &self,
+        _ctrl: Self::Controller,
+        input: SendPunchPacketBothHardSymRequest,
+    ) -> rpc_types::error::Result<SendPunchPacketBothHardSymResponse> {
+        let _locked = get_sym_punch_lock(self.common.get_peer_mgr().my_peer_id())
+            .try_lock_owned()
+            .with_context(|| "sym punch lock is busy")?;
+        self.both_hard_sym_server
+            .send_punch_packet_both_hard_sym(input)
+            .await
+    }
 }
 
 #[derive(Debug)]
@@ -201,6 +226,7 @@ struct UdpHoePunchConnectorData {
     cone_client: PunchConeHoleClient,
     sym_to_cone_client: PunchSymToConeHoleClient,
     both_easy_sym_client: PunchBothEasySymHoleClient,
+    both_hard_sym_client: PunchBothHardSymHoleClient,
     peer_mgr: Arc<PeerManager>,
     blacklist: Arc<timedmap::TimedMap<PeerId, ()>>,
 }
@@ -212,19 +238,64 @@ impl UdpHoePunchConnectorData {
         let sym_to_cone_client = PunchSymToConeHoleClient::new(peer_mgr.clone(), blacklist.clone());
         let both_easy_sym_client =
             PunchBothEasySymHoleClient::new(peer_mgr.clone(), blacklist.clone());
+        let both_hard_sym_client =
+            PunchBothHardSymHoleClient::new(peer_mgr.clone(), blacklist.clone());
 
         Arc::new(Self {
             cone_client,
             sym_to_cone_client,
             both_easy_sym_client,
+            both_hard_sym_client,
             peer_mgr,
             blacklist,
         })
     }
 
+    async fn sleep_for_next_backoff(
+        &self,
+        dst_peer_id: PeerId,
+        strategy: &str,
+        backoff: &mut BackOff,
+    ) {
+        let sleep_ms = backoff.next_backoff();
+        if sleep_ms > 0 {
+            self.peer_mgr
+                .get_global_ctx()
+                .get_connect_attempt_log()
+                .record_backoff(
+                    dst_peer_id,
+                    ConnectAttemptMethod::UdpHolePunch,
+                    strategy,
+                    Duration::from_millis(sleep_ms),
+                );
+            tokio::time::sleep(Duration::from_millis(sleep_ms)).await;
+        }
+    }
+
+    fn record_punch_result(&self, dst_peer_id: PeerId, strategy: &str, ret: Result<(), String>) {
+        let log = self.peer_mgr.get_global_ctx().get_connect_attempt_log();
+        log.record_attempt(
+            dst_peer_id,
+            ConnectAttemptMethod::UdpHolePunch,
+            strategy,
+            &ret,
+        );
+        if ret.is_err() && self.blacklist.contains(&dst_peer_id) {
+            log.record_blacklisted(
+                dst_peer_id,
+                ConnectAttemptMethod::UdpHolePunch,
+                "",
+                "peer does not support or disabled hole punching",
+                Duration::from_secs(BLACKLIST_TIMEOUT_SEC),
+            );
+        }
+    }
+
     #[tracing::instrument(skip(self))]
     async fn handle_punch_result(
         &self,
+        dst_peer_id: PeerId,
+        strategy: &str,
         ret: Result<Option<Box<dyn Tunnel>>, Error>,
         backoff: Option<&mut BackOff>,
         round: Option<&mut u32>,
@@ -248,19 +319,31 @@ impl UdpHoePunchConnectorData {
 
                 if let Err(e) = self.peer_mgr.add_client_tunnel(tunnel, false).await {
                     tracing::warn!("add client tunnel failed, err: {}", e);
+                    self.record_punch_result(
+                        dst_peer_id,
+                        strategy,
+                        Err(format!("add client tunnel failed: {}", e)),
+                    );
                     op(true);
                     false
                 } else {
+                    self.record_punch_result(dst_peer_id, strategy, Ok(()));
                     true
                 }
             }
             Ok(None) => {
                 tracing::info!("hole punching failed, no punch tunnel");
+                self.record_punch_result(
+                    dst_peer_id,
+                    strategy,
+                    Err("no punched tunnel".to_string()),
+                );
                 op(false);
                 false
             }
             Err(e) => {
                 tracing::info!("hole punching failed, err: {}", e);
+                self.record_punch_result(dst_peer_id, strategy, Err(format!("{:#}", e)));
                 op(true);
                 false
             }
@@ -272,7 +355,8 @@ impl UdpHoePunchConnectorData {
         let mut backoff = BackOff::new(vec![1000, 1000, 2000, 4000, 4000, 8000, 8000, 16000]);
 
         loop {
-            backoff.sleep_for_next_backoff().await;
+            self.sleep_for_next_backoff(task_info.dst_peer_id, "cone", &mut backoff)
+                .await;
 
             let ret = self
                 .cone_client
@@ -280,7 +364,7 @@ impl UdpHoePunchConnectorData {
                 .await;
 
             if self
-                .handle_punch_result(ret, Some(&mut backoff), None)
+                .handle_punch_result(task_info.dst_peer_id, "cone", ret, Some(&mut backoff), None)
                 .await
             {
                 break;
@@ -298,7 +382,8 @@ impl UdpHoePunchConnectorData {
         let mut port_idx = rand::random();
 
         loop {
-            backoff.sleep_for_next_backoff().await;
+            self.sleep_for_next_backoff(task_info.dst_peer_id, "sym_to_cone", &mut backoff)
+                .await;
 
             // always try cone first
             if !RUN_TESTING.load(std::sync::atomic::Ordering::Relaxed) {
@@ -306,7 +391,10 @@ impl UdpHoePunchConnectorData {
                     .cone_client
                     .do_hole_punching(task_info.dst_peer_id)
                     .await;
-                if self.handle_punch_result(ret, None, None).await {
+                if self
+                    .handle_punch_result(task_info.dst_peer_id, "cone", ret, None, None)
+                    .await
+                {
                     break;
                 }
             }
@@ -326,7 +414,13 @@ impl UdpHoePunchConnectorData {
             };
 
             if self
-                .handle_punch_result(ret, Some(&mut backoff), Some(&mut round))
+                .handle_punch_result(
+                    task_info.dst_peer_id,
+                    "sym_to_cone",
+                    ret,
+                    Some(&mut backoff),
+                    Some(&mut round),
+                )
                 .await
             {
                 break;
@@ -342,7 +436,8 @@ impl UdpHoePunchConnectorData {
             BackOff::new(vec![1000, 1000, 2000, 4000, 4000, 8000, 8000, 16000, 64000]);
 
         loop {
-            backoff.sleep_for_next_backoff().await;
+            self.sleep_for_next_backoff(task_info.dst_peer_id, "both_easy_sym", &mut backoff)
+                .await;
 
             // always try cone first
             if !RUN_TESTING.load(std::sync::atomic::Ordering::Relaxed) {
@@ -350,7 +445,10 @@ impl UdpHoePunchConnectorData {
                     .cone_client
                     .do_hole_punching(task_info.dst_peer_id)
                     .await;
-                if self.handle_punch_result(ret, None, None).await {
+                if self
+                    .handle_punch_result(task_info.dst_peer_id, "cone", ret, None, None)
+                    .await
+                {
                     break;
                 }
             }
@@ -374,7 +472,89 @@ impl UdpHoePunchConnectorData {
             if is_busy {
                 backoff.rollback();
             } else if self
-                .handle_punch_result(ret, Some(&mut backoff), None)
+                .handle_punch_result(
+                    task_info.dst_peer_id,
+                    "both_easy_sym",
+                    ret,
+                    Some(&mut backoff),
+                    None,
+                )
+                .await
+            {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn both_hard_sym(self: Arc<Self>, task_info: PunchTaskInfo) -> Result<(), Error> {
+        let mut backoff = BackOff::new(vec![1000, 2000, 4000, 8000, 16000, 32000, 64000]);
+        let mut round = 0;
+
+        loop {
+            self.sleep_for_next_backoff(task_info.dst_peer_id, "both_hard_sym", &mut backoff)
+                .await;
+
+            // always try cone first
+            if !RUN_TESTING.load(std::sync::atomic::Ordering::Relaxed) {
+                let ret = self
+                    .cone_client
+                    .do_hole_punching(task_info.dst_peer_id)
+                    .await;
+                if self
+                    .handle_punch_result(task_info.dst_peer_id, "cone", ret, None, None)
+                    .await
+                {
+                    break;
+                }
+            }
+
+            if round >= MAX_BOTH_HARD_SYM_ROUNDS {
+                tracing::info!(
+                    ?task_info,
+                    "both hard sym punching failed too many times, give up for a while"
+                );
+                self.blacklist.insert(
+                    task_info.dst_peer_id,
+                    (),
+                    Duration::from_secs(BOTH_HARD_SYM_GIVE_UP_SEC),
+                );
+                self.peer_mgr
+                    .get_global_ctx()
+                    .get_connect_attempt_log()
+                    .record_blacklisted(
+                        task_info.dst_peer_id,
+                        ConnectAttemptMethod::UdpHolePunch,
+                        "both_hard_sym",
+                        format!("failed {} rounds", MAX_BOTH_HARD_SYM_ROUNDS),
+                        Duration::from_secs(BOTH_HARD_SYM_GIVE_UP_SEC),
+                    );
+                break;
+            }
+
+            let mut is_busy = false;
+
+            let ret = {
+                let _lock = get_sym_punch_lock(self.peer_mgr.my_peer_id())
+                    .lock_owned()
+                    .await;
+                self.both_hard_sym_client
+                    .do_hole_punching(task_info.dst_peer_id, &mut is_busy)
+                    .await
+            };
+
+            if is_busy {
+                backoff.rollback();
+            } else if self
+                .handle_punch_result(
+                    task_info.dst_peer_id,
+                    "both_hard_sym",
+                    ret,
+                    Some(&mut backoff),
+                    Some(&mut round),
+                )
                 .await
             {
                 break;
@@ -515,6 +695,7 @@ impl PeerTaskLauncher for UdpHolePunchPeerTaskLauncher {
             UdpPunchClientMethod::ConeToCone => tokio::spawn(data.cone_to_cone(item)),
             UdpPunchClientMethod::SymToCone => tokio::spawn(data.sym_to_cone(item)),
             UdpPunchClientMethod::EasySymToEasySym => tokio::spawn(data.both_easy_sym(item)),
+            UdpPunchClientMethod::HardSymToHardSym => tokio::spawn(data.both_hard_sym(item)),
             _ => unreachable!(),
         }
     }
@@ -536,6 +717,7 @@ pub struct UdpHolePunchConnector {
 
 // Currently support:
 // Symmetric -> Full Cone
+// Symmetric <-> Symmetric (predictable or random ports)
 // Any Type of Full Cone -> Any Type of Full Cone
 
 // if same level of full cone, node with smaller peer_id will be the initiator
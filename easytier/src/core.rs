@@ -4,10 +4,10 @@ use crate::{
     ShellType,
     common::{
         config::{
-            ConfigFileControl, ConfigLoader, ConsoleLoggerConfig, EncryptionAlgorithm,
-            FileLoggerConfig, LoggingConfigLoader, NetworkIdentity, PeerConfig, PortForwardConfig,
-            TomlConfigLoader, VpnPortalConfig, load_config_from_file, parse_mapped_listener_urls,
-            process_secure_mode_cfg,
+            BgpConfig, ConfigFileControl, ConfigLoader, ConsoleLoggerConfig, EncryptionAlgorithm,
+            FileLoggerConfig, FlowExportFormat, LinkMetricConfig, LoggingConfigLoader,
+            NetworkIdentity, PeerConfig, PortForwardConfig, TomlConfigLoader, VpnPortalConfig,
+            load_config_from_file, parse_mapped_listener_urls, process_secure_mode_cfg,
         },
         constants::EASYTIER_VERSION,
         log,
@@ -156,6 +156,20 @@ struct NetworkOptions {
     )]
     network_secret: Option<String>,
 
+    #[arg(
+        long,
+        env = "ET_NEXT_NETWORK_SECRET",
+        help = t!("core_clap.next_network_secret").to_string(),
+    )]
+    next_network_secret: Option<String>,
+
+    #[arg(
+        long,
+        env = "ET_SECRET_ROTATE_AT",
+        help = t!("core_clap.secret_rotate_at").to_string(),
+    )]
+    secret_rotate_at: Option<u64>,
+
     #[arg(
         short,
         long,
@@ -282,6 +296,97 @@ struct NetworkOptions {
     )]
     vpn_portal: Option<String>,
 
+    #[arg(
+        long,
+        env = "ET_VPN_PORTAL_CLIENT_FILE",
+        help = t!("core_clap.vpn_portal_client_file").to_string()
+    )]
+    vpn_portal_client_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        env = "ET_FLOW_LOG_FILE",
+        help = t!("core_clap.flow_log_file").to_string()
+    )]
+    flow_log_file: Option<String>,
+
+    #[arg(
+        long,
+        env = "ET_FLOW_LOG_COLLECTOR",
+        help = t!("core_clap.flow_log_collector").to_string()
+    )]
+    flow_log_collector: Option<String>,
+
+    #[arg(
+        long,
+        env = "ET_FLOW_LOG_FORMAT",
+        value_parser = ["ipfix", "netflow9"],
+        help = t!("core_clap.flow_log_format").to_string()
+    )]
+    flow_log_format: Option<String>,
+
+    #[arg(
+        long,
+        env = "ET_FLOW_LOG_SAMPLE_RATE",
+        help = t!("core_clap.flow_log_sample_rate").to_string()
+    )]
+    flow_log_sample_rate: Option<u32>,
+
+    #[arg(
+        long,
+        env = "ET_LINK_COST",
+        value_delimiter = ',',
+        help = t!("core_clap.link_cost").to_string(),
+        num_args = 0..
+    )]
+    link_cost: Option<Vec<String>>,
+
+    #[arg(
+        long,
+        env = "ET_LINK_BANDWIDTH",
+        value_delimiter = ',',
+        help = t!("core_clap.link_bandwidth").to_string(),
+        num_args = 0..
+    )]
+    link_bandwidth: Option<Vec<String>>,
+
+    #[arg(
+        long,
+        env = "ET_BGP_NEIGHBOR",
+        help = t!("core_clap.bgp_neighbor").to_string()
+    )]
+    bgp_neighbor: Option<String>,
+
+    #[arg(
+        long,
+        env = "ET_BGP_LOCAL_AS",
+        help = t!("core_clap.bgp_local_as").to_string()
+    )]
+    bgp_local_as: Option<u32>,
+
+    #[arg(
+        long,
+        env = "ET_BGP_REMOTE_AS",
+        help = t!("core_clap.bgp_remote_as").to_string()
+    )]
+    bgp_remote_as: Option<u32>,
+
+    #[arg(
+        long,
+        env = "ET_BGP_LISTEN",
+        help = t!("core_clap.bgp_listen").to_string()
+    )]
+    bgp_listen: Option<SocketAddr>,
+
+    #[arg(
+        long,
+        env = "ET_BGP_IMPORT",
+        help = t!("core_clap.bgp_import").to_string(),
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
+    bgp_import: Option<bool>,
+
     #[arg(
         long,
         env = "ET_DEFAULT_PROTOCOL",
@@ -307,6 +412,15 @@ struct NetworkOptions {
     )]
     encryption_algorithm: Option<EncryptionAlgorithm>,
 
+    #[arg(
+        long,
+        env = "ET_ENABLE_CONN_REKEY",
+        help = t!("core_clap.enable_conn_rekey").to_string(),
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
+    enable_conn_rekey: Option<bool>,
+
     #[arg(
         long,
         env = "ET_MULTI_THREAD",
@@ -493,6 +607,40 @@ struct NetworkOptions {
     )]
     enable_udp_broadcast_relay: Option<bool>,
 
+    #[arg(
+        long,
+        env = "ET_ENABLE_LAN_DISCOVERY",
+        help = t!("core_clap.enable_lan_discovery").to_string(),
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
+    enable_lan_discovery: Option<bool>,
+
+    #[arg(
+        long,
+        env = "ET_ENABLE_TAP",
+        help = t!("core_clap.enable_tap").to_string(),
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
+    enable_tap: Option<bool>,
+
+    #[arg(
+        long,
+        env = "ET_TAP_BRIDGE",
+        help = t!("core_clap.tap_bridge").to_string()
+    )]
+    tap_bridge: Option<String>,
+
+    #[arg(
+        long,
+        env = "ET_ENABLE_TUN_OFFLOAD",
+        help = t!("core_clap.enable_tun_offload").to_string(),
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
+    enable_tun_offload: Option<bool>,
+
     #[arg(
         long,
         env = "ET_RELAY_ALL_PEER_RPC",
@@ -616,6 +764,41 @@ struct NetworkOptions {
     )]
     foreign_relay_bps_limit: Option<u64>,
 
+    #[arg(
+        long,
+        env = "ET_RELAY_MAX_NETWORKS",
+        help = t!("core_clap.relay_max_networks").to_string(),
+    )]
+    relay_max_networks: Option<usize>,
+
+    #[arg(
+        long,
+        env = "ET_RELAY_MAX_PEERS_PER_NETWORK",
+        help = t!("core_clap.relay_max_peers_per_network").to_string(),
+    )]
+    relay_max_peers_per_network: Option<usize>,
+
+    #[arg(
+        long,
+        env = "ET_RELAY_NETWORK_BPS_LIMIT",
+        help = t!("core_clap.relay_network_bps_limit").to_string(),
+    )]
+    relay_network_bps_limit: Option<u64>,
+
+    #[arg(
+        long,
+        env = "ET_RELAY_NETWORK_MONTHLY_BYTES",
+        help = t!("core_clap.relay_network_monthly_bytes").to_string(),
+    )]
+    relay_network_monthly_bytes: Option<u64>,
+
+    #[arg(
+        long,
+        env = "ET_RELAY_QUOTA_USAGE_FILE",
+        help = t!("core_clap.relay_quota_usage_file").to_string(),
+    )]
+    relay_quota_usage_file: Option<PathBuf>,
+
     #[arg(
         long,
         env = "ET_INSTANCE_RECV_BPS_LIMIT",
@@ -716,6 +899,15 @@ struct NetworkOptions {
     )]
     local_public_key: Option<String>,
 
+    #[arg(
+        long,
+        env = "ET_SECURE_MODE_PQ_HYBRID",
+        help = t!("core_clap.secure_mode_pq_hybrid").to_string(),
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
+    secure_mode_pq_hybrid: Option<bool>,
+
     #[arg(
         long,
         env = "ET_CREDENTIAL",
@@ -767,6 +959,14 @@ struct LoggingOptions {
         help = t!("core_clap.file_log_count").to_string()
     )]
     file_log_count: Option<usize>,
+
+    #[arg(
+        long,
+        env = "ET_FILE_LOG_FORMAT",
+        value_parser = ["text", "json"],
+        help = t!("core_clap.file_log_format").to_string()
+    )]
+    file_log_format: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -1011,6 +1211,141 @@ impl NetworkOptions {
             });
         }
 
+        if let Some(vpn_portal_client_file) = &self.vpn_portal_client_file {
+            cfg.set_vpn_portal_client_file(Some(vpn_portal_client_file.clone()));
+        }
+
+        if self.flow_log_file.is_some()
+            || self.flow_log_collector.is_some()
+            || self.flow_log_format.is_some()
+            || self.flow_log_sample_rate.is_some()
+        {
+            let mut flow_log = cfg.get_flow_log_config().unwrap_or_default();
+            if let Some(file) = &self.flow_log_file {
+                flow_log.file = Some(file.clone());
+            }
+            if let Some(collector) = &self.flow_log_collector {
+                flow_log.collector = Some(collector.clone());
+            }
+            if let Some(format) = &self.flow_log_format {
+                flow_log.format = Some(match format.as_str() {
+                    "netflow9" => FlowExportFormat::Netflow9,
+                    _ => FlowExportFormat::Ipfix,
+                });
+            }
+            if let Some(sample_rate) = self.flow_log_sample_rate {
+                flow_log.sample_rate = Some(sample_rate);
+            }
+            cfg.set_flow_log_config(Some(flow_log));
+        }
+
+        if self.link_cost.is_some() || self.link_bandwidth.is_some() {
+            fn link_metric_entry<'a>(
+                link_metrics: &'a mut Vec<LinkMetricConfig>,
+                spec: &str,
+            ) -> anyhow::Result<(&'a mut LinkMetricConfig, u32)> {
+                let (peer, value) = spec
+                    .split_once('=')
+                    .with_context(|| format!("expected <peer>=<value>, got: {}", spec))?;
+                let value = value
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("invalid value in: {}", spec))?;
+                let peer = peer.trim();
+                let idx = match link_metrics.iter().position(|m| m.peer == peer) {
+                    Some(idx) => idx,
+                    None => {
+                        link_metrics.push(LinkMetricConfig {
+                            peer: peer.to_string(),
+                            ..Default::default()
+                        });
+                        link_metrics.len() - 1
+                    }
+                };
+                Ok((&mut link_metrics[idx], value))
+            }
+
+            let mut link_metrics = cfg.get_link_metrics();
+            for spec in self.link_cost.iter().flatten() {
+                let (metric, cost) = link_metric_entry(&mut link_metrics, spec)?;
+                metric.cost = Some(cost);
+            }
+            for spec in self.link_bandwidth.iter().flatten() {
+                let (metric, bandwidth) = link_metric_entry(&mut link_metrics, spec)?;
+                metric.bandwidth_mbps = Some(bandwidth);
+            }
+            cfg.set_link_metrics(Some(link_metrics));
+        }
+
+        if self.bgp_neighbor.is_some()
+            || self.bgp_local_as.is_some()
+            || self.bgp_remote_as.is_some()
+            || self.bgp_listen.is_some()
+            || self.bgp_import.is_some()
+        {
+            let mut bgp = cfg.get_bgp_config().unwrap_or_default();
+            if let Some(neighbor) = &self.bgp_neighbor {
+                bgp.neighbor = neighbor.clone();
+            }
+            if let Some(local_as) = self.bgp_local_as {
+                bgp.local_as = local_as;
+            }
+            // ibgp unless told otherwise
+            bgp.remote_as = self.bgp_remote_as.unwrap_or(match bgp.remote_as {
+                0 => bgp.local_as,
+                remote_as => remote_as,
+            });
+            if let Some(listen) = self.bgp_listen {
+                bgp.listen = Some(listen);
+            }
+            if let Some(import) = self.bgp_import {
+                bgp.import = Some(import);
+            }
+            if bgp.neighbor.is_empty() || bgp.local_as == 0 {
+                anyhow::bail!("--bgp-neighbor and --bgp-local-as are required to enable bgp");
+            }
+            cfg.set_bgp_config(Some(bgp));
+        }
+
+        if self.relay_max_networks.is_some()
+            || self.relay_max_peers_per_network.is_some()
+            || self.relay_network_bps_limit.is_some()
+            || self.relay_network_monthly_bytes.is_some()
+            || self.relay_quota_usage_file.is_some()
+        {
+            let mut quota = cfg.get_relay_quota_config().unwrap_or_default();
+            if let Some(v) = self.relay_max_networks {
+                quota.max_networks = Some(v);
+            }
+            if let Some(v) = self.relay_max_peers_per_network {
+                quota.max_peers_per_network = Some(v);
+            }
+            if let Some(v) = self.relay_network_bps_limit {
+                quota.network_bps_limit = Some(v);
+            }
+            if let Some(v) = self.relay_network_monthly_bytes {
+                quota.network_monthly_bytes = Some(v);
+            }
+            if let Some(path) = &self.relay_quota_usage_file {
+                quota.usage_file = Some(path.clone());
+            }
+            cfg.set_relay_quota_config(Some(quota));
+        }
+
+        if self.next_network_secret.is_some() || self.secret_rotate_at.is_some() {
+            let mut rotation = cfg.get_secret_rotation_config().unwrap_or_default();
+            if let Some(secret) = &self.next_network_secret {
+                rotation.next_network_secret = secret.clone();
+            }
+            if let Some(at) = self.secret_rotate_at {
+                rotation.activate_at = Some(at);
+            }
+            if rotation.next_network_secret.is_empty() {
+                anyhow::bail!("--secret-rotate-at requires --next-network-secret");
+            }
+            cfg.set_secret_rotation_config(Some(rotation));
+        }
+
         if let Some(manual_routes) = self.manual_routes.as_ref() {
             let mut routes = Vec::<cidr::Ipv4Cidr>::with_capacity(manual_routes.len());
             for r in manual_routes {
@@ -1068,22 +1403,34 @@ impl NetworkOptions {
             cfg.set_credential_file(Some(credential_file.clone()));
         }
 
-        if let Some(ref credential_secret) = self.credential {
+        let secure_mode_from_args = if let Some(ref credential_secret) = self.credential {
             // --credential implies --secure-mode and sets the credential private key
-            let c = SecureModeConfig {
+            Some(SecureModeConfig {
                 enabled: true,
                 local_private_key: Some(credential_secret.clone()),
                 local_public_key: None,
-            };
-            cfg.set_secure_mode(Some(process_secure_mode_cfg(c)?));
+                pq_hybrid: self.secure_mode_pq_hybrid.unwrap_or(false),
+            })
         } else if let Some(secure_mode) = self.secure_mode
             && secure_mode
         {
-            let c = SecureModeConfig {
+            Some(SecureModeConfig {
                 enabled: secure_mode,
                 local_private_key: self.local_private_key.clone(),
                 local_public_key: self.local_public_key.clone(),
-            };
+                pq_hybrid: self.secure_mode_pq_hybrid.unwrap_or(false),
+            })
+        } else {
+            None
+        };
+        let has_secure_mode_args = secure_mode_from_args.is_some();
+        if has_secure_mode_args {
+            cfg.set_secure_mode(secure_mode_from_args);
+        }
+
+        // secret references must be resolved before keys are derived from them
+        cfg.resolve_secret_refs(true)?;
+        if has_secure_mode_args && let Some(c) = cfg.get_secure_mode() {
             cfg.set_secure_mode(Some(process_secure_mode_cfg(c)?));
         }
 
@@ -1097,6 +1444,7 @@ impl NetworkOptions {
         if let Some(algorithm) = &self.encryption_algorithm {
             f.encryption_algorithm = algorithm.to_string();
         }
+        f.enable_conn_rekey = self.enable_conn_rekey.unwrap_or(f.enable_conn_rekey);
         if let Some(v) = self.disable_ipv6 {
             f.enable_ipv6 = !v;
         }
@@ -1172,6 +1520,12 @@ impl NetworkOptions {
         f.enable_udp_broadcast_relay = self
             .enable_udp_broadcast_relay
             .unwrap_or(f.enable_udp_broadcast_relay);
+        f.enable_lan_discovery = self.enable_lan_discovery.unwrap_or(f.enable_lan_discovery);
+        f.enable_tap = self.enable_tap.unwrap_or(f.enable_tap);
+        if let Some(tap_bridge) = &self.tap_bridge {
+            f.tap_bridge = tap_bridge.clone();
+        }
+        f.enable_tun_offload = self.enable_tun_offload.unwrap_or(f.enable_tun_offload);
         // Configure tld_dns_zone: use provided value if set
         if let Some(tld_dns_zone) = &self.tld_dns_zone {
             f.tld_dns_zone = tld_dns_zone.clone();
@@ -1225,6 +1579,7 @@ impl LoggingConfigLoader for &LoggingOptions {
             file: None,
             size_mb: self.file_log_size,
             count: self.file_log_count,
+            format: self.file_log_format.clone(),
         }
     }
 }
@@ -33,7 +33,11 @@ use easytier::{
         constants::EASYTIER_VERSION,
         stun::{StunInfoCollector, StunInfoCollectorTrait},
     },
-    peers,
+    peers::{
+        self,
+        packet_capture::{CaptureFilter, PcapNgWriter, describe_packet},
+        route_metric::describe_link_metric,
+    },
     proto::{
         acl::AclStats,
         api::{
@@ -42,29 +46,34 @@ use easytier::{
                 InstanceConfigPatch, PatchConfigRequest, PortForwardPatch, StringPatch, UrlPatch,
             },
             instance::{
-                AclManageRpc, AclManageRpcClientFactory, Connector, ConnectorManageRpc,
+                AclManageRpc, AclManageRpcClientFactory, AddVpnPortalClientRequest,
+                ConnectAttemptMethod, Connector, ConnectorManageRpc,
                 ConnectorManageRpcClientFactory, CredentialManageRpc,
-                CredentialManageRpcClientFactory, DumpRouteRequest, ForeignNetworkEntryPb,
-                GenerateCredentialRequest, GetAclStatsRequest, GetPrometheusStatsRequest,
-                GetStatsRequest, GetVpnPortalInfoRequest, GetWhitelistRequest,
-                GetWhitelistResponse, InstanceIdentifier, ListConnectorRequest,
-                ListCredentialsRequest, ListCredentialsResponse, ListForeignNetworkRequest,
-                ListGlobalForeignNetworkRequest, ListMappedListenerRequest, ListPeerRequest,
+                CredentialManageRpcClientFactory, DiagnosePeerRequest, DumpRouteRequest,
+                FetchCaptureRequest, ForeignNetworkEntryPb, GenerateCredentialRequest,
+                GetAclStatsRequest, GetPrometheusStatsRequest, GetSecretRotationStatusRequest,
+                GetStatsRequest, GetVpnPortalClientConfigRequest, GetVpnPortalInfoRequest,
+                GetWhitelistRequest, GetWhitelistResponse, InstanceIdentifier,
+                ListConnectorRequest, ListCredentialsRequest, ListCredentialsResponse,
+                ListForeignNetworkRequest, ListGlobalForeignNetworkRequest,
+                ListLanDiscoveredPeersRequest, ListMappedListenerRequest, ListPeerRequest,
                 ListPeerResponse, ListPortForwardRequest, ListPortForwardResponse,
                 ListPublicIpv6InfoRequest, ListPublicIpv6InfoResponse, ListRouteRequest,
-                ListRouteResponse, MappedListener, MappedListenerManageRpc,
-                MappedListenerManageRpcClientFactory, MetricSnapshot, NodeInfo, PeerManageRpc,
-                PeerManageRpcClientFactory, PortForwardManageRpc,
-                PortForwardManageRpcClientFactory, RevokeCredentialRequest, Route as ApiRoute,
-                ShowNodeInfoRequest, StatsRpc, StatsRpcClientFactory, TcpProxyEntryState,
+                ListRouteResponse, ListVpnPortalClientsRequest, MappedListener,
+                MappedListenerManageRpc, MappedListenerManageRpcClientFactory, MetricSnapshot,
+                NodeInfo, PeerManageRpc, PeerManageRpcClientFactory, PeerSecretState,
+                PortForwardManageRpc, PortForwardManageRpcClientFactory,
+                RemoveVpnPortalClientRequest, RevokeCredentialRequest, Route as ApiRoute,
+                SecretRotationState, ShowNodeInfoRequest, StartCaptureRequest, StatsRpc,
+                StatsRpcClientFactory, StopCaptureRequest, TcpProxyEntryState,
                 TcpProxyEntryTransportType, TcpProxyRpc, TcpProxyRpcClientFactory,
                 TrustedKeySourcePb, VpnPortalInfo, VpnPortalRpc, VpnPortalRpcClientFactory,
                 instance_identifier::{InstanceSelector, Selector},
                 list_global_foreign_network_response, list_peer_route_pair,
             },
             logger::{
-                GetLoggerConfigRequest, LogLevel, LoggerRpc, LoggerRpcClientFactory,
-                SetLoggerConfigRequest,
+                GetLoggerConfigRequest, LogEvent, LogLevel, LoggerRpc, LoggerRpcClientFactory,
+                SetLoggerConfigRequest, TailLogRequest,
             },
             manage::{
                 ListNetworkInstanceMetaRequest, ListNetworkInstanceRequest, WebClientService,
@@ -133,8 +142,17 @@ enum SubCommand {
     Route(RouteArgs),
     #[command(about = "show global peers info")]
     PeerCenter,
-    #[command(about = "show vpn portal (wireguard) info")]
-    VpnPortal,
+    #[command(about = "explain why a peer is not directly connected")]
+    Diagnose {
+        #[arg(help = "peer id, hostname or virtual ipv4 of the peer")]
+        peer: String,
+    },
+    #[command(about = "capture overlay packets into pcapng, like tcpdump")]
+    Capture(CaptureArgs),
+    #[command(about = "show network secret rotation progress")]
+    SecretRotation,
+    #[command(about = "show vpn portal (wireguard) info and manage its clients")]
+    VpnPortal(VpnPortalArgs),
     #[command(about = "inspect self easytier-core status")]
     Node(NodeArgs),
     #[command(about = "manage easytier-core as a system service")]
@@ -250,6 +268,8 @@ enum ConnectorSubCommand {
     },
     /// List connectors
     List,
+    /// List peers found by lan discovery
+    Discovered,
 }
 
 #[derive(Args, Debug)]
@@ -350,6 +370,43 @@ enum WhitelistSubCommand {
     Show,
 }
 
+#[derive(Args, Debug)]
+struct CaptureArgs {
+    #[arg(
+        help = "capture filter, e.g. \"peer 123 and tcp port 22\", \"host 10.144.144.2 rx\"; \
+                primitives: peer, host, port, proto, tcp, udp, icmp, icmp6, rx, tx, forward"
+    )]
+    filter: Vec<String>,
+
+    #[arg(
+        short = 'w',
+        long,
+        help = "write pcapng to this local file, - for stdout (e.g. pipe into wireshark -k -i -)"
+    )]
+    write: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "write pcapng to this file on the node instead of streaming it over rpc"
+    )]
+    remote_file: Option<String>,
+
+    #[arg(short = 'c', long, help = "stop after this many packets")]
+    count: Option<u32>,
+
+    #[arg(long, help = "stop after this many seconds")]
+    duration: Option<u64>,
+
+    #[arg(short = 's', long, help = "bytes to capture of each packet")]
+    snaplen: Option<u32>,
+
+    #[arg(
+        long,
+        help = "ring buffer size in bytes on the node, oldest packets are dropped when full"
+    )]
+    buffer_size: Option<u32>,
+}
+
 #[derive(Args, Debug)]
 struct StatsArgs {
     #[command(subcommand)]
@@ -374,11 +431,70 @@ struct LoggerArgs {
 enum LoggerSubCommand {
     /// Get current logger configuration
     Get,
-    /// Set logger level
+    /// Set logger level or filter directives
     Set {
-        #[arg(help = "Log level (disabled, error, warning, info, debug, trace)")]
+        #[arg(
+            help = "Log level (disabled, error, warning, info, debug, trace) or filter directives, e.g. info,peers::peer_ospf_route=trace"
+        )]
         level: String,
     },
+    /// Show recent log events kept in memory by the node
+    Tail {
+        #[arg(
+            long,
+            help = "only show matching events, e.g. warn,peers=debug,tunnel::udp"
+        )]
+        filter: Option<String>,
+        #[arg(
+            short = 'n',
+            long,
+            default_value_t = 20,
+            help = "number of recent events to show"
+        )]
+        lines: u32,
+        #[arg(short, long, help = "keep waiting for new events")]
+        follow: bool,
+    },
+}
+
+#[derive(Args, Debug)]
+struct VpnPortalArgs {
+    #[command(subcommand)]
+    sub_command: Option<VpnPortalSubCommand>,
+}
+
+#[derive(Subcommand, Debug)]
+enum VpnPortalSubCommand {
+    /// Show vpn portal info and the shared client config
+    Show,
+    /// Add a client with its own key pair and address
+    Add {
+        #[arg(help = "client name")]
+        name: String,
+        #[arg(
+            long,
+            help = "use the public key (base64) of the client instead of generating a key pair"
+        )]
+        public_key: Option<String>,
+        #[arg(
+            long,
+            default_value = "0",
+            help = "TTL in seconds, 0 means never expires"
+        )]
+        ttl: i64,
+    },
+    /// Remove a client, its active session is disconnected
+    Remove {
+        #[arg(help = "client name")]
+        name: String,
+    },
+    /// List clients
+    List,
+    /// Print the wireguard config of a client
+    Config {
+        #[arg(help = "client name")]
+        name: String,
+    },
 }
 
 #[derive(Args, Debug)]
@@ -1632,6 +1748,27 @@ impl<'a> CommandHandler<'a> {
         self.print_results(&results, |networks| {
             for (idx, (k, v)) in networks.iter().enumerate() {
                 println!("{} Network Name: {}", idx + 1, k);
+                if let Some(quota) = v.relay_quota.as_ref() {
+                    let fmt_limit = |v: Option<u64>| {
+                        v.map(|v| format_size(v, humansize::BINARY))
+                            .unwrap_or_else(|| "-".to_string())
+                    };
+                    println!(
+                        "  relayed_this_month: {} / {}, bps_limit: {}, max_peers: {}, priority: {}, over_budget: {}",
+                        format_size(quota.relayed_bytes_this_month, humansize::BINARY),
+                        fmt_limit(quota.monthly_bytes_limit),
+                        quota
+                            .bps_limit
+                            .map(|v| format!("{}/s", format_size(v, humansize::BINARY)))
+                            .unwrap_or_else(|| "-".to_string()),
+                        quota
+                            .max_peers
+                            .map(|v| v.to_string())
+                            .unwrap_or_else(|| "-".to_string()),
+                        quota.priority,
+                        quota.over_budget,
+                    );
+                }
                 for peer in v.peers.iter() {
                     println!(
                         "  peer_id: {}, peer_conn_count: {}, conns: [ {} ]",
@@ -1713,6 +1850,7 @@ impl<'a> CommandHandler<'a> {
             next_hop_lat: f64,
             path_len: i32,
             path_latency: i32,
+            next_hop_metric: String,
 
             next_hop_ipv4_lat_first: String,
             next_hop_hostname_lat_first: String,
@@ -1732,6 +1870,7 @@ impl<'a> CommandHandler<'a> {
                 next_hop_lat: 0.0,
                 path_len: 0,
                 path_latency: 0,
+                next_hop_metric: "-".to_string(),
                 next_hop_ipv4_lat_first: "-".to_string(),
                 next_hop_hostname_lat_first: "Local".to_string(),
                 path_len_lat_first: 0,
@@ -1780,6 +1919,14 @@ impl<'a> CommandHandler<'a> {
                     next_hop_lat: next_hop_pair.get_latency_ms().unwrap_or(0.0),
                     path_len: route.cost,
                     path_latency: route.path_latency,
+                    next_hop_metric: match route.next_hop_link_metric.as_ref() {
+                        Some(metric) => format!(
+                            "cost {} ({})",
+                            route.next_hop_link_cost,
+                            describe_link_metric(metric)
+                        ),
+                        None => "-".to_string(),
+                    },
                     next_hop_ipv4_lat_first: if route.cost_latency_first.unwrap_or_default() == 1 {
                         "DIRECT".to_string()
                     } else {
@@ -1832,13 +1979,333 @@ impl<'a> CommandHandler<'a> {
             print_output(
                 &items,
                 self.output_format,
-                &["proxy_cidrs", "version"],
-                &["proxy_cidrs", "version"],
+                &["proxy_cidrs", "next_hop_metric", "version"],
+                &["proxy_cidrs", "next_hop_metric", "version"],
                 self.no_trunc,
             )
         })
     }
 
+    async fn handle_capture(&self, args: &CaptureArgs) -> Result<(), Error> {
+        // a capture session lives on a single instance
+        let scoped;
+        let handler = match self.fanout_targets().await? {
+            Some(targets) if targets.len() > 1 => {
+                return Err(anyhow::anyhow!(
+                    "multiple instances are running, select one with --instance-name or --instance-id"
+                ));
+            }
+            Some(targets) => {
+                scoped = self.scoped_to_instance(&targets[0]);
+                &scoped
+            }
+            None => self,
+        };
+
+        let filter = args.filter.join(" ");
+        filter.parse::<CaptureFilter>()?;
+        let snaplen = args
+            .snaplen
+            .unwrap_or(peers::packet_capture::DEFAULT_SNAPLEN);
+        let mut writer: Option<PcapNgWriter<Box<dyn std::io::Write>>> = match &args.write {
+            None => None,
+            Some(path) if path.as_os_str() == "-" => {
+                Some(PcapNgWriter::new(Box::new(std::io::stdout()), snaplen)?)
+            }
+            Some(path) => Some(PcapNgWriter::new(
+                Box::new(std::io::BufWriter::new(
+                    std::fs::File::create(path)
+                        .with_context(|| format!("failed to create {}", path.display()))?,
+                )),
+                snaplen,
+            )?),
+        };
+
+        let client = handler.get_peer_manager_client().await?;
+        let instance = Some(handler.instance_selector.clone());
+        let session_id = client
+            .start_capture(
+                BaseController::default(),
+                StartCaptureRequest {
+                    instance: instance.clone(),
+                    filter,
+                    max_packets: args.count.unwrap_or(0),
+                    buffer_bytes: args.buffer_size.unwrap_or(0),
+                    snaplen,
+                    output_file: args.remote_file.clone(),
+                },
+            )
+            .await?
+            .session_id;
+        // status goes to stderr, stdout may be the pcapng stream
+        eprintln!("capturing, press ctrl-c to stop");
+
+        let deadline = args
+            .duration
+            .map(|secs| std::time::Instant::now() + Duration::from_secs(secs));
+        let mut ctrl_c = std::pin::pin!(tokio::signal::ctrl_c());
+        let ret: Result<(), Error> = async {
+            loop {
+                let fetched = client
+                    .fetch_capture(
+                        BaseController::default(),
+                        FetchCaptureRequest {
+                            instance: instance.clone(),
+                            session_id,
+                        },
+                    )
+                    .await?;
+                for packet in fetched.packets.iter() {
+                    match writer.as_mut() {
+                        Some(writer) => writer.write_packet(packet)?,
+                        None => println!("{}", describe_packet(packet)),
+                    }
+                }
+                if let Some(writer) = writer.as_mut() {
+                    writer.flush()?;
+                }
+
+                if fetched.finished
+                    || deadline
+                        .map(|d| std::time::Instant::now() >= d)
+                        .unwrap_or(false)
+                {
+                    return Ok(());
+                }
+                tokio::select! {
+                    _ = &mut ctrl_c => return Ok(()),
+                    _ = tokio::time::sleep(Duration::from_millis(500)) => {}
+                }
+            }
+        }
+        .await;
+
+        let stopped = client
+            .stop_capture(
+                BaseController::default(),
+                StopCaptureRequest {
+                    instance,
+                    session_id,
+                },
+            )
+            .await;
+        if let Ok(stopped) = stopped {
+            eprintln!(
+                "{} packets captured, {} dropped by the node buffer",
+                stopped.captured, stopped.dropped
+            );
+        }
+        ret
+    }
+
+    async fn handle_diagnose(&self, peer: &str) -> Result<(), Error> {
+        let peer = peer.to_string();
+        let results = self
+            .collect_instance_results(|handler| {
+                let peer = peer.clone();
+                Box::pin(async move {
+                    handler
+                        .get_peer_manager_client()
+                        .await?
+                        .diagnose_peer(
+                            BaseController::default(),
+                            DiagnosePeerRequest {
+                                instance: Some(handler.instance_selector.clone()),
+                                peer,
+                            },
+                        )
+                        .await
+                        .map_err(Into::into)
+                })
+            })
+            .await?;
+
+        if *self.output_format == OutputFormat::Json {
+            return self.print_json_results(results);
+        }
+
+        self.print_results(&results, |response| {
+            let nat_type_str = |stun_info: &Option<easytier::proto::common::StunInfo>| {
+                let stun_info = stun_info.clone().unwrap_or_default();
+                format!(
+                    "udp: {:?}, tcp: {:?}",
+                    stun_info.udp_nat_type(),
+                    stun_info.tcp_nat_type()
+                )
+            };
+            let method_str = |method: i32| {
+                ConnectAttemptMethod::try_from(method)
+                    .map(|m| format!("{:?}", m))
+                    .unwrap_or_else(|_| "Unknown".to_string())
+            };
+            let time_str = |time_unix: i64| {
+                chrono::DateTime::from_timestamp(time_unix, 0)
+                    .map(|t| {
+                        t.with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                            .to_string()
+                    })
+                    .unwrap_or_default()
+            };
+
+            println!("peer: {} ({})", response.peer_id, response.hostname);
+            println!(
+                "route: next hop {}, cost {}, directly connected: {}",
+                response.next_hop_peer_id, response.cost, response.directly_connected
+            );
+            println!("local nat: {}", nat_type_str(&response.my_stun_info));
+            println!("peer nat: {}", nat_type_str(&response.peer_stun_info));
+            if !response.candidate_error.is_empty() {
+                println!("candidates: failed to fetch, {}", response.candidate_error);
+            } else if response.candidate_addresses.is_empty() {
+                println!("candidates: none");
+            } else {
+                println!("candidates: {}", response.candidate_addresses.join(", "));
+            }
+
+            use tabled::{builder::Builder, settings::Style};
+            if !response.attempts.is_empty() {
+                let mut builder = Builder::default();
+                builder.push_record(["Time", "Method", "Target", "Result"]);
+                for attempt in &response.attempts {
+                    let result = if attempt.success {
+                        "success".to_string()
+                    } else {
+                        attempt.error.clone()
+                    };
+                    builder.push_record([
+                        time_str(attempt.time_unix),
+                        method_str(attempt.method),
+                        attempt.target.clone(),
+                        result,
+                    ]);
+                }
+                println!("{}", builder.build().with(Style::rounded()));
+            } else {
+                println!("attempts: none recorded");
+            }
+
+            if !response.blocks.is_empty() {
+                let mut builder = Builder::default();
+                builder.push_record(["Method", "Target", "State", "Until", "Reason"]);
+                for block in &response.blocks {
+                    let state = if block.blacklisted {
+                        "blacklisted"
+                    } else {
+                        "backoff"
+                    };
+                    builder.push_record([
+                        method_str(block.method),
+                        block.target.clone(),
+                        state.to_string(),
+                        time_str(block.until_unix),
+                        block.reason.clone(),
+                    ]);
+                }
+                println!("{}", builder.build().with(Style::rounded()));
+            }
+
+            println!("verdict: {}", response.verdict);
+            for suggestion in &response.suggestions {
+                println!("  - {}", suggestion);
+            }
+            Ok(())
+        })
+    }
+
+    async fn handle_secret_rotation(&self) -> Result<(), Error> {
+        let results = self
+            .collect_instance_results(|handler| {
+                Box::pin(async move {
+                    handler
+                        .get_peer_manager_client()
+                        .await?
+                        .get_secret_rotation_status(
+                            BaseController::default(),
+                            GetSecretRotationStatusRequest {
+                                instance: Some(handler.instance_selector.clone()),
+                            },
+                        )
+                        .await
+                        .map_err(Into::into)
+                })
+            })
+            .await?;
+
+        if *self.output_format == OutputFormat::Json {
+            return self.print_json_results(results);
+        }
+
+        #[derive(tabled::Tabled, serde::Serialize)]
+        struct SecretRotationTableItem {
+            peer_id: u32,
+            hostname: String,
+            secret: String,
+            activate_at: String,
+        }
+
+        self.print_results(&results, |response| {
+            let time_str = |time_unix: u64| {
+                chrono::DateTime::from_timestamp(time_unix as i64, 0)
+                    .map(|t| {
+                        t.with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                            .to_string()
+                    })
+                    .unwrap_or_default()
+            };
+
+            let state = match response.state() {
+                SecretRotationState::RotationIdle => "idle".to_string(),
+                SecretRotationState::RotationStaged => {
+                    "next secret staged, waiting for activation time".to_string()
+                }
+                SecretRotationState::RotationScheduled => {
+                    format!("switching at {}", time_str(response.activate_at))
+                }
+                SecretRotationState::RotationSwitched => format!(
+                    "switched, previous secret accepted until {}",
+                    time_str(response.previous_accepted_until)
+                ),
+            };
+            println!("state: {} (generation {})", state, response.generation);
+
+            let ready = response
+                .peers
+                .iter()
+                .filter(|peer| {
+                    matches!(
+                        peer.state(),
+                        PeerSecretState::PeerSecretActive | PeerSecretState::PeerSecretStaged
+                    )
+                })
+                .count();
+            println!("progress: {}/{} peers ready", ready, response.peers.len());
+
+            let rows = response
+                .peers
+                .iter()
+                .map(|peer| SecretRotationTableItem {
+                    peer_id: peer.peer_id,
+                    hostname: peer.hostname.clone(),
+                    secret: match peer.state() {
+                        PeerSecretState::PeerSecretUnknown => "unknown",
+                        PeerSecretState::PeerSecretActive => "in use",
+                        PeerSecretState::PeerSecretStaged => "staged",
+                        PeerSecretState::PeerSecretMissing => "not staged",
+                    }
+                    .to_string(),
+                    activate_at: if peer.activate_at == 0 {
+                        "-".to_string()
+                    } else {
+                        time_str(peer.activate_at)
+                    },
+                })
+                .collect::<Vec<_>>();
+            print_output(&rows, self.output_format, &[], &[], self.no_trunc)
+        })
+    }
+
     async fn handle_connector_list(&self) -> Result<(), Error> {
         let results = self
             .collect_instance_results(|handler| Box::pin(handler.fetch_connector_list()))
@@ -1852,6 +2319,63 @@ impl<'a> CommandHandler<'a> {
         })
     }
 
+    async fn handle_lan_discovered_peers(&self) -> Result<(), Error> {
+        let results = self
+            .collect_instance_results(|handler| {
+                Box::pin(async move {
+                    handler
+                        .get_connector_manager_client()
+                        .await?
+                        .list_lan_discovered_peers(
+                            BaseController::default(),
+                            ListLanDiscoveredPeersRequest {
+                                instance: Some(handler.instance_selector.clone()),
+                            },
+                        )
+                        .await
+                        .map_err(Into::into)
+                })
+            })
+            .await?;
+
+        if *self.output_format == OutputFormat::Json {
+            return self.print_json_results(results);
+        }
+
+        self.print_results(&results, |response| {
+            if !response.enabled {
+                println!("lan discovery is not enabled, start with --enable-lan-discovery");
+                return Ok(());
+            }
+            if response.peers.is_empty() {
+                println!("No peers discovered in lan");
+                return Ok(());
+            }
+
+            use tabled::{builder::Builder, settings::Style};
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64;
+            let mut builder = Builder::default();
+            builder.push_record(["Peer Id", "Source", "Last Seen", "Connector", "Direct"]);
+            for peer in &response.peers {
+                builder.push_record([
+                    peer.peer_id.to_string(),
+                    peer.source_addr.clone(),
+                    format!("{}s ago", (now - peer.last_seen_unix).max(0)),
+                    peer.connector_url
+                        .as_ref()
+                        .map(ToString::to_string)
+                        .unwrap_or_else(|| "-".to_string()),
+                    peer.directly_connected.to_string(),
+                ]);
+            }
+            println!("{}", builder.build().with(Style::rounded()));
+            Ok(())
+        })
+    }
+
     async fn handle_acl_stats(&self) -> Result<(), Error> {
         let results = self
             .collect_instance_results(|handler| Box::pin(handler.fetch_acl_stats()))
@@ -2193,6 +2717,12 @@ impl<'a> CommandHandler<'a> {
                     LogLevel::Trace => "trace",
                 };
                 println!("Current Log Level: {}", level_str);
+                if !matches!(
+                    response.filter.as_str(),
+                    "" | "off" | "error" | "warn" | "info" | "debug" | "trace"
+                ) {
+                    println!("Current Log Filter: {}", response.filter);
+                }
             }
             OutputFormat::Json => {
                 let json = serde_json::to_string_pretty(&response)?;
@@ -2204,24 +2734,28 @@ impl<'a> CommandHandler<'a> {
     }
 
     async fn handle_logger_set(&self, level: &str) -> Result<(), Error> {
-        let log_level = match level.to_lowercase().as_str() {
-            "disabled" => LogLevel::Disabled,
-            "error" => LogLevel::Error,
-            "warning" => LogLevel::Warning,
-            "info" => LogLevel::Info,
-            "debug" => LogLevel::Debug,
-            "trace" => LogLevel::Trace,
-            _ => {
+        // anything that is not a plain level is sent as filter directives and
+        // validated by the node
+        let (log_level, filter) = match level.to_lowercase().as_str() {
+            "disabled" => (LogLevel::Disabled, None),
+            "error" => (LogLevel::Error, None),
+            "warning" => (LogLevel::Warning, None),
+            "info" => (LogLevel::Info, None),
+            "debug" => (LogLevel::Debug, None),
+            "trace" => (LogLevel::Trace, None),
+            _ if level.trim().is_empty() => {
                 return Err(anyhow::anyhow!(
                     "Invalid log level: {}. Valid levels are: disabled, error, warning, info, debug, trace",
                     level
                 ));
             }
+            _ => (LogLevel::Info, Some(level.trim().to_string())),
         };
 
         let client = self.get_logger_client().await?;
         let request = SetLoggerConfigRequest {
             level: log_level.into(),
+            filter,
         };
         let response = client
             .set_logger_config(BaseController::default(), request)
@@ -2240,6 +2774,89 @@ impl<'a> CommandHandler<'a> {
         Ok(())
     }
 
+    fn print_log_event(&self, event: &LogEvent) -> Result<(), Error> {
+        match self.output_format {
+            OutputFormat::Table => {
+                let time = chrono::DateTime::from_timestamp_millis(event.timestamp_ms)
+                    .map(|t| {
+                        t.with_timezone(&chrono::Local)
+                            .format("%Y-%m-%d %H:%M:%S%.3f")
+                            .to_string()
+                    })
+                    .unwrap_or_default();
+                let level = match event.level() {
+                    LogLevel::Disabled => "OFF",
+                    LogLevel::Error => "ERROR",
+                    LogLevel::Warning => "WARN",
+                    LogLevel::Info => "INFO",
+                    LogLevel::Debug => "DEBUG",
+                    LogLevel::Trace => "TRACE",
+                };
+                let mut line = format!("{} {:>5} {}: {}", time, level, event.target, event.message);
+                for (k, v) in event.fields.iter() {
+                    line.push_str(&format!(" {}={}", k, v));
+                }
+                println!("{}", line);
+            }
+            OutputFormat::Json => {
+                println!("{}", serde_json::to_string(event)?);
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_logger_tail(
+        &self,
+        filter: Option<&str>,
+        lines: u32,
+        follow: bool,
+    ) -> Result<(), Error> {
+        let client = self.get_logger_client().await?;
+        let filter = filter.unwrap_or_default().to_string();
+
+        let mut resp = client
+            .tail_log(
+                BaseController::default(),
+                TailLogRequest {
+                    filter: filter.clone(),
+                    after_seq: 0,
+                    wait_ms: 0,
+                    max_events: lines,
+                },
+            )
+            .await?;
+        let mut ctrl_c = std::pin::pin!(tokio::signal::ctrl_c());
+        loop {
+            if resp.missed > 0 {
+                eprintln!(
+                    "... {} events dropped from the node log buffer",
+                    resp.missed
+                );
+            }
+            for event in resp.events.iter() {
+                self.print_log_event(event)?;
+            }
+            if !follow {
+                return Ok(());
+            }
+
+            let request = TailLogRequest {
+                filter: filter.clone(),
+                after_seq: resp.last_seq,
+                wait_ms: 5000,
+                max_events: 0,
+            };
+            let ctrl = BaseController {
+                timeout_ms: 10000,
+                ..Default::default()
+            };
+            tokio::select! {
+                _ = &mut ctrl_c => return Ok(()),
+                next = client.tail_log(ctrl, request) => resp = next?,
+            }
+        }
+    }
+
     async fn handle_credential_generate(
         &self,
         ttl: i64,
@@ -2459,6 +3076,184 @@ impl<'a> CommandHandler<'a> {
         })
     }
 
+    async fn handle_vpn_portal_client_add(
+        &self,
+        name: &str,
+        public_key: Option<String>,
+        ttl: i64,
+    ) -> Result<(), Error> {
+        let name = name.to_string();
+        let results = self
+            .collect_instance_results(|handler| {
+                let name = name.clone();
+                let public_key = public_key.clone();
+                Box::pin(async move {
+                    handler
+                        .get_vpn_portal_client()
+                        .await?
+                        .add_vpn_portal_client(
+                            BaseController::default(),
+                            AddVpnPortalClientRequest {
+                                instance: Some(handler.instance_selector.clone()),
+                                name,
+                                public_key,
+                                ttl_seconds: ttl,
+                            },
+                        )
+                        .await
+                        .map_err(Into::into)
+                })
+            })
+            .await?;
+
+        if *self.output_format == OutputFormat::Json {
+            return self.print_json_results(results);
+        }
+
+        self.print_results(&results, |response| {
+            let client = response.client.clone().unwrap_or_default();
+            println!("Client added successfully:");
+            println!("  name:       {}", client.name);
+            println!("  address:    {}", client.address);
+            println!("  public_key: {}", client.public_key);
+            println!(
+                r#"
+############### client_config_start ###############
+{}
+############### client_config_end ###############
+"#,
+                response.client_config
+            );
+            Ok(())
+        })
+    }
+
+    async fn handle_vpn_portal_client_remove(&self, name: &str) -> Result<(), Error> {
+        let name = name.to_string();
+        let results = self
+            .collect_instance_results(|handler| {
+                let name = name.clone();
+                Box::pin(async move {
+                    handler
+                        .get_vpn_portal_client()
+                        .await?
+                        .remove_vpn_portal_client(
+                            BaseController::default(),
+                            RemoveVpnPortalClientRequest {
+                                instance: Some(handler.instance_selector.clone()),
+                                name,
+                            },
+                        )
+                        .await
+                        .map_err(Into::into)
+                })
+            })
+            .await?;
+
+        if *self.output_format == OutputFormat::Json {
+            return self.print_json_results(results);
+        }
+
+        self.print_results(&results, |response| {
+            if response.success {
+                println!("Client removed successfully");
+            } else {
+                println!("Client not found");
+            }
+            Ok(())
+        })
+    }
+
+    async fn handle_vpn_portal_client_list(&self) -> Result<(), Error> {
+        let results = self
+            .collect_instance_results(|handler| {
+                Box::pin(async move {
+                    handler
+                        .get_vpn_portal_client()
+                        .await?
+                        .list_vpn_portal_clients(
+                            BaseController::default(),
+                            ListVpnPortalClientsRequest {
+                                instance: Some(handler.instance_selector.clone()),
+                            },
+                        )
+                        .await
+                        .map_err(Into::into)
+                })
+            })
+            .await?;
+
+        if *self.output_format == OutputFormat::Json {
+            return self.print_json_results(results);
+        }
+
+        self.print_results(&results, |response| {
+            if response.clients.is_empty() {
+                println!("No vpn portal clients");
+                return Ok(());
+            }
+
+            use tabled::{builder::Builder, settings::Style};
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs() as i64;
+            let mut builder = Builder::default();
+            builder.push_record(["Name", "Address", "Public Key", "Expiry", "Endpoint"]);
+            for client in &response.clients {
+                let expiry = if client.expiry_unix == 0 {
+                    "never".to_string()
+                } else if client.expiry_unix > now {
+                    format!("{}s remaining", client.expiry_unix - now)
+                } else {
+                    "expired".to_string()
+                };
+                builder.push_record([
+                    &client.name[..],
+                    &client.address,
+                    &client.public_key,
+                    &expiry,
+                    client.endpoint.as_deref().unwrap_or("-"),
+                ]);
+            }
+            let table = builder.build().with(Style::rounded()).to_string();
+            println!("{}", table);
+            Ok(())
+        })
+    }
+
+    async fn handle_vpn_portal_client_config(&self, name: &str) -> Result<(), Error> {
+        let name = name.to_string();
+        let results = self
+            .collect_instance_results(|handler| {
+                let name = name.clone();
+                Box::pin(async move {
+                    handler
+                        .get_vpn_portal_client()
+                        .await?
+                        .get_vpn_portal_client_config(
+                            BaseController::default(),
+                            GetVpnPortalClientConfigRequest {
+                                instance: Some(handler.instance_selector.clone()),
+                                name,
+                            },
+                        )
+                        .await
+                        .map_err(Into::into)
+                })
+            })
+            .await?;
+
+        if *self.output_format == OutputFormat::Json {
+            return self.print_json_results(results);
+        }
+
+        self.print_results(&results, |response| {
+            println!("{}", response.client_config);
+            Ok(())
+        })
+    }
+
     async fn handle_node(&self, sub_command: Option<&NodeSubCommand>) -> Result<(), Error> {
         let results = self
             .collect_instance_results(|handler| Box::pin(handler.fetch_node_info()))
@@ -2912,6 +3707,9 @@ async fn main() -> Result<(), Error> {
             Some(ConnectorSubCommand::List) => {
                 handler.handle_connector_list().await?;
             }
+            Some(ConnectorSubCommand::Discovered) => {
+                handler.handle_lan_discovered_peers().await?;
+            }
             None => {
                 handler.handle_connector_list().await?;
             }
@@ -2966,9 +3764,38 @@ async fn main() -> Result<(), Error> {
         SubCommand::PeerCenter => {
             handler.handle_peer_center().await?;
         }
-        SubCommand::VpnPortal => {
-            handler.handle_vpn_portal().await?;
+        SubCommand::Diagnose { peer } => {
+            handler.handle_diagnose(peer).await?;
+        }
+        SubCommand::Capture(capture_args) => {
+            handler.handle_capture(capture_args).await?;
         }
+        SubCommand::SecretRotation => {
+            handler.handle_secret_rotation().await?;
+        }
+        SubCommand::VpnPortal(vpn_portal_args) => match &vpn_portal_args.sub_command {
+            None | Some(VpnPortalSubCommand::Show) => {
+                handler.handle_vpn_portal().await?;
+            }
+            Some(VpnPortalSubCommand::Add {
+                name,
+                public_key,
+                ttl,
+            }) => {
+                handler
+                    .handle_vpn_portal_client_add(name, public_key.clone(), *ttl)
+                    .await?;
+            }
+            Some(VpnPortalSubCommand::Remove { name }) => {
+                handler.handle_vpn_portal_client_remove(name).await?;
+            }
+            Some(VpnPortalSubCommand::List) => {
+                handler.handle_vpn_portal_client_list().await?;
+            }
+            Some(VpnPortalSubCommand::Config { name }) => {
+                handler.handle_vpn_portal_client_config(name).await?;
+            }
+        },
         SubCommand::Node(sub_cmd) => {
             handler.handle_node(sub_cmd.sub_command.as_ref()).await?;
         }
@@ -3169,6 +3996,15 @@ async fn main() -> Result<(), Error> {
             Some(LoggerSubCommand::Set { level }) => {
                 handler.handle_logger_set(level).await?;
             }
+            Some(LoggerSubCommand::Tail {
+                filter,
+                lines,
+                follow,
+            }) => {
+                handler
+                    .handle_logger_tail(filter.as_deref(), *lines, *follow)
+                    .await?;
+            }
         },
         SubCommand::Credential(credential_args) => match &credential_args.sub_command {
             CredentialSubCommand::Generate {
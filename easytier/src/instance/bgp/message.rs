@@ -0,0 +1,567 @@
+// bgp-4 (rfc 4271) messages, only what a stub speaker exchanging ipv4 unicast
+// routes with a single neighbor needs.
+
+use std::net::Ipv4Addr;
+
+use bytes::{Buf, BufMut, BytesMut};
+use cidr::Ipv4Cidr;
+use tokio_util::codec::{Decoder, Encoder};
+
+pub const BGP_PORT: u16 = 179;
+pub const BGP_VERSION: u8 = 4;
+// placeholder for a 4 byte as number in 2 byte fields (rfc 6793)
+pub const AS_TRANS: u16 = 23456;
+
+const HEADER_LEN: usize = 19;
+const MAX_MESSAGE_LEN: usize = 4096;
+// at most 5 bytes each, keeps an update well below MAX_MESSAGE_LEN
+pub const MAX_PREFIXES_PER_UPDATE: usize = 500;
+
+const MSG_OPEN: u8 = 1;
+const MSG_UPDATE: u8 = 2;
+const MSG_NOTIFICATION: u8 = 3;
+const MSG_KEEPALIVE: u8 = 4;
+
+const OPT_PARAM_CAPABILITY: u8 = 2;
+const CAP_MULTIPROTOCOL: u8 = 1;
+const CAP_FOUR_OCTET_AS: u8 = 65;
+
+const ATTR_FLAG_TRANSITIVE: u8 = 0x40;
+const ATTR_FLAG_EXTENDED_LEN: u8 = 0x10;
+
+const ATTR_ORIGIN: u8 = 1;
+const ATTR_AS_PATH: u8 = 2;
+const ATTR_NEXT_HOP: u8 = 3;
+const ATTR_LOCAL_PREF: u8 = 5;
+
+const ORIGIN_IGP: u8 = 0;
+const AS_SET: u8 = 1;
+const AS_SEQUENCE: u8 = 2;
+
+// notification error codes
+pub const ERR_HEADER: u8 = 1;
+pub const ERR_OPEN: u8 = 2;
+pub const ERR_UPDATE: u8 = 3;
+pub const ERR_HOLD_TIMER_EXPIRED: u8 = 4;
+pub const ERR_FSM: u8 = 5;
+pub const ERR_CEASE: u8 = 6;
+
+pub const OPEN_ERR_UNSUPPORTED_VERSION: u8 = 1;
+pub const OPEN_ERR_BAD_PEER_AS: u8 = 2;
+pub const OPEN_ERR_BAD_BGP_ID: u8 = 3;
+pub const OPEN_ERR_UNACCEPTABLE_HOLD_TIME: u8 = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenMessage {
+    pub my_as: u32,
+    pub hold_time: u16,
+    pub router_id: Ipv4Addr,
+    pub four_octet_as: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct UpdateMessage {
+    pub withdrawn: Vec<Ipv4Cidr>,
+    // flattened as numbers of all as path segments
+    pub as_path: Vec<u32>,
+    pub next_hop: Option<Ipv4Addr>,
+    pub local_pref: Option<u32>,
+    pub nlri: Vec<Ipv4Cidr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NotificationMessage {
+    pub code: u8,
+    pub subcode: u8,
+    pub data: Vec<u8>,
+}
+
+impl NotificationMessage {
+    pub fn new(code: u8, subcode: u8) -> Self {
+        Self {
+            code,
+            subcode,
+            data: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BgpMessage {
+    Open(OpenMessage),
+    Update(UpdateMessage),
+    Notification(NotificationMessage),
+    Keepalive,
+}
+
+/// A malformed message, carries the notification to send before closing.
+#[derive(Debug, thiserror::Error)]
+#[error("malformed bgp message: {reason}")]
+pub struct BgpDecodeError {
+    pub notification: NotificationMessage,
+    pub reason: String,
+}
+
+impl From<std::io::Error> for BgpDecodeError {
+    fn from(e: std::io::Error) -> Self {
+        BgpDecodeError {
+            notification: NotificationMessage::new(ERR_CEASE, 0),
+            reason: e.to_string(),
+        }
+    }
+}
+
+fn malformed(code: u8, subcode: u8, reason: impl Into<String>) -> BgpDecodeError {
+    BgpDecodeError {
+        notification: NotificationMessage::new(code, subcode),
+        reason: reason.into(),
+    }
+}
+
+fn malformed_update(reason: impl Into<String>) -> BgpDecodeError {
+    // 3/1 malformed attribute list
+    malformed(ERR_UPDATE, 1, reason)
+}
+
+fn put_prefix(buf: &mut BytesMut, prefix: &Ipv4Cidr) {
+    let len = prefix.network_length();
+    buf.put_u8(len);
+    let octets = prefix.first_address().octets();
+    buf.put_slice(&octets[..(len as usize).div_ceil(8)]);
+}
+
+fn get_prefixes(mut buf: &[u8]) -> Result<Vec<Ipv4Cidr>, BgpDecodeError> {
+    let mut prefixes = Vec::new();
+    while buf.has_remaining() {
+        let len = buf.get_u8();
+        let byte_len = (len as usize).div_ceil(8);
+        if len > 32 || buf.remaining() < byte_len {
+            // 3/10 invalid network field
+            return Err(malformed(ERR_UPDATE, 10, "invalid prefix"));
+        }
+        let mut octets = [0u8; 4];
+        buf.copy_to_slice(&mut octets[..byte_len]);
+        // host bits are not guaranteed to be zero
+        let addr = u32::from_be_bytes(octets) & u32::MAX.checked_shl(32 - len as u32).unwrap_or(0);
+        prefixes.push(Ipv4Cidr::new(addr.into(), len).unwrap());
+    }
+    Ok(prefixes)
+}
+
+fn put_attr(buf: &mut BytesMut, flags: u8, type_code: u8, value: &[u8]) {
+    if value.len() > u8::MAX as usize {
+        buf.put_u8(flags | ATTR_FLAG_EXTENDED_LEN);
+        buf.put_u8(type_code);
+        buf.put_u16(value.len() as u16);
+    } else {
+        buf.put_u8(flags);
+        buf.put_u8(type_code);
+        buf.put_u8(value.len() as u8);
+    }
+    buf.put_slice(value);
+}
+
+impl OpenMessage {
+    fn encode(&self, buf: &mut BytesMut) {
+        buf.put_u8(BGP_VERSION);
+        buf.put_u16(u16::try_from(self.my_as).unwrap_or(AS_TRANS));
+        buf.put_u16(self.hold_time);
+        buf.put_slice(&self.router_id.octets());
+
+        let mut caps = BytesMut::new();
+        // ipv4 unicast
+        caps.put_slice(&[CAP_MULTIPROTOCOL, 4, 0, 1, 0, 1]);
+        if self.four_octet_as {
+            caps.put_slice(&[CAP_FOUR_OCTET_AS, 4]);
+            caps.put_u32(self.my_as);
+        }
+        buf.put_u8(caps.len() as u8 + 2);
+        buf.put_u8(OPT_PARAM_CAPABILITY);
+        buf.put_u8(caps.len() as u8);
+        buf.put_slice(&caps);
+    }
+
+    fn decode(mut buf: &[u8]) -> Result<Self, BgpDecodeError> {
+        if buf.remaining() < 10 {
+            return Err(malformed(ERR_HEADER, 2, "open too short"));
+        }
+        let version = buf.get_u8();
+        if version != BGP_VERSION {
+            let mut n = NotificationMessage::new(ERR_OPEN, OPEN_ERR_UNSUPPORTED_VERSION);
+            n.data = vec![BGP_VERSION];
+            return Err(BgpDecodeError {
+                notification: n,
+                reason: format!("unsupported version {}", version),
+            });
+        }
+        let mut my_as = buf.get_u16() as u32;
+        let hold_time = buf.get_u16();
+        let router_id = Ipv4Addr::from(buf.get_u32());
+        let opt_len = buf.get_u8() as usize;
+        if buf.remaining() != opt_len {
+            return Err(malformed(ERR_OPEN, 0, "bad optional parameter length"));
+        }
+
+        let mut four_octet_as = false;
+        while buf.has_remaining() {
+            if buf.remaining() < 2 {
+                return Err(malformed(ERR_OPEN, 0, "truncated optional parameter"));
+            }
+            let param_type = buf.get_u8();
+            let param_len = buf.get_u8() as usize;
+            if buf.remaining() < param_len {
+                return Err(malformed(ERR_OPEN, 0, "truncated optional parameter"));
+            }
+            let (mut param, rest) = buf.split_at(param_len);
+            buf = rest;
+            if param_type != OPT_PARAM_CAPABILITY {
+                continue;
+            }
+            while param.remaining() >= 2 {
+                let code = param.get_u8();
+                let len = param.get_u8() as usize;
+                if param.remaining() < len {
+                    return Err(malformed(ERR_OPEN, 0, "truncated capability"));
+                }
+                if code == CAP_FOUR_OCTET_AS && len == 4 {
+                    four_octet_as = true;
+                    my_as = u32::from_be_bytes(param[..4].try_into().unwrap());
+                }
+                param.advance(len);
+            }
+        }
+
+        Ok(OpenMessage {
+            my_as,
+            hold_time,
+            router_id,
+            four_octet_as,
+        })
+    }
+}
+
+impl UpdateMessage {
+    fn encode(&self, buf: &mut BytesMut, four_octet_as: bool) {
+        let mut withdrawn = BytesMut::new();
+        for prefix in &self.withdrawn {
+            put_prefix(&mut withdrawn, prefix);
+        }
+        buf.put_u16(withdrawn.len() as u16);
+        buf.put_slice(&withdrawn);
+
+        let mut attrs = BytesMut::new();
+        if !self.nlri.is_empty() {
+            put_attr(&mut attrs, ATTR_FLAG_TRANSITIVE, ATTR_ORIGIN, &[ORIGIN_IGP]);
+
+            let mut as_path = BytesMut::new();
+            if !self.as_path.is_empty() {
+                as_path.put_u8(AS_SEQUENCE);
+                as_path.put_u8(self.as_path.len() as u8);
+                for asn in &self.as_path {
+                    if four_octet_as {
+                        as_path.put_u32(*asn);
+                    } else {
+                        as_path.put_u16(u16::try_from(*asn).unwrap_or(AS_TRANS));
+                    }
+                }
+            }
+            put_attr(&mut attrs, ATTR_FLAG_TRANSITIVE, ATTR_AS_PATH, &as_path);
+
+            if let Some(next_hop) = self.next_hop {
+                put_attr(
+                    &mut attrs,
+                    ATTR_FLAG_TRANSITIVE,
+                    ATTR_NEXT_HOP,
+                    &next_hop.octets(),
+                );
+            }
+            if let Some(local_pref) = self.local_pref {
+                put_attr(
+                    &mut attrs,
+                    ATTR_FLAG_TRANSITIVE,
+                    ATTR_LOCAL_PREF,
+                    &local_pref.to_be_bytes(),
+                );
+            }
+        }
+        buf.put_u16(attrs.len() as u16);
+        buf.put_slice(&attrs);
+
+        for prefix in &self.nlri {
+            put_prefix(buf, prefix);
+        }
+    }
+
+    fn decode(mut buf: &[u8], four_octet_as: bool) -> Result<Self, BgpDecodeError> {
+        let mut update = UpdateMessage::default();
+
+        if buf.remaining() < 2 {
+            return Err(malformed_update("missing withdrawn routes length"));
+        }
+        let withdrawn_len = buf.get_u16() as usize;
+        if buf.remaining() < withdrawn_len + 2 {
+            return Err(malformed_update("bad withdrawn routes length"));
+        }
+        update.withdrawn = get_prefixes(&buf[..withdrawn_len])?;
+        buf.advance(withdrawn_len);
+
+        let attrs_len = buf.get_u16() as usize;
+        if buf.remaining() < attrs_len {
+            return Err(malformed_update("bad path attribute length"));
+        }
+        let (mut attrs, nlri) = buf.split_at(attrs_len);
+        update.nlri = get_prefixes(nlri)?;
+
+        while attrs.has_remaining() {
+            if attrs.remaining() < 3 {
+                return Err(malformed_update("truncated path attribute"));
+            }
+            let flags = attrs.get_u8();
+            let type_code = attrs.get_u8();
+            let len = if flags & ATTR_FLAG_EXTENDED_LEN != 0 {
+                if attrs.remaining() < 2 {
+                    return Err(malformed_update("truncated path attribute"));
+                }
+                attrs.get_u16() as usize
+            } else {
+                attrs.get_u8() as usize
+            };
+            if attrs.remaining() < len {
+                return Err(malformed_update("truncated path attribute"));
+            }
+            let (mut value, rest) = attrs.split_at(len);
+            attrs = rest;
+
+            match type_code {
+                ATTR_AS_PATH => {
+                    let as_len = if four_octet_as { 4 } else { 2 };
+                    while value.has_remaining() {
+                        if value.remaining() < 2 {
+                            return Err(malformed(ERR_UPDATE, 11, "malformed as path"));
+                        }
+                        let segment_type = value.get_u8();
+                        let count = value.get_u8() as usize;
+                        if !matches!(segment_type, AS_SET | AS_SEQUENCE)
+                            || value.remaining() < count * as_len
+                        {
+                            return Err(malformed(ERR_UPDATE, 11, "malformed as path"));
+                        }
+                        for _ in 0..count {
+                            let asn = if four_octet_as {
+                                value.get_u32()
+                            } else {
+                                value.get_u16() as u32
+                            };
+                            update.as_path.push(asn);
+                        }
+                    }
+                }
+                ATTR_NEXT_HOP | ATTR_LOCAL_PREF => {
+                    if len != 4 {
+                        // 3/5 attribute length error
+                        return Err(malformed(ERR_UPDATE, 5, "bad attribute length"));
+                    }
+                    if type_code == ATTR_NEXT_HOP {
+                        update.next_hop = Some(Ipv4Addr::from(value.get_u32()));
+                    } else {
+                        update.local_pref = Some(value.get_u32());
+                    }
+                }
+                // origin and unknown attributes are ignored, we don't propagate routes
+                _ => {}
+            }
+        }
+
+        Ok(update)
+    }
+}
+
+impl BgpMessage {
+    pub fn encode(&self, buf: &mut BytesMut, four_octet_as: bool) {
+        let start = buf.len();
+        buf.put_slice(&[0xff; 16]);
+        buf.put_u16(0);
+        match self {
+            BgpMessage::Open(open) => {
+                buf.put_u8(MSG_OPEN);
+                open.encode(buf);
+            }
+            BgpMessage::Update(update) => {
+                buf.put_u8(MSG_UPDATE);
+                update.encode(buf, four_octet_as);
+            }
+            BgpMessage::Notification(n) => {
+                buf.put_u8(MSG_NOTIFICATION);
+                buf.put_u8(n.code);
+                buf.put_u8(n.subcode);
+                buf.put_slice(&n.data);
+            }
+            BgpMessage::Keepalive => {
+                buf.put_u8(MSG_KEEPALIVE);
+            }
+        }
+        let len = (buf.len() - start) as u16;
+        buf[start + 16..start + 18].copy_from_slice(&len.to_be_bytes());
+    }
+
+    pub fn decode(msg_type: u8, body: &[u8], four_octet_as: bool) -> Result<Self, BgpDecodeError> {
+        match msg_type {
+            MSG_OPEN => Ok(BgpMessage::Open(OpenMessage::decode(body)?)),
+            MSG_UPDATE => Ok(BgpMessage::Update(UpdateMessage::decode(
+                body,
+                four_octet_as,
+            )?)),
+            MSG_NOTIFICATION if body.len() >= 2 => {
+                Ok(BgpMessage::Notification(NotificationMessage {
+                    code: body[0],
+                    subcode: body[1],
+                    data: body[2..].to_vec(),
+                }))
+            }
+            MSG_KEEPALIVE if body.is_empty() => Ok(BgpMessage::Keepalive),
+            // 1/2 bad message length, 1/3 bad message type
+            MSG_NOTIFICATION | MSG_KEEPALIVE => Err(malformed(ERR_HEADER, 2, "bad length")),
+            _ => Err(malformed(ERR_HEADER, 3, format!("bad type {}", msg_type))),
+        }
+    }
+}
+
+/// Frames bgp messages on a tcp stream. `four_octet_as` is set once both
+/// sides announced the capability.
+#[derive(Debug, Default)]
+pub struct BgpCodec {
+    pub four_octet_as: bool,
+}
+
+impl Decoder for BgpCodec {
+    type Item = BgpMessage;
+    type Error = BgpDecodeError;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        if src.len() < HEADER_LEN {
+            return Ok(None);
+        }
+        if src[..16] != [0xff; 16] {
+            // 1/1 connection not synchronized
+            return Err(malformed(ERR_HEADER, 1, "bad marker"));
+        }
+        let len = u16::from_be_bytes([src[16], src[17]]) as usize;
+        if !(HEADER_LEN..=MAX_MESSAGE_LEN).contains(&len) {
+            return Err(malformed(ERR_HEADER, 2, format!("bad length {}", len)));
+        }
+        if src.len() < len {
+            src.reserve(len - src.len());
+            return Ok(None);
+        }
+
+        let msg = src.split_to(len);
+        BgpMessage::decode(msg[18], &msg[HEADER_LEN..], self.four_octet_as).map(Some)
+    }
+}
+
+impl Encoder<BgpMessage> for BgpCodec {
+    type Error = std::io::Error;
+
+    fn encode(&mut self, item: BgpMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        item.encode(dst, self.four_octet_as);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn roundtrip(msg: BgpMessage, four_octet_as: bool) -> BgpMessage {
+        let mut buf = BytesMut::new();
+        msg.encode(&mut buf, four_octet_as);
+        let mut codec = BgpCodec { four_octet_as };
+        let decoded = codec.decode(&mut buf).unwrap().unwrap();
+        assert!(buf.is_empty());
+        decoded
+    }
+
+    #[test]
+    fn open_roundtrip() {
+        let open = OpenMessage {
+            my_as: 4_200_000_001,
+            hold_time: 90,
+            router_id: Ipv4Addr::new(10, 0, 0, 1),
+            four_octet_as: true,
+        };
+        assert_eq!(
+            roundtrip(BgpMessage::Open(open.clone()), false),
+            BgpMessage::Open(open)
+        );
+
+        // without the capability the peer sees as_trans
+        let open = OpenMessage {
+            my_as: 65001,
+            hold_time: 0,
+            router_id: Ipv4Addr::new(10, 0, 0, 2),
+            four_octet_as: false,
+        };
+        assert_eq!(
+            roundtrip(BgpMessage::Open(open.clone()), false),
+            BgpMessage::Open(open)
+        );
+    }
+
+    #[test]
+    fn update_roundtrip() {
+        let update = UpdateMessage {
+            withdrawn: vec!["10.1.0.0/16".parse().unwrap()],
+            as_path: vec![65001, 65002],
+            next_hop: Some(Ipv4Addr::new(192, 168, 1, 2)),
+            local_pref: Some(100),
+            nlri: vec![
+                "10.126.126.0/24".parse().unwrap(),
+                "0.0.0.0/0".parse().unwrap(),
+                "172.16.0.0/12".parse().unwrap(),
+                "192.168.5.7/32".parse().unwrap(),
+            ],
+        };
+        for four_octet_as in [true, false] {
+            assert_eq!(
+                roundtrip(BgpMessage::Update(update.clone()), four_octet_as),
+                BgpMessage::Update(update.clone())
+            );
+        }
+
+        // host bits are masked
+        assert_eq!(
+            get_prefixes(&[20, 10, 1, 0xff]).unwrap(),
+            vec!["10.1.240.0/20".parse::<Ipv4Cidr>().unwrap()]
+        );
+        assert!(get_prefixes(&[33, 1, 2, 3, 4, 5]).is_err());
+        assert!(get_prefixes(&[24, 10, 1]).is_err());
+    }
+
+    #[test]
+    fn decoder_waits_for_full_message() {
+        let mut buf = BytesMut::new();
+        BgpMessage::Keepalive.encode(&mut buf, false);
+        BgpMessage::Notification(NotificationMessage::new(ERR_CEASE, 2)).encode(&mut buf, false);
+
+        let mut codec = BgpCodec::default();
+        let mut partial = buf.split_to(10);
+        assert!(codec.decode(&mut partial).unwrap().is_none());
+        partial.unsplit(buf);
+        assert_eq!(
+            codec.decode(&mut partial).unwrap(),
+            Some(BgpMessage::Keepalive)
+        );
+        assert_eq!(
+            codec.decode(&mut partial).unwrap(),
+            Some(BgpMessage::Notification(NotificationMessage::new(
+                ERR_CEASE, 2
+            )))
+        );
+
+        let mut bad = BytesMut::from(&[0u8; 19][..]);
+        assert_eq!(
+            codec.decode(&mut bad).unwrap_err().notification.code,
+            ERR_HEADER
+        );
+    }
+}
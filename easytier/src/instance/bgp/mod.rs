@@ -0,0 +1,722 @@
+// embedded bgp speaker. it keeps a single session with the lan router, announces
+// the virtual network and the proxy cidrs of remote peers to it, and optionally
+// learns the prefixes behind the router as local proxy cidrs, so site routers
+// don't need static routes back into the overlay.
+
+pub mod message;
+
+use std::{
+    collections::BTreeSet,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Weak},
+    time::Duration,
+};
+
+use anyhow::Context;
+use cidr::Ipv4Cidr;
+use futures::{SinkExt, StreamExt};
+use tokio::{
+    net::{
+        TcpListener, TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::watch,
+    time::Instant,
+};
+use tokio_util::{
+    codec::{FramedRead, FramedWrite},
+    task::AbortOnDropHandle,
+};
+
+use crate::{
+    common::{config::BgpConfig, global_ctx::ArcGlobalCtx},
+    peers::peer_manager::PeerManager,
+};
+
+use message::{
+    AS_TRANS, BGP_PORT, BgpCodec, BgpDecodeError, BgpMessage, ERR_CEASE, ERR_FSM,
+    ERR_HOLD_TIMER_EXPIRED, ERR_OPEN, MAX_PREFIXES_PER_UPDATE, NotificationMessage,
+    OPEN_ERR_BAD_BGP_ID, OPEN_ERR_BAD_PEER_AS, OPEN_ERR_UNACCEPTABLE_HOLD_TIME, OpenMessage,
+    UpdateMessage,
+};
+
+const DEFAULT_HOLD_TIME_SEC: u16 = 90;
+const CONNECT_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+// time to wait for the open and the first keepalive
+const OPEN_TIMEOUT: Duration = Duration::from_secs(30);
+const IBGP_LOCAL_PREF: u32 = 100;
+const EXPORT_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+type BgpReader = FramedRead<OwnedReadHalf, BgpCodec>;
+type BgpWriter = FramedWrite<OwnedWriteHalf, BgpCodec>;
+
+fn parse_neighbor(neighbor: &str) -> anyhow::Result<SocketAddr> {
+    if let Ok(addr) = neighbor.parse::<SocketAddr>() {
+        return Ok(addr);
+    }
+    let ip: IpAddr = neighbor
+        .parse()
+        .with_context(|| format!("invalid bgp neighbor: {}", neighbor))?;
+    Ok(SocketAddr::new(ip, BGP_PORT))
+}
+
+pub struct BgpSpeaker {
+    config: BgpConfig,
+    neighbor: SocketAddr,
+    router_id: Ipv4Addr,
+    exported: watch::Receiver<BTreeSet<Ipv4Cidr>>,
+    imported: watch::Sender<BTreeSet<Ipv4Cidr>>,
+}
+
+impl BgpSpeaker {
+    pub fn new(
+        config: BgpConfig,
+        router_id: Ipv4Addr,
+        exported: watch::Receiver<BTreeSet<Ipv4Cidr>>,
+    ) -> anyhow::Result<Self> {
+        let neighbor = parse_neighbor(&config.neighbor)?;
+        if config.local_as == 0 || config.remote_as == 0 {
+            anyhow::bail!("bgp local_as and remote_as must be set");
+        }
+        Ok(Self {
+            config,
+            neighbor,
+            router_id,
+            exported,
+            imported: watch::channel(BTreeSet::new()).0,
+        })
+    }
+
+    /// Prefixes currently accepted from the neighbor.
+    pub fn subscribe_imported(&self) -> watch::Receiver<BTreeSet<Ipv4Cidr>> {
+        self.imported.subscribe()
+    }
+
+    fn is_ebgp(&self) -> bool {
+        self.config.local_as != self.config.remote_as
+    }
+
+    /// Keeps a session with the neighbor up, only returns if the listener fails.
+    pub async fn run(self: Arc<Self>) -> anyhow::Result<()> {
+        if self.config.active.unwrap_or(false) {
+            loop {
+                match tokio::time::timeout(OPEN_TIMEOUT, TcpStream::connect(self.neighbor)).await {
+                    Ok(Ok(stream)) => self.handle_connection(stream).await,
+                    Ok(Err(e)) => {
+                        tracing::debug!(?e, neighbor = %self.neighbor, "bgp connect failed")
+                    }
+                    Err(_) => tracing::debug!(neighbor = %self.neighbor, "bgp connect timeout"),
+                }
+                tokio::time::sleep(CONNECT_RETRY_INTERVAL).await;
+            }
+        }
+
+        let listen = self
+            .config
+            .listen
+            .unwrap_or(SocketAddr::from(([0, 0, 0, 0], BGP_PORT)));
+        let listener = TcpListener::bind(listen)
+            .await
+            .with_context(|| format!("failed to listen bgp on {}", listen))?;
+        tracing::info!(%listen, neighbor = %self.neighbor, "bgp speaker waiting for neighbor");
+
+        let mut session = None;
+        loop {
+            let (stream, addr) = listener.accept().await?;
+            if addr.ip().to_canonical() != self.neighbor.ip().to_canonical() {
+                tracing::warn!(%addr, "reject bgp connection from unknown neighbor");
+                continue;
+            }
+            // a new connection from the neighbor replaces the current session,
+            // e.g. the router restarted and the old one waits for its hold timer.
+            if session.take().is_some() {
+                self.clear_imported();
+            }
+            let speaker = self.clone();
+            session = Some(AbortOnDropHandle::new(tokio::spawn(async move {
+                speaker.handle_connection(stream).await
+            })));
+        }
+    }
+
+    async fn handle_connection(&self, stream: TcpStream) {
+        if let Err(e) = self.run_session(stream).await {
+            tracing::warn!(?e, neighbor = %self.neighbor, "bgp session closed");
+        }
+        self.clear_imported();
+    }
+
+    fn clear_imported(&self) {
+        self.imported.send_if_modified(|imported| {
+            let changed = !imported.is_empty();
+            imported.clear();
+            changed
+        });
+    }
+
+    fn check_open(&self, open: &OpenMessage) -> Result<(), NotificationMessage> {
+        let remote_as = self.config.remote_as;
+        let as_matched = open.my_as == remote_as
+            || (!open.four_octet_as
+                && remote_as > u16::MAX as u32
+                && open.my_as == AS_TRANS as u32);
+        if !as_matched {
+            return Err(NotificationMessage::new(ERR_OPEN, OPEN_ERR_BAD_PEER_AS));
+        }
+        if open.router_id == self.router_id || open.router_id.is_unspecified() {
+            return Err(NotificationMessage::new(ERR_OPEN, OPEN_ERR_BAD_BGP_ID));
+        }
+        if open.hold_time == 1 || open.hold_time == 2 {
+            return Err(NotificationMessage::new(
+                ERR_OPEN,
+                OPEN_ERR_UNACCEPTABLE_HOLD_TIME,
+            ));
+        }
+        Ok(())
+    }
+
+    async fn check_received(
+        msg: Option<Result<BgpMessage, BgpDecodeError>>,
+        writer: &mut BgpWriter,
+    ) -> anyhow::Result<BgpMessage> {
+        match msg {
+            None => anyhow::bail!("connection closed by neighbor"),
+            Some(Err(e)) => {
+                let _ = writer
+                    .send(BgpMessage::Notification(e.notification.clone()))
+                    .await;
+                Err(e.into())
+            }
+            Some(Ok(BgpMessage::Notification(n))) => {
+                anyhow::bail!("neighbor sent notification {}/{}", n.code, n.subcode)
+            }
+            Some(Ok(msg)) => Ok(msg),
+        }
+    }
+
+    async fn recv_with_timeout(
+        reader: &mut BgpReader,
+        writer: &mut BgpWriter,
+    ) -> anyhow::Result<BgpMessage> {
+        let msg = tokio::time::timeout(OPEN_TIMEOUT, reader.next())
+            .await
+            .context("timeout waiting for bgp neighbor")?;
+        Self::check_received(msg, writer).await
+    }
+
+    async fn close_with(
+        writer: &mut BgpWriter,
+        notification: NotificationMessage,
+        reason: &str,
+    ) -> anyhow::Error {
+        let err = anyhow::anyhow!(
+            "{} (notification {}/{})",
+            reason,
+            notification.code,
+            notification.subcode
+        );
+        let _ = writer.send(BgpMessage::Notification(notification)).await;
+        err
+    }
+
+    async fn run_session(&self, stream: TcpStream) -> anyhow::Result<()> {
+        stream.set_nodelay(true)?;
+        let local_addr = stream.local_addr()?;
+        let (r, w) = stream.into_split();
+        let mut reader = FramedRead::new(r, BgpCodec::default());
+        let mut writer = FramedWrite::new(w, BgpCodec::default());
+
+        let hold_time = self.config.hold_time_sec.unwrap_or(DEFAULT_HOLD_TIME_SEC);
+        writer
+            .send(BgpMessage::Open(OpenMessage {
+                my_as: self.config.local_as,
+                hold_time,
+                router_id: self.router_id,
+                four_octet_as: true,
+            }))
+            .await?;
+
+        let open = match Self::recv_with_timeout(&mut reader, &mut writer).await? {
+            BgpMessage::Open(open) => open,
+            _ => {
+                let n = NotificationMessage::new(ERR_FSM, 0);
+                return Err(Self::close_with(&mut writer, n, "expect open").await);
+            }
+        };
+        if let Err(n) = self.check_open(&open) {
+            return Err(Self::close_with(&mut writer, n, "unacceptable open").await);
+        }
+        reader.decoder_mut().four_octet_as = open.four_octet_as;
+        writer.encoder_mut().four_octet_as = open.four_octet_as;
+        let hold_time = hold_time.min(open.hold_time);
+
+        writer.send(BgpMessage::Keepalive).await?;
+        if !matches!(
+            Self::recv_with_timeout(&mut reader, &mut writer).await?,
+            BgpMessage::Keepalive
+        ) {
+            let n = NotificationMessage::new(ERR_FSM, 0);
+            return Err(Self::close_with(&mut writer, n, "expect keepalive").await);
+        }
+        tracing::info!(
+            neighbor = %self.neighbor,
+            remote_as = open.my_as,
+            remote_id = %open.router_id,
+            hold_time,
+            "bgp session established"
+        );
+
+        let next_hop = match (self.config.next_hop, local_addr.ip().to_canonical()) {
+            (Some(next_hop), _) => next_hop,
+            (None, IpAddr::V4(ip)) => ip,
+            (None, IpAddr::V6(_)) => {
+                let n = NotificationMessage::new(ERR_CEASE, 0);
+                return Err(
+                    Self::close_with(&mut writer, n, "no ipv4 next hop, set next_hop").await,
+                );
+            }
+        };
+
+        let hold = Duration::from_secs(hold_time as u64);
+        let keepalive_interval = Duration::from_secs((hold_time / 3).max(1) as u64);
+        let mut keepalive =
+            tokio::time::interval_at(Instant::now() + keepalive_interval, keepalive_interval);
+        let mut hold_deadline = Instant::now() + hold;
+
+        let mut exported_rx = self.exported.clone();
+        exported_rx.mark_changed();
+        let mut advertised = BTreeSet::new();
+        let mut rib_in = BTreeSet::new();
+
+        loop {
+            tokio::select! {
+                msg = reader.next() => {
+                    hold_deadline = Instant::now() + hold;
+                    match Self::check_received(msg, &mut writer).await? {
+                        BgpMessage::Update(update) => {
+                            if self.apply_update(&mut rib_in, update) {
+                                self.imported.send_replace(rib_in.clone());
+                            }
+                        }
+                        BgpMessage::Keepalive => {}
+                        _ => {
+                            let n = NotificationMessage::new(ERR_FSM, 0);
+                            return Err(Self::close_with(&mut writer, n, "unexpected open").await);
+                        }
+                    }
+                }
+                _ = keepalive.tick(), if hold_time > 0 => {
+                    writer.send(BgpMessage::Keepalive).await?;
+                }
+                _ = tokio::time::sleep_until(hold_deadline), if hold_time > 0 => {
+                    let n = NotificationMessage::new(ERR_HOLD_TIMER_EXPIRED, 0);
+                    return Err(Self::close_with(&mut writer, n, "hold timer expired").await);
+                }
+                ret = exported_rx.changed() => {
+                    if ret.is_err() {
+                        // the instance is going away
+                        let _ = writer
+                            .send(BgpMessage::Notification(NotificationMessage::new(ERR_CEASE, 0)))
+                            .await;
+                        return Ok(());
+                    }
+                    let exported = exported_rx.borrow_and_update().clone();
+                    self.announce(&mut writer, &advertised, &exported, next_hop).await?;
+                    advertised = exported;
+                }
+            }
+        }
+    }
+
+    fn import_allowed(&self, prefix: &Ipv4Cidr) -> bool {
+        match &self.config.import_filter {
+            None => true,
+            Some(filters) => filters.iter().any(|f| {
+                f.network_length() <= prefix.network_length() && f.contains(&prefix.first_address())
+            }),
+        }
+    }
+
+    // returns true if the accepted prefixes changed
+    fn apply_update(&self, rib_in: &mut BTreeSet<Ipv4Cidr>, update: UpdateMessage) -> bool {
+        if !self.config.import.unwrap_or(false) {
+            return false;
+        }
+
+        let mut changed = false;
+        for prefix in &update.withdrawn {
+            changed |= rib_in.remove(prefix);
+        }
+        // our own announcements coming back through the router
+        let looped = self.is_ebgp() && update.as_path.contains(&self.config.local_as);
+        for prefix in &update.nlri {
+            if looped || !self.import_allowed(prefix) {
+                // the new route replaces any accepted one for the same prefix
+                changed |= rib_in.remove(prefix);
+            } else {
+                changed |= rib_in.insert(*prefix);
+            }
+        }
+        changed
+    }
+
+    async fn announce(
+        &self,
+        writer: &mut BgpWriter,
+        advertised: &BTreeSet<Ipv4Cidr>,
+        exported: &BTreeSet<Ipv4Cidr>,
+        next_hop: Ipv4Addr,
+    ) -> anyhow::Result<()> {
+        let withdrawn = advertised.difference(exported).copied().collect::<Vec<_>>();
+        for chunk in withdrawn.chunks(MAX_PREFIXES_PER_UPDATE) {
+            writer
+                .send(BgpMessage::Update(UpdateMessage {
+                    withdrawn: chunk.to_vec(),
+                    ..Default::default()
+                }))
+                .await?;
+        }
+
+        let (as_path, local_pref) = if self.is_ebgp() {
+            (vec![self.config.local_as], None)
+        } else {
+            (vec![], Some(IBGP_LOCAL_PREF))
+        };
+        let nlri = exported.difference(advertised).copied().collect::<Vec<_>>();
+        for chunk in nlri.chunks(MAX_PREFIXES_PER_UPDATE) {
+            writer
+                .send(BgpMessage::Update(UpdateMessage {
+                    withdrawn: vec![],
+                    as_path: as_path.clone(),
+                    next_hop: Some(next_hop),
+                    local_pref,
+                    nlri: chunk.to_vec(),
+                }))
+                .await?;
+        }
+
+        if !withdrawn.is_empty() || !nlri.is_empty() {
+            tracing::debug!(?withdrawn, ?nlri, "bgp routes announced");
+        }
+        Ok(())
+    }
+}
+
+/// Connects the bgp speaker to an instance: exports the virtual network and the
+/// proxy cidrs of remote peers, and adds imported prefixes to the local proxy cidrs.
+pub struct BgpService {
+    peer_mgr: Weak<PeerManager>,
+    global_ctx: ArcGlobalCtx,
+}
+
+impl BgpService {
+    pub fn new(peer_mgr: Arc<PeerManager>, global_ctx: ArcGlobalCtx) -> Self {
+        Self {
+            peer_mgr: Arc::downgrade(&peer_mgr),
+            global_ctx,
+        }
+    }
+
+    pub fn start(self) -> AbortOnDropHandle<()> {
+        AbortOnDropHandle::new(tokio::spawn(async move {
+            let Some(config) = self.global_ctx.config.get_bgp_config() else {
+                return;
+            };
+
+            // the virtual ip may be assigned by dhcp later
+            let router_id = loop {
+                if let Some(router_id) = config
+                    .router_id
+                    .or_else(|| self.global_ctx.get_ipv4().map(|x| x.address()))
+                {
+                    break router_id;
+                }
+                tokio::time::sleep(EXPORT_REFRESH_INTERVAL).await;
+            };
+
+            let (exported_tx, exported_rx) = watch::channel(BTreeSet::new());
+            let speaker = match BgpSpeaker::new(config, router_id, exported_rx) {
+                Ok(speaker) => Arc::new(speaker),
+                Err(e) => {
+                    tracing::error!(?e, "invalid bgp config");
+                    return;
+                }
+            };
+
+            let _import_task = AbortOnDropHandle::new(tokio::spawn(Self::apply_imported(
+                self.global_ctx.clone(),
+                speaker.subscribe_imported(),
+                exported_tx.subscribe(),
+            )));
+            let _speaker_task = AbortOnDropHandle::new(tokio::spawn(async move {
+                if let Err(e) = speaker.run().await {
+                    tracing::error!(?e, "bgp speaker stopped");
+                }
+            }));
+
+            loop {
+                let Some(peer_mgr) = self.peer_mgr.upgrade() else {
+                    break;
+                };
+                let mut exported = peer_mgr.list_proxy_cidrs().await;
+                drop(peer_mgr);
+                if let Some(ipv4) = self.global_ctx.get_ipv4() {
+                    exported.insert(ipv4.network());
+                }
+                exported_tx.send_if_modified(|cur| {
+                    if *cur == exported {
+                        return false;
+                    }
+                    *cur = exported;
+                    true
+                });
+
+                tokio::time::sleep(EXPORT_REFRESH_INTERVAL).await;
+            }
+        }))
+    }
+
+    async fn apply_imported(
+        global_ctx: ArcGlobalCtx,
+        mut imported_rx: watch::Receiver<BTreeSet<Ipv4Cidr>>,
+        exported_rx: watch::Receiver<BTreeSet<Ipv4Cidr>>,
+    ) {
+        // learned prefixes only live in the runtime proxy cidrs, the config is never touched
+        loop {
+            let imported = imported_rx.borrow_and_update().clone();
+            let exported = exported_rx.borrow().clone();
+            let configured = global_ctx
+                .config
+                .get_proxy_cidrs()
+                .into_iter()
+                .map(|x| x.cidr)
+                .collect::<BTreeSet<_>>();
+            // a default route from the router is the job of exit nodes
+            let wanted = imported
+                .into_iter()
+                .filter(|cidr| {
+                    cidr.network_length() > 0
+                        && !exported.contains(cidr)
+                        && !configured.contains(cidr)
+                })
+                .collect::<BTreeSet<_>>();
+
+            let applied = global_ctx.get_runtime_proxy_cidrs();
+            for cidr in applied.difference(&wanted) {
+                tracing::info!(%cidr, "remove proxy cidr withdrawn by bgp neighbor");
+            }
+            for cidr in wanted.difference(&applied) {
+                tracing::info!(%cidr, "add proxy cidr learned from bgp neighbor");
+            }
+            global_ctx.set_runtime_proxy_cidrs(wanted);
+
+            if imported_rx.changed().await.is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        common::global_ctx::tests::get_mock_global_ctx, tunnel::common::tests::wait_for_condition,
+    };
+
+    fn config(local_as: u32, remote_as: u32, neighbor: &str) -> BgpConfig {
+        BgpConfig {
+            local_as,
+            remote_as,
+            neighbor: neighbor.to_string(),
+            hold_time_sec: Some(3),
+            import: Some(true),
+            ..Default::default()
+        }
+    }
+
+    fn cidrs(list: &[&str]) -> BTreeSet<Ipv4Cidr> {
+        list.iter().map(|x| x.parse().unwrap()).collect()
+    }
+
+    // the passive side plays the overlay gateway, the active one the lan router
+    fn speaker_pair(
+        gateway_config: BgpConfig,
+        router_config: BgpConfig,
+    ) -> (
+        watch::Sender<BTreeSet<Ipv4Cidr>>,
+        Arc<BgpSpeaker>,
+        watch::Sender<BTreeSet<Ipv4Cidr>>,
+        Arc<BgpSpeaker>,
+        Vec<AbortOnDropHandle<anyhow::Result<()>>>,
+    ) {
+        let (gateway_tx, gateway_rx) = watch::channel(BTreeSet::new());
+        let gateway = Arc::new(
+            BgpSpeaker::new(gateway_config, Ipv4Addr::new(10, 126, 126, 1), gateway_rx).unwrap(),
+        );
+        let (router_tx, router_rx) = watch::channel(BTreeSet::new());
+        let router = Arc::new(
+            BgpSpeaker::new(router_config, Ipv4Addr::new(192, 168, 1, 1), router_rx).unwrap(),
+        );
+
+        let tasks = vec![
+            AbortOnDropHandle::new(tokio::spawn(gateway.clone().run())),
+            AbortOnDropHandle::new(tokio::spawn(router.clone().run())),
+        ];
+        (gateway_tx, gateway, router_tx, router, tasks)
+    }
+
+    async fn wait_imported(speaker: &BgpSpeaker, expected: BTreeSet<Ipv4Cidr>) {
+        let rx = speaker.subscribe_imported();
+        wait_for_condition(
+            || {
+                let rx = rx.clone();
+                let expected = expected.clone();
+                async move { *rx.borrow() == expected }
+            },
+            Duration::from_secs(10),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn ebgp_session_exchanges_routes() {
+        let mut gateway_config = config(65001, 4_200_000_000, "127.0.0.1");
+        gateway_config.listen = Some("127.0.0.1:17901".parse().unwrap());
+        gateway_config.import_filter = Some(vec!["192.168.0.0/16".parse().unwrap()]);
+        let mut router_config = config(4_200_000_000, 65001, "127.0.0.1:17901");
+        router_config.active = Some(true);
+
+        let (gateway_tx, gateway, router_tx, router, _tasks) =
+            speaker_pair(gateway_config, router_config);
+
+        gateway_tx.send_replace(cidrs(&["10.126.126.0/24", "10.1.0.0/16"]));
+        router_tx.send_replace(cidrs(&["192.168.1.0/24", "172.16.0.0/12"]));
+
+        wait_imported(&router, cidrs(&["10.126.126.0/24", "10.1.0.0/16"])).await;
+        // 172.16.0.0/12 is outside of the import filter
+        wait_imported(&gateway, cidrs(&["192.168.1.0/24"])).await;
+
+        // withdraw and announce incrementally
+        gateway_tx.send_replace(cidrs(&["10.126.126.0/24", "10.2.0.0/16"]));
+        wait_imported(&router, cidrs(&["10.126.126.0/24", "10.2.0.0/16"])).await;
+
+        router_tx.send_replace(cidrs(&["192.168.1.0/24", "192.168.2.0/24"]));
+        wait_imported(&gateway, cidrs(&["192.168.1.0/24", "192.168.2.0/24"])).await;
+    }
+
+    #[tokio::test]
+    async fn session_down_clears_imported_routes() {
+        let mut gateway_config = config(65001, 65001, "127.0.0.1");
+        gateway_config.listen = Some("127.0.0.1:17902".parse().unwrap());
+        let mut router_config = config(65001, 65001, "127.0.0.1:17902");
+        router_config.active = Some(true);
+
+        let (_gateway_tx, gateway, router_tx, _router, mut tasks) =
+            speaker_pair(gateway_config, router_config);
+        router_tx.send_replace(cidrs(&["192.168.1.0/24"]));
+        wait_imported(&gateway, cidrs(&["192.168.1.0/24"])).await;
+
+        // stop the router, the gateway notices by hold timer or closed socket
+        tasks.pop();
+        wait_imported(&gateway, BTreeSet::new()).await;
+    }
+
+    #[test]
+    fn open_with_wrong_as_is_rejected() {
+        let (_tx, rx) = watch::channel(BTreeSet::new());
+        let speaker = BgpSpeaker::new(
+            config(65001, 65002, "10.0.0.1"),
+            Ipv4Addr::new(1, 1, 1, 1),
+            rx,
+        )
+        .unwrap();
+        let mut open = OpenMessage {
+            my_as: 65003,
+            hold_time: 90,
+            router_id: Ipv4Addr::new(2, 2, 2, 2),
+            four_octet_as: true,
+        };
+        assert_eq!(
+            speaker.check_open(&open).unwrap_err().subcode,
+            OPEN_ERR_BAD_PEER_AS
+        );
+        open.my_as = 65002;
+        assert!(speaker.check_open(&open).is_ok());
+        open.router_id = Ipv4Addr::new(1, 1, 1, 1);
+        assert_eq!(
+            speaker.check_open(&open).unwrap_err().subcode,
+            OPEN_ERR_BAD_BGP_ID
+        );
+
+        assert_eq!(parse_neighbor("10.0.0.1").unwrap().port(), BGP_PORT);
+        assert_eq!(parse_neighbor("[fd00::1]:1179").unwrap().port(), 1179);
+        assert!(parse_neighbor("router").is_err());
+    }
+
+    #[test]
+    fn update_with_own_as_is_not_imported() {
+        let (_tx, rx) = watch::channel(BTreeSet::new());
+        let speaker = BgpSpeaker::new(
+            config(65001, 65002, "10.0.0.1"),
+            Ipv4Addr::new(1, 1, 1, 1),
+            rx,
+        )
+        .unwrap();
+        let mut rib_in = BTreeSet::new();
+
+        let update = |as_path: Vec<u32>, nlri: &[&str]| UpdateMessage {
+            as_path,
+            next_hop: Some(Ipv4Addr::new(10, 0, 0, 1)),
+            nlri: nlri.iter().map(|x| x.parse().unwrap()).collect(),
+            ..Default::default()
+        };
+        assert!(speaker.apply_update(&mut rib_in, update(vec![65002], &["192.168.1.0/24"])));
+        assert!(!speaker.apply_update(
+            &mut rib_in,
+            update(vec![65002, 65001], &["10.126.126.0/24"])
+        ));
+        // a looped route replaces the accepted one
+        assert!(speaker.apply_update(&mut rib_in, update(vec![65002, 65001], &["192.168.1.0/24"])));
+        assert!(rib_in.is_empty());
+    }
+
+    #[tokio::test]
+    async fn imported_routes_stay_out_of_config() {
+        let global_ctx = get_mock_global_ctx();
+        global_ctx
+            .config
+            .add_proxy_cidr("192.168.1.0/24".parse().unwrap(), None)
+            .unwrap();
+        let (imported_tx, imported_rx) = watch::channel(BTreeSet::new());
+        let (_exported_tx, exported_rx) = watch::channel(BTreeSet::new());
+        let _task = AbortOnDropHandle::new(tokio::spawn(BgpService::apply_imported(
+            global_ctx.clone(),
+            imported_rx,
+            exported_rx,
+        )));
+
+        let wait_runtime = |expected: BTreeSet<Ipv4Cidr>| {
+            let global_ctx = global_ctx.clone();
+            wait_for_condition(
+                move || {
+                    let global_ctx = global_ctx.clone();
+                    let expected = expected.clone();
+                    async move { global_ctx.get_runtime_proxy_cidrs() == expected }
+                },
+                Duration::from_secs(5),
+            )
+        };
+
+        imported_tx.send_replace(cidrs(&["192.168.1.0/24", "192.168.2.0/24"]));
+        // the configured cidr is left to the config
+        wait_runtime(cidrs(&["192.168.2.0/24"])).await;
+        assert_eq!(global_ctx.config.get_proxy_cidrs().len(), 1);
+        assert_eq!(global_ctx.get_proxy_cidrs().len(), 2);
+
+        imported_tx.send_replace(BTreeSet::new());
+        wait_runtime(BTreeSet::new()).await;
+        assert_eq!(global_ctx.config.get_proxy_cidrs().len(), 1);
+        assert_eq!(global_ctx.get_proxy_cidrs().len(), 1);
+    }
+}
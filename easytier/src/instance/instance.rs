@@ -23,6 +23,7 @@ use crate::common::config::ConfigLoader;
 use crate::common::error::Error;
 use crate::common::global_ctx::{ArcGlobalCtx, GlobalCtx, GlobalCtxEvent};
 use crate::connector::direct::DirectConnectorManager;
+use crate::connector::lan_discovery::LanDiscovery;
 use crate::connector::manual::{ConnectorManagerRpcService, ManualConnectorManager};
 use crate::connector::tcp_hole_punch::TcpHolePunchConnector;
 use crate::connector::udp_hole_punch::UdpHolePunchConnector;
@@ -45,10 +46,13 @@ use crate::proto::api::config::{
     PatchConfigResponse, PortForwardPatch,
 };
 use crate::proto::api::instance::{
-    GetPrometheusStatsRequest, GetPrometheusStatsResponse, GetStatsRequest, GetStatsResponse,
-    GetVpnPortalInfoRequest, GetVpnPortalInfoResponse, ListMappedListenerRequest,
-    ListMappedListenerResponse, ListPortForwardRequest, ListPortForwardResponse, MappedListener,
-    MappedListenerManageRpc, MetricSnapshot, PortForwardManageRpc, StatsRpc, VpnPortalInfo,
+    AddVpnPortalClientRequest, AddVpnPortalClientResponse, GetPrometheusStatsRequest,
+    GetPrometheusStatsResponse, GetStatsRequest, GetStatsResponse, GetVpnPortalClientConfigRequest,
+    GetVpnPortalClientConfigResponse, GetVpnPortalInfoRequest, GetVpnPortalInfoResponse,
+    ListMappedListenerRequest, ListMappedListenerResponse, ListPortForwardRequest,
+    ListPortForwardResponse, ListVpnPortalClientsRequest, ListVpnPortalClientsResponse,
+    MappedListener, MappedListenerManageRpc, MetricSnapshot, PortForwardManageRpc,
+    RemoveVpnPortalClientRequest, RemoveVpnPortalClientResponse, StatsRpc, VpnPortalInfo,
     VpnPortalRpc,
 };
 use crate::proto::api::manage::NetworkConfig;
@@ -99,8 +103,7 @@ impl IpProxy {
     }
 
     async fn start(&self) -> Result<(), Error> {
-        if (self.global_ctx.config.get_proxy_cidrs().is_empty()
-            || self.started.load(Ordering::Relaxed))
+        if (self.global_ctx.get_proxy_cidrs().is_empty() || self.started.load(Ordering::Relaxed))
             && !self.global_ctx.enable_exit_node()
             && !self.global_ctx.no_tun()
         {
@@ -621,6 +624,7 @@ pub struct Instance {
     peer_manager: Arc<PeerManager>,
     listener_manager: Arc<Mutex<ListenerManager<PeerManager>>>,
     conn_manager: Arc<ManualConnectorManager>,
+    lan_discovery: Arc<LanDiscovery>,
     direct_conn_manager: Arc<DirectConnectorManager>,
     udp_hole_puncher: Arc<Mutex<UdpHolePunchConnector>>,
     tcp_hole_puncher: Arc<Mutex<TcpHolePunchConnector>>,
@@ -643,6 +647,7 @@ pub struct Instance {
     socks5_server: Arc<Socks5Server>,
 
     proxy_cidrs_monitor: Option<AbortOnDropHandle<()>>,
+    bgp_service: Option<AbortOnDropHandle<()>>,
 
     global_ctx: ArcGlobalCtx,
 }
@@ -678,6 +683,12 @@ impl Instance {
             peer_manager.clone(),
         ));
 
+        let lan_discovery = Arc::new(LanDiscovery::new(
+            global_ctx.clone(),
+            peer_manager.clone(),
+            Arc::downgrade(&conn_manager),
+        ));
+
         let mut direct_conn_manager =
             DirectConnectorManager::new(global_ctx.clone(), peer_manager.clone());
         direct_conn_manager.run();
@@ -709,6 +720,7 @@ impl Instance {
             peer_manager,
             listener_manager,
             conn_manager,
+            lan_discovery,
             direct_conn_manager,
             udp_hole_puncher,
             tcp_hole_puncher,
@@ -730,6 +742,7 @@ impl Instance {
             socks5_server,
 
             proxy_cidrs_monitor: None,
+            bgp_service: None,
 
             global_ctx,
         }
@@ -1096,12 +1109,25 @@ impl Instance {
 
         self.add_initial_peers().await?;
 
+        if self.global_ctx.get_flags().enable_lan_discovery {
+            // not fatal, e.g. the discovery port may be blocked
+            if let Err(e) = self.lan_discovery.start() {
+                tracing::warn!(?e, "failed to start lan discovery");
+            }
+        }
+
         let monitor = super::proxy_cidrs_monitor::ProxyCidrsMonitor::new(
             self.peer_manager.clone(),
             self.global_ctx.clone(),
         );
         self.proxy_cidrs_monitor = Some(monitor.start());
 
+        if self.global_ctx.config.get_bgp_config().is_some() {
+            let bgp_service =
+                super::bgp::BgpService::new(self.peer_manager.clone(), self.global_ctx.clone());
+            self.bgp_service = Some(bgp_service.start());
+        }
+
         if self.global_ctx.get_vpn_portal_cidr().is_some() {
             self.run_vpn_portal().await?;
         }
@@ -1209,6 +1235,112 @@ impl Instance {
 
                 Ok(ret)
             }
+
+            async fn add_vpn_portal_client(
+                &self,
+                _: BaseController,
+                request: AddVpnPortalClientRequest,
+            ) -> Result<AddVpnPortalClientResponse, rpc_types::error::Error> {
+                let Some(vpn_portal) = self.vpn_portal.upgrade() else {
+                    return Err(anyhow::anyhow!("vpn portal not available").into());
+                };
+
+                let Some(peer_mgr) = self.peer_mgr.upgrade() else {
+                    return Err(anyhow::anyhow!("peer manager not available").into());
+                };
+
+                let global_ctx = peer_mgr.get_global_ctx();
+                let Some(client_cidr) = global_ctx.get_vpn_portal_cidr() else {
+                    return Err(anyhow::anyhow!("vpn portal is not enabled").into());
+                };
+                if request.ttl_seconds < 0 {
+                    return Err(anyhow::anyhow!("ttl_seconds must not be negative").into());
+                }
+                let ttl = (request.ttl_seconds > 0)
+                    .then(|| std::time::Duration::from_secs(request.ttl_seconds as u64));
+
+                let client = global_ctx.get_vpn_portal_client_manager().add_client(
+                    &request.name,
+                    request.public_key.as_deref(),
+                    ttl,
+                    client_cidr,
+                )?;
+                let client_config = vpn_portal
+                    .lock()
+                    .await
+                    .dump_peer_client_config(peer_mgr, &client.name)
+                    .await?;
+
+                Ok(AddVpnPortalClientResponse {
+                    client: Some(client),
+                    client_config,
+                })
+            }
+
+            async fn remove_vpn_portal_client(
+                &self,
+                _: BaseController,
+                request: RemoveVpnPortalClientRequest,
+            ) -> Result<RemoveVpnPortalClientResponse, rpc_types::error::Error> {
+                let Some(peer_mgr) = self.peer_mgr.upgrade() else {
+                    return Err(anyhow::anyhow!("peer manager not available").into());
+                };
+
+                // connected sessions of the client are closed by the portal on its next check
+                let success = peer_mgr
+                    .get_global_ctx()
+                    .get_vpn_portal_client_manager()
+                    .remove_client(&request.name);
+
+                Ok(RemoveVpnPortalClientResponse { success })
+            }
+
+            async fn list_vpn_portal_clients(
+                &self,
+                _: BaseController,
+                _request: ListVpnPortalClientsRequest,
+            ) -> Result<ListVpnPortalClientsResponse, rpc_types::error::Error> {
+                let Some(vpn_portal) = self.vpn_portal.upgrade() else {
+                    return Err(anyhow::anyhow!("vpn portal not available").into());
+                };
+
+                let Some(peer_mgr) = self.peer_mgr.upgrade() else {
+                    return Err(anyhow::anyhow!("peer manager not available").into());
+                };
+
+                let mut connected = vpn_portal.lock().await.list_connected_peer_clients().await;
+                let mut clients = peer_mgr
+                    .get_global_ctx()
+                    .get_vpn_portal_client_manager()
+                    .list_clients();
+                for client in clients.iter_mut() {
+                    client.endpoint = connected.remove(&client.name);
+                }
+
+                Ok(ListVpnPortalClientsResponse { clients })
+            }
+
+            async fn get_vpn_portal_client_config(
+                &self,
+                _: BaseController,
+                request: GetVpnPortalClientConfigRequest,
+            ) -> Result<GetVpnPortalClientConfigResponse, rpc_types::error::Error> {
+                let Some(vpn_portal) = self.vpn_portal.upgrade() else {
+                    return Err(anyhow::anyhow!("vpn portal not available").into());
+                };
+
+                let Some(peer_mgr) = self.peer_mgr.upgrade() else {
+                    return Err(anyhow::anyhow!("peer manager not available").into());
+                };
+
+                let client_config = vpn_portal
+                    .lock()
+                    .await
+                    .dump_peer_client_config(peer_mgr, &request.name)
+                    .await?;
+
+                Ok(GetVpnPortalClientConfigResponse { client_config })
+            }
         }
 
         VpnPortalRpcService {
@@ -1485,9 +1617,10 @@ impl Instance {
 
         ApiRpcServiceImpl {
             peer_mgr_rpc_service: PeerManagerRpcService::new(self.peer_manager.clone()),
-            connector_mgr_rpc_service: ConnectorManagerRpcService(Arc::downgrade(
-                &self.conn_manager,
-            )),
+            connector_mgr_rpc_service: ConnectorManagerRpcService(
+                Arc::downgrade(&self.conn_manager),
+                Arc::downgrade(&self.lan_discovery),
+            ),
             mapped_listener_mgr_rpc_service: self.get_mapped_listener_manager_rpc_service(),
             vpn_portal_rpc_service: self.get_vpn_portal_rpc_service(),
             tcp_proxy_rpc_services: {
@@ -0,0 +1,414 @@
+// tun device with multiple queues and vnet header offloads on linux.
+//
+// every queue is a separate fd of the same device and gets its own reader, the
+// kernel spreads flows over the queues. writes go to the first queue. features
+// the kernel doesn't support are turned off one after another.
+
+use std::{
+    collections::VecDeque,
+    ffi::CStr,
+    io,
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::{Sink, Stream, ready};
+use nix::libc;
+use tokio::io::unix::AsyncFd;
+
+use crate::{
+    common::log,
+    instance::tun_offload::{
+        TunWrite, VIRTIO_NET_HDR_LEN, VirtioNetHdr, coalesce_tcp, complete_checksum, gso_split,
+    },
+    tunnel::{
+        StreamItem, Tunnel, TunnelError, ZCPacketStream,
+        buf::PACKET_BUF_CHUNK_SIZE,
+        common::{TunnelWrapper, reserve_buf},
+        packet_def::{TAIL_RESERVED_SIZE, ZCPacket, ZCPacketType},
+    },
+};
+
+const IFF_TUN: libc::c_short = 0x0001;
+const IFF_NO_PI: libc::c_short = 0x1000;
+const IFF_MULTI_QUEUE: libc::c_short = 0x0100;
+const IFF_VNET_HDR: libc::c_short = 0x4000;
+
+const TUN_F_CSUM: libc::c_uint = 0x01;
+const TUN_F_TSO4: libc::c_uint = 0x02;
+const TUN_F_TSO6: libc::c_uint = 0x04;
+
+const TUNSETIFF: libc::Ioctl =
+    nix::request_code_write!(b'T', 202, std::mem::size_of::<libc::c_int>()) as libc::Ioctl;
+const TUNSETOFFLOAD: libc::Ioctl =
+    nix::request_code_write!(b'T', 208, std::mem::size_of::<libc::c_uint>()) as libc::Ioctl;
+const TUNSETVNETHDRSZ: libc::Ioctl =
+    nix::request_code_write!(b'T', 216, std::mem::size_of::<libc::c_int>()) as libc::Ioctl;
+
+// a gso packet from the kernel is at most 64k
+const MAX_GSO_READ_LEN: usize = VIRTIO_NET_HDR_LEN + u16::MAX as usize;
+const MAX_READ_LEN: usize = 2500;
+const MAX_WRITE_BATCH: usize = 64;
+
+fn queue_flags(multi_queue: bool, vnet_hdr: bool) -> libc::c_short {
+    let mut flags = IFF_TUN | IFF_NO_PI;
+    if multi_queue {
+        flags |= IFF_MULTI_QUEUE;
+    }
+    if vnet_hdr {
+        flags |= IFF_VNET_HDR;
+    }
+    flags
+}
+
+fn open_queue(name: &str, flags: libc::c_short) -> io::Result<(OwnedFd, String)> {
+    if name.len() >= libc::IFNAMSIZ {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("interface name too long: {}", name),
+        ));
+    }
+
+    let fd = unsafe {
+        libc::open(
+            c"/dev/net/tun".as_ptr(),
+            libc::O_RDWR | libc::O_NONBLOCK | libc::O_CLOEXEC,
+        )
+    };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+    let mut ifr: libc::ifreq = unsafe { std::mem::zeroed() };
+    for (i, b) in name.bytes().enumerate() {
+        ifr.ifr_name[i] = b as libc::c_char;
+    }
+    ifr.ifr_ifru.ifru_flags = flags;
+    if unsafe { libc::ioctl(fd.as_raw_fd(), TUNSETIFF, &mut ifr) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    // the kernel fills in the name if it was empty
+    let name = unsafe { CStr::from_ptr(ifr.ifr_name.as_ptr()) }
+        .to_string_lossy()
+        .into_owned();
+    Ok((fd, name))
+}
+
+fn enable_offload(fd: &OwnedFd) -> io::Result<()> {
+    let hdr_len = VIRTIO_NET_HDR_LEN as libc::c_int;
+    if unsafe { libc::ioctl(fd.as_raw_fd(), TUNSETVNETHDRSZ, &hdr_len) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let offloads = (TUN_F_CSUM | TUN_F_TSO4 | TUN_F_TSO6) as libc::c_ulong;
+    if unsafe { libc::ioctl(fd.as_raw_fd(), TUNSETOFFLOAD, offloads) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+pub struct LinuxTun {
+    pub name: String,
+    pub vnet_hdr: bool,
+    queues: Vec<OwnedFd>,
+}
+
+impl LinuxTun {
+    /// Fails if the offload ioctls are refused, the caller falls back to the
+    /// default tun device then. Only multi-queue is given up on its own.
+    pub fn create(name: &str, queues: usize, offload: bool) -> io::Result<Self> {
+        let mut multi_queue = queues > 1;
+        let vnet_hdr = offload;
+
+        let (first, name) = loop {
+            match open_queue(name, queue_flags(multi_queue, vnet_hdr)) {
+                Ok((fd, name)) => {
+                    // the device goes away with the fd if this fails
+                    if vnet_hdr {
+                        enable_offload(&fd)?;
+                    }
+                    break (fd, name);
+                }
+                Err(error) if error.raw_os_error() == Some(libc::EINVAL) && multi_queue => {
+                    log::warn!(
+                        %error,
+                        "failed to create multi queue tun device, retry with one queue"
+                    );
+                    multi_queue = false;
+                }
+                Err(error) => return Err(error),
+            }
+        };
+
+        let mut fds = vec![first];
+        while multi_queue && fds.len() < queues {
+            let ret = open_queue(&name, queue_flags(multi_queue, vnet_hdr)).and_then(|(fd, _)| {
+                if vnet_hdr {
+                    enable_offload(&fd)?;
+                }
+                Ok(fd)
+            });
+            match ret {
+                Ok(fd) => fds.push(fd),
+                Err(error) => {
+                    log::warn!(%error, queues = fds.len(), "failed to add tun queue");
+                    break;
+                }
+            }
+        }
+
+        Ok(Self {
+            name,
+            vnet_hdr,
+            queues: fds,
+        })
+    }
+
+    pub fn queue_count(&self) -> usize {
+        self.queues.len()
+    }
+
+    /// Tunnel of the first queue and the streams of the other queues.
+    pub fn into_tunnels(self) -> io::Result<(Box<dyn Tunnel>, Vec<Pin<Box<dyn ZCPacketStream>>>)> {
+        let mut queues = self.queues.into_iter();
+        let first = Arc::new(AsyncFd::new(queues.next().unwrap())?);
+        let tunnel = TunnelWrapper::new(
+            TunQueueStream::new(first.clone(), self.vnet_hdr),
+            TunQueueSink::new(first, self.vnet_hdr),
+            None,
+        );
+
+        let mut streams: Vec<Pin<Box<dyn ZCPacketStream>>> = vec![];
+        for fd in queues {
+            let fd = Arc::new(AsyncFd::new(fd)?);
+            streams.push(Box::pin(TunQueueStream::new(fd, self.vnet_hdr)));
+        }
+        Ok((Box::new(tunnel), streams))
+    }
+}
+
+pub struct TunQueueStream {
+    fd: Arc<AsyncFd<OwnedFd>>,
+    vnet_hdr: bool,
+    cur_buf: BytesMut,
+    payload_offset: usize,
+    // segments of the last gso packet
+    pending: VecDeque<ZCPacket>,
+}
+
+impl TunQueueStream {
+    fn new(fd: Arc<AsyncFd<OwnedFd>>, vnet_hdr: bool) -> Self {
+        Self {
+            fd,
+            vnet_hdr,
+            cur_buf: BytesMut::new(),
+            payload_offset: ZCPacketType::NIC.get_packet_offsets().payload_offset,
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn read_packet(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<BytesMut>> {
+        let (read_len, hdr_offset) = if self.vnet_hdr {
+            // the vnet header is read into the room before the payload
+            (MAX_GSO_READ_LEN, self.payload_offset - VIRTIO_NET_HDR_LEN)
+        } else {
+            (MAX_READ_LEN, self.payload_offset)
+        };
+        let min_size = hdr_offset + read_len + TAIL_RESERVED_SIZE;
+        reserve_buf(
+            &mut self.cur_buf,
+            min_size,
+            PACKET_BUF_CHUNK_SIZE.max(min_size * 2),
+        );
+        if self.cur_buf.is_empty() {
+            unsafe { self.cur_buf.set_len(hdr_offset) };
+        }
+
+        loop {
+            let mut guard = ready!(self.fd.poll_read_ready(cx))?;
+            let buf = self.cur_buf.chunk_mut().as_mut_ptr();
+            let ret = guard.try_io(|fd| {
+                let n = unsafe { libc::read(fd.as_raw_fd(), buf as *mut libc::c_void, read_len) };
+                if n < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(n as usize)
+                }
+            });
+            let Ok(ret) = ret else {
+                continue;
+            };
+            let len = ret?;
+            if len == 0 {
+                return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+            }
+
+            unsafe { self.cur_buf.advance_mut(len + TAIL_RESERVED_SIZE) };
+            let mut ret_buf = self.cur_buf.split();
+            let cur_len = ret_buf.len();
+            ret_buf.truncate(cur_len - TAIL_RESERVED_SIZE);
+            return Poll::Ready(Ok(ret_buf));
+        }
+    }
+}
+
+impl Stream for TunQueueStream {
+    type Item = StreamItem;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<StreamItem>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(packet) = this.pending.pop_front() {
+                return Poll::Ready(Some(Ok(packet)));
+            }
+
+            let mut buf = match ready!(this.read_packet(cx)) {
+                Ok(buf) => buf,
+                Err(err) => {
+                    log::error!("tun stream error: {:?}", err);
+                    return Poll::Ready(None);
+                }
+            };
+            if !this.vnet_hdr {
+                return Poll::Ready(Some(Ok(ZCPacket::new_from_buf(buf, ZCPacketType::NIC))));
+            }
+
+            let payload_offset = this.payload_offset;
+            if buf.len() < payload_offset {
+                continue;
+            }
+            let hdr = VirtioNetHdr::decode(&buf[payload_offset - VIRTIO_NET_HDR_LEN..]).unwrap();
+            if !hdr.is_gso() {
+                if !complete_checksum(&hdr, &mut buf[payload_offset..]) {
+                    tracing::warn!(?hdr, "invalid checksum offload from tun");
+                    continue;
+                }
+                return Poll::Ready(Some(Ok(ZCPacket::new_from_buf(buf, ZCPacketType::NIC))));
+            }
+
+            let pending = &mut this.pending;
+            if let Err(error) = gso_split(&hdr, &buf[payload_offset..], |seg| {
+                pending.push_back(ZCPacket::new_with_payload(seg))
+            }) {
+                tracing::warn!(?hdr, %error, "failed to split gso packet from tun");
+            }
+        }
+    }
+}
+
+pub struct TunQueueSink {
+    fd: Arc<AsyncFd<OwnedFd>>,
+    vnet_hdr: bool,
+    batch: Vec<ZCPacket>,
+    writes: VecDeque<Bytes>,
+}
+
+impl TunQueueSink {
+    fn new(fd: Arc<AsyncFd<OwnedFd>>, vnet_hdr: bool) -> Self {
+        Self {
+            fd,
+            vnet_hdr,
+            batch: Vec::with_capacity(MAX_WRITE_BATCH),
+            writes: VecDeque::new(),
+        }
+    }
+
+    fn with_empty_vnet_hdr(packet: ZCPacket) -> Bytes {
+        let payload_offset = packet.payload_offset();
+        if payload_offset < VIRTIO_NET_HDR_LEN {
+            let mut buf = vec![0u8; VIRTIO_NET_HDR_LEN];
+            buf.extend_from_slice(packet.payload());
+            return buf.into();
+        }
+        let mut buf = packet
+            .inner()
+            .split_off(payload_offset - VIRTIO_NET_HDR_LEN);
+        VirtioNetHdr::default().encode(&mut buf[..VIRTIO_NET_HDR_LEN]);
+        buf.freeze()
+    }
+
+    // turn the batch into buffers for the device, merging tcp segments when
+    // offloads are enabled.
+    fn prepare_writes(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let batch = std::mem::take(&mut self.batch);
+        if !self.vnet_hdr {
+            self.writes
+                .extend(batch.into_iter().map(|p| p.payload_bytes().freeze()));
+            return;
+        }
+
+        let writes = coalesce_tcp(&batch.iter().map(|p| p.payload()).collect::<Vec<_>>());
+        let mut batch = batch.into_iter().map(Some).collect::<Vec<_>>();
+        for write in writes {
+            match write {
+                TunWrite::Packet(idx) => {
+                    let packet = batch[idx].take().unwrap();
+                    self.writes.push_back(Self::with_empty_vnet_hdr(packet));
+                }
+                TunWrite::Coalesced { hdr, packet } => {
+                    let mut buf = vec![0u8; VIRTIO_NET_HDR_LEN];
+                    hdr.encode(&mut buf);
+                    buf.extend_from_slice(&packet);
+                    self.writes.push_back(buf.into());
+                }
+            }
+        }
+    }
+}
+
+impl Sink<ZCPacket> for TunQueueSink {
+    type Error = TunnelError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.batch.len() >= MAX_WRITE_BATCH {
+            return self.poll_flush(cx);
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: ZCPacket) -> Result<(), Self::Error> {
+        self.get_mut().batch.push(item);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        this.prepare_writes();
+        while let Some(buf) = this.writes.front() {
+            let mut guard = ready!(this.fd.poll_write_ready(cx))?;
+            let Ok(ret) = guard.try_io(|fd| {
+                let n = unsafe {
+                    libc::write(
+                        fd.as_raw_fd(),
+                        buf.as_ptr() as *const libc::c_void,
+                        buf.len(),
+                    )
+                };
+                if n < 0 {
+                    Err(io::Error::last_os_error())
+                } else {
+                    Ok(())
+                }
+            }) else {
+                continue;
+            };
+            // a packet the kernel refuses is dropped, the next ones may be fine
+            this.writes.pop_front();
+            if let Err(error) = ret {
+                tracing::warn!(%error, "tun refused packet, dropped");
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+}
@@ -51,6 +51,19 @@ pub fn create_listener_by_url(
                 );
                 let mut l = WgTunnelListener::new(l.clone(), wg_config);
                 l.set_socket_mark(socket_mark);
+                // accept peers on either side of a network secret rotation
+                let ctx = global_ctx.clone();
+                l.set_config_provider(Some(Arc::new(move || {
+                    ctx.get_accepted_network_identities()
+                        .iter()
+                        .map(|nid| {
+                            WgConfig::new_from_network_identity(
+                                &nid.network_name,
+                                nid.network_secret.as_deref().unwrap_or_default(),
+                            )
+                        })
+                        .collect()
+                })));
                 l.boxed()
             }
             #[cfg(feature = "quic")]
@@ -66,6 +79,39 @@ pub fn create_listener_by_url(
             }
             #[cfg(feature = "faketcp")]
             IpScheme::FakeTcp => tunnel::fake_tcp::FakeTcpTunnelListener::new(l.clone()).boxed(),
+            #[cfg(feature = "obfs")]
+            IpScheme::Obfs => {
+                use crate::tunnel::obfs::{ObfsConfig, ObfsTunnelListener};
+                let nid = global_ctx.get_network_identity();
+                let obfs_config = ObfsConfig::new_from_network_identity(
+                    &nid.network_name,
+                    &nid.network_secret.unwrap_or_default(),
+                );
+                let mut l = ObfsTunnelListener::new(l.clone(), obfs_config);
+                l.set_socket_mark(socket_mark);
+                // accept peers on either side of a network secret rotation
+                let ctx = global_ctx.clone();
+                l.set_config_provider(Some(Arc::new(move || {
+                    ctx.get_accepted_network_identities()
+                        .iter()
+                        .map(|nid| {
+                            ObfsConfig::new_from_network_identity(
+                                &nid.network_name,
+                                nid.network_secret.as_deref().unwrap_or_default(),
+                            )
+                        })
+                        .collect()
+                })));
+                l.boxed()
+            }
+            #[cfg(feature = "http2")]
+            IpScheme::H2 | IpScheme::H2s => {
+                let mut l = tunnel::http2::H2TunnelListener::new(l.clone());
+                l.set_socket_mark(socket_mark);
+                l.boxed()
+            }
+            #[cfg(feature = "webtransport")]
+            IpScheme::Wt => tunnel::webtransport::WtTunnelListener::new(l.clone()).boxed(),
         },
         #[cfg(unix)]
         TunnelScheme::Unix => tunnel::unix::UnixSocketTunnelListener::new(l.clone()).boxed(),
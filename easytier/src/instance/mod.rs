@@ -1,3 +1,4 @@
+pub mod bgp;
 pub mod dns_server;
 #[allow(clippy::module_inception)]
 pub mod instance;
@@ -11,5 +12,10 @@ pub mod proxy_cidrs_monitor;
 #[cfg(feature = "tun")]
 pub mod virtual_nic;
 
+#[cfg(all(feature = "tun", target_os = "linux"))]
+mod linux_tun;
+#[cfg(all(feature = "tun", target_os = "linux"))]
+mod tun_offload;
+
 #[cfg(any(windows, test))]
 pub(crate) mod windows_udp_broadcast;
@@ -0,0 +1,621 @@
+// segmentation and coalescing of tcp packets for tun devices with vnet headers.
+//
+// with offloads enabled the kernel hands over tcp packets of up to 64k bytes
+// (gso) and packets with only a partial checksum. they are split into mss sized
+// segments with full checksums before they are sent to peers. in the other
+// direction consecutive segments of the same tcp flow are merged into one gso
+// packet, so the kernel stack handles a burst as one packet.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+pub const VIRTIO_NET_HDR_LEN: usize = 10;
+
+const VIRTIO_NET_HDR_F_NEEDS_CSUM: u8 = 1;
+const VIRTIO_NET_HDR_GSO_NONE: u8 = 0;
+const VIRTIO_NET_HDR_GSO_TCPV4: u8 = 1;
+const VIRTIO_NET_HDR_GSO_TCPV6: u8 = 4;
+const VIRTIO_NET_HDR_GSO_ECN: u8 = 0x80;
+
+const IPPROTO_TCP: u8 = 6;
+const IPV6_HEADER_LEN: usize = 40;
+const TCP_CSUM_OFFSET: usize = 16;
+
+const TCP_FLAG_FIN: u8 = 0x01;
+const TCP_FLAG_PSH: u8 = 0x08;
+const TCP_FLAG_ACK: u8 = 0x10;
+const TCP_FLAG_CWR: u8 = 0x80;
+
+// kernel limit of segments in one gso packet
+const MAX_COALESCE_SEGMENTS: usize = 64;
+
+/// `struct virtio_net_hdr`, in native byte order.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct VirtioNetHdr {
+    pub flags: u8,
+    pub gso_type: u8,
+    pub hdr_len: u16,
+    pub gso_size: u16,
+    pub csum_start: u16,
+    pub csum_offset: u16,
+}
+
+impl VirtioNetHdr {
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        let buf = buf.get(..VIRTIO_NET_HDR_LEN)?;
+        let u16_at = |i: usize| u16::from_ne_bytes([buf[i], buf[i + 1]]);
+        Some(Self {
+            flags: buf[0],
+            gso_type: buf[1],
+            hdr_len: u16_at(2),
+            gso_size: u16_at(4),
+            csum_start: u16_at(6),
+            csum_offset: u16_at(8),
+        })
+    }
+
+    pub fn encode(&self, buf: &mut [u8]) {
+        buf[0] = self.flags;
+        buf[1] = self.gso_type;
+        buf[2..4].copy_from_slice(&self.hdr_len.to_ne_bytes());
+        buf[4..6].copy_from_slice(&self.gso_size.to_ne_bytes());
+        buf[6..8].copy_from_slice(&self.csum_start.to_ne_bytes());
+        buf[8..10].copy_from_slice(&self.csum_offset.to_ne_bytes());
+    }
+
+    pub fn is_gso(&self) -> bool {
+        self.gso_type & !VIRTIO_NET_HDR_GSO_ECN != VIRTIO_NET_HDR_GSO_NONE
+    }
+}
+
+fn checksum_sum(data: &[u8], mut sum: u64) -> u64 {
+    let mut chunks = data.chunks_exact(2);
+    for chunk in chunks.by_ref() {
+        sum += u16::from_be_bytes([chunk[0], chunk[1]]) as u64;
+    }
+    if let [last] = chunks.remainder() {
+        sum += (*last as u64) << 8;
+    }
+    sum
+}
+
+fn checksum_fold(mut sum: u64) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+#[derive(Debug, Clone, Copy)]
+struct IpInfo {
+    header_len: usize,
+    protocol: u8,
+    src: IpAddr,
+    dst: IpAddr,
+}
+
+impl IpInfo {
+    fn parse(packet: &[u8]) -> Option<Self> {
+        match packet.first()? >> 4 {
+            4 => {
+                let header_len = ((packet[0] & 0x0f) as usize) * 4;
+                if header_len < 20 || packet.len() < header_len {
+                    return None;
+                }
+                Some(Self {
+                    header_len,
+                    protocol: packet[9],
+                    src: IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(&packet[12..16]).ok()?)),
+                    dst: IpAddr::V4(Ipv4Addr::from(<[u8; 4]>::try_from(&packet[16..20]).ok()?)),
+                })
+            }
+            6 => {
+                if packet.len() < IPV6_HEADER_LEN {
+                    return None;
+                }
+                Some(Self {
+                    header_len: IPV6_HEADER_LEN,
+                    protocol: packet[6],
+                    src: IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(&packet[8..24]).ok()?)),
+                    dst: IpAddr::V6(Ipv6Addr::from(<[u8; 16]>::try_from(&packet[24..40]).ok()?)),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn is_v4(&self) -> bool {
+        self.src.is_ipv4()
+    }
+
+    fn pseudo_header_sum(&self, l4_len: usize) -> u64 {
+        let sum = match (self.src, self.dst) {
+            (IpAddr::V4(src), IpAddr::V4(dst)) => {
+                checksum_sum(&dst.octets(), checksum_sum(&src.octets(), 0))
+            }
+            (IpAddr::V6(src), IpAddr::V6(dst)) => {
+                checksum_sum(&dst.octets(), checksum_sum(&src.octets(), 0))
+            }
+            _ => unreachable!(),
+        };
+        sum + self.protocol as u64 + l4_len as u64
+    }
+
+    // fix length fields (and the header checksum of ipv4) after the packet
+    // got resized to `total_len`.
+    fn set_total_len(&self, packet: &mut [u8], total_len: usize) {
+        if self.is_v4() {
+            packet[2..4].copy_from_slice(&(total_len as u16).to_be_bytes());
+            packet[10..12].fill(0);
+            let csum = !checksum_fold(checksum_sum(&packet[..self.header_len], 0));
+            packet[10..12].copy_from_slice(&csum.to_be_bytes());
+        } else {
+            packet[4..6].copy_from_slice(&((total_len - IPV6_HEADER_LEN) as u16).to_be_bytes());
+        }
+    }
+}
+
+fn tcp_header_len(packet: &[u8], tcp_offset: usize) -> Option<usize> {
+    let len = ((*packet.get(tcp_offset + 12)? >> 4) as usize) * 4;
+    (len >= 20 && packet.len() >= tcp_offset + len).then_some(len)
+}
+
+/// Fill in the checksum of a packet the kernel only computed partially
+/// (`VIRTIO_NET_HDR_F_NEEDS_CSUM`).
+pub fn complete_checksum(hdr: &VirtioNetHdr, packet: &mut [u8]) -> bool {
+    if hdr.flags & VIRTIO_NET_HDR_F_NEEDS_CSUM == 0 {
+        return true;
+    }
+    let start = hdr.csum_start as usize;
+    let field = start + hdr.csum_offset as usize;
+    if field + 2 > packet.len() {
+        return false;
+    }
+    // the checksum field holds the sum of the pseudo header already
+    let csum = match !checksum_fold(checksum_sum(&packet[start..], 0)) {
+        0 => 0xffff,
+        v => v,
+    };
+    packet[field..field + 2].copy_from_slice(&csum.to_be_bytes());
+    true
+}
+
+/// Split a tcp gso packet into segments of `gso_size` payload bytes, each one
+/// with full headers and checksums.
+pub fn gso_split(
+    hdr: &VirtioNetHdr,
+    packet: &[u8],
+    mut emit: impl FnMut(&[u8]),
+) -> Result<(), anyhow::Error> {
+    let info = IpInfo::parse(packet).ok_or_else(|| anyhow::anyhow!("invalid ip packet"))?;
+    let expected_gso_type = if info.is_v4() {
+        VIRTIO_NET_HDR_GSO_TCPV4
+    } else {
+        VIRTIO_NET_HDR_GSO_TCPV6
+    };
+    if hdr.gso_type & !VIRTIO_NET_HDR_GSO_ECN != expected_gso_type || info.protocol != IPPROTO_TCP {
+        return Err(anyhow::anyhow!(
+            "unsupported gso type {} for protocol {}",
+            hdr.gso_type,
+            info.protocol
+        ));
+    }
+    let tcp_offset = info.header_len;
+    let headers_len = tcp_offset
+        + tcp_header_len(packet, tcp_offset)
+            .ok_or_else(|| anyhow::anyhow!("invalid tcp header"))?;
+    let mss = hdr.gso_size as usize;
+    if mss == 0 {
+        return Err(anyhow::anyhow!("gso size is zero"));
+    }
+
+    let payload = &packet[headers_len..];
+    let seq = u32::from_be_bytes(packet[tcp_offset + 4..tcp_offset + 8].try_into().unwrap());
+    let ip_id = u16::from_be_bytes([packet[4], packet[5]]);
+    let segments = payload.len().div_ceil(mss);
+
+    let mut seg = Vec::with_capacity(headers_len + mss);
+    for (i, chunk) in payload.chunks(mss).enumerate() {
+        seg.clear();
+        seg.extend_from_slice(&packet[..headers_len]);
+        seg.extend_from_slice(chunk);
+
+        if info.is_v4() {
+            seg[4..6].copy_from_slice(&ip_id.wrapping_add(i as u16).to_be_bytes());
+        }
+        info.set_total_len(&mut seg, headers_len + chunk.len());
+
+        let tcp = &mut seg[tcp_offset..];
+        tcp[4..8].copy_from_slice(&seq.wrapping_add((i * mss) as u32).to_be_bytes());
+        if i + 1 != segments {
+            tcp[13] &= !(TCP_FLAG_FIN | TCP_FLAG_PSH);
+        }
+        if i != 0 {
+            tcp[13] &= !TCP_FLAG_CWR;
+        }
+        tcp[TCP_CSUM_OFFSET..TCP_CSUM_OFFSET + 2].fill(0);
+        let csum = !checksum_fold(checksum_sum(tcp, info.pseudo_header_sum(tcp.len())));
+        tcp[TCP_CSUM_OFFSET..TCP_CSUM_OFFSET + 2].copy_from_slice(&csum.to_be_bytes());
+
+        emit(&seg);
+    }
+    Ok(())
+}
+
+/// What to write to the tun device for a batch of packets.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TunWrite {
+    /// write the packet with this index unchanged
+    Packet(usize),
+    /// a gso packet made of several packets of the batch
+    Coalesced { hdr: VirtioNetHdr, packet: Vec<u8> },
+}
+
+#[derive(Debug, Hash, PartialEq, Eq)]
+struct FlowKey {
+    src: IpAddr,
+    dst: IpAddr,
+    ports: [u8; 4],
+}
+
+struct CoalesceGroup {
+    packets: Vec<usize>,
+    info: Option<IpInfo>,
+    headers_len: usize,
+    gso_size: usize,
+    next_seq: u32,
+    total_len: usize,
+}
+
+// the merged packet gets fresh checksums, so a corrupted segment must not be
+// merged or the kernel would accept its payload.
+fn checksums_valid(info: &IpInfo, packet: &[u8]) -> bool {
+    let tcp = &packet[info.header_len..];
+    let tcp_ok = checksum_fold(checksum_sum(tcp, info.pseudo_header_sum(tcp.len()))) == 0xffff;
+    tcp_ok
+        && (!info.is_v4() || checksum_fold(checksum_sum(&packet[..info.header_len], 0)) == 0xffff)
+}
+
+// tcp segment that may be merged with others: no ip options or fragments, only
+// ack/psh flags, some payload and valid checksums.
+fn coalescable_tcp(packet: &[u8]) -> Option<(IpInfo, usize)> {
+    let info = IpInfo::parse(packet)?;
+    if info.protocol != IPPROTO_TCP {
+        return None;
+    }
+    if info.is_v4() {
+        let frag = u16::from_be_bytes([packet[6], packet[7]]);
+        // ip options, more fragments or fragment offset
+        if info.header_len != 20 || frag & 0x3fff != 0 {
+            return None;
+        }
+    }
+    let headers_len = info.header_len + tcp_header_len(packet, info.header_len)?;
+    let flags = packet[info.header_len + 13];
+    if flags & !(TCP_FLAG_ACK | TCP_FLAG_PSH) != 0
+        || packet.len() <= headers_len
+        || !checksums_valid(&info, packet)
+    {
+        return None;
+    }
+    Some((info, headers_len))
+}
+
+fn can_append(group: &CoalesceGroup, first: &[u8], packet: &[u8], headers_len: usize) -> bool {
+    let info = group.info.as_ref().unwrap();
+    let tcp_offset = info.header_len;
+    let payload_len = packet.len() - headers_len;
+    if headers_len != group.headers_len
+        || payload_len > group.gso_size
+        || group.packets.len() >= MAX_COALESCE_SEGMENTS
+        || group.total_len + payload_len > u16::MAX as usize
+    {
+        return false;
+    }
+    // the first segment ends the group if it is shorter or has psh set
+    if first[tcp_offset + 13] & TCP_FLAG_PSH != 0 && group.packets.len() == 1 {
+        return false;
+    }
+    let seq = u32::from_be_bytes(packet[tcp_offset + 4..tcp_offset + 8].try_into().unwrap());
+    if seq != group.next_seq {
+        return false;
+    }
+    // same tos/traffic class, ttl/hop limit, ack number and tcp options
+    let ip_same = if info.is_v4() {
+        first[1] == packet[1] && first[8] == packet[8] && first[6] == packet[6]
+    } else {
+        first[..4] == packet[..4] && first[7] == packet[7]
+    };
+    ip_same
+        && first[tcp_offset + 8..tcp_offset + 12] == packet[tcp_offset + 8..tcp_offset + 12]
+        && first[tcp_offset + 20..headers_len] == packet[tcp_offset + 20..headers_len]
+}
+
+fn build_coalesced(packets: &[&[u8]], group: &CoalesceGroup) -> TunWrite {
+    let info = group.info.as_ref().unwrap();
+    let tcp_offset = info.header_len;
+    let first = packets[group.packets[0]];
+    let last = packets[*group.packets.last().unwrap()];
+
+    let mut packet = Vec::with_capacity(group.total_len);
+    packet.extend_from_slice(&first[..group.headers_len]);
+    for idx in group.packets.iter() {
+        packet.extend_from_slice(&packets[*idx][group.headers_len..]);
+    }
+    info.set_total_len(&mut packet, group.total_len);
+
+    let tcp_len = packet.len() - tcp_offset;
+    let tcp = &mut packet[tcp_offset..];
+    tcp[13] |= last[tcp_offset + 13] & TCP_FLAG_PSH;
+    // the kernel completes the checksum, only the pseudo header sum goes here
+    let partial = checksum_fold(info.pseudo_header_sum(tcp_len));
+    tcp[TCP_CSUM_OFFSET..TCP_CSUM_OFFSET + 2].copy_from_slice(&partial.to_be_bytes());
+
+    TunWrite::Coalesced {
+        hdr: VirtioNetHdr {
+            flags: VIRTIO_NET_HDR_F_NEEDS_CSUM,
+            gso_type: if info.is_v4() {
+                VIRTIO_NET_HDR_GSO_TCPV4
+            } else {
+                VIRTIO_NET_HDR_GSO_TCPV6
+            },
+            hdr_len: group.headers_len as u16,
+            gso_size: group.gso_size as u16,
+            csum_start: tcp_offset as u16,
+            csum_offset: TCP_CSUM_OFFSET as u16,
+        },
+        packet,
+    }
+}
+
+/// Merge consecutive segments of the same tcp flows in `packets`. Packets of
+/// one flow keep their order, groups are returned in the order of their first
+/// packet.
+pub fn coalesce_tcp(packets: &[&[u8]]) -> Vec<TunWrite> {
+    let mut groups: Vec<CoalesceGroup> = Vec::with_capacity(packets.len());
+    let mut flows: HashMap<FlowKey, usize> = HashMap::new();
+
+    for (idx, packet) in packets.iter().enumerate() {
+        let single = |info| CoalesceGroup {
+            packets: vec![idx],
+            info,
+            headers_len: 0,
+            gso_size: 0,
+            next_seq: 0,
+            total_len: packet.len(),
+        };
+
+        let Some((info, headers_len)) = coalescable_tcp(packet) else {
+            // a tcp packet that can't be merged ends the group of its flow
+            if let Some(info) = IpInfo::parse(packet)
+                && info.protocol == IPPROTO_TCP
+                && let Some(ports) = packet.get(info.header_len..info.header_len + 4)
+            {
+                flows.remove(&FlowKey {
+                    src: info.src,
+                    dst: info.dst,
+                    ports: ports.try_into().unwrap(),
+                });
+            }
+            groups.push(single(None));
+            continue;
+        };
+
+        let tcp_offset = info.header_len;
+        let key = FlowKey {
+            src: info.src,
+            dst: info.dst,
+            ports: packet[tcp_offset..tcp_offset + 4].try_into().unwrap(),
+        };
+        let payload_len = packet.len() - headers_len;
+        let seq = u32::from_be_bytes(packet[tcp_offset + 4..tcp_offset + 8].try_into().unwrap());
+
+        if let Some(group_idx) = flows.get(&key).copied() {
+            let group = &mut groups[group_idx];
+            let first = packets[group.packets[0]];
+            if can_append(group, first, packet, headers_len) {
+                group.packets.push(idx);
+                group.next_seq = seq.wrapping_add(payload_len as u32);
+                group.total_len += payload_len;
+                // a shorter segment or psh ends the group
+                if payload_len < group.gso_size || packet[tcp_offset + 13] & TCP_FLAG_PSH != 0 {
+                    flows.remove(&key);
+                }
+                continue;
+            }
+        }
+
+        flows.insert(key, groups.len());
+        groups.push(CoalesceGroup {
+            headers_len,
+            gso_size: payload_len,
+            next_seq: seq.wrapping_add(payload_len as u32),
+            ..single(Some(info))
+        });
+    }
+
+    groups
+        .iter()
+        .map(|group| {
+            if group.packets.len() == 1 {
+                TunWrite::Packet(group.packets[0])
+            } else {
+                build_coalesced(packets, group)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verify_tcp_checksum(packet: &[u8]) {
+        let info = IpInfo::parse(packet).unwrap();
+        let tcp = &packet[info.header_len..];
+        assert_eq!(
+            checksum_fold(checksum_sum(tcp, info.pseudo_header_sum(tcp.len()))),
+            0xffff
+        );
+        if info.is_v4() {
+            assert_eq!(
+                checksum_fold(checksum_sum(&packet[..info.header_len], 0)),
+                0xffff
+            );
+        }
+    }
+
+    fn tcp_packet(v6: bool, seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let ip_len = if v6 { IPV6_HEADER_LEN } else { 20 };
+        // tcp header with a timestamp option
+        let tcp_len = 32;
+        let mut packet = vec![0u8; ip_len + tcp_len];
+        if v6 {
+            packet[0] = 0x60;
+            packet[6] = IPPROTO_TCP;
+            packet[7] = 64;
+            packet[8..24].copy_from_slice(&"fd00::1".parse::<Ipv6Addr>().unwrap().octets());
+            packet[24..40].copy_from_slice(&"fd00::2".parse::<Ipv6Addr>().unwrap().octets());
+        } else {
+            packet[0] = 0x45;
+            packet[4..6].copy_from_slice(&100u16.to_be_bytes());
+            packet[6] = 0x40;
+            packet[8] = 64;
+            packet[9] = IPPROTO_TCP;
+            packet[12..16].copy_from_slice(&[10, 126, 126, 1]);
+            packet[16..20].copy_from_slice(&[10, 126, 126, 2]);
+        }
+        let tcp = &mut packet[ip_len..];
+        tcp[0..2].copy_from_slice(&40000u16.to_be_bytes());
+        tcp[2..4].copy_from_slice(&5201u16.to_be_bytes());
+        tcp[4..8].copy_from_slice(&seq.to_be_bytes());
+        tcp[8..12].copy_from_slice(&7u32.to_be_bytes());
+        tcp[12] = ((tcp_len / 4) as u8) << 4;
+        tcp[13] = flags;
+        tcp[14..16].copy_from_slice(&1024u16.to_be_bytes());
+        tcp[20..32].copy_from_slice(&[1, 1, 8, 10, 0, 0, 0, 1, 0, 0, 0, 2]);
+        packet.extend_from_slice(payload);
+
+        let info = IpInfo::parse(&packet).unwrap();
+        let total_len = packet.len();
+        info.set_total_len(&mut packet, total_len);
+        let tcp = &mut packet[ip_len..];
+        let csum = !checksum_fold(checksum_sum(tcp, info.pseudo_header_sum(tcp.len())));
+        tcp[16..18].copy_from_slice(&csum.to_be_bytes());
+        packet
+    }
+
+    fn gso_hdr(v6: bool, mss: u16) -> VirtioNetHdr {
+        VirtioNetHdr {
+            gso_type: if v6 {
+                VIRTIO_NET_HDR_GSO_TCPV6
+            } else {
+                VIRTIO_NET_HDR_GSO_TCPV4
+            },
+            gso_size: mss,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn vnet_hdr_roundtrip() {
+        let hdr = VirtioNetHdr {
+            flags: VIRTIO_NET_HDR_F_NEEDS_CSUM,
+            gso_type: VIRTIO_NET_HDR_GSO_TCPV4,
+            hdr_len: 52,
+            gso_size: 1448,
+            csum_start: 20,
+            csum_offset: 16,
+        };
+        let mut buf = [0u8; VIRTIO_NET_HDR_LEN];
+        hdr.encode(&mut buf);
+        assert_eq!(VirtioNetHdr::decode(&buf), Some(hdr));
+        assert!(hdr.is_gso());
+    }
+
+    #[test]
+    fn split_and_coalesce_roundtrip() {
+        for v6 in [false, true] {
+            let payload = (0..2500u32).map(|i| i as u8).collect::<Vec<_>>();
+            let super_packet = tcp_packet(v6, 1000, TCP_FLAG_ACK | TCP_FLAG_PSH, &payload);
+
+            let mut segments = vec![];
+            gso_split(&gso_hdr(v6, 1000), &super_packet, |seg| {
+                segments.push(seg.to_vec())
+            })
+            .unwrap();
+            assert_eq!(segments.len(), 3);
+            for (i, seg) in segments.iter().enumerate() {
+                verify_tcp_checksum(seg);
+                let info = IpInfo::parse(seg).unwrap();
+                let tcp = &seg[info.header_len..];
+                let seq = u32::from_be_bytes(tcp[4..8].try_into().unwrap());
+                assert_eq!(seq, 1000 + i as u32 * 1000);
+                assert_eq!(tcp[13] & TCP_FLAG_PSH != 0, i == 2);
+            }
+            assert_eq!(segments[2].len(), super_packet.len() - 2000);
+
+            let refs = segments.iter().map(|s| s.as_slice()).collect::<Vec<_>>();
+            let writes = coalesce_tcp(&refs);
+            assert_eq!(writes.len(), 1);
+            let TunWrite::Coalesced { hdr, mut packet } = writes.into_iter().next().unwrap() else {
+                panic!("not coalesced");
+            };
+            assert_eq!(hdr.gso_size, 1000);
+            assert_eq!(packet.len(), super_packet.len());
+            assert_eq!(&packet[packet.len() - payload.len()..], &payload[..]);
+
+            // what the kernel does with the gso packet we write
+            assert!(complete_checksum(&hdr, &mut packet));
+            verify_tcp_checksum(&packet);
+            let mut resplit = vec![];
+            gso_split(&hdr, &packet, |seg| resplit.push(seg.to_vec())).unwrap();
+            assert_eq!(resplit, segments);
+        }
+    }
+
+    #[test]
+    fn coalesce_keeps_unrelated_packets() {
+        let a = tcp_packet(false, 0, TCP_FLAG_ACK, &[1; 100]);
+        let b = tcp_packet(false, 100, TCP_FLAG_ACK, &[2; 100]);
+        // gap in the sequence numbers
+        let c = tcp_packet(false, 300, TCP_FLAG_ACK, &[3; 100]);
+        // syn can't be merged
+        let d = tcp_packet(false, 400, 0x02, &[]);
+        let udp = {
+            let mut p = tcp_packet(false, 0, 0, &[0; 8]);
+            p[9] = 17;
+            p
+        };
+        let writes = coalesce_tcp(&[&a, &udp, &b, &c, &d]);
+        assert_eq!(writes.len(), 4);
+        assert!(
+            matches!(&writes[0], TunWrite::Coalesced { packet, .. } if packet.len() == a.len() + 100)
+        );
+        assert_eq!(writes[1], TunWrite::Packet(1));
+        assert_eq!(writes[2], TunWrite::Packet(3));
+        assert_eq!(writes[3], TunWrite::Packet(4));
+    }
+
+    #[test]
+    fn coalesce_skips_segments_with_bad_checksum() {
+        for v6 in [false, true] {
+            let a = tcp_packet(v6, 0, TCP_FLAG_ACK, &[1; 100]);
+            let mut b = tcp_packet(v6, 100, TCP_FLAG_ACK, &[2; 100]);
+            let c = tcp_packet(v6, 200, TCP_FLAG_ACK, &[3; 100]);
+            let d = tcp_packet(v6, 300, TCP_FLAG_ACK, &[4; 100]);
+            // corrupted payload, the checksum no longer matches
+            *b.last_mut().unwrap() ^= 0xff;
+
+            let writes = coalesce_tcp(&[&a, &b, &c, &d]);
+            assert_eq!(writes.len(), 3);
+            assert_eq!(writes[0], TunWrite::Packet(0));
+            assert_eq!(writes[1], TunWrite::Packet(1));
+            assert!(
+                matches!(&writes[2], TunWrite::Coalesced { packet, .. } if packet.len() == c.len() + 100)
+            );
+        }
+    }
+}
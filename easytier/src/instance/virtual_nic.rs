@@ -15,11 +15,17 @@ use crate::{
         log,
     },
     instance::proxy_cidrs_monitor::ProxyCidrsMonitor,
-    peers::{PacketRecvChanReceiver, peer_manager::PeerManager, recv_packet_from_chan},
+    peers::{
+        PacketRecvChanReceiver,
+        l2_switch::{ETHERNET_HEADER_LEN, L2Switch},
+        peer_manager::PeerManager,
+        recv_packet_from_chan,
+    },
     tunnel::{
         StreamItem, Tunnel, TunnelError, ZCPacketSink, ZCPacketStream,
+        buf::PACKET_BUF_CHUNK_SIZE,
         common::{FramedWriter, TunnelWrapper, ZCPacketToBytes, reserve_buf},
-        packet_def::{TAIL_RESERVED_SIZE, ZCPacket, ZCPacketType},
+        packet_def::{PacketType, TAIL_RESERVED_SIZE, ZCPacket, ZCPacketType},
     },
 };
 
@@ -74,7 +80,11 @@ impl Stream for TunStream {
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<StreamItem>> {
         let self_mut = self.project();
         let mut g = ready!(self_mut.l.poll_lock(cx));
-        reserve_buf(self_mut.cur_buf, 2500, 4 * 1024);
+        reserve_buf(
+            self_mut.cur_buf,
+            *self_mut.payload_offset + 2500 + TAIL_RESERVED_SIZE,
+            PACKET_BUF_CHUNK_SIZE,
+        );
         if self_mut.cur_buf.is_empty() {
             unsafe {
                 self_mut.cur_buf.set_len(*self_mut.payload_offset);
@@ -244,6 +254,10 @@ pub struct VirtualNic {
 
     ifname: Option<String>,
     ifcfg: Box<dyn IfConfiguerTrait + Send + Sync + 'static>,
+
+    // readers of the other queues of a multi-queue tun device
+    #[cfg(target_os = "linux")]
+    extra_queues: Vec<Pin<Box<dyn ZCPacketStream>>>,
 }
 
 impl Drop for VirtualNic {
@@ -270,6 +284,8 @@ impl VirtualNic {
             global_ctx,
             ifname: None,
             ifcfg: Box::new(IfConfiger {}),
+            #[cfg(target_os = "linux")]
+            extra_queues: Vec::new(),
         }
     }
 
@@ -493,8 +509,13 @@ impl VirtualNic {
     }
 
     async fn create_tun(&self) -> Result<tun::platform::Device, Error> {
+        let enable_tap = self.global_ctx.get_flags().enable_tap;
+        if enable_tap && !cfg!(target_os = "linux") {
+            return Err(anyhow::anyhow!("tap mode is only supported on linux").into());
+        }
+
         let mut config = Configuration::default();
-        config.layer(Layer::L3);
+        config.layer(if enable_tap { Layer::L2 } else { Layer::L3 });
 
         // FreeBSD specific: Check and restore TUN interfaces before creating new one
         #[cfg(target_os = "freebsd")]
@@ -619,7 +640,79 @@ impl VirtualNic {
         Ok(Box::new(ft))
     }
 
+    // tun device with vnet header offloads and one queue per worker thread,
+    // returns none if it's not enabled or can't be created.
+    #[cfg(target_os = "linux")]
+    async fn create_offload_dev(&mut self) -> Result<Option<Box<dyn Tunnel>>, Error> {
+        let flags = self.global_ctx.get_flags();
+        if flags.enable_tap || !flags.enable_tun_offload {
+            return Ok(None);
+        }
+
+        Self::ensure_tun_device_node().await;
+        let queues = if flags.multi_thread {
+            2.max(flags.multi_thread_count as usize)
+        } else {
+            1
+        };
+        let dev = {
+            let _g = self.global_ctx.net_ns.guard();
+            super::linux_tun::LinuxTun::create(&flags.dev_name, queues, true)
+        };
+        let dev = match dev {
+            Ok(dev) => dev,
+            Err(error) => {
+                log::warn!(%error, "failed to create tun device with offloads, fall back to the default one");
+                return Ok(None);
+            }
+        };
+        tracing::info!(
+            ifname = %dev.name,
+            queues = dev.queue_count(),
+            vnet_hdr = dev.vnet_hdr,
+            "tun device created"
+        );
+
+        let ifname = dev.name.clone();
+        self.ifcfg.wait_interface_show(ifname.as_str()).await?;
+        self.set_dev_mtu(ifname.as_str()).await?;
+        {
+            let _g = self.global_ctx.net_ns.guard();
+            self.ifcfg.set_link_status(ifname.as_str(), true).await?;
+        }
+
+        let (tunnel, extra_queues) = dev.into_tunnels()?;
+        self.extra_queues = extra_queues;
+        self.ifname = Some(ifname);
+        Ok(Some(tunnel))
+    }
+
+    #[cfg(target_os = "linux")]
+    pub fn take_extra_queues(&mut self) -> Vec<Pin<Box<dyn ZCPacketStream>>> {
+        std::mem::take(&mut self.extra_queues)
+    }
+
+    async fn set_dev_mtu(&self, ifname: &str) -> Result<(), Error> {
+        let flags = self.global_ctx.config.get_flags();
+        let mut mtu_in_config = flags.mtu;
+        if flags.enable_encryption {
+            mtu_in_config -= 20;
+        }
+        if flags.enable_tap {
+            mtu_in_config -= ETHERNET_HEADER_LEN as u32;
+        }
+        // set mtu by ourselves, rust-tun does not handle it correctly on windows
+        let _g = self.global_ctx.net_ns.guard();
+        self.ifcfg.set_mtu(ifname, mtu_in_config).await?;
+        Ok(())
+    }
+
     pub async fn create_dev(&mut self) -> Result<Box<dyn Tunnel>, Error> {
+        #[cfg(target_os = "linux")]
+        if let Some(tunnel) = self.create_offload_dev().await? {
+            return Ok(tunnel);
+        }
+
         let dev = self.create_tun().await?;
 
         #[cfg(not(target_os = "freebsd"))]
@@ -667,17 +760,7 @@ impl VirtualNic {
         }
 
         let dev = AsyncDevice::new(dev)?;
-
-        let flags = self.global_ctx.config.get_flags();
-        let mut mtu_in_config = flags.mtu;
-        if flags.enable_encryption {
-            mtu_in_config -= 20;
-        }
-        {
-            // set mtu by ourselves, rust-tun does not handle it correctly on windows
-            let _g = self.global_ctx.net_ns.guard();
-            self.ifcfg.set_mtu(ifname.as_str(), mtu_in_config).await?;
-        }
+        self.set_dev_mtu(ifname.as_str()).await?;
 
         let has_packet_info = cfg!(all(target_os = "macos", not(feature = "macos-ne")));
         let (a, b) = BiLock::new(dev);
@@ -722,6 +805,25 @@ impl VirtualNic {
         self.ifname.as_ref().unwrap().as_str()
     }
 
+    #[cfg(target_os = "linux")]
+    async fn setup_tap(&self, l2_switch: &L2Switch) -> Result<(), Error> {
+        let ifname = self.ifname();
+        let _g = self.global_ctx.net_ns.guard();
+        let mac = std::fs::read_to_string(format!("/sys/class/net/{}/address", ifname))?;
+        let mac = mac
+            .trim()
+            .parse()
+            .map_err(|e| anyhow::anyhow!("invalid mac address of {}: {:?}", ifname, e))?;
+        l2_switch.set_tap_mac(mac);
+
+        let tap_bridge = self.global_ctx.get_flags().tap_bridge;
+        if !tap_bridge.is_empty() {
+            crate::common::ifcfg::bridge_interface(ifname, &tap_bridge).await?;
+            tracing::info!(ifname, tap_bridge, "tap device bridged");
+        }
+        Ok(())
+    }
+
     pub async fn link_up(&self) -> Result<(), Error> {
         let _g = self.global_ctx.net_ns.guard();
         self.ifcfg.set_link_status(self.ifname(), true).await?;
@@ -953,6 +1055,17 @@ impl NicCtx {
         }
     }
 
+    // frames to the tap device must be ethernet, ip packets from peers without
+    // tap mode are wrapped.
+    fn to_tap_frame(l2_switch: &L2Switch, packet: ZCPacket) -> Option<ZCPacket> {
+        let hdr = packet.peer_manager_header()?;
+        if hdr.packet_type == PacketType::Ethernet as u8 {
+            return Some(packet);
+        }
+        let frame = l2_switch.wrap_ip_packet(hdr.from_peer_id.get(), packet.payload())?;
+        Some(ZCPacket::new_with_payload(&frame))
+    }
+
     async fn do_forward_nic_to_peers(ret: ZCPacket, mgr: &PeerManager) {
         let payload = ret.payload();
         if payload.is_empty() {
@@ -983,7 +1096,14 @@ impl NicCtx {
                     tracing::error!("read from nic failed: {:?}", ret);
                     break;
                 }
-                Self::do_forward_nic_to_peers(ret.unwrap(), mgr.as_ref()).await;
+                let mut packet = ret.unwrap();
+                if mgr.get_l2_switch().is_some() {
+                    let Some(ip_packet) = mgr.send_tap_frame(packet).await else {
+                        continue;
+                    };
+                    packet = ip_packet;
+                }
+                Self::do_forward_nic_to_peers(packet, mgr.as_ref()).await;
             }
             close_notifier.notify_one();
             tracing::error!("nic closed when recving from it");
@@ -995,15 +1115,30 @@ impl NicCtx {
     fn do_forward_peers_to_nic(&mut self, mut sink: Pin<Box<dyn ZCPacketSink>>) {
         let channel = self.peer_packet_receiver.clone();
         let close_notifier = self.close_notifier.clone();
+        let l2_switch = self
+            .peer_mgr
+            .upgrade()
+            .and_then(|mgr| mgr.get_l2_switch().cloned());
         self.tasks.spawn(async move {
             // unlock until coroutine finished
             let mut channel = channel.lock().await;
-            while let Ok(packet) = recv_packet_from_chan(&mut channel).await {
+            while let Ok(mut packet) = recv_packet_from_chan(&mut channel).await {
                 tracing::trace!(
                     "[USER_PACKET] forward packet from peers to nic. packet: {:?}",
                     packet
                 );
-                let ret = sink.send(packet).await;
+                if let Some(l2_switch) = &l2_switch {
+                    let Some(frame) = Self::to_tap_frame(l2_switch, packet) else {
+                        continue;
+                    };
+                    packet = frame;
+                }
+                // only flush when the channel is drained, so the sink can
+                // merge the packets of a burst
+                let mut ret = sink.feed(packet).await;
+                if ret.is_ok() && channel.is_empty() {
+                    ret = sink.flush().await;
+                }
                 if ret.is_err() {
                     tracing::error!(?ret, "do_forward_tunnel_to_nic sink error");
                 }
@@ -1360,6 +1495,15 @@ impl NicCtx {
                             .await;
                     }
 
+                    #[cfg(target_os = "linux")]
+                    if let Some(l2_switch) = self
+                        .peer_mgr
+                        .upgrade()
+                        .and_then(|mgr| mgr.get_l2_switch().cloned())
+                    {
+                        nic.setup_tap(&l2_switch).await?;
+                    }
+
                     self.global_ctx
                         .issue_event(GlobalCtxEvent::TunDeviceReady(nic.ifname().to_string()));
                     ret
@@ -1375,6 +1519,13 @@ impl NicCtx {
         let (stream, sink) = tunnel.split();
 
         self.do_forward_nic_to_peers_task(stream)?;
+        #[cfg(target_os = "linux")]
+        {
+            let extra_queues = self.nic.lock().await.take_extra_queues();
+            for stream in extra_queues {
+                self.do_forward_nic_to_peers_task(stream)?;
+            }
+        }
         self.do_forward_peers_to_nic(sink);
 
         // Assign IPv4 address if provided
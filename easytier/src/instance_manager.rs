@@ -566,6 +566,10 @@ fn handle_event(
                     GlobalCtxEvent::CredentialChanged => {
                         event!(info, "[{}] credential changed", instance_id);
                     }
+
+                    GlobalCtxEvent::NetworkSecretRotated(generation) => {
+                        event!(info, generation, "[{}] network secret rotated", instance_id);
+                    }
                 }
             } else {
                 events = events.resubscribe();
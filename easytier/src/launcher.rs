@@ -356,6 +356,13 @@ pub struct NetworkInstance {
 
 impl NetworkInstance {
     pub fn new(config: TomlConfigLoader, config_file_control: ConfigFileControl) -> Self {
+        // runtime changes such as a secret rotation are written back to a writable config file
+        config.set_config_file(
+            config_file_control
+                .path
+                .clone()
+                .filter(|_| !config_file_control.is_read_only()),
+        );
         Self {
             config,
             launcher: None,
@@ -821,22 +828,29 @@ impl NetworkConfig {
         }
 
         if let Some(credential_secret) = credential_secret {
-            cfg.set_secure_mode(Some(process_secure_mode_cfg(
-                crate::proto::common::SecureModeConfig {
-                    enabled: true,
-                    local_private_key: Some(credential_secret),
-                    local_public_key: None,
-                },
-            )?));
+            cfg.set_secure_mode(Some(crate::proto::common::SecureModeConfig {
+                enabled: true,
+                local_private_key: Some(credential_secret),
+                local_public_key: None,
+                pq_hybrid: self
+                    .secure_mode
+                    .as_ref()
+                    .map(|cfg| cfg.pq_hybrid)
+                    .unwrap_or(false),
+            }));
         } else {
-            cfg.set_secure_mode(
-                self.secure_mode
-                    .clone()
-                    .map(process_secure_mode_cfg)
-                    .transpose()?,
-            );
+            cfg.set_secure_mode(self.secure_mode.clone());
         }
 
+        // a NetworkConfig may come from the web console, so it can't reference local
+        // files or commands. this only rejects references, plain secrets pass.
+        cfg.resolve_secret_refs(false)?;
+        cfg.set_secure_mode(
+            cfg.get_secure_mode()
+                .map(process_secure_mode_cfg)
+                .transpose()?,
+        );
+
         let mut flags = gen_default_flags();
         if let Some(latency_first) = self.latency_first {
             flags.latency_first = latency_first;
@@ -936,6 +950,10 @@ impl NetworkConfig {
             flags.enable_encryption = !disable_encryption;
         }
 
+        if let Some(enable_conn_rekey) = self.enable_conn_rekey {
+            flags.enable_conn_rekey = enable_conn_rekey;
+        }
+
         if self.enable_relay_network_whitelist.unwrap_or_default() {
             if !self.relay_network_whitelist.is_empty() {
                 flags.relay_network_whitelist = self.relay_network_whitelist.join(" ");
@@ -964,6 +982,22 @@ impl NetworkConfig {
             flags.enable_udp_broadcast_relay = enable_udp_broadcast_relay;
         }
 
+        if let Some(enable_lan_discovery) = self.enable_lan_discovery {
+            flags.enable_lan_discovery = enable_lan_discovery;
+        }
+
+        if let Some(enable_tap) = self.enable_tap {
+            flags.enable_tap = enable_tap;
+        }
+
+        if let Some(tap_bridge) = self.tap_bridge.clone() {
+            flags.tap_bridge = tap_bridge;
+        }
+
+        if let Some(enable_tun_offload) = self.enable_tun_offload {
+            flags.enable_tun_offload = enable_tun_offload;
+        }
+
         if let Some(disable_sym_hole_punching) = self.disable_sym_hole_punching {
             flags.disable_sym_hole_punching = disable_sym_hole_punching;
         }
@@ -1022,7 +1056,9 @@ impl NetworkConfig {
 
         let network_identity = config.get_network_identity();
         result.network_name = Some(network_identity.network_name.clone());
-        result.network_secret = network_identity.network_secret;
+        result.network_secret = network_identity
+            .network_secret
+            .map(|secret| config.redact_secret(&secret));
 
         if let Some(ipv4) = config.get_ipv4() {
             result.virtual_ipv4 = Some(ipv4.address().to_string());
@@ -1111,7 +1147,12 @@ impl NetworkConfig {
             result.mapped_listeners = mapped_listeners.iter().map(|l| l.to_string()).collect();
         }
 
-        result.secure_mode = config.get_secure_mode();
+        result.secure_mode = config.get_secure_mode().map(|mut secure_mode| {
+            secure_mode.local_private_key = secure_mode
+                .local_private_key
+                .map(|key| config.redact_secret(&key));
+            secure_mode
+        });
         result.credential_file = config
             .get_credential_file()
             .map(|path| path.to_string_lossy().into_owned());
@@ -1136,11 +1177,16 @@ impl NetworkConfig {
         result.multi_thread = Some(flags.multi_thread);
         result.proxy_forward_by_system = Some(flags.proxy_forward_by_system);
         result.disable_encryption = Some(!flags.enable_encryption);
+        result.enable_conn_rekey = Some(flags.enable_conn_rekey);
         result.disable_tcp_hole_punching = Some(flags.disable_tcp_hole_punching);
         result.disable_udp_hole_punching = Some(flags.disable_udp_hole_punching);
         result.disable_upnp = Some(flags.disable_upnp);
         result.disable_relay_data = Some(flags.disable_relay_data);
         result.enable_udp_broadcast_relay = Some(flags.enable_udp_broadcast_relay);
+        result.enable_lan_discovery = Some(flags.enable_lan_discovery);
+        result.enable_tap = Some(flags.enable_tap);
+        result.tap_bridge = Some(flags.tap_bridge.clone());
+        result.enable_tun_offload = Some(flags.enable_tun_offload);
         result.disable_sym_hole_punching = Some(flags.disable_sym_hole_punching);
         result.enable_magic_dns = Some(flags.accept_dns);
         result.mtu = Some(flags.mtu as i32);
@@ -1382,6 +1428,7 @@ mod tests {
                     enabled: true,
                     local_private_key: None,
                     local_public_key: None,
+                    pq_hybrid: false,
                 }));
             }
 
@@ -1406,10 +1453,14 @@ mod tests {
                 flags.multi_thread = rng.gen_bool(0.7);
                 flags.proxy_forward_by_system = rng.gen_bool(0.3);
                 flags.enable_encryption = rng.gen_bool(0.8);
+                flags.enable_conn_rekey = rng.gen_bool(0.3);
                 flags.disable_tcp_hole_punching = rng.gen_bool(0.2);
                 flags.disable_udp_hole_punching = rng.gen_bool(0.2);
                 flags.disable_upnp = rng.gen_bool(0.2);
                 flags.enable_udp_broadcast_relay = rng.gen_bool(0.2);
+                flags.enable_lan_discovery = rng.gen_bool(0.2);
+                flags.enable_tap = rng.gen_bool(0.1);
+                flags.enable_tun_offload = rng.gen_bool(0.2);
                 flags.accept_dns = rng.gen_bool(0.6);
                 flags.mtu = rng.gen_range(1200..1500);
                 flags.private_mode = rng.gen_bool(0.3);
@@ -1471,6 +1522,7 @@ mod tests {
             enabled: true,
             local_private_key: Some(credential_secret.clone()),
             local_public_key: Some(BASE64_STANDARD.encode(public_key.as_bytes())),
+            pq_hybrid: false,
         }));
 
         let network_config = super::NetworkConfig::new_from_config(&config)?;
@@ -1508,4 +1560,39 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_network_config_rejects_secret_refs() {
+        let dir = tempfile::tempdir().unwrap();
+        let secret_path = dir.path().join("secret");
+        std::fs::write(&secret_path, "from-file").unwrap();
+
+        for secret in [
+            format!("file://{}", secret_path.display()),
+            "credential://secret".to_owned(),
+            "exec:///bin/echo secret".to_owned(),
+        ] {
+            let network_config = super::NetworkConfig {
+                network_name: Some("refs".to_owned()),
+                network_secret: Some(secret.clone()),
+                ..Default::default()
+            };
+            assert!(network_config.gen_config().is_err(), "{}", secret);
+        }
+
+        let network_config = super::NetworkConfig {
+            network_name: Some("refs".to_owned()),
+            network_secret: Some("plain".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            network_config
+                .gen_config()
+                .unwrap()
+                .get_network_identity()
+                .network_secret
+                .as_deref(),
+            Some("plain")
+        );
+    }
 }
@@ -13,6 +13,7 @@ use pnet::packet::{
     Packet as _, ip::IpNextHeaderProtocols, ipv4::Ipv4Packet, tcp::TcpPacket, udp::UdpPacket,
 };
 
+use super::l2_switch::ethernet_ip_payload;
 use crate::proto::acl::{AclStats, Protocol};
 use crate::tunnel::packet_def::PacketType;
 use crate::{
@@ -141,11 +142,9 @@ impl AclFilter {
     /// Extract packet information for ACL processing
     fn extract_packet_info(
         &self,
-        packet: &ZCPacket,
+        payload: &[u8],
         route: &(dyn super::route_trait::Route + Send + Sync + 'static),
     ) -> Option<PacketInfo> {
-        let payload = packet.payload();
-
         let src_ip;
         let dst_ip;
         let src_port;
@@ -327,12 +326,21 @@ impl AclFilter {
             return true;
         }
 
-        if packet.peer_manager_header().unwrap().packet_type != PacketType::Data as u8 {
+        let packet_type = packet.peer_manager_header().unwrap().packet_type;
+        let payload = if packet_type == PacketType::Data as u8 {
+            packet.payload()
+        } else if packet_type == PacketType::Ethernet as u8 {
+            // non-ip frames of tap mode (arp etc.) are not filtered
+            let Some(payload) = ethernet_ip_payload(packet.payload()) else {
+                return true;
+            };
+            payload
+        } else {
             return true;
-        }
+        };
 
         // Extract packet information
-        let packet_info = match self.extract_packet_info(packet, route) {
+        let packet_info = match self.extract_packet_info(payload, route) {
             Some(info) => info,
             None => {
                 tracing::warn!(
@@ -1,8 +1,19 @@
 use crate::{
-    common::{config::EncryptionAlgorithm, log},
+    common::{
+        config::EncryptionAlgorithm,
+        global_ctx::{ArcGlobalCtx, GlobalCtx},
+        log,
+    },
     tunnel::packet_def::ZCPacket,
 };
-use std::sync::Arc;
+use arc_swap::ArcSwap;
+use std::{
+    cell::RefCell,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
 
 #[cfg(feature = "wireguard")]
 pub mod ring;
@@ -56,6 +67,114 @@ impl Encryptor for NullCipher {
     }
 }
 
+/// Encryptor keyed by the network secret. While a secret rotation is in progress,
+/// packets are sealed with the current secret and opened with any accepted one.
+/// Xor cannot tell a wrong key apart, so it only ever uses the current secret.
+pub struct NetworkSecretEncryptor {
+    algorithm: String,
+    global_ctx: ArcGlobalCtx,
+    // (secret generation, encryptors of the accepted secrets, current first)
+    encryptors: ArcSwap<(u32, Vec<Arc<dyn Encryptor>>)>,
+    // index of the encryptor that opened the last packet, tried first
+    last_decrypt_idx: AtomicUsize,
+}
+
+thread_local! {
+    // original payload of the packet being opened, restored after a failed attempt
+    static DECRYPT_SCRATCH: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+impl NetworkSecretEncryptor {
+    pub fn new(algorithm: &str, global_ctx: ArcGlobalCtx) -> Self {
+        Self {
+            algorithm: algorithm.to_owned(),
+            global_ctx,
+            encryptors: ArcSwap::from_pointee((u32::MAX, Vec::new())),
+            last_decrypt_idx: AtomicUsize::new(0),
+        }
+    }
+
+    fn encryptors(&self) -> Arc<(u32, Vec<Arc<dyn Encryptor>>)> {
+        let generation = self.global_ctx.network_secret_generation();
+        let cached = self.encryptors.load_full();
+        if cached.0 == generation && !cached.1.is_empty() {
+            return cached;
+        }
+
+        let is_xor = matches!(
+            EncryptionAlgorithm::try_from(self.algorithm.as_str()),
+            Ok(EncryptionAlgorithm::Xor)
+        );
+        let mut identities = self.global_ctx.get_accepted_network_identities();
+        if is_xor {
+            identities.truncate(1);
+        }
+        let encryptors = identities
+            .into_iter()
+            .map(|identity| {
+                let secret = identity.network_secret.unwrap_or_default();
+                create_encryptor(
+                    &self.algorithm,
+                    GlobalCtx::derive_128_key(&secret),
+                    GlobalCtx::derive_256_key(&secret),
+                )
+            })
+            .collect();
+        let fresh = Arc::new((generation, encryptors));
+        self.encryptors.store(fresh.clone());
+        fresh
+    }
+}
+
+impl Encryptor for NetworkSecretEncryptor {
+    fn decrypt(&self, zc_packet: &mut ZCPacket) -> Result<(), Error> {
+        let encryptors = self.encryptors();
+        let is_encrypted = zc_packet
+            .peer_manager_header()
+            .is_some_and(|hdr| hdr.is_encrypted());
+        if encryptors.1.len() == 1 || !is_encrypted {
+            return encryptors.1[0].decrypt(zc_packet);
+        }
+
+        // a failed attempt may leave the payload half decrypted (or zeroed), keep
+        // the original bytes and put them back before trying the next secret
+        let count = encryptors.1.len();
+        let first = self.last_decrypt_idx.load(Ordering::Relaxed) % count;
+        DECRYPT_SCRATCH.with_borrow_mut(|original| {
+            original.clear();
+            original.extend_from_slice(zc_packet.payload());
+
+            let mut last_err = Error::DecryptionFailed;
+            for idx in (0..count).map(|i| (first + i) % count) {
+                if idx != first {
+                    zc_packet.mut_payload().copy_from_slice(original);
+                }
+                match encryptors.1[idx].decrypt(zc_packet) {
+                    Ok(()) => {
+                        self.last_decrypt_idx.store(idx, Ordering::Relaxed);
+                        return Ok(());
+                    }
+                    Err(e) => last_err = e,
+                }
+            }
+            zc_packet.mut_payload().copy_from_slice(original);
+            Err(last_err)
+        })
+    }
+
+    fn encrypt(&self, zc_packet: &mut ZCPacket) -> Result<(), Error> {
+        self.encryptors().1[0].encrypt(zc_packet)
+    }
+
+    fn encrypt_with_nonce(
+        &self,
+        zc_packet: &mut ZCPacket,
+        nonce: Option<&[u8]>,
+    ) -> Result<(), Error> {
+        self.encryptors().1[0].encrypt_with_nonce(zc_packet, nonce)
+    }
+}
+
 /// Create an encryptor based on the algorithm name
 pub fn create_encryptor(
     algorithm: &str,
@@ -105,3 +224,122 @@ pub fn create_encryptor(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use strum::VariantArray as _;
+
+    use super::*;
+    use crate::{
+        peers::peer_manager::PeerManager,
+        tunnel::packet_def::{CompressorAlgo, PacketType, ZCPacketType},
+    };
+
+    const PAYLOAD_LEN: usize = 1400;
+
+    fn nic_packet(payload: &[u8]) -> ZCPacket {
+        let mut packet = ZCPacket::new_with_payload(payload);
+        packet.fill_peer_manager_hdr(1, 2, PacketType::Data as u8);
+        packet
+    }
+
+    #[tokio::test]
+    async fn encrypt_in_place() {
+        let payload = vec![0x5a; PAYLOAD_LEN];
+        for algo in EncryptionAlgorithm::VARIANTS {
+            let encryptor = create_encryptor(&algo.to_string(), [1; 16], [2; 32]);
+            let mut packet = nic_packet(&payload);
+            let buf = packet.payload().as_ptr();
+
+            PeerManager::try_compress_and_encrypt(
+                CompressorAlgo::None,
+                &encryptor,
+                &mut packet,
+                false,
+            )
+            .await
+            .unwrap();
+            assert_eq!(packet.payload().as_ptr(), buf, "{} reallocated", algo);
+
+            encryptor.decrypt(&mut packet).unwrap();
+            assert_eq!(packet.payload(), &payload[..]);
+        }
+    }
+
+    #[cfg(any(feature = "aes-gcm", feature = "wireguard", feature = "openssl-crypto"))]
+    #[tokio::test]
+    async fn network_secret_encryptor_opens_staged_secret() {
+        use crate::common::{
+            config::{NetworkIdentity, SecretRotationConfig},
+            global_ctx::tests::get_mock_global_ctx_with_network,
+        };
+
+        let algo = EncryptionAlgorithm::AesGcm.to_string();
+        let old_ctx = get_mock_global_ctx_with_network(Some(NetworkIdentity::new(
+            "net".to_string(),
+            "old".to_string(),
+        )));
+        let new_ctx = get_mock_global_ctx_with_network(Some(NetworkIdentity::new(
+            "net".to_string(),
+            "new".to_string(),
+        )));
+        let sender = NetworkSecretEncryptor::new(&algo, new_ctx);
+        let receiver = NetworkSecretEncryptor::new(&algo, old_ctx.clone());
+
+        let payload = vec![0x5a; PAYLOAD_LEN];
+        let mut packet = nic_packet(&payload);
+        sender.encrypt(&mut packet).unwrap();
+        assert!(receiver.decrypt(&mut packet.clone()).is_err());
+
+        old_ctx.set_secret_rotation_config(Some(SecretRotationConfig {
+            next_network_secret: "new".to_string(),
+            activate_at: None,
+        }));
+        receiver.decrypt(&mut packet).unwrap();
+        assert_eq!(packet.payload(), &payload[..]);
+    }
+
+    #[cfg(any(feature = "aes-gcm", feature = "wireguard", feature = "openssl-crypto"))]
+    #[tokio::test]
+    async fn network_secret_encryptor_opens_both_secrets_in_place() {
+        use crate::common::{
+            config::{NetworkIdentity, SecretRotationConfig},
+            global_ctx::tests::get_mock_global_ctx_with_network,
+        };
+
+        let algo = EncryptionAlgorithm::AesGcm.to_string();
+        let ctx = |secret: &str| {
+            get_mock_global_ctx_with_network(Some(NetworkIdentity::new(
+                "net".to_string(),
+                secret.to_string(),
+            )))
+        };
+        let old_sender = NetworkSecretEncryptor::new(&algo, ctx("old"));
+        let new_sender = NetworkSecretEncryptor::new(&algo, ctx("new"));
+        let other_sender = NetworkSecretEncryptor::new(&algo, ctx("other"));
+        let receiver_ctx = ctx("old");
+        receiver_ctx.set_secret_rotation_config(Some(SecretRotationConfig {
+            next_network_secret: "new".to_string(),
+            activate_at: None,
+        }));
+        let receiver = NetworkSecretEncryptor::new(&algo, receiver_ctx);
+
+        let payload = vec![0x5a; PAYLOAD_LEN];
+        for sender in [&new_sender, &old_sender, &new_sender] {
+            let mut packet = nic_packet(&payload);
+            sender.encrypt(&mut packet).unwrap();
+            let buf = packet.payload().as_ptr();
+            receiver.decrypt(&mut packet).unwrap();
+            assert_eq!(packet.payload(), &payload[..]);
+            assert_eq!(packet.payload().as_ptr(), buf);
+        }
+
+        // a packet no accepted secret opens is left as it was received
+        let mut packet = nic_packet(&payload);
+        other_sender.encrypt(&mut packet).unwrap();
+        let sealed = packet.payload().to_vec();
+        assert!(receiver.decrypt(&mut packet).is_err());
+        assert_eq!(packet.payload(), &sealed[..]);
+        assert!(packet.peer_manager_header().unwrap().is_encrypted());
+    }
+}
@@ -1,10 +1,31 @@
+use std::cell::RefCell;
+
 use crate::tunnel::packet_def::{StandardAeadTail, ZCPacket};
-use openssl::symm::{Cipher, Crypter, Mode};
+use openssl::cipher::{Cipher, CipherRef};
+use openssl::cipher_ctx::CipherCtx;
+use openssl::error::ErrorStack;
 use rand::RngCore;
 use zerocopy::{AsBytes, FromBytes, FromZeroes};
 
 use crate::peers::encrypt::{Encryptor, Error};
 
+thread_local! {
+    // reused by all packets of a thread, so no context is allocated per packet
+    static CIPHER_CTX: RefCell<Option<CipherCtx>> = const { RefCell::new(None) };
+}
+
+fn with_cipher_ctx<R>(
+    f: impl FnOnce(&mut CipherCtx) -> Result<R, ErrorStack>,
+) -> Result<R, ErrorStack> {
+    CIPHER_CTX.with_borrow_mut(|ctx| {
+        let ctx = match ctx {
+            Some(ctx) => ctx,
+            None => ctx.insert(CipherCtx::new()?),
+        };
+        f(ctx)
+    })
+}
+
 #[derive(Clone)]
 pub struct OpenSslCipher {
     pub(crate) cipher: OpenSslEnum,
@@ -36,7 +57,7 @@ impl OpenSslCipher {
         }
     }
 
-    fn get_cipher_and_key(&self) -> (Cipher, &[u8]) {
+    fn get_cipher_and_key(&self) -> (&'static CipherRef, &[u8]) {
         match &self.cipher {
             OpenSslEnum::Aes128Gcm(key) => (Cipher::aes_128_gcm(), key.as_slice()),
             OpenSslEnum::Aes256Gcm(key) => (Cipher::aes_256_gcm(), key.as_slice()),
@@ -52,8 +73,7 @@ impl Encryptor for OpenSslCipher {
             return Ok(());
         }
 
-        let payload = zc_packet.payload();
-        let len = payload.len();
+        let len = zc_packet.payload().len();
         if len < StandardAeadTail::SIZE {
             return Err(Error::PacketTooShort(len));
         }
@@ -61,31 +81,25 @@ impl Encryptor for OpenSslCipher {
         let (cipher, key) = self.get_cipher_and_key();
 
         // 提取 nonce/IV 和 tag
-        let tail = StandardAeadTail::ref_from_suffix(payload).unwrap();
-
-        let mut decrypter = Crypter::new(cipher, Mode::Decrypt, key, Some(&tail.nonce))
-            .map_err(|_| Error::DecryptionFailed)?;
-
-        decrypter
-            .set_tag(&tail.tag)
-            .map_err(|_| Error::DecryptionFailed)?;
+        let tail = StandardAeadTail::ref_from_suffix(zc_packet.payload())
+            .unwrap()
+            .clone();
 
+        // aead ciphers are stream ciphers, decrypt in place
         let text_len = len - StandardAeadTail::SIZE;
-        let mut output = vec![0u8; text_len + cipher.block_size()];
-        let mut count = decrypter
-            .update(&payload[..text_len], &mut output)
-            .map_err(|_| Error::DecryptionFailed)?;
-
-        count += decrypter
-            .finalize(&mut output[count..])
-            .map_err(|_| Error::DecryptionFailed)?;
+        let text = &mut zc_packet.mut_payload()[..text_len];
+        with_cipher_ctx(|ctx| {
+            ctx.decrypt_init(Some(cipher), Some(key), Some(&tail.nonce))?;
+            ctx.set_tag(&tail.tag)?;
+            ctx.cipher_update_inplace(text, text_len)?;
+            ctx.cipher_final(&mut [0u8; 16])
+        })
+        .map_err(|_| Error::DecryptionFailed)?;
 
-        // 更新数据包
-        zc_packet.mut_payload()[..count].copy_from_slice(&output[..count]);
         let pm_header = zc_packet.mut_peer_manager_header().unwrap();
         pm_header.set_encrypted(false);
 
-        let len = zc_packet.buf_len() - (len - count);
+        let len = zc_packet.buf_len() - StandardAeadTail::SIZE;
         zc_packet.mut_inner().truncate(len);
 
         Ok(())
@@ -118,26 +132,15 @@ impl Encryptor for OpenSslCipher {
             rand::thread_rng().fill_bytes(&mut tail.nonce);
         }
 
-        let mut encrypter = Crypter::new(cipher, Mode::Encrypt, key, Some(&tail.nonce))
-            .map_err(|_| Error::EncryptionFailed)?;
-
-        let payload_len = zc_packet.payload().len();
-        let mut output = vec![0u8; payload_len + cipher.block_size()];
-
-        let mut count = encrypter
-            .update(zc_packet.payload(), &mut output)
-            .map_err(|_| Error::EncryptionFailed)?;
-
-        count += encrypter
-            .finalize(&mut output[count..])
-            .map_err(|_| Error::EncryptionFailed)?;
-
-        // 更新数据包内容
-        zc_packet.mut_payload()[..count].copy_from_slice(&output[..count]);
-
-        encrypter
-            .get_tag(&mut tail.tag)
-            .map_err(|_| Error::EncryptionFailed)?;
+        let payload = zc_packet.mut_payload();
+        let payload_len = payload.len();
+        with_cipher_ctx(|ctx| {
+            ctx.encrypt_init(Some(cipher), Some(key), Some(&tail.nonce))?;
+            ctx.cipher_update_inplace(payload, payload_len)?;
+            ctx.cipher_final(&mut [0u8; 16])?;
+            ctx.tag(&mut tail.tag)
+        })
+        .map_err(|_| Error::EncryptionFailed)?;
 
         // 添加 nonce/IV & tag 的结构
         zc_packet.mut_inner().extend_from_slice(tail.as_bytes());
@@ -0,0 +1,328 @@
+// IPFIX (rfc 7011) and NetFlow v9 (rfc 3954) encoding of flow records.
+//
+// the local peer id is the observation domain / source id. the remote peer id
+// is exported as ingressInterface of rx flows and egressInterface of tx flows,
+// so collectors can group traffic by peer without enterprise elements.
+
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use crate::common::{PeerId, config::FlowExportFormat};
+
+use super::{FlowDirection, FlowEndReason, FlowRecord, unix_ms};
+
+const TEMPLATE_ID_V4: u16 = 256;
+const TEMPLATE_ID_V6: u16 = 257;
+// keep messages well below the path mtu
+const MAX_RECORDS_PER_MESSAGE: usize = 16;
+// templates are resent periodically because udp export may lose them
+const TEMPLATE_REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+const IE_OCTET_DELTA_COUNT: u16 = 1;
+const IE_PACKET_DELTA_COUNT: u16 = 2;
+const IE_PROTOCOL: u16 = 4;
+const IE_TCP_FLAGS: u16 = 6;
+const IE_SRC_PORT: u16 = 7;
+const IE_SRC_IPV4: u16 = 8;
+const IE_INGRESS_INTERFACE: u16 = 10;
+const IE_DST_PORT: u16 = 11;
+const IE_DST_IPV4: u16 = 12;
+const IE_EGRESS_INTERFACE: u16 = 14;
+const IE_LAST_SWITCHED: u16 = 21;
+const IE_FIRST_SWITCHED: u16 = 22;
+const IE_SRC_IPV6: u16 = 27;
+const IE_DST_IPV6: u16 = 28;
+const IE_SAMPLING_INTERVAL: u16 = 34;
+const IE_FLOW_DIRECTION: u16 = 61;
+const IE_FLOW_END_REASON: u16 = 136;
+const IE_FLOW_START_MS: u16 = 152;
+const IE_FLOW_END_MS: u16 = 153;
+
+fn template_fields(format: FlowExportFormat, ipv6: bool) -> Vec<(u16, u16)> {
+    let (src, dst, addr_len) = if ipv6 {
+        (IE_SRC_IPV6, IE_DST_IPV6, 16)
+    } else {
+        (IE_SRC_IPV4, IE_DST_IPV4, 4)
+    };
+    let mut fields = vec![
+        (src, addr_len),
+        (dst, addr_len),
+        (IE_SRC_PORT, 2),
+        (IE_DST_PORT, 2),
+        (IE_PROTOCOL, 1),
+        (IE_TCP_FLAGS, 1),
+        (IE_OCTET_DELTA_COUNT, 8),
+        (IE_PACKET_DELTA_COUNT, 8),
+        (IE_FLOW_DIRECTION, 1),
+        (IE_INGRESS_INTERFACE, 4),
+        (IE_EGRESS_INTERFACE, 4),
+        (IE_SAMPLING_INTERVAL, 4),
+    ];
+    match format {
+        FlowExportFormat::Ipfix => fields.extend([
+            (IE_FLOW_START_MS, 8),
+            (IE_FLOW_END_MS, 8),
+            (IE_FLOW_END_REASON, 1),
+        ]),
+        // v9 timestamps are relative to the system uptime of the exporter
+        FlowExportFormat::Netflow9 => {
+            fields.extend([(IE_FIRST_SWITCHED, 4), (IE_LAST_SWITCHED, 4)])
+        }
+    }
+    fields
+}
+
+fn push_ip(buf: &mut Vec<u8>, ip: IpAddr) {
+    match ip {
+        IpAddr::V4(ip) => buf.extend_from_slice(&ip.octets()),
+        IpAddr::V6(ip) => buf.extend_from_slice(&ip.octets()),
+    }
+}
+
+fn push_set(buf: &mut Vec<u8>, set_id: u16, mut content: Vec<u8>) {
+    while !content.len().is_multiple_of(4) {
+        content.push(0);
+    }
+    buf.extend_from_slice(&set_id.to_be_bytes());
+    buf.extend_from_slice(&((content.len() + 4) as u16).to_be_bytes());
+    buf.extend_from_slice(&content);
+}
+
+pub struct FlowExportEncoder {
+    format: FlowExportFormat,
+    domain_id: u32,
+    // v9 counts export packets, ipfix counts data records
+    sequence: u32,
+    start_ms: u64,
+    last_template: Option<Instant>,
+}
+
+impl FlowExportEncoder {
+    pub fn new(format: FlowExportFormat, domain_id: PeerId) -> Self {
+        Self {
+            format,
+            domain_id,
+            sequence: 0,
+            start_ms: unix_ms(),
+            last_template: None,
+        }
+    }
+
+    fn uptime_ms(&self, ts_ms: u64) -> u32 {
+        ts_ms.saturating_sub(self.start_ms) as u32
+    }
+
+    fn encode_record(&self, buf: &mut Vec<u8>, record: &FlowRecord) {
+        push_ip(buf, record.src_ip);
+        push_ip(buf, record.dst_ip);
+        buf.extend_from_slice(&record.src_port.to_be_bytes());
+        buf.extend_from_slice(&record.dst_port.to_be_bytes());
+        buf.push(record.protocol);
+        buf.push(record.tcp_flags);
+        buf.extend_from_slice(&record.bytes.to_be_bytes());
+        buf.extend_from_slice(&record.packets.to_be_bytes());
+        let (direction, ingress, egress) = match record.direction {
+            FlowDirection::Rx => (0u8, record.peer_id, 0),
+            FlowDirection::Tx => (1u8, 0, record.peer_id),
+        };
+        buf.push(direction);
+        buf.extend_from_slice(&ingress.to_be_bytes());
+        buf.extend_from_slice(&egress.to_be_bytes());
+        buf.extend_from_slice(&record.sampling_interval.to_be_bytes());
+
+        match self.format {
+            FlowExportFormat::Ipfix => {
+                buf.extend_from_slice(&record.start_ms.to_be_bytes());
+                buf.extend_from_slice(&record.end_ms.to_be_bytes());
+                buf.push(match record.end_reason {
+                    FlowEndReason::IdleTimeout => 1,
+                    FlowEndReason::ActiveTimeout => 2,
+                    FlowEndReason::EndOfFlow => 3,
+                    FlowEndReason::Forced => 4,
+                });
+            }
+            FlowExportFormat::Netflow9 => {
+                buf.extend_from_slice(&self.uptime_ms(record.start_ms).to_be_bytes());
+                buf.extend_from_slice(&self.uptime_ms(record.end_ms).to_be_bytes());
+            }
+        }
+    }
+
+    fn encode_message(&mut self, template_id: u16, records: &[&FlowRecord]) -> Vec<u8> {
+        let now_ms = unix_ms();
+        let mut body = Vec::new();
+        let mut record_count = 0u16;
+
+        let send_templates = self
+            .last_template
+            .map(|t| t.elapsed() >= TEMPLATE_REFRESH_INTERVAL)
+            .unwrap_or(true);
+        if send_templates {
+            self.last_template = Some(Instant::now());
+            let mut set = Vec::new();
+            for (id, ipv6) in [(TEMPLATE_ID_V4, false), (TEMPLATE_ID_V6, true)] {
+                let fields = template_fields(self.format, ipv6);
+                set.extend_from_slice(&id.to_be_bytes());
+                set.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+                for (ie, len) in fields {
+                    set.extend_from_slice(&ie.to_be_bytes());
+                    set.extend_from_slice(&len.to_be_bytes());
+                }
+                record_count += 1;
+            }
+            let set_id = match self.format {
+                FlowExportFormat::Ipfix => 2,
+                FlowExportFormat::Netflow9 => 0,
+            };
+            push_set(&mut body, set_id, set);
+        }
+
+        let mut set = Vec::new();
+        for record in records {
+            self.encode_record(&mut set, record);
+            record_count += 1;
+        }
+        push_set(&mut body, template_id, set);
+
+        let mut msg = Vec::with_capacity(body.len() + 20);
+        match self.format {
+            FlowExportFormat::Netflow9 => {
+                msg.extend_from_slice(&9u16.to_be_bytes());
+                msg.extend_from_slice(&record_count.to_be_bytes());
+                msg.extend_from_slice(&self.uptime_ms(now_ms).to_be_bytes());
+                msg.extend_from_slice(&((now_ms / 1000) as u32).to_be_bytes());
+                msg.extend_from_slice(&self.sequence.to_be_bytes());
+                self.sequence = self.sequence.wrapping_add(1);
+            }
+            FlowExportFormat::Ipfix => {
+                msg.extend_from_slice(&10u16.to_be_bytes());
+                msg.extend_from_slice(&((body.len() + 16) as u16).to_be_bytes());
+                msg.extend_from_slice(&((now_ms / 1000) as u32).to_be_bytes());
+                msg.extend_from_slice(&self.sequence.to_be_bytes());
+                self.sequence = self.sequence.wrapping_add(records.len() as u32);
+            }
+        }
+        msg.extend_from_slice(&self.domain_id.to_be_bytes());
+        msg.extend_from_slice(&body);
+        msg
+    }
+
+    pub fn encode(&mut self, records: &[FlowRecord]) -> Vec<Vec<u8>> {
+        let (v6, v4): (Vec<&FlowRecord>, Vec<&FlowRecord>) =
+            records.iter().partition(|r| r.src_ip.is_ipv6());
+        let mut messages = vec![];
+        for (template_id, group) in [(TEMPLATE_ID_V4, v4), (TEMPLATE_ID_V6, v6)] {
+            for chunk in group.chunks(MAX_RECORDS_PER_MESSAGE) {
+                messages.push(self.encode_message(template_id, chunk));
+            }
+        }
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(src: &str, dst: &str) -> FlowRecord {
+        FlowRecord {
+            start_ms: unix_ms(),
+            end_ms: unix_ms(),
+            src_ip: src.parse().unwrap(),
+            dst_ip: dst.parse().unwrap(),
+            src_port: 40000,
+            dst_port: 443,
+            protocol: 6,
+            direction: FlowDirection::Tx,
+            packets: 3,
+            bytes: 180,
+            tcp_flags: 0x12,
+            peer_id: 7,
+            peer_hostname: None,
+            end_reason: FlowEndReason::IdleTimeout,
+            sampling_interval: 1,
+        }
+    }
+
+    // returns (set id, set content) of every set in the message
+    fn parse_sets(msg: &[u8], header_len: usize) -> Vec<(u16, Vec<u8>)> {
+        let mut sets = vec![];
+        let mut off = header_len;
+        while off < msg.len() {
+            let id = u16::from_be_bytes([msg[off], msg[off + 1]]);
+            let len = u16::from_be_bytes([msg[off + 2], msg[off + 3]]) as usize;
+            sets.push((id, msg[off + 4..off + len].to_vec()));
+            off += len;
+        }
+        assert_eq!(off, msg.len());
+        sets
+    }
+
+    fn record_len(format: FlowExportFormat, ipv6: bool) -> usize {
+        template_fields(format, ipv6)
+            .iter()
+            .map(|(_, len)| *len as usize)
+            .sum()
+    }
+
+    #[test]
+    fn test_ipfix_encode() {
+        let mut encoder = FlowExportEncoder::new(FlowExportFormat::Ipfix, 42);
+        let records = vec![
+            record("10.0.0.1", "10.0.0.2"),
+            record("fd00::1", "fd00::2"),
+            record("10.0.0.1", "10.0.0.3"),
+        ];
+        let msgs = encoder.encode(&records);
+        assert_eq!(msgs.len(), 2);
+
+        let msg = &msgs[0];
+        assert_eq!(u16::from_be_bytes([msg[0], msg[1]]), 10);
+        assert_eq!(u16::from_be_bytes([msg[2], msg[3]]) as usize, msg.len());
+        assert_eq!(u32::from_be_bytes(msg[8..12].try_into().unwrap()), 0);
+        assert_eq!(u32::from_be_bytes(msg[12..16].try_into().unwrap()), 42);
+        let sets = parse_sets(msg, 16);
+        assert_eq!(sets.len(), 2);
+        assert_eq!(sets[0].0, 2);
+        assert_eq!(sets[1].0, TEMPLATE_ID_V4);
+        let len = record_len(FlowExportFormat::Ipfix, false);
+        assert_eq!(sets[1].1.len(), (2 * len).div_ceil(4) * 4);
+        // dst port of the second record
+        assert_eq!(&sets[1].1[len + 10..len + 12], &443u16.to_be_bytes());
+
+        // templates are not repeated, sequence counts data records
+        let msg = &msgs[1];
+        assert_eq!(u32::from_be_bytes(msg[8..12].try_into().unwrap()), 2);
+        let sets = parse_sets(msg, 16);
+        assert_eq!(sets.len(), 1);
+        assert_eq!(sets[0].0, TEMPLATE_ID_V6);
+        assert_eq!(
+            &sets[0].1[..16],
+            &"fd00::1".parse::<std::net::Ipv6Addr>().unwrap().octets()
+        );
+    }
+
+    #[test]
+    fn test_netflow9_encode() {
+        let mut encoder = FlowExportEncoder::new(FlowExportFormat::Netflow9, 42);
+        let msgs = encoder.encode(&[record("10.0.0.1", "10.0.0.2")]);
+        assert_eq!(msgs.len(), 1);
+        let msg = &msgs[0];
+        assert_eq!(u16::from_be_bytes([msg[0], msg[1]]), 9);
+        // two templates and one data record
+        assert_eq!(u16::from_be_bytes([msg[2], msg[3]]), 3);
+        assert_eq!(u32::from_be_bytes(msg[16..20].try_into().unwrap()), 42);
+        let sets = parse_sets(msg, 20);
+        assert_eq!(sets[0].0, 0);
+        assert_eq!(sets[1].0, TEMPLATE_ID_V4);
+        assert_eq!(
+            sets[1].1.len(),
+            record_len(FlowExportFormat::Netflow9, false).div_ceil(4) * 4
+        );
+
+        let msgs = encoder.encode(&[record("10.0.0.1", "10.0.0.2")]);
+        assert_eq!(u32::from_be_bytes(msgs[0][12..16].try_into().unwrap()), 1);
+    }
+}
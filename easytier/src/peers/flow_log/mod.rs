@@ -0,0 +1,463 @@
+// per flow records of the overlay data path (who talked to whom), exported as
+// json lines to a rotating file and to an IPFIX / NetFlow v9 collector.
+//
+// flows are keyed by the 5-tuple of the inner ip packet, so each direction of
+// a connection is a separate flow like in netflow. only traffic of this node is
+// tracked, forwarded packets are usually encrypted and can not be parsed.
+
+pub mod export;
+
+use std::{
+    collections::HashMap,
+    io::Write as _,
+    net::{IpAddr, SocketAddr},
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use dashmap::DashMap;
+use pnet::packet::{
+    Packet as _,
+    ip::{IpNextHeaderProtocol, IpNextHeaderProtocols},
+    ipv4::Ipv4Packet,
+    ipv6::Ipv6Packet,
+    tcp::{TcpFlags, TcpPacket},
+    udp::UdpPacket,
+};
+use serde::Serialize;
+use tokio::net::UdpSocket;
+
+use crate::common::{
+    PeerId, config::FlowLogConfig, tracing_rolling_appender::RollingFileAppenderBase,
+};
+
+use self::export::FlowExportEncoder;
+
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(15);
+pub const DEFAULT_ACTIVE_TIMEOUT: Duration = Duration::from_secs(300);
+pub const DEFAULT_MAX_FLOWS: usize = 65536;
+pub const EXPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowDirection {
+    Rx,
+    Tx,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowEndReason {
+    IdleTimeout,
+    ActiveTimeout,
+    EndOfFlow,
+    Forced,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct FlowKey {
+    src_ip: IpAddr,
+    dst_ip: IpAddr,
+    src_port: u16,
+    dst_port: u16,
+    protocol: u8,
+}
+
+#[derive(Debug, Clone)]
+struct FlowEntry {
+    direction: FlowDirection,
+    peer_id: PeerId,
+    start_ms: u64,
+    last_ms: u64,
+    packets: u64,
+    bytes: u64,
+    tcp_flags: u8,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FlowRecord {
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub src_ip: IpAddr,
+    pub dst_ip: IpAddr,
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub protocol: u8,
+    pub direction: FlowDirection,
+    // counters of sampled packets, multiply by sampling_interval to estimate
+    pub packets: u64,
+    pub bytes: u64,
+    pub tcp_flags: u8,
+    pub peer_id: PeerId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub peer_hostname: Option<String>,
+    pub end_reason: FlowEndReason,
+    pub sampling_interval: u32,
+}
+
+fn unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+fn transport_info(protocol: IpNextHeaderProtocol, payload: &[u8]) -> (u16, u16, u8) {
+    match protocol {
+        IpNextHeaderProtocols::Tcp => TcpPacket::new(payload)
+            .map(|p| (p.get_source(), p.get_destination(), p.get_flags()))
+            .unwrap_or_default(),
+        IpNextHeaderProtocols::Udp => UdpPacket::new(payload)
+            .map(|p| (p.get_source(), p.get_destination(), 0))
+            .unwrap_or_default(),
+        _ => (0, 0, 0),
+    }
+}
+
+fn parse_flow_key(data: &[u8]) -> Option<(FlowKey, u8)> {
+    let (src_ip, dst_ip, protocol, (src_port, dst_port, tcp_flags)) = match data.first()? >> 4 {
+        4 => {
+            let ipv4 = Ipv4Packet::new(data)?;
+            let protocol = ipv4.get_next_level_protocol();
+            (
+                IpAddr::V4(ipv4.get_source()),
+                IpAddr::V4(ipv4.get_destination()),
+                protocol,
+                transport_info(protocol, ipv4.payload()),
+            )
+        }
+        6 => {
+            let ipv6 = Ipv6Packet::new(data)?;
+            let protocol = ipv6.get_next_header();
+            (
+                IpAddr::V6(ipv6.get_source()),
+                IpAddr::V6(ipv6.get_destination()),
+                protocol,
+                transport_info(protocol, ipv6.payload()),
+            )
+        }
+        _ => return None,
+    };
+    Some((
+        FlowKey {
+            src_ip,
+            dst_ip,
+            src_port,
+            dst_port,
+            protocol: protocol.0,
+        },
+        tcp_flags,
+    ))
+}
+
+pub struct FlowTracker {
+    flows: DashMap<FlowKey, FlowEntry>,
+    sample_rate: u32,
+    sample_counter: AtomicU32,
+    idle_timeout: Duration,
+    active_timeout: Duration,
+    max_flows: usize,
+    // new flows not tracked because the table is full
+    dropped_flows: AtomicU64,
+}
+
+impl FlowTracker {
+    pub fn new(config: &FlowLogConfig) -> Self {
+        Self {
+            flows: DashMap::new(),
+            sample_rate: config.sample_rate.unwrap_or(1).max(1),
+            sample_counter: AtomicU32::new(0),
+            idle_timeout: config
+                .idle_timeout_sec
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_IDLE_TIMEOUT),
+            active_timeout: config
+                .active_timeout_sec
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_ACTIVE_TIMEOUT),
+            max_flows: config.max_flows.unwrap_or(DEFAULT_MAX_FLOWS),
+            dropped_flows: AtomicU64::new(0),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn dropped_flows(&self) -> u64 {
+        self.dropped_flows.load(Ordering::Relaxed)
+    }
+
+    // data is the inner ip packet, peer_id the remote peer of the packet
+    pub fn record(&self, direction: FlowDirection, peer_id: PeerId, data: &[u8]) {
+        if self.sample_rate > 1
+            && !self
+                .sample_counter
+                .fetch_add(1, Ordering::Relaxed)
+                .is_multiple_of(self.sample_rate)
+        {
+            return;
+        }
+        let Some((key, tcp_flags)) = parse_flow_key(data) else {
+            return;
+        };
+        let now_ms = unix_ms();
+
+        if !self.flows.contains_key(&key) && self.flows.len() >= self.max_flows {
+            self.dropped_flows.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+
+        let mut entry = self.flows.entry(key).or_insert_with(|| FlowEntry {
+            direction,
+            peer_id,
+            start_ms: now_ms,
+            last_ms: now_ms,
+            packets: 0,
+            bytes: 0,
+            tcp_flags: 0,
+        });
+        entry.last_ms = now_ms;
+        entry.packets += 1;
+        entry.bytes += data.len() as u64;
+        entry.tcp_flags |= tcp_flags;
+    }
+
+    // remove finished flows and return their records, force flushes all flows
+    pub fn collect(&self, force: bool) -> Vec<FlowRecord> {
+        let now_ms = unix_ms();
+        let idle_ms = self.idle_timeout.as_millis() as u64;
+        let active_ms = self.active_timeout.as_millis() as u64;
+        let mut records = vec![];
+
+        self.flows.retain(|key, entry| {
+            let end_reason = if force {
+                FlowEndReason::Forced
+            } else if entry.tcp_flags & (TcpFlags::FIN | TcpFlags::RST) != 0 {
+                FlowEndReason::EndOfFlow
+            } else if now_ms.saturating_sub(entry.last_ms) >= idle_ms {
+                FlowEndReason::IdleTimeout
+            } else if now_ms.saturating_sub(entry.start_ms) >= active_ms {
+                FlowEndReason::ActiveTimeout
+            } else {
+                return true;
+            };
+
+            records.push(FlowRecord {
+                start_ms: entry.start_ms,
+                end_ms: entry.last_ms,
+                src_ip: key.src_ip,
+                dst_ip: key.dst_ip,
+                src_port: key.src_port,
+                dst_port: key.dst_port,
+                protocol: key.protocol,
+                direction: entry.direction,
+                packets: entry.packets,
+                bytes: entry.bytes,
+                tcp_flags: entry.tcp_flags,
+                peer_id: entry.peer_id,
+                peer_hostname: None,
+                end_reason,
+                sampling_interval: self.sample_rate,
+            });
+            false
+        });
+
+        records
+    }
+}
+
+// writes completed flow records to the configured destinations
+pub struct FlowLogger {
+    file: Option<RollingFileAppenderBase>,
+    collector: Option<(UdpSocket, SocketAddr)>,
+    encoder: FlowExportEncoder,
+}
+
+impl FlowLogger {
+    pub async fn new(config: &FlowLogConfig, my_peer_id: PeerId) -> anyhow::Result<Self> {
+        let file = match &config.file {
+            Some(path) => Some(
+                RollingFileAppenderBase::builder()
+                    .filename(path.clone())
+                    .max_filecount(config.count.unwrap_or(10))
+                    .condition_max_file_size(config.size_mb.unwrap_or(100) * 1024 * 1024)
+                    .build()
+                    .with_context(|| format!("failed to open flow log file {}", path))?,
+            ),
+            None => None,
+        };
+
+        let collector = match &config.collector {
+            Some(addr) => {
+                let addr = addr.trim_start_matches("udp://");
+                let addr = tokio::net::lookup_host(addr)
+                    .await
+                    .with_context(|| format!("failed to resolve flow collector {}", addr))?
+                    .next()
+                    .with_context(|| format!("failed to resolve flow collector {}", addr))?;
+                let bind_addr: SocketAddr = if addr.is_ipv4() {
+                    "0.0.0.0:0".parse().unwrap()
+                } else {
+                    "[::]:0".parse().unwrap()
+                };
+                Some((UdpSocket::bind(bind_addr).await?, addr))
+            }
+            None => None,
+        };
+
+        Ok(Self {
+            file,
+            collector,
+            encoder: FlowExportEncoder::new(config.format.unwrap_or_default(), my_peer_id),
+        })
+    }
+
+    pub async fn export(&mut self, records: &[FlowRecord]) {
+        if records.is_empty() {
+            return;
+        }
+
+        if let Some(file) = self.file.as_mut() {
+            for record in records {
+                let Ok(mut line) = serde_json::to_vec(record) else {
+                    continue;
+                };
+                line.push(b'\n');
+                if let Err(e) = file.write_all(&line) {
+                    tracing::warn!(?e, "write flow log failed");
+                    break;
+                }
+            }
+            let _ = file.flush();
+        }
+
+        if let Some((socket, addr)) = &self.collector {
+            for msg in self.encoder.encode(records) {
+                if let Err(e) = socket.send_to(&msg, addr).await {
+                    tracing::debug!(?e, ?addr, "send flow records to collector failed");
+                }
+            }
+        }
+    }
+}
+
+pub(crate) fn fill_hostnames(records: &mut [FlowRecord], hostnames: &HashMap<PeerId, String>) {
+    for record in records.iter_mut() {
+        record.peer_hostname = hostnames.get(&record.peer_id).cloned();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn tcp_packet(
+        src: [u8; 4],
+        dst: [u8; 4],
+        sport: u16,
+        dport: u16,
+        flags: u8,
+    ) -> Vec<u8> {
+        let mut buf = vec![0u8; 40];
+        buf[0] = 0x45;
+        buf[2..4].copy_from_slice(&40u16.to_be_bytes());
+        buf[8] = 64;
+        buf[9] = IpNextHeaderProtocols::Tcp.0;
+        buf[12..16].copy_from_slice(&src);
+        buf[16..20].copy_from_slice(&dst);
+        buf[20..22].copy_from_slice(&sport.to_be_bytes());
+        buf[22..24].copy_from_slice(&dport.to_be_bytes());
+        buf[32] = 5 << 4;
+        buf[33] = flags;
+        buf
+    }
+
+    #[test]
+    fn test_flow_tracker() {
+        let tracker = FlowTracker::new(&FlowLogConfig {
+            idle_timeout_sec: Some(0),
+            max_flows: Some(2),
+            ..Default::default()
+        });
+
+        let syn = tcp_packet([10, 0, 0, 1], [10, 0, 0, 2], 40000, 22, TcpFlags::SYN);
+        let ack = tcp_packet([10, 0, 0, 1], [10, 0, 0, 2], 40000, 22, TcpFlags::ACK);
+        tracker.record(FlowDirection::Tx, 2, &syn);
+        tracker.record(FlowDirection::Tx, 2, &ack);
+        let reply = tcp_packet([10, 0, 0, 2], [10, 0, 0, 1], 22, 40000, TcpFlags::ACK);
+        tracker.record(FlowDirection::Rx, 2, &reply);
+        // table is full
+        let other = tcp_packet([10, 0, 0, 1], [10, 0, 0, 3], 40001, 80, TcpFlags::SYN);
+        tracker.record(FlowDirection::Tx, 3, &other);
+        assert_eq!(tracker.dropped_flows(), 1);
+
+        let mut records = tracker.collect(false);
+        records.sort_by_key(|r| r.src_port);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].direction, FlowDirection::Rx);
+        assert_eq!(records[1].direction, FlowDirection::Tx);
+        assert_eq!(records[1].packets, 2);
+        assert_eq!(records[1].bytes, 80);
+        assert_eq!(records[1].tcp_flags, TcpFlags::SYN | TcpFlags::ACK);
+        assert_eq!(records[1].end_reason, FlowEndReason::IdleTimeout);
+        assert!(tracker.collect(false).is_empty());
+    }
+
+    #[test]
+    fn test_flow_tracker_end_of_flow_and_sampling() {
+        let tracker = FlowTracker::new(&FlowLogConfig {
+            sample_rate: Some(2),
+            ..Default::default()
+        });
+        let fin = tcp_packet([10, 0, 0, 1], [10, 0, 0, 2], 40000, 22, TcpFlags::FIN);
+        for _ in 0..4 {
+            tracker.record(FlowDirection::Tx, 2, &fin);
+        }
+        let records = tracker.collect(false);
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].packets, 2);
+        assert_eq!(records[0].sampling_interval, 2);
+        assert_eq!(records[0].end_reason, FlowEndReason::EndOfFlow);
+    }
+
+    #[tokio::test]
+    async fn test_flow_logger_file_and_collector() {
+        let collector = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("flows.json");
+        let config = FlowLogConfig {
+            file: Some(path.to_string_lossy().into_owned()),
+            collector: Some(collector.local_addr().unwrap().to_string()),
+            ..Default::default()
+        };
+
+        let tracker = FlowTracker::new(&config);
+        let syn = tcp_packet([10, 0, 0, 1], [10, 0, 0, 2], 40000, 22, TcpFlags::SYN);
+        tracker.record(FlowDirection::Tx, 2, &syn);
+        let mut records = tracker.collect(true);
+        fill_hostnames(&mut records, &HashMap::from([(2, "node-b".to_string())]));
+
+        let mut logger = FlowLogger::new(&config, 1).await.unwrap();
+        logger.export(&records).await;
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(content.lines().next().unwrap()).unwrap();
+        assert_eq!(json["peer_hostname"], "node-b");
+        assert_eq!(json["dst_port"], 22);
+        assert_eq!(json["direction"], "tx");
+        assert_eq!(json["end_reason"], "forced");
+
+        let mut buf = [0u8; 2048];
+        let (len, _) = tokio::time::timeout(Duration::from_secs(5), collector.recv_from(&mut buf))
+            .await
+            .unwrap()
+            .unwrap();
+        // ipfix message header: version 10, total length, observation domain
+        assert_eq!(u16::from_be_bytes([buf[0], buf[1]]), 10);
+        assert_eq!(u16::from_be_bytes([buf[2], buf[3]]) as usize, len);
+        assert_eq!(u32::from_be_bytes(buf[12..16].try_into().unwrap()), 1);
+    }
+}
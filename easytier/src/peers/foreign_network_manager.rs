@@ -57,6 +57,7 @@ use super::{
     peer_session::PeerSessionStore,
     recv_packet_from_chan,
     relay_peer_map::RelayPeerMap,
+    relay_quota::{NetworkQuota, NetworkRelayUsage, RelayQuotaManager},
     route_trait::NextHopPolicy,
     traffic_metrics::{
         InstanceLabelKind, LogicalTrafficMetrics, TrafficKind, TrafficMetricRecorder,
@@ -92,6 +93,9 @@ struct ForeignNetworkEntry {
     packet_recv: Mutex<Option<PacketRecvChanReceiver>>,
 
     bps_limiter: Option<Arc<TokenBucket>>,
+    relay_usage: Arc<NetworkRelayUsage>,
+    // relay data of a network over its monthly budget goes through this one
+    over_budget_limiter: Option<Arc<TokenBucket>>,
 
     peer_center: Arc<PeerCenterInstance>,
 
@@ -113,6 +117,7 @@ impl ForeignNetworkEntry {
         relay_data: bool,
         peer_session_store: Arc<PeerSessionStore>,
         pm_packet_sender: PacketRecvChan,
+        relay_usage: Arc<NetworkRelayUsage>,
     ) -> Self {
         let stats_mgr = global_ctx.stats_manager().clone();
         let foreign_global_ctx =
@@ -196,17 +201,31 @@ impl ForeignNetworkEntry {
             &network.network_name,
         );
 
-        let relay_bps_limit = global_ctx.config.get_flags().foreign_relay_bps_limit;
-        let bps_limiter = (relay_bps_limit != u64::MAX).then(|| {
+        let bps_limiter = Self::relay_bps_limit(&global_ctx, relay_usage.quota()).map(|bps| {
             let limiter_config = LimiterConfig {
                 burst_rate: None,
-                bps: Some(relay_bps_limit),
+                bps: Some(bps),
                 fill_duration_ms: None,
             };
             global_ctx
                 .token_bucket_manager()
                 .get_or_create(&network.network_name, limiter_config.into())
         });
+        let over_budget_limiter = relay_usage
+            .quota()
+            .monthly_bytes
+            .and(relay_usage.quota().over_budget_bps)
+            .map(|bps| {
+                let limiter_config = LimiterConfig {
+                    burst_rate: None,
+                    bps: Some(bps),
+                    fill_duration_ms: None,
+                };
+                global_ctx.token_bucket_manager().get_or_create(
+                    &format!("{}:over_budget", network.network_name),
+                    limiter_config.into(),
+                )
+            });
 
         let peer_center = Arc::new(PeerCenterInstance::new(Arc::new(
             PeerMapWithPeerRpcManager {
@@ -233,6 +252,8 @@ impl ForeignNetworkEntry {
             packet_recv: Mutex::new(Some(packet_recv)),
 
             bps_limiter,
+            relay_usage,
+            over_budget_limiter,
 
             stats_mgr,
             traffic_metrics,
@@ -246,6 +267,13 @@ impl ForeignNetworkEntry {
         }
     }
 
+    // the global foreign_relay_bps_limit caps the per network quota
+    fn relay_bps_limit(global_ctx: &ArcGlobalCtx, quota: &NetworkQuota) -> Option<u64> {
+        let global_limit = global_ctx.config.get_flags().foreign_relay_bps_limit;
+        let limit = quota.bps_limit.unwrap_or(u64::MAX).min(global_limit);
+        (limit != u64::MAX).then_some(limit)
+    }
+
     fn desired_avoid_relay_data_feature_flag(
         parent_global_ctx: &ArcGlobalCtx,
         relay_data: bool,
@@ -456,6 +484,8 @@ impl ForeignNetworkEntry {
         let pm_sender = self.pm_packet_sender.lock().await.take().unwrap();
         let network_name = self.network.network_name.clone();
         let bps_limiter = self.bps_limiter.clone();
+        let relay_usage = self.relay_usage.clone();
+        let over_budget_limiter = self.over_budget_limiter.clone();
 
         let label_set =
             LabelSet::new().with_label_type(LabelType::NetworkName(network_name.clone()));
@@ -554,6 +584,14 @@ impl ForeignNetworkEntry {
                         {
                             continue;
                         }
+                        if relay_usage.over_budget()
+                            && !over_budget_limiter
+                                .as_ref()
+                                .is_some_and(|limiter| limiter.try_consume(len.into()))
+                        {
+                            continue;
+                        }
+                        relay_usage.add_relayed_bytes(buf_len as u64);
                     }
 
                     match traffic_kind(packet_type) {
@@ -684,6 +722,7 @@ struct ForeignNetworkManagerData {
     peer_network_map: DashMap<PeerId, DashSet<String>>,
     network_peer_last_update: DashMap<String, SystemTime>,
     accessor: Arc<Box<dyn GlobalForeignNetworkAccessor>>,
+    relay_quota: Arc<RelayQuotaManager>,
     lock: std::sync::Mutex<()>,
     #[cfg(test)]
     fail_next_add_peer_conn_after_entry_insert: AtomicBool,
@@ -801,6 +840,7 @@ impl ForeignNetworkManagerData {
                     relay_data,
                     peer_session_store,
                     pm_packet_sender.clone(),
+                    self.relay_quota.get_usage(&network_identity.network_name),
                 ))
             })
             .clone();
@@ -825,6 +865,8 @@ impl ForeignNetworkManagerData {
 
 pub const FOREIGN_NETWORK_SERVICE_ID: u32 = 1;
 
+const RELAY_QUOTA_MAINTAIN_INTERVAL: std::time::Duration = std::time::Duration::from_secs(60);
+
 pub struct ForeignNetworkManager {
     my_peer_id: PeerId,
     global_ctx: ArcGlobalCtx,
@@ -911,11 +953,18 @@ impl ForeignNetworkManager {
         packet_sender_to_mgr: PacketRecvChan,
         accessor: Box<dyn GlobalForeignNetworkAccessor>,
     ) -> Self {
+        let relay_quota = Arc::new(RelayQuotaManager::new(
+            global_ctx
+                .config
+                .get_relay_quota_config()
+                .unwrap_or_default(),
+        ));
         let data = Arc::new(ForeignNetworkManagerData {
             network_peer_maps: DashMap::new(),
             peer_network_map: DashMap::new(),
             network_peer_last_update: DashMap::new(),
             accessor: Arc::new(accessor),
+            relay_quota: relay_quota.clone(),
             lock: std::sync::Mutex::new(()),
             #[cfg(test)]
             fail_next_add_peer_conn_after_entry_insert: AtomicBool::new(false),
@@ -924,6 +973,20 @@ impl ForeignNetworkManager {
         let tasks = Arc::new(std::sync::Mutex::new(JoinSet::new()));
         join_joinset_background(tasks.clone(), "ForeignNetworkManager".to_string());
 
+        if relay_quota.is_enabled() {
+            tasks.lock().unwrap().spawn(async move {
+                loop {
+                    tokio::time::sleep(RELAY_QUOTA_MAINTAIN_INTERVAL).await;
+                    if relay_quota.maintain() {
+                        tracing::info!("new month started, relay quota usage reset");
+                    }
+                    if let Err(e) = relay_quota.save() {
+                        tracing::warn!(?e, "failed to save relay quota usage");
+                    }
+                }
+            });
+        }
+
         Self {
             my_peer_id,
             global_ctx,
@@ -950,6 +1013,19 @@ impl ForeignNetworkManager {
             .map(|v| v.my_peer_id)
     }
 
+    /// Check the relay quota before accepting a connection of `network_name`,
+    /// called during handshake so the peer is rejected with a clear reason.
+    pub fn check_relay_quota(&self, network_name: &str) -> Result<(), Error> {
+        self.data
+            .relay_quota
+            .check_network_admission(
+                network_name,
+                self.data.network_peer_maps.contains_key(network_name),
+                self.data.network_peer_maps.len(),
+            )
+            .map_err(Into::into)
+    }
+
     pub async fn add_peer_conn(&self, peer_conn: PeerConn) -> Result<(), Error> {
         let conn_info = peer_conn.get_conn_info();
         let peer_network = peer_conn.get_network_identity();
@@ -978,6 +1054,11 @@ impl ForeignNetworkManager {
             .into());
         }
 
+        if let Err(e) = self.check_relay_quota(&peer_network.network_name) {
+            tracing::warn!(?e, "reject foreign peer conn");
+            return Err(e);
+        }
+
         let (entry, new_added) = self
             .data
             .get_or_insert_entry(
@@ -1057,6 +1138,16 @@ impl ForeignNetworkManager {
             return Err(err.into());
         }
 
+        if !entry.peer_map.has_peer(peer_conn.get_peer_id())
+            && let Err(e) = self.data.relay_quota.check_peer_admission(
+                &entry.network.network_name,
+                entry.peer_map.list_peers().len(),
+            )
+        {
+            tracing::warn!(?e, "reject foreign peer conn");
+            return Err(e.into());
+        }
+
         if !new_added && let Some(peer) = entry.peer_map.get_peer_by_id(peer_conn.get_peer_id()) {
             let direct_conns_len = peer.get_directly_connections().len();
             let max_count = use_global_var!(MAX_DIRECT_CONNS_PER_PEER_IN_FOREIGN_NETWORK);
@@ -1157,6 +1248,10 @@ impl ForeignNetworkManager {
                 } else {
                     Default::default()
                 },
+                relay_quota: Some(item.relay_usage.to_pb(ForeignNetworkEntry::relay_bps_limit(
+                    &item.parent_global_ctx,
+                    item.relay_usage.quota(),
+                ))),
             };
             for peer in item.peer_map.list_peers() {
                 let peer_info = PeerInfo {
@@ -1227,6 +1322,9 @@ impl ForeignNetworkManager {
 
 impl Drop for ForeignNetworkManager {
     fn drop(&mut self) {
+        if let Err(e) = self.data.relay_quota.save() {
+            tracing::warn!(?e, "failed to save relay quota usage");
+        }
         self.data.peer_network_map.clear();
         self.data.network_peer_maps.clear();
     }
@@ -1945,6 +2043,7 @@ pub mod tests {
             false,
             Arc::new(PeerSessionStore::new()),
             pm_packet_sender,
+            Default::default(),
         );
         let pubkey = vec![7; 32];
 
@@ -1992,6 +2091,7 @@ pub mod tests {
             true,
             Arc::new(PeerSessionStore::new()),
             pm_packet_sender,
+            Default::default(),
         );
         assert!(!entry.global_ctx.get_feature_flags().avoid_relay_data);
 
@@ -2035,6 +2135,7 @@ pub mod tests {
             false,
             Arc::new(PeerSessionStore::new()),
             pm_packet_sender,
+            Default::default(),
         );
 
         assert!(entry.global_ctx.get_feature_flags().avoid_relay_data);
@@ -2142,6 +2243,67 @@ pub mod tests {
         assert_eq!(2, pmb_net1.list_routes().await.len());
     }
 
+    #[tokio::test]
+    async fn relay_quota_rejects_networks_over_limit() {
+        let (s, _r) = create_packet_recv_chan();
+        let global_ctx = get_mock_global_ctx_with_network(None);
+        global_ctx
+            .config
+            .set_relay_quota_config(Some(crate::common::config::RelayQuotaConfig {
+                max_networks: Some(1),
+                max_peers_per_network: Some(1),
+                network_monthly_bytes: Some(1 << 30),
+                ..Default::default()
+            }));
+        let pm_center = Arc::new(PeerManager::new(RouteAlgoType::Ospf, global_ctx, s));
+        replace_stun_info_collector(pm_center.clone(), NatType::Unknown);
+        pm_center.run().await.unwrap();
+
+        let pma_net1 = create_mock_peer_manager_for_foreign_network("net1").await;
+        connect_peer_manager(pma_net1.clone(), pm_center.clone()).await;
+        wait_for_condition(
+            || async { pma_net1.list_routes().await.len() == 1 },
+            Duration::from_secs(5),
+        )
+        .await;
+
+        let try_connect = |pm: Arc<PeerManager>| {
+            let pm_center = pm_center.clone();
+            async move {
+                let (a_ring, b_ring) = crate::tunnel::ring::create_ring_tunnel_pair();
+                let s_ret =
+                    tokio::spawn(async move { pm_center.add_tunnel_as_server(b_ring, true).await });
+                let c_ret = pm.add_client_tunnel(a_ring, false).await;
+                (s_ret.await.unwrap(), c_ret)
+            }
+        };
+
+        let pma_net2 = create_mock_peer_manager_for_foreign_network("net2").await;
+        let (s_ret, c_ret) = try_connect(pma_net2).await;
+        let err = s_ret.unwrap_err();
+        assert!(err.to_string().contains("relay quota exceeded"), "{}", err);
+        let err = c_ret.unwrap_err();
+        assert!(err.to_string().contains("handshake rejected"), "{}", err);
+        assert!(err.to_string().contains("relay quota exceeded"), "{}", err);
+
+        let pmb_net1 = create_mock_peer_manager_for_foreign_network("net1").await;
+        let (s_ret, c_ret) = try_connect(pmb_net1).await;
+        let err = s_ret.unwrap_err();
+        assert!(err.to_string().contains("relay quota exceeded"), "{}", err);
+        let err = c_ret.unwrap_err();
+        assert!(err.to_string().contains("relay quota exceeded"), "{}", err);
+
+        let rpc_resp = pm_center
+            .get_foreign_network_manager()
+            .list_foreign_networks()
+            .await;
+        assert_eq!(1, rpc_resp.foreign_networks.len());
+        let quota = rpc_resp.foreign_networks["net1"].relay_quota.unwrap();
+        assert_eq!(quota.max_peers, Some(1));
+        assert_eq!(quota.monthly_bytes_limit, Some(1 << 30));
+        assert!(!quota.over_budget);
+    }
+
     #[tokio::test]
     #[should_panic]
     async fn foreign_network_whitelist_fail() {
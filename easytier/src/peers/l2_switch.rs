@@ -0,0 +1,416 @@
+// mac learning switch of tap mode.
+//
+// every node is a port of one virtual switch. frames read from the tap device
+// go to the peer owning the destination mac, or are flooded to all peers in tap
+// mode when the destination is unknown, broadcast or multicast. frames from
+// peers are only written to the tap device and never forwarded to other peers
+// (split horizon), so the overlay itself can't loop. a loop through bridged
+// physical networks shows up as a mac moving between the tap side and a peer,
+// such frames are dropped until the old entry gets stale.
+//
+// peers without tap mode only understand ip packets. arp requests for their
+// addresses are answered locally with a synthetic mac derived from the peer id,
+// frames sent to such a mac are unwrapped and routed as ip packets, and ip
+// packets delivered to this node are wrapped into ethernet frames before they
+// are written to the tap device.
+
+use std::{
+    collections::BTreeSet,
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use arc_swap::ArcSwap;
+use crossbeam::atomic::AtomicCell;
+use dashmap::DashMap;
+use pnet::{
+    packet::{
+        Packet as _,
+        arp::{ArpHardwareTypes, ArpOperations, ArpPacket, MutableArpPacket},
+        ethernet::{EtherType, EtherTypes, EthernetPacket, MutableEthernetPacket},
+        ipv4::Ipv4Packet,
+        ipv6::Ipv6Packet,
+    },
+    util::MacAddr,
+};
+
+use crate::common::PeerId;
+
+pub const ETHERNET_HEADER_LEN: usize = 14;
+const VLAN_TAG_LEN: usize = 4;
+const ARP_PACKET_LEN: usize = 28;
+
+const MAC_AGING_TIME: Duration = Duration::from_secs(300);
+// a mac seen on another port within this time is treated as a loop
+const MAC_MOVE_HOLD_TIME: Duration = Duration::from_secs(5);
+
+// locally administered 02:45 ("E"), followed by the 4 bytes of the peer id
+const SYNTHETIC_MAC_PREFIX: [u8; 2] = [0x02, 0x45];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L2Port {
+    Tap,
+    Peer(PeerId),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TapFrameAction {
+    Drop,
+    Unicast(PeerId),
+    Flood,
+    // arp request for this address, may be answered locally
+    ArpRequest(Ipv4Addr),
+    // frame to a peer without tap mode, route the ip packet inside
+    RouteIp,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct MacEntry {
+    port: L2Port,
+    last_seen: Instant,
+}
+
+pub fn synthetic_peer_mac(peer_id: PeerId) -> MacAddr {
+    let id = peer_id.to_be_bytes();
+    MacAddr::new(
+        SYNTHETIC_MAC_PREFIX[0],
+        SYNTHETIC_MAC_PREFIX[1],
+        id[0],
+        id[1],
+        id[2],
+        id[3],
+    )
+}
+
+fn peer_id_of_synthetic_mac(mac: MacAddr) -> Option<PeerId> {
+    ([mac.0, mac.1] == SYNTHETIC_MAC_PREFIX)
+        .then(|| PeerId::from_be_bytes([mac.2, mac.3, mac.4, mac.5]))
+}
+
+fn is_group_mac(mac: MacAddr) -> bool {
+    mac.0 & 0x01 != 0
+}
+
+/// Ip payload of an ethernet frame, with at most one vlan tag.
+pub fn ethernet_ip_payload(frame: &[u8]) -> Option<&[u8]> {
+    let eth = EthernetPacket::new(frame)?;
+    let (ethertype, offset) = if eth.get_ethertype() == EtherTypes::Vlan {
+        let inner = frame.get(ETHERNET_HEADER_LEN + 2..ETHERNET_HEADER_LEN + VLAN_TAG_LEN)?;
+        (
+            EtherType(u16::from_be_bytes([inner[0], inner[1]])),
+            ETHERNET_HEADER_LEN + VLAN_TAG_LEN,
+        )
+    } else {
+        (eth.get_ethertype(), ETHERNET_HEADER_LEN)
+    };
+    (ethertype == EtherTypes::Ipv4 || ethertype == EtherTypes::Ipv6).then(|| &frame[offset..])
+}
+
+pub struct L2Switch {
+    macs: DashMap<MacAddr, MacEntry>,
+    // addresses of hosts behind the tap device, destination of wrapped ip packets
+    tap_hosts: DashMap<IpAddr, MacAddr>,
+    // mac of the tap device itself
+    tap_mac: AtomicCell<Option<MacAddr>>,
+    // peers running in tap mode, refreshed from the route
+    tap_peers: ArcSwap<BTreeSet<PeerId>>,
+}
+
+impl Default for L2Switch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl L2Switch {
+    pub fn new() -> Self {
+        Self {
+            macs: DashMap::new(),
+            tap_hosts: DashMap::new(),
+            tap_mac: AtomicCell::new(None),
+            tap_peers: ArcSwap::from_pointee(BTreeSet::new()),
+        }
+    }
+
+    pub fn set_tap_mac(&self, mac: MacAddr) {
+        self.tap_mac.store(Some(mac));
+    }
+
+    pub fn set_tap_peers(&self, peers: BTreeSet<PeerId>) {
+        self.tap_peers.store(Arc::new(peers));
+    }
+
+    pub fn tap_peers(&self) -> Arc<BTreeSet<PeerId>> {
+        self.tap_peers.load_full()
+    }
+
+    pub fn is_tap_peer(&self, peer_id: PeerId) -> bool {
+        self.tap_peers.load().contains(&peer_id)
+    }
+
+    // returns false if the mac just moved from another port, which means the
+    // frame looped back.
+    fn learn(&self, mac: MacAddr, port: L2Port) -> bool {
+        if is_group_mac(mac) {
+            return false;
+        }
+        if peer_id_of_synthetic_mac(mac).is_some() {
+            return true;
+        }
+        let now = Instant::now();
+        let mut entry = self.macs.entry(mac).or_insert(MacEntry {
+            port,
+            last_seen: now,
+        });
+        if entry.port != port && now.duration_since(entry.last_seen) < MAC_MOVE_HOLD_TIME {
+            tracing::debug!(?mac, old = ?entry.port, new = ?port, "mac flapping, drop frame");
+            return false;
+        }
+        *entry = MacEntry {
+            port,
+            last_seen: now,
+        };
+        true
+    }
+
+    fn lookup(&self, mac: MacAddr) -> Option<L2Port> {
+        let entry = self.macs.get(&mac)?;
+        (entry.last_seen.elapsed() < MAC_AGING_TIME).then_some(entry.port)
+    }
+
+    fn learn_tap_host(&self, eth: &EthernetPacket) {
+        let src_ip = match eth.get_ethertype() {
+            EtherTypes::Ipv4 => Ipv4Packet::new(eth.payload()).map(|p| IpAddr::V4(p.get_source())),
+            EtherTypes::Ipv6 => Ipv6Packet::new(eth.payload()).map(|p| IpAddr::V6(p.get_source())),
+            EtherTypes::Arp => {
+                ArpPacket::new(eth.payload()).map(|p| IpAddr::V4(p.get_sender_proto_addr()))
+            }
+            _ => None,
+        };
+        if let Some(ip) = src_ip
+            && !ip.is_unspecified()
+        {
+            self.tap_hosts.insert(ip, eth.get_source());
+        }
+    }
+
+    /// Decide where a frame read from the tap device goes.
+    pub fn process_frame_from_tap(&self, frame: &[u8]) -> TapFrameAction {
+        let Some(eth) = EthernetPacket::new(frame) else {
+            return TapFrameAction::Drop;
+        };
+        if !self.learn(eth.get_source(), L2Port::Tap) {
+            return TapFrameAction::Drop;
+        }
+        self.learn_tap_host(&eth);
+
+        if eth.get_ethertype() == EtherTypes::Arp
+            && let Some(arp) = ArpPacket::new(eth.payload())
+            && arp.get_operation() == ArpOperations::Request
+        {
+            return TapFrameAction::ArpRequest(arp.get_target_proto_addr());
+        }
+        let dst = eth.get_destination();
+        if peer_id_of_synthetic_mac(dst).is_some() {
+            return if ethernet_ip_payload(frame).is_some() {
+                TapFrameAction::RouteIp
+            } else {
+                TapFrameAction::Drop
+            };
+        }
+        if is_group_mac(dst) {
+            return TapFrameAction::Flood;
+        }
+        match self.lookup(dst) {
+            Some(L2Port::Peer(peer_id)) => TapFrameAction::Unicast(peer_id),
+            // both ends on the tap side, the bridge already delivered it
+            Some(L2Port::Tap) => TapFrameAction::Drop,
+            None => TapFrameAction::Flood,
+        }
+    }
+
+    /// Learn from a frame received from a peer, returns false if it should be
+    /// dropped.
+    pub fn process_frame_from_peer(&self, from_peer_id: PeerId, frame: &[u8]) -> bool {
+        let Some(eth) = EthernetPacket::new(frame) else {
+            return false;
+        };
+        self.learn(eth.get_source(), L2Port::Peer(from_peer_id))
+    }
+
+    /// Answer an arp request from the tap side on behalf of a peer without tap
+    /// mode.
+    pub fn build_arp_reply(&self, request: &[u8], peer_id: PeerId) -> Option<Vec<u8>> {
+        let eth = EthernetPacket::new(request)?;
+        let arp = ArpPacket::new(eth.payload())?;
+        let peer_mac = synthetic_peer_mac(peer_id);
+
+        let mut buf = vec![0u8; ETHERNET_HEADER_LEN + ARP_PACKET_LEN];
+        let mut reply_eth = MutableEthernetPacket::new(&mut buf)?;
+        reply_eth.set_destination(eth.get_source());
+        reply_eth.set_source(peer_mac);
+        reply_eth.set_ethertype(EtherTypes::Arp);
+
+        let mut reply = MutableArpPacket::new(&mut buf[ETHERNET_HEADER_LEN..])?;
+        reply.set_hardware_type(ArpHardwareTypes::Ethernet);
+        reply.set_protocol_type(EtherTypes::Ipv4);
+        reply.set_hw_addr_len(6);
+        reply.set_proto_addr_len(4);
+        reply.set_operation(ArpOperations::Reply);
+        reply.set_sender_hw_addr(peer_mac);
+        reply.set_sender_proto_addr(arp.get_target_proto_addr());
+        reply.set_target_hw_addr(arp.get_sender_hw_addr());
+        reply.set_target_proto_addr(arp.get_sender_proto_addr());
+        Some(buf)
+    }
+
+    /// Wrap an ip packet from `from_peer_id` into an ethernet frame for the tap
+    /// device.
+    pub fn wrap_ip_packet(&self, from_peer_id: PeerId, ip_packet: &[u8]) -> Option<Vec<u8>> {
+        let (ethertype, dst_ip) = match ip_packet.first()? >> 4 {
+            4 => (
+                EtherTypes::Ipv4,
+                IpAddr::V4(Ipv4Packet::new(ip_packet)?.get_destination()),
+            ),
+            6 => (
+                EtherTypes::Ipv6,
+                IpAddr::V6(Ipv6Packet::new(ip_packet)?.get_destination()),
+            ),
+            _ => return None,
+        };
+        let dst_mac = self
+            .tap_hosts
+            .get(&dst_ip)
+            .map(|v| *v)
+            .or(self.tap_mac.load())
+            .unwrap_or(MacAddr::broadcast());
+
+        let mut buf = vec![0u8; ETHERNET_HEADER_LEN + ip_packet.len()];
+        let mut eth = MutableEthernetPacket::new(&mut buf)?;
+        eth.set_destination(dst_mac);
+        eth.set_source(synthetic_peer_mac(from_peer_id));
+        eth.set_ethertype(ethertype);
+        buf[ETHERNET_HEADER_LEN..].copy_from_slice(ip_packet);
+        Some(buf)
+    }
+
+    pub fn gc(&self) {
+        self.macs
+            .retain(|_, entry| entry.last_seen.elapsed() < MAC_AGING_TIME);
+        self.macs.shrink_to_fit();
+        if self.tap_hosts.len() > self.macs.len() * 4 + 64 {
+            self.tap_hosts.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(dst: MacAddr, src: MacAddr, ethertype: EtherType, payload: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0u8; ETHERNET_HEADER_LEN + payload.len()];
+        let mut eth = MutableEthernetPacket::new(&mut buf).unwrap();
+        eth.set_destination(dst);
+        eth.set_source(src);
+        eth.set_ethertype(ethertype);
+        buf[ETHERNET_HEADER_LEN..].copy_from_slice(payload);
+        buf
+    }
+
+    fn arp_request(src: MacAddr, sender_ip: Ipv4Addr, target_ip: Ipv4Addr) -> Vec<u8> {
+        let mut arp_buf = [0u8; ARP_PACKET_LEN];
+        let mut arp = MutableArpPacket::new(&mut arp_buf).unwrap();
+        arp.set_hardware_type(ArpHardwareTypes::Ethernet);
+        arp.set_protocol_type(EtherTypes::Ipv4);
+        arp.set_hw_addr_len(6);
+        arp.set_proto_addr_len(4);
+        arp.set_operation(ArpOperations::Request);
+        arp.set_sender_hw_addr(src);
+        arp.set_sender_proto_addr(sender_ip);
+        arp.set_target_proto_addr(target_ip);
+        frame(MacAddr::broadcast(), src, EtherTypes::Arp, &arp_buf)
+    }
+
+    const HOST_A: MacAddr = MacAddr(0x52, 0x54, 0, 0, 0, 1);
+    const HOST_B: MacAddr = MacAddr(0x52, 0x54, 0, 0, 0, 2);
+
+    #[test]
+    fn learns_and_floods_unknown() {
+        let switch = L2Switch::new();
+        let to_b = frame(HOST_B, HOST_A, EtherType(0x88b5), b"hello");
+        assert_eq!(switch.process_frame_from_tap(&to_b), TapFrameAction::Flood);
+
+        let to_a = frame(HOST_A, HOST_B, EtherType(0x88b5), b"reply");
+        assert!(switch.process_frame_from_peer(7, &to_a));
+        assert_eq!(
+            switch.process_frame_from_tap(&to_b),
+            TapFrameAction::Unicast(7)
+        );
+
+        let broadcast = frame(MacAddr::broadcast(), HOST_A, EtherType(0x88b5), b"x");
+        assert_eq!(
+            switch.process_frame_from_tap(&broadcast),
+            TapFrameAction::Flood
+        );
+    }
+
+    #[test]
+    fn looped_frames_are_dropped() {
+        let switch = L2Switch::new();
+        let from_a = frame(MacAddr::broadcast(), HOST_A, EtherType(0x88b5), b"x");
+        assert_eq!(
+            switch.process_frame_from_tap(&from_a),
+            TapFrameAction::Flood
+        );
+        // the same broadcast comes back from a peer bridged to the same lan
+        assert!(!switch.process_frame_from_peer(7, &from_a));
+        // and a frame of a remote host looping back into the tap side
+        let from_b = frame(MacAddr::broadcast(), HOST_B, EtherType(0x88b5), b"x");
+        assert!(switch.process_frame_from_peer(7, &from_b));
+        assert_eq!(switch.process_frame_from_tap(&from_b), TapFrameAction::Drop);
+    }
+
+    #[test]
+    fn arp_for_l3_peer_and_ip_wrapping() {
+        let switch = L2Switch::new();
+        let local_ip = Ipv4Addr::new(10, 144, 144, 1);
+        let peer_ip = Ipv4Addr::new(10, 144, 144, 2);
+
+        let request = arp_request(HOST_A, local_ip, peer_ip);
+        assert_eq!(
+            switch.process_frame_from_tap(&request),
+            TapFrameAction::ArpRequest(peer_ip)
+        );
+
+        let reply = switch.build_arp_reply(&request, 9).unwrap();
+        let eth = EthernetPacket::new(&reply).unwrap();
+        assert_eq!(eth.get_destination(), HOST_A);
+        let arp = ArpPacket::new(eth.payload()).unwrap();
+        assert_eq!(arp.get_operation(), ArpOperations::Reply);
+        assert_eq!(arp.get_sender_hw_addr(), synthetic_peer_mac(9));
+        assert_eq!(arp.get_sender_proto_addr(), peer_ip);
+
+        // ip frame to the synthetic mac is routed as ip
+        let mut ip = vec![0u8; 20];
+        ip[0] = 0x45;
+        ip[12..16].copy_from_slice(&local_ip.octets());
+        ip[16..20].copy_from_slice(&peer_ip.octets());
+        let to_peer = frame(synthetic_peer_mac(9), HOST_A, EtherTypes::Ipv4, &ip);
+        assert_eq!(
+            switch.process_frame_from_tap(&to_peer),
+            TapFrameAction::RouteIp
+        );
+        assert_eq!(ethernet_ip_payload(&to_peer), Some(&ip[..]));
+
+        // the reply is sent to the host that owns the destination address
+        ip[12..16].copy_from_slice(&peer_ip.octets());
+        ip[16..20].copy_from_slice(&local_ip.octets());
+        let wrapped = switch.wrap_ip_packet(9, &ip).unwrap();
+        let eth = EthernetPacket::new(&wrapped).unwrap();
+        assert_eq!(eth.get_destination(), HOST_A);
+        assert_eq!(eth.get_source(), synthetic_peer_mac(9));
+        assert_eq!(eth.payload(), &ip[..]);
+    }
+}
@@ -6,6 +6,9 @@ pub mod peer;
 pub mod peer_conn;
 pub mod peer_conn_ping;
 pub mod peer_manager;
+pub mod flow_log;
+pub mod l2_switch;
+pub mod packet_capture;
 pub mod peer_map;
 pub mod peer_ospf_route;
 pub mod peer_rpc;
@@ -13,6 +16,8 @@ pub mod peer_rpc_service;
 pub mod peer_session;
 pub(crate) mod public_ipv6;
 pub mod relay_peer_map;
+pub mod relay_quota;
+pub mod route_metric;
 pub mod route_trait;
 pub mod rpc_service;
 mod traffic_metrics;
@@ -21,6 +26,7 @@ pub mod foreign_network_client;
 pub mod foreign_network_manager;
 
 pub mod encrypt;
+pub(crate) mod pq_kem;
 pub(crate) mod secure_datagram;
 
 pub mod peer_task;
@@ -0,0 +1,831 @@
+// capture packets of the overlay data path into pcapng, so acl drops, mtu and
+// relay problems can be debugged without running tcpdump on every host.
+//
+// packets are captured at the nic stage (tx), after decryption and acl of the
+// peer stage (rx) and when forwarded to another peer. forwarded packets are
+// usually still encrypted, they are written to a separate interface with
+// LINKTYPE_USER0 so wireshark does not try to decode them as ip.
+
+use std::{
+    collections::VecDeque,
+    io::{self, Write},
+    net::IpAddr,
+    path::PathBuf,
+    str::FromStr,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use dashmap::DashMap;
+use pnet::packet::{
+    Packet as _,
+    ip::{IpNextHeaderProtocol, IpNextHeaderProtocols},
+    ipv4::Ipv4Packet,
+    ipv6::Ipv6Packet,
+    tcp::TcpPacket,
+    udp::UdpPacket,
+};
+use tokio::{io::AsyncWriteExt as _, task::JoinSet};
+
+use crate::{
+    common::PeerId,
+    proto::api::instance::{CaptureDirection, CapturedPacket, FetchCaptureResponse},
+    tunnel::packet_def::{PacketType, ZCPacket},
+};
+
+pub const DEFAULT_SNAPLEN: u32 = 65535;
+pub const DEFAULT_BUFFER_BYTES: usize = 4 * 1024 * 1024;
+pub const MAX_BUFFER_BYTES: usize = 64 * 1024 * 1024;
+const MAX_SESSIONS: usize = 4;
+// rpc sessions not fetched within this time are dropped, e.g. the cli was killed
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const FILE_FLUSH_INTERVAL: Duration = Duration::from_millis(200);
+
+const LINKTYPE_RAW: u16 = 101;
+const LINKTYPE_USER0: u16 = 147;
+const IF_RAW: u32 = 0;
+const IF_OPAQUE: u32 = 1;
+
+// a small subset of bpf: "peer <id>", "host <ip>", "port <port>",
+// "proto <num|name>", "tcp", "udp", "icmp", "icmp6", "rx", "tx", "forward".
+// primitives of different kinds are and-ed, repeated kinds are or-ed, so
+// "port 22 port 80 tcp" captures tcp packets on port 22 or 80.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct CaptureFilter {
+    peers: Vec<PeerId>,
+    hosts: Vec<IpAddr>,
+    ports: Vec<u16>,
+    protocols: Vec<u8>,
+    directions: Vec<CaptureDirection>,
+}
+
+fn next_arg<'a>(tokens: &mut impl Iterator<Item = &'a str>, name: &str) -> anyhow::Result<&'a str> {
+    tokens
+        .next()
+        .with_context(|| format!("capture filter: {} requires an argument", name))
+}
+
+fn parse_protocol(s: &str) -> anyhow::Result<u8> {
+    Ok(match s.to_ascii_lowercase().as_str() {
+        "tcp" => IpNextHeaderProtocols::Tcp.0,
+        "udp" => IpNextHeaderProtocols::Udp.0,
+        "icmp" => IpNextHeaderProtocols::Icmp.0,
+        "icmp6" | "icmpv6" => IpNextHeaderProtocols::Icmpv6.0,
+        other => other
+            .parse()
+            .with_context(|| format!("capture filter: invalid protocol {}", s))?,
+    })
+}
+
+impl FromStr for CaptureFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut filter = CaptureFilter::default();
+        let mut tokens = s.split_whitespace();
+        while let Some(token) = tokens.next() {
+            match token.to_ascii_lowercase().as_str() {
+                "and" | "&&" => {}
+                "peer" => {
+                    let arg = next_arg(&mut tokens, "peer")?;
+                    filter.peers.push(
+                        arg.parse()
+                            .with_context(|| format!("capture filter: invalid peer id {}", arg))?,
+                    );
+                }
+                "host" => {
+                    let arg = next_arg(&mut tokens, "host")?;
+                    filter.hosts.push(
+                        arg.parse()
+                            .with_context(|| format!("capture filter: invalid host {}", arg))?,
+                    );
+                }
+                "port" => {
+                    let arg = next_arg(&mut tokens, "port")?;
+                    filter.ports.push(
+                        arg.parse()
+                            .with_context(|| format!("capture filter: invalid port {}", arg))?,
+                    );
+                }
+                "proto" => {
+                    let arg = next_arg(&mut tokens, "proto")?;
+                    filter.protocols.push(parse_protocol(arg)?);
+                }
+                proto @ ("tcp" | "udp" | "icmp" | "icmp6" | "icmpv6") => {
+                    filter.protocols.push(parse_protocol(proto)?);
+                }
+                "rx" | "inbound" => filter.directions.push(CaptureDirection::CaptureRx),
+                "tx" | "outbound" => filter.directions.push(CaptureDirection::CaptureTx),
+                "forward" => filter.directions.push(CaptureDirection::CaptureForward),
+                _ => anyhow::bail!("capture filter: unknown primitive {}", token),
+            }
+        }
+        Ok(filter)
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+struct IpMeta {
+    src: Option<IpAddr>,
+    dst: Option<IpAddr>,
+    protocol: Option<u8>,
+    src_port: Option<u16>,
+    dst_port: Option<u16>,
+}
+
+fn transport_ports(protocol: IpNextHeaderProtocol, payload: &[u8]) -> (Option<u16>, Option<u16>) {
+    match protocol {
+        IpNextHeaderProtocols::Tcp => TcpPacket::new(payload)
+            .map(|p| (Some(p.get_source()), Some(p.get_destination())))
+            .unwrap_or_default(),
+        IpNextHeaderProtocols::Udp => UdpPacket::new(payload)
+            .map(|p| (Some(p.get_source()), Some(p.get_destination())))
+            .unwrap_or_default(),
+        _ => (None, None),
+    }
+}
+
+impl IpMeta {
+    fn parse(data: &[u8]) -> Option<Self> {
+        let (src, dst, protocol, (src_port, dst_port)) = match data.first()? >> 4 {
+            4 => {
+                let ipv4 = Ipv4Packet::new(data)?;
+                let protocol = ipv4.get_next_level_protocol();
+                (
+                    IpAddr::V4(ipv4.get_source()),
+                    IpAddr::V4(ipv4.get_destination()),
+                    protocol,
+                    transport_ports(protocol, ipv4.payload()),
+                )
+            }
+            6 => {
+                let ipv6 = Ipv6Packet::new(data)?;
+                let protocol = ipv6.get_next_header();
+                (
+                    IpAddr::V6(ipv6.get_source()),
+                    IpAddr::V6(ipv6.get_destination()),
+                    protocol,
+                    transport_ports(protocol, ipv6.payload()),
+                )
+            }
+            _ => return None,
+        };
+
+        Some(IpMeta {
+            src: Some(src),
+            dst: Some(dst),
+            protocol: Some(protocol.0),
+            src_port,
+            dst_port,
+        })
+    }
+}
+
+impl CaptureFilter {
+    fn needs_ip_header(&self) -> bool {
+        !self.hosts.is_empty() || !self.ports.is_empty() || !self.protocols.is_empty()
+    }
+
+    pub fn matches(
+        &self,
+        direction: CaptureDirection,
+        from_peer_id: PeerId,
+        to_peer_id: PeerId,
+        data: &[u8],
+        opaque: bool,
+    ) -> bool {
+        if !self.directions.is_empty() && !self.directions.contains(&direction) {
+            return false;
+        }
+        if !self.peers.is_empty()
+            && !self
+                .peers
+                .iter()
+                .any(|p| *p == from_peer_id || *p == to_peer_id)
+        {
+            return false;
+        }
+        if !self.needs_ip_header() {
+            return true;
+        }
+        // the ip header of encrypted packets is not visible
+        if opaque {
+            return false;
+        }
+        let Some(meta) = IpMeta::parse(data) else {
+            return false;
+        };
+
+        if !self.hosts.is_empty()
+            && !self
+                .hosts
+                .iter()
+                .any(|h| Some(*h) == meta.src || Some(*h) == meta.dst)
+        {
+            return false;
+        }
+        if !self.protocols.is_empty()
+            && !meta
+                .protocol
+                .map(|p| self.protocols.contains(&p))
+                .unwrap_or(false)
+        {
+            return false;
+        }
+        if !self.ports.is_empty()
+            && !self
+                .ports
+                .iter()
+                .any(|p| Some(*p) == meta.src_port || Some(*p) == meta.dst_port)
+        {
+            return false;
+        }
+        true
+    }
+}
+
+fn direction_name(direction: CaptureDirection) -> &'static str {
+    match direction {
+        CaptureDirection::CaptureRx => "rx",
+        CaptureDirection::CaptureTx => "tx",
+        CaptureDirection::CaptureForward => "forward",
+    }
+}
+
+// one line summary of a captured packet, like tcpdump without -w
+pub fn describe_packet(packet: &CapturedPacket) -> String {
+    let ts = chrono::DateTime::from_timestamp_micros(packet.timestamp_us)
+        .map(|t| {
+            t.with_timezone(&chrono::Local)
+                .format("%H:%M:%S%.6f")
+                .to_string()
+        })
+        .unwrap_or_default();
+    let mut out = format!(
+        "{} {} peer {} > {}",
+        ts,
+        direction_name(packet.direction()),
+        packet.from_peer_id,
+        packet.to_peer_id
+    );
+
+    let meta = if packet.opaque {
+        None
+    } else {
+        IpMeta::parse(&packet.data)
+    };
+    match meta {
+        Some(meta) => {
+            let endpoint = |ip: Option<IpAddr>, port: Option<u16>| match (ip, port) {
+                (Some(IpAddr::V6(ip)), Some(port)) => format!("[{}]:{}", ip, port),
+                (Some(ip), Some(port)) => format!("{}:{}", ip, port),
+                (Some(ip), None) => ip.to_string(),
+                _ => "?".to_string(),
+            };
+            let proto = match meta.protocol {
+                Some(p) if p == IpNextHeaderProtocols::Tcp.0 => "tcp".to_string(),
+                Some(p) if p == IpNextHeaderProtocols::Udp.0 => "udp".to_string(),
+                Some(p) if p == IpNextHeaderProtocols::Icmp.0 => "icmp".to_string(),
+                Some(p) if p == IpNextHeaderProtocols::Icmpv6.0 => "icmp6".to_string(),
+                Some(p) => format!("proto {}", p),
+                None => "?".to_string(),
+            };
+            out += &format!(
+                ": {} > {} {}",
+                endpoint(meta.src, meta.src_port),
+                endpoint(meta.dst, meta.dst_port),
+                proto
+            );
+        }
+        None if packet.opaque => out += ": encrypted",
+        None => out += ": not ip",
+    }
+    out += &format!(", length {}", packet.original_len);
+    if packet.acl_dropped {
+        out += " [acl dropped]";
+    }
+    out
+}
+
+fn pad32(buf: &mut Vec<u8>) {
+    while !buf.len().is_multiple_of(4) {
+        buf.push(0);
+    }
+}
+
+fn push_option(buf: &mut Vec<u8>, code: u16, value: &[u8]) {
+    buf.extend_from_slice(&code.to_le_bytes());
+    buf.extend_from_slice(&(value.len() as u16).to_le_bytes());
+    buf.extend_from_slice(value);
+    pad32(buf);
+}
+
+fn push_end_of_options(buf: &mut Vec<u8>) {
+    buf.extend_from_slice(&[0, 0, 0, 0]);
+}
+
+// minimal pcapng writer: one section, an interface for ip packets and an
+// interface for opaque forwarded payloads, enhanced packet blocks.
+pub struct PcapNgWriter<W: Write> {
+    inner: W,
+}
+
+impl<W: Write> PcapNgWriter<W> {
+    pub fn new(mut inner: W, snaplen: u32) -> io::Result<Self> {
+        let mut shb = Vec::new();
+        shb.extend_from_slice(&0x1A2B_3C4Du32.to_le_bytes());
+        shb.extend_from_slice(&1u16.to_le_bytes());
+        shb.extend_from_slice(&0u16.to_le_bytes());
+        shb.extend_from_slice(&(-1i64).to_le_bytes());
+        // shb_userappl
+        push_option(
+            &mut shb,
+            4,
+            format!("easytier {}", crate::VERSION).as_bytes(),
+        );
+        push_end_of_options(&mut shb);
+        Self::write_block(&mut inner, 0x0A0D_0D0A, &shb)?;
+
+        for (linktype, name) in [
+            (LINKTYPE_RAW, "easytier"),
+            (LINKTYPE_USER0, "easytier-encrypted"),
+        ] {
+            let mut idb = Vec::new();
+            idb.extend_from_slice(&linktype.to_le_bytes());
+            idb.extend_from_slice(&0u16.to_le_bytes());
+            idb.extend_from_slice(&snaplen.to_le_bytes());
+            // if_name
+            push_option(&mut idb, 2, name.as_bytes());
+            push_end_of_options(&mut idb);
+            Self::write_block(&mut inner, 0x0000_0001, &idb)?;
+        }
+
+        Ok(Self { inner })
+    }
+
+    fn write_block(w: &mut W, block_type: u32, body: &[u8]) -> io::Result<()> {
+        let total_len = (12 + body.len()) as u32;
+        w.write_all(&block_type.to_le_bytes())?;
+        w.write_all(&total_len.to_le_bytes())?;
+        w.write_all(body)?;
+        w.write_all(&total_len.to_le_bytes())
+    }
+
+    pub fn write_packet(&mut self, packet: &CapturedPacket) -> io::Result<()> {
+        let ts = packet.timestamp_us as u64;
+        let mut epb = Vec::with_capacity(packet.data.len() + 64);
+        let if_id = if packet.opaque { IF_OPAQUE } else { IF_RAW };
+        epb.extend_from_slice(&if_id.to_le_bytes());
+        epb.extend_from_slice(&((ts >> 32) as u32).to_le_bytes());
+        epb.extend_from_slice(&(ts as u32).to_le_bytes());
+        epb.extend_from_slice(&(packet.data.len() as u32).to_le_bytes());
+        epb.extend_from_slice(&packet.original_len.to_le_bytes());
+        epb.extend_from_slice(&packet.data);
+        pad32(&mut epb);
+
+        // epb_flags, bits 0-1 are the direction: 1 inbound, 2 outbound
+        let flags: u32 = match packet.direction() {
+            CaptureDirection::CaptureRx => 1,
+            CaptureDirection::CaptureTx => 2,
+            CaptureDirection::CaptureForward => 0,
+        };
+        push_option(&mut epb, 2, &flags.to_le_bytes());
+        let mut comment = format!(
+            "{} peer {} > {}",
+            direction_name(packet.direction()),
+            packet.from_peer_id,
+            packet.to_peer_id
+        );
+        if packet.acl_dropped {
+            comment += " acl dropped";
+        }
+        // opt_comment
+        push_option(&mut epb, 1, comment.as_bytes());
+        push_end_of_options(&mut epb);
+
+        Self::write_block(&mut self.inner, 0x0000_0006, &epb)
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CaptureOptions {
+    pub filter: CaptureFilter,
+    // 0 means no limit
+    pub max_packets: u64,
+    pub buffer_bytes: usize,
+    pub snaplen: u32,
+    pub output_file: Option<PathBuf>,
+}
+
+impl Default for CaptureOptions {
+    fn default() -> Self {
+        Self {
+            filter: CaptureFilter::default(),
+            max_packets: 0,
+            buffer_bytes: DEFAULT_BUFFER_BYTES,
+            snaplen: DEFAULT_SNAPLEN,
+            output_file: None,
+        }
+    }
+}
+
+struct SessionState {
+    packets: VecDeque<CapturedPacket>,
+    buffered_bytes: usize,
+    captured: u64,
+    dropped: u64,
+    last_fetch: Instant,
+}
+
+struct CaptureSession {
+    filter: CaptureFilter,
+    max_packets: u64,
+    buffer_bytes: usize,
+    snaplen: usize,
+    // drained by a local task instead of fetch rpc, never idle
+    to_file: bool,
+    stopped: AtomicBool,
+    state: std::sync::Mutex<SessionState>,
+}
+
+impl CaptureSession {
+    fn finished(&self, state: &SessionState) -> bool {
+        self.max_packets != 0 && state.captured >= self.max_packets
+    }
+
+    // returns false if the session is idle and should be removed
+    #[allow(clippy::too_many_arguments)]
+    fn capture(
+        &self,
+        direction: CaptureDirection,
+        from_peer_id: PeerId,
+        to_peer_id: PeerId,
+        data: &[u8],
+        opaque: bool,
+        acl_dropped: bool,
+        timestamp_us: i64,
+    ) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if !self.to_file && state.last_fetch.elapsed() > SESSION_IDLE_TIMEOUT {
+            return false;
+        }
+        if self.finished(&state)
+            || !self
+                .filter
+                .matches(direction, from_peer_id, to_peer_id, data, opaque)
+        {
+            return true;
+        }
+
+        let cap_len = data.len().min(self.snaplen);
+        state.captured += 1;
+        state.buffered_bytes += cap_len;
+        state.packets.push_back(CapturedPacket {
+            timestamp_us,
+            direction: direction.into(),
+            from_peer_id,
+            to_peer_id,
+            data: data[..cap_len].to_vec(),
+            original_len: data.len() as u32,
+            opaque,
+            acl_dropped,
+        });
+
+        // ring buffer, drop the oldest packets
+        while state.buffered_bytes > self.buffer_bytes {
+            let Some(old) = state.packets.pop_front() else {
+                break;
+            };
+            state.buffered_bytes -= old.data.len();
+            state.dropped += 1;
+        }
+        true
+    }
+
+    fn drain(&self) -> FetchCaptureResponse {
+        let mut state = self.state.lock().unwrap();
+        state.last_fetch = Instant::now();
+        state.buffered_bytes = 0;
+        FetchCaptureResponse {
+            packets: state.packets.drain(..).collect(),
+            captured: state.captured,
+            dropped: state.dropped,
+            finished: self.finished(&state),
+        }
+    }
+}
+
+pub struct PacketCapture {
+    sessions: DashMap<u32, Arc<CaptureSession>>,
+    // cheap check for the data path when nothing is being captured
+    active: AtomicBool,
+    next_session_id: AtomicU32,
+    tasks: std::sync::Mutex<JoinSet<()>>,
+}
+
+impl Default for PacketCapture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PacketCapture {
+    pub fn new() -> Self {
+        Self {
+            sessions: DashMap::new(),
+            active: AtomicBool::new(false),
+            next_session_id: AtomicU32::new(1),
+            tasks: std::sync::Mutex::new(JoinSet::new()),
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    fn update_active(&self) {
+        self.active
+            .store(!self.sessions.is_empty(), Ordering::Relaxed);
+    }
+
+    pub async fn start(&self, options: CaptureOptions) -> anyhow::Result<u32> {
+        if self.sessions.len() >= MAX_SESSIONS {
+            anyhow::bail!("too many capture sessions, at most {}", MAX_SESSIONS);
+        }
+        if options.buffer_bytes == 0 || options.buffer_bytes > MAX_BUFFER_BYTES {
+            anyhow::bail!(
+                "capture buffer size must be between 1 and {} bytes",
+                MAX_BUFFER_BYTES
+            );
+        }
+        if options.snaplen == 0 {
+            anyhow::bail!("capture snaplen must not be 0");
+        }
+
+        let file = match &options.output_file {
+            Some(path) => Some(
+                tokio::fs::File::create(path)
+                    .await
+                    .with_context(|| format!("failed to create capture file {:?}", path))?,
+            ),
+            None => None,
+        };
+
+        let session = Arc::new(CaptureSession {
+            filter: options.filter,
+            max_packets: options.max_packets,
+            buffer_bytes: options.buffer_bytes,
+            snaplen: options.snaplen as usize,
+            to_file: file.is_some(),
+            stopped: AtomicBool::new(false),
+            state: std::sync::Mutex::new(SessionState {
+                packets: VecDeque::new(),
+                buffered_bytes: 0,
+                captured: 0,
+                dropped: 0,
+                last_fetch: Instant::now(),
+            }),
+        });
+
+        if let Some(file) = file {
+            let writer = PcapNgWriter::new(Vec::new(), options.snaplen)?;
+            self.tasks.lock().unwrap().spawn(Self::file_writer_routine(
+                session.clone(),
+                writer,
+                file,
+            ));
+        }
+
+        let session_id = self.next_session_id.fetch_add(1, Ordering::Relaxed);
+        self.sessions.insert(session_id, session);
+        self.update_active();
+        Ok(session_id)
+    }
+
+    async fn file_writer_routine(
+        session: Arc<CaptureSession>,
+        mut writer: PcapNgWriter<Vec<u8>>,
+        mut file: tokio::fs::File,
+    ) {
+        loop {
+            let stopped = session.stopped.load(Ordering::Relaxed);
+            let fetched = session.drain();
+            for packet in fetched.packets.iter() {
+                let _ = writer.write_packet(packet);
+            }
+            let buf = std::mem::take(writer.get_mut());
+            if let Err(e) = file.write_all(&buf).await {
+                tracing::warn!(?e, "write capture file failed");
+                session.stopped.store(true, Ordering::Relaxed);
+                break;
+            }
+            if stopped || fetched.finished {
+                break;
+            }
+            tokio::time::sleep(FILE_FLUSH_INTERVAL).await;
+        }
+        let _ = file.flush().await;
+    }
+
+    pub fn fetch(&self, session_id: u32) -> anyhow::Result<FetchCaptureResponse> {
+        let session = self
+            .sessions
+            .get(&session_id)
+            .map(|s| s.clone())
+            .with_context(|| format!("capture session {} not found", session_id))?;
+        if session.to_file {
+            // packets are consumed by the file writer, only report progress
+            let state = session.state.lock().unwrap();
+            return Ok(FetchCaptureResponse {
+                packets: vec![],
+                captured: state.captured,
+                dropped: state.dropped,
+                finished: session.finished(&state) || session.stopped.load(Ordering::Relaxed),
+            });
+        }
+        Ok(session.drain())
+    }
+
+    pub fn stop(&self, session_id: u32) -> anyhow::Result<(u64, u64)> {
+        let (_, session) = self
+            .sessions
+            .remove(&session_id)
+            .with_context(|| format!("capture session {} not found", session_id))?;
+        self.update_active();
+        session.stopped.store(true, Ordering::Relaxed);
+        let state = session.state.lock().unwrap();
+        Ok((state.captured, state.dropped))
+    }
+
+    // only data packets are captured, control plane traffic is not interesting
+    // and would flood the capture.
+    pub fn capture(&self, direction: CaptureDirection, packet: &ZCPacket, acl_dropped: bool) {
+        let Some(hdr) = packet.peer_manager_header() else {
+            return;
+        };
+        if hdr.packet_type != PacketType::Data as u8 {
+            return;
+        }
+        let from_peer_id = hdr.from_peer_id.get();
+        let to_peer_id = hdr.to_peer_id.get();
+        let opaque = hdr.is_encrypted() || hdr.is_compressed();
+        let data = packet.payload();
+        let timestamp_us = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as i64;
+
+        let mut idle_sessions = vec![];
+        for session in self.sessions.iter() {
+            if !session.capture(
+                direction,
+                from_peer_id,
+                to_peer_id,
+                data,
+                opaque,
+                acl_dropped,
+                timestamp_us,
+            ) {
+                idle_sessions.push(*session.key());
+            }
+        }
+
+        if !idle_sessions.is_empty() {
+            for session_id in idle_sessions {
+                tracing::info!(session_id, "remove idle capture session");
+                self.sessions.remove(&session_id);
+            }
+            self.update_active();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn udp_packet(src: [u8; 4], dst: [u8; 4], sport: u16, dport: u16) -> Vec<u8> {
+        let mut buf = vec![0u8; 28];
+        buf[0] = 0x45;
+        buf[2..4].copy_from_slice(&28u16.to_be_bytes());
+        buf[8] = 64;
+        buf[9] = IpNextHeaderProtocols::Udp.0;
+        buf[12..16].copy_from_slice(&src);
+        buf[16..20].copy_from_slice(&dst);
+        buf[20..22].copy_from_slice(&sport.to_be_bytes());
+        buf[22..24].copy_from_slice(&dport.to_be_bytes());
+        buf[24..26].copy_from_slice(&8u16.to_be_bytes());
+        buf
+    }
+
+    #[test]
+    fn test_capture_filter() {
+        let pkt = udp_packet([10, 0, 0, 1], [10, 0, 0, 2], 5000, 53);
+        let rx = CaptureDirection::CaptureRx;
+
+        let f: CaptureFilter = "".parse().unwrap();
+        assert!(f.matches(rx, 1, 2, &pkt, false));
+
+        let f: CaptureFilter = "udp and port 53 and host 10.0.0.2".parse().unwrap();
+        assert!(f.matches(rx, 1, 2, &pkt, false));
+        // ip filters never match encrypted payloads
+        assert!(!f.matches(rx, 1, 2, &pkt, true));
+
+        let f: CaptureFilter = "tcp port 53".parse().unwrap();
+        assert!(!f.matches(rx, 1, 2, &pkt, false));
+
+        let f: CaptureFilter = "port 22 port 53".parse().unwrap();
+        assert!(f.matches(rx, 1, 2, &pkt, false));
+
+        let f: CaptureFilter = "peer 3 tx".parse().unwrap();
+        assert!(!f.matches(rx, 1, 3, &pkt, false));
+        assert!(f.matches(CaptureDirection::CaptureTx, 1, 3, &pkt, true));
+        assert!(!f.matches(CaptureDirection::CaptureTx, 1, 2, &pkt, false));
+
+        let f: CaptureFilter = "proto 17".parse().unwrap();
+        assert!(f.matches(rx, 1, 2, &pkt, false));
+
+        assert!("port".parse::<CaptureFilter>().is_err());
+        assert!("host 10.0.0.300".parse::<CaptureFilter>().is_err());
+        assert!("src 10.0.0.1".parse::<CaptureFilter>().is_err());
+    }
+
+    #[test]
+    fn test_pcapng_writer() {
+        let mut writer = PcapNgWriter::new(Vec::new(), DEFAULT_SNAPLEN).unwrap();
+        let header_len = writer.get_mut().len();
+        assert_eq!(&writer.get_mut()[0..4], &0x0A0D_0D0Au32.to_le_bytes());
+
+        let data = udp_packet([10, 0, 0, 1], [10, 0, 0, 2], 5000, 53);
+        writer
+            .write_packet(&CapturedPacket {
+                timestamp_us: 1_700_000_000_000_000,
+                direction: CaptureDirection::CaptureRx.into(),
+                from_peer_id: 1,
+                to_peer_id: 2,
+                data: data[..27].to_vec(),
+                original_len: 28,
+                opaque: false,
+                acl_dropped: true,
+            })
+            .unwrap();
+
+        let buf = writer.get_mut();
+        // every block is 32 bit aligned and ends with its total length
+        assert!(buf.len().is_multiple_of(4));
+        let epb = &buf[header_len..];
+        assert_eq!(&epb[0..4], &6u32.to_le_bytes());
+        let total_len = u32::from_le_bytes(epb[4..8].try_into().unwrap()) as usize;
+        assert_eq!(total_len, epb.len());
+        assert_eq!(&epb[total_len - 4..], &(total_len as u32).to_le_bytes());
+        // captured and original length
+        assert_eq!(&epb[20..24], &27u32.to_le_bytes());
+        assert_eq!(&epb[24..28], &28u32.to_le_bytes());
+    }
+
+    #[tokio::test]
+    async fn test_capture_ring_buffer() {
+        let capture = PacketCapture::new();
+        assert!(!capture.is_active());
+        let session_id = capture
+            .start(CaptureOptions {
+                buffer_bytes: 100,
+                max_packets: 5,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(capture.is_active());
+
+        let data = udp_packet([10, 0, 0, 1], [10, 0, 0, 2], 5000, 53);
+        for _ in 0..10 {
+            let mut packet = ZCPacket::new_with_payload(&data);
+            packet.fill_peer_manager_hdr(1, 2, PacketType::Data as u8);
+            capture.capture(CaptureDirection::CaptureRx, &packet, false);
+        }
+
+        // 5 packets captured, only 3 fit into the buffer
+        let ret = capture.fetch(session_id).unwrap();
+        assert!(ret.finished);
+        assert_eq!(ret.captured, 5);
+        assert_eq!(ret.dropped, 2);
+        assert_eq!(ret.packets.len(), 3);
+        assert_eq!(ret.packets[0].data, data);
+
+        assert_eq!(capture.stop(session_id).unwrap(), (5, 2));
+        assert!(!capture.is_active());
+        assert!(capture.fetch(session_id).is_err());
+    }
+}
@@ -319,6 +319,7 @@ mod tests {
                 enabled: true,
                 local_private_key: Some(BASE64_STANDARD.encode(private.as_bytes())),
                 local_public_key: Some(BASE64_STANDARD.encode(public.as_bytes())),
+                pq_hybrid: false,
             }));
         }
     }
@@ -31,15 +31,17 @@ use super::{
     PacketRecvChan,
     peer_conn_ping::PeerConnPinger,
     peer_session::{PeerSession, PeerSessionAction},
+    pq_kem,
+    secure_datagram::{SecureDatagramDirection, SecureDatagramSession},
     traffic_metrics::AggregateTrafficMetrics,
 };
 use crate::utils::BoxExt;
 use crate::{
     common::{
         PeerId,
-        config::{NetworkIdentity, NetworkSecretDigest},
+        config::{EncryptionAlgorithm, NetworkIdentity, NetworkSecretDigest},
         error::Error,
-        global_ctx::ArcGlobalCtx,
+        global_ctx::{ArcGlobalCtx, GlobalCtx},
     },
     peers::peer_session::{PeerSessionStore, SessionKey, UpsertResponderSessionReturn},
     proto::{
@@ -64,6 +66,10 @@ pub type PeerConnId = uuid::Uuid;
 
 const MAGIC: u32 = 0xd1e1a5e1;
 const VERSION: u32 = 1;
+// noise msg1 version of clients offering the hybrid X25519 + ML-KEM-768 handshake
+const PQ_HYBRID_VERSION: u32 = 2;
+
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
 
 /// The proof of client secret.
 #[derive(Debug)]
@@ -92,6 +98,9 @@ struct NoiseHandshakeResult {
 
     my_encrypt_algo: String,
     remote_encrypt_algo: String,
+
+    // the root key was protected by the ML-KEM share
+    pq_hybrid: bool,
 }
 
 #[derive(Clone)]
@@ -247,6 +256,132 @@ impl TunnelFilter for PeerSessionTunnelFilter {
     fn filter_output(&self) {}
 }
 
+struct LinkSession {
+    session: SecureDatagramSession,
+    send_dir: SecureDatagramDirection,
+    recv_dir: SecureDatagramDirection,
+}
+
+/// Per-connection encryption negotiated in the non-secure handshake. It wraps
+/// every packet sent on this connection, including the ones already encrypted
+/// with the network secret, so recorded traffic stays protected even if the
+/// network secret leaks later.
+#[derive(Clone)]
+struct LinkSessionTunnelFilter {
+    session: Arc<std::sync::OnceLock<LinkSession>>,
+}
+
+impl LinkSessionTunnelFilter {
+    fn new() -> Self {
+        Self {
+            session: Arc::new(std::sync::OnceLock::new()),
+        }
+    }
+
+    fn set_session(&self, session: LinkSession) {
+        let _ = self.session.set(session);
+    }
+
+    fn get_session(&self) -> Option<&SecureDatagramSession> {
+        self.session.get().map(|s| &s.session)
+    }
+
+    fn should_skip_encrypt(hdr: &crate::tunnel::packet_def::PeerManagerHeader) -> bool {
+        hdr.packet_type == PacketType::HandShake as u8
+            || hdr.packet_type == PacketType::NoiseHandshakeMsg1 as u8
+            || hdr.packet_type == PacketType::NoiseHandshakeMsg2 as u8
+            || hdr.packet_type == PacketType::NoiseHandshakeMsg3 as u8
+            || hdr.packet_type == PacketType::RelayHandshake as u8
+            || hdr.packet_type == PacketType::RelayHandshakeAck as u8
+            || hdr.packet_type == PacketType::Ping as u8
+            || hdr.packet_type == PacketType::Pong as u8
+    }
+}
+
+impl TunnelFilter for LinkSessionTunnelFilter {
+    type FilterOutput = ();
+
+    fn before_send(&self, mut data: crate::tunnel::SinkItem) -> Option<crate::tunnel::SinkItem> {
+        let Some(link) = self.session.get() else {
+            return Some(data);
+        };
+
+        let Some(hdr) = data.peer_manager_header() else {
+            return Some(data);
+        };
+        if Self::should_skip_encrypt(hdr) {
+            return Some(data);
+        }
+
+        // the encryptors skip packets already carrying the encrypted flag, so hide
+        // the network secret layer while wrapping and restore it afterwards.
+        let was_encrypted = hdr.is_encrypted();
+        data.mut_peer_manager_header().unwrap().set_encrypted(false);
+        if let Err(e) = link.session.encrypt_payload(link.send_dir, &mut data) {
+            tracing::warn!(
+                ?e,
+                "LinkSessionTunnelFilter: encrypt failed, dropping packet"
+            );
+            return None;
+        }
+        data.mut_peer_manager_header()
+            .unwrap()
+            .set_link_encrypted(true)
+            .set_encrypted(was_encrypted);
+
+        Some(data)
+    }
+
+    fn after_received(&self, data: crate::tunnel::StreamItem) -> Option<crate::tunnel::StreamItem> {
+        let mut data = match data {
+            Ok(v) => v,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let Some(hdr) = data.peer_manager_header() else {
+            return Some(Ok(data));
+        };
+
+        let Some(link) = self.session.get() else {
+            if hdr.is_link_encrypted() {
+                tracing::debug!("link encrypted packet without link session, dropping");
+                return None;
+            }
+            return Some(Ok(data));
+        };
+
+        if !hdr.is_link_encrypted() {
+            if Self::should_skip_encrypt(hdr) {
+                return Some(Ok(data));
+            }
+            tracing::debug!(?hdr, "plaintext packet on link encrypted conn, dropping");
+            return None;
+        }
+
+        let was_encrypted = hdr.is_encrypted();
+        data.mut_peer_manager_header()
+            .unwrap()
+            .set_link_encrypted(false)
+            .set_encrypted(true);
+        if let Err(e) = link.session.decrypt_payload(link.recv_dir, &mut data) {
+            if !link.session.is_valid() {
+                tracing::error!(?e, "link session invalidated, closing connection");
+                return Some(Err(TunnelError::InternalError(
+                    "link session invalidated due to consecutive decrypt failures".to_string(),
+                )));
+            }
+            return None;
+        }
+        data.mut_peer_manager_header()
+            .unwrap()
+            .set_encrypted(was_encrypted);
+
+        Some(Ok(data))
+    }
+
+    fn filter_output(&self) {}
+}
+
 pub struct PeerConnCloseNotify {
     conn_id: PeerConnId,
     sender: Arc<std::sync::Mutex<Option<broadcast::Sender<()>>>>,
@@ -291,6 +426,7 @@ pub struct PeerConn {
 
     secure_mode_cfg: Option<SecureModeConfig>,
     session_filter: PeerSessionTunnelFilter,
+    link_filter: LinkSessionTunnelFilter,
     noise_handshake_result: Option<NoiseHandshakeResult>,
 
     tunnel: Arc<Mutex<Box<dyn Any + Send + 'static>>>,
@@ -358,9 +494,12 @@ impl PeerConn {
                 .unwrap_or(false),
         );
 
+        let link_filter = LinkSessionTunnelFilter::new();
+
         let peer_conn_tunnel_filter = StatsRecorderTunnelFilter::new();
         let throughput = peer_conn_tunnel_filter.filter_output();
-        let filter_chain = TunnelFilterChain::new(session_filter.clone(), peer_conn_tunnel_filter);
+        let filter_chain = TunnelFilterChain::new(session_filter.clone(), link_filter.clone())
+            .chain(peer_conn_tunnel_filter);
         let peer_conn_tunnel = TunnelWithFilter::new(tunnel, filter_chain);
         let mut mpsc_tunnel = MpscTunnel::new(peer_conn_tunnel, Some(Duration::from_secs(7)));
 
@@ -378,6 +517,7 @@ impl PeerConn {
 
             secure_mode_cfg,
             session_filter,
+            link_filter,
             noise_handshake_result: None,
 
             tunnel: Arc::new(Mutex::new(
@@ -418,6 +558,111 @@ impl PeerConn {
             .unwrap_or(false)
     }
 
+    fn is_pq_hybrid_enabled(&self) -> bool {
+        self.secure_mode_cfg
+            .as_ref()
+            .map(|cfg| cfg.enabled && cfg.pq_hybrid)
+            .unwrap_or(false)
+    }
+
+    // the link session needs an aead cipher to carry its nonce tail, so xor
+    // networks use the default aead algorithm of this build for it.
+    fn conn_rekey_algo(&self) -> Option<String> {
+        let flags = self.global_ctx.get_flags();
+        if !flags.enable_conn_rekey || !flags.enable_encryption || self.is_secure_mode_enabled() {
+            return None;
+        }
+        let algo = match EncryptionAlgorithm::try_from(flags.encryption_algorithm.as_str()) {
+            Ok(EncryptionAlgorithm::Xor) | Err(_) => EncryptionAlgorithm::default(),
+            Ok(algo) => algo,
+        };
+        if algo == EncryptionAlgorithm::Xor {
+            return None;
+        }
+        Some(algo.to_string())
+    }
+
+    fn new_ephemeral_keypair() -> (x25519_dalek::EphemeralSecret, x25519_dalek::PublicKey) {
+        let secret = x25519_dalek::EphemeralSecret::random_from_rng(rand::rngs::OsRng);
+        let pubkey = x25519_dalek::PublicKey::from(&secret);
+        (secret, pubkey)
+    }
+
+    fn derive_conn_root_key(
+        &self,
+        shared_secret: &[u8; 32],
+        client_pubkey: &[u8],
+        server_pubkey: &[u8],
+    ) -> [u8; 32] {
+        // the network secret is the salt, a peer without it cannot derive the key
+        // even if it completed the diffie-hellman exchange. during a secret rotation
+        // the server salts with the accepted secret the client handshaked with.
+        let salt = if self.is_client.unwrap_or_default() {
+            self.global_ctx.get_256_key()
+        } else {
+            self.global_ctx
+                .get_accepted_network_secret(&self.get_network_identity())
+                .map(|secret| GlobalCtx::derive_256_key(&secret))
+                .unwrap_or_else(|| self.global_ctx.get_256_key())
+        };
+        let mut extract = HmacSha256::new_from_slice(&salt).unwrap();
+        extract.update(shared_secret);
+        let prk = extract.finalize().into_bytes();
+
+        let mut expand = HmacSha256::new_from_slice(&prk).unwrap();
+        expand.update(b"et-conn-rekey");
+        expand.update(client_pubkey);
+        expand.update(server_pubkey);
+        expand.update(&[1u8]);
+        let okm = expand.finalize().into_bytes();
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&okm[..32]);
+        key
+    }
+
+    fn setup_link_session(
+        &self,
+        algo: String,
+        local_secret: x25519_dalek::EphemeralSecret,
+        local_pubkey: &x25519_dalek::PublicKey,
+        remote_pubkey: &[u8],
+    ) -> Result<(), Error> {
+        let remote_pubkey: [u8; 32] = remote_pubkey.try_into().map_err(|_| {
+            Error::WaitRespError(format!(
+                "invalid ephemeral pubkey length: {}",
+                remote_pubkey.len()
+            ))
+        })?;
+        let remote_pubkey = x25519_dalek::PublicKey::from(remote_pubkey);
+        let shared = local_secret.diffie_hellman(&remote_pubkey);
+        if !shared.was_contributory() {
+            return Err(Error::WaitRespError(
+                "non-contributory ephemeral pubkey".to_owned(),
+            ));
+        }
+
+        let is_client = self.is_client.unwrap_or_default();
+        let (client_pubkey, server_pubkey) = if is_client {
+            (local_pubkey.as_bytes(), remote_pubkey.as_bytes())
+        } else {
+            (remote_pubkey.as_bytes(), local_pubkey.as_bytes())
+        };
+        let root_key = self.derive_conn_root_key(shared.as_bytes(), client_pubkey, server_pubkey);
+        let (send_dir, recv_dir) = if is_client {
+            (SecureDatagramDirection::AToB, SecureDatagramDirection::BToA)
+        } else {
+            (SecureDatagramDirection::BToA, SecureDatagramDirection::AToB)
+        };
+
+        self.link_filter.set_session(LinkSession {
+            session: SecureDatagramSession::new(root_key, 1, 0, algo.clone(), algo)
+                .with_rotate_after_bytes(SecureDatagramSession::LINK_ROTATE_AFTER_BYTES),
+            send_dir,
+            recv_dir,
+        });
+        Ok(())
+    }
+
     // pri, pub
     fn get_keypair(&self) -> Result<(Vec<u8>, Vec<u8>), Error> {
         let cfg = self
@@ -487,6 +732,11 @@ impl PeerConn {
             Error::WaitRespError(format!("decode handshake response error: {:?}", e))
         })?;
 
+        if !rsp.reject_reason.is_empty() {
+            *need_retry = false;
+            return Err(Self::handshake_rejected_error(&rsp.reject_reason));
+        }
+
         if rsp.network_secret_digest.len() != std::mem::size_of::<NetworkSecretDigest>() {
             return Err(Error::WaitRespError(
                 "invalid network secret digest".to_owned(),
@@ -520,6 +770,7 @@ impl PeerConn {
     async fn send_handshake(
         &self,
         send_secret_digest: bool,
+        ephemeral_pubkey: Option<&x25519_dalek::PublicKey>,
         metric_network_name: &str,
     ) -> Result<(), Error> {
         let network = self.global_ctx.get_network_identity();
@@ -542,6 +793,10 @@ impl PeerConn {
                 .extend_from_slice(&[0u8; std::mem::size_of::<NetworkSecretDigest>()]);
         }
 
+        if let Some(pubkey) = ephemeral_pubkey {
+            req.ephemeral_pubkey.extend_from_slice(pubkey.as_bytes());
+        }
+
         let hs_req = req.encode_to_vec();
         let mut zc_packet = ZCPacket::new_with_payload(hs_req.as_bytes());
         zc_packet.fill_peer_manager_hdr(
@@ -563,6 +818,32 @@ impl PeerConn {
         Ok(())
     }
 
+    /// Tell the client why its handshake is refused, so it doesn't only see the conn closed.
+    async fn send_handshake_reject(&self, reason: &Error) {
+        let req = HandshakeRequest {
+            magic: MAGIC,
+            my_peer_id: self.my_peer_id,
+            version: VERSION,
+            reject_reason: reason.to_string(),
+            ..Default::default()
+        };
+
+        let mut zc_packet = ZCPacket::new_with_payload(req.encode_to_vec().as_bytes());
+        zc_packet.fill_peer_manager_hdr(
+            self.my_peer_id,
+            PeerId::default(),
+            PacketType::HandShake as u8,
+        );
+        if let Err(e) = self.sink.send(zc_packet).await {
+            tracing::warn!("send handshake reject error: {:?}", e);
+        }
+        tokio::task::yield_now().await;
+    }
+
+    fn handshake_rejected_error(reason: &str) -> Error {
+        Error::WaitRespError(format!("handshake rejected by peer: {}", reason))
+    }
+
     fn decode_handshake_packet(pkt: &ZCPacket) -> Result<HandshakeRequest, Error> {
         let Some(peer_mgr_hdr) = pkt.peer_manager_header() else {
             return Err(Error::WaitRespError(
@@ -617,6 +898,14 @@ impl PeerConn {
                 continue;
             };
 
+            if peer_mgr_hdr.packet_type == PacketType::HandShake as u8
+                && expected_pkt_type.is_some_and(|t| !matches!(t, PacketType::HandShake))
+                && let Ok(rsp) = HandshakeRequest::decode(pkt.payload())
+                && !rsp.reject_reason.is_empty()
+            {
+                return Err(Self::handshake_rejected_error(&rsp.reject_reason));
+            }
+
             if expected_pkt_type.is_none()
                 || peer_mgr_hdr.packet_type == *expected_pkt_type.as_ref().unwrap() as u8
             {
@@ -716,8 +1005,7 @@ impl PeerConn {
     ) -> Result<SecureAuthLevel, Error> {
         // 1. Verify proof
         if let Some(proof) = proof
-            && let Some(mac) = self.global_ctx.get_secret_proof(handshake_hash)
-            && mac.verify_slice(proof).is_ok()
+            && self.global_ctx.verify_secret_proof(handshake_hash, proof)
         {
             return Ok(SecureAuthLevel::NetworkSecretConfirmed);
         }
@@ -814,13 +1102,20 @@ impl PeerConn {
             })
             .map(|s| s.session_generation());
 
+        let pq_kem_key = self.is_pq_hybrid_enabled().then(pq_kem::generate);
+
         let a_conn_id = uuid::Uuid::new_v4();
         let msg1_pb = PeerConnNoiseMsg1Pb {
-            version: VERSION,
+            version: if pq_kem_key.is_some() {
+                PQ_HYBRID_VERSION
+            } else {
+                VERSION
+            },
             a_network_name: network.network_name.clone(),
             a_session_generation,
             a_conn_id: Some(a_conn_id.into()),
             client_encryption_algorithm: self.my_encrypt_algo.clone(),
+            pq_kem_ek: pq_kem_key.as_ref().map(|(_, ek)| ek.clone()),
         };
 
         let mut hs = builder
@@ -939,7 +1234,7 @@ impl PeerConn {
         let handshake_hash = hs.get_handshake_hash().to_vec();
 
         let algo = self.global_ctx.get_flags().encryption_algorithm.clone();
-        let root_key = msg2_pb
+        let mut root_key = msg2_pb
             .root_key_32
             .as_deref()
             .filter(|v| v.len() == 32)
@@ -948,6 +1243,23 @@ impl PeerConn {
                 key.copy_from_slice(v);
                 key
             });
+        // a server without hybrid support just ignores our ek and sends no ct
+        let pq_hybrid = match (msg2_pb.pq_kem_ct.as_deref(), pq_kem_key.as_ref()) {
+            (Some(ct), Some((dk, _))) => {
+                let ss = dk.decapsulate(ct).map_err(|e| {
+                    Error::WaitRespError(format!("pq kem decapsulate failed: {e:?}"))
+                })?;
+                let mask = pq_kem::root_key_mask(&ss, &server_handshake_hash);
+                root_key = root_key.map(|k| pq_kem::xor_root_key(k, &mask));
+                true
+            }
+            (Some(_), None) => {
+                return Err(Error::WaitRespError(
+                    "unexpected pq kem ct in noise msg2".to_owned(),
+                ));
+            }
+            (None, _) => false,
+        };
         let session_action = match action {
             PeerConnSessionActionPb::Join => PeerSessionAction::Join,
             PeerConnSessionActionPb::Sync => PeerSessionAction::Sync,
@@ -979,6 +1291,7 @@ impl PeerConn {
 
             my_encrypt_algo: self.my_encrypt_algo.clone(),
             remote_encrypt_algo: msg2_pb.server_encryption_algorithm.clone(),
+            pq_hybrid,
         })
     }
 
@@ -1076,7 +1389,10 @@ impl PeerConn {
         self.record_control_rx(&remote_network_name, first_msg1_len);
 
         // this may update my peer id
-        handshake_recved(self, &remote_network_name)?;
+        if let Err(e) = handshake_recved(self, &remote_network_name) {
+            self.send_handshake_reject(&e).await;
+            return Err(e);
+        }
 
         let server_network_name = self.global_ctx.get_network_name();
         let (role_hint, secret_proof_32) = if msg1_pb.a_network_name == server_network_name {
@@ -1090,6 +1406,16 @@ impl PeerConn {
             (2, None)
         };
 
+        let pq_kem_encaps = match msg1_pb.pq_kem_ek.as_deref() {
+            Some(ek) if msg1_pb.version >= PQ_HYBRID_VERSION && self.is_pq_hybrid_enabled() => {
+                let (ct, ss) = pq_kem::encapsulate(ek).map_err(|e| {
+                    Error::WaitRespError(format!("pq kem encapsulate failed: {e:?}"))
+                })?;
+                Some((ct, pq_kem::root_key_mask(&ss, hs.get_handshake_hash())))
+            }
+            _ => None,
+        };
+
         let algo = self.global_ctx.get_flags().encryption_algorithm.clone();
         let UpsertResponderSessionReturn {
             session,
@@ -1115,12 +1441,16 @@ impl PeerConn {
                 PeerSessionAction::Create => PeerConnSessionActionPb::Create as i32,
             },
             b_session_generation,
-            root_key_32: root_key_32.map(|k| k.to_vec()),
+            root_key_32: root_key_32.map(|k| match &pq_kem_encaps {
+                Some((_, mask)) => pq_kem::xor_root_key(k, mask).to_vec(),
+                None => k.to_vec(),
+            }),
             initial_epoch,
             b_conn_id: Some(b_conn_id.into()),
             a_conn_id_echo: msg1_pb.a_conn_id,
             secret_proof_32,
             server_encryption_algorithm: algo,
+            pq_kem_ct: pq_kem_encaps.as_ref().map(|(ct, _)| ct.clone()),
         };
         self.send_noise_msg(
             msg2_pb,
@@ -1214,6 +1544,7 @@ impl PeerConn {
 
             my_encrypt_algo: self.my_encrypt_algo.clone(),
             remote_encrypt_algo: msg1_pb.client_encryption_algorithm.clone(),
+            pq_hybrid: pq_kem_encaps.is_some(),
         })
     }
 
@@ -1227,6 +1558,8 @@ impl PeerConn {
 
             features: Vec::new(),
             network_secret_digest: noise.secret_digest.clone(),
+            ephemeral_pubkey: Vec::new(),
+            reject_reason: String::new(),
         }
     }
 
@@ -1264,15 +1597,34 @@ impl PeerConn {
             self.is_client = Some(false);
         } else if hdr.packet_type == PacketType::HandShake as u8 {
             let rsp = Self::decode_handshake_packet(&first_pkt)?;
-            handshake_recved(self, &rsp.network_name)?;
+            if let Err(e) = handshake_recved(self, &rsp.network_name) {
+                self.send_handshake_reject(&e).await;
+                return Err(e);
+            }
             tracing::info!("handshake request: {:?}", rsp);
             self.record_control_rx(&rsp.network_name, first_pkt.buf_len() as u64);
             self.info = Some(rsp);
             self.is_client = Some(false);
 
-            let send_digest = self.get_network_identity() == self.global_ctx.get_network_identity();
-            self.send_handshake(send_digest, &self.get_network_identity().network_name)
-                .await?;
+            let send_digest = self
+                .global_ctx
+                .is_accepted_network_identity(&self.get_network_identity());
+            // only answer the key agreement when the client offered it and shares our secret,
+            // otherwise the connection keeps using the network secret encryption only.
+            let link_algo = self.conn_rekey_algo().filter(|_| {
+                send_digest && !self.info.as_ref().unwrap().ephemeral_pubkey.is_empty()
+            });
+            let ephemeral = link_algo.as_ref().map(|_| Self::new_ephemeral_keypair());
+            self.send_handshake(
+                send_digest,
+                ephemeral.as_ref().map(|(_, pubkey)| pubkey),
+                &self.get_network_identity().network_name,
+            )
+            .await?;
+            if let (Some(algo), Some((secret, pubkey))) = (link_algo, ephemeral) {
+                let remote_pubkey = self.info.as_ref().unwrap().ephemeral_pubkey.clone();
+                self.setup_link_session(algo, secret, &pubkey, &remote_pubkey)?;
+            }
         } else {
             return Err(Error::WaitRespError(format!(
                 "unexpected packet type during handshake: {}",
@@ -1305,12 +1657,27 @@ impl PeerConn {
             self.is_client = Some(true);
         } else {
             let network = self.global_ctx.get_network_identity();
-            self.send_handshake(true, &network.network_name).await?;
+            let link_algo = self.conn_rekey_algo();
+            let ephemeral = link_algo.as_ref().map(|_| Self::new_ephemeral_keypair());
+            self.send_handshake(
+                true,
+                ephemeral.as_ref().map(|(_, pubkey)| pubkey),
+                &network.network_name,
+            )
+            .await?;
             tracing::info!("waiting for handshake request from server");
             let rsp = self.wait_handshake_loop().await?;
             tracing::info!("handshake response: {:?}", rsp);
+            let remote_pubkey = rsp.ephemeral_pubkey.clone();
             self.info = Some(rsp);
             self.is_client = Some(true);
+
+            // an empty pubkey means the server doesn't support or enable it
+            if let (Some(algo), Some((secret, pubkey))) = (link_algo, ephemeral)
+                && !remote_pubkey.is_empty()
+            {
+                self.setup_link_session(algo, secret, &pubkey, &remote_pubkey)?;
+            }
         }
 
         if self.get_peer_id() == self.my_peer_id {
@@ -1500,8 +1867,7 @@ impl PeerConn {
         };
 
         self.global_ctx
-            .get_secret_proof(&secret_proof.challenge)
-            .is_some_and(|mac| mac.verify_slice(&secret_proof.proof).is_ok())
+            .verify_secret_proof(&secret_proof.challenge, &secret_proof.proof)
     }
 
     pub(crate) fn matches_local_network_secret(&self) -> bool {
@@ -1509,12 +1875,16 @@ impl PeerConn {
             return true;
         }
 
-        let my_identity = self.global_ctx.get_network_identity();
         let peer_identity = self.get_network_identity();
-
-        !Self::network_secret_digest_is_empty(&my_identity)
-            && !Self::network_secret_digest_is_empty(&peer_identity)
-            && my_identity.network_secret_digest == peer_identity.network_secret_digest
+        !Self::network_secret_digest_is_empty(&peer_identity)
+            && self
+                .global_ctx
+                .get_accepted_network_identities()
+                .iter()
+                .any(|my_identity| {
+                    !Self::network_secret_digest_is_empty(my_identity)
+                        && my_identity.network_secret_digest == peer_identity.network_secret_digest
+                })
     }
 
     pub fn get_close_notifier(&self) -> Arc<PeerConnCloseNotify> {
@@ -1524,6 +1894,7 @@ impl PeerConn {
     pub fn get_stats(&self) -> PeerConnStats {
         PeerConnStats {
             latency_us: self.latency_stats.get_latency_us(),
+            jitter_us: self.latency_stats.get_jitter_us() as u64,
 
             tx_bytes: self.throughput.tx_bytes(),
             rx_bytes: self.throughput.rx_bytes(),
@@ -1566,6 +1937,22 @@ impl PeerConn {
                 .as_ref()
                 .map(|x| x.peer_identity_type as i32)
                 .unwrap_or(PeerIdentityType::Admin as i32),
+            pq_hybrid: self
+                .noise_handshake_result
+                .as_ref()
+                .map(|x| x.pq_hybrid)
+                .unwrap_or_default(),
+            conn_rekey: self.link_filter.get_session().is_some(),
+            rekey_count: self
+                .link_filter
+                .get_session()
+                .map(|x| x.rekey_count())
+                .or_else(|| {
+                    self.noise_handshake_result
+                        .as_ref()
+                        .map(|x| x.session.rekey_count())
+                })
+                .unwrap_or_default(),
         }
     }
 
@@ -1628,10 +2015,17 @@ pub mod tests {
                 enabled: true,
                 local_private_key: Some(BASE64_STANDARD.encode(private.as_bytes())),
                 local_public_key: Some(BASE64_STANDARD.encode(public.as_bytes())),
+                pq_hybrid: false,
             }));
         }
     }
 
+    pub fn set_pq_hybrid_cfg(global_ctx: &GlobalCtx, enabled: bool) {
+        let mut cfg = global_ctx.config.get_secure_mode().unwrap();
+        cfg.pq_hybrid = enabled;
+        global_ctx.config.set_secure_mode(Some(cfg));
+    }
+
     fn metric_value(global_ctx: &GlobalCtx, metric: MetricName, network_name: &str) -> u64 {
         global_ctx
             .stats_manager()
@@ -2005,6 +2399,145 @@ pub mod tests {
         );
     }
 
+    #[rstest::rstest]
+    #[tokio::test]
+    async fn peer_conn_secure_mode_pq_hybrid_interop(
+        #[values(true, false)] client_pq: bool,
+        #[values(true, false)] server_pq: bool,
+    ) {
+        let (c, s) = create_ring_tunnel_pair();
+
+        let c_peer_id = new_peer_id();
+        let s_peer_id = new_peer_id();
+
+        let c_ctx = get_mock_global_ctx();
+        let s_ctx = get_mock_global_ctx();
+        set_secure_mode_cfg(&c_ctx, true);
+        set_secure_mode_cfg(&s_ctx, true);
+        set_pq_hybrid_cfg(&c_ctx, client_pq);
+        set_pq_hybrid_cfg(&s_ctx, server_pq);
+
+        let ps = Arc::new(PeerSessionStore::new());
+        let mut c_peer = PeerConn::new(c_peer_id, c_ctx, Box::new(c), ps.clone());
+        let mut s_peer = PeerConn::new(s_peer_id, s_ctx, Box::new(s), ps.clone());
+
+        let (c_ret, s_ret) = tokio::join!(
+            c_peer.do_handshake_as_client(),
+            s_peer.do_handshake_as_server()
+        );
+        c_ret.unwrap();
+        s_ret.unwrap();
+
+        // hybrid only when both sides opt in, otherwise fall back to classic
+        let expect_pq = client_pq && server_pq;
+        assert_eq!(c_peer.get_conn_info().pq_hybrid, expect_pq);
+        assert_eq!(s_peer.get_conn_info().pq_hybrid, expect_pq);
+
+        let network_name = c_peer.get_network_identity().network_name;
+        let c_session = ps
+            .get(&SessionKey::new(network_name.clone(), s_peer_id))
+            .unwrap();
+        let s_session = ps.get(&SessionKey::new(network_name, c_peer_id)).unwrap();
+        assert_eq!(c_session.root_key(), s_session.root_key());
+
+        let (packet_send, mut packet_recv) = create_packet_recv_chan();
+        s_peer.start_recv_loop(packet_send).await;
+
+        let payload = b"pq-hybrid-data";
+        let mut pkt = ZCPacket::new_with_payload(payload);
+        pkt.fill_peer_manager_hdr(c_peer_id, s_peer_id, PacketType::Data as u8);
+        c_peer.send_msg(pkt).await.unwrap();
+
+        let got = timeout(Duration::from_secs(2), async move {
+            recv_packet_from_chan(&mut packet_recv).await
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(got.payload(), payload);
+    }
+
+    pub fn set_conn_rekey_cfg(global_ctx: &GlobalCtx, enabled: bool) {
+        let mut flags = global_ctx.get_flags();
+        flags.enable_conn_rekey = enabled;
+        global_ctx.set_flags(flags);
+    }
+
+    #[rstest::rstest]
+    #[tokio::test]
+    async fn peer_conn_rekey_interop(
+        #[values(true, false)] client_rekey: bool,
+        #[values(true, false)] server_rekey: bool,
+    ) {
+        let (c, s) = create_ring_tunnel_pair();
+
+        let c_recorder = Arc::new(PacketRecorderTunnelFilter::new());
+        let c = TunnelWithFilter::new(c, c_recorder.clone());
+
+        let c_peer_id = new_peer_id();
+        let s_peer_id = new_peer_id();
+
+        let c_ctx = get_mock_global_ctx();
+        let s_ctx = get_mock_global_ctx();
+        set_conn_rekey_cfg(&c_ctx, client_rekey);
+        set_conn_rekey_cfg(&s_ctx, server_rekey);
+
+        let ps = Arc::new(PeerSessionStore::new());
+        let mut c_peer = PeerConn::new(c_peer_id, c_ctx, Box::new(c), ps.clone());
+        let mut s_peer = PeerConn::new(s_peer_id, s_ctx, Box::new(s), ps.clone());
+
+        let (c_ret, s_ret) = tokio::join!(
+            c_peer.do_handshake_as_client(),
+            s_peer.do_handshake_as_server()
+        );
+        c_ret.unwrap();
+        s_ret.unwrap();
+
+        // per-connection keys only when both sides opt in, otherwise network secret only
+        let expect_rekey = client_rekey && server_rekey;
+        assert_eq!(c_peer.get_conn_info().conn_rekey, expect_rekey);
+        assert_eq!(s_peer.get_conn_info().conn_rekey, expect_rekey);
+        assert_eq!(c_peer.get_conn_info().rekey_count, 0);
+
+        let (packet_send, mut packet_recv) = create_packet_recv_chan();
+        s_peer.start_recv_loop(packet_send).await;
+
+        // the network secret layer is kept untouched under the link layer
+        let payload = b"conn-rekey-data";
+        let mut pkt = ZCPacket::new_with_payload(payload);
+        pkt.fill_peer_manager_hdr(c_peer_id, s_peer_id, PacketType::Data as u8);
+        pkt.mut_peer_manager_header().unwrap().set_encrypted(true);
+        c_peer.send_msg(pkt).await.unwrap();
+
+        let got = timeout(Duration::from_secs(2), async move {
+            recv_packet_from_chan(&mut packet_recv).await
+        })
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(got.payload(), payload);
+        let hdr = got.peer_manager_header().unwrap();
+        assert!(hdr.is_encrypted());
+        assert!(!hdr.is_link_encrypted());
+
+        let wire = c_recorder
+            .sent
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|p| {
+                p.peer_manager_header()
+                    .is_some_and(|h| h.packet_type == PacketType::Data as u8)
+            })
+            .unwrap()
+            .clone();
+        assert_eq!(
+            wire.peer_manager_header().unwrap().is_link_encrypted(),
+            expect_rekey
+        );
+        assert_eq!(wire.payload() != payload, expect_rekey);
+    }
+
     #[tokio::test]
     async fn peer_conn_secure_mode_network_secret_confirmed() {
         let (c, s) = create_ring_tunnel_pair();
@@ -2314,6 +2847,7 @@ pub mod tests {
             enabled: true,
             local_private_key: Some(BASE64_STANDARD.encode(private_key.as_bytes())),
             local_public_key: Some(BASE64_STANDARD.encode(public.as_bytes())),
+            pq_hybrid: false,
         }));
     }
 
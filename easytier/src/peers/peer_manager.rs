@@ -2,7 +2,7 @@ use anyhow::Context;
 use async_trait::async_trait;
 use cidr::{Ipv4Cidr, Ipv6Cidr};
 use dashmap::DashMap;
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::{
     fmt::Debug,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
@@ -31,6 +31,9 @@ use crate::{
     },
     peers::{
         PeerPacketFilter,
+        flow_log::{EXPORT_INTERVAL, FlowDirection, FlowLogger, FlowTracker, fill_hostnames},
+        l2_switch::{L2Switch, TapFrameAction, ethernet_ip_payload},
+        packet_capture::PacketCapture,
         peer_conn::PeerConn,
         peer_rpc::PeerRpcManagerTransport,
         peer_session::PeerSessionStore,
@@ -43,12 +46,12 @@ use crate::{
     },
     proto::{
         api::instance::{
-            self, ListGlobalForeignNetworkResponse,
+            self, CaptureDirection, ListGlobalForeignNetworkResponse,
             list_global_foreign_network_response::OneForeignNetwork,
         },
         peer_rpc::{
             ForeignNetworkRouteInfoEntry, ForeignNetworkRouteInfoKey, PeerIdentityType,
-            RouteForeignNetworkSummary,
+            RouteForeignNetworkSummary, RouteLinkMetric,
         },
     },
     tunnel::{
@@ -181,6 +184,9 @@ pub struct PeerManager {
 
     self_tx_counters: SelfTxCounters,
     traffic_metrics: Arc<TrafficMetricRecorder>,
+    packet_capture: Arc<PacketCapture>,
+    flow_tracker: Option<Arc<FlowTracker>>,
+    l2_switch: Option<Arc<L2Switch>>,
 
     peer_session_store: Arc<PeerSessionStore>,
     is_secure_mode_enabled: bool,
@@ -247,12 +253,12 @@ impl PeerManager {
 
         let encryptor = if global_ctx.get_flags().enable_encryption {
             // 只有在启用加密时才使用工厂函数选择算法
+            // the key follows the network secret, including during a secret rotation
             let algorithm = &global_ctx.get_flags().encryption_algorithm;
-            super::encrypt::create_encryptor(
+            Arc::new(super::encrypt::NetworkSecretEncryptor::new(
                 algorithm,
-                global_ctx.get_128_key(),
-                global_ctx.get_256_key(),
-            )
+                global_ctx.clone(),
+            ))
         } else {
             // disable_encryption = true 时使用 NullCipher
             Arc::new(NullCipher)
@@ -382,6 +388,15 @@ impl PeerManager {
             MetricName::TrafficControlPacketsRxByInstance,
             InstanceLabelKind::From,
         ));
+        let flow_tracker = global_ctx
+            .config
+            .get_flow_log_config()
+            .map(|config| Arc::new(FlowTracker::new(&config)));
+        let l2_switch = global_ctx
+            .get_flags()
+            .enable_tap
+            .then(|| Arc::new(L2Switch::new()));
+
         let route_algo_inst_for_metrics = route_algo_inst.clone();
         let traffic_metrics = Arc::new(TrafficMetricRecorder::new(
             my_peer_id,
@@ -441,6 +456,9 @@ impl PeerManager {
 
             self_tx_counters,
             traffic_metrics,
+            packet_capture: Arc::new(PacketCapture::new()),
+            flow_tracker,
+            l2_switch,
 
             peer_session_store,
             is_secure_mode_enabled,
@@ -593,7 +611,8 @@ impl PeerManager {
             // Credential node: only check network_name
             my_identity.network_name == peer_identity.network_name
         } else {
-            my_identity == peer_identity
+            // during a secret rotation peers may still be on the old or already on the next secret
+            self.global_ctx.is_accepted_network_identity(&peer_identity)
         };
 
         if !identity_ok {
@@ -749,6 +768,8 @@ impl PeerManager {
                 return Ok(());
             }
 
+            self.foreign_network_manager.check_relay_quota(network_name)?;
+
             let mut peer_id = self
                 .foreign_network_manager
                 .get_network_peer_id(network_name);
@@ -1006,6 +1027,8 @@ impl PeerManager {
         let compress_rx_bytes_after =
             stats_mgr.get_counter(MetricName::CompressionBytesRxAfter, label_set.clone());
         let traffic_metrics = self.traffic_metrics.clone();
+        let packet_capture = self.packet_capture.clone();
+        let flow_tracker = self.flow_tracker.clone();
 
         self.tasks.lock().await.spawn(async move {
             tracing::trace!("start_peer_recv");
@@ -1069,6 +1092,10 @@ impl PeerManager {
 
                     hdr.forward_counter += 1;
 
+                    if from_peer_id != my_peer_id && packet_capture.is_active() {
+                        packet_capture.capture(CaptureDirection::CaptureForward, &ret, false);
+                    }
+
                     if from_peer_id == my_peer_id {
                         compress_tx_bytes_before.add(buf_len as u64);
 
@@ -1160,15 +1187,24 @@ impl PeerManager {
 
                     compress_rx_bytes_after.add(ret.buf_len() as u64);
 
-                    if !acl_filter.process_packet_with_acl(
+                    let acl_allowed = acl_filter.process_packet_with_acl(
                         &ret,
                         true,
                         global_ctx.get_ipv4().map(|x| x.address()),
                         |dst| global_ctx.is_ip_local_ipv6(&dst),
                         &route,
-                    ) {
+                    );
+                    if packet_capture.is_active() {
+                        packet_capture.capture(CaptureDirection::CaptureRx, &ret, !acl_allowed);
+                    }
+                    if !acl_allowed {
                         continue;
                     }
+                    if let Some(flow_tracker) = &flow_tracker
+                        && packet_type == PacketType::Data as u8
+                    {
+                        flow_tracker.record(FlowDirection::Rx, from_peer_id, ret.payload());
+                    }
 
                     let mut processed = false;
                     let mut zc_packet = Some(ret);
@@ -1211,12 +1247,27 @@ impl PeerManager {
         // for tun/tap ip/eth packet.
         struct NicPacketProcessor {
             nic_channel: PacketRecvChan,
+            l2_switch: Option<Arc<L2Switch>>,
         }
         #[async_trait::async_trait]
         impl PeerPacketFilter for NicPacketProcessor {
             async fn try_process_packet_from_peer(&self, packet: ZCPacket) -> Option<ZCPacket> {
                 let hdr = packet.peer_manager_header().unwrap();
-                if hdr.packet_type == PacketType::Data as u8 && !hdr.is_not_send_to_tun() {
+                if hdr.packet_type == PacketType::Ethernet as u8 {
+                    // frames from peers are never forwarded to other peers
+                    let Some(l2_switch) = &self.l2_switch else {
+                        return None;
+                    };
+                    if hdr.is_encrypted()
+                        || hdr.is_compressed()
+                        || !l2_switch
+                            .process_frame_from_peer(hdr.from_peer_id.get(), packet.payload())
+                    {
+                        return None;
+                    }
+                    let _ = self.nic_channel.send(packet).await;
+                    None
+                } else if hdr.packet_type == PacketType::Data as u8 && !hdr.is_not_send_to_tun() {
                     if hdr.is_encrypted() || hdr.is_compressed() {
                         tracing::warn!(
                             from_peer_id = hdr.from_peer_id.get(),
@@ -1238,6 +1289,7 @@ impl PeerManager {
         }
         self.add_packet_process_pipeline(Box::new(NicPacketProcessor {
             nic_channel: self.nic_channel.clone(),
+            l2_switch: self.l2_switch.clone(),
         }))
         .await;
 
@@ -1353,6 +1405,38 @@ impl PeerManager {
                 }
                 ret
             }
+
+            async fn list_peer_link_metrics(&self) -> BTreeMap<PeerId, RouteLinkMetric> {
+                let mut ret = BTreeMap::new();
+                let Some(peer_map) = self.peers.upgrade() else {
+                    return ret;
+                };
+
+                for peer_id in peer_map.list_peers_with_conn().await {
+                    let Some(conns) = peer_map.list_peer_conns(peer_id).await else {
+                        continue;
+                    };
+                    // the lowest latency conn is the one carrying most traffic
+                    let Some((stats, loss_rate)) = conns
+                        .iter()
+                        .filter_map(|c| c.stats.map(|s| (s, c.loss_rate)))
+                        .min_by_key(|(s, _)| s.latency_us)
+                    else {
+                        continue;
+                    };
+                    ret.insert(
+                        peer_id,
+                        RouteLinkMetric {
+                            dst_peer_id: peer_id,
+                            latency_us: stats.latency_us.min(u32::MAX as u64) as u32,
+                            jitter_us: stats.jitter_us.min(u32::MAX as u64) as u32,
+                            loss_rate_bp: (loss_rate.clamp(0.0, 1.0) * 10000.0) as u32,
+                            ..Default::default()
+                        },
+                    );
+                }
+                ret
+            }
         }
 
         let my_peer_id = self.my_peer_id;
@@ -1405,6 +1489,10 @@ impl PeerManager {
         self.get_route().get_local_public_ipv6_info().await
     }
 
+    pub async fn get_secret_rotation_status(&self) -> instance::GetSecretRotationStatusResponse {
+        self.get_route().get_secret_rotation_status().await
+    }
+
     pub async fn dump_route(&self) -> String {
         self.get_route().dump().await
     }
@@ -1440,13 +1528,18 @@ impl PeerManager {
 
     async fn run_nic_packet_process_pipeline(&self, data: &mut ZCPacket) -> bool {
         // Enforce ACL for outbound (NIC-originated) packets. If ACL denies, stop processing.
-        if !self.global_ctx.get_acl_filter().process_packet_with_acl(
+        let acl_allowed = self.global_ctx.get_acl_filter().process_packet_with_acl(
             data,
             false,
             None,
             |_| false,
             &self.get_route(),
-        ) {
+        );
+        if self.packet_capture.is_active() {
+            self.packet_capture
+                .capture(CaptureDirection::CaptureTx, data, !acl_allowed);
+        }
+        if !acl_allowed {
             return false;
         }
 
@@ -1457,6 +1550,10 @@ impl PeerManager {
         true
     }
 
+    pub fn get_packet_capture(&self) -> &Arc<PacketCapture> {
+        &self.packet_capture
+    }
+
     pub async fn remove_nic_packet_process_pipeline(&self, id: String) -> Result<(), Error> {
         let mut pipelines = self.nic_packet_process_pipeline.write().await;
         if let Some(pos) = pipelines.iter().position(|x| x.id() == id) {
@@ -1693,6 +1790,157 @@ impl PeerManager {
         Ok(())
     }
 
+    fn record_tx_flow(&self, msg: &ZCPacket, dst_peer_id: PeerId) {
+        if let Some(flow_tracker) = &self.flow_tracker {
+            flow_tracker.record(FlowDirection::Tx, dst_peer_id, msg.payload());
+        }
+    }
+
+    pub fn get_l2_switch(&self) -> Option<&Arc<L2Switch>> {
+        self.l2_switch.as_ref()
+    }
+
+    // peer answering arp requests for `ip` with its synthetic mac. tap peers
+    // answer for their own virtual ip themselves.
+    async fn get_arp_proxy_peer(&self, l2_switch: &L2Switch, ip: Ipv4Addr) -> Option<PeerId> {
+        let route = self.get_route();
+        let peer_id = route.get_peer_id_by_ip(&IpAddr::V4(ip)).await?;
+        if !l2_switch.is_tap_peer(peer_id) {
+            return Some(peer_id);
+        }
+        let peer_ipv4 = route
+            .get_peer_info(peer_id)
+            .await
+            .and_then(|info| info.ipv4_addr)
+            .map(Ipv4Addr::from);
+        (peer_ipv4 != Some(ip)).then_some(peer_id)
+    }
+
+    /// Forward an ethernet frame read from the tap device. Frames addressed to
+    /// peers without tap mode are returned as ip packets, the caller routes
+    /// them like packets read from a tun device.
+    pub async fn send_tap_frame(&self, frame: ZCPacket) -> Option<ZCPacket> {
+        let l2_switch = self.l2_switch.as_ref()?;
+        let dst_peers = match l2_switch.process_frame_from_tap(frame.payload()) {
+            TapFrameAction::Drop => return None,
+            TapFrameAction::RouteIp => {
+                return ethernet_ip_payload(frame.payload()).map(ZCPacket::new_with_payload);
+            }
+            TapFrameAction::Unicast(peer_id) => vec![peer_id],
+            TapFrameAction::Flood => l2_switch.tap_peers().iter().copied().collect(),
+            TapFrameAction::ArpRequest(target) => {
+                match self.get_arp_proxy_peer(l2_switch, target).await {
+                    Some(peer_id) if peer_id == self.my_peer_id => return None,
+                    Some(peer_id) => {
+                        let reply = l2_switch.build_arp_reply(frame.payload(), peer_id)?;
+                        let mut reply = ZCPacket::new_with_payload(&reply);
+                        reply.fill_peer_manager_hdr(
+                            peer_id,
+                            self.my_peer_id,
+                            PacketType::Ethernet as u8,
+                        );
+                        let _ = self.nic_channel.send(reply).await;
+                        return None;
+                    }
+                    None => l2_switch.tap_peers().iter().copied().collect(),
+                }
+            }
+        };
+
+        if let Err(e) = self.send_ethernet_frame(frame, dst_peers).await {
+            tracing::trace!(?e, "send tap frame failed");
+        }
+        None
+    }
+
+    async fn send_ethernet_frame(
+        &self,
+        mut msg: ZCPacket,
+        dst_peers: Vec<PeerId>,
+    ) -> Result<(), Error> {
+        if dst_peers.is_empty() {
+            return Ok(());
+        }
+        msg.fill_peer_manager_hdr(self.my_peer_id, 0, PacketType::Ethernet as u8);
+        // nic pipelines (proxies etc.) only understand ip packets, only acl applies
+        let acl_allowed = self.global_ctx.get_acl_filter().process_packet_with_acl(
+            &msg,
+            false,
+            None,
+            |_| false,
+            &self.get_route(),
+        );
+        if !acl_allowed {
+            return Ok(());
+        }
+
+        self.self_tx_counters
+            .compress_tx_bytes_before
+            .add(msg.buf_len() as u64);
+        Self::try_compress_and_encrypt(
+            self.data_compress_algo,
+            &self.encryptor,
+            &mut msg,
+            self.is_secure_mode_enabled,
+        )
+        .await?;
+        self.self_tx_counters
+            .compress_tx_bytes_after
+            .add(msg.buf_len() as u64);
+        msg.mut_peer_manager_header()
+            .unwrap()
+            .set_latency_first(self.global_ctx.latency_first());
+
+        let mut errs: Vec<Error> = vec![];
+        let mut msg = Some(msg);
+        let total_dst_peers = dst_peers.len();
+        let should_mark_recent_traffic =
+            Self::should_mark_recent_traffic_for_fanout(total_dst_peers);
+        for (i, peer_id) in dst_peers.iter().enumerate() {
+            if should_mark_recent_traffic {
+                self.mark_recent_traffic(*peer_id);
+            }
+            if let Err(e) = self.check_p2p_only_before_send(*peer_id) {
+                errs.push(e);
+                continue;
+            }
+
+            let mut msg = if i == total_dst_peers - 1 {
+                msg.take().unwrap()
+            } else {
+                msg.clone().unwrap()
+            };
+            msg.mut_peer_manager_header()
+                .unwrap()
+                .to_peer_id
+                .set(*peer_id);
+
+            self.self_tx_counters
+                .self_tx_bytes
+                .add(msg.buf_len() as u64);
+            self.self_tx_counters.self_tx_packets.inc();
+
+            if let Err(e) = Self::send_msg_internal(
+                &self.peers,
+                &self.foreign_network_client,
+                &self.relay_peer_map,
+                Some(&self.traffic_metrics),
+                msg,
+                *peer_id,
+            )
+            .await
+            {
+                errs.push(e);
+            }
+        }
+
+        if errs.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow::anyhow!("send ethernet frame has error: {:?}", errs).into())
+        }
+    }
+
     pub async fn send_msg_by_ip(
         &self,
         mut msg: ZCPacket,
@@ -1715,6 +1963,7 @@ impl PeerManager {
         }
         let cur_to_peer_id = msg.peer_manager_header().unwrap().to_peer_id.into();
         if cur_to_peer_id != 0 {
+            self.record_tx_flow(&msg, cur_to_peer_id);
             self.mark_recent_traffic(cur_to_peer_id);
             return Self::send_msg_internal(
                 &self.peers,
@@ -1736,6 +1985,7 @@ impl PeerManager {
             tracing::info!("no peer id for ip: {}", ip_addr);
             return Ok(());
         }
+        self.record_tx_flow(&msg, dst_peers[0]);
 
         self.self_tx_counters
             .compress_tx_bytes_before
@@ -1920,6 +2170,59 @@ impl PeerManager {
         });
     }
 
+    async fn run_flow_log_routine(&self) -> Result<(), Error> {
+        let Some(flow_tracker) = self.flow_tracker.clone() else {
+            return Ok(());
+        };
+        let config = self
+            .global_ctx
+            .config
+            .get_flow_log_config()
+            .unwrap_or_default();
+        let mut logger = FlowLogger::new(&config, self.my_peer_id).await?;
+        let route = self.get_route();
+        self.tasks.lock().await.spawn(async move {
+            loop {
+                tokio::time::sleep(EXPORT_INTERVAL).await;
+                let mut records = flow_tracker.collect(false);
+                if records.is_empty() {
+                    continue;
+                }
+                let hostnames = route
+                    .list_routes()
+                    .await
+                    .into_iter()
+                    .map(|r| (r.peer_id, r.hostname))
+                    .collect::<HashMap<_, _>>();
+                fill_hostnames(&mut records, &hostnames);
+                logger.export(&records).await;
+            }
+        });
+        Ok(())
+    }
+
+    async fn run_l2_switch_routine(&self) {
+        let Some(l2_switch) = self.l2_switch.clone() else {
+            return;
+        };
+        let route = self.get_route();
+        let my_peer_id = self.my_peer_id;
+        self.tasks.lock().await.spawn(async move {
+            loop {
+                let tap_peers = route
+                    .list_routes()
+                    .await
+                    .into_iter()
+                    .filter(|r| r.peer_id != my_peer_id && r.feature_flag.is_some_and(|f| f.tap))
+                    .map(|r| r.peer_id)
+                    .collect();
+                l2_switch.set_tap_peers(tap_peers);
+                l2_switch.gc();
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+        });
+    }
+
     async fn run_foriegn_network(&self) {
         self.peer_rpc_tspt
             .foreign_peers
@@ -1946,6 +2249,8 @@ impl PeerManager {
         self.run_peer_session_gc_routine().await;
         self.run_credential_gc_routine().await;
         self.run_traffic_metrics_gc_routine().await;
+        self.run_flow_log_routine().await?;
+        self.run_l2_switch_routine().await;
 
         self.run_foriegn_network().await;
 
@@ -2006,7 +2311,6 @@ impl PeerManager {
                 .unwrap_or_default(),
             proxy_cidrs: self
                 .global_ctx
-                .config
                 .get_proxy_cidrs()
                 .into_iter()
                 .map(|x| match x.mapped_cidr {
@@ -2393,6 +2697,7 @@ mod tests {
             PacketType::QuicDst,
             PacketType::DataWithKcpSrcModified,
             PacketType::DataWithQuicSrcModified,
+            PacketType::Ethernet,
             PacketType::ForeignNetworkPacket,
         ] {
             assert!(PeerManager::is_relay_data_packet(packet_type as u8));
@@ -3499,6 +3804,7 @@ mod tests {
                 local_public_key: Some(
                     base64::engine::general_purpose::STANDARD.encode(public.as_bytes()),
                 ),
+                pq_hybrid: false,
             }));
         let credential = Arc::new(PeerManager::new(
             RouteAlgoType::None,
@@ -42,14 +42,18 @@ use crate::{
     peers::route_trait::{Route, RouteInterfaceBox},
     proto::{
         acl::GroupIdentity,
+        api::instance::{
+            GetSecretRotationStatusResponse, PeerSecretState, SecretRotationPeerStatus,
+            SecretRotationState,
+        },
         common::{Ipv4Inet, NatType, StunInfo},
         peer_rpc::{
             ForeignNetworkRouteInfoEntry, ForeignNetworkRouteInfoKey, OspfRouteRpc,
             OspfRouteRpcClientFactory, OspfRouteRpcServer, PeerGroupInfo, PeerIdVersion,
             PeerIdentityType, PublicIpv6AddrRpcServer, RouteForeignNetworkInfos,
-            RouteForeignNetworkSummary, RoutePeerInfo, RoutePeerInfos, SyncRouteInfoError,
-            SyncRouteInfoRequest, SyncRouteInfoResponse, TrustedCredentialPubkey,
-            TrustedCredentialPubkeyProof, route_foreign_network_infos,
+            RouteForeignNetworkSummary, RouteLinkMetric, RouteLinkMetrics, RoutePeerInfo,
+            RoutePeerInfos, SyncRouteInfoError, SyncRouteInfoRequest, SyncRouteInfoResponse,
+            TrustedCredentialPubkey, TrustedCredentialPubkeyProof, route_foreign_network_infos,
             route_foreign_network_summary, sync_route_info_request::ConnInfo,
         },
         rpc_types::{
@@ -62,11 +66,12 @@ use crate::{
 
 use super::{
     PeerPacketFilter,
-    graph_algo::dijkstra_with_first_hop,
+    graph_algo::{DijkstraResult, dijkstra_with_first_hop},
     peer_rpc::PeerRpcManager,
     public_ipv6::{
         PublicIpv6PeerRouteInfo, PublicIpv6RouteControl, PublicIpv6Service, PublicIpv6SyncTrigger,
     },
+    route_metric::{apply_link_metric_config, is_empty_metric, link_cost, merge_link_metrics},
     route_trait::{
         DefaultRouteCostCalculator, ForeignNetworkRouteInfoMap, NextHopPolicy, RouteCostCalculator,
         RouteCostCalculatorInterface,
@@ -83,6 +88,9 @@ static REMOVE_DEAD_PEER_INFO_AFTER: Duration = Duration::from_secs(3660);
 // the cost (latency between two peers) is i32, i32::MAX is large enough.
 static AVOID_RELAY_COST: usize = i32::MAX as usize;
 static FORCE_USE_CONN_LIST: AtomicBool = AtomicBool::new(false);
+static UPDATE_LINK_METRIC_PERIOD: Duration = Duration::from_secs(5);
+// a next hop is only replaced when the new path is at least this much cheaper.
+static ROUTE_HYSTERESIS_PERCENT: usize = 10;
 
 // if a peer is unreachable for `REMOVE_UNREACHABLE_PEER_INFO_AFTER` time, we can remove it because
 // 1. all the ospf sessions between two zone are already destroy, new created session will resend the peer info.
@@ -248,7 +256,6 @@ impl RoutePeerInfo {
             cost: 0,
             ipv4_addr: global_ctx.get_ipv4().map(|x| x.address().into()),
             proxy_cidrs: global_ctx
-                .config
                 .get_proxy_cidrs()
                 .iter()
                 .map(|x| x.mapped_cidr.unwrap_or(x.cidr))
@@ -296,10 +303,31 @@ impl RoutePeerInfo {
                 Vec::new()
             },
 
+            secret_rotation: Self::new_secret_rotation_info(global_ctx),
+
             ..Default::default()
         }
     }
 
+    fn new_secret_rotation_info(global_ctx: &ArcGlobalCtx) -> Option<SecretRotationInfo> {
+        let secret_digest = global_ctx.get_network_identity().network_secret_digest?;
+        let next_secret_digest = global_ctx
+            .get_next_network_identity()
+            .and_then(|identity| identity.network_secret_digest);
+        // the activation time comes from local config only, a learned one is not re-published
+        let activate_at = next_secret_digest
+            .and_then(|_| global_ctx.config.get_secret_rotation_config())
+            .and_then(|rotation| rotation.activate_at)
+            .unwrap_or_default();
+        Some(SecretRotationInfo {
+            secret_digest: secret_digest.to_vec(),
+            next_secret_digest: next_secret_digest
+                .map(|digest| digest.to_vec())
+                .unwrap_or_default(),
+            activate_at,
+        })
+    }
+
     /// Attempts to update the `new` RoutePeerInfo based on the `old` RoutePeerInfo.
     ///
     /// An update is triggered if any fields in `new` differ from `old`, or if the time since
@@ -369,6 +397,10 @@ impl From<RoutePeerInfo> for crate::proto::api::instance::Route {
             cost_latency_first: None,
             path_latency_latency_first: None,
 
+            // link metric to next hop is filled in by PeerRoute::list_routes.
+            next_hop_link_metric: None,
+            next_hop_link_cost: 0,
+
             ipv6_addr: val.ipv6_addr,
             public_ipv6_addr: val.ipv6_public_addr_lease,
             ipv6_public_addr_prefix: val.ipv6_public_addr_prefix,
@@ -397,6 +429,18 @@ impl RouteConnBitmap {
         }
         connected_peers
     }
+
+    fn get_link_metrics(&self, peer_id: PeerId) -> BTreeMap<PeerId, RouteLinkMetric> {
+        self.link_metrics
+            .iter()
+            .find(|x| x.peer_id == peer_id)
+            .map(|x| link_metrics_by_peer(&x.metrics))
+            .unwrap_or_default()
+    }
+}
+
+fn link_metrics_by_peer(metrics: &[RouteLinkMetric]) -> BTreeMap<PeerId, RouteLinkMetric> {
+    metrics.iter().map(|m| (m.dst_peer_id, *m)).collect()
 }
 
 type Error = SyncRouteInfoError;
@@ -404,6 +448,8 @@ type Error = SyncRouteInfoError;
 #[derive(Debug, Clone)]
 struct RouteConnInfo {
     connected_peers: BTreeSet<PeerId>,
+    // measured or configured metric of the links to connected peers
+    link_metrics: BTreeMap<PeerId, RouteLinkMetric>,
     version: AtomicVersion,
     last_update: SystemTime,
 }
@@ -412,6 +458,7 @@ impl Default for RouteConnInfo {
     fn default() -> Self {
         Self {
             connected_peers: BTreeSet::new(),
+            link_metrics: BTreeMap::new(),
             version: AtomicVersion::new(),
             last_update: SystemTime::now(),
         }
@@ -509,16 +556,21 @@ impl SyncedRouteInfo {
         raw_route_info: Option<&DynamicMessage>,
         proof_idx: usize,
         proof: &TrustedCredentialPubkeyProof,
-        network_secret: Option<&str>,
+        network_secrets: Option<&[&str]>,
     ) -> bool {
-        network_secret
-            .map(|secret| {
-                raw_route_info
-                    .and_then(|raw| raw_credential_bytes_from_route_info(raw, proof_idx))
-                    .map(|raw_credential_bytes| {
-                        proof.verify_credential_hmac_with_bytes(&raw_credential_bytes, secret)
-                    })
-                    .unwrap_or_else(|| proof.verify_credential_hmac(secret))
+        let raw_credential_bytes =
+            raw_route_info.and_then(|raw| raw_credential_bytes_from_route_info(raw, proof_idx));
+        network_secrets
+            .map(|secrets| {
+                // admins may sign with either side of a secret rotation
+                secrets.iter().any(|secret| {
+                    raw_credential_bytes
+                        .as_ref()
+                        .map(|raw_credential_bytes| {
+                            proof.verify_credential_hmac_with_bytes(raw_credential_bytes, secret)
+                        })
+                        .unwrap_or_else(|| proof.verify_credential_hmac(secret))
+                })
             })
             .unwrap_or(true)
     }
@@ -526,7 +578,7 @@ impl SyncedRouteInfo {
     fn collect_trusted_credentials(
         &self,
         peer_infos: &OrderedHashMap<PeerId, RoutePeerInfo>,
-        network_secret: Option<&str>,
+        network_secrets: Option<&[&str]>,
         now: i64,
     ) -> (
         HashMap<Vec<u8>, TrustedCredentialPubkey>,
@@ -556,8 +608,12 @@ impl SyncedRouteInfo {
             let raw_route_info = raw_route_info.as_deref();
 
             for (proof_idx, proof) in info.trusted_credential_pubkeys.iter().enumerate() {
-                if !self.credential_proof_is_valid(raw_route_info, proof_idx, proof, network_secret)
-                {
+                if !self.credential_proof_is_valid(
+                    raw_route_info,
+                    proof_idx,
+                    proof,
+                    network_secrets,
+                ) {
                     continue;
                 }
 
@@ -743,6 +799,14 @@ impl SyncedRouteInfo {
             .map(|x| x.connected_peers.iter().copied().collect())
     }
 
+    fn get_link_metrics(&self, peer_id: PeerId) -> BTreeMap<PeerId, RouteLinkMetric> {
+        self.conn_map
+            .read()
+            .get(&peer_id)
+            .map(|x| x.link_metrics.clone())
+            .unwrap_or_default()
+    }
+
     fn remove_peer(&self, peer_id: PeerId) {
         self.remove_peers([peer_id]);
     }
@@ -923,6 +987,7 @@ impl SyncedRouteInfo {
         &self,
         peer_id_version: &PeerIdVersion,
         connected_peers: BTreeSet<PeerId>,
+        link_metrics: BTreeMap<PeerId, RouteLinkMetric>,
     ) -> bool {
         let mut guard = self.conn_map.write();
         if guard
@@ -933,6 +998,7 @@ impl SyncedRouteInfo {
                 peer_id_version.peer_id,
                 RouteConnInfo {
                     connected_peers,
+                    link_metrics,
                     version: peer_id_version.version.into(),
                     last_update: SystemTime::now(),
                 },
@@ -950,8 +1016,10 @@ impl SyncedRouteInfo {
 
         for (peer_idx, peer_id_version) in conn_bitmap.peer_ids.iter().enumerate() {
             let connceted_peers = conn_bitmap.get_connected_peers(peer_idx);
+            let link_metrics = conn_bitmap.get_link_metrics(peer_id_version.peer_id);
             self.fill_empty_peer_info(&connceted_peers);
-            need_inc_version |= self.update_conn_info_one_peer(peer_id_version, connceted_peers);
+            need_inc_version |=
+                self.update_conn_info_one_peer(peer_id_version, connceted_peers, link_metrics);
         }
         if need_inc_version {
             self.version.inc();
@@ -968,8 +1036,11 @@ impl SyncedRouteInfo {
             let connected_peers: BTreeSet<PeerId> =
                 peer_conn_info.connected_peer_ids.iter().copied().collect();
 
+            let link_metrics = link_metrics_by_peer(&peer_conn_info.link_metrics);
+
             self.fill_empty_peer_info(&connected_peers);
-            need_inc_version |= self.update_conn_info_one_peer(&peer_id_version, connected_peers);
+            need_inc_version |=
+                self.update_conn_info_one_peer(&peer_id_version, connected_peers, link_metrics);
         }
         if need_inc_version {
             self.version.inc();
@@ -1070,11 +1141,22 @@ impl SyncedRouteInfo {
         let new_version = my_conn_info.map(|x| x.version.get()).unwrap_or(0) + 1;
 
         if my_conn_info.is_none_or(|old| old.connected_peers != connected_peers) {
+            // metrics of links that are gone are dropped, new links get theirs on next measure
+            let link_metrics = my_conn_info
+                .map(|x| {
+                    x.link_metrics
+                        .iter()
+                        .filter(|(peer_id, _)| connected_peers.contains(peer_id))
+                        .map(|(peer_id, metric)| (*peer_id, *metric))
+                        .collect()
+                })
+                .unwrap_or_default();
             let mut guard = RwLockUpgradableReadGuard::upgrade(guard);
             guard.insert(
                 my_peer_id,
                 RouteConnInfo {
                     connected_peers,
+                    link_metrics,
                     version: new_version.into(),
                     last_update: SystemTime::now(),
                 },
@@ -1086,6 +1168,35 @@ impl SyncedRouteInfo {
         }
     }
 
+    fn update_my_link_metrics(
+        &self,
+        my_peer_id: PeerId,
+        measured: BTreeMap<PeerId, RouteLinkMetric>,
+    ) -> bool {
+        let guard = self.conn_map.upgradable_read();
+        let Some(my_conn_info) = guard.get(&my_peer_id) else {
+            return false;
+        };
+        let Some(link_metrics) = merge_link_metrics(&my_conn_info.link_metrics, measured) else {
+            return false;
+        };
+
+        let connected_peers = my_conn_info.connected_peers.clone();
+        let new_version = my_conn_info.version.get() + 1;
+        let mut guard = RwLockUpgradableReadGuard::upgrade(guard);
+        guard.insert(
+            my_peer_id,
+            RouteConnInfo {
+                connected_peers,
+                link_metrics,
+                version: new_version.into(),
+                last_update: SystemTime::now(),
+            },
+        );
+        self.version.inc();
+        true
+    }
+
     fn update_my_foreign_network(
         &self,
         my_peer_id: PeerId,
@@ -1268,7 +1379,7 @@ impl SyncedRouteInfo {
     {
         let (untrusted_peers, global_trusted_keys, _) = self
             .verify_and_update_credential_trusts_with_active_peers_protecting(
-                network_secret,
+                network_secret.as_ref().map(std::slice::from_ref),
                 is_peer_active,
                 None,
             );
@@ -1277,7 +1388,7 @@ impl SyncedRouteInfo {
 
     fn verify_and_update_credential_trusts_with_active_peers_protecting<F>(
         &self,
-        network_secret: Option<&str>,
+        network_secrets: Option<&[&str]>,
         is_peer_active: F,
         protected_peer_id: Option<PeerId>,
     ) -> (
@@ -1295,7 +1406,7 @@ impl SyncedRouteInfo {
 
         let peer_infos = self.peer_infos.read();
         let (all_trusted, global_trusted_keys) =
-            self.collect_trusted_credentials(&peer_infos, network_secret, now);
+            self.collect_trusted_credentials(&peer_infos, network_secrets, now);
         let prev_trusted = self.replace_trusted_credential_pubkeys(&all_trusted);
         let (active_non_reusable_owners, mut duplicate_untrusted_peers) =
             self.collect_non_reusable_credential_owners(&peer_infos, &all_trusted, is_peer_active);
@@ -1474,13 +1585,18 @@ impl RouteTable {
 
             // if avoid relay, just set all outgoing edges to a large value: AVOID_RELAY_COST.
             let peer_avoid_relay_data = synced_info.get_avoid_relay_data(src_peer_id);
+            let link_metrics = synced_info.get_link_metrics(src_peer_id);
 
             for dst_peer_id in connected_peers.iter() {
                 let Some(dst_node_idx) = peer_id_to_node_index.get(dst_peer_id) else {
                     continue;
                 };
 
-                let mut cost = cost_calc.calculate_cost(src_peer_id, *dst_peer_id) as usize;
+                let mut cost = cost_calc.calculate_cost(src_peer_id, *dst_peer_id);
+                if let Some(metric) = link_metrics.get(dst_peer_id) {
+                    cost = link_cost(metric, cost);
+                }
+                let mut cost = cost as usize;
                 if peer_avoid_relay_data {
                     cost += AVOID_RELAY_COST;
                 }
@@ -1585,14 +1701,42 @@ impl RouteTable {
         }
         let (costs, next_hops) = dijkstra_with_first_hop(&graph, *start_node, |e| *e.weight());
 
+        let node_idx_map: HashMap<PeerId, NodeIndex> = graph
+            .node_references()
+            .map(|(idx, peer_id)| (*peer_id, idx))
+            .collect();
+        let mut prev_hop_dijkstra = HashMap::new();
+
         for (dst, (next_hop, path_len)) in next_hops.iter() {
+            let dst_peer_id = *graph.node_weight(*dst).unwrap();
+            let mut next_hop_peer_id = *graph.node_weight(*next_hop).unwrap();
+            let mut cost = *costs.get(dst).unwrap();
+            let mut path_len = *path_len;
+
+            if let Some(prev) = self.next_hop_map.get(&dst_peer_id).map(|x| *x)
+                && prev.version < version
+                && prev.next_hop_peer_id != next_hop_peer_id
+                && let Some(prev_hop) = node_idx_map.get(&prev.next_hop_peer_id)
+                && let Some((prev_cost, prev_path_len)) = Self::sticky_path_cost(
+                    graph,
+                    start_node,
+                    prev_hop,
+                    dst,
+                    cost,
+                    &mut prev_hop_dijkstra,
+                )
+            {
+                next_hop_peer_id = prev.next_hop_peer_id;
+                cost = prev_cost;
+                path_len = prev_path_len;
+            }
+
             let info = NextHopInfo {
-                next_hop_peer_id: *graph.node_weight(*next_hop).unwrap(),
-                path_latency: (*costs.get(dst).unwrap() % AVOID_RELAY_COST) as i32,
-                path_len: { *path_len },
+                next_hop_peer_id,
+                path_latency: (cost % AVOID_RELAY_COST) as i32,
+                path_len,
                 version,
             };
-            let dst_peer_id = *graph.node_weight(*dst).unwrap();
             self.next_hop_map
                 .entry(dst_peer_id)
                 .and_modify(|x| {
@@ -1606,6 +1750,38 @@ impl RouteTable {
         self.next_hop_map_version.set_if_larger(version);
     }
 
+    // cost and path len to `dst` via the previous next hop, if it should be kept.
+    // the previous hop is kept while it's still adjacent, strictly closer to dst
+    // than we are (so it can't route back through us) and within
+    // ROUTE_HYSTERESIS_PERCENT of the best path, this stops routes from flapping
+    // between two links with similar metrics.
+    fn sticky_path_cost(
+        graph: &PeerGraph,
+        start_node: &NodeIndex,
+        prev_hop: &NodeIndex,
+        dst: &NodeIndex,
+        best_cost: usize,
+        prev_hop_dijkstra: &mut HashMap<NodeIndex, DijkstraResult<usize, NodeIndex>>,
+    ) -> Option<(usize, usize)> {
+        let edge = graph.find_edge(*start_node, *prev_hop)?;
+        let edge_cost = *graph.edge_weight(edge)?;
+
+        let (costs, first_hops) = prev_hop_dijkstra
+            .entry(*prev_hop)
+            .or_insert_with(|| dijkstra_with_first_hop(graph, *prev_hop, |e| *e.weight()));
+        let remain_cost = *costs.get(dst)?;
+        let remain_len = first_hops.get(dst)?.1;
+        if remain_cost >= best_cost {
+            return None;
+        }
+
+        let cost = edge_cost.saturating_add(remain_cost);
+        if cost.saturating_mul(100) > best_cost.saturating_mul(100 + ROUTE_HYSTERESIS_PERCENT) {
+            return None;
+        }
+        Some((cost, remain_len + 1))
+    }
+
     fn build_from_synced_info<T: RouteCostCalculatorInterface>(
         &self,
         my_peer_id: PeerId,
@@ -2169,6 +2345,7 @@ struct PeerRouteServiceImpl {
     applied_interface_peers_generation: AtomicU64,
 
     last_update_my_foreign_network: AtomicCell<Option<std::time::Instant>>,
+    last_update_my_link_metrics: AtomicCell<Option<std::time::Instant>>,
 
     peer_info_last_update: AtomicCell<std::time::Instant>,
 }
@@ -2236,6 +2413,7 @@ impl PeerRouteServiceImpl {
             applied_interface_peers_generation: AtomicU64::new(0),
 
             last_update_my_foreign_network: AtomicCell::new(None),
+            last_update_my_link_metrics: AtomicCell::new(None),
 
             peer_info_last_update: AtomicCell::new(std::time::Instant::now()),
         }
@@ -2450,6 +2628,51 @@ impl PeerRouteServiceImpl {
             .update_my_foreign_network(self.my_peer_id, foreign_networks)
     }
 
+    async fn update_my_link_metrics(&self) -> bool {
+        let last_time = self.last_update_my_link_metrics.load();
+        if last_time.is_some_and(|t| t.elapsed() < UPDATE_LINK_METRIC_PERIOD) {
+            return false;
+        }
+
+        self.last_update_my_link_metrics
+            .store(Some(std::time::Instant::now()));
+
+        let measured = self
+            .interface
+            .lock()
+            .await
+            .as_ref()
+            .unwrap()
+            .list_peer_link_metrics()
+            .await;
+
+        let configs = self.global_ctx.config.get_link_metrics();
+        let connected_peers: BTreeSet<PeerId> = self
+            .synced_route_info
+            .get_connected_peers(self.my_peer_id)
+            .unwrap_or_default();
+        let mut link_metrics = BTreeMap::new();
+        {
+            let peer_infos = self.synced_route_info.peer_infos.read();
+            for peer_id in connected_peers {
+                let mut metric = measured.get(&peer_id).copied().unwrap_or(RouteLinkMetric {
+                    dst_peer_id: peer_id,
+                    ..Default::default()
+                });
+                let hostname = peer_infos
+                    .get(&peer_id)
+                    .and_then(|info| info.hostname.as_deref());
+                apply_link_metric_config(&mut metric, hostname, &configs);
+                if !is_empty_metric(&metric) {
+                    link_metrics.insert(peer_id, metric);
+                }
+            }
+        }
+
+        self.synced_route_info
+            .update_my_link_metrics(self.my_peer_id, link_metrics)
+    }
+
     fn update_route_table(&self) {
         self.cost_calculator
             .write()
@@ -2581,6 +2804,7 @@ impl PeerRouteServiceImpl {
                     version: *x.1,
                 })
                 .collect(),
+            link_metrics: Vec::new(),
         };
 
         let locked_conn_map = self.synced_route_info.conn_map.read();
@@ -2599,6 +2823,13 @@ impl PeerRouteServiceImpl {
                     conn_bitmap.bitmap[bit_idx / 8] |= 1 << (bit_idx % 8);
                 }
             }
+
+            if !connected.link_metrics.is_empty() {
+                conn_bitmap.link_metrics.push(RouteLinkMetrics {
+                    peer_id: peer_id_version.peer_id,
+                    metrics: connected.link_metrics.values().copied().collect(),
+                });
+            }
         }
         drop(locked_conn_map);
 
@@ -2682,9 +2913,11 @@ impl PeerRouteServiceImpl {
                     version: conn_info.version.get(),
                 }),
                 connected_peer_ids: conn_info.connected_peers.iter().copied().collect(),
+                link_metrics: conn_info.link_metrics.values().copied().collect(),
             });
             *estimated_size += std::mem::size_of::<PeerIdVersion>()
-                + conn_info.connected_peers.len() * std::mem::size_of::<PeerId>();
+                + conn_info.connected_peers.len() * std::mem::size_of::<PeerId>()
+                + conn_info.link_metrics.len() * std::mem::size_of::<RouteLinkMetric>();
         };
 
         for (peer_id, conn_info) in conn_map.iter().rev() {
@@ -2739,6 +2972,11 @@ impl PeerRouteServiceImpl {
         let cached_conn_map = self.cached_local_conn_map.lock().unwrap();
         cached_conn_map.bitmap.len()
             + (cached_conn_map.peer_ids.len() * std::mem::size_of::<PeerIdVersion>())
+            + cached_conn_map
+                .link_metrics
+                .iter()
+                .map(|x| x.metrics.len() * std::mem::size_of::<RouteLinkMetric>())
+                .sum::<usize>()
     }
 
     fn build_foreign_network_info(
@@ -2769,9 +3007,119 @@ impl PeerRouteServiceImpl {
         }
     }
 
+    /// Drive a staged network secret rotation: learn the activation time from admin
+    /// peers staging the same secret, switch once it is due, and drop the old secret
+    /// after its grace period.
+    fn update_secret_rotation(&self) {
+        self.global_ctx.expire_previous_network_identity();
+
+        let Some(next_digest) = self
+            .global_ctx
+            .get_next_network_identity()
+            .and_then(|identity| identity.network_secret_digest)
+        else {
+            return;
+        };
+
+        let (announced, next_in_use) = {
+            let peer_infos = self.synced_route_info.peer_infos.read();
+            let admin_rotations = peer_infos
+                .iter()
+                .filter(|(peer_id, info)| {
+                    **peer_id != self.my_peer_id && self.synced_route_info.is_admin_peer(info)
+                })
+                .filter_map(|(_, info)| info.secret_rotation.as_ref());
+            let mut announced = None::<u64>;
+            let mut next_in_use = false;
+            for rotation in admin_rotations {
+                if rotation.next_secret_digest == next_digest && rotation.activate_at != 0 {
+                    announced = Some(
+                        announced.map_or(rotation.activate_at, |at| at.min(rotation.activate_at)),
+                    );
+                }
+                next_in_use |= rotation.secret_digest == next_digest;
+            }
+            (announced, next_in_use)
+        };
+        // a node that missed the announcement follows admins that already switched
+        self.global_ctx
+            .set_announced_secret_activate_at(announced.or(next_in_use.then_some(0)));
+
+        let Some(activate_at) = self.global_ctx.get_secret_activate_at() else {
+            return;
+        };
+        let now = SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        if now < activate_at {
+            return;
+        }
+        match self.global_ctx.rotate_network_secret() {
+            Ok(true) => tracing::info!(activate_at, "switched to the next network secret"),
+            Ok(false) => {}
+            Err(e) => tracing::error!(?e, "failed to switch to the next network secret"),
+        }
+    }
+
+    fn get_secret_rotation_status(&self) -> GetSecretRotationStatusResponse {
+        let current_digest = self.global_ctx.get_network_identity().network_secret_digest;
+        let next_digest = self
+            .global_ctx
+            .get_next_network_identity()
+            .and_then(|identity| identity.network_secret_digest);
+        let activate_at = self.global_ctx.get_secret_activate_at();
+        let previous_accepted_until = self.global_ctx.previous_secret_accepted_until();
+
+        let state = match (next_digest, activate_at, previous_accepted_until) {
+            (Some(_), Some(_), _) => SecretRotationState::RotationScheduled,
+            (Some(_), None, _) => SecretRotationState::RotationStaged,
+            (None, _, Some(_)) => SecretRotationState::RotationSwitched,
+            (None, _, None) => SecretRotationState::RotationIdle,
+        };
+
+        // peers are compared against the secret this node ends up with
+        let target_digest = next_digest.or(current_digest).map(|digest| digest.to_vec());
+        let peers = self
+            .synced_route_info
+            .peer_infos
+            .read()
+            .iter()
+            .filter(|(peer_id, _)| **peer_id != self.my_peer_id)
+            .map(|(peer_id, info)| {
+                let rotation = info.secret_rotation.as_ref();
+                let peer_state = match (rotation, target_digest.as_ref()) {
+                    (Some(r), Some(target)) if r.secret_digest == *target => {
+                        PeerSecretState::PeerSecretActive
+                    }
+                    (Some(r), Some(target)) if r.next_secret_digest == *target => {
+                        PeerSecretState::PeerSecretStaged
+                    }
+                    (Some(_), Some(_)) => PeerSecretState::PeerSecretMissing,
+                    _ => PeerSecretState::PeerSecretUnknown,
+                };
+                SecretRotationPeerStatus {
+                    peer_id: *peer_id,
+                    hostname: info.hostname.clone().unwrap_or_default(),
+                    state: peer_state.into(),
+                    activate_at: rotation.map(|r| r.activate_at).unwrap_or_default(),
+                }
+            })
+            .collect();
+
+        GetSecretRotationStatusResponse {
+            state: state.into(),
+            activate_at: activate_at.unwrap_or_default(),
+            previous_accepted_until: previous_accepted_until.unwrap_or_default(),
+            generation: self.global_ctx.network_secret_generation(),
+            peers,
+        }
+    }
+
     async fn update_my_infos(&self) -> bool {
         let my_peer_info_updated = self.update_my_peer_info();
         let my_conn_info_updated = self.update_my_conn_info().await;
+        let my_link_metrics_updated = self.update_my_link_metrics().await;
         let my_foreign_network_updated = self.update_my_foreign_network().await;
         let mut untrusted_changed = false;
         if my_peer_info_updated || my_conn_info_updated {
@@ -2779,7 +3127,11 @@ impl PeerRouteServiceImpl {
         }
 
         let mut public_ipv6_state_updated = false;
-        if my_peer_info_updated || my_conn_info_updated || untrusted_changed {
+        if my_peer_info_updated
+            || my_conn_info_updated
+            || my_link_metrics_updated
+            || untrusted_changed
+        {
             self.update_route_table_and_cached_local_conn_bitmap();
             self.update_foreign_network_owner_map();
             public_ipv6_state_updated = self.notify_public_ipv6_route_change();
@@ -2789,6 +3141,7 @@ impl PeerRouteServiceImpl {
         }
         my_peer_info_updated
             || my_conn_info_updated
+            || my_link_metrics_updated
             || my_foreign_network_updated
             || public_ipv6_state_updated
     }
@@ -2830,12 +3183,17 @@ impl PeerRouteServiceImpl {
         my_peer_info_updated || !untrusted.is_empty() || public_ipv6_state_updated
     }
 
+    fn as_network_secrets(secrets: &[String]) -> Option<Vec<&str>> {
+        (!secrets.is_empty()).then(|| secrets.iter().map(String::as_str).collect())
+    }
+
     fn refresh_credential_trusts(&self) -> Vec<PeerId> {
         let network_identity = self.global_ctx.get_network_identity();
+        let accepted_secrets = self.global_ctx.get_accepted_network_secrets();
         let (untrusted, global_trusted_keys, _) = self
             .synced_route_info
             .verify_and_update_credential_trusts_with_active_peers_protecting(
-                network_identity.network_secret.as_deref(),
+                Self::as_network_secrets(&accepted_secrets).as_deref(),
                 |_| true,
                 Some(self.my_peer_id),
             );
@@ -2847,6 +3205,7 @@ impl PeerRouteServiceImpl {
 
     fn refresh_credential_trusts_with_current_topology(&self) -> Vec<PeerId> {
         let network_identity = self.global_ctx.get_network_identity();
+        let accepted_secrets = self.global_ctx.get_accepted_network_secrets();
 
         // Non-reusable credential owner election depends on reachability, so rebuild the
         // route table from the latest synced peer/conn state before checking active peers.
@@ -2855,7 +3214,7 @@ impl PeerRouteServiceImpl {
         let (untrusted, global_trusted_keys, suppressed_changed) = self
             .synced_route_info
             .verify_and_update_credential_trusts_with_active_peers_protecting(
-                network_identity.network_secret.as_deref(),
+                Self::as_network_secrets(&accepted_secrets).as_deref(),
                 |peer_id| {
                     peer_id == self.my_peer_id || self.route_table.topology_peer_reachable(peer_id)
                 },
@@ -3864,6 +4223,7 @@ impl PeerRoute {
         let mut global_event_receiver = service_impl.global_ctx.subscribe();
         service_impl.mark_interface_peers_dirty();
         loop {
+            service_impl.update_secret_rotation();
             if service_impl.update_my_infos().await {
                 session_mgr.sync_now("update_my_infos");
             }
@@ -4002,6 +4362,11 @@ impl Route for PeerRoute {
     async fn list_routes(&self) -> Vec<crate::proto::api::instance::Route> {
         let route_table = &self.service_impl.route_table;
         let route_table_with_cost = &self.service_impl.route_table_with_cost;
+        let my_link_metrics = self
+            .service_impl
+            .synced_route_info
+            .get_link_metrics(self.my_peer_id);
+        let cost_calc = self.service_impl.cost_calculator.read().unwrap();
         let mut routes = Vec::new();
         for item in route_table.peer_infos.iter() {
             if *item.key() == self.my_peer_id {
@@ -4021,6 +4386,15 @@ impl Route for PeerRoute {
             route.cost_latency_first = next_hop_peer_latency_first.map(|x| x.path_len as i32);
             route.path_latency_latency_first = next_hop_peer_latency_first.map(|x| x.path_latency);
 
+            if let Some(metric) = my_link_metrics.get(&next_hop_peer.next_hop_peer_id) {
+                let base_cost = cost_calc
+                    .as_ref()
+                    .map(|c| c.calculate_cost(self.my_peer_id, next_hop_peer.next_hop_peer_id))
+                    .unwrap_or(1);
+                route.next_hop_link_metric = Some(*metric);
+                route.next_hop_link_cost = link_cost(metric, base_cost);
+            }
+
             route.feature_flag = item.feature_flag;
 
             routes.push(route);
@@ -4097,6 +4471,10 @@ impl Route for PeerRoute {
         }
     }
 
+    async fn get_secret_rotation_status(&self) -> GetSecretRotationStatusResponse {
+        self.service_impl.get_secret_rotation_status()
+    }
+
     async fn get_peer_id_by_ipv4(&self, ipv4_addr: &Ipv4Addr) -> Option<PeerId> {
         let route_table = &self.service_impl.route_table;
         if let Some(p) = route_table.ipv4_peer_id_map.get(ipv4_addr) {
@@ -4242,7 +4620,7 @@ mod tests {
     use prost_wkt_types::Timestamp;
     use std::net::IpAddr;
     use std::{
-        collections::{BTreeSet, HashMap},
+        collections::{BTreeMap, BTreeSet, HashMap},
         sync::{
             Arc,
             atomic::{AtomicU32, Ordering},
@@ -4252,6 +4630,7 @@ mod tests {
 
     use super::{
         NextHopInfo, PeerRoute, REMOVE_DEAD_PEER_INFO_AFTER, RouteConnInfo, SyncRouteSession,
+        Version,
     };
     use crate::proto::common::TimestampExt;
     use crate::{
@@ -4268,7 +4647,10 @@ mod tests {
             create_packet_recv_chan,
             peer_manager::{PeerManager, RouteAlgoType},
             peer_ospf_route::{FORCE_USE_CONN_LIST, PeerIdVersion, PeerRouteServiceImpl},
-            route_trait::{NextHopPolicy, Route, RouteCostCalculatorInterface, RouteInterface},
+            route_trait::{
+                DefaultRouteCostCalculator, NextHopPolicy, Route, RouteCostCalculatorInterface,
+                RouteInterface,
+            },
             tests::{connect_peer_manager, create_mock_peer_manager, wait_route_appear},
         },
         proto::{
@@ -4276,8 +4658,8 @@ mod tests {
             common::{NatType, PeerFeatureFlag},
             peer_rpc::{
                 ForeignNetworkRouteInfoEntry, ForeignNetworkRouteInfoKey, PeerGroupInfo,
-                PeerIdentityType, RoutePeerInfo, RoutePeerInfos, SyncRouteInfoRequest,
-                TrustedCredentialPubkey, TrustedCredentialPubkeyProof,
+                PeerIdentityType, RouteLinkMetric, RoutePeerInfo, RoutePeerInfos,
+                SyncRouteInfoRequest, TrustedCredentialPubkey, TrustedCredentialPubkeyProof,
             },
         },
         tunnel::common::tests::wait_for_condition,
@@ -4550,6 +4932,7 @@ mod tests {
     {
         RouteConnInfo {
             connected_peers: connected_peers.into_iter().collect(),
+            link_metrics: BTreeMap::new(),
             version: 1.into(),
             last_update,
         }
@@ -5443,6 +5826,66 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn least_cost_next_hop_is_sticky_within_hysteresis() {
+        let service_impl = PeerRouteServiceImpl::new(1, get_mock_global_ctx());
+        let synced_info = &service_impl.synced_route_info;
+        let now = SystemTime::now();
+
+        {
+            let mut peer_infos = synced_info.peer_infos.write();
+            for peer_id in 1..=4 {
+                let mut info = RoutePeerInfo::new();
+                info.peer_id = peer_id;
+                info.version = 1;
+                peer_infos.insert(peer_id, info);
+            }
+        }
+
+        // 1 reaches 4 through either 2 or 3
+        let set_costs = |via_2: u32, via_3: u32, version: Version| {
+            let metric = |dst_peer_id, cost_override| RouteLinkMetric {
+                dst_peer_id,
+                cost_override,
+                ..Default::default()
+            };
+            let mut conn_map = synced_info.conn_map.write();
+            let mut self_conn = make_route_conn_info([2, 3], now);
+            self_conn.link_metrics = BTreeMap::from([(2, metric(2, via_2)), (3, metric(3, via_3))]);
+            conn_map.insert(1, self_conn);
+            for peer_id in [2, 3] {
+                let mut conn = make_route_conn_info([1, 4], now);
+                conn.link_metrics = BTreeMap::from([(4, metric(4, 10))]);
+                conn_map.insert(peer_id, conn);
+            }
+            conn_map.insert(4, make_route_conn_info([2, 3], now));
+            drop(conn_map);
+
+            synced_info.version.set(version);
+            service_impl.route_table_with_cost.build_from_synced_info(
+                1,
+                synced_info,
+                NextHopPolicy::LeastCost,
+                &DefaultRouteCostCalculator,
+            );
+            service_impl.route_table_with_cost.get_next_hop(4).unwrap()
+        };
+
+        let next_hop = set_costs(10, 11, 1);
+        assert_eq!(next_hop.next_hop_peer_id, 2);
+        assert_eq!(next_hop.path_latency, 20);
+
+        // via 3 is cheaper now, but not by enough to switch
+        let next_hop = set_costs(12, 11, 2);
+        assert_eq!(next_hop.next_hop_peer_id, 2);
+        assert_eq!(next_hop.path_latency, 22);
+        assert_eq!(next_hop.path_len, 2);
+
+        let next_hop = set_costs(30, 11, 3);
+        assert_eq!(next_hop.next_hop_peer_id, 3);
+        assert_eq!(next_hop.path_latency, 21);
+    }
+
     #[tokio::test]
     async fn credential_trust_refresh_does_not_remove_self_peer() {
         let my_peer_id = 11;
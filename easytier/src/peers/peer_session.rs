@@ -276,6 +276,10 @@ impl PeerSession {
         self.datagram.root_key()
     }
 
+    pub fn rekey_count(&self) -> u64 {
+        self.datagram.rekey_count()
+    }
+
     pub fn new_root_key() -> [u8; 32] {
         SecureDatagramSession::new_root_key()
     }
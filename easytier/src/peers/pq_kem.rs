@@ -0,0 +1,153 @@
+// ML-KEM-768 (FIPS 203) used to add a post-quantum share to the secure mode
+// noise handshakes. the noise key agreement stays X25519, the kem shared
+// secret is mixed into the session root key so that recording the handshake
+// and later breaking X25519 is not enough to recover traffic keys.
+
+use hmac::{Hmac, Mac as _};
+use ml_kem::{
+    Ciphertext, Encoded, EncodedSizeUser as _, KemCore, MlKem768,
+    kem::{Decapsulate as _, Encapsulate as _},
+};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+type EncapsulationKey = <MlKem768 as KemCore>::EncapsulationKey;
+
+pub(crate) const EK_LEN: usize = 1184;
+pub(crate) const CT_LEN: usize = 1088;
+pub(crate) const SS_LEN: usize = 32;
+
+const Q: u16 = 3329;
+
+pub(crate) struct DecapsulationKey(<MlKem768 as KemCore>::DecapsulationKey);
+
+impl std::fmt::Debug for DecapsulationKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DecapsulationKey").finish_non_exhaustive()
+    }
+}
+
+/// FIPS 203 encapsulation key input check: every 12 bit coefficient of the
+/// encoded vector must already be reduced mod q.
+fn check_ek_modulus(ek: &[u8]) -> bool {
+    ek[..EK_LEN - 32].chunks_exact(3).all(|b| {
+        let a = u16::from(b[0]) | (u16::from(b[1] & 0x0f) << 8);
+        let c = u16::from(b[1] >> 4) | (u16::from(b[2]) << 4);
+        a < Q && c < Q
+    })
+}
+
+/// Generate a fresh ML-KEM-768 key pair, returns the decapsulation key and the
+/// encoded encapsulation key.
+pub(crate) fn generate() -> (DecapsulationKey, Vec<u8>) {
+    let (dk, ek) = MlKem768::generate(&mut rand::rngs::OsRng);
+    (DecapsulationKey(dk), ek.as_bytes().to_vec())
+}
+
+/// Encapsulate to the peer's encapsulation key, returns the ciphertext and the
+/// shared secret.
+pub(crate) fn encapsulate(ek: &[u8]) -> anyhow::Result<(Vec<u8>, [u8; SS_LEN])> {
+    if ek.len() != EK_LEN {
+        return Err(anyhow::anyhow!("invalid ml-kem ek length: {}", ek.len()));
+    }
+    if !check_ek_modulus(ek) {
+        return Err(anyhow::anyhow!("invalid ml-kem ek encoding"));
+    }
+    let ek = Encoded::<EncapsulationKey>::try_from(ek)
+        .map_err(|_| anyhow::anyhow!("invalid ml-kem ek length: {}", ek.len()))?;
+    let (ct, ss) = EncapsulationKey::from_bytes(&ek)
+        .encapsulate(&mut rand::rngs::OsRng)
+        .map_err(|_| anyhow::anyhow!("ml-kem encapsulation failed"))?;
+
+    let mut out = [0u8; SS_LEN];
+    out.copy_from_slice(&ss);
+    Ok((ct.to_vec(), out))
+}
+
+impl DecapsulationKey {
+    /// A ciphertext that doesn't match falls back to the implicit rejection
+    /// secret instead of an error, as FIPS 203 requires.
+    pub(crate) fn decapsulate(&self, ct: &[u8]) -> anyhow::Result<[u8; SS_LEN]> {
+        let ct = Ciphertext::<MlKem768>::try_from(ct)
+            .map_err(|_| anyhow::anyhow!("invalid ml-kem ct length: {}", ct.len()))?;
+        let ss = self
+            .0
+            .decapsulate(&ct)
+            .map_err(|_| anyhow::anyhow!("ml-kem decapsulation failed"))?;
+
+        let mut out = [0u8; SS_LEN];
+        out.copy_from_slice(&ss);
+        Ok(out)
+    }
+}
+
+/// Mask for the session root key sent in noise msg2 when both sides negotiated
+/// the hybrid handshake. Bound to the handshake hash so a ciphertext cannot be
+/// replayed into another handshake.
+pub(crate) fn root_key_mask(shared_secret: &[u8; SS_LEN], handshake_hash: &[u8]) -> [u8; 32] {
+    let mut extract = HmacSha256::new_from_slice(handshake_hash).unwrap();
+    extract.update(shared_secret);
+    let prk = extract.finalize().into_bytes();
+
+    let mut expand = HmacSha256::new_from_slice(&prk).unwrap();
+    expand.update(b"et-pq-hybrid-root");
+    expand.update(&[1u8]);
+    let okm = expand.finalize().into_bytes();
+    let mut mask = [0u8; 32];
+    mask.copy_from_slice(&okm[..32]);
+    mask
+}
+
+pub(crate) fn xor_root_key(root_key: [u8; 32], mask: &[u8; 32]) -> [u8; 32] {
+    let mut out = root_key;
+    for (o, m) in out.iter_mut().zip(mask.iter()) {
+        *o ^= m;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encaps_decaps_roundtrip() {
+        let (dk, ek) = generate();
+        assert_eq!(ek.len(), EK_LEN);
+        let (ct, ss) = encapsulate(&ek).unwrap();
+        assert_eq!(ct.len(), CT_LEN);
+        assert_eq!(dk.decapsulate(&ct).unwrap(), ss);
+
+        // a tampered ciphertext falls back to the implicit rejection key
+        let mut bad = ct.clone();
+        bad[0] ^= 1;
+        let rejected = dk.decapsulate(&bad).unwrap();
+        assert_ne!(rejected, ss);
+        assert_eq!(dk.decapsulate(&bad).unwrap(), rejected);
+
+        assert!(encapsulate(&ek[..EK_LEN - 1]).is_err());
+        assert!(dk.decapsulate(&ct[..CT_LEN - 1]).is_err());
+    }
+
+    #[test]
+    fn unreduced_ek_is_rejected() {
+        let (_, mut ek) = generate();
+        // first coefficient = 0xfff >= q
+        ek[0] = 0xff;
+        ek[1] |= 0x0f;
+        assert!(encapsulate(&ek).is_err());
+    }
+
+    #[test]
+    fn root_key_mask_depends_on_handshake_hash() {
+        let ss = [7u8; SS_LEN];
+        let mask = root_key_mask(&ss, b"hash-a");
+        assert_eq!(mask, root_key_mask(&ss, b"hash-a"));
+        assert_ne!(mask, root_key_mask(&ss, b"hash-b"));
+        assert_eq!(
+            xor_root_key(xor_root_key([3u8; 32], &mask), &mask),
+            [3u8; 32]
+        );
+    }
+}
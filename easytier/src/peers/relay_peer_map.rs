@@ -12,6 +12,7 @@ use crate::{
     common::{PeerId, global_ctx::ArcGlobalCtx, shrink_dashmap},
     peers::peer_map::PeerMap,
     peers::peer_session::{PeerSession, PeerSessionAction, PeerSessionStore, SessionKey},
+    peers::pq_kem,
     peers::route_trait::NextHopPolicy,
     peers::traffic_metrics::AggregateTrafficMetrics,
     proto::peer_rpc::{PeerConnSessionActionPb, RelayNoiseMsg1Pb, RelayNoiseMsg2Pb},
@@ -19,6 +20,8 @@ use crate::{
 };
 
 const RELAY_NOISE_VERSION: u32 = 1;
+// relay msg1 version of initiators offering the hybrid X25519 + ML-KEM-768 handshake
+const RELAY_NOISE_PQ_HYBRID_VERSION: u32 = 2;
 const RELAY_NOISE_PROLOGUE: &[u8] = b"easytier-relay-noise";
 const HANDSHAKE_TIMEOUT_SECS: u64 = 5;
 const HANDSHAKE_RETRY_BASE_MS: u64 = 200;
@@ -54,6 +57,7 @@ pub struct RelayPeerMap {
     pub(crate) pending_packets: DashMap<PeerId, Vec<(ZCPacket, NextHopPolicy)>>,
 
     is_secure_mode_enabled: bool,
+    is_pq_hybrid_enabled: bool,
     control_metrics: AggregateTrafficMetrics,
 }
 
@@ -65,10 +69,11 @@ impl RelayPeerMap {
         my_peer_id: PeerId,
         peer_session_store: Arc<PeerSessionStore>,
     ) -> Arc<Self> {
-        let is_secure_mode_enabled = global_ctx
-            .config
-            .get_secure_mode()
-            .map(|cfg| cfg.enabled)
+        let secure_mode = global_ctx.config.get_secure_mode();
+        let is_secure_mode_enabled = secure_mode.as_ref().map(|cfg| cfg.enabled).unwrap_or(false);
+        let is_pq_hybrid_enabled = secure_mode
+            .as_ref()
+            .map(|cfg| cfg.enabled && cfg.pq_hybrid)
             .unwrap_or(false);
         Arc::new(Self {
             control_metrics: AggregateTrafficMetrics::control(
@@ -85,6 +90,7 @@ impl RelayPeerMap {
             handshake_locks: DashMap::new(),
             pending_packets: DashMap::new(),
             is_secure_mode_enabled,
+            is_pq_hybrid_enabled,
         })
     }
 
@@ -194,9 +200,11 @@ impl RelayPeerMap {
         self.send_via_next_hop(msg, dst_peer_id, policy).await
     }
 
-    fn buffer_pending_packet(&self, dst_peer_id: PeerId, pkt: ZCPacket, policy: NextHopPolicy) {
+    fn buffer_pending_packet(&self, dst_peer_id: PeerId, mut pkt: ZCPacket, policy: NextHopPolicy) {
         let mut entry = self.pending_packets.entry(dst_peer_id).or_default();
         if entry.len() < MAX_PENDING_PACKETS_PER_PEER {
+            // held until the relay handshake is done
+            pkt.detach_buf();
             entry.push((pkt, policy));
         }
         // silently drop when buffer is full
@@ -341,18 +349,25 @@ impl RelayPeerMap {
             .peer_session_store
             .get(&session_key)
             .map(|s| s.session_generation());
+        let pq_kem_key = self.is_pq_hybrid_enabled.then(pq_kem::generate);
         let a_conn_id = uuid::Uuid::new_v4();
         let msg1_pb = RelayNoiseMsg1Pb {
-            version: RELAY_NOISE_VERSION,
+            version: if pq_kem_key.is_some() {
+                RELAY_NOISE_PQ_HYBRID_VERSION
+            } else {
+                RELAY_NOISE_VERSION
+            },
             a_session_generation,
             a_conn_id: Some(a_conn_id.into()),
             client_encryption_algorithm: self.global_ctx.get_flags().encryption_algorithm.clone(),
+            pq_kem_ek: pq_kem_key.as_ref().map(|(_, ek)| ek.clone()),
         };
         let payload = msg1_pb.encode_to_vec();
         let mut out = vec![0u8; 4096];
         let out_len = hs
             .write_message(&payload, &mut out)
             .map_err(|e| Error::RouteError(Some(format!("noise write msg1 failed: {e:?}"))))?;
+        let msg1_handshake_hash = hs.get_handshake_hash().to_vec();
         let (tx, rx) = oneshot::channel();
         self.pending_handshakes.insert(dst_peer_id, tx);
 
@@ -410,7 +425,7 @@ impl RelayPeerMap {
         } else {
             None
         };
-        let root_key_bytes = msg2_pb
+        let mut root_key_bytes = msg2_pb
             .root_key_32
             .as_deref()
             .filter(|v| v.len() == 32)
@@ -419,6 +434,21 @@ impl RelayPeerMap {
                 key_bytes.copy_from_slice(v);
                 key_bytes
             });
+        match (msg2_pb.pq_kem_ct.as_deref(), pq_kem_key.as_ref()) {
+            (Some(ct), Some((dk, _))) => {
+                let ss = dk.decapsulate(ct).map_err(|e| {
+                    Error::RouteError(Some(format!("pq kem decapsulate failed: {e:?}")))
+                })?;
+                let mask = pq_kem::root_key_mask(&ss, &msg1_handshake_hash);
+                root_key_bytes = root_key_bytes.map(|k| pq_kem::xor_root_key(k, &mask));
+            }
+            (Some(_), None) => {
+                return Err(Error::RouteError(Some(
+                    "unexpected pq kem ct in relay msg2".to_string(),
+                )));
+            }
+            (None, _) => {}
+        }
         let algo = self.global_ctx.get_flags().encryption_algorithm.clone();
         let session = self
             .peer_session_store
@@ -563,6 +593,19 @@ impl RelayPeerMap {
             ))));
         }
 
+        let pq_kem_encaps = match msg1_pb.pq_kem_ek.as_deref() {
+            Some(ek)
+                if msg1_pb.version >= RELAY_NOISE_PQ_HYBRID_VERSION
+                    && self.is_pq_hybrid_enabled =>
+            {
+                let (ct, ss) = pq_kem::encapsulate(ek).map_err(|e| {
+                    Error::RouteError(Some(format!("pq kem encapsulate failed: {e:?}")))
+                })?;
+                Some((ct, pq_kem::root_key_mask(&ss, hs.get_handshake_hash())))
+            }
+            _ => None,
+        };
+
         let server_network_name = self.global_ctx.get_network_name();
         let algo = self.global_ctx.get_flags().encryption_algorithm.clone();
         let key = SessionKey::new(server_network_name.clone(), remote_peer_id);
@@ -583,11 +626,15 @@ impl RelayPeerMap {
                 PeerSessionAction::Create => PeerConnSessionActionPb::Create as i32,
             },
             b_session_generation: upsert.session_generation,
-            root_key_32: upsert.root_key.map(|k| k.to_vec()),
+            root_key_32: upsert.root_key.map(|k| match &pq_kem_encaps {
+                Some((_, mask)) => pq_kem::xor_root_key(k, mask).to_vec(),
+                None => k.to_vec(),
+            }),
             initial_epoch: upsert.initial_epoch,
             b_conn_id: Some(uuid::Uuid::new_v4().into()),
             a_conn_id_echo: msg1_pb.a_conn_id,
             server_encryption_algorithm: algo,
+            pq_kem_ct: pq_kem_encaps.map(|(ct, _)| ct),
         };
         let payload = msg2_pb.encode_to_vec();
         let mut out = vec![0u8; 4096];
@@ -0,0 +1,334 @@
+// quotas for foreign networks relayed by this node.
+//
+// admission limits (number of networks, peers per network) are checked when a
+// foreign peer connects, bandwidth and the monthly byte budget are applied to
+// relay data in the foreign network manager. relayed bytes are counted per
+// calendar month (utc) and optionally persisted so a restart doesn't reset the
+// budget.
+
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{common::config::RelayQuotaConfig, proto::api::instance::RelayQuotaUsagePb};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NetworkQuota {
+    pub priority: bool,
+    pub max_peers: Option<usize>,
+    pub bps_limit: Option<u64>,
+    pub monthly_bytes: Option<u64>,
+    pub over_budget_bps: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+pub struct NetworkRelayUsage {
+    quota: NetworkQuota,
+    relayed_bytes: AtomicU64,
+}
+
+impl NetworkRelayUsage {
+    pub fn new(quota: NetworkQuota) -> Self {
+        Self {
+            quota,
+            relayed_bytes: AtomicU64::new(0),
+        }
+    }
+
+    pub fn quota(&self) -> &NetworkQuota {
+        &self.quota
+    }
+
+    pub fn relayed_bytes(&self) -> u64 {
+        self.relayed_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn add_relayed_bytes(&self, bytes: u64) {
+        self.relayed_bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn over_budget(&self) -> bool {
+        self.quota
+            .monthly_bytes
+            .is_some_and(|budget| self.relayed_bytes() >= budget)
+    }
+
+    pub fn to_pb(&self, bps_limit: Option<u64>) -> RelayQuotaUsagePb {
+        RelayQuotaUsagePb {
+            relayed_bytes_this_month: self.relayed_bytes(),
+            monthly_bytes_limit: self.quota.monthly_bytes,
+            bps_limit,
+            max_peers: self.quota.max_peers.map(|v| v as u32),
+            priority: self.quota.priority,
+            over_budget: self.over_budget(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PersistedUsage {
+    month: String,
+    networks: BTreeMap<String, u64>,
+}
+
+fn current_month() -> String {
+    chrono::Utc::now().format("%Y-%m").to_string()
+}
+
+pub struct RelayQuotaManager {
+    config: RelayQuotaConfig,
+    month: Mutex<String>,
+    usage: DashMap<String, Arc<NetworkRelayUsage>>,
+}
+
+impl RelayQuotaManager {
+    pub fn new(config: RelayQuotaConfig) -> Self {
+        let ret = Self {
+            config,
+            month: Mutex::new(current_month()),
+            usage: DashMap::new(),
+        };
+        if let Some(path) = ret.config.usage_file.clone()
+            && let Err(e) = ret.load(&path)
+        {
+            tracing::warn!(?e, ?path, "failed to load relay quota usage");
+        }
+        ret
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config != RelayQuotaConfig::default()
+    }
+
+    pub fn network_quota(&self, network_name: &str) -> NetworkQuota {
+        let matched = self
+            .config
+            .network
+            .iter()
+            .flatten()
+            .find(|n| wildmatch::WildMatch::new(&n.name).matches(network_name));
+        NetworkQuota {
+            priority: matched.and_then(|n| n.priority).unwrap_or(false),
+            max_peers: matched
+                .and_then(|n| n.max_peers)
+                .or(self.config.max_peers_per_network),
+            bps_limit: matched
+                .and_then(|n| n.bps_limit)
+                .or(self.config.network_bps_limit),
+            monthly_bytes: matched
+                .and_then(|n| n.monthly_bytes)
+                .or(self.config.network_monthly_bytes),
+            over_budget_bps: self.config.over_budget_bps,
+        }
+    }
+
+    /// Usage counter of a network, shared by all entries of the same network in
+    /// the current month.
+    pub fn get_usage(&self, network_name: &str) -> Arc<NetworkRelayUsage> {
+        self.usage
+            .entry(network_name.to_string())
+            .or_insert_with(|| Arc::new(NetworkRelayUsage::new(self.network_quota(network_name))))
+            .clone()
+    }
+
+    /// Check whether a connection of `network_name` can be accepted, given the
+    /// number of foreign networks currently relayed.
+    pub fn check_network_admission(
+        &self,
+        network_name: &str,
+        network_exists: bool,
+        network_count: usize,
+    ) -> Result<(), anyhow::Error> {
+        let quota = self.network_quota(network_name);
+        if !network_exists
+            && !quota.priority
+            && let Some(max_networks) = self.config.max_networks
+            && network_count >= max_networks
+        {
+            return Err(anyhow::anyhow!(
+                "relay quota exceeded: this node already relays {} networks (max {}), network {} rejected",
+                network_count,
+                max_networks,
+                network_name
+            ));
+        }
+
+        if quota.over_budget_bps.is_none()
+            && let Some(usage) = self.usage.get(network_name)
+            && usage.over_budget()
+        {
+            return Err(anyhow::anyhow!(
+                "relay quota exceeded: network {} used up its monthly budget of {} bytes",
+                network_name,
+                usage.quota.monthly_bytes.unwrap_or_default()
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn check_peer_admission(
+        &self,
+        network_name: &str,
+        peer_count: usize,
+    ) -> Result<(), anyhow::Error> {
+        if let Some(max_peers) = self.network_quota(network_name).max_peers
+            && peer_count >= max_peers
+        {
+            return Err(anyhow::anyhow!(
+                "relay quota exceeded: network {} already has {} peers on this node (max {})",
+                network_name,
+                peer_count,
+                max_peers
+            ));
+        }
+        Ok(())
+    }
+
+    /// Reset the counters when a new month starts and drop counters nobody
+    /// uses anymore. Returns true if the month rolled over.
+    pub fn maintain(&self) -> bool {
+        let now = current_month();
+        let rolled = {
+            let mut month = self.month.lock().unwrap();
+            if *month != now {
+                *month = now;
+                true
+            } else {
+                false
+            }
+        };
+        if rolled {
+            for usage in self.usage.iter() {
+                usage.relayed_bytes.store(0, Ordering::Relaxed);
+            }
+        }
+        self.usage
+            .retain(|_, usage| Arc::strong_count(usage) > 1 || usage.relayed_bytes() > 0);
+        rolled
+    }
+
+    fn load(&self, path: &Path) -> anyhow::Result<()> {
+        if !path.exists() {
+            return Ok(());
+        }
+        let persisted: PersistedUsage = serde_json::from_slice(&std::fs::read(path)?)?;
+        if persisted.month != *self.month.lock().unwrap() {
+            return Ok(());
+        }
+        for (network_name, bytes) in persisted.networks {
+            self.get_usage(&network_name).add_relayed_bytes(bytes);
+        }
+        Ok(())
+    }
+
+    pub fn save(&self) -> anyhow::Result<()> {
+        let Some(path) = self.config.usage_file.as_ref() else {
+            return Ok(());
+        };
+        let persisted = PersistedUsage {
+            month: self.month.lock().unwrap().clone(),
+            networks: self
+                .usage
+                .iter()
+                .filter(|usage| usage.relayed_bytes() > 0)
+                .map(|usage| (usage.key().clone(), usage.relayed_bytes()))
+                .collect(),
+        };
+        let tmp_path = path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(&persisted)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::common::config::RelayNetworkQuotaConfig;
+
+    use super::*;
+
+    fn quota_config() -> RelayQuotaConfig {
+        RelayQuotaConfig {
+            max_networks: Some(1),
+            max_peers_per_network: Some(2),
+            network_monthly_bytes: Some(1000),
+            network: Some(vec![RelayNetworkQuotaConfig {
+                name: "paid-*".to_string(),
+                priority: Some(true),
+                max_peers: Some(10),
+                monthly_bytes: Some(1_000_000),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn network_override_wins_over_defaults() {
+        let mgr = RelayQuotaManager::new(quota_config());
+        let free = mgr.network_quota("free-net");
+        assert!(!free.priority);
+        assert_eq!(free.max_peers, Some(2));
+        assert_eq!(free.monthly_bytes, Some(1000));
+
+        let paid = mgr.network_quota("paid-acme");
+        assert!(paid.priority);
+        assert_eq!(paid.max_peers, Some(10));
+        assert_eq!(paid.monthly_bytes, Some(1_000_000));
+    }
+
+    #[test]
+    fn admission_respects_limits_and_priority() {
+        let mgr = RelayQuotaManager::new(quota_config());
+        mgr.check_network_admission("net1", false, 0).unwrap();
+        // existing networks are not affected by the network limit
+        mgr.check_network_admission("net1", true, 1).unwrap();
+        let err = mgr.check_network_admission("net2", false, 1).unwrap_err();
+        assert!(err.to_string().contains("relay quota exceeded"), "{}", err);
+        mgr.check_network_admission("paid-acme", false, 1).unwrap();
+
+        mgr.check_peer_admission("net1", 1).unwrap();
+        mgr.check_peer_admission("net1", 2).unwrap_err();
+        mgr.check_peer_admission("paid-acme", 2).unwrap();
+
+        mgr.get_usage("net1").add_relayed_bytes(1000);
+        assert!(mgr.get_usage("net1").over_budget());
+        mgr.check_network_admission("net1", true, 1).unwrap_err();
+    }
+
+    #[test]
+    fn usage_is_persisted_for_current_month() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = RelayQuotaConfig {
+            usage_file: Some(dir.path().join("relay_usage.json")),
+            ..quota_config()
+        };
+
+        let mgr = RelayQuotaManager::new(config.clone());
+        mgr.get_usage("net1").add_relayed_bytes(123);
+        mgr.get_usage("net2");
+        mgr.save().unwrap();
+
+        let mgr = RelayQuotaManager::new(config.clone());
+        assert_eq!(mgr.get_usage("net1").relayed_bytes(), 123);
+        assert_eq!(mgr.get_usage("net2").relayed_bytes(), 0);
+
+        // usage of another month is ignored
+        std::fs::write(
+            config.usage_file.as_ref().unwrap(),
+            r#"{"month":"2000-01","networks":{"net1":5}}"#,
+        )
+        .unwrap();
+        let mgr = RelayQuotaManager::new(config);
+        assert_eq!(mgr.get_usage("net1").relayed_bytes(), 0);
+    }
+}
@@ -0,0 +1,207 @@
+// composite link metric used by the ospf route.
+//
+// the base cost of a link still comes from the route cost calculator (latency
+// from peer center, or hop count), the advertised metric adds penalties for
+// jitter, loss and limited bandwidth on top of it, or replaces it entirely when
+// an administrator configured a cost for the link.
+
+use std::collections::BTreeMap;
+
+use crate::{
+    common::{PeerId, config::LinkMetricConfig},
+    proto::peer_rpc::RouteLinkMetric,
+};
+
+pub const MAX_LINK_COST: i32 = 1_000_000;
+
+// 1 ms of jitter costs as much as 2 ms of latency
+const JITTER_WEIGHT: i64 = 2;
+// each percent of loss scales the cost by 10% and adds 5 on top, so a lossy
+// link loses against a slightly slower clean one
+const LOSS_SCALE_PER_PERCENT: i64 = 10;
+const LOSS_COST_PER_PERCENT: i64 = 5;
+// like ospf, 1 Gbps costs 1 and 10 Mbps costs 100
+const REFERENCE_BANDWIDTH_KBPS: i64 = 1_000_000;
+
+// advertised metrics are only replaced when the cost moved by more than this,
+// so small measurement noise doesn't bump the conn info version.
+const METRIC_CHANGE_PERCENT: i64 = 20;
+const METRIC_CHANGE_MIN_DELTA: i64 = 5;
+
+/// Cost of a link with `base` from the cost calculator.
+pub fn link_cost(metric: &RouteLinkMetric, base: i32) -> i32 {
+    if metric.cost_override > 0 {
+        return (metric.cost_override as i64).min(MAX_LINK_COST as i64) as i32;
+    }
+
+    let loss_bp = metric.loss_rate_bp.min(10000) as i64;
+    let mut cost = base.max(1) as i64 + JITTER_WEIGHT * metric.jitter_us as i64 / 1000;
+    cost += cost * LOSS_SCALE_PER_PERCENT * loss_bp / 10000;
+    cost += LOSS_COST_PER_PERCENT * loss_bp / 100;
+    if metric.bandwidth_kbps > 0 {
+        cost += (REFERENCE_BANDWIDTH_KBPS / metric.bandwidth_kbps as i64).max(1);
+    }
+
+    cost.clamp(1, MAX_LINK_COST as i64) as i32
+}
+
+// cost with the measured latency as base, only used to compare two metrics.
+fn self_contained_cost(metric: &RouteLinkMetric) -> i64 {
+    link_cost(metric, (metric.latency_us / 1000) as i32) as i64
+}
+
+fn metric_changed(old: &RouteLinkMetric, new: &RouteLinkMetric) -> bool {
+    if old.cost_override != new.cost_override || old.bandwidth_kbps != new.bandwidth_kbps {
+        return true;
+    }
+    let (old_cost, new_cost) = (self_contained_cost(old), self_contained_cost(new));
+    let delta = (old_cost - new_cost).abs();
+    delta > METRIC_CHANGE_MIN_DELTA && delta * 100 > old_cost * METRIC_CHANGE_PERCENT
+}
+
+/// Merge freshly measured metrics into the advertised ones. Returns the new
+/// advertised map if anything changed enough to be worth a new version.
+pub fn merge_link_metrics(
+    advertised: &BTreeMap<PeerId, RouteLinkMetric>,
+    measured: BTreeMap<PeerId, RouteLinkMetric>,
+) -> Option<BTreeMap<PeerId, RouteLinkMetric>> {
+    let mut changed = advertised.len() != measured.len();
+    let merged = measured
+        .into_iter()
+        .map(|(peer_id, new)| match advertised.get(&peer_id) {
+            Some(old) if !metric_changed(old, &new) => (peer_id, *old),
+            _ => {
+                changed = true;
+                (peer_id, new)
+            }
+        })
+        .collect::<BTreeMap<_, _>>();
+
+    changed.then_some(merged)
+}
+
+/// Apply configured cost / bandwidth to a measured metric. An entry naming the
+/// peer id or hostname wins over `*`.
+pub fn apply_link_metric_config(
+    metric: &mut RouteLinkMetric,
+    hostname: Option<&str>,
+    configs: &[LinkMetricConfig],
+) {
+    let peer_id = metric.dst_peer_id.to_string();
+    let matched = configs
+        .iter()
+        .find(|c| c.peer == peer_id || Some(c.peer.as_str()) == hostname)
+        .or_else(|| configs.iter().find(|c| c.peer == "*"));
+    let Some(config) = matched else {
+        return;
+    };
+
+    if let Some(cost) = config.cost {
+        metric.cost_override = cost;
+    }
+    if let Some(bandwidth_mbps) = config.bandwidth_mbps {
+        metric.bandwidth_kbps = bandwidth_mbps.saturating_mul(1000);
+    }
+}
+
+pub fn is_empty_metric(metric: &RouteLinkMetric) -> bool {
+    metric.latency_us == 0
+        && metric.jitter_us == 0
+        && metric.loss_rate_bp == 0
+        && metric.bandwidth_kbps == 0
+        && metric.cost_override == 0
+}
+
+/// Short human readable form, e.g. `12.3ms ±1.2ms loss 2.0% 100Mbps`.
+pub fn describe_link_metric(metric: &RouteLinkMetric) -> String {
+    let mut s = format!(
+        "{:.1}ms ±{:.1}ms loss {:.1}%",
+        metric.latency_us as f64 / 1000.0,
+        metric.jitter_us as f64 / 1000.0,
+        metric.loss_rate_bp as f64 / 100.0
+    );
+    if metric.bandwidth_kbps > 0 {
+        s.push_str(&format!(" {}Mbps", metric.bandwidth_kbps / 1000));
+    }
+    if metric.cost_override > 0 {
+        s.push_str(&format!(" cost={}", metric.cost_override));
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metric(latency_ms: u32, jitter_ms: u32, loss_bp: u32) -> RouteLinkMetric {
+        RouteLinkMetric {
+            dst_peer_id: 1,
+            latency_us: latency_ms * 1000,
+            jitter_us: jitter_ms * 1000,
+            loss_rate_bp: loss_bp,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn lossy_link_costs_more_than_slower_clean_link() {
+        // 4g relay: 40ms, 5% loss vs wired: 60ms, no loss
+        let lossy = link_cost(&metric(40, 5, 500), 40);
+        let clean = link_cost(&metric(60, 1, 0), 60);
+        assert!(lossy > clean, "lossy {} clean {}", lossy, clean);
+
+        assert_eq!(link_cost(&metric(10, 0, 0), 10), 10);
+        assert_eq!(link_cost(&metric(0, 0, 0), 0), 1);
+
+        let mut slow = metric(10, 0, 0);
+        slow.bandwidth_kbps = 10_000;
+        assert_eq!(link_cost(&slow, 10), 110);
+
+        slow.cost_override = 7;
+        assert_eq!(link_cost(&slow, 10), 7);
+    }
+
+    #[test]
+    fn merge_ignores_small_changes() {
+        let advertised = BTreeMap::from([(1, metric(50, 2, 0))]);
+
+        let noisy = BTreeMap::from([(1, metric(53, 3, 0))]);
+        assert!(merge_link_metrics(&advertised, noisy).is_none());
+
+        let worse = BTreeMap::from([(1, metric(50, 2, 300))]);
+        let merged = merge_link_metrics(&advertised, worse).unwrap();
+        assert_eq!(merged[&1].loss_rate_bp, 300);
+
+        let new_peer = BTreeMap::from([(1, metric(53, 3, 0)), (2, metric(5, 0, 0))]);
+        let merged = merge_link_metrics(&advertised, new_peer).unwrap();
+        // unchanged link keeps the advertised value
+        assert_eq!(merged[&1].latency_us, 50_000);
+        assert_eq!(merged.len(), 2);
+    }
+
+    #[test]
+    fn config_by_peer_overrides_wildcard() {
+        let configs = vec![
+            LinkMetricConfig {
+                peer: "*".to_string(),
+                cost: None,
+                bandwidth_mbps: Some(100),
+            },
+            LinkMetricConfig {
+                peer: "relay-4g".to_string(),
+                cost: Some(500),
+                bandwidth_mbps: None,
+            },
+        ];
+
+        let mut m = metric(40, 0, 0);
+        apply_link_metric_config(&mut m, Some("relay-4g"), &configs);
+        assert_eq!(m.cost_override, 500);
+        assert_eq!(m.bandwidth_kbps, 0);
+
+        let mut m = metric(40, 0, 0);
+        apply_link_metric_config(&mut m, Some("office"), &configs);
+        assert_eq!(m.cost_override, 0);
+        assert_eq!(m.bandwidth_kbps, 100_000);
+    }
+}
@@ -2,7 +2,7 @@ use cidr::Ipv6Inet;
 use cidr::{Ipv4Cidr, Ipv6Cidr};
 use dashmap::DashMap;
 use std::{
-    collections::BTreeSet,
+    collections::{BTreeMap, BTreeSet},
     net::{Ipv4Addr, Ipv6Addr},
     sync::Arc,
 };
@@ -10,10 +10,10 @@ use std::{
 use crate::{
     common::{PeerId, global_ctx::NetworkIdentity},
     proto::{
-        api::instance::ListPublicIpv6InfoResponse,
+        api::instance::{GetSecretRotationStatusResponse, ListPublicIpv6InfoResponse},
         peer_rpc::{
             ForeignNetworkRouteInfoEntry, ForeignNetworkRouteInfoKey, PeerIdentityType,
-            RouteForeignNetworkInfos, RouteForeignNetworkSummary, RoutePeerInfo,
+            RouteForeignNetworkInfos, RouteForeignNetworkSummary, RouteLinkMetric, RoutePeerInfo,
         },
     },
 };
@@ -45,6 +45,10 @@ pub trait RouteInterface {
     async fn list_foreign_networks(&self) -> ForeignNetworkRouteInfoMap {
         DashMap::new()
     }
+    // measured latency / jitter / loss of the best conn to each direct peer
+    async fn list_peer_link_metrics(&self) -> BTreeMap<PeerId, RouteLinkMetric> {
+        BTreeMap::new()
+    }
 }
 
 pub type RouteInterfaceBox = Box<dyn RouteInterface + Send + Sync>;
@@ -113,6 +117,10 @@ pub trait Route {
         ListPublicIpv6InfoResponse::default()
     }
 
+    async fn get_secret_rotation_status(&self) -> GetSecretRotationStatusResponse {
+        GetSecretRotationStatusResponse::default()
+    }
+
     async fn get_peer_id_by_ipv4(&self, _ipv4: &Ipv4Addr) -> Option<PeerId> {
         None
     }
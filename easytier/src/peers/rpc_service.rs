@@ -1,22 +1,30 @@
 use std::{
     ops::Deref,
+    path::PathBuf,
     sync::{Arc, Weak},
     time::Duration,
 };
 
 use crate::{
+    common::stun::StunInfoCollectorTrait,
+    connector::diagnostics::{DiagnoseInput, diagnose_verdict},
+    peers::packet_capture::CaptureOptions,
     proto::{
         api::instance::{
-            AclManageRpc, CredentialManageRpc, DumpRouteRequest, DumpRouteResponse,
+            AclManageRpc, CredentialManageRpc, DiagnosePeerRequest, DiagnosePeerResponse,
+            DumpRouteRequest, DumpRouteResponse, FetchCaptureRequest, FetchCaptureResponse,
             GenerateCredentialRequest, GenerateCredentialResponse, GetAclStatsRequest,
             GetAclStatsResponse, GetForeignNetworkSummaryRequest, GetForeignNetworkSummaryResponse,
-            GetWhitelistRequest, GetWhitelistResponse, ListCredentialsRequest,
-            ListCredentialsResponse, ListForeignNetworkRequest, ListForeignNetworkResponse,
-            ListGlobalForeignNetworkRequest, ListGlobalForeignNetworkResponse, ListPeerRequest,
-            ListPeerResponse, ListPublicIpv6InfoRequest, ListPublicIpv6InfoResponse,
-            ListRouteRequest, ListRouteResponse, PeerInfo, PeerManageRpc, RevokeCredentialRequest,
+            GetSecretRotationStatusRequest, GetSecretRotationStatusResponse, GetWhitelistRequest,
+            GetWhitelistResponse, ListCredentialsRequest, ListCredentialsResponse,
+            ListForeignNetworkRequest, ListForeignNetworkResponse, ListGlobalForeignNetworkRequest,
+            ListGlobalForeignNetworkResponse, ListPeerRequest, ListPeerResponse,
+            ListPublicIpv6InfoRequest, ListPublicIpv6InfoResponse, ListRouteRequest,
+            ListRouteResponse, PeerInfo, PeerManageRpc, RevokeCredentialRequest,
             RevokeCredentialResponse, ShowNodeInfoRequest, ShowNodeInfoResponse,
+            StartCaptureRequest, StartCaptureResponse, StopCaptureRequest, StopCaptureResponse,
         },
+        peer_rpc::{DirectConnectorRpc, DirectConnectorRpcClientFactory, GetIpListRequest},
         rpc_types::{self, controller::BaseController},
     },
     utils::weak_upgrade,
@@ -176,6 +184,154 @@ impl PeerManageRpc for PeerManagerRpcService {
             node_info: Some(weak_upgrade(&self.peer_manager)?.get_my_info().await),
         })
     }
+
+    async fn diagnose_peer(
+        &self,
+        _: BaseController,
+        request: DiagnosePeerRequest,
+    ) -> Result<DiagnosePeerResponse, rpc_types::error::Error> {
+        let pm = weak_upgrade(&self.peer_manager)?;
+        let global_ctx = pm.get_global_ctx();
+
+        let peer = request.peer.trim();
+        let routes = pm.list_routes().await;
+        let route = routes
+            .iter()
+            .find(|r| {
+                r.peer_id.to_string() == peer
+                    || r.hostname == peer
+                    || r.ipv4_addr
+                        .and_then(|a| a.address)
+                        .is_some_and(|a| a.to_string() == peer)
+            })
+            .ok_or_else(|| {
+                rpc_types::error::Error::ExecutionError(anyhow::anyhow!(
+                    "peer {} not found in routes",
+                    peer
+                ))
+            })?;
+        let peer_id = route.peer_id;
+
+        let my_stun_info = global_ctx.get_stun_info_collector().get_stun_info();
+        let peer_stun_info = route.stun_info.clone().unwrap_or_default();
+
+        // ask the peer for its current candidates, the same way the direct connector does
+        let mut reply = DiagnosePeerResponse {
+            peer_id,
+            hostname: route.hostname.clone(),
+            directly_connected: pm.has_directly_connected_conn(peer_id),
+            next_hop_peer_id: route.next_hop_peer_id,
+            cost: route.cost,
+            ..Default::default()
+        };
+        let rpc_stub = pm
+            .get_peer_rpc_mgr()
+            .rpc_client()
+            .scoped_client::<DirectConnectorRpcClientFactory<BaseController>>(
+                pm.my_peer_id(),
+                peer_id,
+                global_ctx.get_network_name(),
+            );
+        match rpc_stub
+            .get_ip_list(
+                BaseController {
+                    // leave time for the cli request to return
+                    timeout_ms: 3000,
+                    ..Default::default()
+                },
+                GetIpListRequest {},
+            )
+            .await
+        {
+            Ok(ip_list) => {
+                let mut candidates: Vec<String> =
+                    ip_list.listeners.iter().map(ToString::to_string).collect();
+                candidates.extend(ip_list.public_ipv4.iter().map(ToString::to_string));
+                candidates.extend(ip_list.interface_ipv4s.iter().map(ToString::to_string));
+                candidates.extend(ip_list.public_ipv6.iter().map(ToString::to_string));
+                candidates.extend(ip_list.interface_ipv6s.iter().map(ToString::to_string));
+                reply.candidate_addresses = candidates;
+            }
+            Err(e) => {
+                reply.candidate_error = e.to_string();
+            }
+        }
+
+        let log = global_ctx.get_connect_attempt_log();
+        reply.attempts = log.get_attempts(peer_id);
+        reply.blocks = log.get_blocks(peer_id);
+
+        let (verdict, suggestions) = diagnose_verdict(
+            &global_ctx,
+            &DiagnoseInput {
+                directly_connected: reply.directly_connected,
+                my_udp_nat_type: my_stun_info.udp_nat_type(),
+                peer_udp_nat_type: peer_stun_info.udp_nat_type(),
+                peer_feature_flag: route.feature_flag.as_ref(),
+                has_candidates: reply.candidate_error.is_empty()
+                    && !reply.candidate_addresses.is_empty(),
+                attempts: &reply.attempts,
+                blocks: &reply.blocks,
+            },
+        );
+        reply.verdict = verdict;
+        reply.suggestions = suggestions;
+        reply.my_stun_info = Some(my_stun_info);
+        reply.peer_stun_info = Some(peer_stun_info);
+
+        Ok(reply)
+    }
+
+    async fn start_capture(
+        &self,
+        _: BaseController,
+        request: StartCaptureRequest,
+    ) -> Result<StartCaptureResponse, rpc_types::error::Error> {
+        let pm = weak_upgrade(&self.peer_manager)?;
+        let mut options = CaptureOptions {
+            filter: request.filter.parse()?,
+            max_packets: request.max_packets as u64,
+            output_file: request.output_file.map(PathBuf::from),
+            ..Default::default()
+        };
+        if request.buffer_bytes != 0 {
+            options.buffer_bytes = request.buffer_bytes as usize;
+        }
+        if request.snaplen != 0 {
+            options.snaplen = request.snaplen;
+        }
+        let session_id = pm.get_packet_capture().start(options).await?;
+        Ok(StartCaptureResponse { session_id })
+    }
+
+    async fn fetch_capture(
+        &self,
+        _: BaseController,
+        request: FetchCaptureRequest,
+    ) -> Result<FetchCaptureResponse, rpc_types::error::Error> {
+        let pm = weak_upgrade(&self.peer_manager)?;
+        Ok(pm.get_packet_capture().fetch(request.session_id)?)
+    }
+
+    async fn stop_capture(
+        &self,
+        _: BaseController,
+        request: StopCaptureRequest,
+    ) -> Result<StopCaptureResponse, rpc_types::error::Error> {
+        let pm = weak_upgrade(&self.peer_manager)?;
+        let (captured, dropped) = pm.get_packet_capture().stop(request.session_id)?;
+        Ok(StopCaptureResponse { captured, dropped })
+    }
+
+    async fn get_secret_rotation_status(
+        &self,
+        _: BaseController,
+        _request: GetSecretRotationStatusRequest,
+    ) -> Result<GetSecretRotationStatusResponse, rpc_types::error::Error> {
+        Ok(weak_upgrade(&self.peer_manager)?
+            .get_secret_rotation_status()
+            .await)
+    }
 }
 
 #[async_trait::async_trait]
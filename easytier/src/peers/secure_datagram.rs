@@ -212,6 +212,10 @@ pub struct SecureDatagramSession {
     send_seq: [AtomicU64; 2],
     send_epoch_started_ms: AtomicU64,
     send_packets_since_epoch: AtomicU64,
+    send_bytes_since_epoch: AtomicU64,
+    // only set for sessions that also rotate on the amount of data sent
+    rotate_after_bytes: Option<u64>,
+    rekey_count: AtomicU64,
 
     rx_slots: Mutex<[[EpochRxSlot; 2]; 2]>,
     key_cache: Mutex<[[EpochKeySlot; 2]; 2]>,
@@ -234,6 +238,9 @@ impl std::fmt::Debug for SecureDatagramSession {
             .field("send_seq", &self.send_seq)
             .field("send_epoch_started_ms", &self.send_epoch_started_ms)
             .field("send_packets_since_epoch", &self.send_packets_since_epoch)
+            .field("send_bytes_since_epoch", &self.send_bytes_since_epoch)
+            .field("rotate_after_bytes", &self.rotate_after_bytes)
+            .field("rekey_count", &self.rekey_count)
             .field("rx_slots", &self.rx_slots)
             .field("key_cache", &self.key_cache)
             .field("sync_rx_grace", &self.sync_rx_grace)
@@ -251,6 +258,8 @@ impl SecureDatagramSession {
     const EVICT_IDLE_AFTER_MS: u64 = 30_000;
     pub(crate) const SYNC_RX_GRACE_AFTER_MS: u64 = 5_000;
     const ROTATE_AFTER_PACKETS: u64 = 1_000_000;
+    /// Byte budget of a send epoch for the per-connection link sessions.
+    pub(crate) const LINK_ROTATE_AFTER_BYTES: u64 = 4 << 30;
     const ROTATE_AFTER_MS: u64 = 10 * 60 * 1000;
     const MAX_ACCEPTED_RX_EPOCH_AHEAD: u32 = 3;
     const DECRYPT_FAIL_THRESHOLD: u32 = 10;
@@ -278,6 +287,9 @@ impl SecureDatagramSession {
             send_seq: [AtomicU64::new(0), AtomicU64::new(0)],
             send_epoch_started_ms: AtomicU64::new(now_ms),
             send_packets_since_epoch: AtomicU64::new(0),
+            send_bytes_since_epoch: AtomicU64::new(0),
+            rotate_after_bytes: None,
+            rekey_count: AtomicU64::new(0),
             rx_slots: Mutex::new(rx_slots),
             key_cache: Mutex::new(key_cache),
             sync_rx_grace: Mutex::new(SyncRxGrace::default()),
@@ -289,6 +301,12 @@ impl SecureDatagramSession {
         }
     }
 
+    /// Also rotate the send epoch once `bytes` of payload were sent in it.
+    pub fn with_rotate_after_bytes(mut self, bytes: u64) -> Self {
+        self.rotate_after_bytes = Some(bytes);
+        self
+    }
+
     pub fn invalidate(&self) {
         self.invalidated.store(true, Ordering::Relaxed);
     }
@@ -363,6 +381,7 @@ impl SecureDatagramSession {
         self.send_epoch_started_ms
             .store(now_ms(), Ordering::Relaxed);
         self.send_packets_since_epoch.store(0, Ordering::Relaxed);
+        self.send_bytes_since_epoch.store(0, Ordering::Relaxed);
 
         {
             let mut rx = self.rx_slots.lock().unwrap();
@@ -446,13 +465,20 @@ impl SecureDatagramSession {
         ret
     }
 
-    fn maybe_rotate_epoch(&self, now_ms: u64) {
+    fn maybe_rotate_epoch(&self, now_ms: u64, bytes: u64) {
         let packets = self
             .send_packets_since_epoch
             .fetch_add(1, Ordering::Relaxed)
             + 1;
+        let bytes_exhausted = self.rotate_after_bytes.is_some_and(|limit| {
+            self.send_bytes_since_epoch
+                .fetch_add(bytes, Ordering::Relaxed)
+                + bytes
+                >= limit
+        });
         let started = self.send_epoch_started_ms.load(Ordering::Relaxed);
         if packets < Self::ROTATE_AFTER_PACKETS
+            && !bytes_exhausted
             && now_ms.saturating_sub(started) < Self::ROTATE_AFTER_MS
         {
             return;
@@ -467,12 +493,19 @@ impl SecureDatagramSession {
         {
             self.send_epoch_started_ms.store(now_ms, Ordering::Relaxed);
             self.send_packets_since_epoch.store(0, Ordering::Relaxed);
+            self.send_bytes_since_epoch.store(0, Ordering::Relaxed);
+            self.rekey_count.fetch_add(1, Ordering::Relaxed);
         }
     }
 
-    fn next_nonce(&self, dir: SecureDatagramDirection) -> (u32, u64, [u8; 12]) {
+    /// Number of send epoch rotations since the session was created.
+    pub fn rekey_count(&self) -> u64 {
+        self.rekey_count.load(Ordering::Relaxed)
+    }
+
+    fn next_nonce(&self, dir: SecureDatagramDirection, bytes: u64) -> (u32, u64, [u8; 12]) {
         let now_ms = now_ms();
-        self.maybe_rotate_epoch(now_ms);
+        self.maybe_rotate_epoch(now_ms, bytes);
         let epoch = self.send_epoch.load(Ordering::Relaxed);
         let seq = self.send_seq[dir.idx()].fetch_add(1, Ordering::Relaxed);
         let mut nonce = [0u8; 12];
@@ -709,7 +742,7 @@ impl SecureDatagramSession {
         if !self.is_valid() {
             return Err(anyhow!("session invalidated"));
         }
-        let (epoch, _seq, nonce_bytes) = self.next_nonce(dir);
+        let (epoch, _seq, nonce_bytes) = self.next_nonce(dir, pkt.payload().len() as u64);
         let encryptor = self.get_or_create_encryptor(epoch, dir, self.session_generation(), true);
         if let Err(e) = encryptor.encrypt_with_nonce(pkt, Some(nonce_bytes.as_slice())) {
             tracing::warn!(?e, "secure datagram session encrypt failed, invalidating");
@@ -1017,4 +1050,74 @@ mod tests {
         assert!(s.check_replay_for_test(2, 0, SecureDatagramDirection::AToB, now + 2));
         assert!(!s.check_replay_for_test(1, 1, SecureDatagramDirection::AToB, now + 3));
     }
+
+    #[test]
+    fn byte_budget_rotates_epoch_and_keeps_previous_epoch_decryptable() {
+        let root_key = SecureDatagramSession::new_root_key();
+        let tx = SecureDatagramSession::new(
+            root_key,
+            1,
+            0,
+            "aes-256-gcm".to_string(),
+            "aes-256-gcm".to_string(),
+        )
+        .with_rotate_after_bytes(SecureDatagramSession::LINK_ROTATE_AFTER_BYTES);
+        let rx = SecureDatagramSession::new(
+            root_key,
+            1,
+            0,
+            "aes-256-gcm".to_string(),
+            "aes-256-gcm".to_string(),
+        );
+
+        let mut old_pkt = ZCPacket::new_with_payload(b"before rotation");
+        old_pkt.fill_peer_manager_hdr(10, 20, PacketType::Data as u8);
+        tx.encrypt_payload(SecureDatagramDirection::AToB, &mut old_pkt)
+            .unwrap();
+        assert_eq!(tx.rekey_count(), 0);
+
+        tx.maybe_rotate_epoch(now_ms(), SecureDatagramSession::LINK_ROTATE_AFTER_BYTES);
+        assert_eq!(tx.rekey_count(), 1);
+        assert_eq!(tx.send_epoch.load(Ordering::Relaxed), 1);
+
+        let mut new_pkt = ZCPacket::new_with_payload(b"after rotation");
+        new_pkt.fill_peer_manager_hdr(10, 20, PacketType::Data as u8);
+        tx.encrypt_payload(SecureDatagramDirection::AToB, &mut new_pkt)
+            .unwrap();
+        assert_eq!(tx.rekey_count(), 1);
+
+        rx.decrypt_payload(SecureDatagramDirection::AToB, &mut new_pkt)
+            .unwrap();
+        assert_eq!(new_pkt.payload(), b"after rotation");
+        rx.decrypt_payload(SecureDatagramDirection::AToB, &mut old_pkt)
+            .unwrap();
+        assert_eq!(old_pkt.payload(), b"before rotation");
+    }
+
+    #[test]
+    fn session_without_byte_budget_rotates_on_packets_and_time_only() {
+        let s = SecureDatagramSession::new(
+            SecureDatagramSession::new_root_key(),
+            1,
+            0,
+            "aes-256-gcm".to_string(),
+            "aes-256-gcm".to_string(),
+        );
+        let now = now_ms();
+
+        s.maybe_rotate_epoch(now, SecureDatagramSession::LINK_ROTATE_AFTER_BYTES * 4);
+        assert_eq!(s.rekey_count(), 0);
+        assert_eq!(s.send_epoch.load(Ordering::Relaxed), 0);
+
+        s.maybe_rotate_epoch(now + SecureDatagramSession::ROTATE_AFTER_MS, 0);
+        assert_eq!(s.rekey_count(), 1);
+        assert_eq!(s.send_epoch.load(Ordering::Relaxed), 1);
+
+        s.send_packets_since_epoch.store(
+            SecureDatagramSession::ROTATE_AFTER_PACKETS - 1,
+            Ordering::Relaxed,
+        );
+        s.maybe_rotate_epoch(now + SecureDatagramSession::ROTATE_AFTER_MS, 0);
+        assert_eq!(s.send_epoch.load(Ordering::Relaxed), 2);
+    }
 }
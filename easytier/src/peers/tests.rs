@@ -23,7 +23,7 @@ use crate::{
 
 use super::{
     create_packet_recv_chan,
-    peer_conn::tests::set_secure_mode_cfg,
+    peer_conn::tests::{set_pq_hybrid_cfg, set_secure_mode_cfg},
     peer_manager::{PeerManager, RouteAlgoType},
     peer_map::PeerMap,
     peer_session::{PeerSession, PeerSessionStore, SessionKey},
@@ -64,6 +64,21 @@ pub async fn create_mock_peer_manager_secure(
     peer_mgr
 }
 
+pub async fn create_mock_peer_manager_secure_pq(
+    network_name: String,
+    network_secret: String,
+    pq_hybrid: bool,
+) -> Arc<PeerManager> {
+    let (s, _r) = create_packet_recv_chan();
+    let g =
+        get_mock_global_ctx_with_network(Some(NetworkIdentity::new(network_name, network_secret)));
+    set_secure_mode_cfg(&g, true);
+    set_pq_hybrid_cfg(&g, pq_hybrid);
+    let peer_mgr = Arc::new(PeerManager::new(RouteAlgoType::Ospf, g, s));
+    peer_mgr.run().await.unwrap();
+    peer_mgr
+}
+
 fn set_private_mode(peer_mgr: &PeerManager, enabled: bool) {
     let global_ctx = peer_mgr.get_global_ctx();
     let mut flags = global_ctx.get_flags();
@@ -711,6 +726,86 @@ async fn relay_peer_map_real_link_handshake_success() {
     assert!(metric_value(&peer_c, MetricName::TrafficControlBytesRx, "net1") > c_control_rx_before);
 }
 
+#[rstest::rstest]
+#[tokio::test]
+async fn relay_peer_map_pq_hybrid_interop(
+    #[values(true, false)] a_pq: bool,
+    #[values(true, false)] c_pq: bool,
+) {
+    let peer_a =
+        create_mock_peer_manager_secure_pq("net1".to_string(), "sec1".to_string(), a_pq).await;
+    let peer_b = create_mock_peer_manager_secure("net1".to_string(), "sec1".to_string()).await;
+    let peer_c =
+        create_mock_peer_manager_secure_pq("net1".to_string(), "sec1".to_string(), c_pq).await;
+
+    connect_peer_manager(peer_a.clone(), peer_b.clone()).await;
+    connect_peer_manager(peer_b.clone(), peer_c.clone()).await;
+
+    let peer_a_id = peer_a.my_peer_id();
+    let peer_c_id = peer_c.my_peer_id();
+
+    wait_for_condition(
+        || {
+            let peer_a = peer_a.clone();
+            let peer_c = peer_c.clone();
+            async move { wait_route_appear(peer_a.clone(), peer_c).await.is_ok() }
+        },
+        Duration::from_secs(10),
+    )
+    .await;
+
+    wait_for_condition(
+        || {
+            let peer_a = peer_a.clone();
+            async move {
+                peer_a
+                    .get_peer_map()
+                    .get_route_peer_info(peer_c_id)
+                    .await
+                    .map(|info| !info.noise_static_pubkey.is_empty())
+                    .unwrap_or(false)
+            }
+        },
+        Duration::from_secs(10),
+    )
+    .await;
+
+    let relay_a = peer_a.get_relay_peer_map();
+    let relay_c = peer_c.get_relay_peer_map();
+    relay_a
+        .handshake_session(peer_c_id, NextHopPolicy::LeastHop, None)
+        .await
+        .unwrap();
+
+    wait_for_condition(
+        || {
+            let relay_c = relay_c.clone();
+            async move { relay_c.has_session(peer_a_id) }
+        },
+        Duration::from_secs(5),
+    )
+    .await;
+
+    // both sides must end up with the same root key, masked or not
+    let a_session = peer_a
+        .get_peer_session_store()
+        .get(&SessionKey::new("net1".to_string(), peer_c_id))
+        .unwrap();
+    let c_session = peer_c
+        .get_peer_session_store()
+        .get(&SessionKey::new("net1".to_string(), peer_a_id))
+        .unwrap();
+    assert_eq!(a_session.root_key(), c_session.root_key());
+
+    let mut packet = ZCPacket::new_with_payload(b"relay-pq");
+    packet.fill_peer_manager_hdr(peer_a_id, peer_c_id, PacketType::Data as u8);
+    a_session
+        .encrypt_payload(peer_a_id, peer_c_id, &mut packet)
+        .unwrap();
+    assert!(relay_c.decrypt_if_needed(&mut packet).await.unwrap());
+    assert_eq!(packet.payload(), b"relay-pq");
+}
+
 #[tokio::test]
 async fn relay_peer_map_responder_rejects_mismatched_pubkey() {
     // Create three peers: A -> B -> C
@@ -981,6 +1076,7 @@ pub async fn create_mock_peer_manager_credential(
         enabled: true,
         local_private_key: Some(BASE64_STANDARD.encode(private_key.as_bytes())),
         local_public_key: Some(BASE64_STANDARD.encode(public.as_bytes())),
+        pq_hybrid: false,
     }));
 
     let peer_mgr = Arc::new(PeerManager::new(RouteAlgoType::Ospf, g, s));
@@ -239,6 +239,7 @@ pub(crate) fn traffic_kind(packet_type: u8) -> TrafficKind {
         || packet_type == PacketType::QuicDst as u8
         || packet_type == PacketType::DataWithKcpSrcModified as u8
         || packet_type == PacketType::DataWithQuicSrcModified as u8
+        || packet_type == PacketType::Ethernet as u8
     {
         TrafficKind::Data
     } else {
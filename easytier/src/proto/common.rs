@@ -510,6 +510,21 @@ impl fmt::Debug for Ipv6Addr {
     }
 }
 
+// the private key is a secret, keep it out of logs
+impl fmt::Debug for SecureModeConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecureModeConfig")
+            .field("enabled", &self.enabled)
+            .field(
+                "local_private_key",
+                &self.local_private_key.as_ref().map(|_| "<redacted>"),
+            )
+            .field("local_public_key", &self.local_public_key)
+            .field("pq_hybrid", &self.pq_hybrid)
+            .finish()
+    }
+}
+
 impl SecureModeConfig {
     pub fn private_key(&self) -> anyhow::Result<x25519_dalek::StaticSecret> {
         let local_private_key = self
@@ -588,6 +603,18 @@ mod tests {
 
         #[cfg(feature = "faketcp")]
         assert_ipv6_tunnel_normalization("faketcp", 11013);
+
+        #[cfg(feature = "obfs")]
+        assert_ipv6_tunnel_normalization("obfs", 11014);
+
+        #[cfg(feature = "http2")]
+        assert_ipv6_tunnel_normalization("h2", 80);
+
+        #[cfg(feature = "http2")]
+        assert_ipv6_tunnel_normalization("h2s", 443);
+
+        #[cfg(feature = "webtransport")]
+        assert_ipv6_tunnel_normalization("wt", 11013);
     }
 
     #[test]
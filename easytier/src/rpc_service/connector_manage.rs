@@ -3,7 +3,10 @@ use std::sync::Arc;
 use crate::{
     instance_manager::NetworkInstanceManager,
     proto::{
-        api::instance::{ConnectorManageRpc, ListConnectorRequest, ListConnectorResponse},
+        api::instance::{
+            ConnectorManageRpc, ListConnectorRequest, ListConnectorResponse,
+            ListLanDiscoveredPeersRequest, ListLanDiscoveredPeersResponse,
+        },
         rpc_types::controller::BaseController,
     },
 };
@@ -33,4 +36,15 @@ impl ConnectorManageRpc for ConnectorManageRpcService {
             .list_connector(ctrl, req)
             .await
     }
+
+    async fn list_lan_discovered_peers(
+        &self,
+        ctrl: Self::Controller,
+        req: ListLanDiscoveredPeersRequest,
+    ) -> crate::proto::rpc_types::error::Result<ListLanDiscoveredPeersResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_connector_manage_service()
+            .list_lan_discovered_peers(ctrl, req)
+            .await
+    }
 }
@@ -1,15 +1,28 @@
-use std::sync::{Mutex, OnceLock, mpsc::Sender};
+use std::{
+    sync::{Mutex, OnceLock, mpsc::Sender},
+    time::Duration,
+};
 
-use crate::proto::{
-    api::logger::{
-        GetLoggerConfigRequest, GetLoggerConfigResponse, LogLevel, LoggerRpc,
-        SetLoggerConfigRequest, SetLoggerConfigResponse,
+use crate::{
+    common::{
+        log::parse_filter_directives,
+        log_ring::{MAX_TAIL_EVENTS, TailFilter, log_ring},
+    },
+    proto::{
+        api::logger::{
+            GetLoggerConfigRequest, GetLoggerConfigResponse, LogLevel, LoggerRpc,
+            SetLoggerConfigRequest, SetLoggerConfigResponse, TailLogRequest, TailLogResponse,
+        },
+        rpc_types::{self, controller::BaseController},
     },
-    rpc_types::{self, controller::BaseController},
 };
 
 pub static LOGGER_LEVEL_SENDER: std::sync::OnceLock<Mutex<Sender<String>>> = OnceLock::new();
 pub static CURRENT_LOG_LEVEL: std::sync::OnceLock<Mutex<String>> = OnceLock::new();
+pub static CURRENT_LOG_FILTER: std::sync::OnceLock<Mutex<String>> = OnceLock::new();
+
+// 长轮询的最长等待时间，需小于客户端的 rpc 超时
+const MAX_TAIL_WAIT: Duration = Duration::from_secs(10);
 
 #[derive(Clone, Default)]
 pub struct LoggerRpcService;
@@ -48,12 +61,24 @@ impl LoggerRpc for LoggerRpcService {
         _: BaseController,
         request: SetLoggerConfigRequest,
     ) -> Result<SetLoggerConfigResponse, rpc_types::error::Error> {
-        let level_str = Self::log_level_to_string(request.level());
+        // filter 优先于 level，先校验指令，避免重载器收到无效的过滤器
+        let (filter_str, level_str) = match request.filter.as_deref().map(str::trim) {
+            Some(filter) if !filter.is_empty() => {
+                let max_level = parse_filter_directives(filter)?
+                    .max_level_hint()
+                    .unwrap_or(tracing::level_filters::LevelFilter::TRACE);
+                (filter.to_string(), max_level.to_string().to_lowercase())
+            }
+            _ => {
+                let level_str = Self::log_level_to_string(request.level());
+                (level_str.clone(), level_str)
+            }
+        };
 
         // 发送新的日志级别到 logger 重载器
         if let Some(sender) = LOGGER_LEVEL_SENDER.get() {
             if let Ok(sender) = sender.lock() {
-                if let Err(e) = sender.send(level_str) {
+                if let Err(e) = sender.send(filter_str.clone()) {
                     tracing::warn!("Failed to send new log level to reloader: {}", e);
                     return Err(rpc_types::error::Error::ExecutionError(anyhow::anyhow!(
                         "Failed to update log level: {}",
@@ -75,7 +100,12 @@ impl LoggerRpc for LoggerRpcService {
         if let Some(current_level) = CURRENT_LOG_LEVEL.get()
             && let Ok(mut level) = current_level.lock()
         {
-            *level = Self::log_level_to_string(request.level());
+            *level = level_str;
+        }
+        if let Some(current_filter) = CURRENT_LOG_FILTER.get()
+            && let Ok(mut filter) = current_filter.lock()
+        {
+            *filter = filter_str;
         }
 
         Ok(SetLoggerConfigResponse {})
@@ -97,9 +127,31 @@ impl LoggerRpc for LoggerRpcService {
         };
 
         let level = Self::string_to_log_level(&current_level_str);
+        let filter = CURRENT_LOG_FILTER
+            .get()
+            .and_then(|f| f.lock().ok().map(|f| f.clone()))
+            .unwrap_or_else(|| current_level_str.clone());
 
         Ok(GetLoggerConfigResponse {
             level: level.into(),
+            filter,
         })
     }
+
+    async fn tail_log(
+        &self,
+        _: BaseController,
+        request: TailLogRequest,
+    ) -> Result<TailLogResponse, rpc_types::error::Error> {
+        let filter = request.filter.parse::<TailFilter>()?;
+        let max_events = match request.max_events as usize {
+            0 => MAX_TAIL_EVENTS,
+            n => n.min(MAX_TAIL_EVENTS),
+        };
+        let wait = Duration::from_millis(request.wait_ms as u64).min(MAX_TAIL_WAIT);
+
+        Ok(log_ring()
+            .tail(request.after_seq, &filter, max_events, wait)
+            .await)
+    }
 }
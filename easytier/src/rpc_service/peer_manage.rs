@@ -113,4 +113,59 @@ impl PeerManageRpc for PeerManageRpcService {
             .show_node_info(ctrl, req)
             .await
     }
+
+    async fn diagnose_peer(
+        &self,
+        ctrl: Self::Controller,
+        req: crate::proto::api::instance::DiagnosePeerRequest,
+    ) -> crate::proto::rpc_types::error::Result<instance::DiagnosePeerResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_peer_manage_service()
+            .diagnose_peer(ctrl, req)
+            .await
+    }
+
+    async fn start_capture(
+        &self,
+        ctrl: Self::Controller,
+        req: crate::proto::api::instance::StartCaptureRequest,
+    ) -> crate::proto::rpc_types::error::Result<instance::StartCaptureResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_peer_manage_service()
+            .start_capture(ctrl, req)
+            .await
+    }
+
+    async fn fetch_capture(
+        &self,
+        ctrl: Self::Controller,
+        req: crate::proto::api::instance::FetchCaptureRequest,
+    ) -> crate::proto::rpc_types::error::Result<instance::FetchCaptureResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_peer_manage_service()
+            .fetch_capture(ctrl, req)
+            .await
+    }
+
+    async fn stop_capture(
+        &self,
+        ctrl: Self::Controller,
+        req: crate::proto::api::instance::StopCaptureRequest,
+    ) -> crate::proto::rpc_types::error::Result<instance::StopCaptureResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_peer_manage_service()
+            .stop_capture(ctrl, req)
+            .await
+    }
+
+    async fn get_secret_rotation_status(
+        &self,
+        ctrl: Self::Controller,
+        req: crate::proto::api::instance::GetSecretRotationStatusRequest,
+    ) -> crate::proto::rpc_types::error::Result<instance::GetSecretRotationStatusResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_peer_manage_service()
+            .get_secret_rotation_status(ctrl, req)
+            .await
+    }
 }
@@ -3,7 +3,12 @@ use std::sync::Arc;
 use crate::{
     instance_manager::NetworkInstanceManager,
     proto::{
-        api::instance::{GetVpnPortalInfoRequest, GetVpnPortalInfoResponse, VpnPortalRpc},
+        api::instance::{
+            AddVpnPortalClientRequest, AddVpnPortalClientResponse, GetVpnPortalClientConfigRequest,
+            GetVpnPortalClientConfigResponse, GetVpnPortalInfoRequest, GetVpnPortalInfoResponse,
+            ListVpnPortalClientsRequest, ListVpnPortalClientsResponse,
+            RemoveVpnPortalClientRequest, RemoveVpnPortalClientResponse, VpnPortalRpc,
+        },
         rpc_types::controller::BaseController,
     },
 };
@@ -33,4 +38,48 @@ impl VpnPortalRpc for VpnPortalRpcService {
             .get_vpn_portal_info(ctrl, req)
             .await
     }
+
+    async fn add_vpn_portal_client(
+        &self,
+        ctrl: Self::Controller,
+        req: AddVpnPortalClientRequest,
+    ) -> crate::proto::rpc_types::error::Result<AddVpnPortalClientResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_vpn_portal_service()
+            .add_vpn_portal_client(ctrl, req)
+            .await
+    }
+
+    async fn remove_vpn_portal_client(
+        &self,
+        ctrl: Self::Controller,
+        req: RemoveVpnPortalClientRequest,
+    ) -> crate::proto::rpc_types::error::Result<RemoveVpnPortalClientResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_vpn_portal_service()
+            .remove_vpn_portal_client(ctrl, req)
+            .await
+    }
+
+    async fn list_vpn_portal_clients(
+        &self,
+        ctrl: Self::Controller,
+        req: ListVpnPortalClientsRequest,
+    ) -> crate::proto::rpc_types::error::Result<ListVpnPortalClientsResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_vpn_portal_service()
+            .list_vpn_portal_clients(ctrl, req)
+            .await
+    }
+
+    async fn get_vpn_portal_client_config(
+        &self,
+        ctrl: Self::Controller,
+        req: GetVpnPortalClientConfigRequest,
+    ) -> crate::proto::rpc_types::error::Result<GetVpnPortalClientConfigResponse> {
+        super::get_instance_service(&self.instance_manager, &req.instance)?
+            .get_vpn_portal_service()
+            .get_vpn_portal_client_config(ctrl, req)
+            .await
+    }
 }
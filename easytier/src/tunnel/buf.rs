@@ -1,8 +1,17 @@
+use std::cell::RefCell;
 use std::collections::VecDeque;
 use std::io::IoSlice;
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 
+use super::packet_def::TAIL_RESERVED_SIZE;
+
+// packet buffers are cut from chunks of this size
+pub(crate) const PACKET_BUF_CHUNK_SIZE: usize = 64 * 1024;
+// smaller buffers are allocated on their own. they are mostly control packets,
+// which may be held for long and would keep a whole chunk alive
+pub(crate) const SMALL_PACKET_BUF_SIZE: usize = 256;
+
 pub(crate) struct BufList<T> {
     bufs: VecDeque<T>,
 }
@@ -90,3 +99,115 @@ impl<T: Buf> Buf for BufList<T> {
         }
     }
 }
+
+/// Hands out packet buffers cut from larger chunks, with room for the headers
+/// before the payload and for the compression / encryption tail after it, so
+/// the send path never has to grow a buffer. Once all packets cut from a chunk
+/// are dropped, `BytesMut::reserve` takes the chunk back instead of allocating.
+///
+/// A single packet still alive keeps its whole chunk allocated, so small packets
+/// get an exact-size buffer instead, and code holding packets for long should
+/// move them out with `copy_packet_buf` (`ZCPacket::detach_buf`).
+///
+/// This serves packets built from a slice (`ZCPacket::new_with_payload`). The
+/// tun readers cut packets from their own read chunk in the same way, see
+/// `PACKET_BUF_CHUNK_SIZE`.
+pub(crate) struct PacketBufPool {
+    chunk: BytesMut,
+}
+
+impl PacketBufPool {
+    pub(crate) fn new() -> Self {
+        PacketBufPool {
+            chunk: BytesMut::new(),
+        }
+    }
+
+    /// Buffer of `header_len` bytes with capacity for `payload_len` more bytes
+    /// and the tail. The header bytes are zeroed.
+    pub(crate) fn alloc(&mut self, header_len: usize, payload_len: usize) -> BytesMut {
+        let size = header_len + payload_len + TAIL_RESERVED_SIZE;
+        if size <= SMALL_PACKET_BUF_SIZE {
+            let mut buf = BytesMut::zeroed(size);
+            buf.truncate(header_len);
+            return buf;
+        }
+        if self.chunk.len() < size {
+            self.chunk.clear();
+            self.chunk.reserve(PACKET_BUF_CHUNK_SIZE.max(size));
+            // zero the whole chunk once, buffers are then cut from initialized bytes
+            self.chunk.resize(self.chunk.capacity(), 0);
+        }
+        let mut buf = self.chunk.split_to(size);
+        buf.truncate(header_len);
+        buf
+    }
+}
+
+thread_local! {
+    static PACKET_BUF_POOL: RefCell<PacketBufPool> = RefCell::new(PacketBufPool::new());
+}
+
+pub(crate) fn alloc_packet_buf(header_len: usize, payload_len: usize) -> BytesMut {
+    PACKET_BUF_POOL.with_borrow_mut(|pool| pool.alloc(header_len, payload_len))
+}
+
+/// Copy of `buf` in its own allocation, keeping the tail room.
+pub(crate) fn copy_packet_buf(buf: &[u8]) -> BytesMut {
+    let mut copy = BytesMut::with_capacity(buf.len() + TAIL_RESERVED_SIZE);
+    copy.extend_from_slice(buf);
+    copy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packet_buf_pool_reuses_chunk() {
+        let mut pool = PacketBufPool::new();
+        let buf = pool.alloc(40, 1400);
+        assert_eq!(buf.len(), 40);
+        assert!(buf.capacity() >= 40 + 1400 + TAIL_RESERVED_SIZE);
+        let first = buf.as_ptr();
+
+        let buf2 = pool.alloc(40, 1400);
+        assert_ne!(buf2.as_ptr(), first);
+
+        // the chunk is reused once all buffers cut from it are gone
+        drop(buf);
+        drop(buf2);
+        let size = 40 + 1400 + TAIL_RESERVED_SIZE;
+        for _ in 2..PACKET_BUF_CHUNK_SIZE / size {
+            let _ = pool.alloc(40, 1400);
+        }
+        assert_eq!(pool.alloc(40, 1400).as_ptr(), first);
+    }
+
+    #[test]
+    fn small_and_copied_bufs_do_not_pin_chunk() {
+        let mut pool = PacketBufPool::new();
+        let small = pool.alloc(40, 64);
+        assert_eq!(small.len(), 40);
+        assert_eq!(small.capacity(), 40 + 64 + TAIL_RESERVED_SIZE);
+        // nothing was cut from a chunk
+        assert!(pool.chunk.is_empty());
+
+        let mut buf = pool.alloc(40, 1400);
+        buf.resize(40 + 1400, 1);
+        let first = buf.as_ptr();
+        let copy = copy_packet_buf(&buf);
+        assert_eq!(copy, buf);
+        assert_eq!(copy.capacity(), buf.len() + TAIL_RESERVED_SIZE);
+        assert_ne!(copy.as_ptr(), first);
+
+        // the copy outlives the chunk, which is reused right away
+        drop(buf);
+        let size = 40 + 1400 + TAIL_RESERVED_SIZE;
+        for _ in 1..PACKET_BUF_CHUNK_SIZE / size {
+            let _ = pool.alloc(40, 1400);
+        }
+        assert_eq!(pool.alloc(40, 1400).as_ptr(), first);
+        assert_eq!(copy.len(), 40 + 1400);
+    }
+}
@@ -0,0 +1,575 @@
+//! Tunnels over a single bidirectional HTTP/2 stream (`h2://` and `h2s://`).
+//!
+//! The client opens one POST request and keeps both the request and the
+//! response body open; the listener also accepts CONNECT so it can sit behind
+//! proxies that tunnel that way. Packets are framed the same way as in the tcp
+//! tunnel because proxies are free to split or merge DATA frames. `h2` speaks
+//! cleartext HTTP/2 with prior knowledge (as used between a reverse proxy and
+//! its upstream), `h2s` wraps it in TLS with the `h2` ALPN.
+
+use std::{
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Poll, ready},
+    time::Duration,
+};
+
+use anyhow::Context;
+use bytes::Bytes;
+use futures::stream::FuturesUnordered;
+use h2::{RecvStream, SendStream};
+use http::{Method, Request, Response, StatusCode};
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    net::{TcpListener, TcpSocket, TcpStream},
+    task::JoinSet,
+    time::timeout,
+};
+use tokio_rustls::TlsAcceptor;
+use tokio_util::{either::Either, task::AbortOnDropHandle};
+
+use super::{
+    FromUrl, IpVersion, Tunnel, TunnelConnector, TunnelError, TunnelInfo, TunnelListener,
+    common::{FramedReader, FramedWriter, TunnelWrapper, bind, wait_for_connect_futures},
+    insecure_tls::{get_insecure_tls_cert, get_insecure_tls_client_config, init_crypto_provider},
+};
+
+const H2_MTU_BYTES: usize = 2000;
+const H2_ALPN: &[u8] = b"h2";
+const H2_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+const H2_MAX_PENDING_HANDSHAKES: usize = 1024;
+
+fn is_h2s(addr: &url::Url) -> Result<bool, TunnelError> {
+    match addr.scheme() {
+        "h2" => Ok(false),
+        "h2s" => Ok(true),
+        _ => Err(TunnelError::InvalidProtocol(addr.scheme().to_string())),
+    }
+}
+
+/// Reads the DATA frames of an HTTP/2 stream as a byte stream.
+struct H2StreamReader {
+    recv: RecvStream,
+    buf: Bytes,
+}
+
+impl AsyncRead for H2StreamReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if !this.buf.is_empty() {
+                let n = this.buf.len().min(buf.remaining());
+                buf.put_slice(&this.buf.split_to(n));
+                return Poll::Ready(Ok(()));
+            }
+
+            match ready!(this.recv.poll_data(cx)) {
+                Some(Ok(data)) => {
+                    let _ = this.recv.flow_control().release_capacity(data.len());
+                    this.buf = data;
+                }
+                Some(Err(e)) => return Poll::Ready(Err(std::io::Error::other(e))),
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+/// Writes a byte stream as DATA frames, respecting HTTP/2 flow control.
+struct H2StreamWriter {
+    send: SendStream<Bytes>,
+    closed: bool,
+}
+
+impl AsyncWrite for H2StreamWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        this.send.reserve_capacity(buf.len());
+        loop {
+            match ready!(this.send.poll_capacity(cx)) {
+                Some(Ok(0)) => continue,
+                Some(Ok(n)) => {
+                    let n = n.min(buf.len());
+                    this.send
+                        .send_data(Bytes::copy_from_slice(&buf[..n]), false)
+                        .map_err(std::io::Error::other)?;
+                    return Poll::Ready(Ok(n));
+                }
+                Some(Err(e)) => return Poll::Ready(Err(std::io::Error::other(e))),
+                None => return Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into())),
+            }
+        }
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if !this.closed {
+            this.closed = true;
+            this.send
+                .send_data(Bytes::new(), true)
+                .map_err(std::io::Error::other)?;
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn wrap_h2_stream(
+    recv: RecvStream,
+    send: SendStream<Bytes>,
+    conn_task: AbortOnDropHandle<()>,
+    info: TunnelInfo,
+) -> Box<dyn Tunnel> {
+    // the connection task drives all io of the stream, keep it as long as
+    // either half is alive
+    let conn_task = Arc::new(conn_task);
+    Box::new(TunnelWrapper::new(
+        FramedReader::new_with_associate_data(
+            H2StreamReader {
+                recv,
+                buf: Bytes::new(),
+            },
+            H2_MTU_BYTES,
+            Some(Box::new(conn_task.clone())),
+        ),
+        FramedWriter::new_with_associate_data(
+            H2StreamWriter {
+                send,
+                closed: false,
+            },
+            Some(Box::new(conn_task)),
+        ),
+        Some(info),
+    ))
+}
+
+type H2HandshakeResult = Result<Box<dyn Tunnel>, TunnelError>;
+
+#[derive(Debug)]
+pub struct H2TunnelListener {
+    addr: url::Url,
+    listener: Option<TcpListener>,
+    socket_mark: Option<u32>,
+    // tls and h2 handshakes run here so a slow client can't stall accept
+    handshakes: JoinSet<H2HandshakeResult>,
+}
+
+impl H2TunnelListener {
+    pub fn new(addr: url::Url) -> Self {
+        H2TunnelListener {
+            addr,
+            listener: None,
+            socket_mark: None,
+            handshakes: JoinSet::new(),
+        }
+    }
+
+    pub fn set_socket_mark(&mut self, socket_mark: Option<u32>) {
+        self.socket_mark = socket_mark;
+    }
+
+    async fn try_accept(addr: url::Url, stream: TcpStream) -> H2HandshakeResult {
+        let peer_addr = stream.peer_addr()?;
+
+        let stream = if is_h2s(&addr)? {
+            init_crypto_provider();
+            let (certs, key) = get_insecure_tls_cert();
+            let mut config = rustls::ServerConfig::builder()
+                .with_no_client_auth()
+                .with_single_cert(certs, key)
+                .with_context(|| "Failed to create server config")?;
+            config.alpn_protocols = vec![H2_ALPN.to_vec()];
+
+            let stream = TlsAcceptor::from(Arc::new(config)).accept(stream).await?;
+            Either::Left(stream)
+        } else {
+            Either::Right(stream)
+        };
+
+        let mut conn = h2::server::handshake(stream).await?;
+        let (request, mut respond) = conn.accept().await.ok_or(TunnelError::Shutdown)??;
+        if !matches!(*request.method(), Method::POST | Method::CONNECT) {
+            let _ = respond.send_response(not_found(), true);
+            return Err(TunnelError::InvalidPacket(format!(
+                "unexpected h2 request method: {}",
+                request.method()
+            )));
+        }
+        let send = respond.send_response(
+            Response::builder().status(StatusCode::OK).body(()).unwrap(),
+            false,
+        )?;
+
+        // keep driving the connection, nothing else is served on it
+        let conn_task = AbortOnDropHandle::new(tokio::spawn(async move {
+            while let Some(ret) = conn.accept().await {
+                match ret {
+                    Ok((_, mut respond)) => {
+                        let _ = respond.send_response(not_found(), true);
+                    }
+                    Err(error) => {
+                        tracing::debug!(?error, "h2 server connection closed");
+                        break;
+                    }
+                }
+            }
+        }));
+
+        let remote_addr: crate::proto::common::Url =
+            super::build_url_from_socket_addr(&peer_addr.to_string(), addr.scheme()).into();
+        let info = TunnelInfo {
+            tunnel_type: addr.scheme().to_owned(),
+            local_addr: Some(addr.clone().into()),
+            remote_addr: Some(remote_addr.clone()),
+            resolved_remote_addr: Some(remote_addr),
+        };
+
+        Ok(wrap_h2_stream(request.into_body(), send, conn_task, info))
+    }
+}
+
+fn not_found() -> Response<()> {
+    Response::builder()
+        .status(StatusCode::NOT_FOUND)
+        .body(())
+        .unwrap()
+}
+
+#[async_trait::async_trait]
+impl TunnelListener for H2TunnelListener {
+    async fn listen(&mut self) -> Result<(), TunnelError> {
+        self.listener = None;
+
+        let addr = SocketAddr::from_url(self.addr.clone(), IpVersion::Both).await?;
+        let listener = bind::<TcpListener>()
+            .addr(addr)
+            .only_v6(true)
+            .maybe_socket_mark(self.socket_mark)
+            .call()?;
+
+        self.addr
+            .set_port(Some(listener.local_addr()?.port()))
+            .unwrap();
+        self.listener = Some(listener);
+
+        Ok(())
+    }
+
+    async fn accept(&mut self) -> Result<Box<dyn Tunnel>, super::TunnelError> {
+        enum Event {
+            Accepted(std::io::Result<(TcpStream, SocketAddr)>),
+            Handshaked(Result<H2HandshakeResult, tokio::task::JoinError>),
+        }
+
+        loop {
+            let listener = self.listener.as_ref().unwrap();
+            let event = tokio::select! {
+                ret = listener.accept() => Event::Accepted(ret),
+                Some(ret) = self.handshakes.join_next() => Event::Handshaked(ret),
+            };
+
+            match event {
+                Event::Accepted(Ok((stream, peer_addr))) => {
+                    if self.handshakes.len() >= H2_MAX_PENDING_HANDSHAKES {
+                        tracing::warn!(?peer_addr, "too many pending h2 handshakes, drop conn");
+                        continue;
+                    }
+                    if let Err(e) = stream.set_nodelay(true) {
+                        tracing::warn!(?e, "set_nodelay fail in h2 accept");
+                    }
+                    let addr = self.addr.clone();
+                    self.handshakes.spawn(async move {
+                        timeout(H2_HANDSHAKE_TIMEOUT, Self::try_accept(addr, stream)).await?
+                    });
+                }
+                // only fail on tcp accept error
+                Event::Accepted(Err(e)) => return Err(e.into()),
+                Event::Handshaked(Ok(Ok(tunnel))) => return Ok(tunnel),
+                Event::Handshaked(Ok(Err(e))) => {
+                    tracing::error!(?e, ?self.addr, "Failed to accept h2/h2s tunnel");
+                }
+                Event::Handshaked(Err(e)) => {
+                    tracing::warn!(?e, "h2 handshake task failed");
+                }
+            }
+        }
+    }
+
+    fn local_url(&self) -> url::Url {
+        self.addr.clone()
+    }
+}
+
+pub struct H2TunnelConnector {
+    addr: url::Url,
+    ip_version: IpVersion,
+    resolved_addr: Option<SocketAddr>,
+
+    bind_addrs: Vec<SocketAddr>,
+    socket_mark: Option<u32>,
+}
+
+impl H2TunnelConnector {
+    pub fn new(addr: url::Url) -> Self {
+        H2TunnelConnector {
+            addr,
+            ip_version: IpVersion::Both,
+            resolved_addr: None,
+
+            bind_addrs: vec![],
+            socket_mark: None,
+        }
+    }
+
+    /// The http(s) uri of the tunnel request, keeping the host and path of the
+    /// tunnel url so reverse proxies can route it.
+    fn request_uri(addr: &url::Url) -> Result<String, TunnelError> {
+        let scheme = if is_h2s(addr)? { "https" } else { "http" };
+        let host = addr
+            .host_str()
+            .ok_or_else(|| TunnelError::InvalidAddr(addr.to_string()))?;
+        let port = addr.port().map(|p| format!(":{p}")).unwrap_or_default();
+        let path = match addr.path() {
+            "" => "/",
+            path => path,
+        };
+        Ok(format!("{scheme}://{host}{port}{path}"))
+    }
+
+    async fn connect_with(
+        addr: url::Url,
+        socket_addr: SocketAddr,
+        tcp_socket: TcpSocket,
+    ) -> Result<Box<dyn Tunnel>, TunnelError> {
+        let is_h2s = is_h2s(&addr)?;
+        let request_uri = Self::request_uri(&addr)?;
+        let stream = tcp_socket.connect(socket_addr).await?;
+        if let Err(error) = stream.set_nodelay(true) {
+            tracing::warn!(?error, "set_nodelay fail in h2 connect");
+        }
+
+        let info = TunnelInfo {
+            tunnel_type: addr.scheme().to_owned(),
+            local_addr: Some(
+                super::build_url_from_socket_addr(&stream.local_addr()?.to_string(), addr.scheme())
+                    .into(),
+            ),
+            remote_addr: Some(addr.clone().into()),
+            resolved_remote_addr: Some(
+                super::build_url_from_socket_addr(&socket_addr.to_string(), addr.scheme()).into(),
+            ),
+        };
+
+        let stream = if is_h2s {
+            init_crypto_provider();
+            let mut config = get_insecure_tls_client_config();
+            config.alpn_protocols = vec![H2_ALPN.to_vec()];
+            let tls_conn = tokio_rustls::TlsConnector::from(Arc::new(config));
+            // use "localhost" as SNI for url without domain to avoid IP blocking.
+            let sni = match addr.domain() {
+                None => "localhost".to_string(),
+                Some(domain) => domain.to_string(),
+            };
+            let server_name = rustls::pki_types::ServerName::try_from(sni)
+                .map_err(|_| TunnelError::InvalidProtocol("Invalid SNI".to_string()))?;
+            Either::Left(tls_conn.connect(server_name, stream).await?)
+        } else {
+            Either::Right(stream)
+        };
+
+        let (send_request, conn) = h2::client::handshake(stream).await?;
+        let conn_task = AbortOnDropHandle::new(tokio::spawn(async move {
+            if let Err(error) = conn.await {
+                tracing::debug!(?error, "h2 client connection closed");
+            }
+        }));
+
+        let mut send_request = send_request.ready().await?;
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(request_uri)
+            .body(())
+            .map_err(|e| TunnelError::InvalidAddr(e.to_string()))?;
+        let (response, send) = send_request.send_request(request, false)?;
+        let response = response.await?;
+        if response.status() != StatusCode::OK {
+            return Err(TunnelError::InvalidPacket(format!(
+                "unexpected h2 response status: {}",
+                response.status()
+            )));
+        }
+
+        Ok(wrap_h2_stream(response.into_body(), send, conn_task, info))
+    }
+
+    async fn connect_with_default_bind(
+        &self,
+        addr: SocketAddr,
+    ) -> Result<Box<dyn Tunnel>, super::TunnelError> {
+        let socket = if addr.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+        crate::tunnel::common::apply_socket_mark(
+            &socket2::SockRef::from(&socket),
+            self.socket_mark,
+        )?;
+        Self::connect_with(self.addr.clone(), addr, socket).await
+    }
+
+    async fn connect_with_custom_bind(
+        &self,
+        addr: SocketAddr,
+    ) -> Result<Box<dyn Tunnel>, super::TunnelError> {
+        let futures = FuturesUnordered::new();
+
+        for bind_addr in self.bind_addrs.iter() {
+            tracing::info!(?bind_addr, ?addr, "bind addr");
+            match bind()
+                .addr(*bind_addr)
+                .only_v6(true)
+                .maybe_socket_mark(self.socket_mark)
+                .call()
+            {
+                Ok(socket) => futures.push(Self::connect_with(self.addr.clone(), addr, socket)),
+                Err(error) => {
+                    tracing::error!(?bind_addr, ?addr, ?error, "bind addr fail");
+                    continue;
+                }
+            }
+        }
+
+        wait_for_connect_futures(futures).await
+    }
+}
+
+#[async_trait::async_trait]
+impl TunnelConnector for H2TunnelConnector {
+    async fn connect(&mut self) -> Result<Box<dyn Tunnel>, TunnelError> {
+        let addr = match self.resolved_addr {
+            Some(addr) => addr,
+            None => SocketAddr::from_url(self.addr.clone(), self.ip_version).await?,
+        };
+        if self.bind_addrs.is_empty() || addr.is_ipv6() {
+            self.connect_with_default_bind(addr).await
+        } else {
+            self.connect_with_custom_bind(addr).await
+        }
+    }
+
+    fn remote_url(&self) -> url::Url {
+        self.addr.clone()
+    }
+
+    fn set_ip_version(&mut self, ip_version: IpVersion) {
+        self.ip_version = ip_version;
+    }
+
+    fn set_bind_addrs(&mut self, addrs: Vec<SocketAddr>) {
+        self.bind_addrs = addrs;
+    }
+
+    fn set_resolved_addr(&mut self, addr: SocketAddr) {
+        self.resolved_addr = Some(addr);
+    }
+
+    fn set_socket_mark(&mut self, socket_mark: Option<u32>) {
+        self.socket_mark = socket_mark;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tunnel::common::tests::{_tunnel_bench, _tunnel_pingpong};
+
+    use super::*;
+
+    #[rstest::rstest]
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn h2_pingpong(#[values("h2", "h2s")] proto: &str) {
+        let listener = H2TunnelListener::new(format!("{}://0.0.0.0:25561", proto).parse().unwrap());
+        let connector =
+            H2TunnelConnector::new(format!("{}://127.0.0.1:25561", proto).parse().unwrap());
+        _tunnel_pingpong(listener, connector).await
+    }
+
+    #[rstest::rstest]
+    #[tokio::test]
+    #[serial_test::serial]
+    async fn h2_bench(#[values("h2", "h2s")] proto: &str) {
+        let listener = H2TunnelListener::new(format!("{}://0.0.0.0:25562", proto).parse().unwrap());
+        let connector =
+            H2TunnelConnector::new(format!("{}://127.0.0.1:25562", proto).parse().unwrap());
+        _tunnel_bench(listener, connector).await
+    }
+
+    #[test]
+    fn h2_request_uri_keeps_host_and_path() {
+        let uri = H2TunnelConnector::request_uri(&"h2s://example.com/tunnel".parse().unwrap());
+        assert_eq!(uri.unwrap(), "https://example.com/tunnel");
+
+        let uri = H2TunnelConnector::request_uri(&"h2://[::1]:8080".parse().unwrap());
+        assert_eq!(uri.unwrap(), "http://[::1]:8080/");
+    }
+
+    #[tokio::test]
+    async fn h2_listener_rejects_plain_get() {
+        let mut listener = H2TunnelListener::new("h2://127.0.0.1:0".parse().unwrap());
+        listener.listen().await.unwrap();
+        let port = listener.local_url().port().unwrap();
+        let _accept_task = tokio::spawn(async move { listener.accept().await });
+
+        let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let (send_request, conn) = h2::client::handshake(stream).await.unwrap();
+        tokio::spawn(conn);
+        let mut send_request = send_request.ready().await.unwrap();
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(format!("http://127.0.0.1:{port}/"))
+            .body(())
+            .unwrap();
+        let (response, _) = send_request.send_request(request, true).unwrap();
+        assert_eq!(response.await.unwrap().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn h2_listener_accepts_while_handshake_stalls() {
+        let mut listener = H2TunnelListener::new("h2s://127.0.0.1:0".parse().unwrap());
+        listener.listen().await.unwrap();
+        let port = listener.local_url().port().unwrap();
+
+        // connects but never starts the tls handshake
+        let _idle = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let accept_task = tokio::spawn(async move { listener.accept().await });
+
+        let mut connector =
+            H2TunnelConnector::new(format!("h2s://127.0.0.1:{port}").parse().unwrap());
+        let _tunnel = connector.connect().await.unwrap();
+        let accepted = timeout(Duration::from_secs(1), accept_task).await;
+        assert!(matches!(accepted, Ok(Ok(Ok(_)))));
+    }
+}
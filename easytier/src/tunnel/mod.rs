@@ -25,6 +25,7 @@ pub mod ring;
 pub mod stats;
 pub mod tcp;
 pub mod udp;
+pub mod udp_batch;
 
 #[cfg(feature = "faketcp")]
 pub mod fake_tcp;
@@ -38,7 +39,16 @@ pub mod quic;
 #[cfg(feature = "websocket")]
 pub mod websocket;
 
-#[cfg(any(feature = "quic", feature = "websocket"))]
+#[cfg(feature = "obfs")]
+pub mod obfs;
+
+#[cfg(feature = "http2")]
+pub mod http2;
+
+#[cfg(feature = "webtransport")]
+pub mod webtransport;
+
+#[cfg(any(feature = "quic", feature = "websocket", feature = "http2"))]
 pub mod insecure_tls;
 
 #[cfg(unix)]
@@ -82,6 +92,10 @@ pub enum TunnelError {
     #[error("websocket error: {0}")]
     WebSocketError(#[from] tokio_websockets::Error),
 
+    #[cfg(feature = "http2")]
+    #[error("http2 error: {0}")]
+    H2Error(#[from] h2::Error),
+
     #[error("tunnel error: {0}")]
     TunError(String),
 }
@@ -304,6 +318,14 @@ pub enum IpScheme {
     Wss,
     #[cfg(feature = "faketcp")]
     FakeTcp,
+    #[cfg(feature = "obfs")]
+    Obfs,
+    #[cfg(feature = "http2")]
+    H2,
+    #[cfg(feature = "http2")]
+    H2s,
+    #[cfg(feature = "webtransport")]
+    Wt,
 }
 
 impl IpScheme {
@@ -321,6 +343,14 @@ impl IpScheme {
             Self::Wss => (Protocol::TCP, 2),
             #[cfg(feature = "faketcp")]
             Self::FakeTcp => (Protocol::TCP, 3),
+            #[cfg(feature = "obfs")]
+            Self::Obfs => (Protocol::TCP, 4),
+            #[cfg(feature = "http2")]
+            Self::H2 => (Protocol::TCP, 5),
+            #[cfg(feature = "http2")]
+            Self::H2s => (Protocol::TCP, 6),
+            #[cfg(feature = "webtransport")]
+            Self::Wt => (Protocol::UDP, 3),
         };
         IpSchemeAttributes {
             protocol,
@@ -341,6 +371,12 @@ impl IpScheme {
             Self::Ws => 80,
             #[cfg(feature = "websocket")]
             Self::Wss => 443,
+            #[cfg(feature = "http2")]
+            Self::H2 => 80,
+            #[cfg(feature = "http2")]
+            Self::H2s => 443,
+            #[cfg(feature = "webtransport")]
+            Self::Wt => 443,
             _ => 11010 + self.port_offset(),
         }
     }
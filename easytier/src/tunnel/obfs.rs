@@ -0,0 +1,1270 @@
+//! Obfuscated stream tunnel (`obfs://`).
+//!
+//! The transport is a plain TCP connection, but nothing on the wire has a fixed
+//! layout: the client proves knowledge of a key derived from the network
+//! identity in its first flight, every frame afterwards is sealed with
+//! ChaCha20-Poly1305 and carries random padding, and the frame length is masked.
+//! A listener that cannot authenticate the first flight never writes a byte
+//! back, it just holds the connection open for a while and drops it.
+//!
+//! With `?mimic=tls` the handshake is shaped like a TLS 1.3 ClientHello /
+//! ServerHello exchange and data frames travel as TLS application data
+//! records. `?sni=` overrides the server name put into the ClientHello. Both
+//! ends must use the same `mimic` mode.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    task::{Poll, ready},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use async_trait::async_trait;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use futures::stream::FuturesUnordered;
+use hmac::{Hmac, Mac};
+use rand::{Rng, RngCore};
+use ring::aead::{
+    self, Aad, LessSafeKey, Nonce, UnboundKey,
+    quic::{self, HeaderProtectionKey},
+};
+use sha2::Sha256;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt, ReadBuf},
+    net::{TcpListener, TcpSocket, TcpStream},
+    task::JoinSet,
+    time::timeout,
+};
+use tokio_util::io::poll_read_buf;
+
+use super::{
+    FromUrl, IpVersion, Tunnel, TunnelConnector, TunnelError, TunnelInfo, TunnelListener,
+    common::{
+        FramedReader, FramedWriter, TcpZCPacketToBytes, TunnelWrapper, ZCPacketToBytes,
+        apply_socket_mark, bind, wait_for_connect_futures,
+    },
+    packet_def::{TCP_TUNNEL_HEADER_SIZE, ZCPacket},
+};
+
+const OBFS_MTU_BYTES: usize = 2000;
+const OBFS_KEY_LABEL: &[u8] = b"easytier-obfs-v1";
+const OBFS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+// a client hello is accepted if its time slot is within one slot of ours
+const OBFS_AUTH_SLOT_SECS: u64 = 120;
+// how long a connection that failed authentication is held before dropping it
+const OBFS_PROBE_HOLD_SECS: std::ops::Range<u64> = 10..40;
+const OBFS_MAX_PENDING_HANDSHAKES: usize = 1024;
+
+const OBFS_MAX_PADDING: usize = 255;
+const OBFS_MAX_FRAME_LEN: usize = 16384 + 256;
+const OBFS_AEAD_TAG_LEN: usize = 16;
+const OBFS_PAD_LEN_SIZE: usize = 2;
+const OBFS_MIN_FRAME_LEN: usize = OBFS_AEAD_TAG_LEN + OBFS_PAD_LEN_SIZE;
+const OBFS_RANDOM_LEN: usize = 32;
+const OBFS_PLAIN_AUTH_LEN: usize = 16;
+const OBFS_PLAIN_HEADER_LEN: usize = 2;
+
+const TLS_RECORD_HEADER_LEN: usize = 5;
+const TLS_CONTENT_CHANGE_CIPHER_SPEC: u8 = 0x14;
+const TLS_CONTENT_HANDSHAKE: u8 = 0x16;
+const TLS_CONTENT_APPLICATION_DATA: u8 = 0x17;
+const TLS_HANDSHAKE_CLIENT_HELLO: u8 = 0x01;
+const TLS_HANDSHAKE_SERVER_HELLO: u8 = 0x02;
+const TLS_MAX_HELLO_LEN: usize = 2048;
+const TLS_X25519: u16 = 0x001d;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ObfsMimic {
+    /// Random bytes only, no recognizable framing at all.
+    #[default]
+    None,
+    /// TLS 1.3 handshake and application data records.
+    Tls,
+}
+
+impl ObfsMimic {
+    fn from_url(url: &url::Url) -> Result<Self, TunnelError> {
+        let Some((_, mimic)) = url.query_pairs().find(|(k, _)| k == "mimic") else {
+            return Ok(Self::None);
+        };
+        match mimic.as_ref() {
+            "" | "none" => Ok(Self::None),
+            "tls" => Ok(Self::Tls),
+            other => Err(TunnelError::InvalidAddr(format!(
+                "unsupported obfs mimic mode: {other}, url: {url}"
+            ))),
+        }
+    }
+
+    fn header_len(self) -> usize {
+        match self {
+            Self::None => OBFS_PLAIN_HEADER_LEN,
+            Self::Tls => TLS_RECORD_HEADER_LEN,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ObfsConfig {
+    key: [u8; 32],
+}
+
+impl std::fmt::Debug for ObfsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObfsConfig").finish_non_exhaustive()
+    }
+}
+
+pub type ObfsConfigProvider = Arc<dyn Fn() -> Vec<ObfsConfig> + Send + Sync>;
+
+impl ObfsConfig {
+    pub fn new_from_network_identity(network_name: &str, network_secret: &str) -> Self {
+        let mut mac = Hmac::<Sha256>::new_from_slice(OBFS_KEY_LABEL).unwrap();
+        mac.update(network_name.as_bytes());
+        mac.update(&[0]);
+        mac.update(network_secret.as_bytes());
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&mac.finalize().into_bytes());
+        Self { key }
+    }
+
+    fn mac(&self, label: &[u8], parts: &[&[u8]]) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.key).unwrap();
+        mac.update(label);
+        for part in parts {
+            mac.update(part);
+        }
+        mac
+    }
+
+    fn derive(&self, label: &[u8], parts: &[&[u8]]) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        out.copy_from_slice(&self.mac(label, parts).finalize().into_bytes());
+        out
+    }
+
+    fn client_auth(&self, client_random: &[u8], slot: u64) -> [u8; 32] {
+        self.derive(b"client-auth", &[client_random, &slot.to_be_bytes()])
+    }
+
+    /// Checks a (possibly truncated) client auth tag against the neighbouring
+    /// time slots and returns the full tag on success.
+    fn verify_client_auth(&self, client_random: &[u8], tag: &[u8]) -> Option<[u8; 32]> {
+        let now = current_auth_slot();
+        [now.saturating_sub(1), now, now + 1]
+            .into_iter()
+            .find(|slot| {
+                self.mac(b"client-auth", &[client_random, &slot.to_be_bytes()])
+                    .verify_truncated_left(tag)
+                    .is_ok()
+            })
+            .map(|slot| self.client_auth(client_random, slot))
+    }
+
+    fn server_auth(&self, client_random: &[u8], server_nonce: &[u8]) -> [u8; 32] {
+        self.derive(b"server-auth", &[client_random, server_nonce])
+    }
+
+    fn session_keys(
+        &self,
+        client_random: &[u8],
+        server_random: &[u8],
+        is_client: bool,
+    ) -> ObfsSessionKeys {
+        let cipher = |dir: &[u8], hp: &[u8]| {
+            ObfsCipher::new(
+                &self.derive(dir, &[client_random, server_random]),
+                &self.derive(hp, &[client_random, server_random]),
+            )
+        };
+        let c2s = cipher(b"c2s", b"c2s-hp");
+        let s2c = cipher(b"s2c", b"s2c-hp");
+        if is_client {
+            ObfsSessionKeys {
+                send: c2s,
+                recv: s2c,
+            }
+        } else {
+            ObfsSessionKeys {
+                send: s2c,
+                recv: c2s,
+            }
+        }
+    }
+}
+
+fn current_auth_slot() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+        / OBFS_AUTH_SLOT_SECS
+}
+
+/// Rejects client randoms that were already seen within the auth window, so a
+/// recorded first flight cannot be replayed to make the listener answer.
+#[derive(Default)]
+struct ObfsReplayFilter {
+    seen: Mutex<HashMap<[u8; OBFS_RANDOM_LEN], Instant>>,
+}
+
+impl ObfsReplayFilter {
+    fn check_and_insert(&self, client_random: [u8; OBFS_RANDOM_LEN]) -> bool {
+        let window = Duration::from_secs(OBFS_AUTH_SLOT_SECS * 3);
+        let mut seen = self.seen.lock().unwrap();
+        if seen.len() >= 4096 {
+            seen.retain(|_, t| t.elapsed() < window);
+        }
+        match seen.get(&client_random) {
+            Some(t) if t.elapsed() < window => false,
+            _ => {
+                seen.insert(client_random, Instant::now());
+                true
+            }
+        }
+    }
+}
+
+struct ObfsCipher {
+    aead: LessSafeKey,
+    hp: HeaderProtectionKey,
+}
+
+impl ObfsCipher {
+    fn new(key: &[u8; 32], hp_key: &[u8; 32]) -> Self {
+        Self {
+            aead: LessSafeKey::new(UnboundKey::new(&aead::CHACHA20_POLY1305, key).unwrap()),
+            hp: HeaderProtectionKey::new(&quic::CHACHA20, hp_key).unwrap(),
+        }
+    }
+
+    fn nonce(counter: u64) -> Nonce {
+        let mut nonce = [0u8; aead::NONCE_LEN];
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+        Nonce::assume_unique_for_key(nonce)
+    }
+
+    fn length_mask(&self, counter: u64) -> u16 {
+        let mut sample = [0u8; 16];
+        sample[..8].copy_from_slice(&counter.to_le_bytes());
+        let mask = self.hp.new_mask(&sample).unwrap();
+        u16::from_be_bytes([mask[0], mask[1]])
+    }
+
+    fn seal(
+        &self,
+        counter: u64,
+        mimic: ObfsMimic,
+        inner: &[u8],
+        pad_len: usize,
+    ) -> Result<Vec<u8>, TunnelError> {
+        let body_len = inner.len() + pad_len + OBFS_MIN_FRAME_LEN;
+        if body_len > OBFS_MAX_FRAME_LEN {
+            return Err(TunnelError::ExceedMaxPacketSize(
+                OBFS_MAX_FRAME_LEN,
+                body_len,
+            ));
+        }
+        let mut out = Vec::with_capacity(mimic.header_len() + body_len);
+        match mimic {
+            ObfsMimic::None => {
+                out.put_u16(body_len as u16 ^ self.length_mask(counter));
+            }
+            ObfsMimic::Tls => {
+                out.put_slice(&[TLS_CONTENT_APPLICATION_DATA, 0x03, 0x03]);
+                out.put_u16(body_len as u16);
+            }
+        }
+
+        let mut body = Vec::with_capacity(body_len);
+        body.put_slice(inner);
+        body.put_bytes(0, pad_len);
+        body.put_u16(pad_len as u16);
+        self.aead
+            .seal_in_place_append_tag(Self::nonce(counter), Aad::from(&out[..]), &mut body)
+            .unwrap();
+        out.extend_from_slice(&body);
+        Ok(out)
+    }
+}
+
+struct ObfsSessionKeys {
+    send: ObfsCipher,
+    recv: ObfsCipher,
+}
+
+/// Seals the tcp framed packet into one obfs frame with random padding. Packets
+/// the peer's [`FramedReader`] would reject are refused here instead, so they
+/// don't tear the tunnel down on the other side.
+pub struct ObfsZCPacketToBytes {
+    cipher: ObfsCipher,
+    mimic: ObfsMimic,
+    counter: AtomicU64,
+}
+
+impl ObfsZCPacketToBytes {
+    fn new(cipher: ObfsCipher, mimic: ObfsMimic, counter: u64) -> Self {
+        Self {
+            cipher,
+            mimic,
+            counter: AtomicU64::new(counter),
+        }
+    }
+}
+
+impl ZCPacketToBytes for ObfsZCPacketToBytes {
+    fn zcpacket_into_bytes(&self, item: ZCPacket) -> Result<Bytes, TunnelError> {
+        let inner = TcpZCPacketToBytes.zcpacket_into_bytes(item)?;
+        let max_len = TCP_TUNNEL_HEADER_SIZE + OBFS_MTU_BYTES;
+        if inner.len() > max_len {
+            return Err(TunnelError::ExceedMaxPacketSize(max_len, inner.len()));
+        }
+        let pad_len = rand::thread_rng()
+            .gen_range(0..=OBFS_MAX_PADDING)
+            .min(OBFS_MAX_FRAME_LEN - OBFS_MIN_FRAME_LEN - inner.len());
+        let counter = self.counter.fetch_add(1, Ordering::Relaxed);
+        Ok(self
+            .cipher
+            .seal(counter, self.mimic, &inner, pad_len)?
+            .into())
+    }
+}
+
+/// Opens obfs frames and yields the tcp framed stream they carry, so the usual
+/// [`FramedReader`] can be stacked on top of it.
+pub struct ObfsReader<R> {
+    reader: R,
+    cipher: ObfsCipher,
+    mimic: ObfsMimic,
+    counter: u64,
+    raw: BytesMut,
+    plain: BytesMut,
+}
+
+impl<R> ObfsReader<R> {
+    fn new(reader: R, cipher: ObfsCipher, mimic: ObfsMimic) -> Self {
+        Self {
+            reader,
+            cipher,
+            mimic,
+            counter: 0,
+            raw: BytesMut::with_capacity(OBFS_MAX_FRAME_LEN),
+            plain: BytesMut::with_capacity(OBFS_MTU_BYTES),
+        }
+    }
+
+    /// Returns `Ok(false)` when more raw bytes are needed.
+    fn open_one_frame(&mut self) -> Result<bool, TunnelError> {
+        let header_len = self.mimic.header_len();
+        if self.raw.len() < header_len {
+            return Ok(false);
+        }
+
+        let body_len = match self.mimic {
+            ObfsMimic::None => {
+                (u16::from_be_bytes([self.raw[0], self.raw[1]])
+                    ^ self.cipher.length_mask(self.counter)) as usize
+            }
+            ObfsMimic::Tls => {
+                let len = u16::from_be_bytes([self.raw[3], self.raw[4]]) as usize;
+                match self.raw[0] {
+                    TLS_CONTENT_APPLICATION_DATA => {}
+                    TLS_CONTENT_CHANGE_CIPHER_SPEC if len <= 1 => {
+                        if self.raw.len() < header_len + len {
+                            return Ok(false);
+                        }
+                        self.raw.advance(header_len + len);
+                        return Ok(true);
+                    }
+                    t => {
+                        return Err(TunnelError::InvalidPacket(format!(
+                            "unexpected tls record type: {t}"
+                        )));
+                    }
+                }
+                len
+            }
+        };
+        if !(OBFS_MIN_FRAME_LEN..=OBFS_MAX_FRAME_LEN).contains(&body_len) {
+            return Err(TunnelError::InvalidPacket(format!(
+                "invalid obfs frame len: {body_len}"
+            )));
+        }
+        if self.raw.len() < header_len + body_len {
+            return Ok(false);
+        }
+
+        let header = self.raw.split_to(header_len);
+        let mut body = self.raw.split_to(body_len);
+        let plain = self
+            .cipher
+            .aead
+            .open_in_place(
+                ObfsCipher::nonce(self.counter),
+                Aad::from(&header[..]),
+                &mut body,
+            )
+            .map_err(|_| TunnelError::InvalidPacket("obfs frame decrypt failed".to_string()))?;
+        self.counter += 1;
+
+        let plain_len = plain.len();
+        let pad_len = u16::from_be_bytes([plain[plain_len - 2], plain[plain_len - 1]]) as usize;
+        let Some(inner_len) = plain_len.checked_sub(pad_len + OBFS_PAD_LEN_SIZE) else {
+            return Err(TunnelError::InvalidPacket(format!(
+                "invalid obfs padding len: {pad_len}"
+            )));
+        };
+        self.plain.extend_from_slice(&plain[..inner_len]);
+        Ok(true)
+    }
+}
+
+impl<R> AsyncRead for ObfsReader<R>
+where
+    R: AsyncRead + Unpin,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if !this.plain.is_empty() {
+                let n = this.plain.len().min(buf.remaining());
+                buf.put_slice(&this.plain.split_to(n));
+                return Poll::Ready(Ok(()));
+            }
+
+            match this.open_one_frame() {
+                Ok(true) => continue,
+                Ok(false) => {}
+                Err(e) => {
+                    return Poll::Ready(Err(std::io::Error::new(
+                        std::io::ErrorKind::InvalidData,
+                        e,
+                    )));
+                }
+            }
+
+            this.raw.reserve(OBFS_MAX_FRAME_LEN);
+            let n = ready!(poll_read_buf(Pin::new(&mut this.reader), cx, &mut this.raw))?;
+            if n == 0 {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut buf = [0u8; N];
+    rand::thread_rng().fill_bytes(&mut buf);
+    buf
+}
+
+fn put_u24(buf: &mut Vec<u8>, v: usize) {
+    buf.put_slice(&(v as u32).to_be_bytes()[1..]);
+}
+
+fn put_tls_ext(buf: &mut Vec<u8>, ext_type: u16, data: &[u8]) {
+    buf.put_u16(ext_type);
+    buf.put_u16(data.len() as u16);
+    buf.put_slice(data);
+}
+
+fn wrap_tls_record(content_type: u8, version: u16, body: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(TLS_RECORD_HEADER_LEN + body.len());
+    record.put_u8(content_type);
+    record.put_u16(version);
+    record.put_u16(body.len() as u16);
+    record.put_slice(body);
+    record
+}
+
+fn wrap_tls_handshake(handshake_type: u8, body: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(4 + body.len());
+    msg.put_u8(handshake_type);
+    put_u24(&mut msg, body.len());
+    msg.put_slice(body);
+    msg
+}
+
+/// A TLS 1.3 ClientHello whose random and session id carry the obfs client
+/// random and auth tag.
+fn build_client_hello(
+    client_random: &[u8; OBFS_RANDOM_LEN],
+    auth: &[u8; 32],
+    sni: Option<&str>,
+) -> Vec<u8> {
+    let mut exts = Vec::new();
+    if let Some(sni) = sni {
+        let mut data = Vec::new();
+        data.put_u16((sni.len() + 3) as u16);
+        data.put_u8(0);
+        data.put_u16(sni.len() as u16);
+        data.put_slice(sni.as_bytes());
+        put_tls_ext(&mut exts, 0x0000, &data);
+    }
+    put_tls_ext(
+        &mut exts,
+        0x000a,
+        &[0x00, 0x06, 0x00, 0x1d, 0x00, 0x17, 0x00, 0x18],
+    );
+    put_tls_ext(&mut exts, 0x000b, &[0x01, 0x00]);
+    put_tls_ext(
+        &mut exts,
+        0x000d,
+        &[
+            0x00, 0x10, 0x04, 0x03, 0x08, 0x04, 0x04, 0x01, 0x05, 0x03, 0x08, 0x05, 0x05, 0x01,
+            0x08, 0x06, 0x06, 0x01,
+        ],
+    );
+    put_tls_ext(
+        &mut exts,
+        0x0010,
+        &[
+            0x00, 0x0c, 0x02, b'h', b'2', 0x08, b'h', b't', b't', b'p', b'/', b'1', b'.', b'1',
+        ],
+    );
+    put_tls_ext(&mut exts, 0x002b, &[0x04, 0x03, 0x04, 0x03, 0x03]);
+    put_tls_ext(&mut exts, 0x002d, &[0x01, 0x01]);
+    let mut key_share = Vec::with_capacity(38);
+    key_share.put_u16(36);
+    key_share.put_u16(TLS_X25519);
+    key_share.put_u16(32);
+    key_share.put_slice(&random_bytes::<32>());
+    put_tls_ext(&mut exts, 0x0033, &key_share);
+    // the padding extension randomizes the hello size
+    let pad_len = rand::thread_rng().gen_range(0..=OBFS_MAX_PADDING);
+    put_tls_ext(&mut exts, 0x0015, &vec![0u8; pad_len]);
+
+    let mut hello = Vec::with_capacity(128 + exts.len());
+    hello.put_u16(0x0303);
+    hello.put_slice(client_random);
+    hello.put_u8(auth.len() as u8);
+    hello.put_slice(auth);
+    let suites: [u16; 9] = [
+        0x1301, 0x1302, 0x1303, 0xc02b, 0xc02f, 0xc02c, 0xc030, 0xcca9, 0xcca8,
+    ];
+    hello.put_u16((suites.len() * 2) as u16);
+    suites.iter().for_each(|s| hello.put_u16(*s));
+    hello.put_slice(&[0x01, 0x00]);
+    hello.put_u16(exts.len() as u16);
+    hello.put_slice(&exts);
+
+    wrap_tls_record(
+        TLS_CONTENT_HANDSHAKE,
+        0x0301,
+        &wrap_tls_handshake(TLS_HANDSHAKE_CLIENT_HELLO, &hello),
+    )
+}
+
+fn build_server_hello(server_random: &[u8; OBFS_RANDOM_LEN], session_id: &[u8]) -> Vec<u8> {
+    let mut exts = Vec::new();
+    put_tls_ext(&mut exts, 0x002b, &[0x03, 0x04]);
+    let mut key_share = Vec::with_capacity(36);
+    key_share.put_u16(TLS_X25519);
+    key_share.put_u16(32);
+    key_share.put_slice(&random_bytes::<32>());
+    put_tls_ext(&mut exts, 0x0033, &key_share);
+
+    let mut hello = Vec::with_capacity(128);
+    hello.put_u16(0x0303);
+    hello.put_slice(server_random);
+    hello.put_u8(session_id.len() as u8);
+    hello.put_slice(session_id);
+    hello.put_u16(0x1301);
+    hello.put_u8(0);
+    hello.put_u16(exts.len() as u16);
+    hello.put_slice(&exts);
+
+    let mut flight = wrap_tls_record(
+        TLS_CONTENT_HANDSHAKE,
+        0x0303,
+        &wrap_tls_handshake(TLS_HANDSHAKE_SERVER_HELLO, &hello),
+    );
+    flight.extend_from_slice(&wrap_tls_record(
+        TLS_CONTENT_CHANGE_CIPHER_SPEC,
+        0x0303,
+        &[1],
+    ));
+    flight
+}
+
+/// Reads one TLS handshake record and returns its handshake message body.
+async fn read_tls_handshake(
+    stream: &mut TcpStream,
+    handshake_type: u8,
+) -> Result<Vec<u8>, TunnelError> {
+    let mut header = [0u8; TLS_RECORD_HEADER_LEN];
+    stream.read_exact(&mut header).await?;
+    let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+    if header[0] != TLS_CONTENT_HANDSHAKE
+        || header[1] != 0x03
+        || !(4..=TLS_MAX_HELLO_LEN).contains(&len)
+    {
+        return Err(TunnelError::InvalidPacket(
+            "not a tls handshake record".to_string(),
+        ));
+    }
+    let mut record = vec![0u8; len];
+    stream.read_exact(&mut record).await?;
+    if record[0] != handshake_type {
+        return Err(TunnelError::InvalidPacket(format!(
+            "unexpected tls handshake type: {}",
+            record[0]
+        )));
+    }
+    Ok(record.split_off(4))
+}
+
+/// Reads the 32 byte random and the session id out of a hello message body.
+fn parse_hello_random(hello: &[u8]) -> Option<([u8; OBFS_RANDOM_LEN], &[u8])> {
+    let random = hello.get(2..2 + OBFS_RANDOM_LEN)?.try_into().ok()?;
+    let sid_len = *hello.get(2 + OBFS_RANDOM_LEN)? as usize;
+    let sid_start = 3 + OBFS_RANDOM_LEN;
+    let sid = hello.get(sid_start..sid_start + sid_len)?;
+    Some((random, sid))
+}
+
+async fn read_plain_padding(
+    stream: &mut TcpStream,
+    masked_len: u8,
+    mask: u8,
+) -> Result<(), TunnelError> {
+    let mut padding = vec![0u8; (masked_len ^ mask) as usize];
+    stream.read_exact(&mut padding).await?;
+    Ok(())
+}
+
+fn plain_flight(random: &[u8], auth: &[u8]) -> Vec<u8> {
+    let pad_len = rand::thread_rng().gen_range(0..=OBFS_MAX_PADDING);
+    let mut flight = Vec::with_capacity(OBFS_RANDOM_LEN + auth.len() + 1 + pad_len);
+    flight.put_slice(random);
+    flight.put_slice(&auth[..OBFS_PLAIN_AUTH_LEN]);
+    flight.put_u8(pad_len as u8 ^ auth[OBFS_PLAIN_AUTH_LEN]);
+    let mut padding = vec![0u8; pad_len];
+    rand::thread_rng().fill_bytes(&mut padding);
+    flight.put_slice(&padding);
+    flight
+}
+
+/// The first sealed frame of each side, padding only. It stands in for the
+/// encrypted handshake messages that follow the hellos in real TLS.
+fn initial_padding_frame(
+    cipher: &ObfsCipher,
+    mimic: ObfsMimic,
+    is_client: bool,
+) -> Result<Vec<u8>, TunnelError> {
+    let pad_len = match (mimic, is_client) {
+        (ObfsMimic::Tls, false) => rand::thread_rng().gen_range(600..=1400),
+        (ObfsMimic::Tls, true) => rand::thread_rng().gen_range(40..=120),
+        (ObfsMimic::None, _) => rand::thread_rng().gen_range(0..=OBFS_MAX_PADDING),
+    };
+    let mut flight = Vec::new();
+    if mimic == ObfsMimic::Tls && is_client {
+        flight.extend_from_slice(&wrap_tls_record(
+            TLS_CONTENT_CHANGE_CIPHER_SPEC,
+            0x0303,
+            &[1],
+        ));
+    }
+    flight.extend_from_slice(&cipher.seal(0, mimic, &[], pad_len)?);
+    Ok(flight)
+}
+
+async fn client_handshake(
+    stream: &mut TcpStream,
+    config: &ObfsConfig,
+    mimic: ObfsMimic,
+    sni: Option<&str>,
+) -> Result<ObfsSessionKeys, TunnelError> {
+    let client_random = random_bytes::<OBFS_RANDOM_LEN>();
+    let auth = config.client_auth(&client_random, current_auth_slot());
+
+    let server_random: [u8; OBFS_RANDOM_LEN] = match mimic {
+        ObfsMimic::None => {
+            stream
+                .write_all(&plain_flight(&client_random, &auth))
+                .await?;
+            let mut head = [0u8; OBFS_RANDOM_LEN + 1];
+            stream.read_exact(&mut head).await?;
+            let server_random: [u8; OBFS_RANDOM_LEN] = head[..OBFS_RANDOM_LEN].try_into().unwrap();
+            let server_auth = config.server_auth(&client_random, &server_random[..16]);
+            read_plain_padding(stream, head[OBFS_RANDOM_LEN], server_auth[16]).await?;
+            server_random
+        }
+        ObfsMimic::Tls => {
+            stream
+                .write_all(&build_client_hello(&client_random, &auth, sni))
+                .await?;
+            let hello = read_tls_handshake(stream, TLS_HANDSHAKE_SERVER_HELLO).await?;
+            let (server_random, _) = parse_hello_random(&hello)
+                .ok_or_else(|| TunnelError::InvalidPacket("malformed server hello".to_string()))?;
+            server_random
+        }
+    };
+
+    let server_auth = config.server_auth(&client_random, &server_random[..16]);
+    if server_auth[..16] != server_random[16..] {
+        return Err(TunnelError::InvalidPacket(
+            "obfs server auth failed, network secret mismatch?".to_string(),
+        ));
+    }
+
+    Ok(config.session_keys(&client_random, &server_random, true))
+}
+
+async fn server_handshake(
+    stream: &mut TcpStream,
+    configs: &[ObfsConfig],
+    mimic: ObfsMimic,
+    replay_filter: &ObfsReplayFilter,
+) -> Result<ObfsSessionKeys, TunnelError> {
+    let (client_random, auth, plain_pad_len) = match mimic {
+        ObfsMimic::None => {
+            let mut head = [0u8; OBFS_RANDOM_LEN + OBFS_PLAIN_AUTH_LEN + 1];
+            stream.read_exact(&mut head).await?;
+            let client_random: [u8; OBFS_RANDOM_LEN] = head[..OBFS_RANDOM_LEN].try_into().unwrap();
+            (
+                client_random,
+                head[OBFS_RANDOM_LEN..OBFS_RANDOM_LEN + OBFS_PLAIN_AUTH_LEN].to_vec(),
+                Some(head[OBFS_RANDOM_LEN + OBFS_PLAIN_AUTH_LEN]),
+            )
+        }
+        ObfsMimic::Tls => {
+            let hello = read_tls_handshake(stream, TLS_HANDSHAKE_CLIENT_HELLO).await?;
+            let (client_random, session_id) = parse_hello_random(&hello)
+                .ok_or_else(|| TunnelError::InvalidPacket("malformed client hello".to_string()))?;
+            (client_random, session_id.to_vec(), None)
+        }
+    };
+
+    if auth.len() < OBFS_PLAIN_AUTH_LEN {
+        return Err(TunnelError::InvalidPacket(
+            "obfs client auth too short".to_string(),
+        ));
+    }
+    let Some((config, client_auth)) = configs
+        .iter()
+        .find_map(|c| Some((c, c.verify_client_auth(&client_random, &auth)?)))
+    else {
+        return Err(TunnelError::InvalidPacket(
+            "obfs client auth failed".to_string(),
+        ));
+    };
+    if !replay_filter.check_and_insert(client_random) {
+        return Err(TunnelError::InvalidPacket(
+            "obfs client hello replayed".to_string(),
+        ));
+    }
+
+    let mut server_random = random_bytes::<OBFS_RANDOM_LEN>();
+    let server_auth = config.server_auth(&client_random, &server_random[..16]);
+    server_random[16..].copy_from_slice(&server_auth[..16]);
+
+    match plain_pad_len {
+        Some(masked_len) => {
+            // the auth byte following the truncated tag masks the padding len
+            read_plain_padding(stream, masked_len, client_auth[OBFS_PLAIN_AUTH_LEN]).await?;
+
+            let pad_len = rand::thread_rng().gen_range(0..=OBFS_MAX_PADDING);
+            let mut flight = Vec::with_capacity(OBFS_RANDOM_LEN + 1 + pad_len);
+            flight.put_slice(&server_random);
+            flight.put_u8(pad_len as u8 ^ server_auth[16]);
+            let mut padding = vec![0u8; pad_len];
+            rand::thread_rng().fill_bytes(&mut padding);
+            flight.put_slice(&padding);
+            stream.write_all(&flight).await?;
+        }
+        None => {
+            stream
+                .write_all(&build_server_hello(&server_random, &auth))
+                .await?;
+        }
+    }
+
+    Ok(config.session_keys(&client_random, &server_random, false))
+}
+
+/// Keeps a connection that failed authentication open without answering, so
+/// a prober cannot tell the listener apart from a silent service.
+async fn hold_probe_connection(mut stream: TcpStream) {
+    let hold = Duration::from_secs(rand::thread_rng().gen_range(OBFS_PROBE_HOLD_SECS));
+    let _ = timeout(hold, async {
+        let mut buf = [0u8; 1024];
+        while let Ok(n) = stream.read(&mut buf).await {
+            if n == 0 {
+                break;
+            }
+        }
+    })
+    .await;
+}
+
+async fn finish_tunnel(
+    mut stream: TcpStream,
+    keys: ObfsSessionKeys,
+    mimic: ObfsMimic,
+    is_client: bool,
+    info: TunnelInfo,
+) -> Result<Box<dyn Tunnel>, TunnelError> {
+    if let Err(e) = stream.set_nodelay(true) {
+        tracing::warn!(?e, "set_nodelay fail in obfs tunnel");
+    }
+    stream
+        .write_all(&initial_padding_frame(&keys.send, mimic, is_client)?)
+        .await?;
+
+    let (r, w) = stream.into_split();
+    Ok(Box::new(TunnelWrapper::new(
+        FramedReader::new(ObfsReader::new(r, keys.recv, mimic), OBFS_MTU_BYTES),
+        FramedWriter::new_with_converter(w, ObfsZCPacketToBytes::new(keys.send, mimic, 1)),
+        Some(info),
+    )))
+}
+
+type ObfsHandshakeResult = Result<Box<dyn Tunnel>, TunnelError>;
+
+pub struct ObfsTunnelListener {
+    addr: url::Url,
+    config: ObfsConfig,
+    config_provider: Option<ObfsConfigProvider>,
+    replay_filter: Arc<ObfsReplayFilter>,
+    listener: Option<TcpListener>,
+    handshakes: JoinSet<ObfsHandshakeResult>,
+    socket_mark: Option<u32>,
+}
+
+impl std::fmt::Debug for ObfsTunnelListener {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObfsTunnelListener")
+            .field("addr", &self.addr)
+            .field("pending_handshakes", &self.handshakes.len())
+            .finish()
+    }
+}
+
+impl ObfsTunnelListener {
+    pub fn new(addr: url::Url, config: ObfsConfig) -> Self {
+        ObfsTunnelListener {
+            addr,
+            config,
+            config_provider: None,
+            replay_filter: Arc::new(ObfsReplayFilter::default()),
+            listener: None,
+            handshakes: JoinSet::new(),
+            socket_mark: None,
+        }
+    }
+
+    pub fn set_socket_mark(&mut self, socket_mark: Option<u32>) {
+        self.socket_mark = socket_mark;
+    }
+
+    /// Keys to accept in addition to the one given at construction, e.g. both
+    /// sides of a network secret rotation.
+    pub fn set_config_provider(&mut self, provider: Option<ObfsConfigProvider>) {
+        self.config_provider = provider;
+    }
+
+    fn spawn_handshake(
+        &mut self,
+        stream: TcpStream,
+        peer_addr: SocketAddr,
+    ) -> Result<(), TunnelError> {
+        let mimic = ObfsMimic::from_url(&self.addr)?;
+        let mut configs = vec![self.config.clone()];
+        if let Some(provider) = &self.config_provider {
+            configs.extend(provider());
+        }
+        let replay_filter = self.replay_filter.clone();
+        let remote_addr: crate::proto::common::Url =
+            super::build_url_from_socket_addr(&peer_addr.to_string(), "obfs").into();
+        let info = TunnelInfo {
+            tunnel_type: "obfs".to_owned(),
+            local_addr: Some(self.local_url().into()),
+            remote_addr: Some(remote_addr.clone()),
+            resolved_remote_addr: Some(remote_addr),
+        };
+
+        self.handshakes.spawn(async move {
+            let mut stream = stream;
+            let ret = timeout(
+                OBFS_HANDSHAKE_TIMEOUT,
+                server_handshake(&mut stream, &configs, mimic, &replay_filter),
+            )
+            .await;
+            match ret {
+                Ok(Ok(keys)) => finish_tunnel(stream, keys, mimic, false, info).await,
+                Ok(Err(e)) => {
+                    hold_probe_connection(stream).await;
+                    Err(e)
+                }
+                Err(e) => {
+                    hold_probe_connection(stream).await;
+                    Err(e.into())
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl TunnelListener for ObfsTunnelListener {
+    async fn listen(&mut self) -> Result<(), TunnelError> {
+        self.listener = None;
+        ObfsMimic::from_url(&self.addr)?;
+
+        let addr = SocketAddr::from_url(self.addr.clone(), IpVersion::Both).await?;
+        let listener = bind::<TcpListener>()
+            .addr(addr)
+            .only_v6(true)
+            .maybe_socket_mark(self.socket_mark)
+            .call()?;
+
+        self.addr
+            .set_port(Some(listener.local_addr()?.port()))
+            .unwrap();
+        self.listener = Some(listener);
+
+        Ok(())
+    }
+
+    async fn accept(&mut self) -> Result<Box<dyn Tunnel>, TunnelError> {
+        enum Event {
+            Accepted(std::io::Result<(TcpStream, SocketAddr)>),
+            Handshaked(Result<ObfsHandshakeResult, tokio::task::JoinError>),
+        }
+
+        loop {
+            let listener = self.listener.as_ref().unwrap();
+            let event = tokio::select! {
+                ret = listener.accept() => Event::Accepted(ret),
+                Some(ret) = self.handshakes.join_next() => Event::Handshaked(ret),
+            };
+
+            match event {
+                Event::Accepted(Ok((stream, peer_addr))) => {
+                    if self.handshakes.len() >= OBFS_MAX_PENDING_HANDSHAKES {
+                        tracing::warn!(?peer_addr, "too many pending obfs handshakes, drop conn");
+                        continue;
+                    }
+                    self.spawn_handshake(stream, peer_addr)?;
+                }
+                Event::Accepted(Err(e)) => {
+                    use std::io::ErrorKind::*;
+                    if matches!(
+                        e.kind(),
+                        NotConnected | ConnectionAborted | ConnectionRefused | ConnectionReset
+                    ) {
+                        tracing::warn!(?e, "accept fail with retryable error: {:?}", e);
+                        continue;
+                    }
+                    tracing::warn!(?e, "accept fail");
+                    return Err(e.into());
+                }
+                Event::Handshaked(Ok(Ok(tunnel))) => return Ok(tunnel),
+                Event::Handshaked(Ok(Err(e))) => {
+                    tracing::debug!(?e, "obfs handshake failed");
+                }
+                Event::Handshaked(Err(e)) => {
+                    tracing::warn!(?e, "obfs handshake task failed");
+                }
+            }
+        }
+    }
+
+    fn local_url(&self) -> url::Url {
+        self.addr.clone()
+    }
+}
+
+pub struct ObfsTunnelConnector {
+    addr: url::Url,
+    config: ObfsConfig,
+
+    bind_addrs: Vec<SocketAddr>,
+    ip_version: IpVersion,
+    resolved_addr: Option<SocketAddr>,
+    socket_mark: Option<u32>,
+}
+
+impl std::fmt::Debug for ObfsTunnelConnector {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ObfsTunnelConnector")
+            .field("addr", &self.addr)
+            .field("bind_addrs", &self.bind_addrs)
+            .field("ip_version", &self.ip_version)
+            .finish()
+    }
+}
+
+impl ObfsTunnelConnector {
+    pub fn new(addr: url::Url, config: ObfsConfig) -> Self {
+        ObfsTunnelConnector {
+            addr,
+            config,
+            bind_addrs: vec![],
+            ip_version: IpVersion::Both,
+            resolved_addr: None,
+            socket_mark: None,
+        }
+    }
+
+    fn sni(&self) -> Option<String> {
+        self.addr
+            .query_pairs()
+            .find(|(k, _)| k == "sni")
+            .map(|(_, v)| v.into_owned())
+            .or_else(|| self.addr.domain().map(|d| d.to_owned()))
+    }
+
+    async fn connect_with(
+        &self,
+        addr: SocketAddr,
+        socket: TcpSocket,
+    ) -> Result<Box<dyn Tunnel>, TunnelError> {
+        let mimic = ObfsMimic::from_url(&self.addr)?;
+        let mut stream = socket.connect(addr).await?;
+        tracing::info!(url = ?self.addr, ?addr, "connect obfs tcp succ");
+
+        let info = TunnelInfo {
+            tunnel_type: "obfs".to_owned(),
+            local_addr: Some(
+                super::build_url_from_socket_addr(&stream.local_addr()?.to_string(), "obfs").into(),
+            ),
+            remote_addr: Some(self.addr.clone().into()),
+            resolved_remote_addr: Some(
+                super::build_url_from_socket_addr(&addr.to_string(), "obfs").into(),
+            ),
+        };
+
+        let sni = self.sni();
+        let keys = timeout(
+            OBFS_HANDSHAKE_TIMEOUT,
+            client_handshake(&mut stream, &self.config, mimic, sni.as_deref()),
+        )
+        .await??;
+        finish_tunnel(stream, keys, mimic, true, info).await
+    }
+
+    async fn connect_with_default_bind(
+        &self,
+        addr: SocketAddr,
+    ) -> Result<Box<dyn Tunnel>, TunnelError> {
+        let socket = if addr.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+        apply_socket_mark(&socket2::SockRef::from(&socket), self.socket_mark)?;
+        self.connect_with(addr, socket).await
+    }
+
+    async fn connect_with_custom_bind(
+        &self,
+        addr: SocketAddr,
+    ) -> Result<Box<dyn Tunnel>, TunnelError> {
+        let futures = FuturesUnordered::new();
+
+        for bind_addr in self.bind_addrs.iter() {
+            tracing::info!(?bind_addr, ?addr, "bind addr");
+            match bind::<TcpSocket>()
+                .addr(*bind_addr)
+                .only_v6(true)
+                .maybe_socket_mark(self.socket_mark)
+                .call()
+            {
+                Ok(socket) => futures.push(self.connect_with(addr, socket)),
+                Err(error) => {
+                    tracing::error!(?bind_addr, ?addr, ?error, "bind addr fail");
+                    continue;
+                }
+            }
+        }
+
+        wait_for_connect_futures(futures).await
+    }
+}
+
+#[async_trait]
+impl TunnelConnector for ObfsTunnelConnector {
+    async fn connect(&mut self) -> Result<Box<dyn Tunnel>, TunnelError> {
+        let addr = match self.resolved_addr {
+            Some(addr) => addr,
+            None => SocketAddr::from_url(self.addr.clone(), self.ip_version).await?,
+        };
+        if self.bind_addrs.is_empty() {
+            self.connect_with_default_bind(addr).await
+        } else {
+            self.connect_with_custom_bind(addr).await
+        }
+    }
+
+    fn remote_url(&self) -> url::Url {
+        self.addr.clone()
+    }
+
+    fn set_bind_addrs(&mut self, addrs: Vec<SocketAddr>) {
+        self.bind_addrs = addrs;
+    }
+
+    fn set_ip_version(&mut self, ip_version: IpVersion) {
+        self.ip_version = ip_version;
+    }
+
+    fn set_resolved_addr(&mut self, addr: SocketAddr) {
+        self.resolved_addr = Some(addr);
+    }
+
+    fn set_socket_mark(&mut self, socket_mark: Option<u32>) {
+        self.socket_mark = socket_mark;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tunnel::{
+        common::tests::{_tunnel_bench, _tunnel_pingpong},
+        packet_def::{PEER_MANAGER_HEADER_SIZE, ZCPacketType},
+    };
+
+    use super::*;
+
+    fn test_config() -> ObfsConfig {
+        ObfsConfig::new_from_network_identity("net", "secret")
+    }
+
+    #[tokio::test]
+    async fn obfs_pingpong() {
+        let listener =
+            ObfsTunnelListener::new("obfs://0.0.0.0:31041".parse().unwrap(), test_config());
+        let connector =
+            ObfsTunnelConnector::new("obfs://127.0.0.1:31041".parse().unwrap(), test_config());
+        _tunnel_pingpong(listener, connector).await
+    }
+
+    #[tokio::test]
+    async fn obfs_tls_mimic_pingpong() {
+        let listener = ObfsTunnelListener::new(
+            "obfs://0.0.0.0:31042?mimic=tls".parse().unwrap(),
+            test_config(),
+        );
+        let connector = ObfsTunnelConnector::new(
+            "obfs://127.0.0.1:31042?mimic=tls&sni=www.example.com"
+                .parse()
+                .unwrap(),
+            test_config(),
+        );
+        _tunnel_pingpong(listener, connector).await
+    }
+
+    #[tokio::test]
+    async fn obfs_bench() {
+        let listener =
+            ObfsTunnelListener::new("obfs://0.0.0.0:31043".parse().unwrap(), test_config());
+        let connector =
+            ObfsTunnelConnector::new("obfs://127.0.0.1:31043".parse().unwrap(), test_config());
+        _tunnel_bench(listener, connector).await
+    }
+
+    #[tokio::test]
+    async fn obfs_listener_stays_silent_on_wrong_key() {
+        let mut listener =
+            ObfsTunnelListener::new("obfs://127.0.0.1:0".parse().unwrap(), test_config());
+        listener.listen().await.unwrap();
+        let port = listener.local_url().port().unwrap();
+        let _accept_task = tokio::spawn(async move { listener.accept().await });
+
+        let mut connector = ObfsTunnelConnector::new(
+            format!("obfs://127.0.0.1:{port}").parse().unwrap(),
+            ObfsConfig::new_from_network_identity("net", "wrong"),
+        );
+        let err = connector.connect().await.unwrap_err();
+        assert!(matches!(err, TunnelError::Timeout(_)), "{err:?}");
+    }
+
+    #[test]
+    fn obfs_frames_have_no_fixed_layout() {
+        let config = test_config();
+        let keys = config.session_keys(&[1; 32], &[2; 32], true);
+        let peer = config.session_keys(&[1; 32], &[2; 32], false);
+        let sender = ObfsZCPacketToBytes::new(keys.send, ObfsMimic::None, 0);
+
+        let mut wire = BytesMut::new();
+        let mut lens = std::collections::HashSet::new();
+        for _ in 0..16 {
+            let frame = sender
+                .zcpacket_into_bytes(ZCPacket::new_with_payload(b"hello"))
+                .unwrap();
+            lens.insert(frame.len());
+            wire.extend_from_slice(&frame);
+        }
+        assert!(lens.len() > 1);
+
+        let mut reader = ObfsReader::new(tokio::io::empty(), peer.recv, ObfsMimic::None);
+        reader.raw = wire;
+        for _ in 0..16 {
+            assert!(reader.open_one_frame().unwrap());
+        }
+        let packet = ZCPacket::new_from_buf(
+            reader.plain.split_to(reader.plain.len() / 16),
+            ZCPacketType::TCP,
+        );
+        assert_eq!(packet.payload(), b"hello");
+    }
+
+    #[test]
+    fn obfs_max_sized_packet_roundtrip() {
+        let config = test_config();
+        let keys = config.session_keys(&[1; 32], &[2; 32], true);
+        let peer = config.session_keys(&[1; 32], &[2; 32], false);
+        let sender = ObfsZCPacketToBytes::new(keys.send, ObfsMimic::None, 0);
+
+        let payload = vec![0x5a; OBFS_MTU_BYTES - PEER_MANAGER_HEADER_SIZE];
+        let frame = sender
+            .zcpacket_into_bytes(ZCPacket::new_with_payload(&payload))
+            .unwrap();
+        let too_large = vec![0x5a; payload.len() + 1];
+        let err = sender
+            .zcpacket_into_bytes(ZCPacket::new_with_payload(&too_large))
+            .unwrap_err();
+        assert!(
+            matches!(err, TunnelError::ExceedMaxPacketSize(..)),
+            "{err:?}"
+        );
+
+        let mut reader = ObfsReader::new(tokio::io::empty(), peer.recv, ObfsMimic::None);
+        reader.raw = BytesMut::from(&frame[..]);
+        assert!(reader.open_one_frame().unwrap());
+        let packet = ZCPacket::new_from_buf(reader.plain.split(), ZCPacketType::TCP);
+        assert_eq!(packet.payload(), &payload[..]);
+
+        // the frame itself is capped no matter what the caller asks for
+        let max_inner = vec![0; OBFS_MAX_FRAME_LEN - OBFS_MIN_FRAME_LEN];
+        assert!(sender.cipher.seal(1, ObfsMimic::Tls, &max_inner, 0).is_ok());
+        let err = sender
+            .cipher
+            .seal(2, ObfsMimic::Tls, &max_inner, 1)
+            .unwrap_err();
+        assert!(
+            matches!(err, TunnelError::ExceedMaxPacketSize(..)),
+            "{err:?}"
+        );
+    }
+
+    #[test]
+    fn obfs_replay_filter_rejects_seen_random() {
+        let filter = ObfsReplayFilter::default();
+        assert!(filter.check_and_insert([7; 32]));
+        assert!(!filter.check_and_insert([7; 32]));
+        assert!(filter.check_and_insert([8; 32]));
+    }
+}
@@ -5,6 +5,8 @@ use zerocopy::FromBytes;
 use zerocopy::FromZeroes;
 use zerocopy::byteorder::*;
 
+use super::buf::{alloc_packet_buf, copy_packet_buf};
+
 type DefaultEndian = LittleEndian;
 
 const fn max(a: usize, b: usize) -> usize {
@@ -88,6 +90,8 @@ pub enum PacketType {
     NoiseHandshakeMsg3 = 15,
     RelayHandshake = 20,
     RelayHandshakeAck = 21,
+    // ethernet frame of tap mode
+    Ethernet = 22,
 
     // used internally,
     DataWithKcpSrcModified = 18,
@@ -101,8 +105,10 @@ bitflags::bitflags! {
         const EXIT_NODE = 0b0000_0100;
         const NO_PROXY = 0b0000_1000;
         const COMPRESSED = 0b0001_0000;
+        // reuses the deprecated KCP_SRC_MODIFIED bit, only set on connections
+        // that negotiated per-connection encryption.
+        const LINK_ENCRYPTED = 0b0010_0000;
         // deprecated flags, can be reused.
-        // const KCP_SRC_MODIFIED = 0b0010_0000;
         // const QUIC_SRC_MODIFIED = 0b1000_0000;
         const NOT_SEND_TO_TUN = 0b0100_0000;
 
@@ -244,6 +250,23 @@ impl PeerManagerHeader {
             .unwrap()
             .contains(PeerManagerHeaderFlags::NOT_SEND_TO_TUN)
     }
+
+    pub fn set_link_encrypted(&mut self, link_encrypted: bool) -> &mut Self {
+        let mut flags = PeerManagerHeaderFlags::from_bits(self.flags).unwrap();
+        if link_encrypted {
+            flags.insert(PeerManagerHeaderFlags::LINK_ENCRYPTED);
+        } else {
+            flags.remove(PeerManagerHeaderFlags::LINK_ENCRYPTED);
+        }
+        self.flags = flags.bits();
+        self
+    }
+
+    pub fn is_link_encrypted(&self) -> bool {
+        PeerManagerHeaderFlags::from_bits(self.flags)
+            .unwrap()
+            .contains(PeerManagerHeaderFlags::LINK_ENCRYPTED)
+    }
 }
 
 #[repr(C, packed)]
@@ -481,13 +504,18 @@ impl ZCPacket {
     pub fn new_with_payload(payload: &[u8]) -> Self {
         let mut ret = Self::new_nic_packet();
         let payload_off = ret.packet_type.get_packet_offsets().payload_offset;
-        let total_len = payload_off + payload.len();
-        ret.inner.reserve(total_len);
-        unsafe { ret.inner.set_len(total_len) };
-        ret.mut_payload()[..payload.len()].copy_from_slice(payload);
+        // pooled buffer with tail room, so compression and encryption never reallocate
+        ret.inner = alloc_packet_buf(payload_off, payload.len());
+        ret.inner.extend_from_slice(payload);
         ret
     }
 
+    /// Move the packet into a buffer of its own. Call it before holding a packet
+    /// for long, so it doesn't keep the chunk it was cut from allocated.
+    pub fn detach_buf(&mut self) {
+        self.inner = copy_packet_buf(&self.inner);
+    }
+
     pub fn new_for_tun(cap: usize, packet_info_len: usize) -> Self {
         let mut ret = Self::new_nic_packet();
         ret.inner.reserve(cap);
@@ -10,6 +10,10 @@ pub struct WindowLatency {
 
     sum: AtomicU32,
     count: AtomicU32,
+
+    // smoothed mean deviation between consecutive samples, as in rfc 3550
+    jitter_us: AtomicU32,
+    last_latency_us: AtomicU32,
 }
 
 impl std::fmt::Debug for WindowLatency {
@@ -31,15 +35,27 @@ impl WindowLatency {
 
             sum: AtomicU32::new(0),
             count: AtomicU32::new(0),
+
+            jitter_us: AtomicU32::new(0),
+            last_latency_us: AtomicU32::new(0),
         }
     }
 
     pub fn record_latency(&self, latency_us: u32) {
         let index = self.latency_us_window_index.fetch_add(1, Relaxed);
-        if self.count.load(Relaxed) < self.latency_us_window_size {
+        let prev_count = self.count.load(Relaxed);
+        if prev_count < self.latency_us_window_size {
             self.count.fetch_add(1, Relaxed);
         }
 
+        let last_lat = self.last_latency_us.swap(latency_us, Relaxed);
+        if prev_count > 0 {
+            let jitter = self.jitter_us.load(Relaxed) as i64;
+            let delta = (latency_us as i64 - last_lat as i64).abs();
+            self.jitter_us
+                .store((jitter + (delta - jitter) / 16) as u32, Relaxed);
+        }
+
         let index = index % self.latency_us_window_size;
         let old_lat = self.latency_us_window[index as usize].swap(latency_us, Relaxed);
 
@@ -59,6 +75,10 @@ impl WindowLatency {
             (T::from(sum)) / T::from(count)
         }
     }
+
+    pub fn get_jitter_us(&self) -> u32 {
+        self.jitter_us.load(Relaxed)
+    }
 }
 
 #[derive(Debug)]
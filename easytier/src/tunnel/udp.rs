@@ -8,7 +8,7 @@ use anyhow::Context;
 use async_trait::async_trait;
 use bytes::BytesMut;
 use dashmap::DashMap;
-use futures::{SinkExt, StreamExt, stream::FuturesUnordered};
+use futures::{FutureExt as _, SinkExt, StreamExt, stream::FuturesUnordered};
 use rand::{Rng, SeedableRng};
 use zerocopy::{AsBytes, FromBytes};
 
@@ -26,13 +26,14 @@ use super::{
     common::wait_for_connect_futures,
     packet_def::{UDP_TUNNEL_HEADER_SIZE, UDPTunnelHeader, V4HolePunchPacket, V6HolePunchPacket},
     ring::{RingSink, RingStream},
+    udp_batch::{MAX_BATCH_SIZE, UdpBatchReceiver, UdpBatchSender},
 };
 use crate::tunnel::common::bind;
 use crate::{
     common::{join_joinset_background, shrink_dashmap},
     tunnel::{
         build_url_from_socket_addr,
-        common::{TunnelWrapper, reserve_buf},
+        common::TunnelWrapper,
         packet_def::{UdpPacketType, ZCPacket, ZCPacketType},
         ring::RingTunnel,
     },
@@ -274,41 +275,59 @@ async fn forward_from_ring_to_udp(
     conn_id: u32,
 ) -> Option<TunnelError> {
     tracing::debug!("udp forward from ring to udp");
-    loop {
-        let buf = ring_recv.next().await?;
-        let packet = match buf {
-            Ok(v) => v,
-            Err(e) => {
-                return Some(e);
-            }
-        };
-
+    let into_udp_data = |packet: ZCPacket| {
         let mut packet = packet.convert_type(ZCPacketType::UDP);
         let udp_payload_len = packet.udp_payload().len();
         let header = packet.mut_udp_tunnel_header().unwrap();
         header.conn_id.set(conn_id);
         header.len.set(udp_payload_len as u16);
         header.msg_type = UdpPacketType::Data as u8;
+        packet.into_bytes()
+    };
+
+    let mut sender = UdpBatchSender::new();
+    let mut bufs = Vec::with_capacity(MAX_BATCH_SIZE);
+    loop {
+        match ring_recv.next().await? {
+            Ok(packet) => bufs.push(into_udp_data(packet)),
+            Err(e) => return Some(e),
+        }
 
-        let buf = packet.into_bytes();
-        tracing::trace!(?udp_payload_len, ?buf, "udp forward from ring to udp");
-        let ret = socket.send_to(&buf, &addr).await;
-        if ret.is_err() {
-            return Some(TunnelError::IOError(ret.unwrap_err()));
-        } else if ret.unwrap() == 0 {
-            return None;
+        // take packets already queued so they are sent with one syscall
+        let mut ret = None;
+        while bufs.len() < MAX_BATCH_SIZE {
+            match ring_recv.next().now_or_never() {
+                Some(Some(Ok(packet))) => bufs.push(into_udp_data(packet)),
+                Some(Some(Err(e))) => {
+                    ret = Some(Some(e));
+                    break;
+                }
+                Some(None) => {
+                    ret = Some(None);
+                    break;
+                }
+                None => break,
+            }
+        }
+
+        tracing::trace!(batch_len = bufs.len(), "udp forward from ring to udp");
+        if let Err(e) = sender.send(socket, addr, &bufs).await {
+            return Some(TunnelError::IOError(e));
+        }
+        bufs.clear();
+        if let Some(ret) = ret {
+            return ret;
         }
     }
 }
 
 async fn udp_recv_from_socket_forward_task(
     socket: &UdpSocket,
-    buf: &mut BytesMut,
+    receiver: &mut UdpBatchReceiver,
     allow_stun: bool,
 ) -> Result<(ZCPacket, SocketAddr), TunnelError> {
     loop {
-        reserve_buf(buf, UDP_DATA_MTU, UDP_DATA_MTU * 4);
-        let (dg_size, addr) = match socket.recv_buf_from(buf).await {
+        let (buf, addr) = match receiver.recv(socket).await {
             Ok(v) => v,
             Err(e) => {
                 tracing::error!(?e, "udp recv from socket error");
@@ -319,10 +338,10 @@ async fn udp_recv_from_socket_forward_task(
             "udp recv packet: {:?}, buf: {:?}, size: {}",
             addr,
             buf,
-            dg_size
+            buf.len()
         );
 
-        let zc_packet = match get_zcpacket_from_buf(buf.split(), allow_stun) {
+        let zc_packet = match get_zcpacket_from_buf(buf, allow_stun) {
             Ok(v) => v,
             Err(e) => {
                 tracing::warn!(?e, "udp get zc packet from buf error");
@@ -546,9 +565,9 @@ impl UdpTunnelListenerData {
 
     async fn do_forward_task(self) {
         let socket = self.socket.as_ref().unwrap().clone();
-        let mut buf = BytesMut::new();
+        let mut receiver = UdpBatchReceiver::new(&socket);
         loop {
-            match udp_recv_from_socket_forward_task(&socket, &mut buf, true).await {
+            match udp_recv_from_socket_forward_task(&socket, &mut receiver, true).await {
                 Ok((zc_packet, addr)) => self.do_forward_one_packet_to_conn(zc_packet, addr).await,
                 Err(e) => {
                     tracing::error!(?e, "udp recv packet error");
@@ -800,9 +819,9 @@ impl UdpTunnelConnector {
         let socket_clone = socket.clone();
 
         let recv_loop = async move {
-            let mut buf = BytesMut::new();
+            let mut receiver = UdpBatchReceiver::new(&socket_clone);
             loop {
-                match udp_recv_from_socket_forward_task(&socket_clone, &mut buf, false).await {
+                match udp_recv_from_socket_forward_task(&socket_clone, &mut receiver, false).await {
                     Ok((zc_packet, addr)) => {
                         tracing::trace!(?addr, "connector udp forward task done");
                         if let Err(e) = udp_conn.handle_packet_from_remote(zc_packet).await {
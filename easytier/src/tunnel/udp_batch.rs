@@ -0,0 +1,525 @@
+// batched datagram io for the udp tunnel.
+//
+// on linux up to MAX_BATCH_SIZE datagrams are received with one recvmmsg and
+// sent with one sendmmsg. when the kernel supports it, datagrams of the same
+// size to the same address are sent as one UDP_SEGMENT (gso) super packet, and
+// UDP_GRO lets the kernel hand over coalesced datagrams which are split here
+// again. other platforms fall back to one datagram per syscall.
+
+use std::{collections::VecDeque, io, net::SocketAddr};
+
+use bytes::{Bytes, BytesMut};
+use tokio::net::UdpSocket;
+
+use super::common::reserve_buf;
+
+pub const MAX_BATCH_SIZE: usize = 32;
+
+// the kernel accepts at most 64 segments and a 64k udp payload per gso send
+const MAX_GSO_SEGMENTS: usize = 64;
+const MAX_GSO_BYTES: usize = 65000;
+
+// a coalesced gro datagram can be as large as an udp payload can be
+const GRO_BUF_SIZE: usize = u16::MAX as usize;
+const GRO_BATCH_SIZE: usize = 8;
+
+/// Number of leading datagrams in `bufs` that can be sent in one gso send:
+/// all segments have the size of the first one, only the last may be shorter.
+fn gso_batch_len(bufs: &[Bytes]) -> usize {
+    let Some(segment_size) = bufs.first().map(|b| b.len()) else {
+        return 0;
+    };
+    let mut total = 0;
+    let mut n = 0;
+    for buf in bufs.iter().take(MAX_GSO_SEGMENTS) {
+        if buf.len() > segment_size || total + buf.len() > MAX_GSO_BYTES {
+            break;
+        }
+        total += buf.len();
+        n += 1;
+        if buf.len() < segment_size {
+            break;
+        }
+    }
+    n
+}
+
+pub struct UdpBatchReceiver {
+    bufs: Vec<BytesMut>,
+    buf_size: usize,
+    ready: VecDeque<(BytesMut, SocketAddr)>,
+}
+
+impl UdpBatchReceiver {
+    pub fn new(socket: &UdpSocket) -> Self {
+        #[cfg(target_os = "linux")]
+        let (batch_size, buf_size) = if sys::enable_gro(socket) {
+            (GRO_BATCH_SIZE, GRO_BUF_SIZE)
+        } else {
+            (MAX_BATCH_SIZE, super::udp::UDP_DATA_MTU)
+        };
+        #[cfg(not(target_os = "linux"))]
+        let (batch_size, buf_size) = {
+            let _ = socket;
+            (1, super::udp::UDP_DATA_MTU)
+        };
+
+        Self {
+            bufs: (0..batch_size).map(|_| BytesMut::new()).collect(),
+            buf_size,
+            ready: VecDeque::with_capacity(MAX_BATCH_SIZE),
+        }
+    }
+
+    /// Receive one datagram, datagrams already read by the last batch are
+    /// returned first.
+    pub async fn recv(&mut self, socket: &UdpSocket) -> io::Result<(BytesMut, SocketAddr)> {
+        loop {
+            if let Some(ret) = self.ready.pop_front() {
+                return Ok(ret);
+            }
+            self.recv_batch(socket).await?;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    async fn recv_batch(&mut self, socket: &UdpSocket) -> io::Result<()> {
+        use std::os::fd::AsRawFd as _;
+
+        for buf in self.bufs.iter_mut() {
+            reserve_buf(buf, self.buf_size, self.buf_size * 4);
+        }
+        let fd = socket.as_raw_fd();
+        let msgs = socket
+            .async_io(tokio::io::Interest::READABLE, || {
+                sys::recv_mmsg(fd, &mut self.bufs)
+            })
+            .await?;
+
+        for (buf, msg) in self.bufs.iter_mut().zip(msgs) {
+            let mut data = buf.split_to(msg.len);
+            if msg.truncated {
+                tracing::warn!(addr = ?msg.addr, "udp datagram truncated, dropped");
+                continue;
+            }
+            let Some(addr) = msg.addr else {
+                continue;
+            };
+            match msg.segment_size {
+                Some(segment_size) if segment_size > 0 => {
+                    while data.len() > segment_size {
+                        self.ready.push_back((data.split_to(segment_size), addr));
+                    }
+                    self.ready.push_back((data, addr));
+                }
+                _ => self.ready.push_back((data, addr)),
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn recv_batch(&mut self, socket: &UdpSocket) -> io::Result<()> {
+        let buf = &mut self.bufs[0];
+        reserve_buf(buf, self.buf_size, self.buf_size * 4);
+        let (_, addr) = socket.recv_buf_from(buf).await?;
+        self.ready.push_back((buf.split(), addr));
+        Ok(())
+    }
+}
+
+pub struct UdpBatchSender {
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    gso: bool,
+    // whether a gso send went through on this sender, after that EINVAL
+    // points to a bad batch rather than missing offload support
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    gso_confirmed: bool,
+}
+
+impl Default for UdpBatchSender {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl UdpBatchSender {
+    pub fn new() -> Self {
+        Self {
+            #[cfg(target_os = "linux")]
+            gso: sys::gso_supported(),
+            #[cfg(not(target_os = "linux"))]
+            gso: false,
+            gso_confirmed: false,
+        }
+    }
+
+    /// Send all datagrams in `bufs` to `addr`, in order.
+    #[cfg(target_os = "linux")]
+    pub async fn send(
+        &mut self,
+        socket: &UdpSocket,
+        addr: &SocketAddr,
+        mut bufs: &[Bytes],
+    ) -> io::Result<()> {
+        use std::os::fd::AsRawFd as _;
+        use tokio::io::Interest;
+
+        let fd = socket.as_raw_fd();
+        while !bufs.is_empty() {
+            let gso_len = if self.gso { gso_batch_len(bufs) } else { 0 };
+            if gso_len > 1 {
+                let ret = socket
+                    .async_io(Interest::WRITABLE, || {
+                        sys::send_gso(fd, addr, &bufs[..gso_len])
+                    })
+                    .await;
+                match ret {
+                    Ok(()) => {
+                        self.gso_confirmed = true;
+                        bufs = &bufs[gso_len..];
+                        continue;
+                    }
+                    // the route or nic doesn't support segmentation offload
+                    Err(e) if sys::is_gso_unsupported_error(&e, self.gso_confirmed) => {
+                        tracing::warn!(?e, ?addr, "udp gso send failed, disable gso");
+                        self.gso = false;
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+
+            let sent = socket
+                .async_io(Interest::WRITABLE, || sys::send_mmsg(fd, addr, bufs))
+                .await?;
+            bufs = &bufs[sent..];
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub async fn send(
+        &mut self,
+        socket: &UdpSocket,
+        addr: &SocketAddr,
+        bufs: &[Bytes],
+    ) -> io::Result<()> {
+        for buf in bufs {
+            socket.send_to(buf, addr).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::{
+        io, mem,
+        net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+        os::fd::{AsRawFd, RawFd},
+        ptr,
+    };
+
+    use bytes::{Bytes, BytesMut};
+    use nix::libc;
+    use once_cell::sync::Lazy;
+
+    use super::MAX_BATCH_SIZE;
+
+    const CMSG_BUF_LEN: usize = 64;
+
+    #[repr(C, align(8))]
+    #[derive(Clone, Copy)]
+    struct CmsgBuf([u8; CMSG_BUF_LEN]);
+
+    pub struct RecvMsg {
+        pub len: usize,
+        pub addr: Option<SocketAddr>,
+        pub segment_size: Option<usize>,
+        pub truncated: bool,
+    }
+
+    fn set_udp_opt(fd: RawFd, name: libc::c_int, value: libc::c_int) -> io::Result<()> {
+        let ret = unsafe {
+            libc::setsockopt(
+                fd,
+                libc::SOL_UDP,
+                name,
+                &value as *const _ as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    pub fn enable_gro(socket: &impl AsRawFd) -> bool {
+        set_udp_opt(socket.as_raw_fd(), libc::UDP_GRO, 1).is_ok()
+    }
+
+    pub fn gso_supported() -> bool {
+        static SUPPORTED: Lazy<bool> = Lazy::new(|| {
+            socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::DGRAM, None)
+                .is_ok_and(|s| set_udp_opt(s.as_raw_fd(), libc::UDP_SEGMENT, 1400).is_ok())
+        });
+        *SUPPORTED
+    }
+
+    /// EIO comes from drivers without checksum offload. EINVAL is only taken
+    /// as missing support on the first gso send, later it means a bad batch.
+    pub fn is_gso_unsupported_error(e: &io::Error, gso_confirmed: bool) -> bool {
+        match e.raw_os_error() {
+            Some(libc::EIO) => true,
+            Some(libc::EINVAL) => !gso_confirmed,
+            _ => false,
+        }
+    }
+
+    fn to_socket_addr(storage: &libc::sockaddr_storage) -> Option<SocketAddr> {
+        match storage.ss_family as libc::c_int {
+            libc::AF_INET => {
+                let addr = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+                Some(SocketAddr::V4(SocketAddrV4::new(
+                    Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                    u16::from_be(addr.sin_port),
+                )))
+            }
+            libc::AF_INET6 => {
+                let addr = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+                Some(SocketAddr::V6(SocketAddrV6::new(
+                    Ipv6Addr::from(addr.sin6_addr.s6_addr),
+                    u16::from_be(addr.sin6_port),
+                    addr.sin6_flowinfo,
+                    addr.sin6_scope_id,
+                )))
+            }
+            _ => None,
+        }
+    }
+
+    fn from_socket_addr(addr: &SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+        let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+        let len = match addr {
+            SocketAddr::V4(addr) => {
+                let sin = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+                sin.sin_family = libc::AF_INET as libc::sa_family_t;
+                sin.sin_port = addr.port().to_be();
+                sin.sin_addr.s_addr = u32::from(*addr.ip()).to_be();
+                mem::size_of::<libc::sockaddr_in>()
+            }
+            SocketAddr::V6(addr) => {
+                let sin6 = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+                sin6.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+                sin6.sin6_port = addr.port().to_be();
+                sin6.sin6_flowinfo = addr.flowinfo();
+                sin6.sin6_addr.s6_addr = addr.ip().octets();
+                sin6.sin6_scope_id = addr.scope_id();
+                mem::size_of::<libc::sockaddr_in6>()
+            }
+        };
+        (storage, len as libc::socklen_t)
+    }
+
+    fn gro_segment_size(hdr: &libc::msghdr) -> Option<usize> {
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(hdr);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_UDP && (*cmsg).cmsg_type == libc::UDP_GRO {
+                    let size = ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int);
+                    return Some(size as usize);
+                }
+                cmsg = libc::CMSG_NXTHDR(hdr, cmsg);
+            }
+        }
+        None
+    }
+
+    /// Receive into the spare capacity of `bufs`, the length of the buffers is
+    /// advanced by the received bytes.
+    pub fn recv_mmsg(fd: RawFd, bufs: &mut [BytesMut]) -> io::Result<Vec<RecvMsg>> {
+        let n = bufs.len().min(MAX_BATCH_SIZE);
+        let mut iovs: [libc::iovec; MAX_BATCH_SIZE] = unsafe { mem::zeroed() };
+        let mut names: [libc::sockaddr_storage; MAX_BATCH_SIZE] = unsafe { mem::zeroed() };
+        let mut ctrls = [CmsgBuf([0; CMSG_BUF_LEN]); MAX_BATCH_SIZE];
+        let mut hdrs: [libc::mmsghdr; MAX_BATCH_SIZE] = unsafe { mem::zeroed() };
+
+        for (i, buf) in bufs.iter_mut().enumerate().take(n) {
+            let spare = buf.spare_capacity_mut();
+            iovs[i] = libc::iovec {
+                iov_base: spare.as_mut_ptr() as *mut libc::c_void,
+                iov_len: spare.len(),
+            };
+            let hdr = &mut hdrs[i].msg_hdr;
+            hdr.msg_name = &mut names[i] as *mut _ as *mut libc::c_void;
+            hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as _;
+            hdr.msg_iov = &mut iovs[i];
+            hdr.msg_iovlen = 1;
+            hdr.msg_control = ctrls[i].0.as_mut_ptr() as *mut libc::c_void;
+            hdr.msg_controllen = CMSG_BUF_LEN as _;
+        }
+
+        let ret = unsafe {
+            libc::recvmmsg(
+                fd,
+                hdrs.as_mut_ptr(),
+                n as libc::c_uint,
+                0 as _,
+                ptr::null_mut(),
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut msgs = Vec::with_capacity(ret as usize);
+        for (i, buf) in bufs.iter_mut().enumerate().take(ret as usize) {
+            let hdr = &hdrs[i];
+            let len = (hdr.msg_len as usize).min(buf.capacity() - buf.len());
+            unsafe { buf.set_len(buf.len() + len) };
+            msgs.push(RecvMsg {
+                len,
+                addr: to_socket_addr(&names[i]),
+                segment_size: gro_segment_size(&hdr.msg_hdr),
+                truncated: hdr.msg_hdr.msg_flags & libc::MSG_TRUNC != 0,
+            });
+        }
+        Ok(msgs)
+    }
+
+    /// Send each buffer as one datagram, returns the number of datagrams sent.
+    pub fn send_mmsg(fd: RawFd, addr: &SocketAddr, bufs: &[Bytes]) -> io::Result<usize> {
+        let n = bufs.len().min(MAX_BATCH_SIZE);
+        let (mut name, name_len) = from_socket_addr(addr);
+        let mut iovs: [libc::iovec; MAX_BATCH_SIZE] = unsafe { mem::zeroed() };
+        let mut hdrs: [libc::mmsghdr; MAX_BATCH_SIZE] = unsafe { mem::zeroed() };
+
+        for (i, buf) in bufs.iter().enumerate().take(n) {
+            iovs[i] = libc::iovec {
+                iov_base: buf.as_ptr() as *mut libc::c_void,
+                iov_len: buf.len(),
+            };
+            let hdr = &mut hdrs[i].msg_hdr;
+            hdr.msg_name = &mut name as *mut _ as *mut libc::c_void;
+            hdr.msg_namelen = name_len;
+            hdr.msg_iov = &mut iovs[i];
+            hdr.msg_iovlen = 1;
+        }
+
+        let ret = unsafe { libc::sendmmsg(fd, hdrs.as_mut_ptr(), n as libc::c_uint, 0 as _) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(ret as usize)
+    }
+
+    /// Send all buffers in one gso datagram, segmented by the size of the
+    /// first buffer.
+    pub fn send_gso(fd: RawFd, addr: &SocketAddr, bufs: &[Bytes]) -> io::Result<()> {
+        let (mut name, name_len) = from_socket_addr(addr);
+        let mut iovs = bufs
+            .iter()
+            .map(|buf| libc::iovec {
+                iov_base: buf.as_ptr() as *mut libc::c_void,
+                iov_len: buf.len(),
+            })
+            .collect::<Vec<_>>();
+        let mut ctrl = CmsgBuf([0; CMSG_BUF_LEN]);
+
+        let mut hdr: libc::msghdr = unsafe { mem::zeroed() };
+        hdr.msg_name = &mut name as *mut _ as *mut libc::c_void;
+        hdr.msg_namelen = name_len;
+        hdr.msg_iov = iovs.as_mut_ptr();
+        hdr.msg_iovlen = iovs.len() as _;
+        hdr.msg_control = ctrl.0.as_mut_ptr() as *mut libc::c_void;
+        unsafe {
+            hdr.msg_controllen = libc::CMSG_SPACE(mem::size_of::<u16>() as u32) as _;
+            let cmsg = libc::CMSG_FIRSTHDR(&hdr);
+            (*cmsg).cmsg_level = libc::SOL_UDP;
+            (*cmsg).cmsg_type = libc::UDP_SEGMENT;
+            (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<u16>() as u32) as _;
+            ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut u16, bufs[0].len() as u16);
+        }
+
+        let ret = unsafe { libc::sendmsg(fd, &hdr, 0) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gso_batch_stops_at_size_change() {
+        let bufs = |sizes: &[usize]| {
+            sizes
+                .iter()
+                .map(|s| Bytes::from(vec![0u8; *s]))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(gso_batch_len(&[]), 0);
+        assert_eq!(gso_batch_len(&bufs(&[100, 100, 100])), 3);
+        // a shorter segment ends the batch
+        assert_eq!(gso_batch_len(&bufs(&[100, 100, 50, 100])), 3);
+        // a longer one can't be in the batch
+        assert_eq!(gso_batch_len(&bufs(&[100, 200, 100])), 1);
+        assert_eq!(gso_batch_len(&bufs(&[1400; 100])), MAX_GSO_BYTES / 1400);
+        assert_eq!(gso_batch_len(&bufs(&[100; 100])), MAX_GSO_SEGMENTS);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn gso_einval_only_disables_before_first_success() {
+        use nix::libc;
+
+        let eio = io::Error::from_raw_os_error(libc::EIO);
+        let einval = io::Error::from_raw_os_error(libc::EINVAL);
+        assert!(sys::is_gso_unsupported_error(&eio, false));
+        assert!(sys::is_gso_unsupported_error(&eio, true));
+        assert!(sys::is_gso_unsupported_error(&einval, false));
+        assert!(!sys::is_gso_unsupported_error(&einval, true));
+        assert!(!sys::is_gso_unsupported_error(
+            &io::Error::from_raw_os_error(libc::EAGAIN),
+            false
+        ));
+    }
+
+    #[tokio::test]
+    async fn batch_send_and_recv_keep_datagrams() {
+        let receiver_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sender_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = receiver_socket.local_addr().unwrap();
+
+        let bufs = (0..100u32)
+            .map(|i| {
+                // runs of equal sized datagrams mixed with odd ones
+                let len = if i % 10 == 9 { 300 + i as usize } else { 1000 };
+                let mut buf = vec![(i % 251) as u8; len];
+                buf[..4].copy_from_slice(&i.to_be_bytes());
+                Bytes::from(buf)
+            })
+            .collect::<Vec<_>>();
+
+        let mut receiver = UdpBatchReceiver::new(&receiver_socket);
+        let mut sender = UdpBatchSender::new();
+        sender.send(&sender_socket, &addr, &bufs).await.unwrap();
+
+        for expected in bufs.iter() {
+            let (buf, from) = tokio::time::timeout(
+                std::time::Duration::from_secs(1),
+                receiver.recv(&receiver_socket),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+            assert_eq!(from, sender_socket.local_addr().unwrap());
+            assert_eq!(&buf[..], &expected[..]);
+        }
+    }
+}
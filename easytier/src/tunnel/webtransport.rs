@@ -0,0 +1,352 @@
+//! Tunnels over a WebTransport session on HTTP/3 (`wt://`).
+//!
+//! Unlike the `quic` scheme this is a standard HTTP/3 handshake with the `h3`
+//! ALPN, so it passes through reverse proxies and firewalls that only allow
+//! regular HTTP/3. Each tunnel is one bidirectional WebTransport stream framed
+//! like the tcp tunnel. The url path is sent as the session path.
+//!
+//! The listener serves the PEM certificate chain and key given by the `cert`
+//! and `key` url query parameters, e.g.
+//! `wt://0.0.0.0:11013?cert=/etc/et/cert.pem&key=/etc/et/key.pem`, and falls
+//! back to a self signed certificate for `localhost` without them. The
+//! connector validates the server certificate against the system roots unless
+//! `insecure=true` is given, which is needed for self signed listeners.
+
+use std::{
+    net::{SocketAddr, SocketAddrV6},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use tokio::{task::JoinSet, time::timeout};
+use wtransport::{
+    ClientConfig, Connection, Endpoint, Identity, ServerConfig,
+    config::Ipv6DualStackConfig,
+    endpoint::{IncomingSession, endpoint_side::Server},
+};
+
+use super::{
+    FromUrl, IpVersion, Tunnel, TunnelConnector, TunnelError, TunnelInfo, TunnelListener,
+    common::{FramedReader, FramedWriter, TunnelWrapper},
+};
+
+const WT_MTU_BYTES: usize = 2000;
+const WT_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(5);
+const WT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(3);
+const WT_MAX_PENDING_HANDSHAKES: usize = 1024;
+
+fn query_param(addr: &url::Url, key: &str) -> Option<String> {
+    addr.query_pairs()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.into_owned())
+}
+
+type WtHandshakeResult = Result<Box<dyn Tunnel>, TunnelError>;
+
+fn wrap_wt_stream<T: Send + Sync + 'static>(
+    send: wtransport::SendStream,
+    recv: wtransport::RecvStream,
+    conn: Arc<T>,
+    info: TunnelInfo,
+) -> Box<dyn Tunnel> {
+    Box::new(TunnelWrapper::new(
+        FramedReader::new_with_associate_data(recv, WT_MTU_BYTES, Some(Box::new(conn.clone()))),
+        FramedWriter::new_with_associate_data(send, Some(Box::new(conn))),
+        Some(info),
+    ))
+}
+
+pub struct WtTunnelListener {
+    addr: url::Url,
+    endpoint: Option<Endpoint<Server>>,
+    // quic and session handshakes run here so a slow client can't stall accept
+    handshakes: JoinSet<WtHandshakeResult>,
+}
+
+impl std::fmt::Debug for WtTunnelListener {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WtTunnelListener")
+            .field("addr", &self.addr)
+            .field("pending_handshakes", &self.handshakes.len())
+            .finish()
+    }
+}
+
+impl WtTunnelListener {
+    pub fn new(addr: url::Url) -> Self {
+        WtTunnelListener {
+            addr,
+            endpoint: None,
+            handshakes: JoinSet::new(),
+        }
+    }
+
+    async fn identity(&self) -> Result<Identity, TunnelError> {
+        match (
+            query_param(&self.addr, "cert"),
+            query_param(&self.addr, "key"),
+        ) {
+            (Some(cert), Some(key)) => Ok(Identity::load_pemfiles(&cert, &key)
+                .await
+                .with_context(|| format!("Failed to load webtransport cert {cert} / key {key}"))?),
+            (None, None) => Ok(Identity::self_signed(["localhost"])
+                .with_context(|| "Failed to generate webtransport certificate")?),
+            _ => Err(TunnelError::InvalidAddr(format!(
+                "webtransport cert and key must be given together, url: {}",
+                self.addr
+            ))),
+        }
+    }
+
+    async fn try_accept(addr: url::Url, incoming: IncomingSession) -> WtHandshakeResult {
+        let request = incoming
+            .await
+            .with_context(|| "Failed to receive webtransport session request")?;
+        let peer_addr = request.remote_address();
+        let conn = request
+            .accept()
+            .await
+            .with_context(|| "Failed to accept webtransport session")?;
+        let (send, recv) = conn
+            .accept_bi()
+            .await
+            .with_context(|| "Failed to accept webtransport stream")?;
+
+        let remote_addr: crate::proto::common::Url =
+            super::build_url_from_socket_addr(&peer_addr.to_string(), "wt").into();
+        let info = TunnelInfo {
+            tunnel_type: "wt".to_owned(),
+            local_addr: Some(addr.into()),
+            remote_addr: Some(remote_addr.clone()),
+            resolved_remote_addr: Some(remote_addr),
+        };
+
+        Ok(wrap_wt_stream(send, recv, Arc::new(conn), info))
+    }
+}
+
+#[async_trait::async_trait]
+impl TunnelListener for WtTunnelListener {
+    async fn listen(&mut self) -> Result<(), TunnelError> {
+        self.endpoint = None;
+
+        let addr = SocketAddr::from_url(self.addr.clone(), IpVersion::Both).await?;
+        let identity = self.identity().await?;
+        let builder = ServerConfig::builder();
+        // the v4 listener on the same port is created separately
+        let builder = match addr {
+            SocketAddr::V4(_) => builder.with_bind_address(addr),
+            SocketAddr::V6(v6) => builder.with_bind_address_v6(v6, Ipv6DualStackConfig::Deny),
+        };
+        let config = builder
+            .with_identity(identity)
+            .keep_alive_interval(Some(WT_KEEP_ALIVE_INTERVAL))
+            .build();
+        let endpoint = Endpoint::server(config)?;
+
+        self.addr
+            .set_port(Some(endpoint.local_addr()?.port()))
+            .unwrap();
+        self.endpoint = Some(endpoint);
+
+        Ok(())
+    }
+
+    async fn accept(&mut self) -> Result<Box<dyn Tunnel>, TunnelError> {
+        enum Event {
+            Incoming(IncomingSession),
+            Handshaked(Result<WtHandshakeResult, tokio::task::JoinError>),
+        }
+
+        loop {
+            let endpoint = self.endpoint.as_ref().unwrap();
+            let event = tokio::select! {
+                incoming = endpoint.accept() => Event::Incoming(incoming),
+                Some(ret) = self.handshakes.join_next() => Event::Handshaked(ret),
+            };
+
+            match event {
+                Event::Incoming(incoming) => {
+                    if self.handshakes.len() >= WT_MAX_PENDING_HANDSHAKES {
+                        tracing::warn!(
+                            remote_addr = ?incoming.remote_address(),
+                            "too many pending webtransport handshakes, drop session"
+                        );
+                        continue;
+                    }
+                    let addr = self.addr.clone();
+                    self.handshakes.spawn(async move {
+                        timeout(WT_HANDSHAKE_TIMEOUT, Self::try_accept(addr, incoming)).await?
+                    });
+                }
+                Event::Handshaked(Ok(Ok(tunnel))) => return Ok(tunnel),
+                Event::Handshaked(Ok(Err(e))) => {
+                    tracing::error!(?e, ?self, "Failed to accept webtransport tunnel");
+                }
+                Event::Handshaked(Err(e)) => {
+                    tracing::warn!(?e, "webtransport handshake task failed");
+                }
+            }
+        }
+    }
+
+    fn local_url(&self) -> url::Url {
+        self.addr.clone()
+    }
+}
+
+pub struct WtTunnelConnector {
+    addr: url::Url,
+    ip_version: IpVersion,
+    resolved_addr: Option<SocketAddr>,
+}
+
+impl WtTunnelConnector {
+    pub fn new(addr: url::Url) -> Self {
+        WtTunnelConnector {
+            addr,
+            ip_version: IpVersion::Both,
+            resolved_addr: None,
+        }
+    }
+
+    /// Skipping the certificate validation has to be asked for explicitly.
+    fn insecure(&self) -> bool {
+        matches!(
+            query_param(&self.addr, "insecure").as_deref(),
+            Some("1" | "true")
+        )
+    }
+
+    /// The https url of the session. Domains are kept so the authority and SNI
+    /// match what a reverse proxy expects, ip hosts use the resolved address.
+    fn session_url(&self, addr: SocketAddr) -> String {
+        let path = match self.addr.path() {
+            "" => "/",
+            path => path,
+        };
+        match self.addr.domain() {
+            Some(domain) => format!("https://{}:{}{}", domain, addr.port(), path),
+            None => format!("https://{}{}", addr, path),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl TunnelConnector for WtTunnelConnector {
+    async fn connect(&mut self) -> Result<Box<dyn Tunnel>, TunnelError> {
+        let addr = match self.resolved_addr {
+            Some(addr) => addr,
+            None => SocketAddr::from_url(self.addr.clone(), self.ip_version).await?,
+        };
+
+        let builder = ClientConfig::builder();
+        let builder = if addr.is_ipv4() {
+            builder.with_bind_address("0.0.0.0:0".parse().unwrap())
+        } else {
+            builder.with_bind_address_v6(
+                SocketAddrV6::new(std::net::Ipv6Addr::UNSPECIFIED, 0, 0, 0),
+                Ipv6DualStackConfig::Deny,
+            )
+        };
+        let builder = if self.insecure() {
+            builder.with_no_cert_validation()
+        } else {
+            builder.with_native_certs()
+        };
+        let config = builder
+            .keep_alive_interval(Some(WT_KEEP_ALIVE_INTERVAL))
+            .build();
+        let endpoint = Endpoint::client(config)?;
+
+        let conn: Connection = endpoint
+            .connect(self.session_url(addr))
+            .await
+            .with_context(|| format!("Failed to connect webtransport session: {}", self.addr))?;
+        let (send, recv) = conn
+            .open_bi()
+            .await
+            .with_context(|| "Failed to open webtransport stream")?
+            .await
+            .with_context(|| "Failed to open webtransport stream")?;
+
+        let info = TunnelInfo {
+            tunnel_type: "wt".to_owned(),
+            local_addr: Some(
+                super::build_url_from_socket_addr(&endpoint.local_addr()?.to_string(), "wt").into(),
+            ),
+            remote_addr: Some(self.addr.clone().into()),
+            resolved_remote_addr: Some(
+                super::build_url_from_socket_addr(&conn.remote_address().to_string(), "wt").into(),
+            ),
+        };
+
+        Ok(wrap_wt_stream(send, recv, Arc::new((endpoint, conn)), info))
+    }
+
+    fn remote_url(&self) -> url::Url {
+        self.addr.clone()
+    }
+
+    fn set_ip_version(&mut self, ip_version: IpVersion) {
+        self.ip_version = ip_version;
+    }
+
+    fn set_resolved_addr(&mut self, addr: SocketAddr) {
+        self.resolved_addr = Some(addr);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tunnel::common::tests::{_tunnel_bench, _tunnel_pingpong};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn wt_pingpong() {
+        let listener = WtTunnelListener::new("wt://0.0.0.0:31071".parse().unwrap());
+        let connector =
+            WtTunnelConnector::new("wt://127.0.0.1:31071/tunnel?insecure=true".parse().unwrap());
+        _tunnel_pingpong(listener, connector).await
+    }
+
+    #[tokio::test]
+    async fn wt_bench() {
+        let listener = WtTunnelListener::new("wt://0.0.0.0:31072".parse().unwrap());
+        let connector = WtTunnelConnector::new("wt://127.0.0.1:31072?insecure=1".parse().unwrap());
+        _tunnel_bench(listener, connector).await
+    }
+
+    #[tokio::test]
+    async fn wt_connector_validates_cert_by_default() {
+        let mut listener = WtTunnelListener::new("wt://127.0.0.1:0".parse().unwrap());
+        listener.listen().await.unwrap();
+        let port = listener.local_url().port().unwrap();
+        let _accept_task = tokio::spawn(async move { listener.accept().await });
+
+        let mut connector =
+            WtTunnelConnector::new(format!("wt://127.0.0.1:{port}").parse().unwrap());
+        assert!(connector.connect().await.is_err());
+
+        let mut connector =
+            WtTunnelConnector::new(format!("wt://127.0.0.1:{port}?insecure=1").parse().unwrap());
+        assert!(connector.connect().await.is_ok());
+    }
+
+    #[test]
+    fn wt_session_url_keeps_domain_and_path() {
+        let connector = WtTunnelConnector::new("wt://example.com/et".parse().unwrap());
+        assert_eq!(
+            connector.session_url("192.0.2.1:443".parse().unwrap()),
+            "https://example.com:443/et"
+        );
+
+        let connector = WtTunnelConnector::new("wt://[::1]:11013".parse().unwrap());
+        assert_eq!(
+            connector.session_url("[::1]:11013".parse().unwrap()),
+            "https://[::1]:11013/"
+        );
+    }
+}
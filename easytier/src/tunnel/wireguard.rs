@@ -27,7 +27,7 @@ use anyhow::Context;
 use async_recursion::async_recursion;
 use async_trait::async_trait;
 use boringtun::{
-    noise::{Tunn, TunnResult, errors::WireGuardError},
+    noise::{Packet, Tunn, TunnResult, errors::WireGuardError, handshake::parse_handshake_anon},
     x25519::{PublicKey, StaticSecret},
 };
 use bytes::BytesMut;
@@ -449,8 +449,16 @@ impl WgPeer {
     }
 }
 
-type ConnSender = tokio::sync::mpsc::UnboundedSender<Box<dyn Tunnel>>;
-type ConnReceiver = tokio::sync::mpsc::UnboundedReceiver<Box<dyn Tunnel>>;
+// accepted tunnel and the static public key of the wireguard peer behind it
+type ConnSender = tokio::sync::mpsc::UnboundedSender<(Box<dyn Tunnel>, [u8; 32])>;
+type ConnReceiver = tokio::sync::mpsc::UnboundedReceiver<(Box<dyn Tunnel>, [u8; 32])>;
+
+/// Decides whether a wireguard peer with the given static public key may connect.
+pub type WgPeerAuthorizer = Arc<dyn Fn(&[u8; 32]) -> bool + Send + Sync>;
+
+/// Supplies the configs a new peer may handshake with, e.g. one per network secret
+/// accepted during a secret rotation.
+pub type WgConfigProvider = Arc<dyn Fn() -> Vec<WgConfig> + Send + Sync>;
 
 pub struct WgTunnelListener {
     addr: url::Url,
@@ -461,6 +469,8 @@ pub struct WgTunnelListener {
     conn_send: Option<ConnSender>,
 
     wg_peer_map: Arc<DashMap<SocketAddr, Arc<WgPeer>>>,
+    peer_authorizer: Option<WgPeerAuthorizer>,
+    config_provider: Option<WgConfigProvider>,
 
     tasks: JoinSet<()>,
     socket_mark: Option<u32>,
@@ -478,6 +488,8 @@ impl WgTunnelListener {
             conn_send: Some(conn_send),
 
             wg_peer_map: Arc::new(DashMap::new()),
+            peer_authorizer: None,
+            config_provider: None,
 
             tasks: JoinSet::new(),
             socket_mark: None,
@@ -488,6 +500,59 @@ impl WgTunnelListener {
         self.socket_mark = socket_mark;
     }
 
+    /// Accept any peer approved by the authorizer instead of only the peer public key
+    /// in the config. Must be called before `listen`.
+    pub fn set_peer_authorizer(&mut self, authorizer: Option<WgPeerAuthorizer>) {
+        self.peer_authorizer = authorizer;
+    }
+
+    /// Pick the config of a new peer from the provider instead of always using the
+    /// config given to `new`. Must be called before `listen`.
+    pub fn set_config_provider(&mut self, provider: Option<WgConfigProvider>) {
+        self.config_provider = provider;
+    }
+
+    /// Like `accept`, but also returns the static public key of the connected peer.
+    pub async fn accept_with_peer_key(
+        &mut self,
+    ) -> Result<(Box<dyn Tunnel>, [u8; 32]), TunnelError> {
+        if let Some((tunnel, peer_key)) = self.conn_recv.recv().await {
+            tracing::info!(?tunnel, "Accepted tunnel");
+            return Ok((tunnel, peer_key));
+        }
+        Err(TunnelError::Shutdown)
+    }
+
+    // a new peer must start with a handshake initiation, so its static key can be
+    // decrypted with our private key before any state is created for it.
+    fn authorize_handshake(
+        config: &WgConfig,
+        authorizer: &WgPeerAuthorizer,
+        packet: &[u8],
+    ) -> Option<PublicKey> {
+        let Ok(Packet::HandshakeInit(init)) = Tunn::parse_incoming_packet(packet) else {
+            return None;
+        };
+        let half =
+            parse_handshake_anon(&config.my_secret_key, &config.my_public_key, &init).ok()?;
+        authorizer(&half.peer_static_public).then(|| PublicKey::from(half.peer_static_public))
+    }
+
+    // with several candidate configs, the one whose symmetric key opens the
+    // handshake initiation wins.
+    fn select_config(configs: Vec<WgConfig>, packet: &[u8]) -> Option<WgConfig> {
+        if configs.len() <= 1 {
+            return configs.into_iter().next();
+        }
+        let Ok(Packet::HandshakeInit(init)) = Tunn::parse_incoming_packet(packet) else {
+            return None;
+        };
+        configs.into_iter().find(|config| {
+            parse_handshake_anon(&config.my_secret_key, &config.my_public_key, &init)
+                .is_ok_and(|half| half.peer_static_public == config.peer_public_key.to_bytes())
+        })
+    }
+
     fn get_udp_socket(&self) -> Arc<UdpSocket> {
         self.udp.as_ref().unwrap().clone()
     }
@@ -495,6 +560,8 @@ impl WgTunnelListener {
     async fn handle_udp_incoming(
         socket: Arc<UdpSocket>,
         config: WgConfig,
+        authorizer: Option<WgPeerAuthorizer>,
+        config_provider: Option<WgConfigProvider>,
         conn_sender: ConnSender,
         peer_map: Arc<DashMap<SocketAddr, Arc<WgPeer>>>,
     ) {
@@ -522,8 +589,29 @@ impl WgTunnelListener {
             tracing::trace!(?n, ?addr, "Received bytes from peer");
 
             if !peer_map.contains_key(&addr) {
+                let mut peer_config = config.clone();
+                if authorizer.is_none()
+                    && let Some(provider) = &config_provider
+                {
+                    let Some(selected) = Self::select_config(provider(), data) else {
+                        tracing::debug!(?addr, "Dropping packet from wg peer with unknown key");
+                        continue;
+                    };
+                    peer_config = selected;
+                }
+                if let Some(authorizer) = &authorizer {
+                    let Some(peer_public_key) =
+                        Self::authorize_handshake(&config, authorizer, data)
+                    else {
+                        tracing::debug!(?addr, "Dropping packet from unauthorized wg peer");
+                        continue;
+                    };
+                    peer_config.peer_public_key = peer_public_key;
+                }
+
                 tracing::info!("New peer: {}", addr);
-                let mut wg = WgPeer::new(socket.clone(), config.clone(), addr);
+                let peer_key = peer_config.peer_public_key.to_bytes();
+                let mut wg = WgPeer::new(socket.clone(), peer_config, addr);
                 let (stream, sink) = wg.start_and_get_tunnel().split();
                 let tunnel = Box::new(TunnelWrapper::new(
                     stream,
@@ -545,10 +633,10 @@ impl WgTunnelListener {
                         ),
                     }),
                 ));
-                if let Err(e) = conn_sender.send(tunnel) {
+                peer_map.insert(addr, Arc::new(wg));
+                if let Err(e) = conn_sender.send((tunnel, peer_key)) {
                     tracing::error!("Failed to send tunnel to conn_sender: {}", e);
                 }
-                peer_map.insert(addr, Arc::new(wg));
             }
 
             let peer = peer_map.get(&addr).unwrap().clone();
@@ -577,6 +665,8 @@ impl TunnelListener for WgTunnelListener {
         self.tasks.spawn(Self::handle_udp_incoming(
             self.get_udp_socket(),
             self.config.clone(),
+            self.peer_authorizer.clone(),
+            self.config_provider.clone(),
             self.conn_send.take().unwrap(),
             self.wg_peer_map.clone(),
         ));
@@ -585,11 +675,9 @@ impl TunnelListener for WgTunnelListener {
     }
 
     async fn accept(&mut self) -> Result<Box<dyn Tunnel>, super::TunnelError> {
-        if let Some(tunnel) = self.conn_recv.recv().await {
-            tracing::info!(?tunnel, "Accepted tunnel");
-            return Ok(tunnel);
-        }
-        Err(TunnelError::Shutdown)
+        self.accept_with_peer_key()
+            .await
+            .map(|(tunnel, _peer_key)| tunnel)
     }
 
     fn local_url(&self) -> url::Url {
@@ -888,6 +976,60 @@ pub mod tests {
         assert_eq!(0, listener.wg_peer_map.len());
     }
 
+    #[tokio::test]
+    async fn wg_listener_peer_authorizer() {
+        let (server_cfg, client_cfg) = create_wg_config();
+        let (_, other_client_cfg) = create_wg_config();
+        // the other client only knows the server public key
+        let other_client_cfg = WgConfig {
+            peer_public_key: server_cfg.my_public_key,
+            ..other_client_cfg
+        };
+
+        let allowed = client_cfg.my_public_key.to_bytes();
+        let mut listener =
+            WgTunnelListener::new("wg://127.0.0.1:5594".parse().unwrap(), server_cfg);
+        listener.set_peer_authorizer(Some(Arc::new(move |key: &[u8; 32]| *key == allowed)));
+        listener.listen().await.unwrap();
+
+        let mut connector =
+            WgTunnelConnector::new("wg://127.0.0.1:5594".parse().unwrap(), other_client_cfg);
+        let ret = tokio::time::timeout(Duration::from_secs(1), connector.connect()).await;
+        assert!(
+            ret.is_err(),
+            "unauthorized peer should not get a handshake response"
+        );
+
+        let mut connector =
+            WgTunnelConnector::new("wg://127.0.0.1:5594".parse().unwrap(), client_cfg);
+        let _t = connector.connect().await.unwrap();
+        let (_tunnel, peer_key) = listener.accept_with_peer_key().await.unwrap();
+        assert_eq!(peer_key, allowed);
+    }
+
+    #[tokio::test]
+    async fn wg_listener_config_provider_picks_matching_secret() {
+        let current = WgConfig::new_from_network_identity("net", "old");
+        let next = WgConfig::new_from_network_identity("net", "new");
+        let mut listener =
+            WgTunnelListener::new("wg://127.0.0.1:5592".parse().unwrap(), current.clone());
+        let configs = vec![current, next.clone()];
+        listener.set_config_provider(Some(Arc::new(move || configs.clone())));
+        listener.listen().await.unwrap();
+
+        let mut connector = WgTunnelConnector::new("wg://127.0.0.1:5592".parse().unwrap(), next);
+        let _t = connector.connect().await.unwrap();
+        let _tunnel = listener.accept().await.unwrap();
+
+        let other = WgConfig::new_from_network_identity("net", "unknown");
+        let mut connector = WgTunnelConnector::new("wg://127.0.0.1:5592".parse().unwrap(), other);
+        let ret = tokio::time::timeout(Duration::from_secs(1), connector.connect()).await;
+        assert!(
+            ret.is_err(),
+            "peer with an unknown secret should be dropped"
+        );
+    }
+
     #[tokio::test]
     async fn bind_same_port() {
         let (server_cfg, _client_cfg) = create_wg_config();
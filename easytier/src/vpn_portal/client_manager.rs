@@ -0,0 +1,416 @@
+use std::{
+    collections::HashMap,
+    io::Write as _,
+    net::Ipv4Addr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64_STANDARD;
+use serde::{Deserialize, Serialize};
+use x25519_dalek::{PublicKey, StaticSecret};
+
+use crate::proto::api::instance::VpnPortalClientInfo;
+
+const MAX_CLIENT_NAME_LEN: usize = 64;
+
+fn current_unix_timestamp() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ClientEntry {
+    public_key: String,
+    // empty if the client supplied its own public key
+    #[serde(default)]
+    private_key: String,
+    address: Ipv4Addr,
+    // 0 means the client never expires
+    #[serde(default)]
+    expiry_unix: i64,
+    created_at_unix: i64,
+}
+
+impl ClientEntry {
+    fn is_active_at(&self, now: i64) -> bool {
+        self.expiry_unix == 0 || self.expiry_unix > now
+    }
+
+    fn to_api_client_info(&self, name: &str) -> VpnPortalClientInfo {
+        VpnPortalClientInfo {
+            name: name.to_string(),
+            public_key: self.public_key.clone(),
+            address: self.address.to_string(),
+            expiry_unix: self.expiry_unix,
+            created_at_unix: self.created_at_unix,
+            key_generated: !self.private_key.is_empty(),
+            endpoint: None,
+        }
+    }
+}
+
+/// Per-client wireguard peers of the vpn portal. Each client has its own key pair and a
+/// stable address in the portal's client cidr, and can be revoked individually.
+pub struct VpnPortalClientManager {
+    clients: Mutex<HashMap<String, ClientEntry>>,
+    storage_path: Option<PathBuf>,
+}
+
+impl VpnPortalClientManager {
+    pub fn new(storage_path: Option<PathBuf>) -> Self {
+        let mgr = VpnPortalClientManager {
+            clients: Mutex::new(HashMap::new()),
+            storage_path,
+        };
+        mgr.load_from_disk();
+        mgr
+    }
+
+    /// Add a client. A key pair is generated unless `public_key` (base64) is given.
+    pub fn add_client(
+        &self,
+        name: &str,
+        public_key: Option<&str>,
+        ttl: Option<Duration>,
+        client_cidr: cidr::Ipv4Cidr,
+    ) -> anyhow::Result<VpnPortalClientInfo> {
+        let name = name.trim();
+        if name.is_empty()
+            || name.len() > MAX_CLIENT_NAME_LEN
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        {
+            anyhow::bail!(
+                "invalid client name {:?}, use up to {} letters, digits, '-', '_' or '.'",
+                name,
+                MAX_CLIENT_NAME_LEN
+            );
+        }
+
+        let (public_key, private_key) = match public_key {
+            Some(public_key) => {
+                let decoded = Self::decode_key_b64(public_key.trim())
+                    .with_context(|| "public key must be a base64 encoded 32 byte key")?;
+                (BASE64_STANDARD.encode(decoded), String::new())
+            }
+            None => {
+                let private = StaticSecret::random_from_rng(rand::rngs::OsRng);
+                let public = PublicKey::from(&private);
+                (
+                    BASE64_STANDARD.encode(public.as_bytes()),
+                    BASE64_STANDARD.encode(private.as_bytes()),
+                )
+            }
+        };
+
+        self.remove_expired_clients();
+
+        let mut clients = self.clients.lock().unwrap();
+        if clients.contains_key(name) {
+            anyhow::bail!("client {} already exists", name);
+        }
+        if clients.values().any(|c| c.public_key == public_key) {
+            anyhow::bail!("public key is already used by another client");
+        }
+        let address = Self::allocate_address(&clients, client_cidr)?;
+
+        let now = current_unix_timestamp();
+        let entry = ClientEntry {
+            public_key,
+            private_key,
+            address,
+            expiry_unix: ttl.map(|ttl| now + ttl.as_secs() as i64).unwrap_or(0),
+            created_at_unix: now,
+        };
+        let info = entry.to_api_client_info(name);
+        clients.insert(name.to_string(), entry);
+        drop(clients);
+        self.save_to_disk();
+        Ok(info)
+    }
+
+    // lowest free host address, skipping the network and broadcast address
+    fn allocate_address(
+        clients: &HashMap<String, ClientEntry>,
+        client_cidr: cidr::Ipv4Cidr,
+    ) -> anyhow::Result<Ipv4Addr> {
+        let first = u32::from(client_cidr.first_address());
+        let last = u32::from(client_cidr.last_address());
+        let (first, last) = if client_cidr.network_length() < 31 {
+            (first + 1, last - 1)
+        } else {
+            (first, last)
+        };
+
+        (first..=last)
+            .map(Ipv4Addr::from)
+            .find(|addr| clients.values().all(|c| c.address != *addr))
+            .ok_or_else(|| anyhow::anyhow!("no free address left in {}", client_cidr))
+    }
+
+    pub fn remove_client(&self, name: &str) -> bool {
+        let removed = self.clients.lock().unwrap().remove(name).is_some();
+        if removed {
+            self.save_to_disk();
+        }
+        removed
+    }
+
+    pub fn remove_expired_clients(&self) -> bool {
+        let now = current_unix_timestamp();
+        let removed = {
+            let mut clients = self.clients.lock().unwrap();
+            let before = clients.len();
+            clients.retain(|_, entry| entry.is_active_at(now));
+            before != clients.len()
+        };
+
+        if removed {
+            self.save_to_disk();
+        }
+
+        removed
+    }
+
+    pub fn list_clients(&self) -> Vec<VpnPortalClientInfo> {
+        let now = current_unix_timestamp();
+
+        let mut clients: Vec<_> = self
+            .clients
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, entry)| entry.is_active_at(now))
+            .map(|(name, entry)| entry.to_api_client_info(name))
+            .collect();
+        clients.sort_by(|a, b| a.name.cmp(&b.name));
+        clients
+    }
+
+    pub fn get_client(&self, name: &str) -> Option<VpnPortalClientInfo> {
+        let now = current_unix_timestamp();
+        self.clients
+            .lock()
+            .unwrap()
+            .get(name)
+            .filter(|entry| entry.is_active_at(now))
+            .map(|entry| entry.to_api_client_info(name))
+    }
+
+    /// Base64 private key of a client whose key pair was generated by the portal.
+    pub fn get_client_private_key(&self, name: &str) -> Option<String> {
+        self.clients
+            .lock()
+            .unwrap()
+            .get(name)
+            .map(|entry| entry.private_key.clone())
+            .filter(|key| !key.is_empty())
+    }
+
+    /// Name and address of the active client using this public key.
+    pub fn find_by_public_key(&self, public_key: &[u8]) -> Option<(String, Ipv4Addr)> {
+        let now = current_unix_timestamp();
+
+        let encoded = BASE64_STANDARD.encode(public_key);
+        self.clients
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(_, entry)| entry.public_key == encoded && entry.is_active_at(now))
+            .map(|(name, entry)| (name.clone(), entry.address))
+    }
+
+    pub fn has_clients(&self) -> bool {
+        let now = current_unix_timestamp();
+        self.clients
+            .lock()
+            .unwrap()
+            .values()
+            .any(|entry| entry.is_active_at(now))
+    }
+
+    fn save_to_disk(&self) {
+        let Some(path) = &self.storage_path else {
+            return;
+        };
+        let clients = self.clients.lock().unwrap();
+        if let Ok(json) = serde_json::to_string_pretty(&*clients)
+            && let Err(e) = Self::write_private_file(path, json.as_bytes())
+        {
+            tracing::warn!(?e, "failed to save vpn portal clients to disk");
+        }
+    }
+
+    // the file holds wireguard private keys, so it is only readable by the owner
+    // and replaced atomically to never leave a truncated file behind.
+    fn write_private_file(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+        let file_name = path
+            .file_name()
+            .with_context(|| format!("invalid file path {}", path.display()))?;
+        let tmp_path = path.with_file_name(format!(
+            ".{}.tmp-{}",
+            file_name.to_string_lossy(),
+            uuid::Uuid::new_v4()
+        ));
+        {
+            let mut options = std::fs::OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+            let mut file = options
+                .open(&tmp_path)
+                .with_context(|| format!("failed to create {}", tmp_path.display()))?;
+            let ret = file.write_all(data).and_then(|_| file.sync_all());
+            if let Err(err) = ret {
+                let _ = std::fs::remove_file(&tmp_path);
+                return Err(err).with_context(|| format!("failed to write {}", tmp_path.display()));
+            }
+        }
+
+        if let Err(err) = std::fs::rename(&tmp_path, path) {
+            let _ = std::fs::remove_file(&tmp_path);
+            return Err(err).with_context(|| format!("failed to replace {}", path.display()));
+        }
+        Ok(())
+    }
+
+    fn load_from_disk(&self) {
+        let Some(path) = &self.storage_path else {
+            return;
+        };
+        let Ok(data) = std::fs::read_to_string(path) else {
+            return;
+        };
+        match serde_json::from_str::<HashMap<String, ClientEntry>>(&data) {
+            Ok(loaded) => {
+                *self.clients.lock().unwrap() = loaded;
+                tracing::info!("loaded vpn portal clients from {}", path.display());
+            }
+            Err(e) => {
+                tracing::warn!(?e, "failed to parse vpn portal clients file");
+            }
+        }
+    }
+
+    fn decode_key_b64(s: &str) -> Option<[u8; 32]> {
+        BASE64_STANDARD.decode(s).ok()?.try_into().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidr() -> cidr::Ipv4Cidr {
+        "10.14.14.0/24".parse().unwrap()
+    }
+
+    #[test]
+    fn test_add_and_allocate_addresses() {
+        let mgr = VpnPortalClientManager::new(None);
+        let laptop = mgr.add_client("laptop", None, None, cidr()).unwrap();
+        let phone = mgr.add_client("phone", None, None, cidr()).unwrap();
+        assert_eq!(laptop.address, "10.14.14.1");
+        assert_eq!(phone.address, "10.14.14.2");
+        assert!(laptop.key_generated);
+        assert_ne!(laptop.public_key, phone.public_key);
+
+        // the address of a removed client is reused
+        assert!(mgr.remove_client("laptop"));
+        assert!(!mgr.remove_client("laptop"));
+        let tablet = mgr.add_client("tablet", None, None, cidr()).unwrap();
+        assert_eq!(tablet.address, "10.14.14.1");
+
+        assert!(mgr.add_client("phone", None, None, cidr()).is_err());
+        assert!(mgr.add_client("bad name", None, None, cidr()).is_err());
+    }
+
+    #[test]
+    fn test_client_supplied_public_key() {
+        let mgr = VpnPortalClientManager::new(None);
+        let private = StaticSecret::random_from_rng(rand::rngs::OsRng);
+        let public = PublicKey::from(&private);
+        let encoded = BASE64_STANDARD.encode(public.as_bytes());
+
+        let info = mgr
+            .add_client("router", Some(&encoded), None, cidr())
+            .unwrap();
+        assert!(!info.key_generated);
+        assert_eq!(info.public_key, encoded);
+        assert!(mgr.get_client_private_key("router").is_none());
+        assert_eq!(
+            mgr.find_by_public_key(public.as_bytes()),
+            Some(("router".to_string(), "10.14.14.1".parse().unwrap()))
+        );
+
+        // the same key can not be used twice
+        assert!(
+            mgr.add_client("other", Some(&encoded), None, cidr())
+                .is_err()
+        );
+        assert!(mgr.add_client("short", Some("AAAA"), None, cidr()).is_err());
+    }
+
+    #[test]
+    fn test_address_exhaustion() {
+        let mgr = VpnPortalClientManager::new(None);
+        let cidr: cidr::Ipv4Cidr = "10.14.14.0/30".parse().unwrap();
+        mgr.add_client("a", None, None, cidr).unwrap();
+        mgr.add_client("b", None, None, cidr).unwrap();
+        assert!(mgr.add_client("c", None, None, cidr).is_err());
+    }
+
+    #[test]
+    fn test_expired_client_is_inactive() {
+        let mgr = VpnPortalClientManager::new(None);
+        let info = mgr
+            .add_client("temp", None, Some(Duration::from_secs(3600)), cidr())
+            .unwrap();
+        assert!(info.expiry_unix > current_unix_timestamp());
+        assert!(mgr.has_clients());
+
+        mgr.clients
+            .lock()
+            .unwrap()
+            .get_mut("temp")
+            .unwrap()
+            .expiry_unix = current_unix_timestamp() - 1;
+        assert!(!mgr.has_clients());
+        assert!(mgr.get_client("temp").is_none());
+        let key = BASE64_STANDARD.decode(&info.public_key).unwrap();
+        assert!(mgr.find_by_public_key(&key).is_none());
+        assert!(mgr.remove_expired_clients());
+        assert!(mgr.list_clients().is_empty());
+    }
+
+    #[test]
+    fn test_persistence() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("vpn_portal_clients.json");
+
+        let mgr = VpnPortalClientManager::new(Some(path.clone()));
+        let info = mgr.add_client("laptop", None, None, cidr()).unwrap();
+        let private_key = mgr.get_client_private_key("laptop").unwrap();
+        drop(mgr);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        // no temp file is left next to it
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        let reloaded = VpnPortalClientManager::new(Some(path));
+        assert_eq!(reloaded.get_client("laptop"), Some(info));
+        assert_eq!(reloaded.get_client_private_key("laptop"), Some(private_key));
+    }
+}
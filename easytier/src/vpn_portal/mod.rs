@@ -5,10 +5,11 @@
 // 2. openvpn (TODO)
 // 3. shadowsocks (TODO)
 
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use crate::{common::global_ctx::ArcGlobalCtx, peers::peer_manager::PeerManager};
 
+pub mod client_manager;
 #[cfg(feature = "wireguard")]
 pub mod wireguard;
 
@@ -22,6 +23,23 @@ pub trait VpnPortal: Send + Sync {
     async fn dump_client_config(&self, peer_mgr: Arc<PeerManager>) -> String;
     fn name(&self) -> String;
     async fn list_clients(&self) -> Vec<String>;
+
+    // config of a client registered in the vpn portal client manager
+    async fn dump_peer_client_config(
+        &self,
+        _peer_mgr: Arc<PeerManager>,
+        _name: &str,
+    ) -> anyhow::Result<String> {
+        anyhow::bail!(
+            "{} vpn portal does not support per-client peers",
+            self.name()
+        )
+    }
+
+    // client name -> remote endpoint of connected per-client peers
+    async fn list_connected_peer_clients(&self) -> HashMap<String, String> {
+        HashMap::new()
+    }
 }
 
 pub struct NullVpnPortal;
@@ -1,6 +1,8 @@
 use std::{
+    collections::HashMap,
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6},
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::Context;
@@ -27,10 +29,13 @@ use crate::{
     },
 };
 
-use super::VpnPortal;
+use super::{VpnPortal, client_manager::VpnPortalClientManager};
 
 type WgPeerIpTable = Arc<DashMap<Ipv4Addr, Arc<ClientEntry>>>;
 
+// how often a connected client is checked for revocation or expiry
+const CLIENT_RECHECK_INTERVAL: Duration = Duration::from_secs(5);
+
 pub(crate) fn get_wg_config_for_portal(nid: &NetworkIdentity) -> WgConfig {
     let key_seed = format!(
         "{}{}",
@@ -43,6 +48,8 @@ pub(crate) fn get_wg_config_for_portal(nid: &NetworkIdentity) -> WgConfig {
 struct ClientEntry {
     endpoint_addr: Option<url::Url>,
     sink: MpscTunnelSender,
+    // None for clients using the shared key derived from the network identity
+    client_name: Option<String>,
 }
 
 struct WireGuardImpl {
@@ -52,6 +59,7 @@ struct WireGuardImpl {
     listener_addr: SocketAddr,
 
     wg_peer_ip_table: WgPeerIpTable,
+    client_mgr: Arc<VpnPortalClientManager>,
 
     tasks: Arc<std::sync::Mutex<JoinSet<()>>>,
 }
@@ -63,6 +71,7 @@ impl WireGuardImpl {
 
         let vpn_cfg = global_ctx.config.get_vpn_portal_config().unwrap();
         let listener_addr = vpn_cfg.wireguard_listen;
+        let client_mgr = global_ctx.get_vpn_portal_client_manager().clone();
 
         Self {
             global_ctx,
@@ -70,14 +79,28 @@ impl WireGuardImpl {
             wg_config,
             listener_addr,
             wg_peer_ip_table: Arc::new(DashMap::new()),
+            client_mgr,
             tasks: Arc::new(std::sync::Mutex::new(JoinSet::new())),
         }
     }
 
+    // the shared client key is only accepted while no per-client peer is configured,
+    // so adding the first client revokes configs handed out before.
+    fn is_client_authorized(
+        client_mgr: &VpnPortalClientManager,
+        shared_client_key: &[u8],
+        peer_key: &[u8; 32],
+    ) -> bool {
+        client_mgr.find_by_public_key(peer_key).is_some()
+            || (!client_mgr.has_clients() && peer_key[..] == *shared_client_key)
+    }
+
     async fn handle_incoming_conn(
         t: Box<dyn Tunnel>,
+        peer_key: [u8; 32],
         peer_mgr: Arc<PeerManager>,
         wg_peer_ip_table: WgPeerIpTable,
+        client_mgr: Arc<VpnPortalClientManager>,
     ) {
         let info = t.info().unwrap_or_default();
         let mut mpsc_tunnel = MpscTunnel::new(t, None);
@@ -95,8 +118,39 @@ impl WireGuardImpl {
 
         let mut map_key = None;
 
+        // per-client peers always use their allocated address, so register it up front
+        let client = client_mgr.find_by_public_key(&peer_key);
+        if let Some((name, address)) = &client {
+            tracing::info!(?name, ?address, "Wireguard client connected");
+            wg_peer_ip_table.insert(
+                *address,
+                Arc::new(ClientEntry {
+                    endpoint_addr: endpoint_addr.clone(),
+                    sink: mpsc_tunnel.get_sink(),
+                    client_name: Some(name.clone()),
+                }),
+            );
+            map_key = Some(*address);
+            ip_registered = true;
+        }
+
+        let mut recheck = tokio::time::interval(CLIENT_RECHECK_INTERVAL);
         loop {
-            let msg = match stream.next().await {
+            let msg = tokio::select! {
+                msg = stream.next() => msg,
+                _ = recheck.tick() => {
+                    let authorized = match &client {
+                        Some(client) => client_mgr.find_by_public_key(&peer_key).as_ref() == Some(client),
+                        None => !client_mgr.has_clients(),
+                    };
+                    if !authorized {
+                        tracing::info!(?client, "Wireguard client revoked or expired, disconnecting");
+                        break;
+                    }
+                    continue;
+                }
+            };
+            let msg = match msg {
                 Some(Ok(msg)) => msg,
                 Some(Err(err)) => {
                     tracing::error!(?err, "Failed to receive from wg client");
@@ -114,10 +168,17 @@ impl WireGuardImpl {
                 tracing::error!(?inner, "Failed to parse ipv4 packet");
                 continue;
             };
+            if let Some((_, address)) = &client
+                && i.get_source() != *address
+            {
+                tracing::debug!(?i, ?address, "Dropping packet with spoofed source address");
+                continue;
+            }
             if !ip_registered {
                 let client_entry = Arc::new(ClientEntry {
                     endpoint_addr: endpoint_addr.clone(),
                     sink: mpsc_tunnel.get_sink(),
+                    client_name: None,
                 });
                 map_key = Some(i.get_source());
                 // Be careful here: we may overwrite an existing entry if the client IP is reused,
@@ -219,6 +280,11 @@ impl WireGuardImpl {
         listener_url.set_port(Some(listener_addr.port())).unwrap();
         listener_url.set_ip_host(listener_addr.ip()).unwrap();
         let mut l = WgTunnelListener::new(listener_url.clone(), self.wg_config.clone());
+        let client_mgr = self.client_mgr.clone();
+        let shared_client_key = self.wg_config.peer_public_key().to_vec();
+        l.set_peer_authorizer(Some(Arc::new(move |peer_key: &[u8; 32]| {
+            Self::is_client_authorized(&client_mgr, &shared_client_key, peer_key)
+        })));
 
         tracing::info!("Wireguard VPN Portal Starting");
 
@@ -231,15 +297,18 @@ impl WireGuardImpl {
         let tasks = Arc::downgrade(&self.tasks.clone());
         let peer_mgr = self.peer_mgr.clone();
         let wg_peer_ip_table = self.wg_peer_ip_table.clone();
+        let client_mgr = self.client_mgr.clone();
         self.tasks.lock().unwrap().spawn(async move {
-            while let Ok(t) = l.accept().await {
+            while let Ok((t, peer_key)) = l.accept_with_peer_key().await {
                 let Some(tasks) = tasks.upgrade() else {
                     break;
                 };
                 tasks.lock().unwrap().spawn(Self::handle_incoming_conn(
                     t,
+                    peer_key,
                     peer_mgr.clone(),
                     wg_peer_ip_table.clone(),
+                    client_mgr.clone(),
                 ));
             }
         });
@@ -281,34 +350,15 @@ pub struct WireGuard {
     inner: Option<WireGuardImpl>,
 }
 
-#[async_trait::async_trait]
-impl VpnPortal for WireGuard {
-    async fn start(
-        &mut self,
-        global_ctx: ArcGlobalCtx,
+impl WireGuard {
+    async fn render_client_config(
+        &self,
         peer_mgr: Arc<PeerManager>,
-    ) -> anyhow::Result<()> {
-        assert!(self.inner.is_none());
-
-        let vpn_cfg = global_ctx.config.get_vpn_portal_config();
-        if vpn_cfg.is_none() {
-            anyhow::bail!("vpn cfg is not set for wireguard vpn portal");
-        }
-
-        let inner = WireGuardImpl::new(global_ctx, peer_mgr);
-        inner.start().await?;
-        self.inner = Some(inner);
-        Ok(())
-    }
-
-    async fn dump_client_config(&self, peer_mgr: Arc<PeerManager>) -> String {
-        if self.inner.is_none() {
-            return "ERROR: Wireguard VPN Portal Not Started".to_string();
-        }
-        let global_ctx = self.inner.as_ref().unwrap().global_ctx.clone();
-        if global_ctx.config.get_vpn_portal_config().is_none() {
-            return "ERROR: VPN Portal Config Not Set".to_string();
-        }
+        private_key: &str,
+        address: &str,
+    ) -> String {
+        let inner = self.inner.as_ref().unwrap();
+        let global_ctx = inner.global_ctx.clone();
 
         let routes = peer_mgr.list_routes().await;
         let mut allow_ips = routes
@@ -325,19 +375,17 @@ impl VpnPortal for WireGuard {
             allow_ips.push(inet.network().to_string());
         }
 
-        let vpn_cfg = global_ctx.config.get_vpn_portal_config().unwrap();
-        let client_cidr = vpn_cfg.client_cidr;
-
-        allow_ips.push(client_cidr.to_string());
+        if let Some(vpn_cfg) = global_ctx.config.get_vpn_portal_config() {
+            allow_ips.push(vpn_cfg.client_cidr.to_string());
+        }
 
         let allow_ips = allow_ips.into_iter().collect::<Vec<_>>().join(",");
 
-        let cfg = self.inner.as_ref().unwrap().wg_config.clone();
-        let cfg_str = format!(
+        format!(
             r#"
 [Interface]
-PrivateKey = {peer_secret_key}
-Address = {address} # should assign an ip from this cidr manually
+PrivateKey = {private_key}
+Address = {address}
 
 [Peer]
 PublicKey = {my_public_key}
@@ -345,14 +393,100 @@ AllowedIPs = {allow_ips}
 Endpoint = {listenr_addr} # should be the public ip(or domain) of the vpn server
 PersistentKeepalive = 25
 "#,
-            peer_secret_key = BASE64_STANDARD.encode(cfg.peer_secret_key()),
-            my_public_key = BASE64_STANDARD.encode(cfg.my_public_key()),
-            listenr_addr = self.inner.as_ref().unwrap().listener_addr,
+            private_key = private_key,
+            my_public_key = BASE64_STANDARD.encode(inner.wg_config.my_public_key()),
+            listenr_addr = inner.listener_addr,
             allow_ips = allow_ips,
-            address = client_cidr.first_address().to_string() + "/32",
+            address = address,
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl VpnPortal for WireGuard {
+    async fn start(
+        &mut self,
+        global_ctx: ArcGlobalCtx,
+        peer_mgr: Arc<PeerManager>,
+    ) -> anyhow::Result<()> {
+        assert!(self.inner.is_none());
+
+        let vpn_cfg = global_ctx.config.get_vpn_portal_config();
+        if vpn_cfg.is_none() {
+            anyhow::bail!("vpn cfg is not set for wireguard vpn portal");
+        }
+
+        let inner = WireGuardImpl::new(global_ctx, peer_mgr);
+        inner.start().await?;
+        self.inner = Some(inner);
+        Ok(())
+    }
+
+    async fn dump_client_config(&self, peer_mgr: Arc<PeerManager>) -> String {
+        let Some(inner) = self.inner.as_ref() else {
+            return "ERROR: Wireguard VPN Portal Not Started".to_string();
+        };
+        let Some(vpn_cfg) = inner.global_ctx.config.get_vpn_portal_config() else {
+            return "ERROR: VPN Portal Config Not Set".to_string();
+        };
+
+        if inner.client_mgr.has_clients() {
+            return "# per-client peers are configured, the shared client key is disabled.\n\
+                    # use `easytier-cli vpn-portal config <name>` to get the config of a client."
+                .to_string();
+        }
+
+        let private_key = BASE64_STANDARD.encode(inner.wg_config.peer_secret_key());
+        let address = format!(
+            "{}/32 # should assign an ip from this cidr manually",
+            vpn_cfg.client_cidr.first_address()
         );
+        self.render_client_config(peer_mgr, &private_key, &address)
+            .await
+    }
 
-        cfg_str
+    async fn dump_peer_client_config(
+        &self,
+        peer_mgr: Arc<PeerManager>,
+        name: &str,
+    ) -> anyhow::Result<String> {
+        let Some(inner) = self.inner.as_ref() else {
+            anyhow::bail!("wireguard vpn portal not started");
+        };
+        let Some(client) = inner.client_mgr.get_client(name) else {
+            anyhow::bail!("vpn portal client {} not found", name);
+        };
+
+        let private_key = inner
+            .client_mgr
+            .get_client_private_key(name)
+            .unwrap_or_else(|| "<private key of the client>".to_string());
+        let address = format!("{}/32", client.address);
+        Ok(self
+            .render_client_config(peer_mgr, &private_key, &address)
+            .await)
+    }
+
+    async fn list_connected_peer_clients(&self) -> HashMap<String, String> {
+        self.inner
+            .as_ref()
+            .map(|w| {
+                w.wg_peer_ip_table
+                    .iter()
+                    .filter_map(|x| {
+                        let entry = x.value();
+                        Some((
+                            entry.client_name.clone()?,
+                            entry
+                                .endpoint_addr
+                                .as_ref()
+                                .map(|x| x.to_string())
+                                .unwrap_or_default(),
+                        ))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
     }
 
     fn name(&self) -> String {